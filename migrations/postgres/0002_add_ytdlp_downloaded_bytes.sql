@@ -0,0 +1 @@
+ALTER TABLE ytdlp ADD COLUMN downloaded_bytes BIGINT;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS metadata_search (
+    video_id TEXT,
+    title TEXT,
+    channel TEXT,
+    description TEXT,
+    tags TEXT,
+    PRIMARY KEY (video_id)
+);
+
+CREATE INDEX IF NOT EXISTS metadata_search_text_index ON metadata_search USING GIN (
+    to_tsvector('simple', title || ' ' || channel || ' ' || description || ' ' || tags)
+);
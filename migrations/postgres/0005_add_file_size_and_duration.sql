@@ -0,0 +1,4 @@
+ALTER TABLE ytdlp ADD COLUMN file_size_bytes BIGINT;
+ALTER TABLE ytdlp ADD COLUMN duration_ms BIGINT;
+ALTER TABLE ffmpeg ADD COLUMN file_size_bytes BIGINT;
+ALTER TABLE ffmpeg ADD COLUMN duration_ms BIGINT;
@@ -0,0 +1,7 @@
+ALTER TABLE ytdlp ADD COLUMN source_hash TEXT;
+
+ALTER TABLE ffmpeg ADD COLUMN variant TEXT NOT NULL DEFAULT '';
+ALTER TABLE ffmpeg ADD COLUMN source_hash TEXT;
+ALTER TABLE ffmpeg ADD COLUMN transcode_options TEXT;
+ALTER TABLE ffmpeg DROP CONSTRAINT ffmpeg_pkey;
+ALTER TABLE ffmpeg ADD PRIMARY KEY (video_id, audio_ext, variant);
@@ -0,0 +1 @@
+ALTER TABLE ytdlp ADD COLUMN format_id TEXT;
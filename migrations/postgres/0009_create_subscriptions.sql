@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    audio_ext TEXT NOT NULL,
+    transcode_options TEXT,
+    check_interval_seconds BIGINT NOT NULL,
+    unix_time BIGINT,
+    last_check_unix_time BIGINT,
+    last_check_error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS subscription_videos (
+    subscription_id BIGINT,
+    video_id TEXT,
+    unix_time BIGINT,
+    PRIMARY KEY (subscription_id, video_id)
+);
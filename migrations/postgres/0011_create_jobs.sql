@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS jobs (
+    job_id TEXT PRIMARY KEY,
+    idempotency_key TEXT UNIQUE,
+    video_id TEXT NOT NULL,
+    audio_ext TEXT NOT NULL,
+    variant TEXT NOT NULL,
+    unix_time BIGINT
+);
+CREATE INDEX IF NOT EXISTS jobs_transcode ON jobs (video_id, audio_ext, variant, unix_time);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS users (
+    id BIGSERIAL PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    unix_time BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS auth_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    user_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT,
+    unix_time BIGINT,
+    expire_unix_time BIGINT
+);
+CREATE INDEX IF NOT EXISTS auth_tokens_user ON auth_tokens (user_id);
+
+ALTER TABLE ytdlp ADD COLUMN owner BIGINT;
+ALTER TABLE ffmpeg ADD COLUMN owner BIGINT;
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS channel_jobs (
+    id BIGSERIAL PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    audio_ext TEXT NOT NULL,
+    variant TEXT NOT NULL,
+    transcode_options TEXT,
+    filters TEXT,
+    status INTEGER NOT NULL,
+    error TEXT,
+    total_listed BIGINT,
+    owner BIGINT,
+    unix_time BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS channel_job_videos (
+    channel_job_id BIGINT,
+    video_id TEXT,
+    title TEXT,
+    unix_time BIGINT,
+    PRIMARY KEY (channel_job_id, video_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE ffmpeg ADD COLUMN file_hash TEXT;
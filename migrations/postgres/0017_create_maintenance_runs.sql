@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS maintenance_runs (
+    id BIGSERIAL PRIMARY KEY,
+    task TEXT NOT NULL,
+    is_manual BOOLEAN NOT NULL DEFAULT FALSE,
+    status INTEGER NOT NULL,
+    summary TEXT,
+    error TEXT,
+    unix_time BIGINT,
+    end_unix_time BIGINT
+);
+CREATE INDEX IF NOT EXISTS maintenance_runs_task ON maintenance_runs (task);
+
+ALTER TABLE metadata ADD COLUMN unix_time BIGINT;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS trash (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    key TEXT NOT NULL,
+    owner BIGINT,
+    entry TEXT NOT NULL,
+    files TEXT NOT NULL,
+    file_size_bytes BIGINT,
+    deleted_at BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS trash_deleted_at ON trash (deleted_at);
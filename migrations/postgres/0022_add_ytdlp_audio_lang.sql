@@ -0,0 +1 @@
+ALTER TABLE ytdlp ADD COLUMN audio_lang TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE ytdlp ADD COLUMN delete_pending BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE ffmpeg ADD COLUMN delete_pending BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,9 @@
+-- NOTE: Transcodes whose download was deleted are kept by giving them an empty download
+INSERT INTO ytdlp (video_id, status, unix_time, owner)
+SELECT video_id, 0, MIN(unix_time), MIN(owner) FROM ffmpeg
+WHERE video_id NOT IN (SELECT video_id FROM ytdlp)
+GROUP BY video_id;
+
+ALTER TABLE ffmpeg ADD CONSTRAINT ffmpeg_video_id_fkey
+    FOREIGN KEY (video_id) REFERENCES ytdlp (video_id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS ffmpeg_video_id ON ffmpeg (video_id);
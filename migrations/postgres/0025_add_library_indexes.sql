@@ -0,0 +1,9 @@
+-- NOTE: Listing downloads and transcodes filters by status and time, which scans the whole table in large libraries
+CREATE INDEX IF NOT EXISTS ytdlp_status_time ON ytdlp (status, unix_time);
+CREATE INDEX IF NOT EXISTS ffmpeg_status_time ON ffmpeg (status, unix_time);
+CREATE INDEX IF NOT EXISTS ffmpeg_audio_ext_status ON ffmpeg (audio_ext, status);
+-- NOTE: Storage quotas sum the file sizes of each owner
+CREATE INDEX IF NOT EXISTS ytdlp_owner ON ytdlp (owner);
+CREATE INDEX IF NOT EXISTS ffmpeg_owner ON ffmpeg (owner);
+CREATE INDEX IF NOT EXISTS metadata_time ON metadata (unix_time);
+CREATE INDEX IF NOT EXISTS job_history_status_time ON job_history (status, unix_time);
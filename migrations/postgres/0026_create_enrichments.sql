@@ -0,0 +1,14 @@
+-- NOTE: Videos without a match are stored with a null recording_id so they aren't looked up again
+CREATE TABLE IF NOT EXISTS enrichments (
+    video_id TEXT,
+    recording_id TEXT,
+    score DOUBLE PRECISION,
+    title TEXT,
+    artist TEXT,
+    album TEXT,
+    album_artist TEXT,
+    track_number BIGINT,
+    release_date TEXT,
+    unix_time BIGINT,
+    PRIMARY KEY (video_id)
+);
@@ -0,0 +1,3 @@
+-- NOTE: Files in the data directory are served to the owner of the entry stored at their path
+CREATE INDEX IF NOT EXISTS ytdlp_audio_path ON ytdlp (audio_path);
+CREATE INDEX IF NOT EXISTS ffmpeg_audio_path ON ffmpeg (audio_path);
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS ytdlp (
+    video_id TEXT,
+    status INTEGER DEFAULT 0,
+    unix_time INTEGER,
+    stdout_log_path TEXT,
+    stderr_log_path TEXT,
+    system_log_path TEXT,
+    audio_path TEXT,
+    PRIMARY KEY (video_id)
+);
+
+CREATE TABLE IF NOT EXISTS ffmpeg (
+    video_id TEXT,
+    audio_ext TEXT,
+    status INTEGER DEFAULT 0,
+    unix_time INTEGER,
+    stdout_log_path TEXT,
+    stderr_log_path TEXT,
+    system_log_path TEXT,
+    audio_path TEXT,
+    PRIMARY KEY (video_id, audio_ext)
+);
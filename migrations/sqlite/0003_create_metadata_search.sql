@@ -0,0 +1,7 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS metadata_search USING fts5(
+    video_id UNINDEXED,
+    title,
+    channel,
+    description,
+    tags
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS metadata (
+    video_id TEXT,
+    title TEXT,
+    channel TEXT,
+    duration_ms INTEGER,
+    thumbnail_url TEXT,
+    PRIMARY KEY (video_id)
+);
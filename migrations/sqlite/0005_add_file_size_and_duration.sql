@@ -0,0 +1,4 @@
+ALTER TABLE ytdlp ADD COLUMN file_size_bytes INTEGER;
+ALTER TABLE ytdlp ADD COLUMN duration_ms INTEGER;
+ALTER TABLE ffmpeg ADD COLUMN file_size_bytes INTEGER;
+ALTER TABLE ffmpeg ADD COLUMN duration_ms INTEGER;
@@ -0,0 +1,4 @@
+ALTER TABLE ffmpeg ADD COLUMN codec TEXT;
+ALTER TABLE ffmpeg ADD COLUMN bitrate INTEGER;
+ALTER TABLE ffmpeg ADD COLUMN has_artwork INTEGER;
+ALTER TABLE ffmpeg ADD COLUMN max_volume_db REAL;
@@ -0,0 +1,35 @@
+ALTER TABLE ytdlp ADD COLUMN source_hash TEXT;
+
+-- NOTE: sqlite can't alter a primary key so we recreate the table
+CREATE TABLE ffmpeg_variants (
+    video_id TEXT,
+    audio_ext TEXT,
+    variant TEXT NOT NULL DEFAULT '',
+    status INTEGER DEFAULT 0,
+    unix_time INTEGER,
+    stdout_log_path TEXT,
+    stderr_log_path TEXT,
+    system_log_path TEXT,
+    audio_path TEXT,
+    file_size_bytes INTEGER,
+    duration_ms INTEGER,
+    codec TEXT,
+    bitrate INTEGER,
+    has_artwork INTEGER,
+    max_volume_db REAL,
+    source_hash TEXT,
+    transcode_options TEXT,
+    PRIMARY KEY (video_id, audio_ext, variant)
+);
+
+INSERT INTO ffmpeg_variants (
+    video_id, audio_ext, status, unix_time, stdout_log_path, stderr_log_path, system_log_path, audio_path,
+    file_size_bytes, duration_ms, codec, bitrate, has_artwork, max_volume_db
+)
+SELECT
+    video_id, audio_ext, status, unix_time, stdout_log_path, stderr_log_path, system_log_path, audio_path,
+    file_size_bytes, duration_ms, codec, bitrate, has_artwork, max_volume_db
+FROM ffmpeg;
+
+DROP TABLE ffmpeg;
+ALTER TABLE ffmpeg_variants RENAME TO ffmpeg;
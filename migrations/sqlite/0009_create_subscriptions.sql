@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL UNIQUE,
+    audio_ext TEXT NOT NULL,
+    transcode_options TEXT,
+    check_interval_seconds INTEGER NOT NULL,
+    unix_time INTEGER,
+    last_check_unix_time INTEGER,
+    last_check_error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS subscription_videos (
+    subscription_id INTEGER,
+    video_id TEXT,
+    unix_time INTEGER,
+    PRIMARY KEY (subscription_id, video_id)
+);
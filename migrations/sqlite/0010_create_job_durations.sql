@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS job_durations (
+    kind TEXT NOT NULL,
+    unix_time INTEGER NOT NULL,
+    elapsed_ms INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS job_durations_kind_time ON job_durations (kind, unix_time);
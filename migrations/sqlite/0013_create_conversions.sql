@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS conversions (
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    key TEXT NOT NULL,
+    unix_time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS conversions_user_time ON conversions (user_id, unix_time);
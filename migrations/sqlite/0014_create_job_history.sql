@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS job_history (
+    kind TEXT NOT NULL,
+    key TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    error_category TEXT,
+    file_size_bytes INTEGER,
+    elapsed_ms INTEGER NOT NULL,
+    unix_time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS job_history_time ON job_history (unix_time);
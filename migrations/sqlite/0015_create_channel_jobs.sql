@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS channel_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel_id TEXT NOT NULL,
+    audio_ext TEXT NOT NULL,
+    variant TEXT NOT NULL,
+    transcode_options TEXT,
+    filters TEXT,
+    status INTEGER NOT NULL,
+    error TEXT,
+    total_listed INTEGER,
+    owner INTEGER,
+    unix_time INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS channel_job_videos (
+    channel_job_id INTEGER,
+    video_id TEXT,
+    title TEXT,
+    unix_time INTEGER,
+    PRIMARY KEY (channel_job_id, video_id)
+);
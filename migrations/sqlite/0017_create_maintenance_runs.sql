@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS maintenance_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task TEXT NOT NULL,
+    is_manual INTEGER NOT NULL DEFAULT 0,
+    status INTEGER NOT NULL,
+    summary TEXT,
+    error TEXT,
+    unix_time INTEGER,
+    end_unix_time INTEGER
+);
+CREATE INDEX IF NOT EXISTS maintenance_runs_task ON maintenance_runs (task);
+
+ALTER TABLE metadata ADD COLUMN unix_time INTEGER;
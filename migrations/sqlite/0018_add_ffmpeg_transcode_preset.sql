@@ -0,0 +1 @@
+ALTER TABLE ffmpeg ADD COLUMN transcode_preset TEXT;
@@ -0,0 +1 @@
+ALTER TABLE job_history ADD COLUMN request_id TEXT;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS trash (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    key TEXT NOT NULL,
+    owner INTEGER,
+    entry TEXT NOT NULL,
+    files TEXT NOT NULL,
+    file_size_bytes INTEGER,
+    deleted_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS trash_deleted_at ON trash (deleted_at);
@@ -0,0 +1,2 @@
+ALTER TABLE ytdlp ADD COLUMN final_state TEXT;
+ALTER TABLE ffmpeg ADD COLUMN final_state TEXT;
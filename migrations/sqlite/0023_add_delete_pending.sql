@@ -0,0 +1,2 @@
+ALTER TABLE ytdlp ADD COLUMN delete_pending INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ffmpeg ADD COLUMN delete_pending INTEGER NOT NULL DEFAULT 0;
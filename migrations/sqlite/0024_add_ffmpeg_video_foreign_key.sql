@@ -0,0 +1,47 @@
+-- NOTE: Transcodes whose download was deleted are kept by giving them an empty download
+INSERT INTO ytdlp (video_id, status, unix_time, owner)
+SELECT video_id, 0, MIN(unix_time), MIN(owner) FROM ffmpeg
+WHERE video_id NOT IN (SELECT video_id FROM ytdlp)
+GROUP BY video_id;
+
+-- NOTE: sqlite can't add a foreign key to a table so we recreate the table
+CREATE TABLE ffmpeg_video_fk (
+    video_id TEXT REFERENCES ytdlp (video_id) ON DELETE CASCADE,
+    audio_ext TEXT,
+    variant TEXT NOT NULL DEFAULT '',
+    status INTEGER DEFAULT 0,
+    unix_time INTEGER,
+    stdout_log_path TEXT,
+    stderr_log_path TEXT,
+    system_log_path TEXT,
+    audio_path TEXT,
+    file_size_bytes INTEGER,
+    duration_ms INTEGER,
+    codec TEXT,
+    bitrate INTEGER,
+    has_artwork INTEGER,
+    max_volume_db REAL,
+    source_hash TEXT,
+    transcode_options TEXT,
+    owner INTEGER,
+    file_hash TEXT,
+    transcode_preset TEXT,
+    final_state TEXT,
+    delete_pending INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (video_id, audio_ext, variant)
+);
+
+INSERT INTO ffmpeg_video_fk (
+    video_id, audio_ext, variant, status, unix_time, stdout_log_path, stderr_log_path, system_log_path, audio_path,
+    file_size_bytes, duration_ms, codec, bitrate, has_artwork, max_volume_db, source_hash, transcode_options, owner,
+    file_hash, transcode_preset, final_state, delete_pending
+)
+SELECT
+    video_id, audio_ext, variant, status, unix_time, stdout_log_path, stderr_log_path, system_log_path, audio_path,
+    file_size_bytes, duration_ms, codec, bitrate, has_artwork, max_volume_db, source_hash, transcode_options, owner,
+    file_hash, transcode_preset, final_state, delete_pending
+FROM ffmpeg;
+
+DROP TABLE ffmpeg;
+ALTER TABLE ffmpeg_video_fk RENAME TO ffmpeg;
+CREATE INDEX IF NOT EXISTS ffmpeg_video_id ON ffmpeg (video_id);
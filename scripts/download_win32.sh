@@ -12,6 +12,7 @@ echo 468a6f8bf1d156ad173e000a40f696d4fbd69c5aa7360229329b9063a388e7d0 yt-dlp.exe
 # Unzip
 7z x ./ffmpeg.7z -offmpeg -y
 cp ./ffmpeg/ffmpeg-7.0.1-essentials_build/bin/ffmpeg.exe ./ffmpeg.exe
+cp ./ffmpeg/ffmpeg-7.0.1-essentials_build/bin/ffprobe.exe ./ffprobe.exe
 # Cleanup
 rm ./ffmpeg.7z
 rm -rf ./ffmpeg
@@ -0,0 +1,61 @@
+#!/bin/sh
+# Runs the server with the fake yt-dlp, ffmpeg and ffprobe and checks that jobs go through every worker
+# Usage: ./scripts/test_fake_tools.sh [port]
+port=${1:-8089}
+url=http://127.0.0.1:$port/api/v1
+video_id=dQw4w9WgXcQ
+# NOTE: Video ids are 11 characters which this happens to be
+unavailable_id=unavailable
+
+cargo build --features fake_tools || exit 1
+data_dir=$(mktemp -d)
+FAKE_YTDLP_FAIL_IDS=$unavailable_id FAKE_TOOLS_STEP_DELAY_MS=20 ./target/debug/ytdlp_server \
+    --port $port --data-dir $data_dir \
+    --ytdlp-binary-path ./target/debug/fake_ytdlp \
+    --ffmpeg-binary-path ./target/debug/fake_ffmpeg \
+    --ffprobe-binary-path ./target/debug/fake_ffprobe \
+    > $data_dir/server.log 2>&1 &
+server_pid=$!
+trap 'kill $server_pid 2>/dev/null; rm -rf $data_dir' EXIT
+
+fail() {
+    echo "[fail] $1"
+    tail -n 20 $data_dir/server.log
+    exit 1
+}
+
+# wait_for_status <route> <status>
+wait_for_status() {
+    for _ in $(seq 1 100); do
+        state=$(curl -s $url/$1)
+        case "$state" in
+            *"\"worker_status\":\"$2\""*) return 0 ;;
+        esac
+        sleep 0.2
+    done
+    fail "$1 never became $2: $state"
+}
+
+for _ in $(seq 1 50); do
+    curl -s -o /dev/null $url/get_system_info && break
+    sleep 0.2
+done
+
+curl -s $url/request_transcode/$video_id/mp3 > /dev/null || fail "server didn't start"
+wait_for_status get_download_state/$video_id finished
+wait_for_status get_transcode_state/$video_id/mp3 finished
+echo "[pass] download and transcode"
+
+curl -s $url/get_transcode/$video_id/mp3 | grep -q '"codec":"mp3"' || fail "transcode wasn't probed"
+curl -s $url/get_waveform/$video_id/mp3 | grep -q '"peaks"' || fail "waveform wasn't generated"
+curl -s $url/get_formats/$video_id | grep -q '"format_id":"251"' || fail "formats weren't listed"
+echo "[pass] probe, waveform and formats"
+
+curl -s $url/request_hls/$video_id > /dev/null
+wait_for_status get_hls_state/$video_id finished
+curl -s $url/hls/$video_id/playlist.m3u8 | grep -q '#EXT-X-ENDLIST' || fail "hls playlist wasn't finished"
+echo "[pass] hls"
+
+curl -s $url/request_transcode/$unavailable_id/mp3 > /dev/null
+wait_for_status get_download_state/$unavailable_id failed
+echo "[pass] unavailable video"
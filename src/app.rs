@@ -1,17 +1,45 @@
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex, Condvar};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use thiserror::Error;
-use threadpool::ThreadPool;
 use dashmap::DashMap;
 use crate::{
-    database::{DatabasePool, VideoId, setup_database},
-    metadata::{MetadataCache, Metadata},
-    worker_download::{DownloadCache, DownloadState},
+    bandwidth::SharedBandwidthSchedule,
+    cache_eviction::DEFAULT_FINISHED_JOB_CACHE_MINUTES,
+    database::VideoId,
+    disk_space::DEFAULT_MIN_FREE_SPACE_MB,
+    downloader::{select_downloader, DownloaderRule, SharedDownloader},
+    enrichment::Enricher,
+    error_code::ErrorCode,
+    ffmpeg::TranscodePresets,
+    integrations::IntegrationConfig,
+    job_events::JobEventBus,
+    job_store::{SharedJobStore, SqliteJobStore, DatabaseOptions, open_job_store},
+    log_file::{LogLimits, DEFAULT_MAX_LOG_BYTES, DEFAULT_TOTAL_JOB_LOG_BACKUPS},
+    lru_cache::LruCache,
+    maintenance::{
+        CronSchedule, MaintenanceTask, RunningMaintenanceTasks, DEFAULT_PURGE_LOGS_DAYS, DEFAULT_STALE_METADATA_DAYS,
+        DEFAULT_FAILED_JOB_TTL_HOURS,
+    },
+    metadata::{MetadataCache, DEFAULT_METADATA_CACHE_SIZE, DEFAULT_METADATA_CACHE_TTL_SECONDS},
+    notifications::Notifier,
+    process_priority::ProcessPriority,
+    storage::S3Storage,
+    system_info::ToolVersions,
+    transcoder::{FfmpegTranscoder, SharedTranscoder},
+    trash::DEFAULT_TRASH_DAYS,
+    worker_download::{recover_interrupted_downloads, DownloadCache, DownloadState},
     worker_transcode::{TranscodeCache, TranscodeKey, TranscodeState},
+    worker_hls::HlsCache,
+    worker_queue::WorkerQueue,
+    ytdlp::{DownloadOptions, YtdlpDownloader, DEFAULT_OUTPUT_TEMPLATE},
+    ytdlp_auth::YtdlpAuth,
 };
 
-pub type WorkerThreadPool = Arc<Mutex<ThreadPool>>;
-pub type WorkerCacheEntry<T> = Arc<(Mutex<T>, Condvar)>;
+pub type WorkerThreadPool = Arc<WorkerQueue>;
+// NOTE: Changes to an entry are published to the JobEventBus instead of a condvar so async handlers can await them
+pub type WorkerCacheEntry<T> = Arc<Mutex<T>>;
 
 #[derive(Debug,Error)]
 pub enum WorkerError {
@@ -19,6 +47,8 @@ pub enum WorkerError {
     StdoutLogCreate(std::io::Error),
     #[error("Failed to create stderr log: {0:?}")]
     StderrLogCreate(std::io::Error),
+    #[error("Failed to create system log: {0:?}")]
+    SystemLogCreate(std::io::Error),
     #[error("Failed to write to system log: {0:?}")]
     SystemWriteFail(std::io::Error),
     #[error("Failed to write to stdout log: {0:?}")]
@@ -29,10 +59,47 @@ pub enum WorkerError {
     StdoutMissing,
     #[error("Failed to acquire stderr from process")]
     StderrMissing,
-    #[error("Failed to join stdout thread: {0:?}")]
-    StdoutThreadJoin(Box<dyn std::any::Any + Send + 'static>),
-    #[error("Failed to join stderr thread: {0:?}")]
-    StderrThreadJoin(Box<dyn std::any::Any + Send + 'static>),
+    #[error("Failed to acquire stdin from process")]
+    StdinMissing,
+}
+
+impl WorkerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::StdoutLogCreate(err) | Self::StderrLogCreate(err) | Self::SystemLogCreate(err) |
+            Self::SystemWriteFail(err) | Self::StdoutWriteFail(err) | Self::StderrWriteFail(err) => {
+                ErrorCode::from_io_error(err, ErrorCode::InternalError)
+            },
+            Self::StdoutMissing | Self::StderrMissing | Self::StdinMissing => ErrorCode::InternalError,
+        }
+    }
+}
+
+#[derive(Debug,Error)]
+pub enum BasePathError {
+    #[error("Base path must start with a slash: {0}")]
+    MissingLeadingSlash(String),
+    #[error("Base path can only contain letters, digits, '-', '_', '.' and '/' without empty or relative segments: {0}")]
+    InvalidSegment(String),
+}
+
+/// Normalises the prefix that routes are served under, which is empty when served from the root
+pub fn validate_base_path(path: &str) -> Result<String, BasePathError> {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    let Some(segments) = trimmed.strip_prefix('/') else {
+        return Err(BasePathError::MissingLeadingSlash(path.to_owned()));
+    };
+    let is_valid = segments.split('/').all(|segment| {
+        !segment.is_empty() && segment != "." && segment != ".." &&
+        segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    });
+    if !is_valid {
+        return Err(BasePathError::InvalidSegment(path.to_owned()));
+    }
+    Ok(trimmed.to_owned())
 }
 
 #[derive(Clone,Debug)]
@@ -41,8 +108,84 @@ pub struct AppConfig {
     pub data: PathBuf,
     pub download: PathBuf,
     pub transcode: PathBuf,
+    pub hls: PathBuf,
+    /// Unfinished downloads and transcodes are written here so they only show up once they are complete
+    pub staging: PathBuf,
+    /// Frontend served at the root of the site
+    pub static_files: PathBuf,
     pub ffmpeg_binary: PathBuf,
+    pub ffprobe_binary: PathBuf,
     pub ytdlp_binary: PathBuf,
+    /// Downloads with urls matching these rules use another backend instead of the yt-dlp binary
+    pub downloader_rules: Vec<DownloaderRule>,
+    /// Backend of downloads that don't match a rule instead of the yt-dlp binary (e.g. a mock in tests)
+    pub downloader: Option<SharedDownloader>,
+    /// Backend of transcodes, hls streams and probes instead of the ffmpeg and ffprobe binaries (e.g. a mock in tests)
+    pub transcoder: Option<SharedTranscoder>,
+    pub proxy: Option<String>,
+    pub geo_bypass_country: Option<String>,
+    /// PO tokens, cookies and plugins that let yt-dlp download age-restricted videos
+    pub ytdlp_auth: YtdlpAuth,
+    pub download_options: DownloadOptions,
+    /// Caps the rate of downloads depending on the time of day when they start
+    pub bandwidth_schedule: SharedBandwidthSchedule,
+    /// Extractor args that requests can pass to yt-dlp, which includes the default extractor args
+    pub allowed_extractor_args: Vec<String>,
+    /// yt-dlp output template of downloads relative to the download directory
+    pub output_template: String,
+    pub database_url: Option<String>,
+    pub database_options: DatabaseOptions,
+    pub pipeline_transcode: bool,
+    /// Named encoder settings that requests can choose with ?preset=
+    pub transcode_presets: TranscodePresets,
+    pub title_filenames: bool,
+    pub thumbnail_max_size: u32,
+    /// Threads each ffmpeg process can use, 0 splits the cpu budget between the running processes
+    pub ffmpeg_threads: usize,
+    /// Threads shared by the running ffmpeg processes when their thread count isn't fixed
+    pub ffmpeg_cpu_budget: usize,
+    /// Priority of the yt-dlp and ffmpeg processes started by jobs
+    pub process_priority: ProcessPriority,
+    /// Least recently used metadata is evicted once this many videos are cached
+    pub metadata_cache_size: Option<usize>,
+    /// Cached metadata is fetched again from the api once it is this old
+    pub metadata_cache_ttl: Option<Duration>,
+    /// New jobs are rejected once this many are waiting for a worker thread
+    pub max_pending_jobs: Option<usize>,
+    /// Requests must be made by a logged in user and only see their own library
+    pub auth_enabled: bool,
+    /// Users can't start conversions once their downloads and transcodes take up this many bytes
+    pub quota_storage_bytes: Option<u64>,
+    /// Downloads and transcodes are rejected if they would leave less than this much free space on the data volume
+    pub min_free_space_bytes: u64,
+    /// Users can start this many conversions per day
+    pub quota_daily_conversions: Option<u64>,
+    /// Finished transcodes are uploaded here instead of being kept in the transcode directory
+    pub object_storage: Option<S3Storage>,
+    /// Providers that are told when downloads and transcodes finish or fail
+    pub notifier: Notifier,
+    /// Telegram and discord bots that convert the links posted to them
+    pub integrations: IntegrationConfig,
+    /// Looks up the tags of transcodes that ask for ?enrich_tags=true, which are tagged from the video otherwise
+    pub enricher: Option<Enricher>,
+    /// Maintenance tasks run whenever their schedule matches, tasks without a schedule are only run by admins
+    pub maintenance_schedules: Vec<(MaintenanceTask, CronSchedule)>,
+    /// Logs of jobs started this many days ago are removed by the purge_old_logs task
+    pub purge_logs_days: u64,
+    /// Metadata fetched this many days ago is fetched again by the refresh_stale_metadata task
+    pub stale_metadata_days: u64,
+    /// Deleted downloads and transcodes are kept in the trash for this many days before the purge_trash task removes them
+    pub trash_days: u64,
+    /// Downloads and transcodes that failed this many hours ago are forgotten by the expire_failed_jobs task
+    pub failed_job_ttl_hours: u64,
+    /// States of downloads and transcodes that finished this long ago are dropped from memory since they are kept in the job store
+    pub finished_job_cache_duration: Option<Duration>,
+    /// Size limit of the stdout and stderr logs of each download and transcode
+    pub job_log_limits: LogLimits,
+    /// Directories under /data can be browsed, which only lists the files each user can access
+    pub data_listing: bool,
+    /// Every route is served under this prefix (e.g. /ytdlp) when hosted at a subpath behind a reverse proxy
+    pub base_path: String,
 }
 
 impl Default for AppConfig {
@@ -54,17 +197,99 @@ impl Default for AppConfig {
             data: data.to_owned(), 
             download: data.join("downloads"),
             transcode: data.join("transcode"),
+            hls: data.join("hls"),
+            staging: data.join("staging"),
+            static_files: root.join("static"),
             ffmpeg_binary: root.join("bin").join("ffmpeg.exe"),
+            ffprobe_binary: root.join("bin").join("ffprobe.exe"),
             ytdlp_binary: root.join("bin").join("yt-dlp.exe"),
+            downloader_rules: Vec::new(),
+            downloader: None,
+            transcoder: None,
+            proxy: None,
+            geo_bypass_country: None,
+            ytdlp_auth: YtdlpAuth::default(),
+            download_options: DownloadOptions::default(),
+            bandwidth_schedule: SharedBandwidthSchedule::default(),
+            allowed_extractor_args: Vec::new(),
+            output_template: DEFAULT_OUTPUT_TEMPLATE.to_owned(),
+            database_url: None,
+            database_options: DatabaseOptions::default(),
+            pipeline_transcode: false,
+            transcode_presets: TranscodePresets::new(),
+            title_filenames: false,
+            thumbnail_max_size: 600,
+            ffmpeg_threads: 0,
+            ffmpeg_cpu_budget: std::thread::available_parallelism().map(|v| v.get()).unwrap_or(1),
+            process_priority: ProcessPriority::default(),
+            metadata_cache_size: Some(DEFAULT_METADATA_CACHE_SIZE),
+            metadata_cache_ttl: Some(Duration::from_secs(DEFAULT_METADATA_CACHE_TTL_SECONDS)),
+            max_pending_jobs: None,
+            auth_enabled: false,
+            quota_storage_bytes: None,
+            min_free_space_bytes: DEFAULT_MIN_FREE_SPACE_MB*1024*1024,
+            quota_daily_conversions: None,
+            object_storage: None,
+            notifier: Notifier::default(),
+            integrations: IntegrationConfig::default(),
+            enricher: None,
+            maintenance_schedules: Vec::new(),
+            purge_logs_days: DEFAULT_PURGE_LOGS_DAYS,
+            stale_metadata_days: DEFAULT_STALE_METADATA_DAYS,
+            trash_days: DEFAULT_TRASH_DAYS,
+            failed_job_ttl_hours: DEFAULT_FAILED_JOB_TTL_HOURS,
+            finished_job_cache_duration: Some(Duration::from_secs(DEFAULT_FINISHED_JOB_CACHE_MINUTES*60)),
+            job_log_limits: LogLimits { max_bytes: Some(DEFAULT_MAX_LOG_BYTES), total_backups: DEFAULT_TOTAL_JOB_LOG_BACKUPS },
+            data_listing: false,
+            base_path: String::new(),
         }
     }
 }
 
 impl AppConfig {
+    pub fn get_downloader(&self, url: &str) -> SharedDownloader {
+        match select_downloader(self.downloader_rules.as_slice(), url).or(self.downloader.as_ref()) {
+            Some(downloader) => downloader.clone(),
+            None => Arc::new(YtdlpDownloader::new(self.ytdlp_binary.clone())),
+        }
+    }
+
+    pub fn get_transcoder(&self) -> SharedTranscoder {
+        match self.transcoder.as_ref() {
+            Some(transcoder) => transcoder.clone(),
+            None => Arc::new(FfmpegTranscoder::new(self.ffmpeg_binary.clone(), self.ffprobe_binary.clone())),
+        }
+    }
+
+    /// Cookies are scoped to the base path so other apps behind the same proxy don't receive them
+    pub fn get_cookie_path(&self) -> String {
+        format!("{0}/", self.base_path)
+    }
+
+    /// Downloads, transcodes and hls segments are kept in the data directory unless their own directory is given
+    pub fn set_data_directory(&mut self, data: &Path) {
+        self.data = data.to_owned();
+        self.download = data.join("downloads");
+        self.transcode = data.join("transcode");
+        self.hls = data.join("hls");
+        self.staging = data.join("staging");
+    }
+
+    pub fn get_download_staging(&self) -> PathBuf {
+        self.staging.join("downloads")
+    }
+
+    pub fn get_transcode_staging(&self) -> PathBuf {
+        self.staging.join("transcode")
+    }
+
     pub fn seed_directories(&self) -> Result<(), std::io::Error> {
         std::fs::create_dir_all(&self.data)?;
         std::fs::create_dir_all(&self.download)?;
         std::fs::create_dir_all(&self.transcode)?;
+        std::fs::create_dir_all(&self.hls)?;
+        std::fs::create_dir_all(self.get_download_staging())?;
+        std::fs::create_dir_all(self.get_transcode_staging())?;
         Ok(())
     }
 }
@@ -72,29 +297,79 @@ impl AppConfig {
 #[derive(Clone)]
 pub struct AppState {
     pub app_config: Arc<AppConfig>,
-    pub db_pool: DatabasePool,
+    pub job_store: SharedJobStore,
     pub worker_thread_pool: WorkerThreadPool,
     pub download_cache: DownloadCache,
     pub transcode_cache: TranscodeCache,
+    pub hls_cache: HlsCache,
+    pub job_events: JobEventBus,
     pub metadata_cache: MetadataCache,
+    pub http_client: reqwest::Client,
+    pub running_maintenance_tasks: RunningMaintenanceTasks,
+    /// Versions of the external binaries detected at startup
+    pub tool_versions: Arc<ToolVersions>,
 }
 
 impl AppState {
     pub fn new(app_config: AppConfig, total_transcode_threads: usize) -> Result<Self, Box<dyn std::error::Error>> {
-        let db_manager = r2d2_sqlite::SqliteConnectionManager::file(app_config.data.join("index.db"));
-        let db_pool = DatabasePool::new(db_manager)?;
-        setup_database(db_pool.get()?)?;
-        let worker_thread_pool: WorkerThreadPool = Arc::new(Mutex::new(ThreadPool::new(total_transcode_threads)));
+        let tool_versions = ToolVersions::new(
+            app_config.ytdlp_binary.as_path(), app_config.ffmpeg_binary.as_path(), app_config.ffprobe_binary.as_path(),
+        )?;
+        log::info!(
+            "Detected tool versions: yt-dlp={}, ffmpeg={}, ffprobe={}",
+            tool_versions.ytdlp.version, tool_versions.ffmpeg.version, tool_versions.ffprobe.version,
+        );
+        let job_store = open_job_store(
+            app_config.database_url.as_deref(), app_config.data.join("index.db").as_path(), &app_config.database_options,
+        )?;
+        Self::with_job_store(app_config, job_store, tool_versions, total_transcode_threads)
+    }
+
+    /// Server backed by an in-memory sqlite database and a new temporary data directory for integration tests
+    /// The downloader and transcoder of the config should be mocks since the binaries aren't checked
+    pub fn new_in_memory(mut app_config: AppConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        static TOTAL_IN_MEMORY_STATES: AtomicUsize = AtomicUsize::new(0);
+        let index = TOTAL_IN_MEMORY_STATES.fetch_add(1, Ordering::Relaxed);
+        let data = std::env::temp_dir().join(format!("ytdlp_webui-{0}-{index}", std::process::id()));
+        app_config.set_data_directory(data.as_path());
+        app_config.seed_directories()?;
+        let tool_versions = ToolVersions::unchecked(
+            app_config.ytdlp_binary.as_path(), app_config.ffmpeg_binary.as_path(), app_config.ffprobe_binary.as_path(),
+        );
+        let job_store: SharedJobStore = Arc::new(SqliteJobStore::open_in_memory(&app_config.database_options)?);
+        Self::with_job_store(app_config, job_store, tool_versions, 1)
+    }
+
+    fn with_job_store(
+        app_config: AppConfig, job_store: SharedJobStore, tool_versions: ToolVersions, total_transcode_threads: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match recover_interrupted_downloads(&app_config, &job_store) {
+            Ok(0) => {},
+            Ok(total) => log::info!("Marked downloads interrupted by the last shutdown as resumable: total={total}"),
+            Err(err) => log::error!("Failed to recover interrupted downloads: {err:?}"),
+        }
+        let worker_thread_pool: WorkerThreadPool = Arc::new(WorkerQueue::new(total_transcode_threads)?);
         let download_cache: DownloadCache = Arc::new(DashMap::<VideoId, WorkerCacheEntry<DownloadState>>::new());
         let transcode_cache: TranscodeCache = Arc::new(DashMap::<TranscodeKey, WorkerCacheEntry<TranscodeState>>::new());
-        let metadata_cache: MetadataCache = Arc::new(DashMap::<VideoId, Arc<Metadata>>::new());
+        let hls_cache: HlsCache = Arc::new(DashMap::<VideoId, WorkerCacheEntry<TranscodeState>>::new());
+        let metadata_cache: MetadataCache = Arc::new(LruCache::new(app_config.metadata_cache_size, app_config.metadata_cache_ttl));
+        let mut http_client = reqwest::Client::builder();
+        if let Some(proxy) = app_config.proxy.as_ref() {
+            http_client = http_client.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        let http_client = http_client.build()?;
         Ok(Self {
             app_config: Arc::new(app_config),
-            db_pool, 
+            job_store,
             worker_thread_pool,
             download_cache,
             transcode_cache,
+            hls_cache,
+            job_events: JobEventBus::new(),
             metadata_cache,
+            http_client,
+            running_maintenance_tasks: Arc::new(DashMap::<MaintenanceTask, i64>::new()),
+            tool_versions: Arc::new(tool_versions),
         })
     }
 }
@@ -0,0 +1,391 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use futures_util::Stream;
+use thiserror::Error;
+
+// NOTE: We only write the original zip format without zip64 extensions
+const MAX_ENTRIES: usize = u16::MAX as usize;
+const MAX_ARCHIVE_SIZE: u64 = u32::MAX as u64;
+const CHUNK_SIZE: usize = 64*1024;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const LOCAL_HEADER_SIZE: u64 = 30;
+const DATA_DESCRIPTOR_SIZE: u64 = 16;
+const CENTRAL_HEADER_SIZE: u64 = 46;
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+const VERSION: u16 = 20;
+// NOTE: bit 3 means sizes and crc are in the data descriptor after the file, bit 11 means utf8 filenames
+const FLAGS: u16 = (1 << 3) | (1 << 11);
+const METHOD_STORE: u16 = 0;
+
+#[derive(Debug,Error)]
+pub enum ArchiveError {
+    #[error("Too many files in archive: max={max}, given={given}")]
+    TooManyFiles { max: usize, given: usize },
+    #[error("Archive is too large: max={max}, given={given}")]
+    TooLarge { max: u64, given: u64 },
+}
+
+#[derive(Clone,Debug)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl ArchiveEntry {
+    pub fn open(name: String, path: PathBuf) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(&path)?;
+        Ok(Self {
+            name,
+            path,
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+        })
+    }
+}
+
+#[derive(Clone,Copy,Debug)]
+enum Stage {
+    LocalHeader,
+    FileData,
+    DataDescriptor,
+    CentralDirectory,
+    Done,
+}
+
+/// Store-only zip archive which is generated chunk by chunk as it is read
+/// Files are not compressed since transcoded audio is already compressed
+pub struct ZipStream {
+    entries: Vec<ArchiveEntry>,
+    stage: Stage,
+    index: usize,
+    file: Option<File>,
+    hasher: crc32fast::Hasher,
+    remaining_bytes: u64,
+    offset: u64,
+    // (crc32, offset of local header) for each written entry
+    written: Vec<(u32, u64)>,
+}
+
+impl ZipStream {
+    pub fn new(entries: Vec<ArchiveEntry>) -> Result<Self, ArchiveError> {
+        if entries.len() > MAX_ENTRIES {
+            return Err(ArchiveError::TooManyFiles { max: MAX_ENTRIES, given: entries.len() });
+        }
+        let stream = Self {
+            entries,
+            stage: Stage::LocalHeader,
+            index: 0,
+            file: None,
+            hasher: crc32fast::Hasher::new(),
+            remaining_bytes: 0,
+            offset: 0,
+            written: Vec::new(),
+        };
+        let total_size = stream.total_size();
+        if total_size > MAX_ARCHIVE_SIZE {
+            return Err(ArchiveError::TooLarge { max: MAX_ARCHIVE_SIZE, given: total_size });
+        }
+        Ok(stream)
+    }
+
+    /// Size of the archive is known upfront since we don't compress
+    pub fn total_size(&self) -> u64 {
+        let entries_size: u64 = self.entries.iter().map(|entry| {
+            let name_size = entry.name.len() as u64;
+            LOCAL_HEADER_SIZE + name_size + entry.size + DATA_DESCRIPTOR_SIZE + CENTRAL_HEADER_SIZE + name_size
+        }).sum();
+        entries_size + END_OF_CENTRAL_DIRECTORY_SIZE
+    }
+
+    fn write_local_header(&mut self) -> std::io::Result<Vec<u8>> {
+        let entry = &self.entries[self.index];
+        self.file = Some(File::open(&entry.path)?);
+        self.hasher = crc32fast::Hasher::new();
+        self.remaining_bytes = entry.size;
+        self.written.push((0, self.offset));
+        let (time, date) = get_dos_time(entry.modified);
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        buffer.extend(VERSION.to_le_bytes());
+        buffer.extend(FLAGS.to_le_bytes());
+        buffer.extend(METHOD_STORE.to_le_bytes());
+        buffer.extend(time.to_le_bytes());
+        buffer.extend(date.to_le_bytes());
+        buffer.extend(0u32.to_le_bytes()); // crc32
+        buffer.extend(0u32.to_le_bytes()); // compressed size
+        buffer.extend(0u32.to_le_bytes()); // uncompressed size
+        buffer.extend((entry.name.len() as u16).to_le_bytes());
+        buffer.extend(0u16.to_le_bytes()); // extra field length
+        buffer.extend(entry.name.as_bytes());
+        Ok(buffer)
+    }
+
+    fn write_file_data(&mut self) -> std::io::Result<Vec<u8>> {
+        let total_bytes = CHUNK_SIZE.min(self.remaining_bytes as usize);
+        let mut buffer = vec![0u8; total_bytes];
+        let file = self.file.as_mut().expect("File should be opened by local header");
+        file.read_exact(buffer.as_mut_slice())?;
+        self.hasher.update(buffer.as_slice());
+        self.remaining_bytes -= total_bytes as u64;
+        Ok(buffer)
+    }
+
+    fn write_data_descriptor(&mut self) -> Vec<u8> {
+        let entry = &self.entries[self.index];
+        let crc = std::mem::take(&mut self.hasher).finalize();
+        self.file = None;
+        self.written.last_mut().expect("Local header should be written").0 = crc;
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend(DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        buffer.extend(crc.to_le_bytes());
+        buffer.extend((entry.size as u32).to_le_bytes());
+        buffer.extend((entry.size as u32).to_le_bytes());
+        buffer
+    }
+
+    fn write_central_directory(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        for (entry, &(crc, offset)) in self.entries.iter().zip(self.written.iter()) {
+            let (time, date) = get_dos_time(entry.modified);
+            buffer.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            buffer.extend(VERSION.to_le_bytes()); // version made by
+            buffer.extend(VERSION.to_le_bytes()); // version needed to extract
+            buffer.extend(FLAGS.to_le_bytes());
+            buffer.extend(METHOD_STORE.to_le_bytes());
+            buffer.extend(time.to_le_bytes());
+            buffer.extend(date.to_le_bytes());
+            buffer.extend(crc.to_le_bytes());
+            buffer.extend((entry.size as u32).to_le_bytes());
+            buffer.extend((entry.size as u32).to_le_bytes());
+            buffer.extend((entry.name.len() as u16).to_le_bytes());
+            buffer.extend(0u16.to_le_bytes()); // extra field length
+            buffer.extend(0u16.to_le_bytes()); // comment length
+            buffer.extend(0u16.to_le_bytes()); // disk number
+            buffer.extend(0u16.to_le_bytes()); // internal attributes
+            buffer.extend(0u32.to_le_bytes()); // external attributes
+            buffer.extend((offset as u32).to_le_bytes());
+            buffer.extend(entry.name.as_bytes());
+        }
+        let total_entries = self.entries.len() as u16;
+        let central_directory_size = buffer.len() as u32;
+        buffer.extend(END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        buffer.extend(0u16.to_le_bytes()); // disk number
+        buffer.extend(0u16.to_le_bytes()); // disk with central directory
+        buffer.extend(total_entries.to_le_bytes());
+        buffer.extend(total_entries.to_le_bytes());
+        buffer.extend(central_directory_size.to_le_bytes());
+        buffer.extend((self.offset as u32).to_le_bytes());
+        buffer.extend(0u16.to_le_bytes()); // comment length
+        buffer
+    }
+}
+
+impl Iterator for ZipStream {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let chunk = match self.stage {
+                Stage::LocalHeader => {
+                    if self.index >= self.entries.len() {
+                        self.stage = Stage::CentralDirectory;
+                        continue;
+                    }
+                    self.stage = Stage::FileData;
+                    self.write_local_header()
+                },
+                Stage::FileData => {
+                    if self.remaining_bytes == 0 {
+                        self.stage = Stage::DataDescriptor;
+                        continue;
+                    }
+                    self.write_file_data()
+                },
+                Stage::DataDescriptor => {
+                    let chunk = self.write_data_descriptor();
+                    self.index += 1;
+                    self.stage = Stage::LocalHeader;
+                    Ok(chunk)
+                },
+                Stage::CentralDirectory => {
+                    self.stage = Stage::Done;
+                    Ok(self.write_central_directory())
+                },
+                Stage::Done => return None,
+            };
+            match chunk {
+                Ok(ref chunk) => self.offset += chunk.len() as u64,
+                // NOTE: The archive is unusable after an error so we stop here
+                Err(_) => self.stage = Stage::Done,
+            }
+            return Some(chunk);
+        }
+    }
+}
+
+impl ZipStream {
+    /// Generates each chunk on the blocking thread pool since reading the files would stall the runtime serving the response
+    pub fn into_stream(self) -> impl Stream<Item = std::io::Result<Vec<u8>>> {
+        futures_util::stream::unfold(Some(self), |stream| async move {
+            let mut stream = stream?;
+            let res = tokio::task::spawn_blocking(move || {
+                let chunk = stream.next();
+                (chunk, stream)
+            }).await;
+            match res {
+                Ok((Some(chunk), stream)) => Some((chunk, Some(stream))),
+                Ok((None, _)) => None,
+                Err(err) => Some((Err(std::io::Error::other(err)), None)),
+            }
+        })
+    }
+}
+
+/// Converts to the (time, date) format used by MS-DOS which is limited to 1980 to 2107
+fn get_dos_time(time: SystemTime) -> (u16, u16) {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+    let days = seconds / 86400;
+    let seconds = seconds % 86400;
+    // NOTE: Converts days since unix epoch into a civil date (http://howardhinnant.github.io/date_algorithms.html)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era/1460 + day_of_era/36524 - day_of_era/146096) / 365;
+    let day_of_year = day_of_era - (365*year_of_era + year_of_era/4 - year_of_era/100);
+    let mp = (5*day_of_year + 2)/153;
+    let day = day_of_year - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era*400 + if month <= 2 { 1 } else { 0 };
+    if !(1980..=2107).contains(&year) {
+        return (0, (1 << 5) | 1);
+    }
+    let time = ((seconds / 3600) << 11) | (((seconds % 3600) / 60) << 5) | ((seconds % 60) / 2);
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::path::Path;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use futures_util::StreamExt;
+    use super::*;
+
+    // 2024-02-29 13:45:58 UTC
+    const MODIFIED_UNIX_TIME: u64 = 1709214358;
+
+    fn create_entry(directory: &Path, name: &str, data: &[u8]) -> ArchiveEntry {
+        let path = directory.join(format!("{0}.bin", data.len()));
+        std::fs::write(&path, data).unwrap();
+        let mut entry = ArchiveEntry::open(name.to_owned(), path).unwrap();
+        entry.modified = UNIX_EPOCH + Duration::from_secs(MODIFIED_UNIX_TIME);
+        entry
+    }
+
+    fn create_entries(directory: &Path) -> Vec<(String, Vec<u8>, ArchiveEntry)> {
+        let files: [(&str, Vec<u8>); 3] = [
+            ("empty.mp3", Vec::new()),
+            ("Rick Astley - Never Gonna Give You Up.mp3", (0..200_000u32).map(|i| (i % 251) as u8).collect()),
+            // NOTE: Spans more than one chunk and has a utf8 name
+            ("夜に駆ける.m4a", (0..(3*CHUNK_SIZE as u32 + 7)).map(|i| (i % 13) as u8).collect()),
+        ];
+        files.into_iter()
+            .map(|(name, data)| {
+                let entry = create_entry(directory, name, data.as_slice());
+                (name.to_owned(), data, entry)
+            })
+            .collect()
+    }
+
+    fn read_back(archive: Vec<u8>, files: &[(String, Vec<u8>, ArchiveEntry)]) {
+        let mut reader = zip::ZipArchive::new(Cursor::new(archive)).expect("Archive should be readable");
+        assert_eq!(reader.len(), files.len());
+        for (index, (name, data, _)) in files.iter().enumerate() {
+            let mut file = reader.by_index(index).unwrap();
+            assert_eq!(file.name(), name.as_str());
+            assert_eq!(file.compression(), zip::CompressionMethod::Stored);
+            assert_eq!(file.size(), data.len() as u64);
+            let modified = file.last_modified().unwrap();
+            assert_eq!(
+                (modified.year(), modified.month(), modified.day(), modified.hour(), modified.minute(), modified.second()),
+                (2024, 2, 29, 13, 45, 58),
+            );
+            // NOTE: The reader checks the crc once the file is read to the end
+            let mut given = Vec::new();
+            file.read_to_end(&mut given).expect("File should match its crc");
+            assert_eq!(&given, data);
+        }
+    }
+
+    #[test]
+    fn archive_is_read_back() {
+        let directory = tempfile::tempdir().unwrap();
+        let files = create_entries(directory.path());
+        let stream = ZipStream::new(files.iter().map(|(_, _, entry)| entry.clone()).collect()).unwrap();
+        let total_size = stream.total_size();
+        let archive: Vec<u8> = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().concat();
+        assert_eq!(archive.len() as u64, total_size);
+        read_back(archive, files.as_slice());
+    }
+
+    #[tokio::test]
+    async fn archive_is_streamed() {
+        let directory = tempfile::tempdir().unwrap();
+        let files = create_entries(directory.path());
+        let stream = ZipStream::new(files.iter().map(|(_, _, entry)| entry.clone()).collect()).unwrap();
+        let chunks: Vec<std::io::Result<Vec<u8>>> = stream.into_stream().collect().await;
+        let archive = chunks.into_iter().collect::<std::io::Result<Vec<_>>>().unwrap().concat();
+        read_back(archive, files.as_slice());
+    }
+
+    #[test]
+    fn empty_archive_is_read_back() {
+        let archive: Vec<u8> = ZipStream::new(Vec::new()).unwrap().map(|chunk| chunk.unwrap()).collect::<Vec<_>>().concat();
+        assert_eq!(archive.len() as u64, END_OF_CENTRAL_DIRECTORY_SIZE);
+        read_back(archive, &[]);
+    }
+
+    #[test]
+    fn missing_file_ends_archive() {
+        let directory = tempfile::tempdir().unwrap();
+        let entry = create_entry(directory.path(), "missing.mp3", &[1, 2, 3]);
+        std::fs::remove_file(&entry.path).unwrap();
+        let mut stream = ZipStream::new(vec![entry]).unwrap();
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn limits_are_checked() {
+        let entry = ArchiveEntry { name: "a.mp3".to_owned(), path: PathBuf::new(), size: 0, modified: UNIX_EPOCH };
+        let res = ZipStream::new(vec![entry.clone(); MAX_ENTRIES+1]);
+        assert!(matches!(res, Err(ArchiveError::TooManyFiles { max: MAX_ENTRIES, given }) if given == MAX_ENTRIES+1));
+        assert!(ZipStream::new(vec![entry.clone(); MAX_ENTRIES]).is_ok());
+        let large_entry = ArchiveEntry { size: MAX_ARCHIVE_SIZE, ..entry };
+        assert!(matches!(ZipStream::new(vec![large_entry]), Err(ArchiveError::TooLarge { max: MAX_ARCHIVE_SIZE, .. })));
+    }
+
+    #[test]
+    fn dos_time_is_converted() {
+        let time = |unix_time: u64| UNIX_EPOCH + Duration::from_secs(unix_time);
+        // 2024-02-29 13:45:58 has odd seconds rounded down to 2 second steps
+        assert_eq!(get_dos_time(time(MODIFIED_UNIX_TIME)), ((13 << 11) | (45 << 5) | 29, (44 << 9) | (2 << 5) | 29));
+        assert_eq!(get_dos_time(time(MODIFIED_UNIX_TIME+1)), ((13 << 11) | (45 << 5) | 29, (44 << 9) | (2 << 5) | 29));
+        // 1980-01-01 00:00:00 and 2107-12-31 23:59:58 are the limits
+        assert_eq!(get_dos_time(time(315532800)), (0, (1 << 5) | 1));
+        assert_eq!(get_dos_time(time(4354819198)), ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31));
+        // Times outside of the range use the earliest date
+        assert_eq!(get_dos_time(time(315532799)), (0, (1 << 5) | 1));
+        assert_eq!(get_dos_time(time(4354819200)), (0, (1 << 5) | 1));
+        assert_eq!(get_dos_time(SystemTime::UNIX_EPOCH), (0, (1 << 5) | 1));
+    }
+}
@@ -0,0 +1,218 @@
+use std::future::{ready, Ready};
+use actix_web::{
+    dev::Payload,
+    http::{header::{ContentType, AUTHORIZATION}, StatusCode},
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use thiserror::Error;
+use crate::app::AppState;
+use crate::database::{AuthTokenKind, UserRow};
+use crate::error_code::ErrorCode;
+use crate::job_store::{JobStoreError, SharedJobStore};
+use crate::util::get_unix_time;
+
+pub const SESSION_COOKIE: &str = "session";
+pub const SESSION_DURATION_SECONDS: u64 = 30*24*60*60;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+const TOKEN_BYTES: usize = 32;
+const SALT_BYTES: usize = 16;
+
+#[derive(Debug,Error)]
+pub enum AuthError {
+    #[error("Missing session cookie or bearer token")]
+    MissingToken,
+    #[error("Invalid or expired token")]
+    InvalidToken,
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Admin role is required")]
+    AdminRequired,
+    #[error("Username must be 1 to 32 characters of [a-zA-Z0-9_.-]: {0}")]
+    InvalidUsername(String),
+    #[error("Password must be at least {MIN_PASSWORD_LENGTH} characters")]
+    InvalidPassword,
+    #[error("Authentication is disabled")]
+    Disabled,
+    #[error("Failed to generate random bytes: {0}")]
+    Random(#[from] getrandom::Error),
+    #[error("Failed to hash password: {0}")]
+    PasswordHash(argon2::password_hash::Error),
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+#[derive(Serialize)]
+struct AuthErrorResponse {
+    error: String,
+    code: ErrorCode,
+}
+
+impl ResponseError for AuthError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .json(AuthErrorResponse { error: self.to_string(), code: self.code() })
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingToken | Self::InvalidToken | Self::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            Self::AdminRequired => StatusCode::FORBIDDEN,
+            Self::InvalidUsername(_) | Self::InvalidPassword | Self::Disabled => StatusCode::BAD_REQUEST,
+            Self::Random(_) | Self::PasswordHash(_) | Self::JobStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl AuthError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::MissingToken | Self::InvalidToken | Self::InvalidCredentials => ErrorCode::Unauthorized,
+            Self::AdminRequired => ErrorCode::Forbidden,
+            Self::InvalidUsername(_) | Self::InvalidPassword | Self::Disabled => ErrorCode::InvalidRequest,
+            Self::Random(_) | Self::PasswordHash(_) | Self::JobStore(_) => ErrorCode::InternalError,
+        }
+    }
+}
+
+pub fn validate_username(username: &str) -> Result<(), AuthError> {
+    lazy_static! {
+        static ref USERNAME_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_.\-]{1,32}$").unwrap();
+    }
+    if !USERNAME_REGEX.is_match(username) {
+        return Err(AuthError::InvalidUsername(username.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn validate_password(password: &str) -> Result<(), AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AuthError::InvalidPassword);
+    }
+    Ok(())
+}
+
+/// Argon2 hash in the PHC string format which includes its salt and parameters
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let mut salt = [0u8; SALT_BYTES];
+    getrandom::getrandom(&mut salt)?;
+    let salt = SaltString::encode_b64(&salt).map_err(AuthError::PasswordHash)?;
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt).map_err(AuthError::PasswordHash)?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+    Argon2::default().verify_password(password.as_bytes(), &password_hash).is_ok()
+}
+
+// NOTE: Tokens are random so a fast hash is enough unlike passwords which can be guessed
+fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a token for the user and returns it, since only its hash is stored it can't be retrieved later
+pub fn create_auth_token(
+    job_store: &SharedJobStore, user_id: i64, kind: AuthTokenKind, name: Option<&str>,
+) -> Result<(i64, String), AuthError> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes)?;
+    let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    let expire_unix_time = match kind {
+        AuthTokenKind::Session => Some(get_unix_time() + SESSION_DURATION_SECONDS),
+        AuthTokenKind::Api => None,
+    };
+    let id = job_store.insert_auth_token_entry(hash_token(token.as_str()).as_str(), user_id, kind, name, expire_unix_time)?;
+    Ok((id, token))
+}
+
+/// Scripts pass an api token in the Authorization header while the web ui uses the session cookie
+fn get_request_token(req: &HttpRequest) -> Option<String> {
+    let bearer = req.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_owned());
+    bearer.or_else(|| req.cookie(SESSION_COOKIE).map(|cookie| cookie.value().to_owned()))
+}
+
+#[derive(Clone,Debug,Serialize)]
+pub struct AuthUser {
+    pub id: i64,
+    pub username: String,
+    pub is_admin: bool,
+    /// Token used to authenticate the request so it can be revoked on logout
+    #[serde(skip)]
+    pub token_id: i64,
+}
+
+/// User making the request which is only missing when authentication is disabled
+#[derive(Clone,Debug)]
+pub struct Identity {
+    pub user: Option<AuthUser>,
+}
+
+impl Identity {
+    /// Owner given to entries created by this request
+    pub fn owner(&self) -> Option<i64> {
+        self.user.as_ref().map(|user| user.id)
+    }
+
+    // NOTE: Without authentication there is a single user who manages everything
+    pub fn is_admin(&self) -> bool {
+        self.user.as_ref().map(|user| user.is_admin).unwrap_or(true)
+    }
+
+    pub fn require_admin(&self) -> Result<(), AuthError> {
+        if !self.is_admin() {
+            return Err(AuthError::AdminRequired);
+        }
+        Ok(())
+    }
+
+    /// Entries without an owner are shared with everyone
+    pub fn can_access(&self, owner: Option<i64>) -> bool {
+        self.is_admin() || owner.is_none() || owner == self.owner()
+    }
+
+    /// Only admins can modify shared entries
+    pub fn can_modify(&self, owner: Option<i64>) -> bool {
+        self.is_admin() || (owner.is_some() && owner == self.owner())
+    }
+}
+
+pub fn authenticate(app: &AppState, req: &HttpRequest) -> Result<Identity, AuthError> {
+    if !app.app_config.auth_enabled {
+        return Ok(Identity { user: None });
+    }
+    let token = get_request_token(req).ok_or(AuthError::MissingToken)?;
+    let entry = app.job_store.select_auth_token_entry(hash_token(token.as_str()).as_str())?;
+    let entry = entry.ok_or(AuthError::InvalidToken)?;
+    if entry.expire_unix_time.is_some_and(|expire_unix_time| expire_unix_time <= get_unix_time()) {
+        app.job_store.delete_auth_token_entry(entry.id)?;
+        return Err(AuthError::InvalidToken);
+    }
+    let user: UserRow = app.job_store.select_user_entry(entry.user_id)?.ok_or(AuthError::InvalidToken)?;
+    Ok(Identity {
+        user: Some(AuthUser { id: user.id, username: user.username, is_admin: user.is_admin, token_id: entry.id }),
+    })
+}
+
+impl FromRequest for Identity {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let app = req.app_data::<AppState>().unwrap();
+        ready(authenticate(app, req))
+    }
+}
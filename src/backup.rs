@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::app::{AppConfig, AppState};
+use crate::data_files::{resolve_data_path, DataFileError};
+use crate::database::{TableRow, WorkerStatus, EXPORT_TABLES};
+use crate::job_store::JobStoreError;
+use crate::storage::is_object_url;
+use crate::util::get_unix_time;
+
+// NOTE: These tables reference files that were written by the workers
+const FILE_TABLES: &[&str] = &["ytdlp", "ffmpeg"];
+const PATH_COLUMNS: &[&str] = &["stdout_log_path", "stderr_log_path", "system_log_path", "audio_path"];
+
+#[derive(Debug,Error)]
+pub enum BackupError {
+    #[error("Export has schema version {given} but the database has version {expected}")]
+    SchemaVersion { given: usize, expected: usize },
+    #[error("Unknown table: {0}")]
+    UnknownTable(String),
+    #[error("Invalid column name: table={table}, column={column}")]
+    InvalidColumn { table: String, column: String },
+    #[error("Files referenced by the export are missing, pass allow_missing=true to skip their entries: {0:?}")]
+    MissingFiles(Vec<String>),
+    #[error("Jobs are still running, wait for them to finish before importing")]
+    JobsRunning,
+    #[error("Path of imported entry is invalid: {0}")]
+    DataFile(#[from] DataFileError),
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+/// Contents of every table so the library can be backed up or moved to another machine
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct DatabaseExport {
+    pub schema_version: usize,
+    pub unix_time: u64,
+    pub tables: BTreeMap<String, Vec<TableRow>>,
+}
+
+#[derive(Clone,Debug,Default,Serialize)]
+pub struct ImportSummary {
+    pub total_rows: usize,
+    /// Finished entries that were skipped since their audio file is missing
+    pub missing_files: Vec<String>,
+}
+
+/// Paths are stored relative to the data directory so the export can be imported into another one
+// NOTE: Separators are always forward slashes so exports from windows can be imported elsewhere
+fn make_paths_relative(app_config: &AppConfig, row: &mut TableRow) {
+    for &column in PATH_COLUMNS {
+        let Some(path) = row.get(column).and_then(|path| path.as_str()) else { continue; };
+        let Ok(relative_path) = Path::new(path).strip_prefix(&app_config.data) else { continue; };
+        let relative_path = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        row.insert(column.to_owned(), relative_path.into());
+    }
+}
+
+pub fn export_database(app: &AppState) -> Result<DatabaseExport, BackupError> {
+    let mut tables = BTreeMap::new();
+    for &table in EXPORT_TABLES {
+        let mut rows = app.job_store.select_table_rows(table)?;
+        if FILE_TABLES.contains(&table) {
+            rows.iter_mut().for_each(|row| make_paths_relative(&app.app_config, row));
+        }
+        tables.insert(table.to_owned(), rows);
+    }
+    Ok(DatabaseExport {
+        schema_version: app.job_store.select_schema_version()?,
+        unix_time: get_unix_time(),
+        tables,
+    })
+}
+
+fn is_valid_column(column: &str) -> bool {
+    !column.is_empty() && column.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Resolves the paths of the entry against the data directory and gives the audio path if its file is missing
+// NOTE: Paths are rejected if they are outside the data directory since the entry's files would be served from them
+fn resolve_row_paths(app_config: &AppConfig, row: &mut TableRow) -> Result<Option<String>, BackupError> {
+    let status = row.get("status").and_then(|status| status.as_i64());
+    let is_finished = status == Some(WorkerStatus::Finished as i64);
+    let mut missing_file = None;
+    for &column in PATH_COLUMNS {
+        let Some(path) = row.get(column).and_then(|path| path.as_str()).map(|path| path.to_owned()) else { continue; };
+        // NOTE: Uploaded files live in the bucket so they can't be checked from here
+        if is_object_url(path.as_str()) {
+            continue;
+        }
+        // NOTE: Older exports stored the path with the data directory it was exported from
+        let relative_path = match Path::new(path.as_str()).strip_prefix(&app_config.data) {
+            Ok(relative_path) => relative_path.to_string_lossy().into_owned(),
+            Err(_) => path.clone(),
+        };
+        match resolve_data_path(app_config, relative_path.as_str()) {
+            Ok(_) => {},
+            // NOTE: Entries are only usable once finished so unfinished ones don't need their files
+            Err(DataFileError::NotFound(_)) => {
+                if column == "audio_path" && is_finished {
+                    missing_file = Some(path);
+                }
+            },
+            Err(err) => return Err(err.into()),
+        }
+        // NOTE: Workers store paths under the configured data directory so lookups by path find imported entries too
+        let full_path = app_config.data.join(relative_path);
+        row.insert(column.to_owned(), full_path.to_string_lossy().into_owned().into());
+    }
+    Ok(missing_file)
+}
+
+/// Checks the export can be imported and removes entries whose files are missing if allowed
+pub fn validate_export(app_config: &AppConfig, export: &mut DatabaseExport, allow_missing: bool) -> Result<Vec<String>, BackupError> {
+    for (table, rows) in export.tables.iter() {
+        if !EXPORT_TABLES.contains(&table.as_str()) {
+            return Err(BackupError::UnknownTable(table.clone()));
+        }
+        let invalid_column = rows.iter().flat_map(|row| row.keys()).find(|column| !is_valid_column(column));
+        if let Some(column) = invalid_column {
+            return Err(BackupError::InvalidColumn { table: table.clone(), column: column.clone() });
+        }
+    }
+    let mut missing_files = Vec::<String>::new();
+    for &table in FILE_TABLES {
+        let Some(rows) = export.tables.get_mut(table) else { continue; };
+        let mut valid_rows = Vec::with_capacity(rows.len());
+        for mut row in rows.drain(..) {
+            match resolve_row_paths(app_config, &mut row)? {
+                Some(path) => missing_files.push(path),
+                None => valid_rows.push(row),
+            }
+        }
+        *rows = valid_rows;
+    }
+    if !allow_missing && !missing_files.is_empty() {
+        return Err(BackupError::MissingFiles(missing_files));
+    }
+    add_placeholder_downloads(export);
+    Ok(missing_files)
+}
+
+/// Transcodes must have a download so one without any files is added for transcodes whose download is missing
+// NOTE: Older exports can have these since deleting a download used to keep its transcodes
+fn add_placeholder_downloads(export: &mut DatabaseExport) {
+    let get_video_id = |row: &TableRow| row.get("video_id").and_then(|id| id.as_str()).map(|id| id.to_owned());
+    let download_ids: BTreeSet<String> = export.tables.get("ytdlp").into_iter().flatten().filter_map(get_video_id).collect();
+    let mut placeholders = BTreeMap::<String, TableRow>::new();
+    for row in export.tables.get("ffmpeg").into_iter().flatten() {
+        let Some(video_id) = get_video_id(row) else { continue; };
+        if download_ids.contains(&video_id) {
+            continue;
+        }
+        placeholders.entry(video_id.clone()).or_insert_with(|| {
+            let mut placeholder = TableRow::new();
+            placeholder.insert("video_id".to_owned(), video_id.into());
+            placeholder.insert("status".to_owned(), (WorkerStatus::None as i64).into());
+            placeholder.insert("unix_time".to_owned(), row.get("unix_time").cloned().unwrap_or_default());
+            placeholder.insert("owner".to_owned(), row.get("owner").cloned().unwrap_or_default());
+            // NOTE: postgres imports rows as records where missing columns are null instead of their default
+            placeholder.insert("delete_pending".to_owned(), false.into());
+            placeholder
+        });
+    }
+    if !placeholders.is_empty() {
+        export.tables.entry("ytdlp".to_owned()).or_default().extend(placeholders.into_values());
+    }
+}
+
+fn is_any_job_running(app: &AppState) -> bool {
+    app.download_cache.iter().any(|entry| entry.lock().unwrap().worker_status.is_busy()) ||
+    app.transcode_cache.iter().any(|entry| entry.lock().unwrap().worker_status.is_busy()) ||
+    app.hls_cache.iter().any(|entry| entry.lock().unwrap().worker_status.is_busy())
+}
+
+/// Replaces the database with the export and forgets cached job states which no longer match it
+pub fn import_database(app: &AppState, mut export: DatabaseExport, allow_missing: bool) -> Result<ImportSummary, BackupError> {
+    let expected = app.job_store.select_schema_version()?;
+    if export.schema_version != expected {
+        return Err(BackupError::SchemaVersion { given: export.schema_version, expected });
+    }
+    let missing_files = validate_export(&app.app_config, &mut export, allow_missing)?;
+    if is_any_job_running(app) {
+        return Err(BackupError::JobsRunning);
+    }
+    let total_rows = app.job_store.replace_table_rows(&export.tables)?;
+    app.download_cache.clear();
+    app.transcode_cache.clear();
+    app.hls_cache.clear();
+    app.metadata_cache.clear();
+    Ok(ImportSummary { total_rows, missing_files })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn create_app_config(data: &Path) -> AppConfig {
+        let mut app_config = AppConfig::default();
+        app_config.set_data_directory(data);
+        app_config
+    }
+
+    fn create_row(audio_path: &str) -> TableRow {
+        let row = json!({ "video_id": "dQw4w9WgXcQ", "status": WorkerStatus::Finished as i64, "audio_path": audio_path });
+        row.as_object().unwrap().clone()
+    }
+
+    fn create_export(audio_path: &str) -> DatabaseExport {
+        DatabaseExport {
+            schema_version: 0,
+            unix_time: 0,
+            tables: BTreeMap::from([("ytdlp".to_owned(), vec![create_row(audio_path)])]),
+        }
+    }
+
+    #[test]
+    fn paths_are_exported_relative_to_data() {
+        let app_config = create_app_config(Path::new("./data"));
+        let mut row = create_row("./data/downloads/dQw4w9WgXcQ.m4a");
+        row.insert("system_log_path".to_owned(), "/var/log/system.log".into());
+        make_paths_relative(&app_config, &mut row);
+        assert_eq!(row["audio_path"], "downloads/dQw4w9WgXcQ.m4a");
+        assert_eq!(row["system_log_path"], "/var/log/system.log");
+    }
+
+    #[test]
+    fn paths_are_imported_into_data() {
+        let directory = tempfile::tempdir().unwrap();
+        let app_config = create_app_config(directory.path());
+        std::fs::create_dir_all(&app_config.download).unwrap();
+        std::fs::write(app_config.download.join("dQw4w9WgXcQ.m4a"), b"audio").unwrap();
+        let full_path = app_config.download.join("dQw4w9WgXcQ.m4a").to_string_lossy().into_owned();
+        for audio_path in ["downloads/dQw4w9WgXcQ.m4a", full_path.as_str()] {
+            let mut export = create_export(audio_path);
+            let missing_files = validate_export(&app_config, &mut export, false).unwrap();
+            assert!(missing_files.is_empty());
+            assert_eq!(export.tables["ytdlp"][0]["audio_path"], full_path.as_str());
+        }
+    }
+
+    #[test]
+    fn paths_outside_data_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let app_config = create_app_config(directory.path().join("data").as_path());
+        std::fs::create_dir_all(&app_config.data).unwrap();
+        std::fs::write(directory.path().join("secret.txt"), b"secret").unwrap();
+        let outside_path = directory.path().join("secret.txt").to_string_lossy().into_owned();
+        for audio_path in ["../secret.txt", outside_path.as_str()] {
+            let mut export = create_export(audio_path);
+            let res = validate_export(&app_config, &mut export, true);
+            assert!(matches!(res, Err(BackupError::DataFile(DataFileError::OutsideDirectory(_)))), "{audio_path}: {res:?}");
+        }
+    }
+
+    #[test]
+    fn missing_files_are_skipped_if_allowed() {
+        let directory = tempfile::tempdir().unwrap();
+        let app_config = create_app_config(directory.path());
+        let mut export = create_export("downloads/dQw4w9WgXcQ.m4a");
+        assert!(matches!(validate_export(&app_config, &mut export, false), Err(BackupError::MissingFiles(_))));
+        let mut export = create_export("downloads/dQw4w9WgXcQ.m4a");
+        let missing_files = validate_export(&app_config, &mut export, true).unwrap();
+        assert_eq!(missing_files, vec!["downloads/dQw4w9WgXcQ.m4a".to_owned()]);
+        assert!(export.tables["ytdlp"].is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use crate::ytdlp::validate_limit_rate;
+
+#[derive(Debug,Error)]
+pub enum BandwidthScheduleError {
+    #[error("Rate limit window must be formatted as HH:MM-HH:MM=RATE: {0}")]
+    InvalidWindow(String),
+    #[error("Rate limit window must have a number with an optional K/M/G suffix as its rate: {0}")]
+    InvalidRate(String),
+}
+
+/// Caps the download rate of yt-dlp between two times of the day in UTC (e.g. 08:00-23:00=2M)
+// NOTE: A window that ends before it starts wraps past midnight such as 22:00-06:00 or 08:00-00:00
+#[derive(Clone,Debug)]
+pub struct RateLimitWindow {
+    expression: String,
+    start_minute: u64,
+    end_minute: u64,
+    limit_rate: String,
+}
+
+fn parse_time_of_day(value: &str) -> Option<u64> {
+    let (hours, minutes) = value.split_once(':')?;
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(hours*60 + minutes)
+}
+
+/// Converts a rate such as 2.5M into bytes per second so rates with different suffixes can be compared
+fn get_rate_bytes(rate: &str) -> f64 {
+    let (value, scale) = match rate.as_bytes().last() {
+        Some(b'K') => (&rate[..rate.len()-1], 1024.0),
+        Some(b'M') => (&rate[..rate.len()-1], 1024.0*1024.0),
+        Some(b'G') => (&rate[..rate.len()-1], 1024.0*1024.0*1024.0),
+        _ => (rate, 1.0),
+    };
+    value.parse::<f64>().unwrap_or(f64::MAX) * scale
+}
+
+impl RateLimitWindow {
+    pub fn parse(expression: &str) -> Result<Self, BandwidthScheduleError> {
+        let invalid = || BandwidthScheduleError::InvalidWindow(expression.to_owned());
+        let (times, limit_rate) = expression.trim().split_once('=').ok_or_else(invalid)?;
+        let (start, end) = times.split_once('-').ok_or_else(invalid)?;
+        let start_minute = parse_time_of_day(start.trim()).ok_or_else(invalid)?;
+        let end_minute = parse_time_of_day(end.trim()).ok_or_else(invalid)?;
+        if start_minute == end_minute {
+            return Err(invalid());
+        }
+        let limit_rate = limit_rate.trim();
+        validate_limit_rate(limit_rate).map_err(|_| BandwidthScheduleError::InvalidRate(expression.to_owned()))?;
+        Ok(Self {
+            expression: format!("{0}-{1}={limit_rate}", start.trim(), end.trim()),
+            start_minute,
+            end_minute,
+            limit_rate: limit_rate.to_owned(),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.expression.as_str()
+    }
+
+    fn contains(&self, minute_of_day: u64) -> bool {
+        if self.start_minute < self.end_minute {
+            minute_of_day >= self.start_minute && minute_of_day < self.end_minute
+        } else {
+            minute_of_day >= self.start_minute || minute_of_day < self.end_minute
+        }
+    }
+}
+
+/// Download rate caps that change with the time of day, outside of every window downloads run at their own rate
+#[derive(Clone,Debug,Default)]
+pub struct BandwidthSchedule {
+    windows: Vec<RateLimitWindow>,
+}
+
+/// Admins can replace the schedule while the server is running
+pub type SharedBandwidthSchedule = Arc<RwLock<BandwidthSchedule>>;
+
+impl BandwidthSchedule {
+    pub fn parse(expressions: &[String]) -> Result<Self, BandwidthScheduleError> {
+        let windows = expressions.iter().map(|expression| RateLimitWindow::parse(expression)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { windows })
+    }
+
+    pub fn windows(&self) -> &[RateLimitWindow] {
+        self.windows.as_slice()
+    }
+
+    /// Rate cap at the time, where the first matching window is used if they overlap
+    pub fn get_limit_rate(&self, unix_time: u64) -> Option<&str> {
+        let minute_of_day = (unix_time % 86400) / 60;
+        self.windows.iter()
+            .find(|window| window.contains(minute_of_day))
+            .map(|window| window.limit_rate.as_str())
+    }
+
+    /// Lowers the rate of a download to the cap at the time, slower rates set by the server or request are kept
+    // NOTE: yt-dlp can't change its rate once started so running downloads keep the rate they started with
+    pub fn apply(&self, limit_rate: Option<String>, unix_time: u64) -> Option<String> {
+        let Some(cap) = self.get_limit_rate(unix_time) else {
+            return limit_rate;
+        };
+        match limit_rate {
+            Some(rate) if get_rate_bytes(rate.as_str()) <= get_rate_bytes(cap) => Some(rate),
+            _ => Some(cap.to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minute(time: &str) -> u64 {
+        parse_time_of_day(time).unwrap()
+    }
+
+    fn unix_time(time: &str) -> u64 {
+        // NOTE: Any day works since windows only depend on the time of day in UTC
+        const DAY: u64 = 20_000*86400;
+        DAY + minute(time)*60
+    }
+
+    #[test]
+    fn window_wraps_past_midnight() {
+        let window = RateLimitWindow::parse("22:00-06:00=1M").unwrap();
+        for time in ["22:00", "23:59", "00:00", "03:00", "05:59"] {
+            assert!(window.contains(minute(time)), "{time}");
+        }
+        for time in ["06:00", "12:00", "21:59"] {
+            assert!(!window.contains(minute(time)), "{time}");
+        }
+    }
+
+    #[test]
+    fn window_ends_at_midnight() {
+        let window = RateLimitWindow::parse("08:00-00:00=1M").unwrap();
+        for time in ["08:00", "12:00", "23:59"] {
+            assert!(window.contains(minute(time)), "{time}");
+        }
+        for time in ["00:00", "03:00", "07:59"] {
+            assert!(!window.contains(minute(time)), "{time}");
+        }
+    }
+
+    #[test]
+    fn windows_are_validated() {
+        let window = RateLimitWindow::parse(" 08:00 - 23:00 = 2.5M ").unwrap();
+        assert_eq!(window.as_str(), "08:00-23:00=2.5M");
+        for expression in ["08:00-08:00=1M", "00:00-00:00=1M", "08:00=1M", "08:00-23:00", "24:00-06:00=1M", "08:60-09:00=1M", "8-9=1M"] {
+            assert!(matches!(RateLimitWindow::parse(expression), Err(BandwidthScheduleError::InvalidWindow(_))), "{expression}");
+        }
+        for expression in ["08:00-23:00=", "08:00-23:00=1T", "08:00-23:00=-1M", "08:00-23:00=fast"] {
+            assert!(matches!(RateLimitWindow::parse(expression), Err(BandwidthScheduleError::InvalidRate(_))), "{expression}");
+        }
+    }
+
+    #[test]
+    fn rates_are_compared_across_suffixes() {
+        assert_eq!(get_rate_bytes("512"), 512.0);
+        assert_eq!(get_rate_bytes("2K"), 2048.0);
+        assert_eq!(get_rate_bytes("2048K"), get_rate_bytes("2M"));
+        assert_eq!(get_rate_bytes("1024M"), get_rate_bytes("1G"));
+        assert_eq!(get_rate_bytes("0.5M"), get_rate_bytes("512K"));
+    }
+
+    #[test]
+    fn schedule_keeps_slower_rates() {
+        let schedule = BandwidthSchedule::parse(&["22:00-06:00=2M".to_owned(), "20:00-23:00=100K".to_owned()]).unwrap();
+        let night = unix_time("23:30");
+        let apply = |rate: Option<&str>, unix_time: u64| schedule.apply(rate.map(str::to_owned), unix_time);
+        assert_eq!(apply(None, night).as_deref(), Some("2M"));
+        assert_eq!(apply(Some("500K"), night).as_deref(), Some("500K"));
+        assert_eq!(apply(Some("2048K"), night).as_deref(), Some("2048K"));
+        assert_eq!(apply(Some("2049K"), night).as_deref(), Some("2M"));
+        assert_eq!(apply(Some("1G"), night).as_deref(), Some("2M"));
+        // NOTE: The first matching window is used where windows overlap
+        assert_eq!(apply(Some("1M"), unix_time("22:30")).as_deref(), Some("1M"));
+        assert_eq!(apply(Some("1M"), unix_time("21:00")).as_deref(), Some("100K"));
+        // NOTE: Outside of every window the rate is left as is
+        assert_eq!(apply(None, unix_time("12:00")), None);
+        assert_eq!(apply(Some("1G"), unix_time("12:00")).as_deref(), Some("1G"));
+    }
+}
@@ -0,0 +1,173 @@
+// Stand-in for ffmpeg that reads and writes the fake audio of fake_ytdlp and prints the same progress as ffmpeg
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use ytdlp_server::fake_tools::{
+    format_time, get_arg_value, get_arg_values, get_codec_from_extension, print_line, FakeAudio, FakeToolsConfig,
+    FAKE_BITRATE, FAKE_CHUNK_BYTES, TOTAL_FAKE_CHUNKS,
+};
+
+const VERSION: &str = "ffmpeg version 7.0-fake Copyright (c) 2000-2024 the FFmpeg developers";
+const DEFAULT_SAMPLE_RATE: u64 = 44100;
+const DEFAULT_SEGMENT_SECONDS: u64 = 6;
+const MAX_VOLUME_DB: f64 = -1.5;
+const TRACK_GAIN_DB: f64 = -4.2;
+const TRACK_PEAK: f64 = 0.84;
+
+/// Reads the first input which is the audio, where pipe:0 is a partial download fed to stdin
+fn read_input(input: &str) -> std::io::Result<Option<FakeAudio>> {
+    if input == "pipe:0" || input == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        return Ok(FakeAudio::read(bytes.as_slice()));
+    }
+    Ok(FakeAudio::load(Path::new(input)))
+}
+
+fn print_invalid_input(input: &str) -> ExitCode {
+    print_line(&mut std::io::stderr(), format!("{input}: Invalid data found when processing input").as_str());
+    ExitCode::FAILURE
+}
+
+fn print_source_info(stderr: &mut impl Write, input: &str, audio: &FakeAudio) {
+    print_line(stderr, format!("Input #0, {0}, from '{input}':", audio.codec).as_str());
+    print_line(stderr, format!(
+        "  Duration: {0}, start: 0.000000, bitrate: {1} kb/s",
+        format_time(audio.duration_ms), FAKE_BITRATE / 1000,
+    ).as_str());
+    print_line(stderr, format!("  Stream #0:0: Audio: {0}, 48000 Hz, stereo, fltp", audio.codec).as_str());
+}
+
+/// Progress printed to stderr and to stdout when asked with -progress
+fn print_progress(args: &[String], config: &FakeToolsConfig, duration_ms: u64) {
+    let is_progress_stdout = get_arg_value(args, "-progress") == Some("-");
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    let step_ms = duration_ms / TOTAL_FAKE_CHUNKS as u64;
+    let speed = step_ms as f64 / config.step_delay.as_millis().max(1) as f64;
+    for step in 1..=TOTAL_FAKE_CHUNKS {
+        std::thread::sleep(config.step_delay);
+        let time_ms = step_ms*step as u64;
+        let size_kib = step*FAKE_CHUNK_BYTES / 1024;
+        print_line(&mut stderr, format!(
+            "size={size_kib:>8}KiB time={0} bitrate={1:>6.1}kbits/s speed={speed:.1}x",
+            format_time(time_ms), FAKE_BITRATE as f64 / 1000.0,
+        ).as_str());
+        if is_progress_stdout {
+            let state = if step == TOTAL_FAKE_CHUNKS { "end" } else { "continue" };
+            print_line(&mut stdout, format!(
+                "bitrate={0:>6.1}kbits/s\ntotal_size={1}\nout_time_us={2}\nout_time_ms={2}\nout_time={3}\nspeed={speed:.1}x\nprogress={state}",
+                FAKE_BITRATE as f64 / 1000.0, step*FAKE_CHUNK_BYTES, time_ms*1000, format_time(time_ms),
+            ).as_str());
+        }
+    }
+}
+
+// NOTE: The playlist is rewritten after every segment and only ends with the end tag once all segments are written
+fn write_hls(args: &[String], playlist_path: &Path, audio: &FakeAudio) -> std::io::Result<()> {
+    let segment_seconds = get_arg_value(args, "-hls_time").and_then(|time| time.parse().ok()).unwrap_or(DEFAULT_SEGMENT_SECONDS);
+    let directory = playlist_path.parent().unwrap_or(Path::new("."));
+    let segment_template = get_arg_value(args, "-hls_segment_filename")
+        .map(PathBuf::from)
+        .unwrap_or(directory.join("segment_%05d.ts"));
+    let total_segments = audio.duration_ms.div_ceil(segment_seconds*1000).max(1);
+    std::fs::create_dir_all(directory)?;
+    let mut playlist = format!("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{segment_seconds}\n#EXT-X-MEDIA-SEQUENCE:0\n");
+    for index in 0..total_segments {
+        let segment_path = PathBuf::from(segment_template.to_string_lossy().replace("%05d", format!("{index:05}").as_str()));
+        let segment_ms = (audio.duration_ms - index*segment_seconds*1000).min(segment_seconds*1000);
+        let segment = FakeAudio { codec: "aac".to_owned(), duration_ms: segment_ms, has_artwork: false };
+        std::fs::write(&segment_path, segment.get_bytes(FAKE_CHUNK_BYTES))?;
+        let segment_name = segment_path.file_name().unwrap_or_default().to_string_lossy();
+        playlist.push_str(format!("#EXTINF:{0:.6},\n{segment_name}\n", segment_ms as f64 / 1000.0).as_str());
+        std::fs::write(playlist_path, playlist.as_bytes())?;
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    std::fs::write(playlist_path, playlist.as_bytes())
+}
+
+/// Decoded mono samples of a tone that fades in and out so the waveform isn't flat
+fn write_samples(args: &[String], audio: &FakeAudio) -> std::io::Result<()> {
+    let sample_rate = get_arg_value(args, "-ar").and_then(|rate| rate.parse().ok()).unwrap_or(DEFAULT_SAMPLE_RATE);
+    let total_samples = audio.duration_ms*sample_rate / 1000;
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    for index in 0..total_samples {
+        let time = index as f64 / sample_rate as f64;
+        let envelope = (std::f64::consts::PI * index as f64 / total_samples as f64).sin();
+        let sample = (envelope * (2.0*std::f64::consts::PI*440.0*time).sin() * i16::MAX as f64 * 0.8) as i16;
+        stdout.write_all(&sample.to_le_bytes())?;
+    }
+    stdout.flush()
+}
+
+fn run(args: &[String], config: &FakeToolsConfig) -> std::io::Result<ExitCode> {
+    let mut stderr = std::io::stderr();
+    let Some(input) = get_arg_value(args, "-i") else {
+        print_line(&mut stderr, "At least one output file must be specified");
+        return Ok(ExitCode::FAILURE);
+    };
+    let Some(audio) = read_input(input)? else {
+        return Ok(print_invalid_input(input));
+    };
+    let Some(output) = args.last() else {
+        return Ok(ExitCode::FAILURE);
+    };
+    print_source_info(&mut stderr, input, &audio);
+    // measurements and decoding write to stdout or nowhere instead of a file
+    if output == "-" {
+        let filters = get_arg_values(args, "-af").join(",");
+        match get_arg_value(args, "-f") {
+            Some("s16le") => write_samples(args, &audio)?,
+            // NOTE: Not a real fingerprint so AcoustID won't match it
+            Some("chromaprint") => print_line(&mut std::io::stdout(), format!("AQAAF{0:x}", audio.duration_ms).as_str()),
+            _ if filters.contains("volumedetect") => {
+                print_line(&mut stderr, format!("[Parsed_volumedetect_0 @ 0x0] max_volume: {MAX_VOLUME_DB:.1} dB").as_str());
+            },
+            _ if filters.contains("replaygain") => {
+                print_line(&mut stderr, format!("[Parsed_replaygain_0 @ 0x0] track_gain = {TRACK_GAIN_DB:+.2} dB").as_str());
+                print_line(&mut stderr, format!("[Parsed_replaygain_0 @ 0x0] track_peak = {TRACK_PEAK:.6}").as_str());
+            },
+            _ => {},
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let output_path = Path::new(output);
+    if output_path.exists() && !args.iter().any(|arg| arg == "-y") {
+        print_line(&mut stderr, format!("File '{output}' already exists. Exiting.").as_str());
+        return Ok(ExitCode::FAILURE);
+    }
+    print_progress(args, config, audio.duration_ms);
+    if get_arg_value(args, "-f") == Some("hls") {
+        write_hls(args, output_path, &audio)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let codec = match get_arg_value(args, "-c:a") {
+        Some("copy") => audio.codec.clone(),
+        _ => {
+            let extension = output_path.extension().unwrap_or_default().to_string_lossy();
+            get_codec_from_extension(extension.as_ref()).to_owned()
+        },
+    };
+    let has_artwork = get_arg_value(args, "-disposition:0") == Some("attached_pic");
+    let transcoded = FakeAudio { codec, duration_ms: audio.duration_ms, has_artwork };
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(output_path, transcoded.get_bytes(FAKE_CHUNK_BYTES*TOTAL_FAKE_CHUNKS))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|arg| arg.as_str()) == Some("-version") {
+        print_line(&mut std::io::stdout(), VERSION);
+        return ExitCode::SUCCESS;
+    }
+    match run(args.as_slice(), &FakeToolsConfig::from_env()) {
+        Ok(code) => code,
+        Err(err) => {
+            print_line(&mut std::io::stderr(), format!("Error: {err}").as_str());
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,40 @@
+// Stand-in for ffprobe that describes the fake audio written by fake_ytdlp and fake_ffmpeg
+use std::path::Path;
+use std::process::ExitCode;
+use ytdlp_server::fake_tools::{print_line, FakeAudio, FAKE_BITRATE};
+
+const VERSION: &str = "ffprobe version 7.0-fake Copyright (c) 2007-2024 the FFmpeg developers";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|arg| arg.as_str()) == Some("-version") {
+        print_line(&mut std::io::stdout(), VERSION);
+        return ExitCode::SUCCESS;
+    }
+    let Some(path) = args.last() else {
+        print_line(&mut std::io::stderr(), "You have to specify one input file.");
+        return ExitCode::FAILURE;
+    };
+    let Some(audio) = FakeAudio::load(Path::new(path)) else {
+        print_line(&mut std::io::stderr(), format!("{path}: Invalid data found when processing input").as_str());
+        return ExitCode::FAILURE;
+    };
+    let mut streams = vec![serde_json::json!({
+        "index": 0, "codec_name": audio.codec, "codec_type": "audio", "disposition": { "attached_pic": 0 },
+    })];
+    if audio.has_artwork {
+        streams.push(serde_json::json!({
+            "index": 1, "codec_name": "mjpeg", "codec_type": "video", "disposition": { "attached_pic": 1 },
+        }));
+    }
+    let output = serde_json::json!({
+        "streams": streams,
+        "format": {
+            "filename": path,
+            "duration": format!("{0:.6}", audio.duration_ms as f64 / 1000.0),
+            "bit_rate": FAKE_BITRATE.to_string(),
+        },
+    });
+    print_line(&mut std::io::stdout(), serde_json::to_string_pretty(&output).unwrap_or_default().as_str());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,199 @@
+// Stand-in for yt-dlp that prints the same output the server asks yt-dlp for, without using the network
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+use lazy_static::lazy_static;
+use regex::Regex;
+use ytdlp_server::fake_tools::{get_arg_value, get_arg_values, print_line, FakeAudio, FakeToolsConfig, FAKE_CHUNK_BYTES, TOTAL_FAKE_CHUNKS};
+use ytdlp_server::url_parse::parse_video_url;
+
+const VERSION: &str = "2024.08.06";
+const DOWNLOAD_EXT: &str = "webm";
+const DOWNLOAD_CODEC: &str = "opus";
+const EXTRACT_EXT: &str = "opus";
+const FORMAT_ID: &str = "251";
+const TOTAL_PLAYLIST_VIDEOS: usize = 5;
+
+lazy_static! {
+    static ref TEMPLATE_FIELD_REGEX: Regex = Regex::new(r"%\(([^)]+)\)s").unwrap();
+}
+
+fn get_video_id(url: &str) -> String {
+    match parse_video_url(url) {
+        Ok(video_url) => video_url.video_id.as_str().to_owned(),
+        Err(_) => url.to_owned(),
+    }
+}
+
+fn get_playlist_video_id(index: usize) -> String {
+    format!("fakevideo{index:02}")
+}
+
+/// Fills in the output template where unknown fields are NA like yt-dlp
+fn fill_template(template: &str, video_id: &str, ext: &str) -> PathBuf {
+    let path = TEMPLATE_FIELD_REGEX.replace_all(template, |captures: &regex::Captures| {
+        match &captures[1] {
+            "id" => video_id.to_owned(),
+            "ext" => ext.to_owned(),
+            "title" => format!("Fake video {video_id}"),
+            _ => "NA".to_owned(),
+        }
+    });
+    PathBuf::from(path.as_ref())
+}
+
+fn print_unavailable(video_id: &str) -> ExitCode {
+    print_line(&mut std::io::stderr(), format!("ERROR: [youtube] {video_id}: Video unavailable. This video has been removed by the uploader").as_str());
+    ExitCode::FAILURE
+}
+
+fn dump_single_json(video_id: &str, config: &FakeToolsConfig) -> ExitCode {
+    let duration = config.duration_ms / 1000;
+    let info = serde_json::json!({
+        "id": video_id,
+        "title": format!("Fake video {video_id}"),
+        "duration": duration,
+        "formats": [
+            {
+                "format_id": "140", "ext": "m4a", "acodec": "mp4a.40.2", "vcodec": "none",
+                "abr": 129.5, "asr": 44100, "filesize": duration*129_500/8, "format_note": "medium", "language": "en",
+            },
+            {
+                "format_id": FORMAT_ID, "ext": DOWNLOAD_EXT, "acodec": DOWNLOAD_CODEC, "vcodec": "none",
+                "abr": 135.2, "asr": 48000, "filesize_approx": duration*135_200/8, "format_note": "medium", "language": "en",
+            },
+            {
+                "format_id": "18", "ext": "mp4", "acodec": "mp4a.40.2", "vcodec": "avc1.42001E",
+                "abr": 96.0, "asr": 44100, "filesize": duration*500_000/8, "format_note": "360p",
+            },
+        ],
+    });
+    print_line(&mut std::io::stdout(), info.to_string().as_str());
+    ExitCode::SUCCESS
+}
+
+// NOTE: Channels are listed with a tab separated template while playlists only print ids
+fn print_flat_playlist(args: &[String], config: &FakeToolsConfig) -> ExitCode {
+    let template = get_arg_value(args, "--print").unwrap_or("id");
+    let playlist_end: usize = get_arg_value(args, "--playlist-end").and_then(|end| end.parse().ok()).unwrap_or(TOTAL_PLAYLIST_VIDEOS);
+    let mut stdout = std::io::stdout();
+    for index in 0..TOTAL_PLAYLIST_VIDEOS.min(playlist_end) {
+        let video_id = get_playlist_video_id(index);
+        if template.contains('\t') {
+            let upload_date = format!("202401{0:02}", TOTAL_PLAYLIST_VIDEOS-index);
+            let duration = config.duration_ms / 1000;
+            print_line(&mut stdout, format!("{video_id}\t{upload_date}\t{duration}\tFake video {video_id}").as_str());
+        } else {
+            print_line(&mut stdout, video_id.as_str());
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn download(args: &[String], url: &str, config: &FakeToolsConfig) -> std::io::Result<ExitCode> {
+    let video_id = get_video_id(url);
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    print_line(&mut stderr, format!("[debug] Command-line config: {args:?}").as_str());
+    print_line(&mut stdout, format!("[youtube] Extracting URL: {url}").as_str());
+    if config.fail_video_ids.contains(&video_id) {
+        return Ok(print_unavailable(video_id.as_str()));
+    }
+    // NOTE: The subtitle template is given with a subtitle: prefix after the download's template
+    let template = get_arg_values(args, "--output").into_iter().find(|template| !template.starts_with("subtitle:"));
+    let Some(template) = template else {
+        print_line(&mut stderr, "yt-dlp.exe: error: missing --output");
+        return Ok(ExitCode::from(2));
+    };
+    let download_path = fill_template(template, video_id.as_str(), DOWNLOAD_EXT);
+    let extract_path = fill_template(template, video_id.as_str(), EXTRACT_EXT);
+    let partial_path = PathBuf::from(format!("{0}.part", download_path.to_string_lossy()));
+    if let Some(parent) = download_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    print_line(&mut stdout, format!("@[download-path] {0}", download_path.to_string_lossy()).as_str());
+    print_line(&mut stdout, format!("@[before-dl-path] {0}", download_path.to_string_lossy()).as_str());
+    print_line(&mut stdout, format!("[info] {video_id}: Downloading 1 format(s): {FORMAT_ID}").as_str());
+    print_line(&mut stdout, format!("[download] Destination: {0}", download_path.to_string_lossy()).as_str());
+    // download in chunks and resume from the partial file if asked to
+    let audio = FakeAudio { codec: DOWNLOAD_CODEC.to_owned(), duration_ms: config.duration_ms, has_artwork: false };
+    let bytes = audio.get_bytes(FAKE_CHUNK_BYTES*TOTAL_FAKE_CHUNKS);
+    let is_resume = args.iter().any(|arg| arg == "--continue");
+    let mut downloaded_bytes = match std::fs::metadata(&partial_path) {
+        Ok(metadata) if is_resume => (metadata.len() as usize).min(bytes.len()),
+        _ => 0,
+    };
+    if downloaded_bytes > 0 {
+        print_line(&mut stdout, format!("[download] Resuming download at byte {downloaded_bytes}").as_str());
+    } else {
+        std::fs::write(&partial_path, [])?;
+    }
+    let start = Instant::now();
+    let mut partial_file = std::fs::OpenOptions::new().append(true).open(&partial_path)?;
+    while downloaded_bytes < bytes.len() {
+        let chunk = &bytes[downloaded_bytes..(downloaded_bytes+FAKE_CHUNK_BYTES).min(bytes.len())];
+        std::io::Write::write_all(&mut partial_file, chunk)?;
+        downloaded_bytes += chunk.len();
+        std::thread::sleep(config.step_delay);
+        let elapsed = start.elapsed().as_secs_f64().max(0.001);
+        let speed = (downloaded_bytes as f64 / elapsed) as u64;
+        let eta = (bytes.len()-downloaded_bytes) as u64 / speed.max(1);
+        print_line(&mut stdout, format!(
+            "@[progress] eta={eta},elapsed={0},downloaded_bytes={downloaded_bytes},total_bytes={1},speed={speed}",
+            elapsed as u64, bytes.len(),
+        ).as_str());
+    }
+    drop(partial_file);
+    std::fs::rename(&partial_path, &download_path)?;
+    // extract audio from the downloaded container
+    print_line(&mut stdout, format!("@[post-process-path] {0}", download_path.to_string_lossy()).as_str());
+    print_line(&mut stderr, format!("[ExtractAudio] Destination: {0}", extract_path.to_string_lossy()).as_str());
+    let extracted = FakeAudio { codec: EXTRACT_EXT.to_owned(), ..audio };
+    std::fs::write(&extract_path, extracted.get_bytes(bytes.len()))?;
+    print_line(&mut stdout, format!("Deleting original file {0} (pass -k to keep)", download_path.to_string_lossy()).as_str());
+    std::fs::remove_file(&download_path)?;
+    if args.iter().any(|arg| arg == "--write-subs") {
+        let lang = get_arg_value(args, "--sub-langs").and_then(|langs| langs.split(',').next()).unwrap_or("en");
+        let lang: String = lang.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+        let subtitle_template = get_arg_values(args, "--output").into_iter().find_map(|template| template.strip_prefix("subtitle:"));
+        if let Some(subtitle_template) = subtitle_template {
+            let subtitle_path = fill_template(subtitle_template, video_id.as_str(), format!("{lang}.vtt").as_str());
+            print_line(&mut stdout, format!("[info] Writing video subtitles to: {0}", subtitle_path.to_string_lossy()).as_str());
+            std::fs::write(&subtitle_path, "WEBVTT\n\n00:00:00.000 --> 00:00:05.000\nFake subtitle\n")?;
+        }
+    }
+    print_line(&mut stdout, format!("@[after-move-path] {0}", extract_path.to_string_lossy()).as_str());
+    print_line(&mut stdout, format!("@[duration] {0:.1}", config.duration_ms as f64 / 1000.0).as_str());
+    print_line(&mut stdout, format!("@[format-id] {FORMAT_ID}").as_str());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = FakeToolsConfig::from_env();
+    if args.iter().any(|arg| arg == "--version") {
+        print_line(&mut std::io::stdout(), VERSION);
+        return ExitCode::SUCCESS;
+    }
+    let Some(url) = args.first().filter(|arg| !arg.starts_with('-')) else {
+        print_line(&mut std::io::stderr(), "yt-dlp.exe: error: You must provide at least one URL.");
+        return ExitCode::from(2);
+    };
+    if args.iter().any(|arg| arg == "--flat-playlist") {
+        return print_flat_playlist(args.as_slice(), &config);
+    }
+    if args.iter().any(|arg| arg == "--dump-single-json") {
+        let video_id = get_video_id(url);
+        if config.fail_video_ids.contains(&video_id) {
+            return print_unavailable(video_id.as_str());
+        }
+        return dump_single_json(video_id.as_str(), &config);
+    }
+    match download(args.as_slice(), url, &config) {
+        Ok(code) => code,
+        Err(err) => {
+            print_line(&mut std::io::stderr(), format!("ERROR: {err}").as_str());
+            ExitCode::FAILURE
+        },
+    }
+}
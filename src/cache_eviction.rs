@@ -0,0 +1,67 @@
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+use dashmap::DashMap;
+use crate::app::{AppState, WorkerCacheEntry};
+use crate::database::WorkerStatus;
+use crate::util::get_unix_time;
+use crate::worker_download::DownloadState;
+use crate::worker_transcode::TranscodeState;
+
+pub const DEFAULT_FINISHED_JOB_CACHE_MINUTES: u64 = 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time the job finished at if its state can be dropped, since finished jobs are looked up in the job store again on a cache miss
+// NOTE: Failed states are kept since their reason isn't stored, they are reset once the expire_failed_jobs task forgets them
+fn get_evictable_time(worker_status: WorkerStatus, end_time_unix: u64) -> Option<u64> {
+    match worker_status {
+        WorkerStatus::None | WorkerStatus::Finished => Some(end_time_unix),
+        WorkerStatus::Queued | WorkerStatus::Running | WorkerStatus::Failed => None,
+    }
+}
+
+/// Removes states that finished before the cutoff and returns how many were removed
+fn evict_states<K: Eq + Hash, T>(
+    cache: &DashMap<K, WorkerCacheEntry<T>>, cutoff_unix_time: u64, get_finish_time: fn(&T) -> Option<u64>,
+) -> usize {
+    let mut total_evicted = 0;
+    cache.retain(|_, state| {
+        // NOTE: Pollers and workers hold their own reference which they keep updating so those states are kept
+        //       The shard is locked while retaining so nobody else can take a reference after this check
+        if Arc::strong_count(state) > 1 {
+            return true;
+        }
+        // NOTE: Don't block on the state since other threads lock it before looking up another entry in the cache
+        let Ok(state) = state.try_lock() else { return true; };
+        let is_evicted = get_finish_time(&state).is_some_and(|time| time < cutoff_unix_time);
+        if is_evicted {
+            total_evicted += 1;
+        }
+        !is_evicted
+    });
+    total_evicted
+}
+
+fn evict_finished_job_states(app: &AppState, max_age: Duration) {
+    let cutoff_unix_time = get_unix_time().saturating_sub(max_age.as_secs());
+    let total_downloads = evict_states(&app.download_cache, cutoff_unix_time, |state: &DownloadState| {
+        get_evictable_time(state.worker_status, state.end_time_unix)
+    });
+    let total_transcodes = evict_states(&app.transcode_cache, cutoff_unix_time, |state: &TranscodeState| {
+        get_evictable_time(state.worker_status, state.end_time_unix)
+    });
+    if total_downloads > 0 || total_transcodes > 0 {
+        log::debug!("Evicted finished job states: downloads={total_downloads}, transcodes={total_transcodes}");
+    }
+}
+
+/// Stops the download and transcode caches from growing for the life of the server
+pub async fn run_cache_eviction(app: AppState) {
+    let Some(max_age) = app.app_config.finished_job_cache_duration else {
+        return;
+    };
+    loop {
+        actix_web::rt::time::sleep(POLL_INTERVAL).await;
+        evict_finished_job_states(&app, max_age);
+    }
+}
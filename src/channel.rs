@@ -0,0 +1,200 @@
+use actix_web::web;
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::app::AppState;
+use crate::database::{ChannelJobRow, JobKind, WorkerStatus};
+use crate::ffmpeg::TranscodeOptions;
+use crate::job_store::JobStoreError;
+use crate::metadata::get_metadata_from_cache;
+use crate::quota::{get_quota_usage, record_conversion, QuotaError};
+use crate::worker_download::try_start_download_worker;
+use crate::worker_transcode::{try_start_transcode_worker, TranscodeKey};
+use crate::ytdlp::{list_channel_entries, ChannelEntry, DownloadOptions, PlaylistError};
+
+// NOTE: Channels can have thousands of uploads so a single request can't flood the queue with all of them
+pub const MAX_CHANNEL_VIDEOS: usize = 500;
+const MAX_TITLE_REGEX_SIZE: usize = 1 << 16;
+
+#[derive(Debug,Error)]
+pub enum ChannelError {
+    #[error("Channel id must be a UC... id or an @handle: {0}")]
+    InvalidChannelId(String),
+    #[error("Date must be formatted as YYYYMMDD: {0}")]
+    InvalidDate(String),
+    #[error("Invalid title regex: {0}")]
+    InvalidTitleRegex(#[from] regex::Error),
+    #[error("Max count must be between 1 and {max}: {given}")]
+    InvalidMaxCount { max: usize, given: usize },
+    #[error("Failed to list videos: {0}")]
+    Playlist(#[from] PlaylistError),
+    #[error("Failed to run blocking task: {0:?}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+    #[error("Invalid stored options: {0}")]
+    InvalidOptions(#[from] serde_json::Error),
+    #[error("Stopped adding videos: {0}")]
+    Quota(#[from] QuotaError),
+}
+
+pub fn validate_channel_id(channel_id: &str) -> Result<(), ChannelError> {
+    lazy_static! {
+        static ref CHANNEL_ID_REGEX: Regex = Regex::new(r"^(?:UC[\w\-]{22}|@[\w.\-]{3,30})$").unwrap();
+    }
+    if !CHANNEL_ID_REGEX.is_match(channel_id) {
+        return Err(ChannelError::InvalidChannelId(channel_id.to_owned()));
+    }
+    Ok(())
+}
+
+// NOTE: Only the videos tab is listed so shorts and past livestreams aren't included
+pub fn get_channel_url(channel_id: &str) -> String {
+    if channel_id.starts_with('@') {
+        format!("https://www.youtube.com/{channel_id}/videos")
+    } else {
+        format!("https://www.youtube.com/channel/{channel_id}/videos")
+    }
+}
+
+/// Rules for which uploads of a channel are downloaded
+#[derive(Clone,Debug,Default,Deserialize,Serialize)]
+pub struct ChannelFilters {
+    /// Uploaded on or after this date formatted as YYYYMMDD
+    pub date_after: Option<String>,
+    /// Uploaded on or before this date formatted as YYYYMMDD
+    pub date_before: Option<String>,
+    pub min_duration_seconds: Option<u64>,
+    pub title_regex: Option<String>,
+    /// Newest uploads are kept once this many match
+    pub max_count: Option<usize>,
+}
+
+impl ChannelFilters {
+    pub fn validate(&self) -> Result<(), ChannelError> {
+        for date in [&self.date_after, &self.date_before].into_iter().flatten() {
+            if date.len() != 8 || !date.chars().all(|c| c.is_ascii_digit()) {
+                return Err(ChannelError::InvalidDate(date.to_owned()));
+            }
+        }
+        self.get_title_regex()?;
+        if let Some(max_count) = self.max_count {
+            if !(1..=MAX_CHANNEL_VIDEOS).contains(&max_count) {
+                return Err(ChannelError::InvalidMaxCount { max: MAX_CHANNEL_VIDEOS, given: max_count });
+            }
+        }
+        Ok(())
+    }
+
+    fn get_title_regex(&self) -> Result<Option<Regex>, ChannelError> {
+        let Some(ref title_regex) = self.title_regex else {
+            return Ok(None);
+        };
+        let regex = RegexBuilder::new(title_regex.as_str()).size_limit(MAX_TITLE_REGEX_SIZE).build()?;
+        Ok(Some(regex))
+    }
+
+    /// Keeps the entries that match every filter in the order they were listed
+    // NOTE: Entries missing a field that is filtered on are skipped since we can't tell if they match
+    pub fn apply(&self, entries: Vec<ChannelEntry>) -> Result<Vec<ChannelEntry>, ChannelError> {
+        let title_regex = self.get_title_regex()?;
+        let max_count = self.max_count.unwrap_or(MAX_CHANNEL_VIDEOS);
+        let is_match = |entry: &ChannelEntry| -> bool {
+            let date = entry.upload_date.as_deref();
+            if let Some(ref after) = self.date_after {
+                if date.is_none_or(|date| date < after.as_str()) { return false; }
+            }
+            if let Some(ref before) = self.date_before {
+                if date.is_none_or(|date| date > before.as_str()) { return false; }
+            }
+            if let Some(min_duration) = self.min_duration_seconds {
+                if entry.duration_seconds.is_none_or(|duration| duration < min_duration) { return false; }
+            }
+            if let Some(ref title_regex) = title_regex {
+                if entry.title.as_deref().is_none_or(|title| !title_regex.is_match(title)) { return false; }
+            }
+            true
+        };
+        Ok(entries.into_iter().filter(is_match).take(max_count).collect())
+    }
+}
+
+/// Lists the uploads of the channel, newest first
+pub async fn list_channel_videos(app: &AppState, channel_id: &str) -> Result<Vec<ChannelEntry>, ChannelError> {
+    let app_config = app.app_config.clone();
+    let url = get_channel_url(channel_id);
+    let entries = web::block(move || list_channel_entries(
+        &app_config.ytdlp_binary, url.as_str(),
+        app_config.proxy.as_deref(), app_config.geo_bypass_country.as_deref(),
+    )).await??;
+    Ok(entries)
+}
+
+/// Starts download and transcode jobs for the uploads that matched the filters
+// NOTE: The quota is checked before each video since a channel can add many conversions at once
+async fn enqueue_channel_videos(app: &AppState, entry: &ChannelJobRow, quota_user_id: Option<i64>) -> Result<(), ChannelError> {
+    let filters: ChannelFilters = match entry.filters {
+        Some(ref filters) => serde_json::from_str(filters.as_str())?,
+        None => ChannelFilters::default(),
+    };
+    let transcode_options: TranscodeOptions = match entry.transcode_options {
+        Some(ref options) => serde_json::from_str(options.as_str())?,
+        None => TranscodeOptions::default(),
+    };
+    let download_options = DownloadOptions { audio_lang: transcode_options.audio_lang.clone(), ..app.app_config.download_options.clone() };
+    let channel_entries = list_channel_videos(app, entry.channel_id.as_str()).await?;
+    let total_listed = channel_entries.len() as u64;
+    app.job_store.select_and_update_channel_job_entry(entry.id, |entry| entry.total_listed = Some(total_listed))?;
+    for channel_entry in filters.apply(channel_entries)? {
+        if let Some(user_id) = quota_user_id {
+            get_quota_usage(app, user_id)?.check()?;
+        }
+        let video_id = channel_entry.video_id;
+        app.job_store.insert_channel_job_video_entry(entry.id, &video_id, channel_entry.title.as_deref())?;
+        let res = try_start_download_worker(
+            video_id.clone(),
+            app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+            download_options.clone(), entry.owner,
+        );
+        if let Err(err) = res {
+            log::error!("Channel job failed to start download: channel_job={0}, id={1}, err={2:?}", entry.id, video_id.as_str(), err);
+            continue;
+        }
+        let metadata = get_metadata_from_cache(
+            video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store,
+        ).await.ok();
+        let transcode_key = TranscodeKey::new(video_id.clone(), entry.audio_ext, &transcode_options);
+        let res = try_start_transcode_worker(
+            transcode_key.clone(), transcode_options.clone(),
+            app.download_cache.clone(), app.transcode_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(),
+            app.worker_thread_pool.clone(), metadata, entry.owner,
+        );
+        match res {
+            Ok(status) => record_conversion(app, quota_user_id, status, JobKind::Transcode, transcode_key.as_str().as_str()),
+            Err(err) => log::error!(
+                "Channel job failed to start transcode: channel_job={0}, id={1}, err={2:?}", entry.id, video_id.as_str(), err,
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Lists the channel in the background and records which videos were enqueued so progress can be queried
+pub async fn run_channel_job(app: AppState, entry: ChannelJobRow, quota_user_id: Option<i64>) {
+    let res = app.job_store.select_and_update_channel_job_entry(entry.id, |entry| entry.status = WorkerStatus::Running);
+    if let Err(err) = res {
+        log::error!("Failed to update channel job: channel_job={0}, err={1:?}", entry.id, err);
+    }
+    let res = enqueue_channel_videos(&app, &entry, quota_user_id).await;
+    if let Err(ref err) = res {
+        log::error!("Channel job failed: channel_job={0}, err={1:?}", entry.id, err);
+    }
+    let res = app.job_store.select_and_update_channel_job_entry(entry.id, |entry| {
+        entry.status = if res.is_ok() { WorkerStatus::Finished } else { WorkerStatus::Failed };
+        entry.error = res.err().map(|err| err.to_string());
+    });
+    if let Err(err) = res {
+        log::error!("Failed to update channel job: channel_job={0}, err={1:?}", entry.id, err);
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::SeekFrom;
+use actix_files::HttpRange;
+use actix_web::{
+    http::{header::{ContentDisposition, EntityTag, IfNoneMatch, IfRange, ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, RANGE}, StatusCode},
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures_util::Stream;
+use tokio::{fs::File, io::{AsyncReadExt, AsyncSeekExt}};
+
+const CHUNK_SIZE: usize = 64*1024;
+
+/// Strong validator derived from the sha256 of the file so it stays the same across servers and restores
+pub fn get_file_etag(file_hash: &str) -> EntityTag {
+    EntityTag::new_strong(file_hash.to_owned())
+}
+
+/// Strong validator of a worker state which changes whenever the state does
+pub fn get_revision_etag(revision: u64) -> EntityTag {
+    EntityTag::new_strong(format!("{revision:x}"))
+}
+
+/// Client already has this version of the file cached
+pub fn is_not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    is_etag_matched(req.get_header::<IfNoneMatch>().as_ref(), etag)
+}
+
+/// Same as is_not_modified() for when the header has to be checked away from the request (e.g. on another thread)
+pub fn is_etag_matched(if_none_match: Option<&IfNoneMatch>, etag: &EntityTag) -> bool {
+    match if_none_match {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(etag)),
+        None => false,
+    }
+}
+
+/// Range the client requested if it still refers to this version of the file
+// NOTE: A partial download of another version can't be resumed so a mismatched If-Range gets the whole file
+//       Dates are never matched since we only give out the ETag as a validator
+pub fn get_range<'a>(req: &'a HttpRequest, etag: &EntityTag) -> Option<&'a str> {
+    let range = req.headers().get(RANGE)?.to_str().ok()?;
+    if !req.headers().contains_key(IF_RANGE) {
+        return Some(range);
+    }
+    match req.get_header::<IfRange>() {
+        Some(IfRange::EntityTag(ref tag)) if tag.strong_eq(etag) => Some(range),
+        _ => None,
+    }
+}
+
+/// Reads the file in chunks without blocking the runtime serving the response
+fn read_file_chunks(file: File, length: u64) -> impl Stream<Item = std::io::Result<web::Bytes>> {
+    futures_util::stream::unfold(Some((file, length)), |state| async move {
+        let (mut file, remaining_bytes) = state?;
+        if remaining_bytes == 0 {
+            return None;
+        }
+        let mut chunk = vec![0u8; remaining_bytes.min(CHUNK_SIZE as u64) as usize];
+        if let Err(err) = file.read_exact(chunk.as_mut_slice()).await {
+            return Some((Err(err), None));
+        }
+        let remaining_bytes = remaining_bytes - chunk.len() as u64;
+        Some((Ok(web::Bytes::from(chunk)), Some((file, remaining_bytes))))
+    })
+}
+
+/// Serves the file with its ETag so interrupted downloads can be resumed with Range and If-Range
+pub async fn serve_file(
+    req: &HttpRequest, mut file: File, etag: &EntityTag, content_type: &str, content_disposition: ContentDisposition,
+) -> std::io::Result<HttpResponse> {
+    let total_bytes = file.metadata().await?.len();
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((ETAG, etag.to_string()))
+        .insert_header((ACCEPT_RANGES, "bytes"));
+    if is_not_modified(req, etag) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+    response
+        .content_type(content_type)
+        .insert_header(content_disposition);
+    let (offset, length) = match get_range(req, etag).map(|range| HttpRange::parse(range, total_bytes)) {
+        // NOTE: A header without any ranges (e.g. "bytes=") is ignored like a missing one
+        None => (0, total_bytes),
+        Some(Ok(ranges)) if ranges.is_empty() => (0, total_bytes),
+        // NOTE: Multiple ranges would need a multipart response so only the first one is served
+        Some(Ok(ranges)) => {
+            let range = ranges[0];
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .insert_header((CONTENT_RANGE, format!("bytes {0}-{1}/{total_bytes}", range.start, range.start + range.length - 1)));
+            (range.start, range.length)
+        },
+        Some(Err(_)) => {
+            return Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .insert_header((CONTENT_RANGE, format!("bytes */{total_bytes}")))
+                .finish());
+        },
+    };
+    file.seek(SeekFrom::Start(offset)).await?;
+    Ok(response
+        .no_chunking(length)
+        .streaming(read_file_chunks(file, length)))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::to_bytes, http::header::DispositionType, test::TestRequest};
+    use super::*;
+
+    const DATA: &[u8] = b"0123456789";
+
+    async fn serve(range: &str) -> (StatusCode, Vec<u8>) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), DATA).unwrap();
+        let req = TestRequest::get().insert_header((RANGE, range)).to_http_request();
+        let etag = get_file_etag("hash");
+        let content_disposition = ContentDisposition { disposition: DispositionType::Attachment, parameters: Vec::new() };
+        let file = File::open(file.path()).await.unwrap();
+        let response = serve_file(&req, file, &etag, "audio/mpeg", content_disposition).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[actix_web::test]
+    async fn empty_range_serves_whole_file() {
+        for range in ["bytes=", "bytes=,", "bytes= , "] {
+            assert_eq!(serve(range).await, (StatusCode::OK, DATA.to_vec()), "{range}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn range_is_served() {
+        assert_eq!(serve("bytes=4-").await, (StatusCode::PARTIAL_CONTENT, DATA[4..].to_vec()));
+        assert_eq!(serve("bytes=2-5").await, (StatusCode::PARTIAL_CONTENT, DATA[2..6].to_vec()));
+        assert_eq!(serve("bytes=20-").await, (StatusCode::RANGE_NOT_SATISFIABLE, Vec::new()));
+    }
+}
@@ -0,0 +1,76 @@
+use actix_cors::Cors;
+use actix_web::http::{header::{self, HeaderName}, Method};
+use thiserror::Error;
+use crate::request_id::REQUEST_ID_HEADER;
+
+pub const DEFAULT_CORS_METHODS: &str = "GET,POST,PUT,PATCH,DELETE";
+pub const DEFAULT_CORS_HEADERS: &str = "authorization,content-type,if-none-match,range,x-request-id";
+// NOTE: Browsers cap this anyway (Chrome at 2 hours) so there is no point making it configurable
+const CORS_MAX_AGE_SECONDS: usize = 60*60;
+
+#[derive(Debug,Error)]
+pub enum CorsError {
+    #[error("Invalid allowed origin {0}, expected * or a scheme and host such as https://example.com")]
+    InvalidOrigin(String),
+    #[error("Invalid allowed method: {0}")]
+    InvalidMethod(String),
+    #[error("Invalid allowed header: {0}")]
+    InvalidHeader(String),
+}
+
+/// Lets frontends hosted on other origins call the api, which is disabled when there are no allowed origins
+#[derive(Clone,Debug,Default)]
+pub struct CorsConfig {
+    /// Origins such as https://example.com or * for any origin
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+}
+
+impl CorsConfig {
+    /// Methods and headers are comma separated lists
+    pub fn parse(origins: &[String], methods: &str, headers: &str) -> Result<Self, CorsError> {
+        let allowed_origins = origins.iter().map(|origin| {
+            let origin = origin.trim().trim_end_matches('/');
+            let host = origin.strip_prefix("https://").or_else(|| origin.strip_prefix("http://"));
+            match host {
+                _ if origin == "*" => Ok(origin.to_owned()),
+                Some(host) if !host.is_empty() && !host.contains('/') => Ok(origin.to_owned()),
+                _ => Err(CorsError::InvalidOrigin(origin.to_owned())),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+        let allowed_methods = methods.split(',').map(|method| method.trim()).filter(|method| !method.is_empty()).map(|method| {
+            Method::from_bytes(method.to_ascii_uppercase().as_bytes()).map_err(|_| CorsError::InvalidMethod(method.to_owned()))
+        }).collect::<Result<Vec<_>, _>>()?;
+        let allowed_headers = headers.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()).map(|name| {
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| CorsError::InvalidHeader(name.to_owned()))
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { allowed_origins, allowed_methods, allowed_headers })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+
+    pub fn build(&self) -> Cors {
+        let is_any_origin = self.allowed_origins.iter().any(|origin| origin == "*");
+        let mut cors = Cors::default()
+            .allowed_methods(self.allowed_methods.clone())
+            .allowed_headers(self.allowed_headers.clone())
+            .expose_headers([
+                header::CONTENT_DISPOSITION, header::CONTENT_RANGE, header::ETAG,
+                HeaderName::from_static(REQUEST_ID_HEADER),
+            ])
+            .max_age(CORS_MAX_AGE_SECONDS);
+        if is_any_origin {
+            cors = cors.allow_any_origin();
+        } else {
+            // NOTE: Session cookies are only sent from listed origins since any site could otherwise act as the logged in user
+            cors = cors.supports_credentials();
+            for origin in self.allowed_origins.iter() {
+                cors = cors.allowed_origin(origin.as_str());
+            }
+        }
+        cors
+    }
+}
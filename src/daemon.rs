@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use fs2::FileExt;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+pub const DEFAULT_SERVICE_NAME: &str = "ytdlp_webui";
+
+#[derive(Debug,Error)]
+pub enum DaemonError {
+    #[error("Failed to open pid file {path}: {error}")]
+    PidFile { path: PathBuf, error: std::io::Error },
+    #[error("Another server is already running with pid file {0}")]
+    AlreadyRunning(PathBuf),
+    #[error("Failed to detach from the terminal: {0}")]
+    Detach(std::io::Error),
+    #[error("Failed to find the server executable or working directory: {0}")]
+    Executable(std::io::Error),
+    #[cfg(windows)]
+    #[error("Windows service failed: {0}")]
+    Service(#[from] windows_service::Error),
+    #[error("{0} is only supported on {1}")]
+    Unsupported(&'static str, &'static str),
+}
+
+/// Pid file that is locked for as long as the server runs and removed once it exits
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    /// Locks the file before the server forks so a second server fails while it still has a terminal
+    pub fn lock(path: &Path) -> Result<Self, DaemonError> {
+        let map_err = |error| DaemonError::PidFile { path: path.to_owned(), error };
+        // NOTE: Not truncated until the lock is held so the pid of the running server isn't erased
+        let file = File::options().create(true).truncate(false).write(true).open(path).map_err(map_err)?;
+        if file.try_lock_exclusive().is_err() {
+            return Err(DaemonError::AlreadyRunning(path.to_owned()));
+        }
+        Ok(Self { path: path.to_owned(), file })
+    }
+
+    /// Writes the pid of this process, which has to be called after forking
+    pub fn write_pid(&mut self) -> Result<(), DaemonError> {
+        let map_err = |error| DaemonError::PidFile { path: self.path.clone(), error };
+        self.file.set_len(0).map_err(map_err)?;
+        writeln!(self.file, "{0}", std::process::id()).map_err(map_err)?;
+        self.file.sync_all().map_err(map_err)?;
+        Ok(())
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Forks into the background and detaches from the terminal, which has to be done before any threads are started
+// NOTE: The working directory is kept so relative paths like ./data and ./static point to the same place
+//       SIGTERM and SIGINT are still handled by the server which shuts down gracefully
+#[cfg(unix)]
+pub fn daemonize() -> Result<(), DaemonError> {
+    use std::os::fd::AsRawFd;
+    // SAFETY: Only one thread is running so the child is a full copy, and the parent exits without running destructors
+    unsafe {
+        fork_and_exit_parent()?;
+        if libc::setsid() < 0 {
+            return Err(DaemonError::Detach(std::io::Error::last_os_error()));
+        }
+        // NOTE: Forking again means the server isn't a session leader so it can never get a terminal back
+        fork_and_exit_parent()?;
+    }
+    let null = File::options().read(true).write(true).open("/dev/null").map_err(DaemonError::Detach)?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: dup2 replaces the standard descriptors which aren't owned by anything else
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(DaemonError::Detach(std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+unsafe fn fork_and_exit_parent() -> Result<(), DaemonError> {
+    match libc::fork() {
+        pid if pid < 0 => Err(DaemonError::Detach(std::io::Error::last_os_error())),
+        0 => Ok(()),
+        _ => libc::_exit(0),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> Result<(), DaemonError> {
+    Err(DaemonError::Unsupported("--daemonize", "unix platforms, use install-service on windows"))
+}
+
+/// Resolves once the service manager asks the server to stop
+pub type ShutdownSignal = oneshot::Receiver<()>;
+
+/// Runs the server until it exits or is stopped
+pub type ServiceMain = Box<dyn FnOnce(ShutdownSignal) -> Result<(), Box<dyn std::error::Error>> + Send>;
+
+/// Arguments for the service to start the server with
+// NOTE: Services are started in the system directory so the current one is passed along for relative paths like ./data
+#[cfg(windows)]
+fn get_service_arguments(service_name: &str) -> Result<Vec<std::ffi::OsString>, DaemonError> {
+    use std::ffi::OsString;
+    let working_dir = std::env::current_dir().map_err(DaemonError::Executable)?;
+    // NOTE: Server options come before the subcommand so everything after it is replaced
+    let mut arguments: Vec<OsString> = std::env::args_os().skip(1).take_while(|arg| arg != "install-service").collect();
+    arguments.extend([
+        OsString::from("run-service"),
+        OsString::from("--service-name"), OsString::from(service_name),
+        OsString::from("--working-dir"), working_dir.into_os_string(),
+    ]);
+    Ok(arguments)
+}
+
+/// Registers a service that starts the server at boot with the options given before install-service
+#[cfg(windows)]
+pub fn install_service(service_name: &str) -> Result<(), DaemonError> {
+    use std::ffi::OsString;
+    use windows_service::service::{ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType};
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    let service_manager = ServiceManager::local_computer(
+        None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let service_info = ServiceInfo {
+        name: OsString::from(service_name),
+        display_name: OsString::from(service_name),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe().map_err(DaemonError::Executable)?,
+        launch_arguments: get_service_arguments(service_name)?,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = service_manager.create_service(&service_info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description("Web interface for downloading and transcoding audio with yt-dlp and ffmpeg")?;
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn install_service(_service_name: &str) -> Result<(), DaemonError> {
+    Err(DaemonError::Unsupported("install-service", "windows, use --daemonize or a systemd unit on unix"))
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use windows_service::define_windows_service;
+    use windows_service::service::{ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType};
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use tokio::sync::oneshot;
+    use super::{DaemonError, ServiceMain};
+
+    // NOTE: The service manager calls a plain function so the server is handed over through statics
+    pub(super) static SERVICE_NAME: Mutex<String> = Mutex::new(String::new());
+    pub(super) static SERVICE_MAIN: Mutex<Option<ServiceMain>> = Mutex::new(None);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub(super) fn start(service_name: &str) -> Result<(), DaemonError> {
+        windows_service::service_dispatcher::start(service_name, ffi_service_main)?;
+        Ok(())
+    }
+
+    fn set_state(handle: &service_control_handler::ServiceStatusHandle, state: ServiceState, exit_code: u32) {
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+        if let Err(err) = handle.set_service_status(status) {
+            log::error!("Failed to set service status: {err:?}");
+        }
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let service_name = SERVICE_NAME.lock().unwrap().clone();
+        let Some(run) = SERVICE_MAIN.lock().unwrap().take() else { return; };
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let shutdown_tx = Mutex::new(Some(shutdown_tx));
+        let event_handler = move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(shutdown_tx) = shutdown_tx.lock().unwrap().take() {
+                    let _ = shutdown_tx.send(());
+                }
+                ServiceControlHandlerResult::NoError
+            },
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let handle = match service_control_handler::register(service_name.as_str(), event_handler) {
+            Ok(handle) => handle,
+            Err(err) => {
+                log::error!("Failed to register service control handler: {err:?}");
+                return;
+            },
+        };
+        set_state(&handle, ServiceState::Running, 0);
+        let exit_code = match run(shutdown_rx) {
+            Ok(()) => 0,
+            Err(err) => {
+                log::error!("Server failed: {err:?}");
+                1
+            },
+        };
+        set_state(&handle, ServiceState::Stopped, exit_code);
+    }
+}
+
+/// Runs the server as a service, which only works when started by the service manager
+#[cfg(windows)]
+pub fn run_service(service_name: &str, working_dir: Option<&Path>, run: ServiceMain) -> Result<(), DaemonError> {
+    if let Some(working_dir) = working_dir {
+        std::env::set_current_dir(working_dir).map_err(DaemonError::Executable)?;
+    }
+    *windows::SERVICE_NAME.lock().unwrap() = service_name.to_owned();
+    *windows::SERVICE_MAIN.lock().unwrap() = Some(run);
+    windows::start(service_name)
+}
+
+#[cfg(not(windows))]
+pub fn run_service(_service_name: &str, _working_dir: Option<&Path>, _run: ServiceMain) -> Result<(), DaemonError> {
+    Err(DaemonError::Unsupported("run-service", "windows"))
+}
@@ -0,0 +1,139 @@
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use crate::app::AppConfig;
+use crate::job_store::{SharedJobStore, JobStoreError};
+
+#[derive(Debug,Error)]
+pub enum DataFileError {
+    #[error("Path must be inside the data directory: {0}")]
+    OutsideDirectory(String),
+    #[error("File not found: {0}")]
+    NotFound(String),
+    #[error("Listing the data directory is disabled")]
+    ListingDisabled,
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+    #[error("Failed to read data directory: {0}")]
+    Io(std::io::Error),
+}
+
+/// Resolves a path relative to the data directory, following symlinks only if they stay inside it
+pub fn resolve_data_path(app_config: &AppConfig, path: &str) -> Result<PathBuf, DataFileError> {
+    let relative_path = Path::new(path);
+    if !relative_path.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(DataFileError::OutsideDirectory(path.to_owned()));
+    }
+    let data_path = std::fs::canonicalize(&app_config.data).map_err(DataFileError::Io)?;
+    let full_path = match std::fs::canonicalize(app_config.data.join(relative_path)) {
+        Ok(full_path) => full_path,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(DataFileError::NotFound(path.to_owned())),
+        Err(err) => return Err(DataFileError::Io(err)),
+    };
+    if !full_path.starts_with(&data_path) {
+        return Err(DataFileError::OutsideDirectory(path.to_owned()));
+    }
+    Ok(full_path)
+}
+
+/// Owner of the download or transcode stored at this file
+/// Files that don't belong to one such as logs and the database return None so they are never served
+// NOTE: Workers store paths under the configured data directory which is only canonical if it was configured that way
+fn find_data_file_owner(
+    app_config: &AppConfig, job_store: &SharedJobStore, relative_path: &Path, full_path: &Path,
+) -> Result<Option<Option<i64>>, DataFileError> {
+    for audio_path in [app_config.data.join(relative_path), full_path.to_owned()] {
+        if let Some(owner) = job_store.select_audio_path_owner(audio_path.to_string_lossy().as_ref())? {
+            return Ok(Some(owner));
+        }
+    }
+    Ok(None)
+}
+
+pub struct DataListingEntry {
+    pub name: String,
+    pub is_directory: bool,
+}
+
+/// Subdirectories and the files in a directory that pass the owner check
+fn list_data_directory(
+    app_config: &AppConfig, job_store: &SharedJobStore, relative_path: &Path, full_path: &Path,
+    can_access: impl Fn(Option<i64>) -> bool,
+) -> Result<Vec<DataListingEntry>, DataFileError> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(full_path).map_err(DataFileError::Io)? {
+        let entry = entry.map_err(DataFileError::Io)?;
+        let is_directory = entry.file_type().map_err(DataFileError::Io)?.is_dir();
+        let is_visible = is_directory || {
+            let owner = find_data_file_owner(app_config, job_store, &relative_path.join(entry.file_name()), &entry.path())?;
+            owner.is_some_and(&can_access)
+        };
+        if is_visible {
+            entries.push(DataListingEntry { name: entry.file_name().to_string_lossy().into_owned(), is_directory });
+        }
+    }
+    entries.sort_by(|a, b| b.is_directory.cmp(&a.is_directory).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+pub enum DataFile {
+    Directory(Vec<DataListingEntry>),
+    File(PathBuf),
+}
+
+/// Resolves the path and checks that the user can access the file or lists what they can access in the directory
+// NOTE: This blocks on the filesystem and the job store so it shouldn't be called on the server's runtime
+pub fn find_data_file(
+    app_config: &AppConfig, job_store: &SharedJobStore, path: &str, can_access: impl Fn(Option<i64>) -> bool,
+) -> Result<DataFile, DataFileError> {
+    let full_path = resolve_data_path(app_config, path)?;
+    if full_path.is_dir() {
+        if !app_config.data_listing {
+            return Err(DataFileError::ListingDisabled);
+        }
+        let entries = list_data_directory(app_config, job_store, Path::new(path), &full_path, can_access)?;
+        return Ok(DataFile::Directory(entries));
+    }
+    let owner = find_data_file_owner(app_config, job_store, Path::new(path), &full_path)?;
+    if !owner.is_some_and(can_access) {
+        return Err(DataFileError::NotFound(path.to_owned()));
+    }
+    Ok(DataFile::File(full_path))
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn encode_path_segment(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => char::from(byte).to_string(),
+        byte => format!("%{byte:02X}"),
+    }).collect()
+}
+
+/// Page linking to each entry relative to the directory, so the request path has to end with a slash
+pub fn render_data_listing(request_path: &str, entries: &[DataListingEntry]) -> String {
+    let title = escape_html(format!("Index of {request_path}").as_str());
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n");
+    html.push_str("<li><a href=\"../\">../</a></li>\n");
+    for entry in entries {
+        let suffix = if entry.is_directory { "/" } else { "" };
+        html.push_str(format!(
+            "<li><a href=\"{0}{suffix}\">{1}{suffix}</a></li>\n",
+            encode_path_segment(entry.name.as_str()), escape_html(entry.name.as_str()),
+        ).as_str());
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    html
+}
@@ -1,9 +1,11 @@
+use std::collections::{BTreeMap, HashMap};
 use rusqlite::{params, OptionalExtension};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::cast::{FromPrimitive, ToPrimitive};
 use thiserror::Error;
 use crate::generate_bidirectional_binding;
+use crate::query::{column, columns, Column, Compare, Dialect, Order, Table};
 use crate::util::get_unix_time;
 
 #[derive(Clone,Debug,PartialEq,Eq,Hash,Serialize)]
@@ -38,7 +40,14 @@ impl VideoId {
     }
 }
 
-#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Serialize)]
+impl<'de> Deserialize<'de> for VideoId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        Self::try_new(id.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Serialize,Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum AudioExtension {
     M4A,
@@ -59,9 +68,18 @@ impl AudioExtension {
     pub fn as_str(&self) -> &'static str {
         (*self).into()
     }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::M4A => "audio/mp4",
+            Self::AAC => "audio/aac",
+            Self::MP3 => "audio/mpeg",
+            Self::WEBM => "audio/webm",
+        }
+    }
 }
 
-#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Serialize,FromPrimitive,ToPrimitive)]
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Serialize,Deserialize,FromPrimitive,ToPrimitive)]
 #[serde(rename_all = "lowercase")]
 pub enum WorkerStatus {
     #[default]
@@ -81,7 +99,29 @@ impl WorkerStatus {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Type of worker job which is used to estimate how long queued jobs will take
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Deserialize,Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Download,
+    Transcode,
+    Hls,
+}
+
+generate_bidirectional_binding!(
+    JobKind, &'static str, &str,
+    (Download, "download"),
+    (Transcode, "transcode"),
+    (Hls, "hls"),
+);
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        (*self).into()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct YtdlpRow {
     pub video_id: VideoId,
     pub status: WorkerStatus,
@@ -89,10 +129,27 @@ pub struct YtdlpRow {
     pub stdout_log_path: Option<String>,
     pub stderr_log_path: Option<String>,
     pub system_log_path: Option<String>,
+    /// Path that yt-dlp resolved from the output template
     pub audio_path: Option<String>,
+    pub downloaded_bytes: Option<usize>,
+    pub file_size_bytes: Option<u64>,
+    pub duration_ms: Option<u64>,
+    /// Hex encoded sha256 of the downloaded file
+    pub source_hash: Option<String>,
+    /// Format that yt-dlp downloaded which can be pinned when requesting a download
+    pub format_id: Option<String>,
+    /// User that requested the download, shared with everyone if there is none
+    pub owner: Option<i64>,
+    /// Json of the download state when it ended so its progress can be shown after a restart
+    pub final_state: Option<String>,
+    /// Dubbed audio track that was requested, the default track of the video if there is none
+    pub audio_lang: Option<String>,
+    /// Some files couldn't be moved to the trash so the delete has to be retried
+    #[serde(default)]
+    pub delete_pending: bool,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FfmpegRow {
     pub video_id: VideoId,
     pub audio_ext: AudioExtension,
@@ -102,72 +159,524 @@ pub struct FfmpegRow {
     pub stderr_log_path: Option<String>,
     pub system_log_path: Option<String>,
     pub audio_path: Option<String>,
+    pub file_size_bytes: Option<u64>,
+    pub duration_ms: Option<u64>,
+    pub codec: Option<String>,
+    pub bitrate: Option<u64>,
+    pub has_artwork: Option<bool>,
+    pub max_volume_db: Option<f64>,
+    pub variant: String,
+    pub source_hash: Option<String>,
+    pub transcode_options: Option<String>,
+    /// User that requested the transcode, shared with everyone if there is none
+    pub owner: Option<i64>,
+    /// Hex encoded sha256 of the transcoded file which is used as its ETag
+    pub file_hash: Option<String>,
+    /// Json of the preset the transcode was encoded with so edits to the preset can be detected
+    pub transcode_preset: Option<String>,
+    /// Json of the transcode state when it ended so its progress can be shown after a restart
+    pub final_state: Option<String>,
+    /// Some files couldn't be moved to the trash so the delete has to be retried
+    #[serde(default)]
+    pub delete_pending: bool,
+}
+
+/// Narrows down listed downloads and transcodes in the query so the indexes on these columns are used
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EntryFilter {
+    pub status: Option<WorkerStatus>,
+    /// Only applies to transcodes
+    pub audio_ext: Option<AudioExtension>,
+    /// Entries last updated at or after this time
+    pub since_unix_time: Option<u64>,
+    /// Entries last updated before this time
+    pub before_unix_time: Option<u64>,
+}
+
+impl EntryFilter {
+    /// Conditions of the filter with the value each one is compared to, where the audio extension is skipped for downloads
+    // NOTE: The table must have status and unix_time columns
+    pub(crate) fn get_conditions<const N: usize>(&self, table: &Table<N>) -> Vec<(Column, Compare, EntryFilterValue)> {
+        let mut conditions = Vec::new();
+        if let Some(status) = self.status {
+            conditions.push((table.column("status"), Compare::Equal, EntryFilterValue::Status(status)));
+        }
+        if let (Some(audio_ext), Some(column)) = (self.audio_ext, table.get_column("audio_ext")) {
+            conditions.push((column, Compare::Equal, EntryFilterValue::AudioExt(audio_ext)));
+        }
+        if let Some(since_unix_time) = self.since_unix_time {
+            conditions.push((table.column("unix_time"), Compare::GreaterOrEqual, EntryFilterValue::UnixTime(since_unix_time)));
+        }
+        if let Some(before_unix_time) = self.before_unix_time {
+            conditions.push((table.column("unix_time"), Compare::Less, EntryFilterValue::UnixTime(before_unix_time)));
+        }
+        conditions
+    }
+}
+
+pub(crate) enum EntryFilterValue {
+    Status(WorkerStatus),
+    AudioExt(AudioExtension),
+    UnixTime(u64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchRow {
+    pub video_id: VideoId,
+    pub title: String,
+    pub channel: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataRow {
+    pub video_id: VideoId,
+    pub title: String,
+    pub channel: String,
+    pub duration_ms: Option<u64>,
+    pub thumbnail_url: Option<String>,
+}
+
+/// Tags of the recording that the audio of a video was identified as by AcoustID and MusicBrainz
+#[derive(Debug, Clone, Serialize)]
+pub struct EnrichmentRow {
+    pub video_id: VideoId,
+    /// MusicBrainz id of the recording which is None if the fingerprint had no match
+    pub recording_id: Option<String>,
+    /// How closely the fingerprint matched between 0 and 1
+    pub score: Option<f64>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    /// Date of the release in MusicBrainz's format (e.g. 1987-07-27, 1987-07 or 1987)
+    pub release_date: Option<String>,
+    pub unix_time: u64,
+}
+
+/// All jobs and metadata associated with a video
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryRow {
+    pub video_id: VideoId,
+    pub download: Option<YtdlpRow>,
+    pub transcodes: Vec<FfmpegRow>,
+    pub metadata: Option<MetadataRow>,
+}
+
+/// Accepted transcode request which clients can track by its id
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRow {
+    pub job_id: String,
+    /// Key given by the client so a retried request returns the same job
+    pub idempotency_key: Option<String>,
+    pub video_id: VideoId,
+    pub audio_ext: AudioExtension,
+    pub variant: String,
+    pub unix_time: u64,
+}
+
+/// Number of jobs of a kind that ended on a day starting at midnight UTC
+#[derive(Debug, Clone, Serialize)]
+pub struct JobsPerDayRow {
+    pub day_unix_time: u64,
+    pub kind: JobKind,
+    pub total: u64,
+    pub total_failed: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFailureRow {
+    pub kind: JobKind,
+    pub error_category: String,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelCountRow {
+    pub channel: String,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableUsageRow {
+    pub table: String,
+    pub total_entries: u64,
+    pub total_bytes: u64,
+}
+
+/// Total bytes written by successful jobs of a kind and the time they took
+#[derive(Debug, Clone, Serialize)]
+pub struct JobThroughputRow {
+    pub kind: JobKind,
+    pub total_bytes: u64,
+    pub total_elapsed_ms: u64,
+}
+
+/// Channel or playlist that is periodically checked for new uploads
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionRow {
+    pub id: i64,
+    pub url: String,
+    pub audio_ext: AudioExtension,
+    pub transcode_options: Option<String>,
+    pub check_interval_seconds: u64,
+    pub unix_time: u64,
+    pub last_check_unix_time: Option<u64>,
+    pub last_check_error: Option<String>,
+}
+
+/// Request to download the uploads of a channel which matched its filters
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelJobRow {
+    pub id: i64,
+    pub channel_id: String,
+    pub audio_ext: AudioExtension,
+    pub variant: String,
+    pub transcode_options: Option<String>,
+    pub filters: Option<String>,
+    /// Status of listing the channel, the videos have their own download and transcode status
+    pub status: WorkerStatus,
+    pub error: Option<String>,
+    /// Number of uploads listed before the filters were applied
+    pub total_listed: Option<u64>,
+    pub owner: Option<i64>,
+    pub unix_time: u64,
+}
+
+/// Video enqueued by a channel job along with the progress of its download and transcode
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelJobVideoRow {
+    pub video_id: VideoId,
+    pub title: Option<String>,
+    pub download_status: WorkerStatus,
+    pub transcode_status: WorkerStatus,
+}
+
+/// Execution of a maintenance task started by its schedule or by an admin
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceRunRow {
+    pub id: i64,
+    pub task: String,
+    pub is_manual: bool,
+    pub status: WorkerStatus,
+    /// Human readable outcome such as the number of entries that were changed
+    pub summary: Option<String>,
+    pub error: Option<String>,
+    pub unix_time: u64,
+    pub end_unix_time: Option<u64>,
+}
+
+/// Download or transcode that was deleted and can be restored until it is purged
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashRow {
+    pub id: i64,
+    pub kind: JobKind,
+    /// Video id of a download or transcode key of a transcode
+    pub key: String,
+    pub owner: Option<i64>,
+    /// Json of the deleted ytdlp or ffmpeg row
+    #[serde(skip)]
+    pub entry: String,
+    /// Json of where each file was moved to in the trash directory
+    #[serde(skip)]
+    pub files: String,
+    pub file_size_bytes: Option<u64>,
+    pub deleted_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserRow {
+    pub id: i64,
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub is_admin: bool,
+    pub unix_time: u64,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthTokenKind {
+    /// Created by logging in and expires
+    Session,
+    /// Created by the user for scripts and never expires
+    Api,
+}
+
+generate_bidirectional_binding!(
+    AuthTokenKind, &'static str, &str,
+    (Session, "session"),
+    (Api, "api"),
+);
+
+impl AuthTokenKind {
+    pub fn as_str(&self) -> &'static str {
+        (*self).into()
+    }
+}
+
+/// Only a hash of the token is stored so a leaked database can't be used to log in
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthTokenRow {
+    pub id: i64,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub user_id: i64,
+    pub kind: AuthTokenKind,
+    pub name: Option<String>,
+    pub unix_time: u64,
+    pub expire_unix_time: Option<u64>,
+}
+
+impl LibraryRow {
+    fn get_latest_unix_time(&self) -> u64 {
+        let download_time = self.download.as_ref().map(|entry| entry.unix_time).unwrap_or(0);
+        let transcode_time = self.transcodes.iter().map(|entry| entry.unix_time).max().unwrap_or(0);
+        download_time.max(transcode_time)
+    }
+}
+
+fn get_library_row<'a>(
+    entries: &'a mut Vec<LibraryRow>, indices: &mut HashMap<VideoId, usize>,
+    video_id: &VideoId, metadata: Option<MetadataRow>,
+) -> &'a mut LibraryRow {
+    let index = *indices.entry(video_id.clone()).or_insert_with(|| {
+        entries.push(LibraryRow {
+            video_id: video_id.clone(),
+            download: None,
+            transcodes: Vec::new(),
+            metadata: None,
+        });
+        entries.len()-1
+    });
+    let entry = &mut entries[index];
+    if entry.metadata.is_none() {
+        entry.metadata = metadata;
+    }
+    entry
 }
 
+/// Groups the joined download and transcode rows by video with the most recently active videos first
+pub fn merge_library_rows(
+    downloads: Vec<(YtdlpRow, Option<MetadataRow>)>, transcodes: Vec<(FfmpegRow, Option<MetadataRow>)>,
+) -> Vec<LibraryRow> {
+    let mut entries = Vec::<LibraryRow>::new();
+    let mut indices = HashMap::<VideoId, usize>::new();
+    for (download, metadata) in downloads {
+        let video_id = download.video_id.clone();
+        get_library_row(&mut entries, &mut indices, &video_id, metadata).download = Some(download);
+    }
+    for (transcode, metadata) in transcodes {
+        let video_id = transcode.video_id.clone();
+        get_library_row(&mut entries, &mut indices, &video_id, metadata).transcodes.push(transcode);
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.get_latest_unix_time()));
+    entries
+}
+
+/// Statements prepared by each pooled connection which are kept so they aren't parsed and planned again
+// NOTE: This is above the number of distinct queries so commonly used statements aren't evicted
+pub const STATEMENT_CACHE_CAPACITY: usize = 128;
+
 pub type DatabasePool = r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>;
 pub type DatabaseConnection = r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>;
 
-pub fn setup_database(conn: DatabaseConnection) -> Result<(), Box<dyn std::error::Error>> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS ytdlp (
-            video_id TEXT,
-            status INTEGER DEFAULT 0,
-            unix_time INTEGER,
-            stdout_log_path TEXT,
-            stderr_log_path TEXT,
-            system_log_path TEXT,
-            audio_path TEXT,
-            PRIMARY KEY (video_id)
-        )",
-        (),
-    )?;
+// NOTE: Migrations are applied in order and must never be modified once released
+//       Add a new migration to change the schema instead
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/sqlite/0001_create_worker_tables.sql"),
+    include_str!("../migrations/sqlite/0002_add_ytdlp_downloaded_bytes.sql"),
+    include_str!("../migrations/sqlite/0003_create_metadata_search.sql"),
+    include_str!("../migrations/sqlite/0004_create_metadata.sql"),
+    include_str!("../migrations/sqlite/0005_add_file_size_and_duration.sql"),
+    include_str!("../migrations/sqlite/0006_add_ffmpeg_probe_results.sql"),
+    include_str!("../migrations/sqlite/0007_add_transcode_variants.sql"),
+    include_str!("../migrations/sqlite/0008_add_ytdlp_format_id.sql"),
+    include_str!("../migrations/sqlite/0009_create_subscriptions.sql"),
+    include_str!("../migrations/sqlite/0010_create_job_durations.sql"),
+    include_str!("../migrations/sqlite/0011_create_jobs.sql"),
+    include_str!("../migrations/sqlite/0012_create_users.sql"),
+    include_str!("../migrations/sqlite/0013_create_conversions.sql"),
+    include_str!("../migrations/sqlite/0014_create_job_history.sql"),
+    include_str!("../migrations/sqlite/0015_create_channel_jobs.sql"),
+    include_str!("../migrations/sqlite/0016_add_ffmpeg_file_hash.sql"),
+    include_str!("../migrations/sqlite/0017_create_maintenance_runs.sql"),
+    include_str!("../migrations/sqlite/0018_add_ffmpeg_transcode_preset.sql"),
+    include_str!("../migrations/sqlite/0019_add_job_history_request_id.sql"),
+    include_str!("../migrations/sqlite/0020_create_trash.sql"),
+    include_str!("../migrations/sqlite/0021_add_final_state.sql"),
+    include_str!("../migrations/sqlite/0022_add_ytdlp_audio_lang.sql"),
+    include_str!("../migrations/sqlite/0023_add_delete_pending.sql"),
+    include_str!("../migrations/sqlite/0024_add_ffmpeg_video_foreign_key.sql"),
+    include_str!("../migrations/sqlite/0025_add_library_indexes.sql"),
+    include_str!("../migrations/sqlite/0026_create_enrichments.sql"),
+    include_str!("../migrations/sqlite/0027_add_audio_path_indexes.sql"),
+];
+
+// NOTE: Column order must match the indices used when mapping rows to entries
+pub(crate) const YTDLP_TABLE: Table<16> = Table {
+    name: "ytdlp",
+    columns: [
+        "video_id", "status", "unix_time", "stdout_log_path", "stderr_log_path", "system_log_path", "audio_path",
+        "downloaded_bytes", "file_size_bytes", "duration_ms", "source_hash", "format_id", "owner", "final_state", "audio_lang",
+        "delete_pending",
+    ],
+};
+pub(crate) const TOTAL_YTDLP_COLUMNS: usize = YTDLP_TABLE.columns.len();
+pub(crate) const FFMPEG_TABLE: Table<22> = Table {
+    name: "ffmpeg",
+    columns: [
+        "video_id", "audio_ext", "status", "unix_time", "stdout_log_path", "stderr_log_path", "system_log_path", "audio_path",
+        "file_size_bytes", "duration_ms", "codec", "bitrate", "has_artwork", "max_volume_db", "variant", "source_hash",
+        "transcode_options", "owner", "file_hash", "transcode_preset", "final_state", "delete_pending",
+    ],
+};
+pub(crate) const TOTAL_FFMPEG_COLUMNS: usize = FFMPEG_TABLE.columns.len();
+pub(crate) const JOB_TABLE: Table<6> = Table {
+    name: "jobs",
+    columns: ["job_id", "idempotency_key", "video_id", "audio_ext", "variant", "unix_time"],
+};
+pub(crate) const USER_TABLE: Table<5> = Table {
+    name: "users",
+    columns: ["id", "username", "password_hash", "is_admin", "unix_time"],
+};
+pub(crate) const AUTH_TOKEN_TABLE: Table<7> = Table {
+    name: "auth_tokens",
+    columns: ["id", "token_hash", "user_id", "kind", "name", "unix_time", "expire_unix_time"],
+};
+pub(crate) const SUBSCRIPTION_TABLE: Table<8> = Table {
+    name: "subscriptions",
+    columns: [
+        "id", "url", "audio_ext", "transcode_options", "check_interval_seconds", "unix_time", "last_check_unix_time",
+        "last_check_error",
+    ],
+};
+pub(crate) const CHANNEL_JOB_TABLE: Table<11> = Table {
+    name: "channel_jobs",
+    columns: [
+        "id", "channel_id", "audio_ext", "variant", "transcode_options", "filters", "status", "error", "total_listed", "owner",
+        "unix_time",
+    ],
+};
+pub(crate) const MAINTENANCE_RUN_TABLE: Table<8> = Table {
+    name: "maintenance_runs",
+    columns: ["id", "task", "is_manual", "status", "summary", "error", "unix_time", "end_unix_time"],
+};
+pub(crate) const TRASH_TABLE: Table<8> = Table {
+    name: "trash",
+    columns: ["id", "kind", "key", "owner", "entry", "files", "file_size_bytes", "deleted_at"],
+};
+pub(crate) const ENRICHMENT_TABLE: Table<10> = Table {
+    name: "enrichments",
+    columns: [
+        "video_id", "recording_id", "score", "title", "artist", "album", "album_artist", "track_number", "release_date",
+        "unix_time",
+    ],
+};
+// NOTE: Metadata columns are renamed so they don't clash with job columns of the same name
+pub(crate) const METADATA_JOIN: &str =
+    "LEFT JOIN (\
+        SELECT video_id AS metadata_video_id, title, channel, duration_ms AS metadata_duration_ms, thumbnail_url \
+        FROM metadata\
+     ) AS metadata ON video_id = metadata_video_id";
+pub(crate) const METADATA_JOIN_COLUMNS: &str = "title, channel, metadata_duration_ms, thumbnail_url";
+// NOTE: Every table except schema_version which belongs to the server that created it
+pub const EXPORT_TABLES: &[&str] = &[
+    "ytdlp", "ffmpeg", "metadata", "metadata_search", "subscriptions", "subscription_videos", "job_durations", "jobs",
+    "users", "auth_tokens", "conversions", "job_history", "channel_jobs", "channel_job_videos",
+    "maintenance_runs", "trash", "enrichments",
+];
+
+/// Row of any table keyed by column name so it can be imported into either backend
+pub type TableRow = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Debug,Error)]
+pub enum MigrationError {
+    #[error("Database schema version {given} is newer than latest known version {latest}")]
+    UnknownVersion { given: usize, latest: usize },
+    #[error("Migration {version} failed: {error:?}")]
+    Failed { version: usize, error: rusqlite::Error },
+    #[error("Database execute failed: {0:?}")]
+    DatabaseExecute(#[from] rusqlite::Error),
+}
+
+pub fn get_schema_version(conn: &DatabaseConnection) -> Result<usize, rusqlite::Error> {
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS ffmpeg (
-            video_id TEXT,
-            audio_ext TEXT,
-            status INTEGER DEFAULT 0,
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER,
             unix_time INTEGER,
-            stdout_log_path TEXT,
-            stderr_log_path TEXT,
-            system_log_path TEXT,
-            audio_path TEXT,
-            PRIMARY KEY (video_id, audio_ext)
+            PRIMARY KEY (version)
         )",
         (),
     )?;
-    Ok(())
+    let version: Option<usize> = conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+    Ok(version.unwrap_or(0))
 }
 
-#[derive(Debug,Clone,Copy)]
-enum WorkerTable {
-    Ytdlp,
-    Ffmpeg,
+pub fn setup_database(mut conn: DatabaseConnection) -> Result<(), MigrationError> {
+    let latest_version = MIGRATIONS.len();
+    let current_version = get_schema_version(&conn)?;
+    if current_version > latest_version {
+        return Err(MigrationError::UnknownVersion { given: current_version, latest: latest_version });
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current_version) {
+        let version = index+1;
+        log::info!("Applying database migration: version={version}");
+        let map_error = |error: rusqlite::Error| MigrationError::Failed { version, error };
+        let tx = conn.transaction().map_err(map_error)?;
+        tx.execute_batch(migration).map_err(map_error)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, unix_time) VALUES (?1,?2)",
+            (version, get_unix_time()),
+        ).map_err(map_error)?;
+        tx.commit().map_err(map_error)?;
+    }
+    Ok(())
 }
 
-generate_bidirectional_binding!(
-    WorkerTable, &'static str, &str,
-    (Ytdlp, "ytdlp"),
-    (Ffmpeg, "ffmpeg"),
+// NOTE: Shared by both job stores where values are bound in the order of these columns
+pub(crate) const YTDLP_KEY_COLUMNS: [Column; 1] = columns!(YTDLP_TABLE; video_id);
+pub(crate) const YTDLP_INSERT_COLUMNS: [Column; 6] =
+    columns!(YTDLP_TABLE; video_id, status, unix_time, downloaded_bytes, owner, delete_pending);
+pub(crate) const YTDLP_UPDATE_COLUMNS: [Column; 13] = columns!(YTDLP_TABLE;
+    unix_time, status, stdout_log_path, stderr_log_path, system_log_path, audio_path,
+    downloaded_bytes, file_size_bytes, duration_ms, source_hash, format_id, final_state, audio_lang,
+);
+pub(crate) const FFMPEG_KEY_COLUMNS: [Column; 3] = columns!(FFMPEG_TABLE; video_id, audio_ext, variant);
+pub(crate) const FFMPEG_INSERT_COLUMNS: [Column; 8] =
+    columns!(FFMPEG_TABLE; video_id, audio_ext, variant, status, unix_time, transcode_options, owner, delete_pending);
+pub(crate) const FFMPEG_UPDATE_COLUMNS: [Column; 17] = columns!(FFMPEG_TABLE;
+    unix_time, status, stdout_log_path, stderr_log_path, system_log_path, audio_path,
+    file_size_bytes, duration_ms, codec, bitrate, has_artwork, max_volume_db,
+    source_hash, transcode_options, file_hash, transcode_preset, final_state,
+);
+pub(crate) const ENRICHMENT_INSERT_COLUMNS: [Column; 10] = columns!(ENRICHMENT_TABLE;
+    video_id, recording_id, score, title, artist, album, album_artist, track_number, release_date, unix_time,
 );
 
 // insert
+// NOTE: The owner of an existing entry is kept so requesting it again doesn't take it from another user
 pub fn insert_ytdlp_entry(
-    db_conn: &DatabaseConnection, video_id: &VideoId,
+    db_conn: &DatabaseConnection, video_id: &VideoId, downloaded_bytes: Option<usize>, owner: Option<i64>,
 ) -> Result<usize, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ytdlp.into();
+    let query = YTDLP_TABLE.insert(&YTDLP_INSERT_COLUMNS).on_conflict(&YTDLP_KEY_COLUMNS, &columns!(YTDLP_TABLE; owner));
     db_conn.execute(
-        format!("INSERT OR REPLACE INTO {table} (video_id, status, unix_time) VALUES (?1,?2,?3)").as_str(),
-        (video_id.as_str(), WorkerStatus::Queued as u8, get_unix_time()),
+        query.build(Dialect::Sqlite).as_str(),
+        (video_id.as_str(), WorkerStatus::Queued as u8, get_unix_time(), downloaded_bytes, owner, false),
     )
 }
 
 pub fn insert_ffmpeg_entry(
-    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension,
+    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension, variant: &str,
+    transcode_options: Option<&str>, owner: Option<i64>,
 ) -> Result<usize, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ffmpeg.into();
+    let query = FFMPEG_TABLE.insert(&FFMPEG_INSERT_COLUMNS).on_conflict(&FFMPEG_KEY_COLUMNS, &columns!(FFMPEG_TABLE; owner));
     db_conn.execute(
-        format!("INSERT OR REPLACE INTO {table} (video_id, audio_ext, status, unix_time) VALUES (?1,?2,?3,?4)").as_str(),
-        (video_id.as_str(), audio_ext.as_str(), WorkerStatus::Queued as u8, get_unix_time()),
+        query.build(Dialect::Sqlite).as_str(),
+        params![
+            video_id.as_str(), audio_ext.as_str(), variant, WorkerStatus::Queued as u8, get_unix_time(), transcode_options,
+            owner, false,
+        ],
     )
 }
 
@@ -175,18 +684,15 @@ pub fn insert_ffmpeg_entry(
 pub fn update_ytdlp_entry(
     db_conn: &DatabaseConnection, entry: &YtdlpRow,
 ) -> Result<usize, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ytdlp.into();
-    db_conn.execute(
-        format!(
-            "UPDATE {table} SET \
-            unix_time=?2, status=?3, \
-            stdout_log_path=?4, stderr_log_path=?5, system_log_path=?6, audio_path=?7 \
-            WHERE video_id=?1"
-        ).as_str(),
+    let query = YTDLP_TABLE.update(&YTDLP_UPDATE_COLUMNS).filter_equal(&YTDLP_KEY_COLUMNS);
+    // NOTE: Workers update their rows on every progress report so the statement is cached
+    db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?.execute(
         params![
-            entry.video_id.as_str(),
-            entry.unix_time, entry.status.to_u8(), 
+            entry.unix_time, entry.status.to_u8(),
             entry.stdout_log_path, entry.stderr_log_path, entry.system_log_path, entry.audio_path,
+            entry.downloaded_bytes, entry.file_size_bytes, entry.duration_ms, entry.source_hash, entry.format_id,
+            entry.final_state, entry.audio_lang,
+            entry.video_id.as_str(),
         ],
     )
 }
@@ -194,35 +700,43 @@ pub fn update_ytdlp_entry(
 pub fn update_ffmpeg_entry(
     db_conn: &DatabaseConnection, entry: &FfmpegRow,
 ) -> Result<usize, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ffmpeg.into();
-    db_conn.execute(
-        format!(
-            "UPDATE {table} SET \
-            unix_time=?3, status=?4, stdout_log_path=?5, stderr_log_path=?6, system_log_path=?7, audio_path=?8 \
-            WHERE video_id=?1 AND audio_ext=?2"
-        ).as_str(),
+    let query = FFMPEG_TABLE.update(&FFMPEG_UPDATE_COLUMNS).filter_equal(&FFMPEG_KEY_COLUMNS);
+    // NOTE: Workers update their rows on every progress report so the statement is cached
+    db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?.execute(
         params![
-            entry.video_id.as_str(), entry.audio_ext.as_str(),
             entry.unix_time, entry.status.to_u8(),
             entry.stdout_log_path, entry.stderr_log_path, entry.system_log_path, entry.audio_path,
+            entry.file_size_bytes, entry.duration_ms,
+            entry.codec, entry.bitrate, entry.has_artwork, entry.max_volume_db,
+            entry.source_hash, entry.transcode_options, entry.file_hash, entry.transcode_preset, entry.final_state,
+            entry.video_id.as_str(), entry.audio_ext.as_str(), entry.variant,
         ],
     )
 }
 
 // delete
 pub fn delete_ytdlp_entry(db_conn: &DatabaseConnection, video_id: &VideoId) -> Result<usize, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ytdlp.into();
-    db_conn.execute(format!("DELETE FROM {table} WHERE video_id=?1").as_str(), (video_id.as_str(),))
+    let query = YTDLP_TABLE.delete().filter_equal(&YTDLP_KEY_COLUMNS);
+    db_conn.execute(query.build(Dialect::Sqlite).as_str(), (video_id.as_str(),))
 }
 
 pub fn delete_ffmpeg_entry(
-    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension,
+    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension, variant: &str,
 ) -> Result<usize, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ffmpeg.into();
-    db_conn.execute(
-        format!("DELETE FROM {table} WHERE video_id=?1 AND audio_ext=?2").as_str(),
-        (video_id.as_str(), audio_ext.as_str()),
-    )
+    let query = FFMPEG_TABLE.delete().filter_equal(&FFMPEG_KEY_COLUMNS);
+    db_conn.execute(query.build(Dialect::Sqlite).as_str(), (video_id.as_str(), audio_ext.as_str(), variant))
+}
+
+pub fn set_ytdlp_delete_pending(db_conn: &DatabaseConnection, video_id: &VideoId, is_pending: bool) -> Result<usize, rusqlite::Error> {
+    let query = YTDLP_TABLE.update(&columns!(YTDLP_TABLE; delete_pending)).filter_equal(&YTDLP_KEY_COLUMNS);
+    db_conn.execute(query.build(Dialect::Sqlite).as_str(), (is_pending, video_id.as_str()))
+}
+
+pub fn set_ffmpeg_delete_pending(
+    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension, variant: &str, is_pending: bool,
+) -> Result<usize, rusqlite::Error> {
+    let query = FFMPEG_TABLE.update(&columns!(FFMPEG_TABLE; delete_pending)).filter_equal(&FFMPEG_KEY_COLUMNS);
+    db_conn.execute(query.build(Dialect::Sqlite).as_str(), (is_pending, video_id.as_str(), audio_ext.as_str(), variant))
 }
 
 // select
@@ -246,14 +760,20 @@ fn map_ytdlp_row_to_entry(row: &rusqlite::Row) -> Result<YtdlpRow, rusqlite::Err
         stderr_log_path: row.get(4)?,
         system_log_path: row.get(5)?,
         audio_path: row.get(6)?,
+        downloaded_bytes: row.get(7)?,
+        file_size_bytes: row.get(8)?,
+        duration_ms: row.get(9)?,
+        source_hash: row.get(10)?,
+        format_id: row.get(11)?,
+        owner: row.get(12)?,
+        final_state: row.get(13)?,
+        audio_lang: row.get(14)?,
+        delete_pending: row.get(15)?,
     })
 }
 
 pub fn select_ytdlp_entries(db_conn: &DatabaseConnection) -> Result<Vec<YtdlpRow>, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ytdlp.into();
-    let mut stmt = db_conn.prepare(format!(
-        "SELECT video_id, status, unix_time,\
-         stdout_log_path, stderr_log_path, system_log_path, audio_path FROM {table}").as_str())?;
+    let mut stmt = db_conn.prepare_cached(YTDLP_TABLE.select().build(Dialect::Sqlite).as_str())?;
     let row_iter = stmt.query_map([], map_ytdlp_row_to_entry)?;
     let mut entries = Vec::<YtdlpRow>::new();
     for row in row_iter {
@@ -263,14 +783,24 @@ pub fn select_ytdlp_entries(db_conn: &DatabaseConnection) -> Result<Vec<YtdlpRow
 }
 
 pub fn select_ytdlp_entry(db_conn: &DatabaseConnection, video_id: &VideoId) -> Result<Option<YtdlpRow>, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ytdlp.into();
-    let mut stmt = db_conn.prepare(format!(
-        "SELECT video_id, status, unix_time, \
-         stdout_log_path, stderr_log_path, system_log_path, audio_path \
-         FROM {table} WHERE video_id=?1").as_str())?;
+    let query = YTDLP_TABLE.select().filter_equal(&YTDLP_KEY_COLUMNS);
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
     stmt.query_row([video_id.as_str()], map_ytdlp_row_to_entry).optional()
 }
 
+/// Owner of the download or transcode stored at this path, which is None if neither is
+pub fn select_audio_path_owner(db_conn: &DatabaseConnection, audio_path: &str) -> Result<Option<Option<i64>>, rusqlite::Error> {
+    let query = YTDLP_TABLE.select().filter_equal(&columns!(YTDLP_TABLE; audio_path));
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    if let Some(entry) = stmt.query_row([audio_path], map_ytdlp_row_to_entry).optional()? {
+        return Ok(Some(entry.owner));
+    }
+    let query = FFMPEG_TABLE.select().filter_equal(&columns!(FFMPEG_TABLE; audio_path));
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    let entry = stmt.query_row([audio_path], map_ffmpeg_row_to_entry).optional()?;
+    Ok(entry.map(|entry| entry.owner))
+}
+
 fn map_ffmpeg_row_to_entry(row: &rusqlite::Row) -> Result<FfmpegRow, rusqlite::Error> {
     let video_id: Option<String> = row.get(0)?;
     let video_id = video_id.expect("video_id is a primary key");
@@ -296,15 +826,62 @@ fn map_ffmpeg_row_to_entry(row: &rusqlite::Row) -> Result<FfmpegRow, rusqlite::E
         stderr_log_path: row.get(5)?,
         system_log_path: row.get(6)?,
         audio_path: row.get(7)?,
+        file_size_bytes: row.get(8)?,
+        duration_ms: row.get(9)?,
+        codec: row.get(10)?,
+        bitrate: row.get(11)?,
+        has_artwork: row.get(12)?,
+        max_volume_db: row.get(13)?,
+        variant: row.get::<_, Option<String>>(14)?.unwrap_or_default(),
+        source_hash: row.get(15)?,
+        transcode_options: row.get(16)?,
+        owner: row.get(17)?,
+        file_hash: row.get(18)?,
+        transcode_preset: row.get(19)?,
+        final_state: row.get(20)?,
+        delete_pending: row.get(21)?,
     })
 }
 
-pub fn select_ffmpeg_entries(db_conn: &DatabaseConnection) -> Result<Vec<FfmpegRow>, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ffmpeg.into();
-    let mut stmt = db_conn.prepare(format!(
-        "SELECT video_id, audio_ext, status, unix_time,\
-         stdout_log_path, stderr_log_path, system_log_path, audio_path FROM {table}").as_str())?;
+/// Select of the filter along with the values to bind in order
+fn get_entry_filter_query<const N: usize>(table: &Table<N>, filter: &EntryFilter) -> (String, Vec<rusqlite::types::Value>) {
+    use rusqlite::types::Value;
+    let mut query = table.select();
+    let mut values = Vec::new();
+    for (column, compare, value) in filter.get_conditions(table) {
+        query = query.filter(column, compare);
+        values.push(match value {
+            EntryFilterValue::Status(status) => Value::Integer(status as i64),
+            EntryFilterValue::AudioExt(audio_ext) => Value::Text(audio_ext.as_str().to_owned()),
+            EntryFilterValue::UnixTime(unix_time) => Value::Integer(unix_time as i64),
+        });
+    }
+    (query.build(Dialect::Sqlite), values)
+}
+
+pub fn select_filtered_ytdlp_entries(db_conn: &DatabaseConnection, filter: &EntryFilter) -> Result<Vec<YtdlpRow>, rusqlite::Error> {
+    let (query, values) = get_entry_filter_query(&YTDLP_TABLE, filter);
+    let mut stmt = db_conn.prepare_cached(query.as_str())?;
+    let row_iter = stmt.query_map(rusqlite::params_from_iter(values), map_ytdlp_row_to_entry)?;
+    row_iter.collect()
+}
 
+pub fn select_filtered_ffmpeg_entries(db_conn: &DatabaseConnection, filter: &EntryFilter) -> Result<Vec<FfmpegRow>, rusqlite::Error> {
+    let (query, values) = get_entry_filter_query(&FFMPEG_TABLE, filter);
+    let mut stmt = db_conn.prepare_cached(query.as_str())?;
+    let row_iter = stmt.query_map(rusqlite::params_from_iter(values), map_ffmpeg_row_to_entry)?;
+    row_iter.collect()
+}
+
+pub fn select_video_ffmpeg_entries(db_conn: &DatabaseConnection, video_id: &VideoId) -> Result<Vec<FfmpegRow>, rusqlite::Error> {
+    let query = FFMPEG_TABLE.select().filter_equal(&columns!(FFMPEG_TABLE; video_id));
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([video_id.as_str()], map_ffmpeg_row_to_entry)?;
+    row_iter.collect()
+}
+
+pub fn select_ffmpeg_entries(db_conn: &DatabaseConnection) -> Result<Vec<FfmpegRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(FFMPEG_TABLE.select().build(Dialect::Sqlite).as_str())?;
     let row_iter = stmt.query_map([], map_ffmpeg_row_to_entry)?;
     let mut entries = Vec::<FfmpegRow>::new();
     for row in row_iter {
@@ -314,39 +891,815 @@ pub fn select_ffmpeg_entries(db_conn: &DatabaseConnection) -> Result<Vec<FfmpegR
 }
 
 pub fn select_ffmpeg_entry(
-    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension,
+    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension, variant: &str,
 ) -> Result<Option<FfmpegRow>, rusqlite::Error> {
-    let table: &'static str = WorkerTable::Ffmpeg.into();
-    let mut stmt = db_conn.prepare(format!(
-        "SELECT video_id, audio_ext, status, unix_time,\
-         stdout_log_path, stderr_log_path, system_log_path, audio_path \
-         FROM {table} WHERE video_id=?1 AND audio_ext=?2").as_str())?;
-    stmt.query_row([video_id.as_str(), audio_ext.as_str()], map_ffmpeg_row_to_entry).optional()
-}
-
-// select and update
-pub fn select_and_update_ytdlp_entry<F>(
-    db_conn: &DatabaseConnection, video_id: &VideoId, callback: F,
-) -> Result<usize, rusqlite::Error> 
-where F: FnOnce(&mut YtdlpRow)
-{
-    let entry = select_ytdlp_entry(db_conn, video_id)?;
-    let Some(mut entry) = entry else {
-        return Ok(0);
-    };
-    callback(&mut entry);
-    update_ytdlp_entry(db_conn, &entry)
-}
-
-pub fn select_and_update_ffmpeg_entry<F>(
-    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension, callback: F,
-) -> Result<usize, rusqlite::Error> 
-where F: FnOnce(&mut FfmpegRow)
-{
-    let entry = select_ffmpeg_entry(db_conn, video_id, audio_ext)?;
-    let Some(mut entry) = entry else {
-        return Err(rusqlite::Error::QueryReturnedNoRows);
+    let query = FFMPEG_TABLE.select().filter_equal(&FFMPEG_KEY_COLUMNS);
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([video_id.as_str(), audio_ext.as_str(), variant], map_ffmpeg_row_to_entry).optional()
+}
+
+// search
+pub fn upsert_metadata_search_entry(
+    db_conn: &DatabaseConnection, video_id: &VideoId, title: &str, channel: &str, description: &str, tags: &str,
+) -> Result<usize, rusqlite::Error> {
+    // NOTE: fts5 virtual tables don't support primary keys so we replace manually
+    db_conn.execute("DELETE FROM metadata_search WHERE video_id=?1", (video_id.as_str(),))?;
+    db_conn.execute(
+        "INSERT INTO metadata_search (video_id, title, channel, description, tags) VALUES (?1,?2,?3,?4,?5)",
+        (video_id.as_str(), title, channel, description, tags),
+    )
+}
+
+/// Converts free form user input into a fts5 query where every word is a quoted prefix match
+/// This avoids syntax errors from user input that contains fts5 operators or unbalanced quotes
+fn to_fts5_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{0}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// NOTE: Owners are filtered in the query so the limit applies to the entries the user can access
+pub fn search_metadata_entries(
+    db_conn: &DatabaseConnection, query: &str, owner: Option<i64>, limit: usize,
+) -> Result<Vec<SearchRow>, rusqlite::Error> {
+    let query = to_fts5_query(query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let table = YTDLP_TABLE.name;
+    let mut stmt = db_conn.prepare_cached(format!(
+        "SELECT video_id, title, channel FROM metadata_search \
+         WHERE metadata_search MATCH ?1 \
+         AND video_id IN (SELECT video_id FROM {table} WHERE ?2 IS NULL OR owner IS NULL OR owner=?2) \
+         ORDER BY rank LIMIT ?3").as_str())?;
+    let row_iter = stmt.query_map((query, owner, limit), |row| {
+        let video_id: String = row.get(0)?;
+        Ok(SearchRow {
+            video_id: VideoId::try_new(video_id.as_str()).expect("video_id should be valid"),
+            title: row.get(1)?,
+            channel: row.get(2)?,
+        })
+    })?;
+    let mut entries = Vec::<SearchRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+// metadata
+pub fn upsert_metadata_entry(db_conn: &DatabaseConnection, entry: &MetadataRow) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT OR REPLACE INTO metadata (video_id, title, channel, duration_ms, thumbnail_url, unix_time) VALUES (?1,?2,?3,?4,?5,?6)",
+        params![
+            entry.video_id.as_str(), entry.title.as_str(), entry.channel.as_str(), entry.duration_ms, entry.thumbnail_url.as_ref(),
+            get_unix_time(),
+        ],
+    )
+}
+
+/// Videos whose metadata was fetched before this time, metadata stored before fetches were timestamped comes first
+pub fn select_stale_metadata_video_ids(
+    db_conn: &DatabaseConnection, before_unix_time: u64, limit: usize,
+) -> Result<Vec<VideoId>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(
+        "SELECT video_id FROM metadata WHERE unix_time IS NULL OR unix_time < ?1 \
+         ORDER BY COALESCE(unix_time, 0), video_id LIMIT ?2"
+    )?;
+    let row_iter = stmt.query_map(params![before_unix_time, limit], |row| {
+        let video_id: String = row.get(0)?;
+        Ok(VideoId::try_new(video_id.as_str()).expect("video_id should be valid"))
+    })?;
+    let mut entries = Vec::<VideoId>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+fn map_metadata_row_to_entry(row: &rusqlite::Row, video_id: &VideoId, offset: usize) -> Result<Option<MetadataRow>, rusqlite::Error> {
+    // NOTE: Columns are null when the left join has no matching metadata
+    let title: Option<String> = row.get(offset)?;
+    let Some(title) = title else {
+        return Ok(None);
     };
-    callback(&mut entry);
-    update_ffmpeg_entry(db_conn, &entry)
+    let channel: Option<String> = row.get(offset+1)?;
+    Ok(Some(MetadataRow {
+        video_id: video_id.clone(),
+        title,
+        channel: channel.unwrap_or_default(),
+        duration_ms: row.get(offset+2)?,
+        thumbnail_url: row.get(offset+3)?,
+    }))
+}
+
+pub fn select_metadata_entry(db_conn: &DatabaseConnection, video_id: &VideoId) -> Result<Option<MetadataRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(
+        "SELECT title, channel, duration_ms, thumbnail_url FROM metadata WHERE video_id=?1")?;
+    let entry = stmt.query_row([video_id.as_str()], |row| map_metadata_row_to_entry(row, video_id, 0)).optional()?;
+    Ok(entry.flatten())
+}
+
+// enrichments
+pub fn upsert_enrichment_entry(db_conn: &DatabaseConnection, entry: &EnrichmentRow) -> Result<usize, rusqlite::Error> {
+    let query = ENRICHMENT_TABLE.insert(&ENRICHMENT_INSERT_COLUMNS).on_conflict(&columns!(ENRICHMENT_TABLE; video_id), &[]);
+    db_conn.execute(
+        query.build(Dialect::Sqlite).as_str(),
+        params![
+            entry.video_id.as_str(), entry.recording_id, entry.score, entry.title, entry.artist, entry.album,
+            entry.album_artist, entry.track_number, entry.release_date, entry.unix_time,
+        ],
+    )
+}
+
+fn map_enrichment_row_to_entry(row: &rusqlite::Row) -> Result<EnrichmentRow, rusqlite::Error> {
+    let video_id: String = row.get(0)?;
+    let unix_time: Option<u64> = row.get(9)?;
+    Ok(EnrichmentRow {
+        video_id: VideoId::try_new(video_id.as_str()).expect("video_id should be valid"),
+        recording_id: row.get(1)?,
+        score: row.get(2)?,
+        title: row.get(3)?,
+        artist: row.get(4)?,
+        album: row.get(5)?,
+        album_artist: row.get(6)?,
+        track_number: row.get(7)?,
+        release_date: row.get(8)?,
+        unix_time: unix_time.unwrap_or(0),
+    })
+}
+
+pub fn select_enrichment_entry(db_conn: &DatabaseConnection, video_id: &VideoId) -> Result<Option<EnrichmentRow>, rusqlite::Error> {
+    let query = ENRICHMENT_TABLE.select().filter_equal(&columns!(ENRICHMENT_TABLE; video_id));
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([video_id.as_str()], map_enrichment_row_to_entry).optional()
+}
+
+pub fn select_library_entries(db_conn: &DatabaseConnection) -> Result<Vec<LibraryRow>, rusqlite::Error> {
+    let query = YTDLP_TABLE.select().join(METADATA_JOIN, METADATA_JOIN_COLUMNS);
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([], |row| {
+        let entry = map_ytdlp_row_to_entry(row)?;
+        let metadata = map_metadata_row_to_entry(row, &entry.video_id, TOTAL_YTDLP_COLUMNS)?;
+        Ok((entry, metadata))
+    })?;
+    let mut downloads = Vec::new();
+    for row in row_iter {
+        downloads.push(row?);
+    }
+
+    let query = FFMPEG_TABLE.select().join(METADATA_JOIN, METADATA_JOIN_COLUMNS);
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([], |row| {
+        let entry = map_ffmpeg_row_to_entry(row)?;
+        let metadata = map_metadata_row_to_entry(row, &entry.video_id, TOTAL_FFMPEG_COLUMNS)?;
+        Ok((entry, metadata))
+    })?;
+    let mut transcodes = Vec::new();
+    for row in row_iter {
+        transcodes.push(row?);
+    }
+    Ok(merge_library_rows(downloads, transcodes))
+}
+
+// subscriptions
+pub fn insert_subscription_entry(
+    db_conn: &DatabaseConnection, url: &str, audio_ext: AudioExtension, transcode_options: Option<&str>,
+    check_interval_seconds: u64,
+) -> Result<i64, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO subscriptions (url, audio_ext, transcode_options, check_interval_seconds, unix_time) \
+         VALUES (?1,?2,?3,?4,?5)",
+        (url, audio_ext.as_str(), transcode_options, check_interval_seconds, get_unix_time()),
+    )?;
+    Ok(db_conn.last_insert_rowid())
+}
+
+pub fn update_subscription_entry(db_conn: &DatabaseConnection, entry: &SubscriptionRow) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "UPDATE subscriptions SET \
+         audio_ext=?2, transcode_options=?3, check_interval_seconds=?4, last_check_unix_time=?5, last_check_error=?6 \
+         WHERE id=?1",
+        params![
+            entry.id, entry.audio_ext.as_str(), entry.transcode_options, entry.check_interval_seconds,
+            entry.last_check_unix_time, entry.last_check_error,
+        ],
+    )
+}
+
+pub fn delete_subscription_entry(db_conn: &DatabaseConnection, id: i64) -> Result<usize, rusqlite::Error> {
+    db_conn.execute("DELETE FROM subscription_videos WHERE subscription_id=?1", (id,))?;
+    db_conn.execute("DELETE FROM subscriptions WHERE id=?1", (id,))
+}
+
+fn map_subscription_row_to_entry(row: &rusqlite::Row) -> Result<SubscriptionRow, rusqlite::Error> {
+    let audio_ext: String = row.get(2)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let unix_time: Option<u64> = row.get(5)?;
+    Ok(SubscriptionRow {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        audio_ext,
+        transcode_options: row.get(3)?,
+        check_interval_seconds: row.get(4)?,
+        unix_time: unix_time.unwrap_or(0),
+        last_check_unix_time: row.get(6)?,
+        last_check_error: row.get(7)?,
+    })
+}
+
+pub fn select_subscription_entries(db_conn: &DatabaseConnection) -> Result<Vec<SubscriptionRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(SUBSCRIPTION_TABLE.select().order_by(column!(SUBSCRIPTION_TABLE; id), Order::Ascending).build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([], map_subscription_row_to_entry)?;
+    let mut entries = Vec::<SubscriptionRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+pub fn select_subscription_entry(db_conn: &DatabaseConnection, id: i64) -> Result<Option<SubscriptionRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(SUBSCRIPTION_TABLE.select().filter_equal(&columns!(SUBSCRIPTION_TABLE; id)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([id], map_subscription_row_to_entry).optional()
+}
+
+/// Returns 0 if the video was already seen by the subscription
+pub fn insert_subscription_video_entry(
+    db_conn: &DatabaseConnection, id: i64, video_id: &VideoId,
+) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT OR IGNORE INTO subscription_videos (subscription_id, video_id, unix_time) VALUES (?1,?2,?3)",
+        (id, video_id.as_str(), get_unix_time()),
+    )
+}
+
+// channel jobs
+pub fn insert_channel_job_entry(
+    db_conn: &DatabaseConnection, channel_id: &str, audio_ext: AudioExtension, variant: &str,
+    transcode_options: Option<&str>, filters: Option<&str>, owner: Option<i64>,
+) -> Result<i64, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO channel_jobs (channel_id, audio_ext, variant, transcode_options, filters, status, owner, unix_time) \
+         VALUES (?1,?2,?3,?4,?5,?6,?7,?8)",
+        params![
+            channel_id, audio_ext.as_str(), variant, transcode_options, filters,
+            WorkerStatus::Queued.to_u8(), owner, get_unix_time(),
+        ],
+    )?;
+    Ok(db_conn.last_insert_rowid())
+}
+
+pub fn update_channel_job_entry(db_conn: &DatabaseConnection, entry: &ChannelJobRow) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "UPDATE channel_jobs SET status=?2, error=?3, total_listed=?4 WHERE id=?1",
+        params![entry.id, entry.status.to_u8(), entry.error, entry.total_listed],
+    )
+}
+
+fn map_channel_job_row_to_entry(row: &rusqlite::Row) -> Result<ChannelJobRow, rusqlite::Error> {
+    let audio_ext: String = row.get(2)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let status: u8 = row.get(6)?;
+    let status = WorkerStatus::from_u8(status).expect("status should be valid");
+    let unix_time: Option<u64> = row.get(10)?;
+    Ok(ChannelJobRow {
+        id: row.get(0)?,
+        channel_id: row.get(1)?,
+        audio_ext,
+        variant: row.get(3)?,
+        transcode_options: row.get(4)?,
+        filters: row.get(5)?,
+        status,
+        error: row.get(7)?,
+        total_listed: row.get(8)?,
+        owner: row.get(9)?,
+        unix_time: unix_time.unwrap_or(0),
+    })
+}
+
+pub fn select_channel_job_entries(db_conn: &DatabaseConnection) -> Result<Vec<ChannelJobRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(CHANNEL_JOB_TABLE.select().order_by(column!(CHANNEL_JOB_TABLE; id), Order::Descending).build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([], map_channel_job_row_to_entry)?;
+    let mut entries = Vec::<ChannelJobRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+pub fn select_channel_job_entry(db_conn: &DatabaseConnection, id: i64) -> Result<Option<ChannelJobRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(CHANNEL_JOB_TABLE.select().filter_equal(&columns!(CHANNEL_JOB_TABLE; id)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([id], map_channel_job_row_to_entry).optional()
+}
+
+/// Returns 0 if the video was already added to the channel job
+pub fn insert_channel_job_video_entry(
+    db_conn: &DatabaseConnection, id: i64, video_id: &VideoId, title: Option<&str>,
+) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT OR IGNORE INTO channel_job_videos (channel_job_id, video_id, title, unix_time) VALUES (?1,?2,?3,?4)",
+        (id, video_id.as_str(), title, get_unix_time()),
+    )
+}
+
+// NOTE: Videos without a download or transcode entry haven't been started yet
+pub fn select_channel_job_video_entries(
+    db_conn: &DatabaseConnection, id: i64, audio_ext: AudioExtension, variant: &str,
+) -> Result<Vec<ChannelJobVideoRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(
+        "SELECT videos.video_id, videos.title, ytdlp.status, ffmpeg.status FROM channel_job_videos AS videos \
+         LEFT JOIN ytdlp ON ytdlp.video_id = videos.video_id \
+         LEFT JOIN ffmpeg ON ffmpeg.video_id = videos.video_id AND ffmpeg.audio_ext=?2 AND ffmpeg.variant=?3 \
+         WHERE videos.channel_job_id=?1 ORDER BY videos.unix_time, videos.video_id"
+    )?;
+    let row_iter = stmt.query_map(params![id, audio_ext.as_str(), variant], |row| {
+        let video_id: String = row.get(0)?;
+        let download_status: Option<u8> = row.get(2)?;
+        let transcode_status: Option<u8> = row.get(3)?;
+        Ok(ChannelJobVideoRow {
+            video_id: VideoId::try_new(video_id.as_str()).expect("video_id should be valid"),
+            title: row.get(1)?,
+            download_status: download_status.and_then(WorkerStatus::from_u8).unwrap_or_default(),
+            transcode_status: transcode_status.and_then(WorkerStatus::from_u8).unwrap_or_default(),
+        })
+    })?;
+    let mut entries = Vec::<ChannelJobVideoRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+// maintenance runs
+/// Returns the id of the new run which starts as running
+pub fn insert_maintenance_run_entry(db_conn: &DatabaseConnection, task: &str, is_manual: bool) -> Result<i64, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO maintenance_runs (task, is_manual, status, unix_time) VALUES (?1,?2,?3,?4)",
+        params![task, is_manual, WorkerStatus::Running.to_u8(), get_unix_time()],
+    )?;
+    Ok(db_conn.last_insert_rowid())
+}
+
+pub fn update_maintenance_run_entry(db_conn: &DatabaseConnection, entry: &MaintenanceRunRow) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "UPDATE maintenance_runs SET status=?2, summary=?3, error=?4, end_unix_time=?5 WHERE id=?1",
+        params![entry.id, entry.status.to_u8(), entry.summary, entry.error, entry.end_unix_time],
+    )
+}
+
+fn map_maintenance_run_row_to_entry(row: &rusqlite::Row) -> Result<MaintenanceRunRow, rusqlite::Error> {
+    let status: u8 = row.get(3)?;
+    let status = WorkerStatus::from_u8(status).expect("status should be valid");
+    let unix_time: Option<u64> = row.get(6)?;
+    Ok(MaintenanceRunRow {
+        id: row.get(0)?,
+        task: row.get(1)?,
+        is_manual: row.get(2)?,
+        status,
+        summary: row.get(4)?,
+        error: row.get(5)?,
+        unix_time: unix_time.unwrap_or(0),
+        end_unix_time: row.get(7)?,
+    })
+}
+
+pub fn select_maintenance_run_entry(db_conn: &DatabaseConnection, id: i64) -> Result<Option<MaintenanceRunRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(MAINTENANCE_RUN_TABLE.select().filter_equal(&columns!(MAINTENANCE_RUN_TABLE; id)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([id], map_maintenance_run_row_to_entry).optional()
+}
+
+pub fn select_maintenance_run_entries(db_conn: &DatabaseConnection, limit: usize) -> Result<Vec<MaintenanceRunRow>, rusqlite::Error> {
+    let query = MAINTENANCE_RUN_TABLE.select().order_by(column!(MAINTENANCE_RUN_TABLE; id), Order::Descending).limit();
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([limit], map_maintenance_run_row_to_entry)?;
+    let mut entries = Vec::<MaintenanceRunRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+// trash
+/// Returns the id of the new trash entry
+pub fn insert_trash_entry(
+    db_conn: &DatabaseConnection, kind: JobKind, key: &str, owner: Option<i64>, entry: &str, files: &str,
+    file_size_bytes: Option<u64>,
+) -> Result<i64, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO trash (kind, key, owner, entry, files, file_size_bytes, deleted_at) VALUES (?1,?2,?3,?4,?5,?6,?7)",
+        params![kind.as_str(), key, owner, entry, files, file_size_bytes, get_unix_time()],
+    )?;
+    Ok(db_conn.last_insert_rowid())
+}
+
+pub fn delete_trash_entry(db_conn: &DatabaseConnection, id: i64) -> Result<usize, rusqlite::Error> {
+    db_conn.execute("DELETE FROM trash WHERE id=?1", [id])
+}
+
+fn map_trash_row_to_entry(row: &rusqlite::Row) -> Result<TrashRow, rusqlite::Error> {
+    let kind: String = row.get(1)?;
+    let kind = JobKind::try_from(kind.as_str()).expect("kind should be valid");
+    Ok(TrashRow {
+        id: row.get(0)?,
+        kind,
+        key: row.get(2)?,
+        owner: row.get(3)?,
+        entry: row.get(4)?,
+        files: row.get(5)?,
+        file_size_bytes: row.get(6)?,
+        deleted_at: row.get(7)?,
+    })
+}
+
+pub fn select_trash_entry(db_conn: &DatabaseConnection, id: i64) -> Result<Option<TrashRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(TRASH_TABLE.select().filter_equal(&columns!(TRASH_TABLE; id)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([id], map_trash_row_to_entry).optional()
+}
+
+pub fn select_trash_entries(db_conn: &DatabaseConnection) -> Result<Vec<TrashRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(TRASH_TABLE.select().order_by(column!(TRASH_TABLE; id), Order::Descending).build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([], map_trash_row_to_entry)?;
+    let mut entries = Vec::<TrashRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+// export
+pub fn select_table_rows(db_conn: &DatabaseConnection, table: &str) -> Result<Vec<TableRow>, rusqlite::Error> {
+    use rusqlite::types::ValueRef;
+    let mut stmt = db_conn.prepare_cached(format!("SELECT * FROM {table}").as_str())?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|&column| column.to_owned()).collect();
+    let row_iter = stmt.query_map([], |row| {
+        let mut entry = TableRow::new();
+        for (index, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(index)? {
+                ValueRef::Null => serde_json::Value::Null,
+                ValueRef::Integer(value) => serde_json::Value::from(value),
+                ValueRef::Real(value) => serde_json::Value::from(value),
+                ValueRef::Text(value) => serde_json::Value::from(String::from_utf8_lossy(value).into_owned()),
+                // NOTE: None of our tables store blobs
+                ValueRef::Blob(_) => serde_json::Value::Null,
+            };
+            entry.insert(column.clone(), value);
+        }
+        Ok(entry)
+    })?;
+    let mut entries = Vec::<TableRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+fn map_json_to_sql_value(value: &serde_json::Value) -> rusqlite::types::Value {
+    use rusqlite::types::Value;
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(value) => Value::Integer(*value as i64),
+        serde_json::Value::Number(value) => match value.as_i64() {
+            Some(value) => Value::Integer(value),
+            None => Value::Real(value.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(value) => Value::Text(value.clone()),
+        value => Value::Text(value.to_string()),
+    }
+}
+
+/// Replaces the rows of every exported table in a single transaction and returns the number of rows inserted
+// NOTE: Column names are inserted into the query so they must be validated by the caller
+pub fn replace_table_rows(
+    db_conn: &mut DatabaseConnection, tables: &BTreeMap<String, Vec<TableRow>>,
+) -> Result<usize, rusqlite::Error> {
+    let tx = db_conn.transaction()?;
+    let mut total_inserted = 0;
+    for &table in EXPORT_TABLES {
+        tx.execute(format!("DELETE FROM {table}").as_str(), ())?;
+        for row in tables.get(table).into_iter().flatten() {
+            let columns: Vec<&str> = row.keys().map(|column| column.as_str()).collect();
+            let placeholders: Vec<String> = (1..=columns.len()).map(|index| format!("?{index}")).collect();
+            let values: Vec<rusqlite::types::Value> = row.values().map(map_json_to_sql_value).collect();
+            total_inserted += tx.execute(
+                format!("INSERT INTO {table} ({0}) VALUES ({1})", columns.join(", "), placeholders.join(",")).as_str(),
+                rusqlite::params_from_iter(values),
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(total_inserted)
+}
+
+// job durations
+pub fn insert_job_duration_entry(db_conn: &DatabaseConnection, kind: JobKind, elapsed_ms: u64) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO job_durations (kind, unix_time, elapsed_ms) VALUES (?1,?2,?3)",
+        (kind.as_str(), get_unix_time(), elapsed_ms),
+    )
+}
+
+/// Average over the most recent jobs so the estimate follows changes in load and network speed
+pub fn select_average_job_duration(
+    db_conn: &DatabaseConnection, kind: JobKind, limit: usize,
+) -> Result<Option<u64>, rusqlite::Error> {
+    let average: Option<f64> = db_conn.query_row(
+        "SELECT AVG(elapsed_ms) FROM (\
+            SELECT elapsed_ms FROM job_durations WHERE kind=?1 ORDER BY unix_time DESC LIMIT ?2\
+         )",
+        (kind.as_str(), limit),
+        |row| row.get(0),
+    )?;
+    Ok(average.map(|average| average as u64))
+}
+
+// jobs
+pub fn insert_job_entry(db_conn: &DatabaseConnection, entry: &JobRow) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO jobs (job_id, idempotency_key, video_id, audio_ext, variant, unix_time) VALUES (?1,?2,?3,?4,?5,?6)",
+        params![
+            entry.job_id, entry.idempotency_key, entry.video_id.as_str(), entry.audio_ext.as_str(), entry.variant,
+            entry.unix_time,
+        ],
+    )
+}
+
+fn map_job_row_to_entry(row: &rusqlite::Row) -> Result<JobRow, rusqlite::Error> {
+    let video_id: String = row.get(2)?;
+    let video_id = VideoId::try_new(video_id.as_str()).expect("video_id should be valid");
+    let audio_ext: String = row.get(3)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let unix_time: Option<u64> = row.get(5)?;
+    Ok(JobRow {
+        job_id: row.get(0)?,
+        idempotency_key: row.get(1)?,
+        video_id,
+        audio_ext,
+        variant: row.get(4)?,
+        unix_time: unix_time.unwrap_or(0),
+    })
+}
+
+pub fn select_job_entry(db_conn: &DatabaseConnection, job_id: &str) -> Result<Option<JobRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(JOB_TABLE.select().filter_equal(&columns!(JOB_TABLE; job_id)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([job_id], map_job_row_to_entry).optional()
+}
+
+pub fn select_job_entry_by_idempotency_key(
+    db_conn: &DatabaseConnection, idempotency_key: &str,
+) -> Result<Option<JobRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(JOB_TABLE.select().filter_equal(&columns!(JOB_TABLE; idempotency_key)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([idempotency_key], map_job_row_to_entry).optional()
+}
+
+pub fn select_latest_job_entry(
+    db_conn: &DatabaseConnection, video_id: &VideoId, audio_ext: AudioExtension, variant: &str,
+) -> Result<Option<JobRow>, rusqlite::Error> {
+    let query = JOB_TABLE.select()
+        .filter_equal(&columns!(JOB_TABLE; video_id, audio_ext, variant))
+        .order_by(column!(JOB_TABLE; unix_time), Order::Descending)
+        .limit();
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    stmt.query_row(params![video_id.as_str(), audio_ext.as_str(), variant, 1], map_job_row_to_entry).optional()
+}
+
+// users
+pub fn insert_user_entry(
+    db_conn: &DatabaseConnection, username: &str, password_hash: &str, is_admin: bool,
+) -> Result<i64, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO users (username, password_hash, is_admin, unix_time) VALUES (?1,?2,?3,?4)",
+        (username, password_hash, is_admin, get_unix_time()),
+    )?;
+    Ok(db_conn.last_insert_rowid())
+}
+
+/// Tokens of the user are also deleted while the entries they own are left for admins to manage
+pub fn delete_user_entry(db_conn: &DatabaseConnection, id: i64) -> Result<usize, rusqlite::Error> {
+    db_conn.execute("DELETE FROM auth_tokens WHERE user_id=?1", (id,))?;
+    db_conn.execute("DELETE FROM users WHERE id=?1", (id,))
+}
+
+fn map_user_row_to_entry(row: &rusqlite::Row) -> Result<UserRow, rusqlite::Error> {
+    let unix_time: Option<u64> = row.get(4)?;
+    Ok(UserRow {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        password_hash: row.get(2)?,
+        is_admin: row.get(3)?,
+        unix_time: unix_time.unwrap_or(0),
+    })
+}
+
+pub fn select_user_entries(db_conn: &DatabaseConnection) -> Result<Vec<UserRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(USER_TABLE.select().order_by(column!(USER_TABLE; id), Order::Ascending).build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([], map_user_row_to_entry)?;
+    let mut entries = Vec::<UserRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+pub fn select_user_entry(db_conn: &DatabaseConnection, id: i64) -> Result<Option<UserRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(USER_TABLE.select().filter_equal(&columns!(USER_TABLE; id)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([id], map_user_row_to_entry).optional()
+}
+
+pub fn select_user_entry_by_username(db_conn: &DatabaseConnection, username: &str) -> Result<Option<UserRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(USER_TABLE.select().filter_equal(&columns!(USER_TABLE; username)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([username], map_user_row_to_entry).optional()
+}
+
+// auth tokens
+pub fn insert_auth_token_entry(
+    db_conn: &DatabaseConnection, token_hash: &str, user_id: i64, kind: AuthTokenKind, name: Option<&str>,
+    expire_unix_time: Option<u64>,
+) -> Result<i64, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO auth_tokens (token_hash, user_id, kind, name, unix_time, expire_unix_time) VALUES (?1,?2,?3,?4,?5,?6)",
+        (token_hash, user_id, kind.as_str(), name, get_unix_time(), expire_unix_time),
+    )?;
+    Ok(db_conn.last_insert_rowid())
+}
+
+pub fn delete_auth_token_entry(db_conn: &DatabaseConnection, id: i64) -> Result<usize, rusqlite::Error> {
+    db_conn.execute("DELETE FROM auth_tokens WHERE id=?1", (id,))
+}
+
+fn map_auth_token_row_to_entry(row: &rusqlite::Row) -> Result<AuthTokenRow, rusqlite::Error> {
+    let kind: String = row.get(3)?;
+    let kind = AuthTokenKind::try_from(kind.as_str()).expect("kind should be valid");
+    let unix_time: Option<u64> = row.get(5)?;
+    Ok(AuthTokenRow {
+        id: row.get(0)?,
+        token_hash: row.get(1)?,
+        user_id: row.get(2)?,
+        kind,
+        name: row.get(4)?,
+        unix_time: unix_time.unwrap_or(0),
+        expire_unix_time: row.get(6)?,
+    })
+}
+
+pub fn select_auth_token_entry(db_conn: &DatabaseConnection, token_hash: &str) -> Result<Option<AuthTokenRow>, rusqlite::Error> {
+    let mut stmt = db_conn.prepare_cached(AUTH_TOKEN_TABLE.select().filter_equal(&columns!(AUTH_TOKEN_TABLE; token_hash)).build(Dialect::Sqlite).as_str())?;
+    stmt.query_row([token_hash], map_auth_token_row_to_entry).optional()
+}
+
+pub fn select_auth_token_entries(db_conn: &DatabaseConnection, user_id: i64) -> Result<Vec<AuthTokenRow>, rusqlite::Error> {
+    let query = AUTH_TOKEN_TABLE.select()
+        .filter_equal(&columns!(AUTH_TOKEN_TABLE; user_id))
+        .order_by(column!(AUTH_TOKEN_TABLE; id), Order::Ascending);
+    let mut stmt = db_conn.prepare_cached(query.build(Dialect::Sqlite).as_str())?;
+    let row_iter = stmt.query_map([user_id], map_auth_token_row_to_entry)?;
+    let mut entries = Vec::<AuthTokenRow>::new();
+    for row in row_iter {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+// quotas
+pub fn insert_conversion_entry(db_conn: &DatabaseConnection, user_id: i64, kind: JobKind, key: &str) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO conversions (user_id, kind, key, unix_time) VALUES (?1,?2,?3,?4)",
+        (user_id, kind.as_str(), key, get_unix_time()),
+    )
+}
+
+pub fn select_total_conversions(db_conn: &DatabaseConnection, user_id: i64, since_unix_time: u64) -> Result<u64, rusqlite::Error> {
+    db_conn.query_row(
+        "SELECT COUNT(*) FROM conversions WHERE user_id=?1 AND unix_time>=?2",
+        (user_id, since_unix_time),
+        |row| row.get(0),
+    )
+}
+
+/// Files that finished before their size was recorded aren't counted
+pub fn select_owner_storage_bytes(db_conn: &DatabaseConnection, owner: i64) -> Result<u64, rusqlite::Error> {
+    let (ytdlp_table, ffmpeg_table) = (YTDLP_TABLE.name, FFMPEG_TABLE.name);
+    db_conn.query_row(
+        format!(
+            "SELECT \
+             (SELECT COALESCE(SUM(file_size_bytes),0) FROM {ytdlp_table} WHERE owner=?1) + \
+             (SELECT COALESCE(SUM(file_size_bytes),0) FROM {ffmpeg_table} WHERE owner=?1)"
+        ).as_str(),
+        (owner,),
+        |row| row.get(0),
+    )
+}
+
+// job history
+#[allow(clippy::too_many_arguments)]
+pub fn insert_job_history_entry(
+    db_conn: &DatabaseConnection, kind: JobKind, key: &str, status: WorkerStatus,
+    error_category: Option<&str>, file_size_bytes: Option<u64>, elapsed_ms: u64, request_id: Option<&str>,
+) -> Result<usize, rusqlite::Error> {
+    db_conn.execute(
+        "INSERT INTO job_history (kind, key, status, error_category, file_size_bytes, elapsed_ms, unix_time, request_id) \
+         VALUES (?1,?2,?3,?4,?5,?6,?7,?8)",
+        params![kind.as_str(), key, status as u8, error_category, file_size_bytes, elapsed_ms, get_unix_time(), request_id],
+    )
+}
+
+pub fn map_job_kind(kind: String) -> JobKind {
+    JobKind::try_from(kind.as_str()).expect("kind should be valid")
+}
+
+pub fn select_jobs_per_day(db_conn: &DatabaseConnection, since_unix_time: u64) -> Result<Vec<JobsPerDayRow>, rusqlite::Error> {
+    let mut statement = db_conn.prepare_cached(
+        "SELECT unix_time/86400*86400 AS day, kind, COUNT(*), SUM(CASE WHEN status=?2 THEN 1 ELSE 0 END) \
+         FROM job_history WHERE unix_time>=?1 GROUP BY day, kind ORDER BY day, kind"
+    )?;
+    let rows = statement.query_map((since_unix_time, WorkerStatus::Failed as u8), |row| Ok(JobsPerDayRow {
+        day_unix_time: row.get(0)?,
+        kind: map_job_kind(row.get(1)?),
+        total: row.get(2)?,
+        total_failed: row.get(3)?,
+    }))?;
+    rows.collect()
+}
+
+pub fn select_job_failures(db_conn: &DatabaseConnection, since_unix_time: u64) -> Result<Vec<JobFailureRow>, rusqlite::Error> {
+    let mut statement = db_conn.prepare_cached(
+        "SELECT kind, COALESCE(error_category,'unknown') AS category, COUNT(*) AS total \
+         FROM job_history WHERE unix_time>=?1 AND status=?2 GROUP BY kind, category ORDER BY total DESC"
+    )?;
+    let rows = statement.query_map((since_unix_time, WorkerStatus::Failed as u8), |row| Ok(JobFailureRow {
+        kind: map_job_kind(row.get(0)?),
+        error_category: row.get(1)?,
+        total: row.get(2)?,
+    }))?;
+    rows.collect()
+}
+
+/// Channels of the videos with the most transcode requests
+pub fn select_top_channels(
+    db_conn: &DatabaseConnection, since_unix_time: u64, limit: usize,
+) -> Result<Vec<ChannelCountRow>, rusqlite::Error> {
+    let mut statement = db_conn.prepare_cached(
+        "SELECT metadata.channel, COUNT(*) AS total FROM jobs \
+         INNER JOIN metadata ON jobs.video_id=metadata.video_id \
+         WHERE jobs.unix_time>=?1 GROUP BY metadata.channel ORDER BY total DESC LIMIT ?2"
+    )?;
+    let rows = statement.query_map((since_unix_time, limit), |row| Ok(ChannelCountRow {
+        channel: row.get(0)?,
+        total: row.get(1)?,
+    }))?;
+    rows.collect()
+}
+
+pub fn select_table_usage(db_conn: &DatabaseConnection) -> Result<Vec<TableUsageRow>, rusqlite::Error> {
+    [YTDLP_TABLE.name, FFMPEG_TABLE.name].into_iter().map(|table| {
+        db_conn.query_row(
+            format!("SELECT COUNT(*), COALESCE(SUM(file_size_bytes),0) FROM {table}").as_str(), [],
+            |row| Ok(TableUsageRow { table: table.to_owned(), total_entries: row.get(0)?, total_bytes: row.get(1)? }),
+        )
+    }).collect()
+}
+
+pub fn select_job_throughputs(db_conn: &DatabaseConnection, since_unix_time: u64) -> Result<Vec<JobThroughputRow>, rusqlite::Error> {
+    let mut statement = db_conn.prepare_cached(
+        "SELECT kind, SUM(file_size_bytes), SUM(elapsed_ms) FROM job_history \
+         WHERE unix_time>=?1 AND status=?2 AND file_size_bytes IS NOT NULL GROUP BY kind ORDER BY kind"
+    )?;
+    let rows = statement.query_map((since_unix_time, WorkerStatus::Finished as u8), |row| Ok(JobThroughputRow {
+        kind: map_job_kind(row.get(0)?),
+        total_bytes: row.get(1)?,
+        total_elapsed_ms: row.get(2)?,
+    }))?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{AppConfig, AppState};
+    use super::VideoId;
+
+    #[test]
+    fn search_only_finds_accessible_downloads() {
+        let app = AppState::new_in_memory(AppConfig::default()).expect("App state should be created");
+        let job_store = &app.job_store;
+        let videos = [("aaaaaaaaaaa", None), ("bbbbbbbbbbb", Some(1)), ("ccccccccccc", Some(2))];
+        for (video_id, owner) in videos {
+            let video_id = VideoId::try_new(video_id).unwrap();
+            job_store.insert_ytdlp_entry(&video_id, None, owner).unwrap();
+            job_store.upsert_metadata_search_entry(&video_id, "Never Gonna Give You Up", "Rick Astley", "", "").unwrap();
+        }
+        let search = |owner: Option<i64>, limit: usize| -> Vec<String> {
+            let mut video_ids: Vec<String> = job_store.search_metadata_entries("never gonna", owner, limit).unwrap()
+                .into_iter()
+                .map(|entry| entry.video_id.as_str().to_owned())
+                .collect();
+            video_ids.sort();
+            video_ids
+        };
+        assert_eq!(search(None, 10), ["aaaaaaaaaaa", "bbbbbbbbbbb", "ccccccccccc"]);
+        assert_eq!(search(Some(1), 10), ["aaaaaaaaaaa", "bbbbbbbbbbb"]);
+        assert_eq!(search(Some(3), 10), ["aaaaaaaaaaa"]);
+        // NOTE: The limit only counts accessible entries
+        assert_eq!(search(Some(2), 2).len(), 2);
+    }
 }
@@ -0,0 +1,33 @@
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Space kept free on the data volume so the database and logs can still be written
+pub const DEFAULT_MIN_FREE_SPACE_MB: u64 = 256;
+// NOTE: yt-dlp picks the format so we assume a bitrate above the usual opus and aac audio to avoid underestimating
+const DOWNLOAD_BITRATE_KBPS: u64 = 192;
+
+#[derive(Debug,Error)]
+#[error("Not enough disk space in {path}: required={required_bytes} bytes, available={available_bytes} bytes")]
+pub struct DiskSpaceError {
+    pub path: PathBuf,
+    pub required_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Size of the downloaded audio of a video with this duration
+pub fn estimate_download_bytes(duration_ms: u64) -> u64 {
+    duration_ms * DOWNLOAD_BITRATE_KBPS / 8
+}
+
+/// Checks the volume of the path can fit the file along with the space that is kept free
+// NOTE: The check is skipped if the free space can't be read (e.g. the directory doesn't exist yet)
+pub fn check_free_space(path: &Path, file_bytes: Option<u64>, min_free_bytes: u64) -> Result<(), DiskSpaceError> {
+    let Ok(available_bytes) = fs2::available_space(path) else {
+        return Ok(());
+    };
+    let required_bytes = file_bytes.unwrap_or(0).saturating_add(min_free_bytes);
+    if required_bytes > available_bytes {
+        return Err(DiskSpaceError { path: path.to_owned(), required_bytes, available_bytes });
+    }
+    Ok(())
+}
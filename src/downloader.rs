@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use regex::Regex;
+use serde::Serialize;
+use thiserror::Error;
+use crate::error_code::ErrorCode;
+use crate::job_process::JobProcess;
+use crate::process_priority::ProcessPriority;
+use crate::ytdlp::{DownloadOptions, YtdlpDownloader};
+use crate::ytdlp_auth::YtdlpAuth;
+
+/// Everything a downloader needs to fetch the audio of a url
+pub struct DownloadRequest<'a> {
+    pub url: &'a str,
+    pub ffmpeg_binary: &'a Path,
+    pub output_template: &'a Path,
+    pub subtitle_output_template: &'a Path,
+    /// Continue from the partially downloaded file instead of starting again
+    pub is_resume: bool,
+    pub proxy: Option<&'a str>,
+    pub geo_bypass_country: Option<&'a str>,
+    pub options: &'a DownloadOptions,
+    /// Workarounds for videos that YouTube only shows to logged in clients
+    pub auth: &'a YtdlpAuth,
+}
+
+#[derive(Clone,Copy,Debug,Default,Serialize)]
+pub struct DownloadProgress {
+    pub eta_seconds: Option<u64>,
+    pub elapsed_seconds: Option<u64>,
+    pub downloaded_bytes: Option<usize>,
+    pub total_bytes: Option<usize>,
+    pub speed_bytes: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ParsedStdoutLine {
+    DownloadProgress(DownloadProgress),
+    OutputPath(String),
+    DownloadPath(String),
+    DurationMilliseconds(u64),
+    FormatId(String),
+}
+
+#[derive(Clone,Debug)]
+pub enum ParsedStderrLine {
+    UsageError(String),
+    MissingVideo(String),
+    ExtractPath(String),
+    Failure(ErrorCode),
+}
+
+/// Backend that runs a download process whose output is scraped by the download worker
+pub trait Downloader: std::fmt::Debug + Send + Sync {
+    /// Name used in logs and when choosing the backend with --downloader
+    fn name(&self) -> &'static str;
+    fn binary(&self) -> &Path;
+    fn get_arguments(&self, request: &DownloadRequest) -> Vec<String>;
+    fn parse_stdout_line(&self, line: &str) -> Option<ParsedStdoutLine>;
+    fn parse_stderr_line(&self, line: &str) -> Option<ParsedStderrLine>;
+    /// Starts the download whose output is parsed by the methods above
+    fn spawn(&self, request: &DownloadRequest, priority: &ProcessPriority) -> std::io::Result<JobProcess> {
+        JobProcess::spawn(self.binary(), self.get_arguments(request).as_slice(), false, priority)
+    }
+}
+
+pub type SharedDownloader = Arc<dyn Downloader>;
+
+pub const DOWNLOADER_BACKENDS: &[&str] = &[YtdlpDownloader::NAME];
+
+#[derive(Debug,Error)]
+pub enum DownloaderRuleError {
+    #[error("Downloader rule must be <backend>:<binary path>=<url regex>: given={0}")]
+    InvalidFormat(String),
+    #[error("Invalid url pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+    #[error("Unknown downloader backend {given}, expected one of: {expected:?}")]
+    UnknownBackend { given: String, expected: &'static [&'static str] },
+}
+
+pub fn create_downloader(backend: &str, binary: PathBuf) -> Result<SharedDownloader, DownloaderRuleError> {
+    match backend {
+        YtdlpDownloader::NAME => Ok(Arc::new(YtdlpDownloader::new(binary))),
+        _ => Err(DownloaderRuleError::UnknownBackend { given: backend.to_owned(), expected: DOWNLOADER_BACKENDS }),
+    }
+}
+
+/// Urls matching the pattern are downloaded with this backend instead of the default yt-dlp binary
+#[derive(Clone,Debug)]
+pub struct DownloaderRule {
+    pub url_pattern: Regex,
+    pub downloader: SharedDownloader,
+}
+
+impl DownloaderRule {
+    // NOTE: The binary path comes before the pattern since windows paths contain a colon and patterns can contain anything
+    pub fn parse(rule: &str) -> Result<Self, DownloaderRuleError> {
+        let (downloader, url_pattern) = rule.split_once('=').ok_or(DownloaderRuleError::InvalidFormat(rule.to_owned()))?;
+        let (backend, binary) = downloader.split_once(':').ok_or(DownloaderRuleError::InvalidFormat(rule.to_owned()))?;
+        if binary.is_empty() || url_pattern.is_empty() {
+            return Err(DownloaderRuleError::InvalidFormat(rule.to_owned()));
+        }
+        Ok(Self {
+            url_pattern: Regex::new(url_pattern)?,
+            downloader: create_downloader(backend, PathBuf::from(binary))?,
+        })
+    }
+}
+
+/// The first rule matching the url is used
+pub fn select_downloader<'a>(rules: &'a [DownloaderRule], url: &str) -> Option<&'a SharedDownloader> {
+    rules.iter().find(|rule| rule.url_pattern.is_match(url)).map(|rule| &rule.downloader)
+}
@@ -0,0 +1,245 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::task::block_in_place;
+use crate::database::{EnrichmentRow, VideoId};
+use crate::ffprobe::ProbeError;
+use crate::job_store::{JobStoreError, SharedJobStore};
+use crate::transcoder::SharedTranscoder;
+use crate::util::get_unix_time;
+
+const ACOUSTID_LOOKUP_URL: &str = "https://api.acoustid.org/v2/lookup";
+const MUSICBRAINZ_RECORDING_URL: &str = "https://musicbrainz.org/ws/2/recording";
+// NOTE: MusicBrainz blocks clients without a user agent that identifies the application
+const USER_AGENT: &str = concat!("ytdlp_webui/", env!("CARGO_PKG_VERSION"), " ( https://github.com/williamyang98/ytdlp_webui )");
+// NOTE: MusicBrainz blocks clients that make more than one request per second
+const MUSICBRAINZ_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Matches below this score are usually a different recording of the same song or a different song entirely
+pub const MIN_ACOUSTID_SCORE: f64 = 0.8;
+
+#[derive(Debug,Error)]
+pub enum EnrichmentError {
+    #[error("Failed to fingerprint audio: {0}")]
+    Fingerprint(#[from] ProbeError),
+    #[error("Source duration is needed to look up the fingerprint")]
+    MissingDuration,
+    #[error("Failed to send request: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Failed to parse response: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("AcoustID returned an error: code={code}, message={message}")]
+    AcoustId { code: i64, message: String },
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct AcoustIdError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct AcoustIdRecording {
+    id: String,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct AcoustIdResult {
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<AcoustIdRecording>,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct AcoustIdResponse {
+    status: String,
+    #[serde(default)]
+    results: Vec<AcoustIdResult>,
+    error: Option<AcoustIdError>,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct Track {
+    number: Option<String>,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct Medium {
+    #[serde(default)]
+    tracks: Vec<Track>,
+    #[serde(rename = "track-offset")]
+    track_offset: Option<u32>,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct Release {
+    title: String,
+    status: Option<String>,
+    date: Option<String>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+impl Release {
+    // NOTE: Vinyl sides number their tracks as A1, B2 which aren't stored as a number
+    fn get_track_number(&self) -> Option<u32> {
+        let medium = self.media.first()?;
+        medium.tracks.first()
+            .and_then(|track| track.number.as_ref()?.parse().ok())
+            .or(medium.track_offset.map(|offset| offset+1))
+    }
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct Recording {
+    title: String,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+fn join_artist_credit(credits: &[ArtistCredit]) -> Option<String> {
+    if credits.is_empty() {
+        return None;
+    }
+    Some(credits.iter().map(|credit| format!("{0}{1}", credit.name, credit.joinphrase)).collect())
+}
+
+/// Official releases are picked over bootlegs and promotions, then the earliest release which is usually the original album
+fn find_original_release(releases: &[Release]) -> Option<&Release> {
+    releases.iter().min_by_key(|release| (
+        release.status.as_deref() != Some("Official"),
+        release.date.is_none(),
+        release.date.clone(),
+    ))
+}
+
+/// Identifies the audio with AcoustID and fills in its tags from MusicBrainz
+#[derive(Clone)]
+pub struct Enricher {
+    acoustid_api_key: String,
+    http_client: reqwest::Client,
+    next_musicbrainz_request: Arc<Mutex<Instant>>,
+}
+
+// NOTE: The config is logged on startup so the api key is left out
+impl std::fmt::Debug for Enricher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Enricher").finish_non_exhaustive()
+    }
+}
+
+impl Enricher {
+    pub fn new(acoustid_api_key: &str, proxy: Option<&str>) -> Result<Self, reqwest::Error> {
+        let mut http_client = reqwest::Client::builder().user_agent(USER_AGENT).timeout(REQUEST_TIMEOUT);
+        if let Some(proxy) = proxy {
+            http_client = http_client.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(Self {
+            acoustid_api_key: acoustid_api_key.to_owned(),
+            http_client: http_client.build()?,
+            next_musicbrainz_request: Arc::new(Mutex::new(Instant::now())),
+        })
+    }
+
+    /// Recording id of the best match and its score if it is above MIN_ACOUSTID_SCORE
+    async fn lookup_fingerprint(&self, fingerprint: &str, duration_ms: u64) -> Result<Option<(String, f64)>, EnrichmentError> {
+        // NOTE: Fingerprints are a few kilobytes so they are posted instead of being put in the url
+        let duration_seconds = (duration_ms / 1000).to_string();
+        let response = self.http_client.post(ACOUSTID_LOOKUP_URL)
+            .form(&[
+                ("client", self.acoustid_api_key.as_str()),
+                ("meta", "recordingids"),
+                ("duration", duration_seconds.as_str()),
+                ("fingerprint", fingerprint),
+            ])
+            .send().await?
+            .text().await?;
+        let response: AcoustIdResponse = serde_json::from_str(response.as_str())?;
+        if response.status != "ok" {
+            let error = response.error.unwrap_or(AcoustIdError { code: 0, message: response.status });
+            return Err(EnrichmentError::AcoustId { code: error.code, message: error.message });
+        }
+        let best_match = response.results.into_iter()
+            .filter(|result| result.score >= MIN_ACOUSTID_SCORE)
+            .filter_map(|result| Some((result.recordings.first()?.id.clone(), result.score)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        Ok(best_match)
+    }
+
+    async fn lookup_recording(&self, recording_id: &str) -> Result<Recording, EnrichmentError> {
+        // NOTE: Requests reserve the next free slot so concurrent transcodes queue up instead of sending at once
+        let send_at = {
+            let mut next_request = self.next_musicbrainz_request.lock().unwrap();
+            let send_at = (*next_request).max(Instant::now());
+            *next_request = send_at + MUSICBRAINZ_REQUEST_INTERVAL;
+            send_at
+        };
+        tokio::time::sleep_until(send_at.into()).await;
+        let recording = self.http_client.get(format!("{MUSICBRAINZ_RECORDING_URL}/{recording_id}"))
+            .query(&[("inc", "artist-credits+releases+media"), ("fmt", "json")])
+            .send().await?
+            .error_for_status()?
+            .text().await?;
+        Ok(serde_json::from_str(recording.as_str())?)
+    }
+
+    /// Tags of the recording, which are empty if the fingerprint didn't match one
+    pub async fn lookup(&self, video_id: &VideoId, fingerprint: &str, duration_ms: u64) -> Result<EnrichmentRow, EnrichmentError> {
+        let mut entry = EnrichmentRow {
+            video_id: video_id.clone(),
+            recording_id: None,
+            score: None,
+            title: None,
+            artist: None,
+            album: None,
+            album_artist: None,
+            track_number: None,
+            release_date: None,
+            unix_time: get_unix_time(),
+        };
+        let Some((recording_id, score)) = self.lookup_fingerprint(fingerprint, duration_ms).await? else {
+            return Ok(entry);
+        };
+        let recording = self.lookup_recording(recording_id.as_str()).await?;
+        let release = find_original_release(recording.releases.as_slice());
+        entry.recording_id = Some(recording_id);
+        entry.score = Some(score);
+        entry.title = Some(recording.title.clone());
+        entry.artist = join_artist_credit(recording.artist_credit.as_slice());
+        entry.album = release.map(|release| release.title.clone());
+        entry.album_artist = release.and_then(|release| join_artist_credit(release.artist_credit.as_slice()));
+        entry.track_number = release.and_then(|release| release.get_track_number());
+        entry.release_date = release.and_then(|release| release.date.clone()).filter(|date| !date.is_empty());
+        Ok(entry)
+    }
+}
+
+/// Looks up the tags once per video and stores them, including videos without a match so they aren't fingerprinted again
+pub async fn get_enrichment_from_cache(
+    enricher: &Enricher, job_store: &SharedJobStore, transcoder: &SharedTranscoder,
+    video_id: &VideoId, source_path: &Path, duration_ms: Option<u64>,
+) -> Result<EnrichmentRow, EnrichmentError> {
+    if let Some(entry) = job_store.select_enrichment_entry(video_id)? {
+        return Ok(entry);
+    }
+    let duration_ms = duration_ms.ok_or(EnrichmentError::MissingDuration)?;
+    let fingerprint = block_in_place(|| transcoder.fingerprint(source_path))?;
+    let entry = enricher.lookup(video_id, fingerprint.as_str(), duration_ms).await?;
+    job_store.upsert_enrichment_entry(&entry)?;
+    Ok(entry)
+}
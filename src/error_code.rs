@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use crate::generate_bidirectional_binding;
+
+/// Machine readable reason for a failed request or job so clients can show an actionable message
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    QuotaExceeded,
+    QueueFull,
+    InternalError,
+    VideoUnavailable,
+    PrivateVideo,
+    GeoBlocked,
+    AgeRestricted,
+    LoginRequired,
+    RateLimited,
+    NetworkError,
+    DiskFull,
+    InvalidSource,
+    UnsupportedCodec,
+    PermissionDenied,
+    StorageFailed,
+    LiveStream,
+    UpcomingStream,
+    YtdlpFailed,
+    FfmpegFailed,
+    Interrupted,
+}
+
+generate_bidirectional_binding!(
+    ErrorCode, &'static str, &str,
+    (InvalidRequest, "invalid_request"),
+    (Unauthorized, "unauthorized"),
+    (Forbidden, "forbidden"),
+    (NotFound, "not_found"),
+    (Conflict, "conflict"),
+    (QuotaExceeded, "quota_exceeded"),
+    (QueueFull, "queue_full"),
+    (InternalError, "internal_error"),
+    (VideoUnavailable, "video_unavailable"),
+    (PrivateVideo, "private_video"),
+    (GeoBlocked, "geo_blocked"),
+    (AgeRestricted, "age_restricted"),
+    (LoginRequired, "login_required"),
+    (RateLimited, "rate_limited"),
+    (NetworkError, "network_error"),
+    (DiskFull, "disk_full"),
+    (InvalidSource, "invalid_source"),
+    (UnsupportedCodec, "unsupported_codec"),
+    (PermissionDenied, "permission_denied"),
+    (StorageFailed, "storage_failed"),
+    (LiveStream, "live_stream"),
+    (UpcomingStream, "upcoming_stream"),
+    (YtdlpFailed, "ytdlp_failed"),
+    (FfmpegFailed, "ffmpeg_failed"),
+    (Interrupted, "interrupted"),
+);
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        (*self).into()
+    }
+
+    /// Errors writing to our own files are reported separately when the disk is full
+    pub fn from_io_error(err: &std::io::Error, default: Self) -> Self {
+        if err.kind() == std::io::ErrorKind::StorageFull {
+            return Self::DiskFull;
+        }
+        default
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
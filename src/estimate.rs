@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+use actix_web::web;
+use serde::Serialize;
+use thiserror::Error;
+use crate::app::AppState;
+use crate::database::{AudioExtension, VideoId, WorkerStatus};
+use crate::ffmpeg::{can_copy_codec, TranscodeOptions};
+use crate::job_store::JobStoreError;
+use crate::metadata::get_metadata_from_cache;
+
+#[derive(Debug,Error)]
+pub enum EstimateError {
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+    #[error("Failed to run blocking task: {0:?}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+}
+
+/// Where the duration of the source was taken from, from most to least accurate
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationSource {
+    Ffprobe,
+    Download,
+    Metadata,
+}
+
+/// Predicted output of a transcode which doesn't enqueue any work
+#[derive(Clone,Debug,Serialize)]
+pub struct TranscodeEstimate {
+    pub variant: String,
+    pub bitrate_kbps: u32,
+    /// Bitrate of the downloaded source if it was probed, a higher output bitrate won't improve quality
+    pub source_bitrate_kbps: Option<u64>,
+    pub duration_source: Option<DurationSource>,
+    pub duration_ms: Option<u64>,
+    /// Size of the encoded audio which excludes container overhead and embedded artwork
+    pub size_bytes: Option<u64>,
+    /// Removing silence can only shorten the output so the estimate is an upper bound
+    pub is_upper_bound: bool,
+}
+
+/// Duration, bitrate and codec of the source that the estimate is based on
+#[derive(Clone,Debug,Default)]
+struct SourceInfo {
+    duration: Option<(u64, DurationSource)>,
+    bitrate_kbps: Option<u64>,
+    codec: Option<String>,
+}
+
+/// Finds the duration and bitrate of the source from the download if it finished or from the video's metadata
+// NOTE: The download is probed instead of trusting its recorded duration since that can be missing for older entries
+async fn get_source_info(app: &AppState, video_id: &VideoId) -> Result<SourceInfo, EstimateError> {
+    let mut info = SourceInfo::default();
+    let download = app.job_store.select_ytdlp_entry(video_id)?;
+    let download = download.filter(|entry| entry.status == WorkerStatus::Finished);
+    if let Some(audio_path) = download.as_ref().and_then(|entry| entry.audio_path.clone()) {
+        let transcoder = app.app_config.get_transcoder();
+        let probe = web::block(move || transcoder.probe_format(&PathBuf::from(audio_path))).await?;
+        match probe {
+            Ok(probe) => {
+                info.bitrate_kbps = probe.bitrate.map(|bitrate| bitrate / 1000);
+                info.codec = probe.codec;
+                if let Some(duration_ms) = probe.duration_ms {
+                    info.duration = Some((duration_ms, DurationSource::Ffprobe));
+                    return Ok(info);
+                }
+            },
+            Err(err) => log::warn!("Failed to probe download for estimate: id={0}, err={1:?}", video_id.as_str(), err),
+        }
+    }
+    if let Some(duration_ms) = download.and_then(|entry| entry.duration_ms) {
+        info.duration = Some((duration_ms, DurationSource::Download));
+        return Ok(info);
+    }
+    let duration_ms = match app.job_store.select_metadata_entry(video_id)?.and_then(|metadata| metadata.duration_ms) {
+        Some(duration_ms) => Some(duration_ms),
+        None => get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await
+            .ok()
+            .and_then(|metadata| metadata.items.first().and_then(|item| item.get_duration_milliseconds())),
+    };
+    info.duration = duration_ms.map(|duration_ms| (duration_ms, DurationSource::Metadata));
+    Ok(info)
+}
+
+pub async fn estimate_transcode(
+    app: &AppState, video_id: &VideoId, audio_ext: AudioExtension, options: &TranscodeOptions,
+) -> Result<TranscodeEstimate, EstimateError> {
+    let source = get_source_info(app, video_id).await?;
+    // NOTE: Passthrough keeps the bitrate of the source if its codec can be copied into the format
+    let is_stream_copy = options.passthrough && source.codec.as_deref().is_some_and(|codec| can_copy_codec(codec, audio_ext));
+    let bitrate_kbps = match source.bitrate_kbps {
+        Some(source_bitrate_kbps) if is_stream_copy => source_bitrate_kbps as u32,
+        _ => {
+            let preset = options.preset.as_ref().and_then(|name| app.app_config.transcode_presets.get(name));
+            options.get_output_bitrate_kbps(audio_ext, preset)
+        },
+    };
+    let duration_ms = source.duration.map(|(duration_ms, _)| options.get_output_duration_ms(duration_ms));
+    Ok(TranscodeEstimate {
+        variant: options.get_variant(),
+        bitrate_kbps,
+        source_bitrate_kbps: source.bitrate_kbps,
+        duration_source: source.duration.map(|(_, source)| source),
+        duration_ms,
+        size_bytes: duration_ms.map(|duration_ms| duration_ms * bitrate_kbps as u64 / 8),
+        is_upper_bound: options.trim_silence,
+    })
+}
@@ -0,0 +1,126 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const FAKE_AUDIO_MAGIC: &str = "FAKE_AUDIO";
+const DEFAULT_DURATION_SECONDS: u64 = 180;
+const DEFAULT_STEP_DELAY_MILLISECONDS: u64 = 50;
+pub const FAKE_BITRATE: u64 = 128_000;
+/// Size of each chunk a fake tool writes before it prints its progress
+pub const FAKE_CHUNK_BYTES: usize = 16*1024;
+pub const TOTAL_FAKE_CHUNKS: usize = 16;
+
+/// Settings shared by the fake binaries which are read from the environment of the server that runs them
+/// - FAKE_TOOLS_DURATION_SECONDS: Duration of every fake video
+/// - FAKE_TOOLS_STEP_DELAY_MS: Sleep between progress lines so the progress can be polled
+/// - FAKE_YTDLP_FAIL_IDS: Comma separated videos that yt-dlp reports as unavailable
+#[derive(Clone,Debug)]
+pub struct FakeToolsConfig {
+    pub duration_ms: u64,
+    pub step_delay: Duration,
+    pub fail_video_ids: Vec<String>,
+}
+
+impl FakeToolsConfig {
+    pub fn from_env() -> Self {
+        let get_number = |name: &str, default: u64| -> u64 {
+            std::env::var(name).ok().and_then(|value| value.trim().parse().ok()).unwrap_or(default)
+        };
+        let fail_video_ids = std::env::var("FAKE_YTDLP_FAIL_IDS").unwrap_or_default()
+            .split(',')
+            .map(|id| id.trim().to_owned())
+            .filter(|id| !id.is_empty())
+            .collect();
+        Self {
+            duration_ms: get_number("FAKE_TOOLS_DURATION_SECONDS", DEFAULT_DURATION_SECONDS)*1000,
+            step_delay: Duration::from_millis(get_number("FAKE_TOOLS_STEP_DELAY_MS", DEFAULT_STEP_DELAY_MILLISECONDS)),
+            fail_video_ids,
+        }
+    }
+}
+
+/// Audio written by the fake tools whose first line describes it so ffprobe and ffmpeg can read it back
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct FakeAudio {
+    pub codec: String,
+    pub duration_ms: u64,
+    pub has_artwork: bool,
+}
+
+impl FakeAudio {
+    fn get_header(&self) -> String {
+        format!(
+            "{FAKE_AUDIO_MAGIC} codec={0} duration_ms={1} artwork={2}\n",
+            self.codec, self.duration_ms, self.has_artwork,
+        )
+    }
+
+    fn parse_header(line: &str) -> Option<Self> {
+        let mut fields = line.trim().split(' ');
+        if fields.next()? != FAKE_AUDIO_MAGIC {
+            return None;
+        }
+        let (mut codec, mut duration_ms, mut has_artwork) = (None, None, false);
+        for field in fields {
+            match field.split_once('=')? {
+                ("codec", value) => codec = Some(value.to_owned()),
+                ("duration_ms", value) => duration_ms = value.parse().ok(),
+                ("artwork", value) => has_artwork = value == "true",
+                _ => {},
+            }
+        }
+        Some(Self { codec: codec?, duration_ms: duration_ms?, has_artwork })
+    }
+
+    /// Header followed by filler up to the total size
+    pub fn get_bytes(&self, total_bytes: usize) -> Vec<u8> {
+        let mut bytes = self.get_header().into_bytes();
+        bytes.resize(total_bytes.max(bytes.len()), 0);
+        bytes
+    }
+
+    pub fn read(reader: impl Read) -> Option<Self> {
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).ok()?;
+        Self::parse_header(line.as_str())
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        Self::read(std::fs::File::open(path).ok()?)
+    }
+}
+
+/// Value following the first occurrence of the flag
+pub fn get_arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index+1)).map(|value| value.as_str())
+}
+
+/// Values following every occurrence of the flag
+pub fn get_arg_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+    args.windows(2).filter(|pair| pair[0] == flag).map(|pair| pair[1].as_str()).collect()
+}
+
+/// Name ffprobe reports for the codec that ffmpeg picks by default for the extension
+pub fn get_codec_from_extension(extension: &str) -> &'static str {
+    match extension {
+        "mp3" => "mp3",
+        "m4a" | "aac" | "mp4" => "aac",
+        "flac" => "flac",
+        "wav" => "pcm_s16le",
+        "ogg" | "opus" | "webm" => "opus",
+        _ => "aac",
+    }
+}
+
+/// Formats the time as HH:MM:SS.cc the same way ffmpeg does
+pub fn format_time(milliseconds: u64) -> String {
+    let centiseconds = (milliseconds / 10) % 100;
+    let seconds = milliseconds / 1000;
+    format!("{0:02}:{1:02}:{2:02}.{centiseconds:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+/// Prints the line and flushes it immediately since the server reads the output while we are running
+pub fn print_line(writer: &mut impl Write, line: &str) {
+    let _ = writeln!(writer, "{line}");
+    let _ = writer.flush();
+}
@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+use lazy_static::lazy_static;
+use regex::Regex;
+use crate::database::VideoId;
+
+// NOTE: YouTube only shows chapters if the description has at least this many timestamps starting from 0:00
+const MIN_CHAPTERS: usize = 3;
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Chapter {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub title: String,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct LyricLine {
+    pub start_ms: u64,
+    pub text: String,
+}
+
+/// Global metadata and chapters which are passed to ffmpeg as an extra input
+#[derive(Clone,Debug,Default)]
+pub struct FfMetadata {
+    pub chapters: Vec<Chapter>,
+    pub lyrics: Option<String>,
+}
+
+fn parse_timestamp_ms(hours: Option<&str>, minutes: &str, seconds: &str, milliseconds: Option<&str>) -> Option<u64> {
+    let hours: u64 = hours.map(|hours| hours.parse()).transpose().ok()?.unwrap_or(0);
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    let milliseconds: u64 = milliseconds.map(|ms| ms.parse()).transpose().ok()?.unwrap_or(0);
+    Some(((hours*60 + minutes)*60 + seconds)*1000 + milliseconds)
+}
+
+/// Finds chapters using the same rules as YouTube where each line starts with a timestamp
+pub fn parse_description_chapters(description: &str, duration_ms: u64) -> Vec<Chapter> {
+    lazy_static! {
+        static ref CHAPTER_REGEX: Regex = Regex::new(
+            r"^\s*[-*•]?\s*[\[(]?(?:(\d{1,2}):)?(\d{1,2}):(\d{2})[\])]?\s*[-–—:|]?\s*(.+?)\s*$"
+        ).unwrap();
+    }
+    let mut starts = Vec::<(u64, String)>::new();
+    for line in description.lines() {
+        let Some(captures) = CHAPTER_REGEX.captures(line) else {
+            continue;
+        };
+        let start_ms = parse_timestamp_ms(
+            captures.get(1).map(|m| m.as_str()), &captures[2], &captures[3], None,
+        );
+        let Some(start_ms) = start_ms else {
+            continue;
+        };
+        // NOTE: Timestamps must be in order otherwise they are probably references in the text
+        if start_ms >= duration_ms || starts.last().is_some_and(|&(last_ms, _)| start_ms <= last_ms) {
+            return Vec::new();
+        }
+        starts.push((start_ms, captures[4].to_owned()));
+    }
+    if starts.len() < MIN_CHAPTERS || starts[0].0 != 0 {
+        return Vec::new();
+    }
+    let ends: Vec<u64> = starts.iter().skip(1).map(|&(start_ms, _)| start_ms).chain([duration_ms]).collect();
+    starts.into_iter().zip(ends).map(|((start_ms, title), end_ms)| Chapter { start_ms, end_ms, title }).collect()
+}
+
+/// Reads the cues from a WebVTT subtitle file
+// NOTE: Automatic captions repeat the previous line in each cue so we drop consecutive duplicates
+pub fn parse_vtt(data: &str) -> Vec<LyricLine> {
+    lazy_static! {
+        static ref CUE_TIMING_REGEX: Regex = Regex::new(
+            r"^(?:(\d+):)?(\d{2}):(\d{2})\.(\d{3})\s+-->"
+        ).unwrap();
+        static ref TAG_REGEX: Regex = Regex::new(r"<[^>]*>").unwrap();
+    }
+    let mut lines = Vec::<LyricLine>::new();
+    let mut cue: Option<(u64, Vec<String>)> = None;
+    let mut push_cue = |cue: Option<(u64, Vec<String>)>| {
+        let Some((start_ms, text)) = cue else {
+            return;
+        };
+        let text = text.join(" ");
+        if text.is_empty() || lines.last().is_some_and(|line| line.text == text) {
+            return;
+        }
+        lines.push(LyricLine { start_ms, text });
+    };
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            push_cue(cue.take());
+            continue;
+        }
+        if let Some(captures) = CUE_TIMING_REGEX.captures(line) {
+            push_cue(cue.take());
+            let start_ms = parse_timestamp_ms(
+                captures.get(1).map(|m| m.as_str()), &captures[2], &captures[3], Some(&captures[4]),
+            );
+            cue = start_ms.map(|start_ms| (start_ms, Vec::new()));
+            continue;
+        }
+        if let Some((_, ref mut text)) = cue {
+            let line = TAG_REGEX.replace_all(line, "");
+            let line = line.replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&nbsp;", " ");
+            let line = line.trim();
+            if !line.is_empty() {
+                text.push(line.to_owned());
+            }
+        }
+    }
+    push_cue(cue.take());
+    lines
+}
+
+/// Formats lyrics as LRC which players use to show synchronised lyrics from an unsynchronised lyrics tag
+pub fn format_lrc(lines: &[LyricLine]) -> String {
+    lines.iter().map(|line| {
+        let centiseconds = line.start_ms / 10;
+        format!("[{0:02}:{1:02}.{2:02}]{3}", centiseconds / 6000, (centiseconds / 100) % 60, centiseconds % 100, line.text)
+    }).collect::<Vec<String>>().join("\n")
+}
+
+/// yt-dlp writes subtitles next to the download as {video_id}.{lang}.vtt
+pub fn find_subtitle_paths(download_directory: &Path, video_id: &VideoId) -> Vec<PathBuf> {
+    let prefix = format!("{}.", video_id.as_str());
+    let Ok(entries) = std::fs::read_dir(download_directory) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let Some(filename) = path.file_name().and_then(|name| name.to_str()) else {
+                return false;
+            };
+            filename.starts_with(prefix.as_str()) && filename.ends_with(".vtt")
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Special characters in keys and values are escaped with a backslash
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl FfMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.chapters.is_empty() && self.lyrics.is_none()
+    }
+
+    /// Serialises to ffmpeg's metadata format (https://ffmpeg.org/ffmpeg-formats.html#Metadata-2)
+    pub fn to_ffmetadata(&self) -> String {
+        let mut data = String::from(";FFMETADATA1\n");
+        if let Some(ref lyrics) = self.lyrics {
+            data.push_str(format!("lyrics={}\n", escape(lyrics)).as_str());
+        }
+        for chapter in &self.chapters {
+            data.push_str("[CHAPTER]\nTIMEBASE=1/1000\n");
+            data.push_str(format!("START={0}\nEND={1}\n", chapter.start_ms, chapter.end_ms).as_str());
+            data.push_str(format!("title={}\n", escape(chapter.title.as_str())).as_str());
+        }
+        data
+    }
+}
@@ -1,6 +1,436 @@
+use std::collections::HashMap;
+use std::path::Path;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+use crate::database::AudioExtension;
+use crate::error_code::ErrorCode;
+use crate::ytdlp::validate_audio_lang;
+
+const SILENCE_REMOVE_FILTER: &str = "silenceremove=start_periods=1:start_threshold=-50dB:start_silence=0.1";
+
+// NOTE: Floats aren't Eq or Hash so we compare their bits instead
+macro_rules! impl_float_key {
+    ($name:ident) => {
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_bits() == other.0.to_bits()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl std::hash::Hash for $name {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state);
+            }
+        }
+    };
+}
+
+/// Gain in decibels which can be used as part of a hashable key
+#[derive(Clone,Copy,Debug,Default,Deserialize,Serialize)]
+#[serde(transparent)]
+pub struct Decibels(pub f64);
+
+impl_float_key!(Decibels);
+
+/// Playback speed multiplier which can be used as part of a hashable key
+#[derive(Clone,Copy,Debug,Deserialize,Serialize)]
+#[serde(transparent)]
+pub struct Tempo(pub f64);
+
+impl_float_key!(Tempo);
+
+/// Options that change the transcoded output so each combination is cached as a separate variant
+#[derive(Clone,Debug,Default,PartialEq,Eq,Hash,Deserialize,Serialize)]
+pub struct TranscodeOptions {
+    pub bitrate_kbps: Option<u32>,
+    pub trim_start_ms: Option<u64>,
+    pub trim_end_ms: Option<u64>,
+    #[serde(default)]
+    pub normalize: bool,
+    #[serde(default)]
+    pub skip_thumbnail: bool,
+    // NOTE: Skipped when false so variants of options from before this was added keep the same hash
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trim_silence: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fade_in_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fade_out_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain_db: Option<Decibels>,
+    /// Measure the loudness of the output and store it as ReplayGain tags
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replaygain: bool,
+    /// Copy the audio stream without re-encoding when the source codec can be stored in the requested format
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub passthrough: bool,
+    /// Name of the admin defined preset that chooses the encoder settings, see TranscodePreset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Number of output channels, 1 downmixes to mono
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u8>,
+    /// Output sample rate in Hz, see TranscodeOptions::SAMPLE_RATES
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    /// Speeds up or slows down the output without changing its pitch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo: Option<Tempo>,
+    /// Dubbed audio track that is downloaded as the source, see DownloadOptions::audio_lang
+    // NOTE: Kept with the options so each language is cached as a separate variant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_lang: Option<String>,
+    /// Tag the output with the artist, album and track of the recording that AcoustID identifies the audio as
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub enrich_tags: bool,
+}
+
+#[derive(Clone,Debug,Error)]
+pub enum TranscodeOptionsError {
+    #[error("Bitrate must be between {min}kbps and {max}kbps: given={given}")]
+    InvalidBitrate { min: u32, max: u32, given: u32 },
+    #[error("Trim end must be after trim start: start={start}ms, end={end}ms")]
+    InvalidTrim { start: u64, end: u64 },
+    #[error("Fade must be between 1ms and {max}ms: given={given}")]
+    InvalidFade { max: u64, given: u64 },
+    #[error("Fades are longer than the trimmed output: fade={fade}ms, output={output}ms")]
+    FadeTooLong { fade: u64, output: u64 },
+    #[error("Gain must be between -{max}dB and {max}dB: given={given}")]
+    InvalidGain { max: f64, given: f64 },
+    #[error("Passthrough copies the audio so it can't be combined with {0}")]
+    PassthroughConflict(&'static str),
+    #[error("Unknown preset: {0}")]
+    UnknownPreset(String),
+    #[error("Preset {0} already sets the bitrate")]
+    PresetBitrateConflict(String),
+    #[error("Channels must be between 1 and {max}: given={given}")]
+    InvalidChannels { max: u8, given: u8 },
+    #[error("Sample rate must be one of {valid:?}: given={given}")]
+    InvalidSampleRate { valid: &'static [u32], given: u32 },
+    #[error("Tempo must be between {min} and {max}: given={given}")]
+    InvalidTempo { min: f64, max: f64, given: f64 },
+    #[error("Audio language must be a language code such as es or en-US: given={0}")]
+    InvalidAudioLang(String),
+}
+
+impl TranscodeOptions {
+    pub const MIN_BITRATE_KBPS: u32 = 8;
+    pub const MAX_BITRATE_KBPS: u32 = 512;
+    pub const MAX_FADE_MS: u64 = 60_000;
+    pub const MAX_GAIN_DB: f64 = 30.0;
+    pub const MAX_CHANNELS: u8 = 8;
+    pub const MIN_TEMPO: f64 = 0.25;
+    pub const MAX_TEMPO: f64 = 4.0;
+    // NOTE: libopus only supports 8000, 12000, 16000, 24000 and 48000 so ffmpeg rejects the others for webm
+    pub const SAMPLE_RATES: &'static [u32] = &[8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+    const VARIANT_LENGTH: usize = 16;
+
+    pub fn validate(&self, presets: &TranscodePresets) -> Result<(), TranscodeOptionsError> {
+        if let Some(bitrate) = self.bitrate_kbps {
+            if !(Self::MIN_BITRATE_KBPS..=Self::MAX_BITRATE_KBPS).contains(&bitrate) {
+                return Err(TranscodeOptionsError::InvalidBitrate {
+                    min: Self::MIN_BITRATE_KBPS,
+                    max: Self::MAX_BITRATE_KBPS,
+                    given: bitrate,
+                });
+            }
+        }
+        if let (start, Some(end)) = (self.trim_start_ms.unwrap_or(0), self.trim_end_ms) {
+            if end <= start {
+                return Err(TranscodeOptionsError::InvalidTrim { start, end });
+            }
+        }
+        for fade in [self.fade_in_ms, self.fade_out_ms].into_iter().flatten() {
+            if !(1..=Self::MAX_FADE_MS).contains(&fade) {
+                return Err(TranscodeOptionsError::InvalidFade { max: Self::MAX_FADE_MS, given: fade });
+            }
+        }
+        if let Some(Decibels(gain)) = self.gain_db {
+            if !gain.is_finite() || gain.abs() > Self::MAX_GAIN_DB {
+                return Err(TranscodeOptionsError::InvalidGain { max: Self::MAX_GAIN_DB, given: gain });
+            }
+        }
+        if let Some(channels) = self.channels {
+            if !(1..=Self::MAX_CHANNELS).contains(&channels) {
+                return Err(TranscodeOptionsError::InvalidChannels { max: Self::MAX_CHANNELS, given: channels });
+            }
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            if !Self::SAMPLE_RATES.contains(&sample_rate) {
+                return Err(TranscodeOptionsError::InvalidSampleRate { valid: Self::SAMPLE_RATES, given: sample_rate });
+            }
+        }
+        if let Some(Tempo(tempo)) = self.tempo {
+            if !tempo.is_finite() || !(Self::MIN_TEMPO..=Self::MAX_TEMPO).contains(&tempo) {
+                return Err(TranscodeOptionsError::InvalidTempo { min: Self::MIN_TEMPO, max: Self::MAX_TEMPO, given: tempo });
+            }
+        }
+        if let Some(ref lang) = self.audio_lang {
+            validate_audio_lang(lang).map_err(|_| TranscodeOptionsError::InvalidAudioLang(lang.clone()))?;
+        }
+        if self.passthrough {
+            let conflicts = [
+                (self.bitrate_kbps.is_some(), "bitrate_kbps"),
+                (self.normalize, "normalize"),
+                (self.trim_silence, "trim_silence"),
+                (self.fade_in_ms.is_some() || self.fade_out_ms.is_some(), "fades"),
+                (self.gain_db.is_some(), "gain_db"),
+                (self.preset.is_some(), "preset"),
+                (self.channels.is_some(), "channels"),
+                (self.sample_rate.is_some(), "sample_rate"),
+                (self.tempo.is_some(), "tempo"),
+            ];
+            if let Some((_, option)) = conflicts.into_iter().find(|(is_set, _)| *is_set) {
+                return Err(TranscodeOptionsError::PassthroughConflict(option));
+            }
+        }
+        if let Some(ref name) = self.preset {
+            let preset = presets.get(name).ok_or_else(|| TranscodeOptionsError::UnknownPreset(name.clone()))?;
+            if preset.bitrate_mode.is_some() && self.bitrate_kbps.is_some() {
+                return Err(TranscodeOptionsError::PresetBitrateConflict(name.clone()));
+            }
+        }
+        // NOTE: We can only check against the output length upfront if both ends are trimmed
+        if let Some(end) = self.trim_end_ms {
+            let output = end.saturating_sub(self.trim_start_ms.unwrap_or(0));
+            let fade = self.fade_in_ms.unwrap_or(0) + self.fade_out_ms.unwrap_or(0);
+            if fade > output {
+                return Err(TranscodeOptionsError::FadeTooLong { fade, output });
+            }
+        }
+        Ok(())
+    }
+
+    /// Stable identifier of these options used in cache keys and filenames
+    // NOTE: Default options have an empty variant so transcodes from before variants existed remain valid
+    pub fn get_variant(&self) -> String {
+        if *self == Self::default() {
+            return String::new();
+        }
+        let options = serde_json::to_string(self).expect("Options should be serialisable");
+        let digest = format!("{:x}", Sha256::digest(options.as_bytes()));
+        digest[..Self::VARIANT_LENGTH].to_owned()
+    }
+
+    pub fn is_valid_variant(variant: &str) -> bool {
+        variant.is_empty() || (variant.len() == Self::VARIANT_LENGTH && variant.chars().all(|c| c.is_ascii_hexdigit()))
+    }
+
+    /// Output arguments which must be placed before the output path
+    /// The source duration is needed to place the fade out unless the end is trimmed
+    pub fn get_ffmpeg_arguments(&self, source_duration_ms: Option<u64>) -> Vec<String> {
+        let mut args = Vec::<String>::new();
+        let to_seconds = |milliseconds: u64| format!("{0:.3}", milliseconds as f64 / 1000.0);
+        // NOTE: Output trimming happens after the tempo is changed so we scale the trim to the output timeline
+        let to_output_seconds = |milliseconds: u64| format!("{0:.3}", milliseconds as f64 / 1000.0 / self.get_tempo());
+        if let Some(start) = self.trim_start_ms {
+            args.extend(["-ss".to_owned(), to_output_seconds(start)]);
+        }
+        if let Some(end) = self.trim_end_ms {
+            args.extend(["-to".to_owned(), to_output_seconds(end)]);
+        }
+        if let Some(bitrate) = self.bitrate_kbps {
+            args.extend(["-b:a".to_owned(), format!("{bitrate}k")]);
+        }
+        if let Some(channels) = self.channels {
+            args.extend(["-ac".to_owned(), channels.to_string()]);
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            args.extend(["-ar".to_owned(), sample_rate.to_string()]);
+        }
+        let mut filters = Vec::<String>::new();
+        let fade_out = self.fade_out_ms.map(|duration| format!("d={}", to_seconds(duration)));
+        if self.trim_silence {
+            // NOTE: silenceremove only trims the start so we reverse the audio to trim the end
+            //       This buffers the whole output in memory which is fine for music length audio
+            filters.extend([SILENCE_REMOVE_FILTER, "areverse", SILENCE_REMOVE_FILTER].map(str::to_owned));
+            // NOTE: The end isn't known after removing silence so we fade in the reversed audio instead
+            if let Some(ref fade_out) = fade_out {
+                filters.push(format!("afade=t=in:{fade_out}"));
+            }
+            filters.push("areverse".to_owned());
+        }
+        // NOTE: Output trimming happens after filtering so fades are anchored to the source timeline
+        if let Some(fade_in) = self.fade_in_ms {
+            let start = self.trim_start_ms.unwrap_or(0);
+            filters.push(format!("afade=t=in:st={0}:d={1}", to_seconds(start), to_seconds(fade_in)));
+        }
+        if let (false, Some(fade_out_ms), Some(end)) = (self.trim_silence, self.fade_out_ms, self.get_output_end_ms(source_duration_ms)) {
+            let start = end.saturating_sub(fade_out_ms);
+            filters.push(format!("afade=t=out:st={0}:d={1}", to_seconds(start), to_seconds(fade_out_ms)));
+        }
+        if let Some(filter) = self.get_volume_filter() {
+            filters.push(filter);
+        }
+        // NOTE: Tempo is changed after fading so the fades stay anchored to the source timeline
+        filters.extend(self.get_tempo_filters());
+        if self.normalize {
+            filters.push("loudnorm".to_owned());
+        }
+        if !filters.is_empty() {
+            args.extend(["-af".to_owned(), filters.join(",")]);
+        }
+        args
+    }
+
+    pub fn get_volume_filter(&self) -> Option<String> {
+        self.gain_db.map(|Decibels(gain)| format!("volume={gain:.2}dB"))
+    }
+
+    pub fn get_tempo(&self) -> f64 {
+        self.tempo.map(|Tempo(tempo)| tempo).unwrap_or(1.0)
+    }
+
+    /// atempo only accepts factors between 0.5 and 2.0 so larger changes are chained
+    pub fn get_tempo_filters(&self) -> Vec<String> {
+        let Some(Tempo(mut tempo)) = self.tempo else {
+            return Vec::new();
+        };
+        let mut filters = Vec::<String>::new();
+        while tempo > 2.0 {
+            filters.push("atempo=2.0".to_owned());
+            tempo /= 2.0;
+        }
+        while tempo < 0.5 {
+            filters.push("atempo=0.5".to_owned());
+            tempo /= 0.5;
+        }
+        filters.push(format!("atempo={tempo:.6}"));
+        filters
+    }
+
+    /// Position in the source where the output ends if it can be determined
+    pub fn get_output_end_ms(&self, source_duration_ms: Option<u64>) -> Option<u64> {
+        match (self.trim_end_ms, source_duration_ms) {
+            (Some(end), Some(source)) => Some(end.min(source)),
+            (end, source) => end.or(source),
+        }
+    }
+
+    /// Bitrate the output is encoded at
+    // NOTE: ffmpeg's encoders default to 128kbps except for libopus which uses 96kbps for stereo and 64kbps for mono
+    //       The bitrate of variable bitrate presets depends on the audio so we also fall back to the default
+    pub fn get_output_bitrate_kbps(&self, audio_ext: AudioExtension, preset: Option<&TranscodePreset>) -> u32 {
+        if let Some(BitrateMode::Constant(bitrate)) = preset.and_then(|preset| preset.bitrate_mode) {
+            return bitrate;
+        }
+        self.bitrate_kbps.unwrap_or(match audio_ext {
+            AudioExtension::WEBM if self.channels == Some(1) => 64,
+            AudioExtension::WEBM => 96,
+            AudioExtension::M4A | AudioExtension::AAC | AudioExtension::MP3 => 128,
+        })
+    }
+
+    /// Duration of the output after trimming the source and changing its tempo
+    pub fn get_output_duration_ms(&self, source_duration_ms: u64) -> u64 {
+        let end = self.trim_end_ms.unwrap_or(source_duration_ms).min(source_duration_ms);
+        let duration_ms = end.saturating_sub(self.trim_start_ms.unwrap_or(0));
+        (duration_ms as f64 / self.get_tempo()).round() as u64
+    }
+}
+
+/// How a preset chooses the bitrate of the encoder
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Deserialize,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitrateMode {
+    /// Bitrate in kbps passed to -b:a
+    Constant(u32),
+    /// Encoder specific quality passed to -q:a (e.g. 0 for V0 with libmp3lame)
+    Variable(u32),
+}
+
+/// Encoder settings that admins define by name so requests can choose them with ?preset=
+#[derive(Clone,Debug,Default,PartialEq,Eq,Deserialize,Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TranscodePreset {
+    /// Audio encoder passed to -c:a (e.g. libopus), uses ffmpeg's default for the format if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate_mode: Option<BitrateMode>,
+    /// Output arguments placed after the encoder settings (e.g. ["-ac", "1"])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_args: Vec<String>,
+    /// Input arguments placed before the source (e.g. ["-hwaccel", "auto"])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hwaccel_args: Vec<String>,
+}
+
+/// Presets keyed by the name used in ?preset=
+pub type TranscodePresets = HashMap<String, TranscodePreset>;
+
+#[derive(Debug,Error)]
+pub enum TranscodePresetError {
+    #[error("Failed to read presets file: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("Failed to parse presets file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Preset name can only contain lowercase letters, digits, '-' and '_': {0}")]
+    InvalidName(String),
+    #[error("Preset {name} bitrate must be between {min}kbps and {max}kbps: given={given}")]
+    InvalidBitrate { name: String, min: u32, max: u32, given: u32 },
+    #[error("Preset {0} has an empty codec")]
+    EmptyCodec(String),
+}
+
+impl TranscodePreset {
+    /// Output arguments which must be placed before the output path
+    pub fn get_ffmpeg_arguments(&self) -> Vec<String> {
+        let mut args = Vec::<String>::new();
+        if let Some(ref codec) = self.codec {
+            args.extend(["-c:a".to_owned(), codec.clone()]);
+        }
+        match self.bitrate_mode {
+            Some(BitrateMode::Constant(bitrate)) => args.extend(["-b:a".to_owned(), format!("{bitrate}k")]),
+            Some(BitrateMode::Variable(quality)) => args.extend(["-q:a".to_owned(), quality.to_string()]),
+            None => {},
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
+
+/// Reads presets from a json object of names to presets
+pub fn load_transcode_presets(path: &Path) -> Result<TranscodePresets, TranscodePresetError> {
+    let data = std::fs::read_to_string(path)?;
+    let presets: TranscodePresets = serde_json::from_str(data.as_str())?;
+    for (name, preset) in presets.iter() {
+        let is_valid_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !is_valid_name {
+            return Err(TranscodePresetError::InvalidName(name.clone()));
+        }
+        if preset.codec.as_ref().is_some_and(|codec| codec.trim().is_empty()) {
+            return Err(TranscodePresetError::EmptyCodec(name.clone()));
+        }
+        if let Some(BitrateMode::Constant(bitrate)) = preset.bitrate_mode {
+            let (min, max) = (TranscodeOptions::MIN_BITRATE_KBPS, TranscodeOptions::MAX_BITRATE_KBPS);
+            if !(min..=max).contains(&bitrate) {
+                return Err(TranscodePresetError::InvalidBitrate { name: name.clone(), min, max, given: bitrate });
+            }
+        }
+    }
+    Ok(presets)
+}
+
+/// Whether the codec reported by ffprobe can be stored in the format without re-encoding
+pub fn can_copy_codec(codec: &str, audio_ext: AudioExtension) -> bool {
+    match audio_ext {
+        AudioExtension::WEBM => matches!(codec, "opus" | "vorbis"),
+        AudioExtension::M4A | AudioExtension::AAC => codec == "aac",
+        AudioExtension::MP3 => codec == "mp3",
+    }
+}
+
+/// Center crops the thumbnail to a square and shrinks it to fit within the max size
+// NOTE: YouTube thumbnails are 16:9 but most players expect square album art
+pub fn get_thumbnail_filter(max_size: u32) -> String {
+    format!("crop='min(iw,ih)':'min(iw,ih)',scale='min({max_size},iw)':'min({max_size},ih)'")
+}
 
 #[derive(Clone,Copy,Debug)]
 enum SizeBytes {
@@ -95,13 +525,24 @@ pub struct Time {
 
 impl Time {
     pub fn to_milliseconds(&self) -> u64 {
-        let mut v: u64 = 0;
-        v += (self.seconds*1000.0) as u64;
-        v += self.minutes as u64 * 1000*60;
-        v += self.hours   as u64 * 1000*60*60;
-        v += self.days    as u64 * 1000*60*60*24;
+        // NOTE: Seconds are stored as a float so 05.43 would otherwise be truncated to 5429ms
+        //       and a garbled value can be large enough to overflow once the other units are added
+        let mut v: u64 = (self.seconds*1000.0).round() as u64;
+        v = v.saturating_add(self.minutes as u64 * 1000*60);
+        v = v.saturating_add(self.hours   as u64 * 1000*60*60);
+        v = v.saturating_add(self.days    as u64 * 1000*60*60*24);
         v
     }
+
+    pub fn from_microseconds(v: u64) -> Self {
+        let total_seconds = v / 1_000_000;
+        Self {
+            days: (total_seconds / (60*60*24)).min(u8::MAX as u64) as u8,
+            hours: ((total_seconds / (60*60)) % 24) as u8,
+            minutes: ((total_seconds / 60) % 60) as u8,
+            seconds: (total_seconds % 60) as f32 + (v % 1_000_000) as f32 / 1_000_000.0,
+        }
+    }
 }
 
 #[derive(Clone,Debug,Error)]
@@ -119,6 +560,8 @@ pub enum TimeParseError {
 impl Time {
     pub fn try_from_str(v: &str) -> Result<Self, TimeParseError> {
         type E = TimeParseError;
+        // NOTE: Builds using a locale with a decimal comma print the seconds as 05,43
+        let v = v.replace(',', ".");
         let mut parts: Vec<&str> = v.split(':'). collect();
         parts.reverse();
         let mut time = Time::default();
@@ -130,11 +573,29 @@ impl Time {
     }
 }
 
-const FLOAT32_REGEX: &str = r"\d+(?:\.\d+)?";
-const BYTES_REGEX: &str = r"[kKMG]i?B";
+// NOTE: Values can be negative before the first packet is written (e.g. time=-577014:32:22.77 and bitrate=-0.0kbits/s)
+//       and some locales print a decimal comma, while very fast speeds are printed in scientific notation (e.g. speed=2.47e+03x)
+const FLOAT32_REGEX: &str = r"-?\d+(?:[.,]\d+)?(?:e[+-]?\d+)?";
+const BYTES_REGEX: &str = r"(?:[kKMG]i?)?B";
 const BITS_LONG_REGEX: &str = r"[kMG]?bits";
 const BITS_SHORT_REGEX: &str = r"[kMG]?b";
-const TIME_REGEX: &str = r"(?:\d+:)*\d+(?:\.\d+)?";
+const TIME_REGEX: &str = r"-?(?:\d+:)*\d+(?:[.,]\d+)?";
+// NOTE: Printed instead of a value when it isn't known such as the size when writing to a null output
+const NOT_AVAILABLE_REGEX: &str = r"N/A";
+
+/// Negative values are only printed before ffmpeg knows the actual value so they are treated as missing
+fn parse_positive_float(v: &str) -> Option<f32> {
+    let value: f32 = v.replace(',', ".").parse().ok()?;
+    // NOTE: -0.0 compares equal to 0.0 so the sign has to be checked directly
+    (!value.is_sign_negative()).then_some(value)
+}
+
+fn parse_positive_time(v: &str) -> Option<Time> {
+    if v.starts_with('-') {
+        return None;
+    }
+    Time::try_from_str(v).ok()
+}
 
 #[derive(Clone,Copy,Debug,Default)]
 pub struct TranscodeProgress {
@@ -158,24 +619,89 @@ pub struct TranscodeSourceInfo {
 pub enum ParsedStderrLine {
     TranscodeProgress(TranscodeProgress),
     TranscodeSourceInfo(TranscodeSourceInfo),
+    Failure(FfmpegError),
+}
+
+/// Fatal error printed by ffmpeg along with the line it was parsed from
+#[derive(Clone,Debug,Error)]
+pub enum FfmpegError {
+    #[error("Source contains invalid data: {0}")]
+    InvalidData(String),
+    #[error("Unsupported codec: {0}")]
+    UnsupportedCodec(String),
+    #[error("Disk is full: {0}")]
+    DiskFull(String),
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("Failed to fetch thumbnail: {0}")]
+    ThumbnailFetch(String),
+}
+
+impl FfmpegError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidData(_) => ErrorCode::InvalidSource,
+            Self::UnsupportedCodec(_) => ErrorCode::UnsupportedCodec,
+            Self::DiskFull(_) => ErrorCode::DiskFull,
+            Self::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            Self::ThumbnailFetch(_) => ErrorCode::NetworkError,
+        }
+    }
+}
+
+/// Classifies the error messages printed by ffmpeg so clients know why a transcode failed
+fn parse_error_line(line: &str) -> Option<FfmpegError> {
+    lazy_static! {
+        // NOTE: The audio is always read from a local file or pipe so network errors come from the thumbnail url
+        static ref THUMBNAIL_FETCH_REGEX: Regex = Regex::new(
+            r"(?i)^\[(?:tcp|tls|http|https) @ [^\]]+\]|^https?://\S+:|failed to resolve hostname"
+        ).unwrap();
+        static ref INVALID_DATA_REGEX: Regex = Regex::new(
+            r"(?i)invalid data found when processing input|moov atom not found|could not find codec parameters"
+        ).unwrap();
+        static ref UNSUPPORTED_CODEC_REGEX: Regex = Regex::new(
+            r"(?i)unknown encoder|encoder not found|specified sample rate \d+ is not supported|decoder \(codec \w+\) not found|could not find tag for codec|not currently supported in container|unsupported codec"
+        ).unwrap();
+        static ref DISK_FULL_REGEX: Regex = Regex::new(r"(?i)no space left on device").unwrap();
+        static ref PERMISSION_DENIED_REGEX: Regex = Regex::new(r"(?i)permission denied").unwrap();
+    }
+    let message = line.to_owned();
+    if THUMBNAIL_FETCH_REGEX.is_match(line) {
+        Some(FfmpegError::ThumbnailFetch(message))
+    } else if INVALID_DATA_REGEX.is_match(line) {
+        Some(FfmpegError::InvalidData(message))
+    } else if UNSUPPORTED_CODEC_REGEX.is_match(line) {
+        Some(FfmpegError::UnsupportedCodec(message))
+    } else if DISK_FULL_REGEX.is_match(line) {
+        Some(FfmpegError::DiskFull(message))
+    } else if PERMISSION_DENIED_REGEX.is_match(line) {
+        Some(FfmpegError::PermissionDenied(message))
+    } else {
+        None
+    }
 }
 
 pub fn parse_stderr_line(line: &str) -> Option<ParsedStderrLine> {
     lazy_static! {
         static ref PROGRESS_REGEX: Regex = Regex::new(format!(
-            r"(?:frame\s*=\s*(\d+)\s+fps\s*=\s*({2})\s+q\s*=\s*({2})\s+)?size\s*=\s*(\d+)({0})\s+time\s*=\s*({1})\s+bitrate\s*=\s*({2})({3})\/s\s+speed\s*=\s*({2})\s*x",
-            BYTES_REGEX, TIME_REGEX, FLOAT32_REGEX, BITS_LONG_REGEX,
+            concat!(
+                r"(?:frame\s*=\s*(\d+)\s+fps\s*=\s*({2})\s+q\s*=\s*({2})\s+)?",
+                r"size\s*=\s*(?:(\d+)\s*({0})|{4})\s+time\s*=\s*({1}|{4})\s+",
+                r"bitrate\s*=\s*(?:({2})\s*({3})\/s|{4})\s+speed\s*=\s*(?:({2})\s*x|{4})",
+            ),
+            BYTES_REGEX, TIME_REGEX, FLOAT32_REGEX, BITS_LONG_REGEX, NOT_AVAILABLE_REGEX,
         ).as_str()).unwrap();
         static ref SOURCE_INFO_REGEX: Regex = Regex::new(format!(
-            r"Duration:\s*({0}),\s*start:\s*({1}),\s*bitrate:\s*({2})\s*({3})\/s",
-            TIME_REGEX, TIME_REGEX, FLOAT32_REGEX, BITS_SHORT_REGEX,
+            r"Duration:\s*({0}|{4}),\s*start:\s*({1}|{4}),\s*bitrate:\s*(?:({2})\s*({3})\/s|{4})",
+            TIME_REGEX, TIME_REGEX, FLOAT32_REGEX, BITS_SHORT_REGEX, NOT_AVAILABLE_REGEX,
         ).as_str()).unwrap();
     }
     let line = line.trim();
     if let Some(captures) = PROGRESS_REGEX.captures(line) {
         let frame: Option<usize> = captures.get(1).and_then(|m| m.as_str().parse().ok());
-        let fps: Option<f32> = captures.get(2).and_then(|m| m.as_str().parse().ok());
-        let q_factor: Option<f32> = captures.get(3).and_then(|m| m.as_str().parse().ok());
+        let fps: Option<f32> = captures.get(2).and_then(|m| parse_positive_float(m.as_str()));
+        // NOTE: Audio only outputs report q=-1.0 since the quality factor only applies to video
+        let q_factor: Option<f32> = captures.get(3).and_then(|m| m.as_str().replace(',', ".").parse().ok());
         let size_bytes = {
             let value: Option<u32> = captures.get(4).and_then(|m| m.as_str().parse().ok());
             let unit: Option<SizeBytes> = captures.get(5).and_then(|m| m.as_str().try_into().ok());
@@ -184,16 +710,16 @@ pub fn parse_stderr_line(line: &str) -> Option<ParsedStderrLine> {
                 _ => None,
             }
         };
-        let total_time_transcoded: Option<Time> = captures.get(6).and_then(|m| Time::try_from_str(m.as_str()).ok());
+        let total_time_transcoded: Option<Time> = captures.get(6).and_then(|m| parse_positive_time(m.as_str()));
         let speed_bits = {
-            let value: Option<f32> = captures.get(7).and_then(|m| m.as_str().parse().ok());
+            let value: Option<f32> = captures.get(7).and_then(|m| parse_positive_float(m.as_str()));
             let unit: Option<SizeBits> = captures.get(8).and_then(|m| SizeBits::try_from_long(m.as_str()));
             match (value, unit) {
-                (Some(value), Some(unit)) => Some((value * unit.to_bits() as f32) as usize),
+                (Some(value), Some(unit)) => Some((value as f64 * unit.to_bits() as f64).round() as usize),
                 _ => None,
             }
         };
-        let speed_factor: Option<f32> = captures.get(9).and_then(|m| m.as_str().parse().ok());
+        let speed_factor: Option<f32> = captures.get(9).and_then(|m| parse_positive_float(m.as_str()));
         let result = TranscodeProgress {
             frame,
             fps,
@@ -205,13 +731,13 @@ pub fn parse_stderr_line(line: &str) -> Option<ParsedStderrLine> {
         };
         return Some(ParsedStderrLine::TranscodeProgress(result));
     } else if let Some(captures) = SOURCE_INFO_REGEX.captures(line) {
-        let duration: Option<Time> = captures.get(1).and_then(|m| Time::try_from_str(m.as_str()).ok());
-        let start_time: Option<Time> = captures.get(2).and_then(|m| Time::try_from_str(m.as_str()).ok());
+        let duration: Option<Time> = captures.get(1).and_then(|m| parse_positive_time(m.as_str()));
+        let start_time: Option<Time> = captures.get(2).and_then(|m| parse_positive_time(m.as_str()));
         let speed_bits = {
-            let value: Option<f32> = captures.get(3).and_then(|m| m.as_str().parse().ok());
+            let value: Option<f32> = captures.get(3).and_then(|m| parse_positive_float(m.as_str()));
             let unit: Option<SizeBits> = captures.get(4).and_then(|m| SizeBits::try_from_short(m.as_str()));
             match (value, unit) {
-                (Some(value), Some(unit)) => Some((value * unit.to_bits() as f32) as usize),
+                (Some(value), Some(unit)) => Some((value as f64 * unit.to_bits() as f64).round() as usize),
                 _ => None,
             }
         };
@@ -222,5 +748,169 @@ pub fn parse_stderr_line(line: &str) -> Option<ParsedStderrLine> {
         };
         return Some(ParsedStderrLine::TranscodeSourceInfo(result));
     }
-    None
+    parse_error_line(line).map(ParsedStderrLine::Failure)
+}
+
+/// Parses the key=value records written by "-progress -" which are printed the same way regardless of version or locale
+/// ```text
+/// total_size=786432
+/// out_time_us=49130000
+/// out_time=00:00:49.130000
+/// bitrate= 128.0kbits/s
+/// speed=24.5x
+/// progress=continue
+/// ```
+#[derive(Clone,Debug,Default)]
+pub struct ProgressRecordParser {
+    progress: TranscodeProgress,
+}
+
+impl ProgressRecordParser {
+    /// Returns the progress once the progress key that ends each record is read
+    pub fn parse_line(&mut self, line: &str) -> Option<TranscodeProgress> {
+        lazy_static! {
+            static ref BITRATE_REGEX: Regex = Regex::new(format!(
+                r"^({0})\s*({1})\/s$", FLOAT32_REGEX, BITS_LONG_REGEX,
+            ).as_str()).unwrap();
+            static ref SPEED_REGEX: Regex = Regex::new(format!(r"^({0})\s*x$", FLOAT32_REGEX).as_str()).unwrap();
+        }
+        let (key, value) = line.trim().split_once('=')?;
+        let value = value.trim();
+        let progress = &mut self.progress;
+        match key {
+            "frame" => progress.frame = value.parse().ok(),
+            "fps" => progress.fps = parse_positive_float(value),
+            "stream_0_0_q" => progress.q_factor = value.replace(',', ".").parse().ok(),
+            "total_size" => progress.size_bytes = value.parse().ok(),
+            // NOTE: out_time_ms is also in microseconds and is the only one printed by older releases
+            "out_time_us" | "out_time_ms" => {
+                let time: Option<i64> = value.parse().ok();
+                progress.total_time_transcoded = time
+                    .and_then(|time| u64::try_from(time).ok())
+                    .map(Time::from_microseconds)
+                    .or(progress.total_time_transcoded);
+            },
+            "out_time" => {
+                progress.total_time_transcoded = progress.total_time_transcoded.or(parse_positive_time(value));
+            },
+            "bitrate" => {
+                progress.speed_bits = BITRATE_REGEX.captures(value).and_then(|captures| {
+                    let value = parse_positive_float(captures.get(1)?.as_str())?;
+                    let unit = SizeBits::try_from_long(captures.get(2)?.as_str())?;
+                    Some((value as f64 * unit.to_bits() as f64).round() as usize)
+                });
+            },
+            "speed" => {
+                progress.speed_factor = SPEED_REGEX.captures(value)
+                    .and_then(|captures| parse_positive_float(captures.get(1)?.as_str()));
+            },
+            "progress" => return Some(std::mem::take(&mut self.progress)),
+            _ => (),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_tempo(tempo: f64) -> TranscodeOptions {
+        TranscodeOptions { tempo: Some(Tempo(tempo)), ..TranscodeOptions::default() }
+    }
+
+    #[test]
+    fn tempo_is_chained_outside_atempo_range() {
+        let cases: [(f64, &[&str]); 4] = [
+            (4.0, &["atempo=2.0", "atempo=2.000000"]),
+            (0.25, &["atempo=0.5", "atempo=0.500000"]),
+            (3.0, &["atempo=2.0", "atempo=1.500000"]),
+            (1.5, &["atempo=1.500000"]),
+        ];
+        for (tempo, filters) in cases {
+            let options = with_tempo(tempo);
+            assert_eq!(options.get_tempo_filters(), filters, "tempo={tempo}");
+            assert_eq!(options.get_ffmpeg_arguments(None), ["-af".to_owned(), filters.join(",")], "tempo={tempo}");
+        }
+        assert!(TranscodeOptions::default().get_tempo_filters().is_empty());
+    }
+
+    #[test]
+    fn trim_is_scaled_by_tempo() {
+        let options = TranscodeOptions { trim_start_ms: Some(10_000), trim_end_ms: Some(70_000), ..with_tempo(2.0) };
+        assert_eq!(options.get_ffmpeg_arguments(None), ["-ss", "5.000", "-to", "35.000", "-af", "atempo=2.000000"]);
+        let options = TranscodeOptions { trim_start_ms: Some(1_500), ..with_tempo(0.5) };
+        assert_eq!(options.get_ffmpeg_arguments(None), ["-ss", "3.000", "-af", "atempo=0.500000"]);
+        assert_eq!(options.get_output_duration_ms(61_500), 120_000);
+    }
+
+    fn get_audio_filters(options: &TranscodeOptions, source_duration_ms: Option<u64>) -> Option<String> {
+        let args = options.get_ffmpeg_arguments(source_duration_ms);
+        let index = args.iter().position(|arg| arg == "-af")?;
+        args.get(index+1).cloned()
+    }
+
+    #[test]
+    fn fade_in_is_anchored_at_trim_start() {
+        let options = TranscodeOptions { fade_in_ms: Some(2_000), ..TranscodeOptions::default() };
+        assert_eq!(get_audio_filters(&options, None).as_deref(), Some("afade=t=in:st=0.000:d=2.000"));
+        let options = TranscodeOptions { trim_start_ms: Some(10_000), ..options };
+        assert_eq!(get_audio_filters(&options, None).as_deref(), Some("afade=t=in:st=10.000:d=2.000"));
+    }
+
+    #[test]
+    fn fade_out_is_anchored_at_output_end() {
+        let options = TranscodeOptions { fade_out_ms: Some(3_000), ..TranscodeOptions::default() };
+        // NOTE: The end can't be placed without the source duration or a trimmed end
+        assert_eq!(get_audio_filters(&options, None), None);
+        assert_eq!(get_audio_filters(&options, Some(60_000)).as_deref(), Some("afade=t=out:st=57.000:d=3.000"));
+        let options = TranscodeOptions { trim_end_ms: Some(30_000), ..options };
+        assert_eq!(get_audio_filters(&options, None).as_deref(), Some("afade=t=out:st=27.000:d=3.000"));
+        assert_eq!(get_audio_filters(&options, Some(60_000)).as_deref(), Some("afade=t=out:st=27.000:d=3.000"));
+        // NOTE: A trim past the end of the source fades out at the end of the source
+        let options = TranscodeOptions { trim_end_ms: Some(90_000), ..options };
+        assert_eq!(get_audio_filters(&options, Some(60_000)).as_deref(), Some("afade=t=out:st=57.000:d=3.000"));
+    }
+
+    #[test]
+    fn fades_are_combined() {
+        let options = TranscodeOptions {
+            trim_start_ms: Some(5_000), trim_end_ms: Some(65_000), fade_in_ms: Some(1_000), fade_out_ms: Some(4_000),
+            ..TranscodeOptions::default()
+        };
+        assert_eq!(
+            get_audio_filters(&options, Some(120_000)).as_deref(),
+            Some("afade=t=in:st=5.000:d=1.000,afade=t=out:st=61.000:d=4.000"),
+        );
+    }
+
+    #[test]
+    fn fade_out_is_reversed_when_trimming_silence() {
+        let options = TranscodeOptions { trim_silence: true, fade_out_ms: Some(3_000), ..TranscodeOptions::default() };
+        let expected = format!("{SILENCE_REMOVE_FILTER},areverse,{SILENCE_REMOVE_FILTER},afade=t=in:d=3.000,areverse");
+        // NOTE: The source duration is ignored since silence changes where the output ends
+        assert_eq!(get_audio_filters(&options, Some(60_000)), Some(expected));
+        let options = TranscodeOptions { fade_in_ms: Some(1_000), fade_out_ms: None, ..options };
+        let expected = format!("{SILENCE_REMOVE_FILTER},areverse,{SILENCE_REMOVE_FILTER},areverse,afade=t=in:st=0.000:d=1.000");
+        assert_eq!(get_audio_filters(&options, Some(60_000)), Some(expected));
+    }
+
+    #[test]
+    fn fades_are_validated() {
+        let presets = TranscodePresets::new();
+        let options = TranscodeOptions {
+            trim_start_ms: Some(10_000), trim_end_ms: Some(15_000), fade_in_ms: Some(3_000), fade_out_ms: Some(3_000),
+            ..TranscodeOptions::default()
+        };
+        assert!(matches!(options.validate(&presets), Err(TranscodeOptionsError::FadeTooLong { fade: 6_000, output: 5_000 })));
+        let options = TranscodeOptions { fade_out_ms: Some(2_000), ..options };
+        assert!(options.validate(&presets).is_ok());
+        // NOTE: The output length isn't known without a trimmed end
+        let options = TranscodeOptions { trim_end_ms: None, fade_out_ms: Some(TranscodeOptions::MAX_FADE_MS), ..options };
+        assert!(options.validate(&presets).is_ok());
+        for fade in [0, TranscodeOptions::MAX_FADE_MS+1] {
+            let options = TranscodeOptions { fade_in_ms: Some(fade), ..TranscodeOptions::default() };
+            assert!(matches!(options.validate(&presets), Err(TranscodeOptionsError::InvalidFade { given, .. }) if given == fade));
+        }
+    }
 }
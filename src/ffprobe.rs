@@ -0,0 +1,256 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// NOTE: ffmpeg reports -91dB for digital silence in 16bit audio
+const SILENCE_THRESHOLD_DB: f64 = -90.0;
+// NOTE: Encoders pad or trim a few frames so we allow a small difference in duration
+const TRUNCATION_TOLERANCE_MILLISECONDS: u64 = 1000;
+const TRUNCATION_TOLERANCE_RATIO: f64 = 0.02;
+// NOTE: AcoustID only needs the start of the audio so we fingerprint the same length as fpcalc
+const FINGERPRINT_SECONDS: u64 = 120;
+
+#[derive(Clone,Debug,Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    #[serde(default)]
+    disposition: ProbeDisposition,
+}
+
+#[derive(Clone,Debug,Default,Deserialize)]
+struct ProbeDisposition {
+    #[serde(default)]
+    attached_pic: u8,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct ProbeFormat {
+    // NOTE: ffprobe prints numbers as strings in json output
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Clone,Debug,Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Clone,Debug,Default,Serialize)]
+pub struct ProbeResult {
+    pub codec: Option<String>,
+    pub bitrate: Option<u64>,
+    pub duration_ms: Option<u64>,
+    pub has_artwork: bool,
+    pub max_volume_db: Option<f64>,
+}
+
+/// Loudness measured by ffmpeg's replaygain filter
+#[derive(Clone,Copy,Debug,Serialize)]
+pub struct ReplayGain {
+    pub track_gain_db: f64,
+    pub track_peak: f64,
+}
+
+#[derive(Debug,Error)]
+pub enum ProbeError {
+    #[error("Failed to run {binary}: {error:?}")]
+    Spawn { binary: String, error: std::io::Error },
+    #[error("ffprobe failed with bad code: {0:?}")]
+    BadExitCode(Option<i32>),
+    #[error("Failed to parse ffprobe output: {0:?}")]
+    InvalidOutput(#[from] serde_json::Error),
+    #[error("ffmpeg didn't report a replaygain measurement")]
+    MissingReplayGain,
+    #[error("ffmpeg didn't print a fingerprint, it may have been built without chromaprint")]
+    MissingFingerprint,
+}
+
+#[derive(Clone,Debug,Error)]
+pub enum ProbeValidationError {
+    #[error("Output file is empty")]
+    EmptyFile,
+    #[error("Output file has no audio stream")]
+    MissingAudioStream,
+    #[error("Output file has no duration")]
+    MissingDuration,
+    #[error("Output file is truncated: expected={expected_ms}ms, given={given_ms}ms")]
+    Truncated { expected_ms: u64, given_ms: u64 },
+    #[error("Output file is silent: max_volume={max_volume_db}dB")]
+    Silent { max_volume_db: f64 },
+}
+
+pub fn get_ffprobe_arguments(path: &str) -> Vec<String> {
+    [
+        "-v", "error",
+        "-print_format", "json",
+        "-show_format", "-show_streams",
+        path,
+    ].iter().map(|&arg| arg.to_owned()).collect()
+}
+
+pub fn get_volume_detect_arguments(path: &str) -> Vec<String> {
+    [
+        "-hide_banner", "-nostats",
+        "-i", path,
+        "-map", "0:a:0",
+        "-af", "volumedetect",
+        "-f", "null", "-",
+    ].iter().map(|&arg| arg.to_owned()).collect()
+}
+
+pub fn get_replay_gain_arguments(path: &str, volume_filter: Option<&str>) -> Vec<String> {
+    let filter = match volume_filter {
+        Some(volume_filter) => format!("{volume_filter},replaygain"),
+        None => "replaygain".to_owned(),
+    };
+    [
+        "-hide_banner", "-nostats",
+        "-i", path,
+        "-map", "0:a:0",
+        "-af", filter.as_str(),
+        "-f", "null", "-",
+    ].iter().map(|&arg| arg.to_owned()).collect()
+}
+
+pub fn get_fingerprint_arguments(path: &str) -> Vec<String> {
+    [
+        "-hide_banner", "-nostats",
+        "-i", path,
+        "-map", "0:a:0",
+        "-t", FINGERPRINT_SECONDS.to_string().as_str(),
+        "-f", "chromaprint", "-fp_format", "base64", "-",
+    ].iter().map(|&arg| arg.to_owned()).collect()
+}
+
+pub fn parse_ffprobe_output(output: &str) -> Result<ProbeResult, serde_json::Error> {
+    let output: ProbeOutput = serde_json::from_str(output)?;
+    let audio_stream = output.streams.iter().find(|stream| stream.codec_type.as_deref() == Some("audio"));
+    let has_artwork = output.streams.iter().any(|stream| {
+        stream.codec_type.as_deref() == Some("video") && stream.disposition.attached_pic != 0
+    });
+    let format = output.format.as_ref();
+    let duration_ms = format
+        .and_then(|format| format.duration.as_ref())
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .map(|seconds| (seconds*1000.0) as u64);
+    let bitrate = format
+        .and_then(|format| format.bit_rate.as_ref())
+        .and_then(|bitrate| bitrate.parse::<u64>().ok());
+    Ok(ProbeResult {
+        codec: audio_stream.and_then(|stream| stream.codec_name.clone()),
+        bitrate,
+        duration_ms,
+        has_artwork,
+        max_volume_db: None,
+    })
+}
+
+pub fn parse_max_volume(output: &str) -> Option<f64> {
+    lazy_static! {
+        static ref MAX_VOLUME_REGEX: Regex = Regex::new(r"max_volume:\s*(-?\d+(?:\.\d+)?|-inf)\s*dB").unwrap();
+    }
+    let captures = MAX_VOLUME_REGEX.captures(output)?;
+    match captures.get(1)?.as_str() {
+        "-inf" => Some(f64::NEG_INFINITY),
+        value => value.parse().ok(),
+    }
+}
+
+pub fn parse_replay_gain(output: &str) -> Option<ReplayGain> {
+    lazy_static! {
+        static ref TRACK_GAIN_REGEX: Regex = Regex::new(r"track_gain\s*=\s*([+-]?\d+(?:\.\d+)?)\s*dB").unwrap();
+        static ref TRACK_PEAK_REGEX: Regex = Regex::new(r"track_peak\s*=\s*(\d+(?:\.\d+)?)").unwrap();
+    }
+    let track_gain_db = TRACK_GAIN_REGEX.captures(output)?.get(1)?.as_str().parse().ok()?;
+    let track_peak = TRACK_PEAK_REGEX.captures(output)?.get(1)?.as_str().parse().ok()?;
+    Some(ReplayGain { track_gain_db, track_peak })
+}
+
+/// Decodes the whole file to measure its loudness after the volume filter is applied
+pub fn measure_replay_gain(ffmpeg_binary: &Path, path: &Path, volume_filter: Option<&str>) -> Result<ReplayGain, ProbeError> {
+    // NOTE: replaygain prints its measurement to stderr
+    let output = Command::new(ffmpeg_binary)
+        .args(get_replay_gain_arguments(path.to_str().unwrap(), volume_filter))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| ProbeError::Spawn { binary: ffmpeg_binary.to_string_lossy().to_string(), error })?;
+    if !output.status.success() {
+        return Err(ProbeError::BadExitCode(output.status.code()));
+    }
+    parse_replay_gain(String::from_utf8_lossy(&output.stderr).as_ref()).ok_or(ProbeError::MissingReplayGain)
+}
+
+/// Compressed chromaprint fingerprint of the start of the audio in the base64 format used by AcoustID
+pub fn fingerprint(ffmpeg_binary: &Path, path: &Path) -> Result<String, ProbeError> {
+    let output = Command::new(ffmpeg_binary)
+        .args(get_fingerprint_arguments(path.to_str().unwrap()))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| ProbeError::Spawn { binary: ffmpeg_binary.to_string_lossy().to_string(), error })?;
+    if !output.status.success() {
+        return Err(ProbeError::BadExitCode(output.status.code()));
+    }
+    let fingerprint = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if fingerprint.is_empty() {
+        return Err(ProbeError::MissingFingerprint);
+    }
+    Ok(fingerprint)
+}
+
+/// Inspects the file with ffprobe without decoding it
+pub fn probe_format(ffprobe_binary: &Path, path: &Path) -> Result<ProbeResult, ProbeError> {
+    let output = Command::new(ffprobe_binary)
+        .args(get_ffprobe_arguments(path.to_str().unwrap()))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| ProbeError::Spawn { binary: ffprobe_binary.to_string_lossy().to_string(), error })?;
+    if !output.status.success() {
+        return Err(ProbeError::BadExitCode(output.status.code()));
+    }
+    Ok(parse_ffprobe_output(String::from_utf8_lossy(&output.stdout).as_ref())?)
+}
+
+/// Inspects the file with ffprobe and measures its peak volume with ffmpeg
+pub fn probe_file(ffprobe_binary: &Path, ffmpeg_binary: &Path, path: &Path) -> Result<ProbeResult, ProbeError> {
+    let mut result = probe_format(ffprobe_binary, path)?;
+    let path = path.to_str().unwrap();
+    if result.codec.is_some() {
+        // NOTE: volumedetect prints its summary to stderr
+        let output = Command::new(ffmpeg_binary)
+            .args(get_volume_detect_arguments(path))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|error| ProbeError::Spawn { binary: ffmpeg_binary.to_string_lossy().to_string(), error })?;
+        result.max_volume_db = parse_max_volume(String::from_utf8_lossy(&output.stderr).as_ref());
+    }
+    Ok(result)
+}
+
+impl ProbeResult {
+    pub fn validate(&self, expected_duration_ms: Option<u64>) -> Result<(), ProbeValidationError> {
+        if self.codec.is_none() {
+            return Err(ProbeValidationError::MissingAudioStream);
+        }
+        let Some(duration_ms) = self.duration_ms.filter(|&duration| duration > 0) else {
+            return Err(ProbeValidationError::MissingDuration);
+        };
+        if let Some(expected_ms) = expected_duration_ms {
+            let tolerance_ms = TRUNCATION_TOLERANCE_MILLISECONDS.max((expected_ms as f64 * TRUNCATION_TOLERANCE_RATIO) as u64);
+            if duration_ms + tolerance_ms < expected_ms {
+                return Err(ProbeValidationError::Truncated { expected_ms, given_ms: duration_ms });
+            }
+        }
+        if let Some(max_volume_db) = self.max_volume_db {
+            if max_volume_db <= SILENCE_THRESHOLD_DB {
+                return Err(ProbeValidationError::Silent { max_volume_db });
+            }
+        }
+        Ok(())
+    }
+}
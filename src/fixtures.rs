@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use lazy_static::lazy_static;
+use regex::Regex;
+use crate::downloader::{Downloader, DownloadProgress, DownloadRequest, ParsedStdoutLine, ParsedStderrLine};
+use crate::error_code::ErrorCode;
+use crate::ffprobe::{ProbeError, ProbeResult, ReplayGain};
+use crate::job_process::JobProcess;
+use crate::process_priority::ProcessPriority;
+use crate::transcoder::Transcoder;
+use crate::url_parse::parse_video_url;
+use crate::waveform::{Waveform, WaveformError};
+
+pub const DEFAULT_MOCK_DURATION_MS: u64 = 60_000;
+const MOCK_BITRATE: u64 = 128_000;
+const MOCK_AUDIO_EXT: &str = "m4a";
+const TOTAL_MOCK_WAVEFORM_PEAKS: usize = 100;
+/// Fingerprint given for every file by MockTranscoder
+pub const MOCK_FINGERPRINT: &str = "AQAAAA";
+
+lazy_static! {
+    static ref TEMPLATE_FIELD_REGEX: Regex = Regex::new(r"%\(([^)]+)\)s").unwrap();
+}
+
+/// Writes a small fake audio file instead of running yt-dlp so routes can be tested without the network
+#[derive(Clone,Debug)]
+pub struct MockDownloader {
+    pub duration_ms: u64,
+    /// Downloads of these videos fail like a yt-dlp error would
+    pub fail_video_ids: HashSet<String>,
+}
+
+impl Default for MockDownloader {
+    fn default() -> Self {
+        Self {
+            duration_ms: DEFAULT_MOCK_DURATION_MS,
+            fail_video_ids: HashSet::new(),
+        }
+    }
+}
+
+impl MockDownloader {
+    pub const NAME: &'static str = "mock";
+
+    /// Fills in the output template the same way yt-dlp would for the fields we know
+    fn get_output_path(request: &DownloadRequest, video_id: &str) -> PathBuf {
+        let template = request.output_template.to_string_lossy();
+        let path = TEMPLATE_FIELD_REGEX.replace_all(template.as_ref(), |captures: &regex::Captures| {
+            match &captures[1] {
+                "id" => video_id.to_owned(),
+                "ext" => MOCK_AUDIO_EXT.to_owned(),
+                _ => Self::NAME.to_owned(),
+            }
+        });
+        PathBuf::from(path.as_ref())
+    }
+}
+
+impl Downloader for MockDownloader {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn binary(&self) -> &Path {
+        Path::new(Self::NAME)
+    }
+
+    fn get_arguments(&self, request: &DownloadRequest) -> Vec<String> {
+        vec![request.url.to_owned()]
+    }
+
+    fn parse_stdout_line(&self, line: &str) -> Option<ParsedStdoutLine> {
+        let (key, value) = line.trim().split_once('=')?;
+        match key {
+            "output" => Some(ParsedStdoutLine::OutputPath(value.to_owned())),
+            "duration_ms" => value.parse().ok().map(ParsedStdoutLine::DurationMilliseconds),
+            "downloaded_bytes" => value.parse().ok().map(|bytes| ParsedStdoutLine::DownloadProgress(DownloadProgress {
+                downloaded_bytes: Some(bytes),
+                total_bytes: Some(bytes),
+                ..DownloadProgress::default()
+            })),
+            _ => None,
+        }
+    }
+
+    fn parse_stderr_line(&self, line: &str) -> Option<ParsedStderrLine> {
+        match line.trim() {
+            "failed" => Some(ParsedStderrLine::Failure(ErrorCode::YtdlpFailed)),
+            _ => None,
+        }
+    }
+
+    fn spawn(&self, request: &DownloadRequest, _priority: &ProcessPriority) -> std::io::Result<JobProcess> {
+        let video_id = parse_video_url(request.url)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err.to_string()))?
+            .video_id;
+        if self.fail_video_ids.contains(video_id.as_str()) {
+            return Ok(JobProcess::finished("", "failed\n", 1));
+        }
+        let output_path = Self::get_output_path(request, video_id.as_str());
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let audio = format!("mock audio of {0}", video_id.as_str());
+        std::fs::write(&output_path, audio.as_bytes())?;
+        let stdout = format!(
+            "downloaded_bytes={0}\nduration_ms={1}\noutput={2}\n",
+            audio.len(), self.duration_ms, output_path.to_string_lossy(),
+        );
+        Ok(JobProcess::finished(stdout, "", 0))
+    }
+}
+
+/// Writes fake transcodes and hls playlists instead of running ffmpeg, and reports every file as valid audio
+#[derive(Clone,Debug)]
+pub struct MockTranscoder {
+    pub duration_ms: u64,
+}
+
+impl Default for MockTranscoder {
+    fn default() -> Self {
+        Self { duration_ms: DEFAULT_MOCK_DURATION_MS }
+    }
+}
+
+impl MockTranscoder {
+    pub const NAME: &'static str = "mock";
+
+    fn get_probe_result(&self) -> ProbeResult {
+        ProbeResult {
+            codec: Some(Self::NAME.to_owned()),
+            bitrate: Some(MOCK_BITRATE),
+            duration_ms: Some(self.duration_ms),
+            has_artwork: false,
+            max_volume_db: Some(-1.0),
+        }
+    }
+}
+
+impl Transcoder for MockTranscoder {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    // NOTE: Hls playlists need the end tag to be treated as finished
+    fn spawn(&self, args: &[String], _is_stdin_piped: bool, _priority: &ProcessPriority) -> std::io::Result<JobProcess> {
+        let Some(output_path) = args.last().map(PathBuf::from) else {
+            return Ok(JobProcess::finished("", "", 1));
+        };
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let is_playlist = output_path.extension().is_some_and(|ext| ext == "m3u8");
+        let output = if is_playlist { "#EXTM3U\n#EXT-X-ENDLIST\n" } else { "mock transcode" };
+        std::fs::write(&output_path, output)?;
+        Ok(JobProcess::finished("", "", 0))
+    }
+
+    fn probe_format(&self, _path: &Path) -> Result<ProbeResult, ProbeError> {
+        Ok(self.get_probe_result())
+    }
+
+    fn probe_file(&self, _path: &Path) -> Result<ProbeResult, ProbeError> {
+        Ok(self.get_probe_result())
+    }
+
+    fn measure_replay_gain(&self, _path: &Path, _volume_filter: Option<&str>) -> Result<ReplayGain, ProbeError> {
+        Ok(ReplayGain { track_gain_db: 0.0, track_peak: 1.0 })
+    }
+
+    fn fingerprint(&self, _path: &Path) -> Result<String, ProbeError> {
+        Ok(MOCK_FINGERPRINT.to_owned())
+    }
+
+    fn generate_waveform(&self, _path: &Path, _priority: &ProcessPriority) -> Result<Waveform, WaveformError> {
+        Ok(Waveform { duration_ms: self.duration_ms, peaks: vec![0.5; TOTAL_MOCK_WAVEFORM_PEAKS] })
+    }
+}
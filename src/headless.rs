@@ -0,0 +1,143 @@
+use thiserror::Error;
+use crate::app::{AppState, WorkerCacheEntry};
+use crate::database::{AudioExtension, JobKind, VideoId, VideoIdError, WorkerStatus};
+use crate::error_code::ErrorCode;
+use crate::ffmpeg::{TranscodeOptions, TranscodeOptionsError};
+use crate::job_store::JobStoreError;
+use crate::maintenance::{run_maintenance_task_now, MaintenanceError, MaintenanceTask};
+use crate::metadata::get_metadata_from_cache;
+use crate::worker_download::{try_start_download_worker, DownloadStartError};
+use crate::worker_queue::JobId;
+use crate::worker_transcode::{try_start_transcode_worker, TranscodeKey, TranscodeStartError};
+use crate::ytdlp::DownloadOptions;
+
+#[derive(Debug,Error)]
+pub enum HeadlessError {
+    #[error("Invalid video id: {0}")]
+    InvalidVideoId(#[from] VideoIdError),
+    #[error("Invalid audio extension: {0}")]
+    InvalidAudioExtension(String),
+    #[error("Invalid transcode options: {0}")]
+    InvalidTranscodeOptions(#[from] TranscodeOptionsError),
+    #[error("Failed to start download: {0}")]
+    DownloadStart(#[from] DownloadStartError),
+    #[error("Failed to start transcode: {0}")]
+    TranscodeStart(#[from] TranscodeStartError),
+    #[error("Job failed: key={key}, reason={reason}")]
+    JobFailed { key: String, reason: String },
+    #[error("Maintenance task failed: {0}")]
+    MaintenanceFailed(MaintenanceTask),
+    #[error("Maintenance failed: {0}")]
+    Maintenance(#[from] MaintenanceError),
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+/// Tasks run by the purge command, which clean up data instead of starting jobs
+const PURGE_TASKS: [MaintenanceTask; 3] = [MaintenanceTask::PurgeTrash, MaintenanceTask::PurgeOldLogs, MaintenanceTask::ExpireFailedJobs];
+
+/// Waits until the job is no longer queued or running
+async fn wait_for_job<T>(app: &AppState, job: JobId, state: &WorkerCacheEntry<T>, get_status: fn(&T) -> WorkerStatus) {
+    let mut events = app.job_events.subscribe(job);
+    while get_status(&state.lock().unwrap()).is_busy() {
+        if !events.changed().await {
+            break;
+        }
+    }
+}
+
+fn get_fail_reason(fail_reason: &Option<String>, fail_code: Option<ErrorCode>) -> String {
+    match (fail_reason, fail_code) {
+        (Some(reason), _) => reason.clone(),
+        (None, Some(code)) => code.as_str().to_owned(),
+        (None, None) => "unknown".to_owned(),
+    }
+}
+
+/// Downloads the video if it isn't already and prints the path of the audio
+pub async fn download(app: &AppState, video_id: &str) -> Result<(), HeadlessError> {
+    let video_id = VideoId::try_new(video_id)?;
+    try_start_download_worker(
+        video_id.clone(),
+        app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        app.app_config.download_options.clone(), None,
+    )?;
+    let download_state = app.download_cache.entry(video_id.clone()).or_default().clone();
+    wait_for_job(app, JobId::new(JobKind::Download, video_id.as_str()), &download_state, |state| state.worker_status).await;
+    {
+        let state = download_state.lock().unwrap();
+        if state.worker_status != WorkerStatus::Finished {
+            let reason = get_fail_reason(&state.fail_reason, state.fail_code);
+            return Err(HeadlessError::JobFailed { key: video_id.as_str().to_owned(), reason });
+        }
+    }
+    let audio_path = app.job_store.select_ytdlp_entry(&video_id)?.and_then(|entry| entry.audio_path).unwrap_or_default();
+    println!("{audio_path}");
+    Ok(())
+}
+
+/// Downloads and transcodes the video if it isn't already and prints the path of the transcode
+pub async fn transcode(app: &AppState, video_id: &str, audio_ext: &str, options: TranscodeOptions) -> Result<(), HeadlessError> {
+    let video_id = VideoId::try_new(video_id)?;
+    let audio_ext = AudioExtension::try_from(audio_ext).map_err(|_| HeadlessError::InvalidAudioExtension(audio_ext.to_owned()))?;
+    options.validate(&app.app_config.transcode_presets)?;
+    let key = TranscodeKey::new(video_id.clone(), audio_ext, &options);
+    let download_options = DownloadOptions { audio_lang: options.audio_lang.clone(), ..app.app_config.download_options.clone() };
+    try_start_download_worker(
+        video_id.clone(),
+        app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        download_options, None,
+    )?;
+    let metadata = get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await.ok();
+    try_start_transcode_worker(
+        key.clone(), options,
+        app.download_cache.clone(), app.transcode_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        metadata, None,
+    )?;
+    let transcode_state = app.transcode_cache.entry(key.clone()).or_default().clone();
+    wait_for_job(app, JobId::new(JobKind::Transcode, key.as_str()), &transcode_state, |state| state.worker_status).await;
+    {
+        let state = transcode_state.lock().unwrap();
+        if state.worker_status != WorkerStatus::Finished {
+            let reason = get_fail_reason(&state.fail_reason, state.fail_code);
+            return Err(HeadlessError::JobFailed { key: key.as_str(), reason });
+        }
+    }
+    let audio_path = app.job_store.select_ffmpeg_entry(&video_id, audio_ext, key.variant.as_str())?
+        .and_then(|entry| entry.audio_path)
+        .unwrap_or_default();
+    println!("{audio_path}");
+    Ok(())
+}
+
+/// Prints a tab separated line for each download and transcode
+pub fn list(app: &AppState) -> Result<(), HeadlessError> {
+    for entry in app.job_store.select_ytdlp_entries()? {
+        println!(
+            "download\t{0}\t{1:?}\t{2}",
+            entry.video_id.as_str(), entry.status, entry.audio_path.unwrap_or_default(),
+        );
+    }
+    for entry in app.job_store.select_ffmpeg_entries()? {
+        let key = TranscodeKey { video_id: entry.video_id.clone(), audio_ext: entry.audio_ext, variant: entry.variant.clone() };
+        println!(
+            "transcode\t{0}\t{1:?}\t{2}",
+            key.as_str(), entry.status, entry.audio_path.unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+/// Runs the cleanup maintenance tasks and fails if any of them did
+pub async fn purge(app: &AppState) -> Result<(), HeadlessError> {
+    let mut failed_task = None;
+    for task in PURGE_TASKS {
+        if !run_maintenance_task_now(app, task).await? {
+            failed_task = Some(task);
+        }
+    }
+    match failed_task {
+        Some(task) => Err(HeadlessError::MaintenanceFailed(task)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,174 @@
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+use crate::database::{AudioExtension, VideoId};
+use crate::ffmpeg::TranscodeOptions;
+use crate::url_parse::parse_video_url;
+
+const DISCORD_PUBLIC_KEY_BYTES: usize = 32;
+const DISCORD_PING: u8 = 1;
+const DISCORD_APPLICATION_COMMAND: u8 = 2;
+const DISCORD_PONG: u8 = 1;
+const DISCORD_CHANNEL_MESSAGE: u8 = 4;
+
+#[derive(Debug,Error)]
+pub enum IntegrationError {
+    #[error("Discord public key must be {DISCORD_PUBLIC_KEY_BYTES} bytes of hex: {0}")]
+    InvalidDiscordPublicKey(String),
+    #[error("Request is missing the {0} header")]
+    MissingHeader(&'static str),
+    #[error("Request signature doesn't match the public key")]
+    InvalidSignature,
+    #[error("Request secret token doesn't match")]
+    InvalidSecretToken,
+    #[error("Failed to parse request: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Chat bots that can start conversions by posting links to /integrations
+#[derive(Clone)]
+pub struct IntegrationConfig {
+    /// Telegram sends this in X-Telegram-Bot-Api-Secret-Token when given to setWebhook
+    pub telegram_secret_token: Option<String>,
+    /// Public key of the discord application which signs every interaction
+    pub discord_public_key: Option<Vec<u8>>,
+    pub audio_ext: AudioExtension,
+    pub transcode_options: TranscodeOptions,
+}
+
+impl Default for IntegrationConfig {
+    fn default() -> Self {
+        Self {
+            telegram_secret_token: None,
+            discord_public_key: None,
+            audio_ext: AudioExtension::M4A,
+            transcode_options: TranscodeOptions::default(),
+        }
+    }
+}
+
+// NOTE: The config is logged on startup so the secret token is left out
+impl std::fmt::Debug for IntegrationConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntegrationConfig")
+            .field("telegram", &self.telegram_secret_token.is_some())
+            .field("discord", &self.discord_public_key.is_some())
+            .field("audio_ext", &self.audio_ext)
+            .field("transcode_options", &self.transcode_options)
+            .finish()
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len()).step_by(2).map(|i| u8::from_str_radix(&value[i..i+2], 16).ok()).collect()
+}
+
+pub fn parse_discord_public_key(value: &str) -> Result<Vec<u8>, IntegrationError> {
+    decode_hex(value)
+        .filter(|key| key.len() == DISCORD_PUBLIC_KEY_BYTES)
+        .ok_or_else(|| IntegrationError::InvalidDiscordPublicKey(value.to_owned()))
+}
+
+/// Discord signs the timestamp followed by the body with ed25519
+// NOTE: Discord sends requests with bad signatures when the endpoint is added and refuses it unless they are rejected
+pub fn verify_discord_signature(
+    public_key: &[u8], signature: Option<&str>, timestamp: Option<&str>, body: &[u8],
+) -> Result<(), IntegrationError> {
+    let signature = signature.ok_or(IntegrationError::MissingHeader("X-Signature-Ed25519"))?;
+    let timestamp = timestamp.ok_or(IntegrationError::MissingHeader("X-Signature-Timestamp"))?;
+    let signature = decode_hex(signature).ok_or(IntegrationError::InvalidSignature)?;
+    let message = [timestamp.as_bytes(), body].concat();
+    ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key)
+        .verify(message.as_slice(), signature.as_slice())
+        .map_err(|_| IntegrationError::InvalidSignature)
+}
+
+pub fn verify_telegram_secret_token(secret_token: &str, given: Option<&str>) -> Result<(), IntegrationError> {
+    let given = given.ok_or(IntegrationError::MissingHeader("X-Telegram-Bot-Api-Secret-Token"))?;
+    // NOTE: Every byte is compared so the time taken doesn't reveal how much of the token matched
+    let is_equal = secret_token.len() == given.len() &&
+        secret_token.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+    if !is_equal {
+        return Err(IntegrationError::InvalidSecretToken);
+    }
+    Ok(())
+}
+
+/// Finds the first YouTube link in a chat message, or the message itself if it is a video id
+pub fn find_video_id(text: &str) -> Option<VideoId> {
+    text.split_whitespace()
+        .find_map(|word| parse_video_url(word).ok().map(|url| url.video_id))
+        .or_else(|| VideoId::try_new(text.trim()).ok())
+}
+
+#[derive(Debug,Deserialize)]
+pub struct TelegramChat {
+    pub id: i64,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct TelegramMessage {
+    pub message_id: i64,
+    pub chat: TelegramChat,
+    pub text: Option<String>,
+}
+
+/// Update posted to the webhook, where other kinds of updates such as edits are ignored
+#[derive(Debug,Deserialize)]
+pub struct TelegramUpdate {
+    pub message: Option<TelegramMessage>,
+}
+
+/// Replies to the message by answering the webhook with a sendMessage call instead of calling the bot api
+pub fn get_telegram_reply(message: &TelegramMessage, text: &str) -> serde_json::Value {
+    json!({
+        "method": "sendMessage",
+        "chat_id": message.chat.id,
+        "reply_to_message_id": message.message_id,
+        "text": text,
+    })
+}
+
+#[derive(Debug,Deserialize)]
+pub struct DiscordCommandOption {
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct DiscordCommandData {
+    #[serde(default)]
+    pub options: Vec<DiscordCommandOption>,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct DiscordInteraction {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub data: Option<DiscordCommandData>,
+}
+
+impl DiscordInteraction {
+    pub fn is_ping(&self) -> bool {
+        self.kind == DISCORD_PING
+    }
+
+    /// Text given to the options of a slash command such as /convert url:<link>
+    pub fn get_command_text(&self) -> Option<String> {
+        if self.kind != DISCORD_APPLICATION_COMMAND {
+            return None;
+        }
+        let options = self.data.as_ref()?.options.iter().filter_map(|option| option.value.as_str());
+        Some(options.collect::<Vec<_>>().join(" "))
+    }
+}
+
+pub fn get_discord_pong() -> serde_json::Value {
+    json!({ "type": DISCORD_PONG })
+}
+
+pub fn get_discord_reply(text: &str) -> serde_json::Value {
+    json!({ "type": DISCORD_CHANNEL_MESSAGE, "data": { "content": text } })
+}
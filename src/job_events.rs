@@ -0,0 +1,68 @@
+use tokio::sync::broadcast::{self, error::RecvError};
+use crate::worker_queue::JobId;
+
+// NOTE: Receivers that fall behind skip the missed events and read the state again
+//       so the capacity only has to cover bursts of progress updates
+const JOB_EVENT_CAPACITY: usize = 1024;
+
+/// Published whenever the state of a download, transcode or hls job changes in its cache
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct JobEvent {
+    pub job: JobId,
+}
+
+/// Lets workers and route handlers wait for a job to change without sharing a condvar with its state
+#[derive(Clone)]
+pub struct JobEventBus {
+    sender: broadcast::Sender<JobEvent>,
+}
+
+impl Default for JobEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobEventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(JOB_EVENT_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, job: JobId) {
+        // NOTE: Sending only fails if nobody is listening which is fine
+        let _ = self.sender.send(JobEvent { job });
+    }
+
+    /// Subscribe before reading the state so changes made after the read aren't missed
+    pub fn subscribe(&self, job: JobId) -> JobEventReceiver {
+        JobEventReceiver { receiver: self.sender.subscribe(), job }
+    }
+}
+
+pub struct JobEventReceiver {
+    receiver: broadcast::Receiver<JobEvent>,
+    job: JobId,
+}
+
+impl JobEventReceiver {
+    fn is_changed(&self, res: Result<JobEvent, RecvError>) -> Option<bool> {
+        match res {
+            Ok(event) if event.job == self.job => Some(true),
+            Ok(_) => None,
+            // NOTE: The missed events could have been for our job so the caller has to check again
+            Err(RecvError::Lagged(_)) => Some(true),
+            Err(RecvError::Closed) => Some(false),
+        }
+    }
+
+    /// Waits until the job changes, returns false if the bus was closed
+    pub async fn changed(&mut self) -> bool {
+        loop {
+            let res = self.receiver.recv().await;
+            if let Some(is_changed) = self.is_changed(res) {
+                return is_changed;
+            }
+        }
+    }
+}
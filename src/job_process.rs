@@ -0,0 +1,66 @@
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::process::{Child, Command};
+use crate::process_priority::ProcessPriority;
+
+pub type ProcessReader = Box<dyn AsyncRead + Send + Unpin>;
+pub type ProcessWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Download or transcode whose stdout and stderr are scraped by the workers
+pub struct JobProcess {
+    pub stdin: Option<ProcessWriter>,
+    pub stdout: Option<ProcessReader>,
+    pub stderr: Option<ProcessReader>,
+    // NOTE: Processes that already finished (e.g. mocks used in tests) don't have a child to wait on
+    child: Option<Child>,
+    exit_code: Option<i32>,
+}
+
+impl JobProcess {
+    /// Runs the binary with piped output, where stdin is only piped when feeding it a partial download
+    pub fn spawn(binary: &Path, args: &[String], is_stdin_piped: bool, priority: &ProcessPriority) -> std::io::Result<Self> {
+        let mut command = Command::new(binary);
+        priority.apply(command.as_std_mut());
+        let mut child = command
+            .args(args)
+            .stdin(if is_stdin_piped { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        Ok(Self {
+            stdin: child.stdin.take().map(|stdin| Box::new(stdin) as ProcessWriter),
+            stdout: child.stdout.take().map(|stdout| Box::new(stdout) as ProcessReader),
+            stderr: child.stderr.take().map(|stderr| Box::new(stderr) as ProcessReader),
+            child: Some(child),
+            exit_code: None,
+        })
+    }
+
+    /// Process that already exited with this output, where anything written to stdin is discarded
+    pub fn finished(stdout: impl Into<Vec<u8>>, stderr: impl Into<Vec<u8>>, exit_code: i32) -> Self {
+        Self {
+            stdin: Some(Box::new(tokio::io::sink())),
+            stdout: Some(Box::new(std::io::Cursor::new(stdout.into()))),
+            stderr: Some(Box::new(std::io::Cursor::new(stderr.into()))),
+            child: None,
+            exit_code: Some(exit_code),
+        }
+    }
+
+    /// Exit code of the process which is None if it was killed by a signal
+    pub async fn wait(&mut self) -> std::io::Result<Option<i32>> {
+        match self.child.as_mut() {
+            Some(child) => Ok(child.wait().await?.code()),
+            None => Ok(self.exit_code),
+        }
+    }
+
+    pub fn start_kill(&mut self) -> std::io::Result<()> {
+        match self.child.as_mut() {
+            Some(child) => child.start_kill(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use serde::Serialize;
+use crate::database::WorkerStatus;
+use crate::error_code::ErrorCode;
+use crate::worker_download::DownloadState;
+use crate::worker_transcode::TranscodeState;
+
+/// Combined status of downloading and then transcoding a video
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    DownloadQueued,
+    Downloading,
+    /// Download finished but the transcode is waiting for a worker thread
+    TranscodeQueued,
+    Transcoding,
+    Finished,
+    Failed,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStage {
+    Download,
+    Transcode,
+}
+
+/// Single state machine over the download and transcode so clients don't need to merge both states
+#[derive(Clone,Debug,Serialize)]
+pub struct JobState {
+    pub status: JobStatus,
+    /// Download and transcode each make up half of the progress, missing if either total is unknown
+    pub progress_percent: Option<f32>,
+    pub fail_stage: Option<JobStage>,
+    pub fail_code: Option<ErrorCode>,
+    pub fail_reason: Option<String>,
+}
+
+fn get_download_fraction(state: &DownloadState) -> Option<f32> {
+    match state.worker_status {
+        WorkerStatus::Finished => Some(1.0),
+        WorkerStatus::None | WorkerStatus::Queued => Some(0.0),
+        WorkerStatus::Running => state.progress_percent.map(|percent| percent / 100.0),
+        WorkerStatus::Failed => None,
+    }
+}
+
+fn get_transcode_fraction(state: &TranscodeState) -> Option<f32> {
+    match state.worker_status {
+        WorkerStatus::Finished => Some(1.0),
+        WorkerStatus::None | WorkerStatus::Queued => Some(0.0),
+        // NOTE: ffmpeg hasn't reported any progress yet
+        WorkerStatus::Running if state.transcode_duration_milliseconds.is_none() => Some(0.0),
+        WorkerStatus::Running => state.progress_percent.map(|percent| percent / 100.0),
+        WorkerStatus::Failed => None,
+    }
+}
+
+impl JobState {
+    /// Returns None if the transcode wasn't requested
+    // NOTE: The download state is missing after a restart if only the transcode was requested again
+    //       The transcode worker then reads the finished download from the database
+    pub fn new(download: Option<&DownloadState>, transcode: &TranscodeState) -> Option<Self> {
+        if transcode.worker_status == WorkerStatus::None {
+            return None;
+        }
+        let download = download.filter(|state| state.worker_status != WorkerStatus::None);
+        let mut job = Self {
+            status: JobStatus::TranscodeQueued,
+            progress_percent: None,
+            fail_stage: None,
+            fail_code: None,
+            fail_reason: None,
+        };
+        // NOTE: A failed download also fails the transcode so we report the download as the cause
+        if let Some(state) = download.filter(|state| state.worker_status == WorkerStatus::Failed) {
+            job.status = JobStatus::Failed;
+            job.fail_stage = Some(JobStage::Download);
+            job.fail_code = state.fail_code;
+            job.fail_reason = state.fail_reason.clone();
+            return Some(job);
+        }
+        if transcode.worker_status == WorkerStatus::Failed {
+            job.status = JobStatus::Failed;
+            job.fail_stage = Some(JobStage::Transcode);
+            job.fail_code = transcode.fail_code;
+            job.fail_reason = transcode.fail_reason.clone();
+            return Some(job);
+        }
+        let download_status = download.map(|state| state.worker_status).unwrap_or(WorkerStatus::Finished);
+        // NOTE: Pipelined transcodes run while the download is still running so the transcode takes priority
+        job.status = match (download_status, transcode.worker_status) {
+            (_, WorkerStatus::Finished) => JobStatus::Finished,
+            (_, WorkerStatus::Running) => JobStatus::Transcoding,
+            (WorkerStatus::Queued, _) => JobStatus::DownloadQueued,
+            (WorkerStatus::Running, _) => JobStatus::Downloading,
+            _ => JobStatus::TranscodeQueued,
+        };
+        let download_fraction = match download {
+            Some(state) if job.status != JobStatus::Finished => get_download_fraction(state),
+            _ => Some(1.0),
+        };
+        if let (Some(download), Some(transcode)) = (download_fraction, get_transcode_fraction(transcode)) {
+            job.progress_percent = Some((download + transcode) * 50.0);
+        }
+        Some(job)
+    }
+}
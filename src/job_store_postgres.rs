@@ -0,0 +1,1245 @@
+use std::collections::BTreeMap;
+use num_traits::cast::FromPrimitive;
+use postgres::NoTls;
+use postgres::types::ToSql;
+use r2d2_postgres::PostgresConnectionManager;
+use crate::database::{
+    VideoId, AudioExtension, WorkerStatus, JobKind, YtdlpRow, FfmpegRow, SearchRow, MetadataRow, EnrichmentRow, LibraryRow, SubscriptionRow, JobRow,
+    UserRow, AuthTokenRow, AuthTokenKind, JobsPerDayRow, JobFailureRow, ChannelCountRow, TableUsageRow, JobThroughputRow,
+    ChannelJobRow, ChannelJobVideoRow, MaintenanceRunRow, TrashRow, TableRow, MigrationError, EntryFilter, EntryFilterValue,
+    merge_library_rows, map_job_kind, YTDLP_TABLE, TOTAL_YTDLP_COLUMNS, FFMPEG_TABLE, TOTAL_FFMPEG_COLUMNS,
+    METADATA_JOIN, METADATA_JOIN_COLUMNS, SUBSCRIPTION_TABLE, JOB_TABLE, USER_TABLE, AUTH_TOKEN_TABLE,
+    CHANNEL_JOB_TABLE, MAINTENANCE_RUN_TABLE, TRASH_TABLE, ENRICHMENT_TABLE, EXPORT_TABLES,
+    YTDLP_KEY_COLUMNS, YTDLP_INSERT_COLUMNS, YTDLP_UPDATE_COLUMNS, FFMPEG_KEY_COLUMNS, FFMPEG_INSERT_COLUMNS, FFMPEG_UPDATE_COLUMNS,
+    ENRICHMENT_INSERT_COLUMNS,
+};
+use crate::job_store::{JobStore, JobStoreError, DatabaseOptions};
+use crate::query::{column, columns, Dialect, Order, Table};
+use crate::util::get_unix_time;
+
+pub type PostgresPool = r2d2::Pool<PostgresConnectionManager<NoTls>>;
+
+// NOTE: Migrations are applied in order and must never be modified once released
+//       These must be kept in sync with the sqlite migrations
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/postgres/0001_create_worker_tables.sql"),
+    include_str!("../migrations/postgres/0002_add_ytdlp_downloaded_bytes.sql"),
+    include_str!("../migrations/postgres/0003_create_metadata_search.sql"),
+    include_str!("../migrations/postgres/0004_create_metadata.sql"),
+    include_str!("../migrations/postgres/0005_add_file_size_and_duration.sql"),
+    include_str!("../migrations/postgres/0006_add_ffmpeg_probe_results.sql"),
+    include_str!("../migrations/postgres/0007_add_transcode_variants.sql"),
+    include_str!("../migrations/postgres/0008_add_ytdlp_format_id.sql"),
+    include_str!("../migrations/postgres/0009_create_subscriptions.sql"),
+    include_str!("../migrations/postgres/0010_create_job_durations.sql"),
+    include_str!("../migrations/postgres/0011_create_jobs.sql"),
+    include_str!("../migrations/postgres/0012_create_users.sql"),
+    include_str!("../migrations/postgres/0013_create_conversions.sql"),
+    include_str!("../migrations/postgres/0014_create_job_history.sql"),
+    include_str!("../migrations/postgres/0015_create_channel_jobs.sql"),
+    include_str!("../migrations/postgres/0016_add_ffmpeg_file_hash.sql"),
+    include_str!("../migrations/postgres/0017_create_maintenance_runs.sql"),
+    include_str!("../migrations/postgres/0018_add_ffmpeg_transcode_preset.sql"),
+    include_str!("../migrations/postgres/0019_add_job_history_request_id.sql"),
+    include_str!("../migrations/postgres/0020_create_trash.sql"),
+    include_str!("../migrations/postgres/0021_add_final_state.sql"),
+    include_str!("../migrations/postgres/0022_add_ytdlp_audio_lang.sql"),
+    include_str!("../migrations/postgres/0023_add_delete_pending.sql"),
+    include_str!("../migrations/postgres/0024_add_ffmpeg_video_foreign_key.sql"),
+    include_str!("../migrations/postgres/0025_add_library_indexes.sql"),
+    include_str!("../migrations/postgres/0026_create_enrichments.sql"),
+    include_str!("../migrations/postgres/0027_add_audio_path_indexes.sql"),
+];
+
+// NOTE: The synchronous postgres client drives its own tokio runtime which panics if it is
+//       used from inside another runtime (our actix request handlers). We run every query
+//       on a short lived thread so the store can be used from both workers and handlers.
+fn run_blocking<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| scope.spawn(f).join().expect("Postgres query thread panicked"))
+}
+
+pub struct PostgresJobStore {
+    pool: PostgresPool,
+}
+
+impl PostgresJobStore {
+    pub fn open(url: &str, options: &DatabaseOptions) -> Result<Self, JobStoreError> {
+        let mut config: postgres::Config = url.parse().map_err(|_| JobStoreError::InvalidUrl(url.to_owned()))?;
+        config.options(format!("-c lock_timeout={0}", options.busy_timeout.as_millis()).as_str());
+        let manager = PostgresConnectionManager::new(config, NoTls);
+        let pool = run_blocking(|| PostgresPool::builder().max_size(options.pool_size).build(manager))?;
+        let store = Self { pool };
+        run_blocking(|| store.setup_database())?;
+        Ok(store)
+    }
+
+    fn setup_database(&self) -> Result<(), JobStoreError> {
+        let mut client = self.pool.get()?;
+        client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER,
+                unix_time BIGINT,
+                PRIMARY KEY (version)
+            )",
+        )?;
+        let current_version: Option<i32> = client.query_one("SELECT MAX(version) FROM schema_version", &[])?.get(0);
+        let current_version = current_version.unwrap_or(0) as usize;
+        let latest_version = MIGRATIONS.len();
+        if current_version > latest_version {
+            return Err(MigrationError::UnknownVersion { given: current_version, latest: latest_version }.into());
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current_version) {
+            let version = index+1;
+            log::info!("Applying database migration: version={version}");
+            let mut tx = client.transaction()?;
+            tx.batch_execute(migration)?;
+            tx.execute(
+                "INSERT INTO schema_version (version, unix_time) VALUES ($1,$2)",
+                &[&(version as i32), &(get_unix_time() as i64)],
+            )?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
+
+fn map_ytdlp_row_to_entry(row: &postgres::Row) -> Result<YtdlpRow, postgres::Error> {
+    let video_id: String = row.try_get(0)?;
+    let video_id = VideoId::try_new(video_id.as_str()).expect("video_id should be valid");
+    let status: i32 = row.try_get(1)?;
+    let status = WorkerStatus::from_i32(status).expect("status should be valid");
+    let unix_time: Option<i64> = row.try_get(2)?;
+    let downloaded_bytes: Option<i64> = row.try_get(7)?;
+    let file_size_bytes: Option<i64> = row.try_get(8)?;
+    let duration_ms: Option<i64> = row.try_get(9)?;
+    Ok(YtdlpRow {
+        video_id,
+        status,
+        unix_time: unix_time.unwrap_or(0) as u64,
+        stdout_log_path: row.try_get(3)?,
+        stderr_log_path: row.try_get(4)?,
+        system_log_path: row.try_get(5)?,
+        audio_path: row.try_get(6)?,
+        downloaded_bytes: downloaded_bytes.map(|v| v as usize),
+        file_size_bytes: file_size_bytes.map(|v| v as u64),
+        duration_ms: duration_ms.map(|v| v as u64),
+        source_hash: row.try_get(10)?,
+        format_id: row.try_get(11)?,
+        owner: row.try_get(12)?,
+        final_state: row.try_get(13)?,
+        audio_lang: row.try_get(14)?,
+        delete_pending: row.try_get(15)?,
+    })
+}
+
+fn map_ffmpeg_row_to_entry(row: &postgres::Row) -> Result<FfmpegRow, postgres::Error> {
+    let video_id: String = row.try_get(0)?;
+    let video_id = VideoId::try_new(video_id.as_str()).expect("video_id should be valid");
+    let audio_ext: String = row.try_get(1)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let status: i32 = row.try_get(2)?;
+    let status = WorkerStatus::from_i32(status).expect("status should be valid");
+    let unix_time: Option<i64> = row.try_get(3)?;
+    let file_size_bytes: Option<i64> = row.try_get(8)?;
+    let duration_ms: Option<i64> = row.try_get(9)?;
+    let bitrate: Option<i64> = row.try_get(11)?;
+    Ok(FfmpegRow {
+        video_id,
+        audio_ext,
+        status,
+        unix_time: unix_time.unwrap_or(0) as u64,
+        stdout_log_path: row.try_get(4)?,
+        stderr_log_path: row.try_get(5)?,
+        system_log_path: row.try_get(6)?,
+        audio_path: row.try_get(7)?,
+        file_size_bytes: file_size_bytes.map(|v| v as u64),
+        duration_ms: duration_ms.map(|v| v as u64),
+        codec: row.try_get(10)?,
+        bitrate: bitrate.map(|v| v as u64),
+        has_artwork: row.try_get(12)?,
+        max_volume_db: row.try_get(13)?,
+        variant: row.try_get(14)?,
+        source_hash: row.try_get(15)?,
+        transcode_options: row.try_get(16)?,
+        owner: row.try_get(17)?,
+        file_hash: row.try_get(18)?,
+        transcode_preset: row.try_get(19)?,
+        final_state: row.try_get(20)?,
+        delete_pending: row.try_get(21)?,
+    })
+}
+
+fn map_metadata_row_to_entry(
+    row: &postgres::Row, video_id: &VideoId, offset: usize,
+) -> Result<Option<MetadataRow>, postgres::Error> {
+    // NOTE: Columns are null when the left join has no matching metadata
+    let title: Option<String> = row.try_get(offset)?;
+    let Some(title) = title else {
+        return Ok(None);
+    };
+    let channel: Option<String> = row.try_get(offset+1)?;
+    let duration_ms: Option<i64> = row.try_get(offset+2)?;
+    Ok(Some(MetadataRow {
+        video_id: video_id.clone(),
+        title,
+        channel: channel.unwrap_or_default(),
+        duration_ms: duration_ms.map(|v| v as u64),
+        thumbnail_url: row.try_get(offset+3)?,
+    }))
+}
+
+fn map_job_row_to_entry(row: &postgres::Row) -> Result<JobRow, postgres::Error> {
+    let video_id: String = row.try_get(2)?;
+    let video_id = VideoId::try_new(video_id.as_str()).expect("video_id should be valid");
+    let audio_ext: String = row.try_get(3)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let unix_time: Option<i64> = row.try_get(5)?;
+    Ok(JobRow {
+        job_id: row.try_get(0)?,
+        idempotency_key: row.try_get(1)?,
+        video_id,
+        audio_ext,
+        variant: row.try_get(4)?,
+        unix_time: unix_time.unwrap_or(0) as u64,
+    })
+}
+
+fn map_subscription_row_to_entry(row: &postgres::Row) -> Result<SubscriptionRow, postgres::Error> {
+    let audio_ext: String = row.try_get(2)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let check_interval_seconds: i64 = row.try_get(4)?;
+    let unix_time: Option<i64> = row.try_get(5)?;
+    let last_check_unix_time: Option<i64> = row.try_get(6)?;
+    Ok(SubscriptionRow {
+        id: row.try_get(0)?,
+        url: row.try_get(1)?,
+        audio_ext,
+        transcode_options: row.try_get(3)?,
+        check_interval_seconds: check_interval_seconds as u64,
+        unix_time: unix_time.unwrap_or(0) as u64,
+        last_check_unix_time: last_check_unix_time.map(|v| v as u64),
+        last_check_error: row.try_get(7)?,
+    })
+}
+
+fn map_channel_job_row_to_entry(row: &postgres::Row) -> Result<ChannelJobRow, postgres::Error> {
+    let audio_ext: String = row.try_get(2)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).expect("audio_ext should be valid");
+    let status: i32 = row.try_get(6)?;
+    let status = WorkerStatus::from_i32(status).expect("status should be valid");
+    let total_listed: Option<i64> = row.try_get(8)?;
+    let unix_time: Option<i64> = row.try_get(10)?;
+    Ok(ChannelJobRow {
+        id: row.try_get(0)?,
+        channel_id: row.try_get(1)?,
+        audio_ext,
+        variant: row.try_get(3)?,
+        transcode_options: row.try_get(4)?,
+        filters: row.try_get(5)?,
+        status,
+        error: row.try_get(7)?,
+        total_listed: total_listed.map(|v| v as u64),
+        owner: row.try_get(9)?,
+        unix_time: unix_time.unwrap_or(0) as u64,
+    })
+}
+
+fn map_maintenance_run_row_to_entry(row: &postgres::Row) -> Result<MaintenanceRunRow, postgres::Error> {
+    let status: i32 = row.try_get(3)?;
+    let status = WorkerStatus::from_i32(status).expect("status should be valid");
+    let unix_time: Option<i64> = row.try_get(6)?;
+    let end_unix_time: Option<i64> = row.try_get(7)?;
+    Ok(MaintenanceRunRow {
+        id: row.try_get(0)?,
+        task: row.try_get(1)?,
+        is_manual: row.try_get(2)?,
+        status,
+        summary: row.try_get(4)?,
+        error: row.try_get(5)?,
+        unix_time: unix_time.unwrap_or(0) as u64,
+        end_unix_time: end_unix_time.map(|v| v as u64),
+    })
+}
+
+fn map_trash_row_to_entry(row: &postgres::Row) -> Result<TrashRow, postgres::Error> {
+    let kind: String = row.try_get(1)?;
+    let kind = JobKind::try_from(kind.as_str()).expect("kind should be valid");
+    let file_size_bytes: Option<i64> = row.try_get(6)?;
+    let deleted_at: i64 = row.try_get(7)?;
+    Ok(TrashRow {
+        id: row.try_get(0)?,
+        kind,
+        key: row.try_get(2)?,
+        owner: row.try_get(3)?,
+        entry: row.try_get(4)?,
+        files: row.try_get(5)?,
+        file_size_bytes: file_size_bytes.map(|v| v as u64),
+        deleted_at: deleted_at as u64,
+    })
+}
+
+fn map_enrichment_row_to_entry(row: &postgres::Row) -> Result<EnrichmentRow, postgres::Error> {
+    let video_id: String = row.try_get(0)?;
+    let track_number: Option<i64> = row.try_get(7)?;
+    let unix_time: Option<i64> = row.try_get(9)?;
+    Ok(EnrichmentRow {
+        video_id: VideoId::try_new(video_id.as_str()).expect("video_id should be valid"),
+        recording_id: row.try_get(1)?,
+        score: row.try_get(2)?,
+        title: row.try_get(3)?,
+        artist: row.try_get(4)?,
+        album: row.try_get(5)?,
+        album_artist: row.try_get(6)?,
+        track_number: track_number.map(|v| v as u32),
+        release_date: row.try_get(8)?,
+        unix_time: unix_time.unwrap_or(0) as u64,
+    })
+}
+
+fn map_user_row_to_entry(row: &postgres::Row) -> Result<UserRow, postgres::Error> {
+    let unix_time: Option<i64> = row.try_get(4)?;
+    Ok(UserRow {
+        id: row.try_get(0)?,
+        username: row.try_get(1)?,
+        password_hash: row.try_get(2)?,
+        is_admin: row.try_get(3)?,
+        unix_time: unix_time.unwrap_or(0) as u64,
+    })
+}
+
+fn map_auth_token_row_to_entry(row: &postgres::Row) -> Result<AuthTokenRow, postgres::Error> {
+    let kind: String = row.try_get(3)?;
+    let kind = AuthTokenKind::try_from(kind.as_str()).expect("kind should be valid");
+    let unix_time: Option<i64> = row.try_get(5)?;
+    let expire_unix_time: Option<i64> = row.try_get(6)?;
+    Ok(AuthTokenRow {
+        id: row.try_get(0)?,
+        token_hash: row.try_get(1)?,
+        user_id: row.try_get(2)?,
+        kind,
+        name: row.try_get(4)?,
+        unix_time: unix_time.unwrap_or(0) as u64,
+        expire_unix_time: expire_unix_time.map(|v| v as u64),
+    })
+}
+
+/// Select of the filter along with the values to bind in order
+fn get_entry_filter_query<const N: usize>(table: &Table<N>, filter: &EntryFilter) -> (String, Vec<Box<dyn ToSql + Sync>>) {
+    let mut query = table.select();
+    let mut values = Vec::<Box<dyn ToSql + Sync>>::new();
+    for (column, compare, value) in filter.get_conditions(table) {
+        query = query.filter(column, compare);
+        values.push(match value {
+            EntryFilterValue::Status(status) => Box::new(status as i32),
+            EntryFilterValue::AudioExt(audio_ext) => Box::new(audio_ext.as_str()),
+            EntryFilterValue::UnixTime(unix_time) => Box::new(unix_time as i64),
+        });
+    }
+    (query.build(Dialect::Postgres), values)
+}
+
+impl JobStore for PostgresJobStore {
+    fn insert_ytdlp_entry(
+        &self, video_id: &VideoId, downloaded_bytes: Option<usize>, owner: Option<i64>,
+    ) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = YTDLP_TABLE.insert(&YTDLP_INSERT_COLUMNS).on_conflict(&YTDLP_KEY_COLUMNS, &columns!(YTDLP_TABLE; owner));
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(),
+                &[
+                    &video_id.as_str(), &(WorkerStatus::Queued as i32), &(get_unix_time() as i64),
+                    &downloaded_bytes.map(|v| v as i64), &owner, &false,
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn insert_ffmpeg_entry(
+        &self, video_id: &VideoId, audio_ext: AudioExtension, variant: &str, transcode_options: Option<&str>,
+        owner: Option<i64>,
+    ) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = FFMPEG_TABLE.insert(&FFMPEG_INSERT_COLUMNS).on_conflict(&FFMPEG_KEY_COLUMNS, &columns!(FFMPEG_TABLE; owner));
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(),
+                &[
+                    &video_id.as_str(), &audio_ext.as_str(), &variant,
+                    &(WorkerStatus::Queued as i32), &(get_unix_time() as i64), &transcode_options, &owner, &false,
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn update_ytdlp_entry(&self, entry: &YtdlpRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = YTDLP_TABLE.update(&YTDLP_UPDATE_COLUMNS).filter_equal(&YTDLP_KEY_COLUMNS);
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(),
+                &[
+                    &(entry.unix_time as i64), &(entry.status as i32),
+                    &entry.stdout_log_path, &entry.stderr_log_path, &entry.system_log_path, &entry.audio_path,
+                    &entry.downloaded_bytes.map(|v| v as i64),
+                    &entry.file_size_bytes.map(|v| v as i64), &entry.duration_ms.map(|v| v as i64),
+                    &entry.source_hash, &entry.format_id, &entry.final_state, &entry.audio_lang,
+                    &entry.video_id.as_str(),
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn update_ffmpeg_entry(&self, entry: &FfmpegRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = FFMPEG_TABLE.update(&FFMPEG_UPDATE_COLUMNS).filter_equal(&FFMPEG_KEY_COLUMNS);
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(),
+                &[
+                    &(entry.unix_time as i64), &(entry.status as i32),
+                    &entry.stdout_log_path, &entry.stderr_log_path, &entry.system_log_path, &entry.audio_path,
+                    &entry.file_size_bytes.map(|v| v as i64), &entry.duration_ms.map(|v| v as i64),
+                    &entry.codec, &entry.bitrate.map(|v| v as i64), &entry.has_artwork, &entry.max_volume_db,
+                    &entry.source_hash, &entry.transcode_options, &entry.file_hash, &entry.transcode_preset, &entry.final_state,
+                    &entry.video_id.as_str(), &entry.audio_ext.as_str(), &entry.variant,
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn delete_ytdlp_entry(&self, video_id: &VideoId) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = YTDLP_TABLE.delete().filter_equal(&YTDLP_KEY_COLUMNS);
+            let total = self.pool.get()?.execute(query.build(Dialect::Postgres).as_str(), &[&video_id.as_str()])?;
+            Ok(total as usize)
+        })
+    }
+
+    fn delete_ffmpeg_entry(&self, video_id: &VideoId, audio_ext: AudioExtension, variant: &str) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = FFMPEG_TABLE.delete().filter_equal(&FFMPEG_KEY_COLUMNS);
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(), &[&video_id.as_str(), &audio_ext.as_str(), &variant],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn set_ytdlp_delete_pending(&self, video_id: &VideoId, is_pending: bool) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = YTDLP_TABLE.update(&columns!(YTDLP_TABLE; delete_pending)).filter_equal(&YTDLP_KEY_COLUMNS);
+            let total = self.pool.get()?.execute(query.build(Dialect::Postgres).as_str(), &[&is_pending, &video_id.as_str()])?;
+            Ok(total as usize)
+        })
+    }
+
+    fn set_ffmpeg_delete_pending(
+        &self, video_id: &VideoId, audio_ext: AudioExtension, variant: &str, is_pending: bool,
+    ) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = FFMPEG_TABLE.update(&columns!(FFMPEG_TABLE; delete_pending)).filter_equal(&FFMPEG_KEY_COLUMNS);
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(), &[&is_pending, &video_id.as_str(), &audio_ext.as_str(), &variant],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_ytdlp_entries(&self) -> Result<Vec<YtdlpRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(YTDLP_TABLE.select().build(Dialect::Postgres).as_str(), &[])?;
+            let entries = rows.iter().map(map_ytdlp_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_ytdlp_entry(&self, video_id: &VideoId) -> Result<Option<YtdlpRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                YTDLP_TABLE.select().filter_equal(&YTDLP_KEY_COLUMNS).build(Dialect::Postgres).as_str(),
+                &[&video_id.as_str()],
+            )?;
+            Ok(row.as_ref().map(map_ytdlp_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_audio_path_owner(&self, audio_path: &str) -> Result<Option<Option<i64>>, JobStoreError> {
+        run_blocking(|| {
+            let mut client = self.pool.get()?;
+            let row = client.query_opt(
+                YTDLP_TABLE.select().filter_equal(&columns!(YTDLP_TABLE; audio_path)).build(Dialect::Postgres).as_str(),
+                &[&audio_path],
+            )?;
+            if let Some(entry) = row.as_ref().map(map_ytdlp_row_to_entry).transpose()? {
+                return Ok(Some(entry.owner));
+            }
+            let row = client.query_opt(
+                FFMPEG_TABLE.select().filter_equal(&columns!(FFMPEG_TABLE; audio_path)).build(Dialect::Postgres).as_str(),
+                &[&audio_path],
+            )?;
+            Ok(row.as_ref().map(map_ffmpeg_row_to_entry).transpose()?.map(|entry| entry.owner))
+        })
+    }
+
+    fn select_filtered_ytdlp_entries(&self, filter: &EntryFilter) -> Result<Vec<YtdlpRow>, JobStoreError> {
+        run_blocking(|| {
+            let (query, values) = get_entry_filter_query(&YTDLP_TABLE, filter);
+            let params: Vec<&(dyn ToSql + Sync)> = values.iter().map(|value| value.as_ref()).collect();
+            let rows = self.pool.get()?.query(query.as_str(), &params)?;
+            let entries = rows.iter().map(map_ytdlp_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_filtered_ffmpeg_entries(&self, filter: &EntryFilter) -> Result<Vec<FfmpegRow>, JobStoreError> {
+        run_blocking(|| {
+            let (query, values) = get_entry_filter_query(&FFMPEG_TABLE, filter);
+            let params: Vec<&(dyn ToSql + Sync)> = values.iter().map(|value| value.as_ref()).collect();
+            let rows = self.pool.get()?.query(query.as_str(), &params)?;
+            let entries = rows.iter().map(map_ffmpeg_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_video_ffmpeg_entries(&self, video_id: &VideoId) -> Result<Vec<FfmpegRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                FFMPEG_TABLE.select().filter_equal(&columns!(FFMPEG_TABLE; video_id)).build(Dialect::Postgres).as_str(), &[&video_id.as_str()],
+            )?;
+            let entries = rows.iter().map(map_ffmpeg_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_ffmpeg_entries(&self) -> Result<Vec<FfmpegRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(FFMPEG_TABLE.select().build(Dialect::Postgres).as_str(), &[])?;
+            let entries = rows.iter().map(map_ffmpeg_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_ffmpeg_entry(
+        &self, video_id: &VideoId, audio_ext: AudioExtension, variant: &str,
+    ) -> Result<Option<FfmpegRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                FFMPEG_TABLE.select().filter_equal(&FFMPEG_KEY_COLUMNS).build(Dialect::Postgres).as_str(),
+                &[&video_id.as_str(), &audio_ext.as_str(), &variant],
+            )?;
+            Ok(row.as_ref().map(map_ffmpeg_row_to_entry).transpose()?)
+        })
+    }
+
+    fn upsert_metadata_search_entry(
+        &self, video_id: &VideoId, title: &str, channel: &str, description: &str, tags: &str,
+    ) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO metadata_search (video_id, title, channel, description, tags) VALUES ($1,$2,$3,$4,$5) \
+                 ON CONFLICT (video_id) DO UPDATE SET \
+                 title=EXCLUDED.title, channel=EXCLUDED.channel, description=EXCLUDED.description, tags=EXCLUDED.tags",
+                &[&video_id.as_str(), &title, &channel, &description, &tags],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn search_metadata_entries(&self, query: &str, owner: Option<i64>, limit: usize) -> Result<Vec<SearchRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT video_id, title, channel FROM ( \
+                    SELECT video_id, title, channel, \
+                    to_tsvector('simple', title || ' ' || channel || ' ' || description || ' ' || tags) AS document \
+                    FROM metadata_search WHERE video_id IN ( \
+                        SELECT video_id FROM ytdlp WHERE $2::BIGINT IS NULL OR owner IS NULL OR owner=$2 \
+                    ) \
+                 ) AS search, plainto_tsquery('simple', $1) AS query \
+                 WHERE document @@ query ORDER BY ts_rank(document, query) DESC LIMIT $3",
+                &[&query, &owner, &(limit as i64)],
+            )?;
+            let entries = rows.iter().map(|row| -> Result<SearchRow, postgres::Error> {
+                let video_id: String = row.try_get(0)?;
+                Ok(SearchRow {
+                    video_id: VideoId::try_new(video_id.as_str()).expect("video_id should be valid"),
+                    title: row.try_get(1)?,
+                    channel: row.try_get(2)?,
+                })
+            }).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn upsert_metadata_entry(&self, entry: &MetadataRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO metadata (video_id, title, channel, duration_ms, thumbnail_url, unix_time) VALUES ($1,$2,$3,$4,$5,$6) \
+                 ON CONFLICT (video_id) DO UPDATE SET \
+                 title=EXCLUDED.title, channel=EXCLUDED.channel, \
+                 duration_ms=EXCLUDED.duration_ms, thumbnail_url=EXCLUDED.thumbnail_url, unix_time=EXCLUDED.unix_time",
+                &[
+                    &entry.video_id.as_str(), &entry.title, &entry.channel,
+                    &entry.duration_ms.map(|v| v as i64), &entry.thumbnail_url, &(get_unix_time() as i64),
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_metadata_entry(&self, video_id: &VideoId) -> Result<Option<MetadataRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                "SELECT title, channel, duration_ms, thumbnail_url FROM metadata WHERE video_id=$1",
+                &[&video_id.as_str()],
+            )?;
+            let entry = row.as_ref().map(|row| map_metadata_row_to_entry(row, video_id, 0)).transpose()?;
+            Ok(entry.flatten())
+        })
+    }
+
+    fn select_stale_metadata_video_ids(&self, before_unix_time: u64, limit: usize) -> Result<Vec<VideoId>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT video_id FROM metadata WHERE unix_time IS NULL OR unix_time < $1 \
+                 ORDER BY COALESCE(unix_time, 0), video_id LIMIT $2",
+                &[&(before_unix_time as i64), &(limit as i64)],
+            )?;
+            let entries = rows.iter().map(|row| -> Result<VideoId, postgres::Error> {
+                let video_id: String = row.try_get(0)?;
+                Ok(VideoId::try_new(video_id.as_str()).expect("video_id should be valid"))
+            }).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn upsert_enrichment_entry(&self, entry: &EnrichmentRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let query = ENRICHMENT_TABLE.insert(&ENRICHMENT_INSERT_COLUMNS).on_conflict(&columns!(ENRICHMENT_TABLE; video_id), &[]);
+            let total = self.pool.get()?.execute(
+                query.build(Dialect::Postgres).as_str(),
+                &[
+                    &entry.video_id.as_str(), &entry.recording_id, &entry.score, &entry.title, &entry.artist, &entry.album,
+                    &entry.album_artist, &entry.track_number.map(|v| v as i64), &entry.release_date, &(entry.unix_time as i64),
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_enrichment_entry(&self, video_id: &VideoId) -> Result<Option<EnrichmentRow>, JobStoreError> {
+        run_blocking(|| {
+            let query = ENRICHMENT_TABLE.select().filter_equal(&columns!(ENRICHMENT_TABLE; video_id));
+            let row = self.pool.get()?.query_opt(query.build(Dialect::Postgres).as_str(), &[&video_id.as_str()])?;
+            Ok(row.as_ref().map(map_enrichment_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_library_entries(&self) -> Result<Vec<LibraryRow>, JobStoreError> {
+        run_blocking(|| {
+            let mut client = self.pool.get()?;
+            let rows = client.query(
+                YTDLP_TABLE.select().join(METADATA_JOIN, METADATA_JOIN_COLUMNS).build(Dialect::Postgres).as_str(),
+                &[],
+            )?;
+            let downloads = rows.iter().map(|row| {
+                let entry = map_ytdlp_row_to_entry(row)?;
+                let metadata = map_metadata_row_to_entry(row, &entry.video_id, TOTAL_YTDLP_COLUMNS)?;
+                Ok((entry, metadata))
+            }).collect::<Result<Vec<_>, postgres::Error>>()?;
+            let rows = client.query(
+                FFMPEG_TABLE.select().join(METADATA_JOIN, METADATA_JOIN_COLUMNS).build(Dialect::Postgres).as_str(),
+                &[],
+            )?;
+            let transcodes = rows.iter().map(|row| {
+                let entry = map_ffmpeg_row_to_entry(row)?;
+                let metadata = map_metadata_row_to_entry(row, &entry.video_id, TOTAL_FFMPEG_COLUMNS)?;
+                Ok((entry, metadata))
+            }).collect::<Result<Vec<_>, postgres::Error>>()?;
+            Ok(merge_library_rows(downloads, transcodes))
+        })
+    }
+
+    fn insert_subscription_entry(
+        &self, url: &str, audio_ext: AudioExtension, transcode_options: Option<&str>, check_interval_seconds: u64,
+    ) -> Result<i64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "INSERT INTO subscriptions (url, audio_ext, transcode_options, check_interval_seconds, unix_time) \
+                 VALUES ($1,$2,$3,$4,$5) RETURNING id",
+                &[
+                    &url, &audio_ext.as_str(), &transcode_options,
+                    &(check_interval_seconds as i64), &(get_unix_time() as i64),
+                ],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn update_subscription_entry(&self, entry: &SubscriptionRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "UPDATE subscriptions SET \
+                 audio_ext=$2, transcode_options=$3, check_interval_seconds=$4, last_check_unix_time=$5, last_check_error=$6 \
+                 WHERE id=$1",
+                &[
+                    &entry.id, &entry.audio_ext.as_str(), &entry.transcode_options,
+                    &(entry.check_interval_seconds as i64), &entry.last_check_unix_time.map(|v| v as i64),
+                    &entry.last_check_error,
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn delete_subscription_entry(&self, id: i64) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let mut client = self.pool.get()?;
+            let mut tx = client.transaction()?;
+            tx.execute("DELETE FROM subscription_videos WHERE subscription_id=$1", &[&id])?;
+            let total = tx.execute("DELETE FROM subscriptions WHERE id=$1", &[&id])?;
+            tx.commit()?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_subscription_entries(&self) -> Result<Vec<SubscriptionRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                SUBSCRIPTION_TABLE.select().order_by(column!(SUBSCRIPTION_TABLE; id), Order::Ascending).build(Dialect::Postgres).as_str(), &[],
+            )?;
+            let entries = rows.iter().map(map_subscription_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_subscription_entry(&self, id: i64) -> Result<Option<SubscriptionRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                SUBSCRIPTION_TABLE.select().filter_equal(&columns!(SUBSCRIPTION_TABLE; id)).build(Dialect::Postgres).as_str(), &[&id],
+            )?;
+            Ok(row.as_ref().map(map_subscription_row_to_entry).transpose()?)
+        })
+    }
+
+    fn insert_subscription_video_entry(&self, id: i64, video_id: &VideoId) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO subscription_videos (subscription_id, video_id, unix_time) VALUES ($1,$2,$3) \
+                 ON CONFLICT (subscription_id, video_id) DO NOTHING",
+                &[&id, &video_id.as_str(), &(get_unix_time() as i64)],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn insert_channel_job_entry(
+        &self, channel_id: &str, audio_ext: AudioExtension, variant: &str,
+        transcode_options: Option<&str>, filters: Option<&str>, owner: Option<i64>,
+    ) -> Result<i64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "INSERT INTO channel_jobs (channel_id, audio_ext, variant, transcode_options, filters, status, owner, unix_time) \
+                 VALUES ($1,$2,$3,$4,$5,$6,$7,$8) RETURNING id",
+                &[
+                    &channel_id, &audio_ext.as_str(), &variant, &transcode_options, &filters,
+                    &(WorkerStatus::Queued as i32), &owner, &(get_unix_time() as i64),
+                ],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn update_channel_job_entry(&self, entry: &ChannelJobRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "UPDATE channel_jobs SET status=$2, error=$3, total_listed=$4 WHERE id=$1",
+                &[&entry.id, &(entry.status as i32), &entry.error, &entry.total_listed.map(|v| v as i64)],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_channel_job_entries(&self) -> Result<Vec<ChannelJobRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                CHANNEL_JOB_TABLE.select().order_by(column!(CHANNEL_JOB_TABLE; id), Order::Descending).build(Dialect::Postgres).as_str(), &[],
+            )?;
+            let entries = rows.iter().map(map_channel_job_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_channel_job_entry(&self, id: i64) -> Result<Option<ChannelJobRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                CHANNEL_JOB_TABLE.select().filter_equal(&columns!(CHANNEL_JOB_TABLE; id)).build(Dialect::Postgres).as_str(), &[&id],
+            )?;
+            Ok(row.as_ref().map(map_channel_job_row_to_entry).transpose()?)
+        })
+    }
+
+    fn insert_channel_job_video_entry(&self, id: i64, video_id: &VideoId, title: Option<&str>) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO channel_job_videos (channel_job_id, video_id, title, unix_time) VALUES ($1,$2,$3,$4) \
+                 ON CONFLICT (channel_job_id, video_id) DO NOTHING",
+                &[&id, &video_id.as_str(), &title, &(get_unix_time() as i64)],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_channel_job_video_entries(
+        &self, id: i64, audio_ext: AudioExtension, variant: &str,
+    ) -> Result<Vec<ChannelJobVideoRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT videos.video_id, videos.title, ytdlp.status, ffmpeg.status FROM channel_job_videos AS videos \
+                 LEFT JOIN ytdlp ON ytdlp.video_id = videos.video_id \
+                 LEFT JOIN ffmpeg ON ffmpeg.video_id = videos.video_id AND ffmpeg.audio_ext=$2 AND ffmpeg.variant=$3 \
+                 WHERE videos.channel_job_id=$1 ORDER BY videos.unix_time, videos.video_id",
+                &[&id, &audio_ext.as_str(), &variant],
+            )?;
+            let mut entries = Vec::<ChannelJobVideoRow>::new();
+            for row in rows.iter() {
+                let video_id: String = row.try_get(0)?;
+                let download_status: Option<i32> = row.try_get(2)?;
+                let transcode_status: Option<i32> = row.try_get(3)?;
+                entries.push(ChannelJobVideoRow {
+                    video_id: VideoId::try_new(video_id.as_str()).expect("video_id should be valid"),
+                    title: row.try_get(1)?,
+                    download_status: download_status.and_then(WorkerStatus::from_i32).unwrap_or_default(),
+                    transcode_status: transcode_status.and_then(WorkerStatus::from_i32).unwrap_or_default(),
+                });
+            }
+            Ok(entries)
+        })
+    }
+
+    fn insert_maintenance_run_entry(&self, task: &str, is_manual: bool) -> Result<i64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "INSERT INTO maintenance_runs (task, is_manual, status, unix_time) VALUES ($1,$2,$3,$4) RETURNING id",
+                &[&task, &is_manual, &(WorkerStatus::Running as i32), &(get_unix_time() as i64)],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn update_maintenance_run_entry(&self, entry: &MaintenanceRunRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "UPDATE maintenance_runs SET status=$2, summary=$3, error=$4, end_unix_time=$5 WHERE id=$1",
+                &[&entry.id, &(entry.status as i32), &entry.summary, &entry.error, &entry.end_unix_time.map(|v| v as i64)],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_maintenance_run_entry(&self, id: i64) -> Result<Option<MaintenanceRunRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                MAINTENANCE_RUN_TABLE.select().filter_equal(&columns!(MAINTENANCE_RUN_TABLE; id)).build(Dialect::Postgres).as_str(), &[&id],
+            )?;
+            Ok(row.as_ref().map(map_maintenance_run_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_maintenance_run_entries(&self, limit: usize) -> Result<Vec<MaintenanceRunRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                MAINTENANCE_RUN_TABLE.select().order_by(column!(MAINTENANCE_RUN_TABLE; id), Order::Descending).limit().build(Dialect::Postgres).as_str(),
+                &[&(limit as i64)],
+            )?;
+            let entries = rows.iter().map(map_maintenance_run_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn insert_trash_entry(
+        &self, kind: JobKind, key: &str, owner: Option<i64>, entry: &str, files: &str, file_size_bytes: Option<u64>,
+    ) -> Result<i64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "INSERT INTO trash (kind, key, owner, entry, files, file_size_bytes, deleted_at) \
+                 VALUES ($1,$2,$3,$4,$5,$6,$7) RETURNING id",
+                &[
+                    &kind.as_str(), &key, &owner, &entry, &files, &file_size_bytes.map(|v| v as i64),
+                    &(get_unix_time() as i64),
+                ],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn delete_trash_entry(&self, id: i64) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute("DELETE FROM trash WHERE id=$1", &[&id])?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_trash_entry(&self, id: i64) -> Result<Option<TrashRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(TRASH_TABLE.select().filter_equal(&columns!(TRASH_TABLE; id)).build(Dialect::Postgres).as_str(), &[&id])?;
+            Ok(row.as_ref().map(map_trash_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_trash_entries(&self) -> Result<Vec<TrashRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(TRASH_TABLE.select().order_by(column!(TRASH_TABLE; id), Order::Descending).build(Dialect::Postgres).as_str(), &[])?;
+            let entries = rows.iter().map(map_trash_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn insert_job_entry(&self, entry: &JobRow) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO jobs (job_id, idempotency_key, video_id, audio_ext, variant, unix_time) \
+                 VALUES ($1,$2,$3,$4,$5,$6)",
+                &[
+                    &entry.job_id, &entry.idempotency_key, &entry.video_id.as_str(), &entry.audio_ext.as_str(),
+                    &entry.variant, &(entry.unix_time as i64),
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_job_entry(&self, job_id: &str) -> Result<Option<JobRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                JOB_TABLE.select().filter_equal(&columns!(JOB_TABLE; job_id)).build(Dialect::Postgres).as_str(), &[&job_id],
+            )?;
+            Ok(row.as_ref().map(map_job_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_job_entry_by_idempotency_key(&self, idempotency_key: &str) -> Result<Option<JobRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                JOB_TABLE.select().filter_equal(&columns!(JOB_TABLE; idempotency_key)).build(Dialect::Postgres).as_str(), &[&idempotency_key],
+            )?;
+            Ok(row.as_ref().map(map_job_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_latest_job_entry(
+        &self, video_id: &VideoId, audio_ext: AudioExtension, variant: &str,
+    ) -> Result<Option<JobRow>, JobStoreError> {
+        run_blocking(|| {
+            let query = JOB_TABLE.select()
+                .filter_equal(&columns!(JOB_TABLE; video_id, audio_ext, variant))
+                .order_by(column!(JOB_TABLE; unix_time), Order::Descending)
+                .limit();
+            let row = self.pool.get()?.query_opt(
+                query.build(Dialect::Postgres).as_str(), &[&video_id.as_str(), &audio_ext.as_str(), &variant, &1i64],
+            )?;
+            Ok(row.as_ref().map(map_job_row_to_entry).transpose()?)
+        })
+    }
+
+    fn insert_job_duration_entry(&self, kind: JobKind, elapsed_ms: u64) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO job_durations (kind, unix_time, elapsed_ms) VALUES ($1,$2,$3)",
+                &[&kind.as_str(), &(get_unix_time() as i64), &(elapsed_ms as i64)],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_average_job_duration(&self, kind: JobKind, limit: usize) -> Result<Option<u64>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "SELECT AVG(elapsed_ms)::DOUBLE PRECISION FROM (\
+                    SELECT elapsed_ms FROM job_durations WHERE kind=$1 ORDER BY unix_time DESC LIMIT $2\
+                 ) AS recent",
+                &[&kind.as_str(), &(limit as i64)],
+            )?;
+            let average: Option<f64> = row.try_get(0)?;
+            Ok(average.map(|average| average as u64))
+        })
+    }
+
+    fn insert_user_entry(&self, username: &str, password_hash: &str, is_admin: bool) -> Result<i64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "INSERT INTO users (username, password_hash, is_admin, unix_time) VALUES ($1,$2,$3,$4) RETURNING id",
+                &[&username, &password_hash, &is_admin, &(get_unix_time() as i64)],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn delete_user_entry(&self, id: i64) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let mut client = self.pool.get()?;
+            let mut tx = client.transaction()?;
+            tx.execute("DELETE FROM auth_tokens WHERE user_id=$1", &[&id])?;
+            let total = tx.execute("DELETE FROM users WHERE id=$1", &[&id])?;
+            tx.commit()?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_user_entries(&self) -> Result<Vec<UserRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(USER_TABLE.select().order_by(column!(USER_TABLE; id), Order::Ascending).build(Dialect::Postgres).as_str(), &[])?;
+            let entries = rows.iter().map(map_user_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_user_entry(&self, id: i64) -> Result<Option<UserRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(USER_TABLE.select().filter_equal(&columns!(USER_TABLE; id)).build(Dialect::Postgres).as_str(), &[&id])?;
+            Ok(row.as_ref().map(map_user_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_user_entry_by_username(&self, username: &str) -> Result<Option<UserRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                USER_TABLE.select().filter_equal(&columns!(USER_TABLE; username)).build(Dialect::Postgres).as_str(), &[&username],
+            )?;
+            Ok(row.as_ref().map(map_user_row_to_entry).transpose()?)
+        })
+    }
+
+    fn insert_auth_token_entry(
+        &self, token_hash: &str, user_id: i64, kind: AuthTokenKind, name: Option<&str>, expire_unix_time: Option<u64>,
+    ) -> Result<i64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "INSERT INTO auth_tokens (token_hash, user_id, kind, name, unix_time, expire_unix_time) \
+                 VALUES ($1,$2,$3,$4,$5,$6) RETURNING id",
+                &[
+                    &token_hash, &user_id, &kind.as_str(), &name,
+                    &(get_unix_time() as i64), &expire_unix_time.map(|v| v as i64),
+                ],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn delete_auth_token_entry(&self, id: i64) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute("DELETE FROM auth_tokens WHERE id=$1", &[&id])?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_auth_token_entry(&self, token_hash: &str) -> Result<Option<AuthTokenRow>, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_opt(
+                AUTH_TOKEN_TABLE.select().filter_equal(&columns!(AUTH_TOKEN_TABLE; token_hash)).build(Dialect::Postgres).as_str(), &[&token_hash],
+            )?;
+            Ok(row.as_ref().map(map_auth_token_row_to_entry).transpose()?)
+        })
+    }
+
+    fn select_auth_token_entries(&self, user_id: i64) -> Result<Vec<AuthTokenRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                AUTH_TOKEN_TABLE.select().filter_equal(&columns!(AUTH_TOKEN_TABLE; user_id)).order_by(column!(AUTH_TOKEN_TABLE; id), Order::Ascending).build(Dialect::Postgres).as_str(), &[&user_id],
+            )?;
+            let entries = rows.iter().map(map_auth_token_row_to_entry).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn insert_conversion_entry(&self, user_id: i64, kind: JobKind, key: &str) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO conversions (user_id, kind, key, unix_time) VALUES ($1,$2,$3,$4)",
+                &[&user_id, &kind.as_str(), &key, &(get_unix_time() as i64)],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_total_conversions(&self, user_id: i64, since_unix_time: u64) -> Result<u64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "SELECT COUNT(*) FROM conversions WHERE user_id=$1 AND unix_time>=$2",
+                &[&user_id, &(since_unix_time as i64)],
+            )?;
+            let total: i64 = row.try_get(0)?;
+            Ok(total as u64)
+        })
+    }
+
+    fn select_owner_storage_bytes(&self, owner: i64) -> Result<u64, JobStoreError> {
+        run_blocking(|| {
+            let row = self.pool.get()?.query_one(
+                "SELECT (\
+                    (SELECT COALESCE(SUM(file_size_bytes),0) FROM ytdlp WHERE owner=$1) + \
+                    (SELECT COALESCE(SUM(file_size_bytes),0) FROM ffmpeg WHERE owner=$1)\
+                 )::BIGINT",
+                &[&owner],
+            )?;
+            let total: i64 = row.try_get(0)?;
+            Ok(total as u64)
+        })
+    }
+
+    fn insert_job_history_entry(
+        &self, kind: JobKind, key: &str, status: WorkerStatus,
+        error_category: Option<&str>, file_size_bytes: Option<u64>, elapsed_ms: u64, request_id: Option<&str>,
+    ) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let total = self.pool.get()?.execute(
+                "INSERT INTO job_history (kind, key, status, error_category, file_size_bytes, elapsed_ms, unix_time, request_id) \
+                 VALUES ($1,$2,$3,$4,$5,$6,$7,$8)",
+                &[
+                    &kind.as_str(), &key, &(status as i32), &error_category, &file_size_bytes.map(|v| v as i64),
+                    &(elapsed_ms as i64), &(get_unix_time() as i64), &request_id,
+                ],
+            )?;
+            Ok(total as usize)
+        })
+    }
+
+    fn select_jobs_per_day(&self, since_unix_time: u64) -> Result<Vec<JobsPerDayRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT unix_time/86400*86400 AS day, kind, COUNT(*), SUM(CASE WHEN status=$2 THEN 1 ELSE 0 END) \
+                 FROM job_history WHERE unix_time>=$1 GROUP BY day, kind ORDER BY day, kind",
+                &[&(since_unix_time as i64), &(WorkerStatus::Failed as i32)],
+            )?;
+            let entries = rows.iter().map(|row| -> Result<JobsPerDayRow, postgres::Error> {
+                Ok(JobsPerDayRow {
+                    day_unix_time: row.try_get::<_, i64>(0)? as u64,
+                    kind: map_job_kind(row.try_get(1)?),
+                    total: row.try_get::<_, i64>(2)? as u64,
+                    total_failed: row.try_get::<_, i64>(3)? as u64,
+                })
+            }).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_job_failures(&self, since_unix_time: u64) -> Result<Vec<JobFailureRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT kind, COALESCE(error_category,'unknown') AS category, COUNT(*) AS total \
+                 FROM job_history WHERE unix_time>=$1 AND status=$2 GROUP BY kind, category ORDER BY total DESC",
+                &[&(since_unix_time as i64), &(WorkerStatus::Failed as i32)],
+            )?;
+            let entries = rows.iter().map(|row| -> Result<JobFailureRow, postgres::Error> {
+                Ok(JobFailureRow {
+                    kind: map_job_kind(row.try_get(0)?),
+                    error_category: row.try_get(1)?,
+                    total: row.try_get::<_, i64>(2)? as u64,
+                })
+            }).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_top_channels(&self, since_unix_time: u64, limit: usize) -> Result<Vec<ChannelCountRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT metadata.channel, COUNT(*) AS total FROM jobs \
+                 INNER JOIN metadata ON jobs.video_id=metadata.video_id \
+                 WHERE jobs.unix_time>=$1 GROUP BY metadata.channel ORDER BY total DESC LIMIT $2",
+                &[&(since_unix_time as i64), &(limit as i64)],
+            )?;
+            let entries = rows.iter().map(|row| -> Result<ChannelCountRow, postgres::Error> {
+                Ok(ChannelCountRow {
+                    channel: row.try_get(0)?,
+                    total: row.try_get::<_, i64>(1)? as u64,
+                })
+            }).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_table_usage(&self) -> Result<Vec<TableUsageRow>, JobStoreError> {
+        run_blocking(|| {
+            let mut client = self.pool.get()?;
+            let mut entries = Vec::new();
+            for table in [YTDLP_TABLE.name, FFMPEG_TABLE.name] {
+                let row = client.query_one(
+                    format!("SELECT COUNT(*), COALESCE(SUM(file_size_bytes),0)::BIGINT FROM {table}").as_str(), &[],
+                )?;
+                entries.push(TableUsageRow {
+                    table: table.to_owned(),
+                    total_entries: row.try_get::<_, i64>(0)? as u64,
+                    total_bytes: row.try_get::<_, i64>(1)? as u64,
+                });
+            }
+            Ok(entries)
+        })
+    }
+
+    fn select_job_throughputs(&self, since_unix_time: u64) -> Result<Vec<JobThroughputRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(
+                "SELECT kind, SUM(file_size_bytes)::BIGINT, SUM(elapsed_ms)::BIGINT FROM job_history \
+                 WHERE unix_time>=$1 AND status=$2 AND file_size_bytes IS NOT NULL GROUP BY kind ORDER BY kind",
+                &[&(since_unix_time as i64), &(WorkerStatus::Finished as i32)],
+            )?;
+            let entries = rows.iter().map(|row| -> Result<JobThroughputRow, postgres::Error> {
+                Ok(JobThroughputRow {
+                    kind: map_job_kind(row.try_get(0)?),
+                    total_bytes: row.try_get::<_, i64>(1)? as u64,
+                    total_elapsed_ms: row.try_get::<_, i64>(2)? as u64,
+                })
+            }).collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn select_schema_version(&self) -> Result<usize, JobStoreError> {
+        run_blocking(|| {
+            let version: Option<i32> = self.pool.get()?.query_one("SELECT MAX(version) FROM schema_version", &[])?.try_get(0)?;
+            Ok(version.unwrap_or(0) as usize)
+        })
+    }
+
+    fn select_table_rows(&self, table: &str) -> Result<Vec<TableRow>, JobStoreError> {
+        run_blocking(|| {
+            let rows = self.pool.get()?.query(format!("SELECT row_to_json(t)::TEXT FROM {table} AS t").as_str(), &[])?;
+            let mut entries = Vec::<TableRow>::new();
+            for row in rows.iter() {
+                let entry: String = row.try_get(0)?;
+                entries.push(serde_json::from_str(entry.as_str())?);
+            }
+            Ok(entries)
+        })
+    }
+
+    // NOTE: Postgres converts each json field to the type of its column which also accepts the 0/1 booleans from sqlite
+    //       Serial ids are then moved past the imported ids so new entries don't collide with them
+    fn replace_table_rows(&self, tables: &BTreeMap<String, Vec<TableRow>>) -> Result<usize, JobStoreError> {
+        const SERIAL_TABLES: &[&str] = &["subscriptions", "users", "auth_tokens", "channel_jobs", "maintenance_runs", "trash"];
+        run_blocking(|| {
+            let mut client = self.pool.get()?;
+            let mut tx = client.transaction()?;
+            let mut total_inserted = 0;
+            for &table in EXPORT_TABLES {
+                tx.execute(format!("DELETE FROM {table}").as_str(), &[])?;
+                for row in tables.get(table).into_iter().flatten() {
+                    let row = serde_json::to_string(row)?;
+                    total_inserted += tx.execute(
+                        format!("INSERT INTO {table} SELECT * FROM json_populate_record(NULL::{table}, $1::TEXT::json)").as_str(),
+                        &[&row],
+                    )? as usize;
+                }
+            }
+            for &table in SERIAL_TABLES {
+                tx.execute(
+                    format!("SELECT setval(pg_get_serial_sequence('{table}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {table}").as_str(),
+                    &[],
+                )?;
+            }
+            tx.commit()?;
+            Ok(total_inserted)
+        })
+    }
+}
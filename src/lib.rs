@@ -1,9 +1,62 @@
 pub mod app;
+pub mod archive;
+pub mod auth;
+pub mod backup;
+pub mod bandwidth;
+pub mod cache_eviction;
+pub mod channel;
+pub mod conditional;
+pub mod cors;
+pub mod daemon;
+pub mod data_files;
 pub mod database;
+pub mod disk_space;
+pub mod downloader;
+pub mod enrichment;
+pub mod error_code;
+pub mod estimate;
+#[cfg(feature = "fake_tools")]
+pub mod fake_tools;
+pub mod ffmetadata;
 pub mod ffmpeg;
+pub mod ffprobe;
+#[cfg(any(test, feature = "fake_tools"))]
+pub mod fixtures;
+pub mod headless;
+pub mod integrations;
+pub mod job_events;
+pub mod job_process;
+pub mod job_state;
+pub mod job_store;
+#[cfg(feature = "postgres")]
+pub mod job_store_postgres;
+pub mod listener;
+pub mod log_file;
+pub mod lru_cache;
+pub mod maintenance;
 pub mod metadata;
+pub mod notifications;
+#[cfg(test)]
+mod parser_corpus;
+pub mod process_priority;
+pub mod query;
+pub mod quota;
+pub mod request_id;
 pub mod routes;
+pub mod scheduler;
+pub mod storage;
+pub mod system_info;
+pub mod thread_budget;
+pub mod tls;
+pub mod transcoder;
+pub mod trash;
+pub mod url_parse;
 pub mod util;
+pub mod verify;
+pub mod waveform;
 pub mod worker_download;
+pub mod worker_hls;
+pub mod worker_queue;
 pub mod worker_transcode;
 pub mod ytdlp;
+pub mod ytdlp_auth;
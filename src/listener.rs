@@ -0,0 +1,93 @@
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+/// Socket that was opened for the server instead of binding to --url and --port
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+#[derive(Debug,Error)]
+pub enum ListenerError {
+    #[error("Invalid {name} passed by systemd: {value}")]
+    InvalidEnvironment { name: &'static str, value: String },
+    #[error("Failed to use socket passed by systemd: fd={fd}, err={error}")]
+    Socket { fd: i32, error: std::io::Error },
+    #[error("Failed to remove stale unix socket {path}: {error}")]
+    RemoveStaleSocket { path: PathBuf, error: std::io::Error },
+    #[error("Unix sockets are only supported on unix platforms")]
+    Unsupported,
+}
+
+// NOTE: systemd passes sockets starting after stdin, stdout and stderr
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Sockets passed by systemd socket activation, which is empty if the server wasn't socket activated
+#[cfg(unix)]
+pub fn take_systemd_listeners() -> Result<Vec<Listener>, ListenerError> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+    let Ok(listen_pid) = std::env::var("LISTEN_PID") else {
+        return Ok(Vec::new());
+    };
+    // NOTE: The variables are inherited by child processes so check they were meant for us
+    if listen_pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+    let listen_fds = std::env::var("LISTEN_FDS").unwrap_or_default();
+    let total_fds: i32 = listen_fds.parse().map_err(|_| ListenerError::InvalidEnvironment { name: "LISTEN_FDS", value: listen_fds })?;
+    // NOTE: Stop yt-dlp and ffmpeg from seeing the variables
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START+total_fds).map(|fd| {
+        let map_err = |error| ListenerError::Socket { fd, error };
+        // NOTE: systemd doesn't set close on exec so the sockets would be inherited by every process we spawn
+        // SAFETY: fcntl only changes the flags of the descriptor
+        let is_cloexec_set = unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+            flags >= 0 && libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) >= 0
+        };
+        if !is_cloexec_set {
+            return Err(map_err(std::io::Error::last_os_error()));
+        }
+        // SAFETY: systemd gives us ownership of these descriptors and the variables were removed so they are only taken once
+        let listener = unsafe { UnixListener::from_raw_fd(fd) };
+        // NOTE: Getting the address of a tcp socket as a unix socket fails which tells us which kind it is
+        if listener.local_addr().is_ok() {
+            listener.set_nonblocking(true).map_err(map_err)?;
+            return Ok(Listener::Unix(listener));
+        }
+        let listener = unsafe { TcpListener::from_raw_fd(listener.into_raw_fd()) };
+        listener.local_addr().map_err(map_err)?;
+        listener.set_nonblocking(true).map_err(map_err)?;
+        Ok(Listener::Tcp(listener))
+    }).collect()
+}
+
+#[cfg(not(unix))]
+pub fn take_systemd_listeners() -> Result<Vec<Listener>, ListenerError> {
+    Ok(Vec::new())
+}
+
+/// Removes the socket left behind by a previous run since binding to an existing path fails
+#[cfg(unix)]
+pub fn remove_stale_unix_socket(path: &Path) -> Result<(), ListenerError> {
+    use std::os::unix::fs::FileTypeExt;
+    match std::fs::symlink_metadata(path) {
+        // NOTE: Only sockets are removed so a mistyped path can't delete a regular file
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path).map_err(|error| ListenerError::RemoveStaleSocket { path: path.to_owned(), error })
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn remove_stale_unix_socket(_path: &Path) -> Result<(), ListenerError> {
+    Err(ListenerError::Unsupported)
+}
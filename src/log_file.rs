@@ -0,0 +1,96 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_MAX_LOG_BYTES: u64 = 16*1024*1024;
+pub const DEFAULT_TOTAL_JOB_LOG_BACKUPS: usize = 1;
+pub const DEFAULT_TOTAL_SERVER_LOG_BACKUPS: usize = 5;
+
+#[derive(Clone,Copy,Debug,Default)]
+pub struct LogLimits {
+    /// The log is rotated once writing to it would take it past this many bytes
+    pub max_bytes: Option<u64>,
+    /// Rotated logs are kept with .1, .2, ... suffixes where .1 is the newest, the log is truncated instead if this is 0
+    pub total_backups: usize,
+}
+
+pub fn get_log_backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{index}"));
+    PathBuf::from(path)
+}
+
+/// Rotated copies of a log from newest to oldest
+pub fn find_log_backups(path: &Path) -> Vec<PathBuf> {
+    (1..).map(|index| get_log_backup_path(path, index)).take_while(|path| path.exists()).collect()
+}
+
+/// Log file that is rotated or truncated once it reaches its size limit
+// NOTE: Verbose yt-dlp output on long livestreams would otherwise grow the log without bound
+pub struct RotatingLogFile {
+    path: PathBuf,
+    file: File,
+    total_bytes: u64,
+    limits: LogLimits,
+}
+
+impl RotatingLogFile {
+    /// Starts an empty log and removes the backups left over from a previous run of the job
+    pub fn create(path: &Path, limits: LogLimits) -> Result<Self, std::io::Error> {
+        for backup_path in find_log_backups(path) {
+            std::fs::remove_file(backup_path)?;
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            file: File::create(path)?,
+            total_bytes: 0,
+            limits,
+        })
+    }
+
+    /// Continues an existing log so restarting the server doesn't lose it
+    pub fn append(path: &Path, limits: LogLimits) -> Result<Self, std::io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let total_bytes = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            total_bytes,
+            limits,
+        })
+    }
+
+    fn rotate(&mut self) -> Result<(), std::io::Error> {
+        self.file.flush()?;
+        if self.limits.total_backups > 0 {
+            for index in (1..self.limits.total_backups).rev() {
+                let backup_path = get_log_backup_path(&self.path, index);
+                if backup_path.exists() {
+                    std::fs::rename(backup_path, get_log_backup_path(&self.path, index+1))?;
+                }
+            }
+            std::fs::rename(&self.path, get_log_backup_path(&self.path, 1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.total_bytes = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // NOTE: A single write larger than the limit is kept whole so lines aren't split across files
+        if let Some(max_bytes) = self.limits.max_bytes {
+            if self.total_bytes > 0 && self.total_bytes + buf.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        let total_written = self.file.write(buf)?;
+        self.total_bytes += total_written as u64;
+        Ok(total_written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct LruEntry<V> {
+    value: V,
+    insert_time: Instant,
+    last_used: u64,
+}
+
+struct LruState<K, V> {
+    entries: HashMap<K, LruEntry<V>>,
+    /// Keys ordered from least to most recently used
+    usage: BTreeMap<u64, K>,
+    next_use: u64,
+}
+
+/// Cache that evicts the least recently used entry once it is full and expires entries some time after they were inserted
+pub struct LruCache<K, V> {
+    state: Mutex<LruState<K, V>>,
+    max_entries: Option<usize>,
+    ttl: Option<Duration>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LruCache<K, V> {
+    /// Without a maximum or ttl the cache grows forever and keeps entries forever
+    pub fn new(max_entries: Option<usize>, ttl: Option<Duration>) -> Self {
+        Self {
+            state: Mutex::new(LruState { entries: HashMap::new(), usage: BTreeMap::new(), next_use: 0 }),
+            max_entries,
+            ttl,
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let entry = state.entries.get_mut(key)?;
+        if self.ttl.is_some_and(|ttl| entry.insert_time.elapsed() >= ttl) {
+            let last_used = entry.last_used;
+            state.entries.remove(key);
+            state.usage.remove(&last_used);
+            return None;
+        }
+        state.usage.remove(&entry.last_used);
+        entry.last_used = state.next_use;
+        state.usage.insert(state.next_use, key.clone());
+        state.next_use += 1;
+        Some(entry.value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut state = self.state.lock().unwrap();
+        let last_used = state.next_use;
+        state.next_use += 1;
+        let entry = LruEntry { value, insert_time: Instant::now(), last_used };
+        if let Some(old_entry) = state.entries.insert(key.clone(), entry) {
+            state.usage.remove(&old_entry.last_used);
+        }
+        state.usage.insert(last_used, key);
+        let Some(max_entries) = self.max_entries else { return; };
+        while state.entries.len() > max_entries {
+            let Some((_, key)) = state.usage.pop_first() else { break; };
+            state.entries.remove(&key);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.usage.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_sizes<K, V>(cache: &LruCache<K, V>) -> (usize, usize) {
+        let state = cache.state.lock().unwrap();
+        (state.entries.len(), state.usage.len())
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let cache = LruCache::new(Some(2), None);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.get(&"c"), Some(3));
+        // NOTE: Reading b makes c the least recently used
+        assert_eq!(cache.get(&"b"), Some(2));
+        cache.insert("d", 4);
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.get(&"d"), Some(4));
+        assert_eq!(get_sizes(&cache), (2, 2));
+    }
+
+    #[test]
+    fn reinserted_key_drops_old_usage() {
+        let cache = LruCache::new(Some(2), None);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+        assert_eq!(get_sizes(&cache), (2, 2));
+        // NOTE: The stale slot of a would evict it here if it was kept
+        cache.insert("c", 4);
+        assert_eq!(cache.get(&"a"), Some(3));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(4));
+        assert_eq!(get_sizes(&cache), (2, 2));
+    }
+
+    #[test]
+    fn expired_entries_are_removed() {
+        let cache = LruCache::new(None, Some(Duration::ZERO));
+        cache.insert("a", 1);
+        assert_eq!(get_sizes(&cache), (1, 1));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(get_sizes(&cache), (0, 0));
+        let cache = LruCache::new(None, Some(Duration::from_secs(3600)));
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+    }
+
+    #[test]
+    fn unbounded_cache_keeps_entries() {
+        let cache = LruCache::new(None, None);
+        for i in 0..1000 {
+            cache.insert(i, i*2);
+        }
+        assert_eq!(get_sizes(&cache), (1000, 1000));
+        assert_eq!(cache.get(&0), Some(0));
+        cache.clear();
+        assert_eq!(get_sizes(&cache), (0, 0));
+        assert_eq!(cache.get(&0), None);
+    }
+}
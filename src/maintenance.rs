@@ -0,0 +1,433 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use dashmap::{DashMap, mapref::entry::Entry};
+use serde::Serialize;
+use thiserror::Error;
+use crate::app::AppState;
+use crate::database::{EntryFilter, JobKind, WorkerStatus};
+use crate::generate_bidirectional_binding;
+use crate::job_store::JobStoreError;
+use crate::log_file::find_log_backups;
+use crate::metadata::fetch_metadata;
+use crate::trash::purge_trash_entry;
+use crate::util::{get_civil_date, get_unix_time};
+use crate::worker_download::{try_start_download_worker, DownloadState};
+use crate::worker_queue::JobId;
+use crate::worker_transcode::{TranscodeKey, TranscodeState};
+
+/// Tasks that are running along with the id of their run
+pub type RunningMaintenanceTasks = Arc<DashMap<MaintenanceTask, i64>>;
+
+pub const DEFAULT_PURGE_LOGS_DAYS: u64 = 30;
+pub const DEFAULT_STALE_METADATA_DAYS: u64 = 30;
+pub const DEFAULT_FAILED_JOB_TTL_HOURS: u64 = 24;
+/// Used when a ttl for failed jobs is given without a schedule so they expire close to their ttl
+pub const DEFAULT_EXPIRE_FAILED_JOBS_CRON: &str = "*/15 * * * *";
+// NOTE: Cron schedules have a resolution of a minute
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+// NOTE: Each refresh is a request to the YouTube api so a large library is refreshed over several runs
+const MAX_REFRESH_METADATA: usize = 200;
+
+#[derive(Debug,Error)]
+pub enum CronError {
+    #[error("Cron expression must have 5 fields (minute hour day month weekday): {0}")]
+    InvalidFieldCount(String),
+    #[error("Invalid {field} field in cron expression: {value}")]
+    InvalidField { field: &'static str, value: String },
+    #[error("Cron expression never matches a date: {0}")]
+    NeverMatches(String),
+}
+
+#[derive(Debug,Error)]
+pub enum MaintenanceError {
+    #[error("Maintenance task is already running: task={task}, run={run_id}")]
+    AlreadyRunning { task: MaintenanceTask, run_id: i64 },
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+/// Cron expression with the fields minute (0-59), hour (0-23), day (1-31), month (1-12) and weekday (0-7, sunday is 0 or 7)
+/// Each field is * or a list of values, ranges (a-b) and steps (*/n or a-b/n) which are evaluated in UTC
+#[derive(Clone,Debug)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    is_any_day: bool,
+    is_any_weekday: bool,
+}
+
+fn parse_cron_field(field: &'static str, value: &str, min: u64, max: u64) -> Result<u64, CronError> {
+    let invalid = || CronError::InvalidField { field, value: value.to_owned() };
+    let parse_value = |v: &str| v.parse::<u64>().map_err(|_| invalid());
+    let mut mask: u64 = 0;
+    for part in value.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_value(step)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+            // NOTE: A single value with a step such as 5/15 runs from that value until the end of the field
+            None if part.contains('/') => (parse_value(range)?, max),
+            None => (parse_value(range)?, parse_value(range)?),
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(CronError::InvalidFieldCount(expression.to_owned()));
+        };
+        let mut weekday_mask = parse_cron_field("weekday", weekdays, 0, 7)?;
+        if weekday_mask & (1 << 7) != 0 {
+            weekday_mask = (weekday_mask & !(1 << 7)) | 1;
+        }
+        let schedule = Self {
+            expression: fields.join(" "),
+            minutes: parse_cron_field("minute", minutes, 0, 59)?,
+            hours: parse_cron_field("hour", hours, 0, 23)?,
+            days: parse_cron_field("day", days, 1, 31)?,
+            months: parse_cron_field("month", months, 1, 12)?,
+            weekdays: weekday_mask,
+            is_any_day: days.starts_with('*'),
+            is_any_weekday: weekdays.starts_with('*'),
+        };
+        // NOTE: Dates such as 30 2 * would otherwise be searched for forever
+        if schedule.get_next_unix_time(0).is_none() {
+            return Err(CronError::NeverMatches(expression.to_owned()));
+        }
+        Ok(schedule)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.expression.as_str()
+    }
+
+    fn matches_day(&self, days: u64) -> bool {
+        let (_, month, day) = get_civil_date(days);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        // NOTE: The Unix epoch was a thursday
+        let weekday = (days + 4) % 7;
+        let is_day = self.days & (1 << day) != 0;
+        let is_weekday = self.weekdays & (1 << weekday) != 0;
+        // NOTE: Like cron a restricted day and weekday match if either of them matches
+        if !self.is_any_day && !self.is_any_weekday {
+            is_day || is_weekday
+        } else {
+            is_day && is_weekday
+        }
+    }
+
+    /// Start of the first minute after the time which matches the schedule
+    pub fn get_next_unix_time(&self, unix_time: u64) -> Option<u64> {
+        // NOTE: The 29th of february can be 8 years apart such as from 2096 to 2104
+        const MAX_SEARCH_DAYS: u64 = 8*366;
+        let start = (unix_time / 60 + 1) * 60;
+        let start_day = start / 86400;
+        for days in start_day..start_day+MAX_SEARCH_DAYS {
+            if !self.matches_day(days) {
+                continue;
+            }
+            let first_minute = if days == start_day { (start % 86400) / 60 } else { 0 };
+            for minute_of_day in first_minute..24*60 {
+                if self.hours & (1 << (minute_of_day / 60)) != 0 && self.minutes & (1 << (minute_of_day % 60)) != 0 {
+                    return Some(days*86400 + minute_of_day*60);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    RetryFailedDownloads,
+    PurgeOldLogs,
+    RefreshStaleMetadata,
+    PurgeTrash,
+    ExpireFailedJobs,
+}
+
+generate_bidirectional_binding!(
+    MaintenanceTask, &'static str, &str,
+    (RetryFailedDownloads, "retry_failed_downloads"),
+    (PurgeOldLogs, "purge_old_logs"),
+    (RefreshStaleMetadata, "refresh_stale_metadata"),
+    (PurgeTrash, "purge_trash"),
+    (ExpireFailedJobs, "expire_failed_jobs"),
+);
+
+impl MaintenanceTask {
+    pub const ALL: [Self; 5] = [
+        Self::RetryFailedDownloads, Self::PurgeOldLogs, Self::RefreshStaleMetadata, Self::PurgeTrash, Self::ExpireFailedJobs,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        (*self).into()
+    }
+}
+
+impl std::fmt::Display for MaintenanceTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Restarts failed downloads with the default download options and the format they were pinned to
+// NOTE: Stops once the queue is full so a large backlog of failures doesn't starve requests from users
+fn retry_failed_downloads(app: &AppState) -> Result<String, MaintenanceError> {
+    let entries = app.job_store.select_ytdlp_entries()?;
+    let entries: Vec<_> = entries.into_iter().filter(|entry| entry.status == WorkerStatus::Failed).collect();
+    let mut total_retried: usize = 0;
+    for entry in entries.iter() {
+        let is_queue_full = app.app_config.max_pending_jobs
+            .is_some_and(|max_pending_jobs| app.worker_thread_pool.total_pending() >= max_pending_jobs);
+        if is_queue_full {
+            break;
+        }
+        let mut download_options = app.app_config.download_options.clone();
+        if entry.format_id.is_some() {
+            download_options.format_id = entry.format_id.clone();
+        }
+        download_options.audio_lang = entry.audio_lang.clone();
+        let res = try_start_download_worker(
+            entry.video_id.clone(),
+            app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+            download_options, entry.owner,
+        );
+        match res {
+            Ok(_) => total_retried += 1,
+            Err(err) => log::error!("Failed to retry download: id={0}, err={1:?}", entry.video_id.as_str(), err),
+        }
+    }
+    Ok(format!("retried {total_retried} of {0} failed downloads", entries.len()))
+}
+
+/// Removes the file along with its rotated backups and returns whether it existed
+fn remove_log_file(path: &str) -> bool {
+    for backup_path in find_log_backups(Path::new(path)) {
+        if let Err(err) = std::fs::remove_file(&backup_path) {
+            log::warn!("Failed to remove log backup: path={0}, err={err:?}", backup_path.to_string_lossy());
+        }
+    }
+    match std::fs::remove_file(path) {
+        Ok(()) => true,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => false,
+        Err(err) => {
+            log::warn!("Failed to remove log: path={path}, err={err:?}");
+            false
+        },
+    }
+}
+
+/// Removes the logs of downloads and transcodes that were started before the cutoff and are no longer running
+fn purge_old_logs(app: &AppState) -> Result<String, MaintenanceError> {
+    let before_unix_time = get_unix_time().saturating_sub(app.app_config.purge_logs_days*24*60*60);
+    let mut total_removed: usize = 0;
+    for entry in app.job_store.select_ytdlp_entries()? {
+        let log_paths = [&entry.stdout_log_path, &entry.stderr_log_path, &entry.system_log_path];
+        if entry.status.is_busy() || entry.unix_time >= before_unix_time || log_paths.iter().all(|path| path.is_none()) {
+            continue;
+        }
+        total_removed += log_paths.iter().filter_map(|path| path.as_deref()).filter(|path| remove_log_file(path)).count();
+        app.job_store.select_and_update_ytdlp_entry(&entry.video_id, |entry| {
+            entry.stdout_log_path = None;
+            entry.stderr_log_path = None;
+            entry.system_log_path = None;
+        })?;
+    }
+    for entry in app.job_store.select_ffmpeg_entries()? {
+        let log_paths = [&entry.stdout_log_path, &entry.stderr_log_path, &entry.system_log_path];
+        if entry.status.is_busy() || entry.unix_time >= before_unix_time || log_paths.iter().all(|path| path.is_none()) {
+            continue;
+        }
+        total_removed += log_paths.iter().filter_map(|path| path.as_deref()).filter(|path| remove_log_file(path)).count();
+        app.job_store.select_and_update_ffmpeg_entry(&entry.video_id, entry.audio_ext, entry.variant.as_str(), |entry| {
+            entry.stdout_log_path = None;
+            entry.stderr_log_path = None;
+            entry.system_log_path = None;
+        })?;
+    }
+    Ok(format!("removed {total_removed} logs older than {0} days", app.app_config.purge_logs_days))
+}
+
+/// Forgets downloads and transcodes that failed before the cutoff along with their logs
+/// so requesting them again starts a fresh attempt instead of reporting the old failure
+fn expire_failed_jobs(app: &AppState) -> Result<String, MaintenanceError> {
+    let before_unix_time = get_unix_time().saturating_sub(app.app_config.failed_job_ttl_hours*60*60);
+    let mut total_expired: usize = 0;
+    let filter = EntryFilter { status: Some(WorkerStatus::Failed), before_unix_time: Some(before_unix_time), ..Default::default() };
+    // NOTE: Transcodes are expired first so downloads whose transcodes all failed can be expired too
+    for entry in app.job_store.select_filtered_ffmpeg_entries(&filter)? {
+        let key = TranscodeKey { video_id: entry.video_id.clone(), audio_ext: entry.audio_ext, variant: entry.variant.clone() };
+        let transcode_state = app.transcode_cache.entry(key.clone()).or_default();
+        let mut state = transcode_state.lock().unwrap();
+        if state.worker_status.is_busy() {
+            continue;
+        }
+        [&entry.stdout_log_path, &entry.stderr_log_path, &entry.system_log_path].into_iter().flatten().for_each(|path| {
+            remove_log_file(path);
+        });
+        total_expired += app.job_store.delete_ffmpeg_entry(&entry.video_id, entry.audio_ext, entry.variant.as_str())?;
+        *state = TranscodeState::default();
+        app.job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
+    }
+    for entry in app.job_store.select_filtered_ytdlp_entries(&filter)? {
+        // NOTE: Deleting the download would also delete its transcodes which still have their files
+        if !app.job_store.select_video_ffmpeg_entries(&entry.video_id)?.is_empty() {
+            continue;
+        }
+        // NOTE: The lock is held while deleting so a request can't restart the download in between
+        let download_state = app.download_cache.entry(entry.video_id.clone()).or_default();
+        let mut state = download_state.lock().unwrap();
+        if state.worker_status.is_busy() {
+            continue;
+        }
+        [&entry.stdout_log_path, &entry.stderr_log_path, &entry.system_log_path].into_iter().flatten().for_each(|path| {
+            remove_log_file(path);
+        });
+        total_expired += app.job_store.delete_ytdlp_entry(&entry.video_id)?;
+        *state = DownloadState::default();
+        app.job_events.publish(JobId::new(JobKind::Download, entry.video_id.as_str()));
+    }
+    // NOTE: Hls streams aren't stored in the database so their failures only live in the cache
+    for item in app.hls_cache.iter() {
+        let mut state = item.value().lock().unwrap();
+        if state.worker_status == WorkerStatus::Failed && state.end_time_unix < before_unix_time {
+            *state = TranscodeState::default();
+            app.job_events.publish(JobId::new(JobKind::Hls, item.key().as_str()));
+            total_expired += 1;
+        }
+    }
+    Ok(format!("expired {total_expired} jobs that failed more than {0} hours ago", app.app_config.failed_job_ttl_hours))
+}
+
+/// Fetches the metadata of videos again so changed titles and thumbnails are picked up
+async fn refresh_stale_metadata(app: &AppState) -> Result<String, MaintenanceError> {
+    let before_unix_time = get_unix_time().saturating_sub(app.app_config.stale_metadata_days*24*60*60);
+    let video_ids = app.job_store.select_stale_metadata_video_ids(before_unix_time, MAX_REFRESH_METADATA)?;
+    let mut total_refreshed: usize = 0;
+    for video_id in video_ids.iter() {
+        let res = fetch_metadata(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await;
+        match res {
+            Ok(_) => total_refreshed += 1,
+            Err(err) => log::warn!("Failed to refresh metadata: id={0}, err={1:?}", video_id.as_str(), err),
+        }
+    }
+    Ok(format!("refreshed {total_refreshed} of {0} stale metadata", video_ids.len()))
+}
+
+/// Removes downloads and transcodes for good once they have been in the trash for long enough
+async fn purge_trash(app: &AppState) -> Result<String, MaintenanceError> {
+    let before_unix_time = get_unix_time().saturating_sub(app.app_config.trash_days*24*60*60);
+    let entries: Vec<_> = app.job_store.select_trash_entries()?.into_iter().filter(|entry| entry.deleted_at < before_unix_time).collect();
+    let mut total_purged: usize = 0;
+    for entry in entries.iter() {
+        match purge_trash_entry(app, entry).await {
+            Ok(()) => total_purged += 1,
+            Err(err) => log::warn!("Failed to purge trash: id={0}, key={1}, err={err}", entry.id, entry.key),
+        }
+    }
+    Ok(format!("purged {total_purged} of {0} entries deleted more than {1} days ago", entries.len(), app.app_config.trash_days))
+}
+
+/// Returns whether the task succeeded
+async fn run_maintenance_task(app: AppState, task: MaintenanceTask, run_id: i64) -> bool {
+    log::info!("Starting maintenance task: task={task}, run={run_id}");
+    let res = match task {
+        MaintenanceTask::RetryFailedDownloads => retry_failed_downloads(&app),
+        MaintenanceTask::PurgeOldLogs => purge_old_logs(&app),
+        MaintenanceTask::RefreshStaleMetadata => refresh_stale_metadata(&app).await,
+        MaintenanceTask::PurgeTrash => purge_trash(&app).await,
+        MaintenanceTask::ExpireFailedJobs => expire_failed_jobs(&app),
+    };
+    match res {
+        Ok(ref summary) => log::info!("Finished maintenance task: task={task}, run={run_id}, summary={summary}"),
+        Err(ref err) => log::error!("Maintenance task failed: task={task}, run={run_id}, err={err:?}"),
+    }
+    let is_success = res.is_ok();
+    let res = app.job_store.select_and_update_maintenance_run_entry(run_id, |entry| {
+        match res {
+            Ok(summary) => {
+                entry.status = WorkerStatus::Finished;
+                entry.summary = Some(summary);
+            },
+            Err(err) => {
+                entry.status = WorkerStatus::Failed;
+                entry.error = Some(err.to_string());
+            },
+        }
+        entry.end_unix_time = Some(get_unix_time());
+    });
+    if let Err(err) = res {
+        log::error!("Failed to update maintenance run: task={task}, run={run_id}, err={err:?}");
+    }
+    app.running_maintenance_tasks.remove(&task);
+    is_success
+}
+
+/// Records a new run of the task unless it is already running
+fn insert_maintenance_run(app: &AppState, task: MaintenanceTask, is_manual: bool) -> Result<i64, MaintenanceError> {
+    match app.running_maintenance_tasks.entry(task) {
+        Entry::Occupied(entry) => Err(MaintenanceError::AlreadyRunning { task, run_id: *entry.get() }),
+        Entry::Vacant(entry) => {
+            let run_id = app.job_store.insert_maintenance_run_entry(task.as_str(), is_manual)?;
+            entry.insert(run_id);
+            Ok(run_id)
+        },
+    }
+}
+
+/// Records a new run of the task and starts it in the background, returning the id of the run
+pub fn try_start_maintenance_task(app: &AppState, task: MaintenanceTask, is_manual: bool) -> Result<i64, MaintenanceError> {
+    let run_id = insert_maintenance_run(app, task, is_manual)?;
+    actix_web::rt::spawn(run_maintenance_task(app.clone(), task, run_id));
+    Ok(run_id)
+}
+
+/// Records a new run of the task and waits for it to finish, returning whether it succeeded
+pub async fn run_maintenance_task_now(app: &AppState, task: MaintenanceTask) -> Result<bool, MaintenanceError> {
+    let run_id = insert_maintenance_run(app, task, true)?;
+    Ok(run_maintenance_task(app.clone(), task, run_id).await)
+}
+
+/// Starts maintenance tasks whenever their schedule matches
+// NOTE: Runs that were missed while the server was stopped are skipped instead of being caught up on
+pub async fn run_maintenance_scheduler(app: AppState) {
+    if app.app_config.maintenance_schedules.is_empty() {
+        return;
+    }
+    let mut last_check_unix_time = get_unix_time();
+    loop {
+        actix_web::rt::time::sleep(POLL_INTERVAL).await;
+        let unix_time = get_unix_time();
+        for (task, schedule) in app.app_config.maintenance_schedules.iter() {
+            let is_due = schedule.get_next_unix_time(last_check_unix_time).is_some_and(|next| next <= unix_time);
+            if !is_due {
+                continue;
+            }
+            if let Err(err) = try_start_maintenance_task(&app, *task, false) {
+                log::warn!("Skipped scheduled maintenance task: task={task}, err={err}");
+            }
+        }
+        last_check_unix_time = unix_time;
+    }
+}
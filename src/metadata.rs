@@ -1,9 +1,16 @@
 use std::{collections::HashMap, sync::Arc};
-use dashmap::DashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Serialize,Deserialize};
-use crate::database::VideoId;
+use crate::database::{VideoId, MetadataRow};
+use crate::job_store::SharedJobStore;
+use crate::lru_cache::LruCache;
 
-pub type MetadataCache = Arc<DashMap<VideoId, Arc<Metadata>>>;
+pub type MetadataCache = Arc<LruCache<VideoId, Arc<Metadata>>>;
+
+pub const DEFAULT_METADATA_CACHE_SIZE: usize = 10000;
+// NOTE: Expired metadata is fetched from the api again which also picks up renamed titles and channels
+pub const DEFAULT_METADATA_CACHE_TTL_SECONDS: u64 = 24*60*60;
 
 pub fn get_metadata_url(video_id: &str) -> String {
     const URL: &str = "https://www.googleapis.com/youtube/v3/videos";
@@ -12,6 +19,49 @@ pub fn get_metadata_url(video_id: &str) -> String {
     format!("{URL}?part={PARTS}&id={video_id}&key={API_KEY}")
 }
 
+/// Fetches metadata once and stores it so it can be searched and shown without the api
+pub async fn get_metadata_from_cache(
+    video_id: VideoId, cache: MetadataCache, client: &reqwest::Client, job_store: &SharedJobStore,
+) -> Result<Arc<Metadata>, Box<dyn std::error::Error>> {
+    if let Some(metadata) = cache.get(&video_id) {
+        return Ok(metadata);
+    }
+    fetch_metadata(video_id, cache, client, job_store).await
+}
+
+/// Fetches metadata from the api even if it is cached and replaces the stored copy
+pub async fn fetch_metadata(
+    video_id: VideoId, cache: MetadataCache, client: &reqwest::Client, job_store: &SharedJobStore,
+) -> Result<Arc<Metadata>, Box<dyn std::error::Error>> {
+    let metadata_url = get_metadata_url(video_id.as_str());
+    let response = client.get(metadata_url).send().await?;
+    let metadata = response.text().await?;
+    let metadata: Metadata = serde_json::from_str(metadata.as_str())?;
+    let metadata = Arc::new(metadata);
+    if let Some(item) = metadata.items.first() {
+        let snippet = &item.snippet;
+        let res = job_store.upsert_metadata_search_entry(
+            &video_id, snippet.title.as_str(), snippet.channel_title.as_str(), snippet.description.as_str(),
+            snippet.tags.join(" ").as_str(),
+        );
+        if let Err(err) = res {
+            log::warn!("Failed to index metadata for search: id={0}, err={1:?}", video_id.as_str(), err);
+        }
+        let res = job_store.upsert_metadata_entry(&MetadataRow {
+            video_id: video_id.clone(),
+            title: snippet.title.clone(),
+            channel: snippet.channel_title.clone(),
+            duration_ms: item.get_duration_milliseconds(),
+            thumbnail_url: item.get_largest_thumbnail().map(|thumbnail| thumbnail.url.clone()),
+        });
+        if let Err(err) = res {
+            log::warn!("Failed to store metadata: id={0}, err={1:?}", video_id.as_str(), err);
+        }
+    }
+    cache.insert(video_id, metadata.clone());
+    Ok(metadata)
+}
+
 #[derive(Clone,Debug,Deserialize,Serialize)]
 pub struct Thumbnail {
     pub url: String,
@@ -45,7 +95,16 @@ pub struct Snippet {
     pub tags: Vec<String>,
     #[serde(rename="categoryId")]
     pub category_id: String,
+    /// Either none, live or upcoming for scheduled livestreams and premieres
+    #[serde(rename="liveBroadcastContent", default)]
+    pub live_broadcast_content: String,
+}
 
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum LiveStatus {
+    None,
+    Live,
+    Upcoming,
 }
 
 #[derive(Clone,Debug,Deserialize,Serialize)]
@@ -58,6 +117,35 @@ pub struct Item {
     pub content_details: ContentDetails,
 }
 
+impl Item {
+    /// Converts the ISO 8601 duration (e.g. PT1H4M13S) into milliseconds
+    pub fn get_duration_milliseconds(&self) -> Option<u64> {
+        lazy_static! {
+            static ref DURATION_REGEX: Regex = Regex::new(
+                r"^P(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+)S)?)?$"
+            ).unwrap();
+        }
+        let captures = DURATION_REGEX.captures(self.content_details.duration.as_str())?;
+        let get_value = |index: usize| -> u64 {
+            captures.get(index).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0)
+        };
+        let seconds = get_value(1)*24*60*60 + get_value(2)*60*60 + get_value(3)*60 + get_value(4);
+        Some(seconds*1000)
+    }
+
+    pub fn get_live_status(&self) -> LiveStatus {
+        match self.snippet.live_broadcast_content.as_str() {
+            "live" => LiveStatus::Live,
+            "upcoming" => LiveStatus::Upcoming,
+            _ => LiveStatus::None,
+        }
+    }
+
+    pub fn get_largest_thumbnail(&self) -> Option<&Thumbnail> {
+        self.snippet.thumbnails.values().max_by_key(|thumbnail| thumbnail.width * thumbnail.height)
+    }
+}
+
 #[derive(Clone,Debug,Deserialize,Serialize)]
 pub struct PageInfo {
     #[serde(rename="totalResults")]
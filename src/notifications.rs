@@ -0,0 +1,230 @@
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+use thiserror::Error;
+use crate::database::{JobKind, VideoId, WorkerStatus};
+use crate::job_store::SharedJobStore;
+
+// NOTE: Notifications are sent in the background so a provider that hangs only holds up its own task
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+#[derive(Debug,Error)]
+pub enum NotificationError {
+    #[error("Failed to read notifications file: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("Failed to parse notifications file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Notification provider {provider} has an invalid url: {url}")]
+    InvalidUrl { provider: &'static str, url: String },
+    #[error("Failed to send notification: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    Finished,
+    Failed,
+}
+
+/// Service that pushes the notification to a phone
+#[derive(Clone,Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum NotificationProvider {
+    /// Posts to the /message endpoint of a gotify server with an application token
+    Gotify { url: String, token: String, priority: Option<i8> },
+    /// Posts to a ntfy topic url (e.g. https://ntfy.sh/my-topic) with an optional access token
+    Ntfy { url: String, token: Option<String>, priority: Option<i8> },
+    /// Posts to the pushover api with an application token and user key
+    Pushover { token: String, user: String, url: Option<String>, priority: Option<i8> },
+}
+
+// NOTE: The config is logged on startup so tokens are left out
+impl std::fmt::Debug for NotificationProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(self.name()).field("url", &self.get_url()).finish()
+    }
+}
+
+impl NotificationProvider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gotify { .. } => "gotify",
+            Self::Ntfy { .. } => "ntfy",
+            Self::Pushover { .. } => "pushover",
+        }
+    }
+
+    fn get_url(&self) -> &str {
+        match self {
+            Self::Gotify { url, .. } | Self::Ntfy { url, .. } => url.as_str(),
+            Self::Pushover { url, .. } => url.as_deref().unwrap_or(DEFAULT_PUSHOVER_URL),
+        }
+    }
+
+    fn validate(&self) -> Result<(), NotificationError> {
+        let url = self.get_url();
+        let is_valid_url = reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some());
+        if !is_valid_url {
+            return Err(NotificationError::InvalidUrl { provider: self.name(), url: url.to_owned() });
+        }
+        Ok(())
+    }
+
+    async fn send(&self, client: &reqwest::Client, notification: &Notification) -> Result<(), NotificationError> {
+        let title = notification.get_title();
+        let message = notification.get_message();
+        let request = match self {
+            Self::Gotify { url, token, priority } => {
+                let body = serde_json::json!({
+                    "title": title,
+                    "message": message,
+                    "priority": priority,
+                    "extras": { "client::notification": { "click": { "url": notification.link } } },
+                });
+                client.post(format!("{0}/message", url.trim_end_matches('/')))
+                    .header("X-Gotify-Key", token.as_str())
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.to_string())
+            },
+            Self::Ntfy { url, token, priority } => {
+                // NOTE: Titles are passed as query parameters since headers can't hold unicode
+                let mut query = vec![("title", title), ("click", notification.link.clone())];
+                if let Some(priority) = priority {
+                    query.push(("priority", priority.to_string()));
+                }
+                let mut request = client.post(url.as_str()).query(&query).body(message);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                request
+            },
+            Self::Pushover { token, user, priority, .. } => {
+                let mut form = vec![
+                    ("token", token.clone()), ("user", user.clone()),
+                    ("title", title), ("message", message), ("url", notification.link.clone()),
+                ];
+                if let Some(priority) = priority {
+                    form.push(("priority", priority.to_string()));
+                }
+                client.post(self.get_url()).form(&form)
+            },
+        };
+        request.timeout(NOTIFICATION_TIMEOUT).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Provider along with the jobs and events it is notified of
+#[derive(Clone,Debug,Deserialize)]
+pub struct NotificationTarget {
+    #[serde(flatten)]
+    pub provider: NotificationProvider,
+    /// Finished and failed jobs are sent when not given
+    pub events: Option<Vec<NotificationEvent>>,
+    /// Downloads and transcodes are sent when not given
+    pub jobs: Option<Vec<JobKind>>,
+}
+
+impl NotificationTarget {
+    fn is_subscribed(&self, job: JobKind, event: NotificationEvent) -> bool {
+        let is_event = self.events.as_ref().is_none_or(|events| events.contains(&event));
+        let is_job = match self.jobs {
+            Some(ref jobs) => jobs.contains(&job),
+            None => matches!(job, JobKind::Download | JobKind::Transcode),
+        };
+        is_event && is_job
+    }
+}
+
+/// Finished or failed job that providers are told about
+#[derive(Clone,Debug)]
+pub struct Notification {
+    pub job: JobKind,
+    pub key: String,
+    pub event: NotificationEvent,
+    pub title: Option<String>,
+    pub link: String,
+    pub fail_reason: Option<String>,
+}
+
+impl Notification {
+    fn get_title(&self) -> String {
+        let event = match self.event {
+            NotificationEvent::Finished => "finished",
+            NotificationEvent::Failed => "failed",
+        };
+        let mut job = self.job.as_str().to_owned();
+        job[..1].make_ascii_uppercase();
+        format!("{job} {event}: {0}", self.title.as_deref().unwrap_or(self.key.as_str()))
+    }
+
+    fn get_message(&self) -> String {
+        match self.fail_reason {
+            Some(ref reason) => format!("{0}\n{reason}\n{1}", self.key, self.link),
+            None => format!("{0}\n{1}", self.key, self.link),
+        }
+    }
+}
+
+/// Sends notifications to every provider subscribed to the job and event
+#[derive(Clone,Debug,Default)]
+pub struct Notifier {
+    targets: Vec<NotificationTarget>,
+    http_client: reqwest::Client,
+}
+
+/// Reads providers from a json list where each entry has a provider (gotify, ntfy or pushover) and its settings
+pub fn load_notifier(path: &Path) -> Result<Notifier, NotificationError> {
+    let data = std::fs::read_to_string(path)?;
+    let targets: Vec<NotificationTarget> = serde_json::from_str(data.as_str())?;
+    for target in targets.iter() {
+        target.provider.validate()?;
+    }
+    Ok(Notifier { targets, http_client: reqwest::Client::new() })
+}
+
+impl Notifier {
+    pub fn publish(&self, notification: Notification) {
+        let targets: Vec<NotificationTarget> = self.targets.iter()
+            .filter(|target| target.is_subscribed(notification.job, notification.event))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+        let http_client = self.http_client.clone();
+        tokio::spawn(async move {
+            for target in targets {
+                if let Err(err) = target.provider.send(&http_client, &notification).await {
+                    log::warn!("Failed to send notification: provider={0}, key={1}, err={err}", target.provider.name(), notification.key);
+                }
+            }
+        });
+    }
+
+    /// Publishes the outcome of a job along with the title of its video if the metadata was fetched
+    pub fn publish_job(
+        &self, job_store: &SharedJobStore, job: JobKind, key: &str, video_id: &VideoId,
+        status: WorkerStatus, fail_reason: Option<String>,
+    ) {
+        let event = match status {
+            WorkerStatus::Finished => NotificationEvent::Finished,
+            WorkerStatus::Failed => NotificationEvent::Failed,
+            _ => return,
+        };
+        if !self.targets.iter().any(|target| target.is_subscribed(job, event)) {
+            return;
+        }
+        let title = job_store.select_metadata_entry(video_id).ok().flatten().map(|entry| entry.title);
+        self.publish(Notification {
+            job,
+            key: key.to_owned(),
+            event,
+            title,
+            link: format!("https://www.youtube.com/watch?v={0}", video_id.as_str()),
+            fail_reason,
+        });
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt::Display;
+use proptest::prelude::*;
+use crate::downloader::{ParsedStderrLine as YtdlpStderrLine, ParsedStdoutLine as YtdlpStdoutLine};
+use crate::ffmpeg::{self, ParsedStderrLine as FfmpegStderrLine, TranscodeProgress};
+use crate::ytdlp;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum OutputParser {
+    FfmpegStderr,
+    FfmpegProgress,
+    YtdlpStdout,
+    YtdlpStderr,
+}
+
+impl OutputParser {
+    pub const ALL: [Self; 4] = [Self::FfmpegStderr, Self::FfmpegProgress, Self::YtdlpStdout, Self::YtdlpStderr];
+
+    /// Short description of what the line was parsed as which is compared against the expected one
+    pub fn summarize(self, line: &str) -> String {
+        match self {
+            Self::FfmpegStderr => summarize_ffmpeg_stderr(ffmpeg::parse_stderr_line(line)),
+            // NOTE: Samples hold a whole record with a line for each key
+            Self::FfmpegProgress => {
+                let mut parser = ffmpeg::ProgressRecordParser::default();
+                let progress = line.lines().filter_map(|line| parser.parse_line(line)).last();
+                progress.map(summarize_ffmpeg_progress).unwrap_or("none".to_owned())
+            },
+            Self::YtdlpStdout => summarize_ytdlp_stdout(ytdlp::parse_stdout_line(line)),
+            Self::YtdlpStderr => summarize_ytdlp_stderr(ytdlp::parse_stderr_line(line)),
+        }
+    }
+}
+
+/// Output line recorded from a release of ffmpeg or yt-dlp along with what it should be parsed as
+#[derive(Clone,Copy,Debug)]
+pub struct Sample {
+    pub parser: OutputParser,
+    pub source: &'static str,
+    pub line: &'static str,
+    pub expected: &'static str,
+}
+
+const fn sample(parser: OutputParser, source: &'static str, line: &'static str, expected: &'static str) -> Sample {
+    Sample { parser, source, line, expected }
+}
+
+fn format_value<T: Display>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+fn summarize_ffmpeg_progress(progress: TranscodeProgress) -> String {
+    format!(
+        "progress time={0} size={1} bitrate={2} speed={3}",
+        format_value(progress.total_time_transcoded.map(|time| time.to_milliseconds())),
+        format_value(progress.size_bytes),
+        format_value(progress.speed_bits),
+        format_value(progress.speed_factor),
+    )
+}
+
+fn summarize_ffmpeg_stderr(line: Option<FfmpegStderrLine>) -> String {
+    match line {
+        None => "none".to_owned(),
+        Some(FfmpegStderrLine::TranscodeProgress(progress)) => summarize_ffmpeg_progress(progress),
+        Some(FfmpegStderrLine::TranscodeSourceInfo(info)) => format!(
+            "source duration={0} start={1} bitrate={2}",
+            format_value(info.duration.map(|time| time.to_milliseconds())),
+            format_value(info.start_time.map(|time| time.to_milliseconds())),
+            format_value(info.speed_bits),
+        ),
+        Some(FfmpegStderrLine::Failure(err)) => format!("failure {0}", err.code()),
+    }
+}
+
+fn summarize_ytdlp_stdout(line: Option<YtdlpStdoutLine>) -> String {
+    match line {
+        None => "none".to_owned(),
+        Some(YtdlpStdoutLine::DownloadProgress(progress)) => format!(
+            "progress downloaded={0} total={1} eta={2} elapsed={3} speed={4}",
+            format_value(progress.downloaded_bytes),
+            format_value(progress.total_bytes),
+            format_value(progress.eta_seconds),
+            format_value(progress.elapsed_seconds),
+            format_value(progress.speed_bytes),
+        ),
+        Some(YtdlpStdoutLine::OutputPath(path)) => format!("output {path}"),
+        Some(YtdlpStdoutLine::DownloadPath(path)) => format!("download {path}"),
+        Some(YtdlpStdoutLine::DurationMilliseconds(duration)) => format!("duration {duration}"),
+        Some(YtdlpStdoutLine::FormatId(format_id)) => format!("format {format_id}"),
+    }
+}
+
+fn summarize_ytdlp_stderr(line: Option<YtdlpStderrLine>) -> String {
+    match line {
+        None => "none".to_owned(),
+        Some(YtdlpStderrLine::UsageError(message)) => format!("usage {message}"),
+        Some(YtdlpStderrLine::MissingVideo(video_id)) => format!("missing {video_id}"),
+        Some(YtdlpStderrLine::ExtractPath(path)) => format!("extract {path}"),
+        Some(YtdlpStderrLine::Failure(code)) => format!("failure {code}"),
+    }
+}
+
+use OutputParser::{FfmpegStderr, FfmpegProgress, YtdlpStdout, YtdlpStderr};
+
+pub const SAMPLES: &[Sample] = &[
+    // ffmpeg 4
+    sample(FfmpegStderr, "ffmpeg 4.4", "  Duration: 00:03:32.57, start: 0.000000, bitrate: 131 kb/s", "source duration=212570 start=0 bitrate=131000"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "  Duration: N/A, start: 0.000000, bitrate: N/A", "source duration=- start=0 bitrate=-"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "size=    1024kB time=00:01:05.43 bitrate= 128.2kbits/s speed=30.5x", "progress time=65430 size=1024000 bitrate=128200 speed=30.5"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "size=    3365kB time=00:03:32.57 bitrate= 129.7kbits/s speed=  42x", "progress time=212570 size=3365000 bitrate=129700 speed=42"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "frame=    1 fps=0.0 q=-0.0 size=     256kB time=00:00:05.48 bitrate= 382.6kbits/s speed=10.9x", "progress time=5480 size=256000 bitrate=382600 speed=10.9"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "size=N/A time=00:03:32.56 bitrate=N/A speed= 635x", "progress time=212560 size=- bitrate=- speed=635"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "video:0kB audio:3325kB subtitle:0kB other streams:0kB global headers:0kB muxing overhead: 0.011%", "none"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "pipe:0: Invalid data found when processing input", "failure invalid_source"),
+    sample(FfmpegStderr, "ffmpeg 4.4", "[mov,mp4,m4a,3gp,3g2,mj2 @ 0x55d5c0c0a840] moov atom not found", "failure invalid_source"),
+    // ffmpeg 5
+    sample(FfmpegStderr, "ffmpeg 5.1", "  Duration: 00:03:32.58, start: -0.007000, bitrate: 135 kb/s", "source duration=212580 start=- bitrate=135000"),
+    sample(FfmpegStderr, "ffmpeg 5.1", "size=       0kB time=-577014:32:22.77 bitrate=  -0.0kbits/s speed=N/A", "progress time=- size=0 bitrate=- speed=-"),
+    sample(FfmpegStderr, "ffmpeg 5.1", "size=     768kB time=00:00:49.13 bitrate= 128.0kbits/s speed=24.5x", "progress time=49130 size=768000 bitrate=128000 speed=24.5"),
+    sample(FfmpegStderr, "ffmpeg 5.1", "Unknown encoder 'libfdk_aac'", "failure unsupported_codec"),
+    sample(FfmpegStderr, "ffmpeg 5.1", "av_interleaved_write_frame(): No space left on device", "failure disk_full"),
+    // ffmpeg 6
+    sample(FfmpegStderr, "ffmpeg 6.0", "size=     512kB time=00:00:32.70 bitrate= 128.2kbits/s speed=65.4x", "progress time=32700 size=512000 bitrate=128200 speed=65.4"),
+    sample(FfmpegStderr, "ffmpeg 6.0", "size=    3325kB time=00:03:32.56 bitrate= 128.1kbits/s speed=  61x", "progress time=212560 size=3325000 bitrate=128100 speed=61"),
+    sample(FfmpegStderr, "ffmpeg 6.0", "[https @ 0x5581c3a0] HTTP error 404 Not Found", "failure network_error"),
+    sample(FfmpegStderr, "ffmpeg 6.1", "size=     768KiB time=00:00:49.13 bitrate= 128.0kbits/s speed=24.5x", "progress time=49130 size=786432 bitrate=128000 speed=24.5"),
+    sample(FfmpegStderr, "ffmpeg 6.1", "size=N/A time=00:03:32.56 bitrate=N/A speed=2.47e+03x", "progress time=212560 size=- bitrate=- speed=2470"),
+    sample(FfmpegStderr, "ffmpeg 6.1", "[out#0/mp3 @ 0x55d5c0c0a840] video:0KiB audio:3325KiB subtitle:0KiB other streams:0KiB global headers:0KiB muxing overhead: 0.011%", "none"),
+    sample(FfmpegStderr, "ffmpeg 6.1", "[out#0/mp3 @ 0x55d5c0c0a840] Error opening output ./data/transcode/dQw4w9WgXcQ.mp3: Permission denied", "failure permission_denied"),
+    // ffmpeg 7
+    sample(FfmpegStderr, "ffmpeg 7.0", "size=    1280KiB time=00:01:21.75 bitrate= 128.3kbits/s speed=40.9x elapsed=0:00:02.00", "progress time=81750 size=1310720 bitrate=128300 speed=40.9"),
+    sample(FfmpegStderr, "ffmpeg 7.0", "size=N/A time=N/A bitrate=N/A speed=N/A elapsed=0:00:00.50", "progress time=- size=- bitrate=- speed=-"),
+    sample(FfmpegStderr, "ffmpeg 7.0", "size=       0KiB time=-00:00:00.02 bitrate=N/A speed=-2.5e-05x elapsed=0:00:00.50", "progress time=- size=0 bitrate=- speed=-"),
+    sample(FfmpegStderr, "ffmpeg 7.1", "frame=    1 fps=0.0 q=-1.0 size=     512KiB time=00:00:31.06 bitrate= 135.0kbits/s speed=62.1x elapsed=0:00:00.50", "progress time=31060 size=524288 bitrate=135000 speed=62.1"),
+    sample(FfmpegStderr, "ffmpeg 7.1", "  Duration: 00:03:32.57, start: 0.000000, bitrate: 1.2 Mb/s", "source duration=212570 start=0 bitrate=1200000"),
+    // NOTE: Recorded from a build using the de_DE locale
+    sample(FfmpegStderr, "ffmpeg 7.1", "size=    1024KiB time=00:01:05,43 bitrate= 128,2kbits/s speed=30,5x elapsed=0:00:02,14", "progress time=65430 size=1048576 bitrate=128200 speed=30.5"),
+    sample(FfmpegStderr, "ffmpeg 7.1", "[aost#0:0/libmp3lame @ 0x55d5c0c0a840] Specified sample rate 96000 is not supported", "failure unsupported_codec"),
+    // ffmpeg records from "-progress -"
+    sample(FfmpegProgress, "ffmpeg 4.4", "bitrate= 128.0kbits/s\ntotal_size=786432\nout_time_us=49130000\nout_time_ms=49130000\nout_time=00:00:49.130000\ndup_frames=0\ndrop_frames=0\nspeed=24.5x\nprogress=continue", "progress time=49130 size=786432 bitrate=128000 speed=24.5"),
+    sample(FfmpegProgress, "ffmpeg 4.4", "bitrate=N/A\ntotal_size=N/A\nout_time_us=-9223372036854775807\nout_time_ms=-9223372036854775807\nout_time=-2562047:47:16.854775\ndup_frames=0\ndrop_frames=0\nspeed=N/A\nprogress=continue", "progress time=- size=- bitrate=- speed=-"),
+    sample(FfmpegProgress, "ffmpeg 6.0", "frame=1\nfps=0.00\nstream_0_0_q=-1.0\nbitrate= 135.0kbits/s\ntotal_size=3670016\nout_time_us=212567000\nout_time_ms=212567000\nout_time=00:03:32.567000\ndup_frames=0\ndrop_frames=0\nspeed=61.2x\nprogress=end", "progress time=212567 size=3670016 bitrate=135000 speed=61.2"),
+    sample(FfmpegProgress, "ffmpeg 7.1", "bitrate=N/A\ntotal_size=N/A\nout_time_us=N/A\nout_time_ms=N/A\nout_time=N/A\ndup_frames=0\ndrop_frames=0\nspeed=N/A\nprogress=continue", "progress time=- size=- bitrate=- speed=-"),
+    sample(FfmpegProgress, "ffmpeg 7.1", "bitrate=2048.3kbits/s\ntotal_size=1048576\nout_time_us=4096000\nout_time_ms=4096000\nout_time=00:00:04.096000\ndup_frames=0\ndrop_frames=0\nspeed=2.47e+03x\nprogress=continue", "progress time=4096 size=1048576 bitrate=2048300 speed=2470"),
+    sample(FfmpegProgress, "ffmpeg 7.1", "out_time_us=4096000\nspeed=2.47e+03x", "none"),
+    // yt-dlp progress from the template in get_ytdlp_arguments()
+    sample(YtdlpStdout, "yt-dlp 2023.12.30", "@[progress] eta=3,elapsed=1,downloaded_bytes=1048576,total_bytes=3456789,speed=524288", "progress downloaded=1048576 total=3456789 eta=3 elapsed=1 speed=524288"),
+    sample(YtdlpStdout, "yt-dlp 2023.12.30", "@[progress] eta=NA,elapsed=0,downloaded_bytes=1024,total_bytes=NA,speed=NA", "progress downloaded=1024 total=- eta=- elapsed=0 speed=-"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[progress] eta=0,elapsed=4,downloaded_bytes=3456789,total_bytes=3456789,speed=NA", "progress downloaded=3456789 total=3456789 eta=0 elapsed=4 speed=-"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[progress] eta=NA,elapsed=NA,downloaded_bytes=NA,total_bytes=NA,speed=NA", "progress downloaded=- total=- eta=- elapsed=- speed=-"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[before-dl-path] ./data/staging/downloads/dQw4w9WgXcQ.webm", "download ./data/staging/downloads/dQw4w9WgXcQ.webm"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[after-move-path] ./data/staging/downloads/dQw4w9WgXcQ.opus", "output ./data/staging/downloads/dQw4w9WgXcQ.opus"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[after-move-path] ./data/staging/downloads/Rick Astley - Never Gonna Give You Up [dQw4w9WgXcQ].opus", "output ./data/staging/downloads/Rick Astley - Never Gonna Give You Up [dQw4w9WgXcQ].opus"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[duration] 212.0", "duration 212000"),
+    sample(YtdlpStdout, "yt-dlp 2023.12.30", "@[duration] 212", "duration 212000"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[duration] NA", "none"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[format-id] 251", "format 251"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "@[format-id] 140-drc", "format 140-drc"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "[download]  45.2% of    3.30MiB at    1.20MiB/s ETA 00:01", "none"),
+    sample(YtdlpStdout, "yt-dlp 2024.08.06", "[youtube] Extracting URL: https://www.youtube.com/watch?v=dQw4w9WgXcQ", "none"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "[ExtractAudio] Destination: ./data/staging/downloads/dQw4w9WgXcQ.mp3", "extract ./data/staging/downloads/dQw4w9WgXcQ.mp3"),
+    sample(YtdlpStderr, "yt-dlp 2023.12.30", "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable", "missing dQw4w9WgXcQ"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader", "missing dQw4w9WgXcQ"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video", "failure private_video"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm your age. This video may be inappropriate for some users.", "failure age_restricted"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm you’re not a bot. Use --cookies-from-browser or --cookies for the authentication.", "failure login_required"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "ERROR: [youtube] dQw4w9WgXcQ: This live event will begin in 3 hours.", "failure upcoming_stream"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "ERROR: [youtube] dQw4w9WgXcQ: Requested format is not available. Use --list-formats for a list of available formats", "failure invalid_request"),
+    sample(YtdlpStderr, "yt-dlp 2023.12.30", "ERROR: unable to download video data: HTTP Error 429: Too Many Requests", "failure rate_limited"),
+    sample(YtdlpStderr, "yt-dlp 2023.12.30", "ERROR: [Errno 28] No space left on device", "failure disk_full"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "WARNING: [youtube] Unable to download webpage: HTTP Error 429: Too Many Requests", "none"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "[debug] yt-dlp version stable@2024.08.06 from yt-dlp/yt-dlp [4d9231208] (pip)", "none"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "yt-dlp: error: no such option: --bogus", "usage no such option: --bogus"),
+    sample(YtdlpStderr, "yt-dlp 2024.08.06", "yt-dlp.exe: error: no such option: --bogus", "usage no such option: --bogus"),
+];
+
+fn format_ffmpeg_time(milliseconds: u64) -> String {
+    let seconds = milliseconds / 1000;
+    format!("{0:02}:{1:02}:{2:02}.{3:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60, (milliseconds / 10) % 100)
+}
+
+fn format_not_available<T: Display>(value: Option<T>, not_available: &str) -> String {
+    value.map(|value| value.to_string()).unwrap_or(not_available.to_owned())
+}
+
+/// Value of speed= in the formats printed by each version along with what it should be parsed as
+fn speed_strategy() -> impl Strategy<Value = (String, Option<f32>)> {
+    prop_oneof![
+        Just(("N/A".to_owned(), None)),
+        (1..10_000u64).prop_map(|tenths| (format!("{0:>4}.{1}x", tenths / 10, tenths % 10), Some((tenths as f64 / 10.0) as f32))),
+        // NOTE: ffmpeg 6.1 switched to exponents for large speeds
+        (100..1000u64, 2..7i32).prop_map(|(mantissa, exponent)| {
+            let speed = format!("{0}.{1:02}e+{exponent:02}x", mantissa / 100, mantissa % 100);
+            (speed, Some((mantissa as f64 * 10f64.powi(exponent-2)) as f32))
+        }),
+        (1..10_000u64).prop_map(|tenths| (format!("-{0}.{1}e-05x", tenths / 10, tenths % 10), None)),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn ffmpeg_progress_line_is_parsed_back(
+        time_ms in prop::option::of((0..10*60*60*100u64).prop_map(|centiseconds| centiseconds*10)),
+        is_negative_time in any::<bool>(),
+        size in prop::option::of((0..10_000_000u64, any::<bool>())),
+        bitrate_tenths in prop::option::of(0..100_000u64),
+        is_negative_bitrate in any::<bool>(),
+        (speed, speed_factor) in speed_strategy(),
+        is_decimal_comma in any::<bool>(),
+        is_frame_prefix in any::<bool>(),
+        is_elapsed_suffix in any::<bool>(),
+    ) {
+        let time = match time_ms {
+            Some(time_ms) if is_negative_time => format!("-{0}", format_ffmpeg_time(time_ms)),
+            Some(time_ms) => format_ffmpeg_time(time_ms),
+            None => "N/A".to_owned(),
+        };
+        let (size, size_bytes) = match size {
+            Some((size, true)) => (format!("{size:>8}KiB"), Some(size*1024)),
+            Some((size, false)) => (format!("{size:>8}kB"), Some(size*1000)),
+            None => ("N/A".to_owned(), None),
+        };
+        let bitrate = match bitrate_tenths {
+            Some(tenths) if is_negative_bitrate => format!("-{0}.{1}kbits/s", tenths / 10, tenths % 10),
+            Some(tenths) => format!("{0:>4}.{1}kbits/s", tenths / 10, tenths % 10),
+            None => "N/A".to_owned(),
+        };
+        let prefix = if is_frame_prefix { "frame=    1 fps=0.0 q=-1.0 " } else { "" };
+        let suffix = if is_elapsed_suffix { " elapsed=0:00:01.50" } else { "" };
+        let mut line = format!("{prefix}size={size} time={time} bitrate={bitrate} speed={speed}{suffix}");
+        // NOTE: Builds using a locale such as de_DE print a comma in every decimal
+        if is_decimal_comma {
+            line = line.replace('.', ",");
+        }
+        let expected = format!(
+            "progress time={0} size={1} bitrate={2} speed={3}",
+            format_value(time_ms.filter(|_| !is_negative_time)),
+            format_value(size_bytes),
+            format_value(bitrate_tenths.filter(|_| !is_negative_bitrate).map(|tenths| tenths*100)),
+            format_value(speed_factor),
+        );
+        prop_assert_eq!(FfmpegStderr.summarize(line.as_str()), expected, "line={:?}", line);
+    }
+
+    #[test]
+    fn ffmpeg_progress_record_is_parsed_back(
+        time_us in prop::option::of((0..10*60*60*1000u64).prop_map(|milliseconds| milliseconds*1000)),
+        size_bytes in prop::option::of(0..u32::MAX as u64),
+        bitrate_tenths in prop::option::of(0..100_000u64),
+        (speed, speed_factor) in speed_strategy(),
+    ) {
+        let record = [
+            format!("bitrate={0}", format_not_available(bitrate_tenths.map(|v| format!("{0:>4}.{1}kbits/s", v / 10, v % 10)), "N/A")),
+            format!("total_size={0}", format_not_available(size_bytes, "N/A")),
+            format!("out_time_us={0}", format_not_available(time_us, "N/A")),
+            format!("out_time_ms={0}", format_not_available(time_us, "N/A")),
+            format!("out_time={0}", format_not_available(time_us.map(|v| format_ffmpeg_time(v / 1000)), "N/A")),
+            "dup_frames=0".to_owned(),
+            "drop_frames=0".to_owned(),
+            format!("speed={speed}"),
+            "progress=continue".to_owned(),
+        ].join("\n");
+        let expected = format!(
+            "progress time={0} size={1} bitrate={2} speed={3}",
+            format_value(time_us.map(|v| v / 1000)),
+            format_value(size_bytes),
+            format_value(bitrate_tenths.map(|v| v*100)),
+            format_value(speed_factor),
+        );
+        prop_assert_eq!(FfmpegProgress.summarize(record.as_str()), expected, "record={:?}", record);
+    }
+
+    #[test]
+    fn ytdlp_progress_is_parsed_back(values in prop::array::uniform5(prop::option::of(0..u32::MAX as u64))) {
+        let [eta, elapsed, downloaded, total, speed] = values;
+        let line = format!(
+            "@[progress] eta={0},elapsed={1},downloaded_bytes={2},total_bytes={3},speed={4}",
+            format_not_available(eta, "NA"), format_not_available(elapsed, "NA"),
+            format_not_available(downloaded, "NA"), format_not_available(total, "NA"), format_not_available(speed, "NA"),
+        );
+        let expected = format!(
+            "progress downloaded={0} total={1} eta={2} elapsed={3} speed={4}",
+            format_value(downloaded), format_value(total), format_value(eta), format_value(elapsed), format_value(speed),
+        );
+        prop_assert_eq!(YtdlpStdout.summarize(line.as_str()), expected, "line={:?}", line);
+    }
+
+    #[test]
+    fn arbitrary_lines_dont_panic(line in "\\PC{0,200}") {
+        for parser in OutputParser::ALL {
+            parser.summarize(line.as_str());
+        }
+    }
+
+    /// Samples that are truncated or have fragments from the output of the binaries inserted into them
+    #[test]
+    fn mangled_samples_dont_panic(
+        sample in prop::sample::select(SAMPLES),
+        edits in prop::collection::vec((any::<prop::sample::Index>(), prop::option::of(prop::sample::select(MUTATIONS))), 1..5),
+    ) {
+        let mut chars: Vec<char> = sample.line.chars().collect();
+        for (index, mutation) in edits {
+            let index = index.index(chars.len()+1);
+            match mutation {
+                Some(mutation) => { chars.splice(index..index, mutation.chars()); },
+                None => chars.truncate(index),
+            }
+        }
+        let line: String = chars.into_iter().collect();
+        for parser in OutputParser::ALL {
+            parser.summarize(line.as_str());
+        }
+    }
+}
+
+const MUTATIONS: &[&str] = &["", "-", ":", "=", ",", ".", "N/A", "NA", "x", "e+", "9999999999999999999999", "é", "\t", " "];
+
+#[test]
+fn samples_are_parsed_as_recorded() {
+    let failures: Vec<String> = SAMPLES.iter()
+        .filter_map(|sample| {
+            let given = sample.parser.summarize(sample.line);
+            (given != sample.expected).then(|| format!(
+                "{0:?} ({1}): line={2:?}\n  expected: {3}\n  given:    {4}",
+                sample.parser, sample.source, sample.line, sample.expected, given,
+            ))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{0}", failures.join("\n"));
+}
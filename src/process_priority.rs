@@ -0,0 +1,100 @@
+use std::process::Command;
+use thiserror::Error;
+use crate::generate_bidirectional_binding;
+
+#[derive(Debug,Error)]
+pub enum ProcessPriorityError {
+    #[error("Invalid priority class, expected idle, below_normal or normal: {0}")]
+    InvalidPriorityClass(String),
+}
+
+/// Priority class of processes on windows
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum PriorityClass {
+    Idle,
+    BelowNormal,
+    Normal,
+}
+
+generate_bidirectional_binding!(
+    PriorityClass, &'static str, &str,
+    (Idle, "idle"),
+    (BelowNormal, "below_normal"),
+    (Normal, "normal"),
+);
+
+impl PriorityClass {
+    pub fn parse(value: &str) -> Result<Self, ProcessPriorityError> {
+        Self::try_from(value).map_err(|_| ProcessPriorityError::InvalidPriorityClass(value.to_owned()))
+    }
+
+    #[cfg(windows)]
+    fn get_creation_flags(self) -> u32 {
+        const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+        const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+        const NORMAL_PRIORITY_CLASS: u32 = 0x0000_0020;
+        match self {
+            Self::Idle => IDLE_PRIORITY_CLASS,
+            Self::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            Self::Normal => NORMAL_PRIORITY_CLASS,
+        }
+    }
+}
+
+/// Scheduling priority of the yt-dlp and ffmpeg processes started by jobs
+// NOTE: Only the child processes are changed so the server keeps answering requests at its own priority
+#[derive(Clone,Debug,Default)]
+pub struct ProcessPriority {
+    /// Nice level on unix (0 to 19) where higher levels get less cpu time
+    pub nice: Option<i32>,
+    /// Only read and write the disk when nothing else is using it on linux
+    pub io_idle: bool,
+    /// Priority class on windows
+    pub priority_class: Option<PriorityClass>,
+}
+
+impl ProcessPriority {
+    pub fn apply(&self, command: &mut Command) {
+        #[cfg(unix)]
+        self.apply_unix(command);
+        #[cfg(windows)]
+        if let Some(priority_class) = self.priority_class {
+            use std::os::windows::process::CommandExt;
+            command.creation_flags(priority_class.get_creation_flags());
+        }
+        #[cfg(not(any(unix, windows)))]
+        let _ = command;
+    }
+
+    #[cfg(unix)]
+    fn apply_unix(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+        let (nice, io_idle) = (self.nice, self.io_idle);
+        if nice.is_none() && !io_idle {
+            return;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = io_idle;
+        // SAFETY: The closure only makes system calls which are safe to make between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                // NOTE: libc doesn't wrap ioprio_set so the syscall is made directly, see ioprio_set(2)
+                #[cfg(target_os = "linux")]
+                if io_idle {
+                    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+                    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+                    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+                    if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT) < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
@@ -0,0 +1,297 @@
+/// Placeholder syntax of the database the query is sent to
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+}
+
+impl Dialect {
+    fn placeholder(self, index: usize) -> String {
+        match self {
+            Self::Sqlite => format!("?{index}"),
+            Self::Postgres => format!("${index}"),
+        }
+    }
+}
+
+/// Column which is known to belong to a table since it can only be taken from one
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Column(&'static str);
+
+impl Column {
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+/// Columns of a table in the order that selected rows are mapped to entries
+#[derive(Debug)]
+pub struct Table<const N: usize> {
+    pub name: &'static str,
+    pub columns: [&'static str; N],
+}
+
+const fn is_str_equal(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Looks up columns of a table when compiling so a misspelt or removed column is a build error
+/// e.g. columns!(YTDLP_TABLE; video_id, status)
+macro_rules! columns {
+    ($table:path; $($column:ident),+ $(,)?) => {
+        const { [$($table.column(stringify!($column))),+] }
+    };
+}
+pub(crate) use columns;
+
+/// Single column version of columns!()
+macro_rules! column {
+    ($table:path; $column:ident) => {
+        const { $table.column(stringify!($column)) }
+    };
+}
+pub(crate) use column;
+
+impl<const N: usize> Table<N> {
+    pub const TOTAL_COLUMNS: usize = N;
+
+    // NOTE: Panics which fails the build when called through columns!() or column!()
+    pub const fn column(&self, name: &str) -> Column {
+        let mut i = 0;
+        while i < N {
+            if is_str_equal(self.columns[i], name) {
+                return Column(self.columns[i]);
+            }
+            i += 1;
+        }
+        panic!("Table doesn't have column");
+    }
+
+    /// Column that only some tables have such as audio_ext
+    pub fn get_column(&self, name: &str) -> Option<Column> {
+        self.columns.iter().find(|column| **column == name).map(|column| Column(column))
+    }
+
+    /// Comma separated columns for selects that can't be built by Select such as exports
+    pub fn get_column_list(&self) -> String {
+        self.columns.join(", ")
+    }
+
+    pub fn select(&self) -> Select {
+        Select {
+            table: self.name,
+            columns: self.get_column_list(),
+            join: None,
+            conditions: Vec::new(),
+            order_by: Vec::new(),
+            has_limit: false,
+        }
+    }
+
+    pub fn insert(&self, columns: &[Column]) -> Insert {
+        Insert {
+            table: self.name,
+            table_columns: self.columns.to_vec(),
+            columns: columns.to_vec(),
+            conflict: None,
+        }
+    }
+
+    pub fn update(&self, columns: &[Column]) -> Update {
+        Update { table: self.name, columns: columns.to_vec(), conditions: Vec::new() }
+    }
+
+    pub fn delete(&self) -> Delete {
+        Delete { table: self.name, conditions: Vec::new() }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Compare {
+    Equal,
+    GreaterOrEqual,
+    Less,
+}
+
+impl Compare {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Equal => "=",
+            Self::GreaterOrEqual => ">=",
+            Self::Less => "<",
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+/// Conditions joined by AND where values are bound in order starting from the given placeholder index
+fn push_where_clause(sql: &mut String, conditions: &[(Column, Compare)], dialect: Dialect, start_index: usize) {
+    for (index, (column, compare)) in conditions.iter().enumerate() {
+        sql.push_str(if index == 0 { " WHERE " } else { " AND " });
+        sql.push_str(column.as_str());
+        sql.push_str(compare.as_str());
+        sql.push_str(dialect.placeholder(start_index+index).as_str());
+    }
+}
+
+/// SELECT where values of the conditions are bound first followed by the limit
+#[derive(Clone,Debug)]
+pub struct Select {
+    table: &'static str,
+    columns: String,
+    join: Option<(&'static str, &'static str)>,
+    conditions: Vec<(Column, Compare)>,
+    order_by: Vec<(Column, Order)>,
+    has_limit: bool,
+}
+
+impl Select {
+    /// Join whose columns are selected after the columns of the table
+    pub fn join(mut self, join: &'static str, columns: &'static str) -> Self {
+        self.join = Some((join, columns));
+        self
+    }
+
+    pub fn filter(mut self, column: Column, compare: Compare) -> Self {
+        self.conditions.push((column, compare));
+        self
+    }
+
+    pub fn filter_equal(self, columns: &[Column]) -> Self {
+        columns.iter().fold(self, |select, column| select.filter(*column, Compare::Equal))
+    }
+
+    pub fn order_by(mut self, column: Column, order: Order) -> Self {
+        self.order_by.push((column, order));
+        self
+    }
+
+    pub fn limit(mut self) -> Self {
+        self.has_limit = true;
+        self
+    }
+
+    pub fn build(&self, dialect: Dialect) -> String {
+        let mut sql = format!("SELECT {0}", self.columns);
+        if let Some((_, columns)) = self.join {
+            sql.push_str(", ");
+            sql.push_str(columns);
+        }
+        sql.push_str(" FROM ");
+        sql.push_str(self.table);
+        if let Some((join, _)) = self.join {
+            sql.push(' ');
+            sql.push_str(join);
+        }
+        push_where_clause(&mut sql, self.conditions.as_slice(), dialect, 1);
+        for (index, (column, order)) in self.order_by.iter().enumerate() {
+            sql.push_str(if index == 0 { " ORDER BY " } else { ", " });
+            sql.push_str(column.as_str());
+            sql.push_str(match order {
+                Order::Ascending => " ASC",
+                Order::Descending => " DESC",
+            });
+        }
+        if self.has_limit {
+            sql.push_str(" LIMIT ");
+            sql.push_str(dialect.placeholder(self.conditions.len()+1).as_str());
+        }
+        sql
+    }
+}
+
+/// INSERT which can be turned into an upsert with on_conflict()
+#[derive(Clone,Debug)]
+pub struct Insert {
+    table: &'static str,
+    table_columns: Vec<&'static str>,
+    columns: Vec<Column>,
+    conflict: Option<(Vec<Column>, Vec<Column>)>,
+}
+
+impl Insert {
+    /// Updates the existing row with the inserted values and sets every other column to NULL except the kept ones
+    // NOTE: Used instead of INSERT OR REPLACE since replacing deletes the row first which cascades to other tables
+    pub fn on_conflict(mut self, keys: &[Column], keep: &[Column]) -> Self {
+        self.conflict = Some((keys.to_vec(), keep.to_vec()));
+        self
+    }
+
+    pub fn build(&self, dialect: Dialect) -> String {
+        let columns: Vec<&str> = self.columns.iter().map(|column| column.as_str()).collect();
+        let placeholders: Vec<String> = (1..=columns.len()).map(|index| dialect.placeholder(index)).collect();
+        let mut sql = format!(
+            "INSERT INTO {0} ({1}) VALUES ({2})",
+            self.table, columns.join(", "), placeholders.join(","),
+        );
+        if let Some((keys, keep)) = self.conflict.as_ref() {
+            let keys: Vec<&str> = keys.iter().map(|column| column.as_str()).collect();
+            let keep: Vec<&str> = keep.iter().map(|column| column.as_str()).collect();
+            let values: Vec<String> = self.table_columns.iter()
+                .filter(|column| !keys.contains(column) && !keep.contains(column))
+                .map(|column| if columns.contains(column) { format!("{column}=excluded.{column}") } else { format!("{column}=NULL") })
+                .collect();
+            sql.push_str(format!(" ON CONFLICT ({0}) DO UPDATE SET {1}", keys.join(", "), values.join(", ")).as_str());
+        }
+        sql
+    }
+}
+
+/// UPDATE where the new values are bound first followed by values of the conditions
+#[derive(Clone,Debug)]
+pub struct Update {
+    table: &'static str,
+    columns: Vec<Column>,
+    conditions: Vec<(Column, Compare)>,
+}
+
+impl Update {
+    pub fn filter_equal(mut self, columns: &[Column]) -> Self {
+        self.conditions.extend(columns.iter().map(|column| (*column, Compare::Equal)));
+        self
+    }
+
+    pub fn build(&self, dialect: Dialect) -> String {
+        let values: Vec<String> = self.columns.iter().enumerate()
+            .map(|(index, column)| format!("{0}={1}", column.as_str(), dialect.placeholder(index+1)))
+            .collect();
+        let mut sql = format!("UPDATE {0} SET {1}", self.table, values.join(", "));
+        push_where_clause(&mut sql, self.conditions.as_slice(), dialect, self.columns.len()+1);
+        sql
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct Delete {
+    table: &'static str,
+    conditions: Vec<(Column, Compare)>,
+}
+
+impl Delete {
+    pub fn filter_equal(mut self, columns: &[Column]) -> Self {
+        self.conditions.extend(columns.iter().map(|column| (*column, Compare::Equal)));
+        self
+    }
+
+    pub fn build(&self, dialect: Dialect) -> String {
+        let mut sql = format!("DELETE FROM {0}", self.table);
+        push_where_clause(&mut sql, self.conditions.as_slice(), dialect, 1);
+        sql
+    }
+}
@@ -0,0 +1,158 @@
+use actix_web::{
+    http::{header::{ContentType, RETRY_AFTER}, StatusCode},
+    HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use thiserror::Error;
+use crate::app::AppState;
+use crate::database::{JobKind, WorkerStatus};
+use crate::error_code::ErrorCode;
+use crate::job_store::JobStoreError;
+use crate::util::get_unix_time;
+
+const SECONDS_PER_DAY: u64 = 24*60*60;
+
+#[derive(Debug,Error)]
+pub enum QuotaError {
+    #[error("Storage quota exceeded: used={used}, limit={limit} bytes")]
+    StorageExceeded { used: u64, limit: u64 },
+    #[error("Daily conversion quota exceeded: used={used}, limit={limit}")]
+    DailyConversionsExceeded { used: u64, limit: u64, reset_seconds: u64 },
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+#[derive(Serialize)]
+struct QuotaErrorResponse {
+    error: String,
+    code: ErrorCode,
+}
+
+impl ResponseError for QuotaError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(ContentType::json());
+        // NOTE: Storage is only freed by deleting entries so there is no time to retry after
+        if let Self::DailyConversionsExceeded { reset_seconds, .. } = self {
+            response.insert_header((RETRY_AFTER, reset_seconds.max(&1).to_string()));
+        }
+        let code = match self {
+            Self::StorageExceeded { .. } | Self::DailyConversionsExceeded { .. } => ErrorCode::QuotaExceeded,
+            Self::JobStore(_) => ErrorCode::InternalError,
+        };
+        response.json(QuotaErrorResponse { error: self.to_string(), code })
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::StorageExceeded { .. } | Self::DailyConversionsExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::JobStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Usage of a user towards the configured limits, which are missing if there is no limit
+#[derive(Clone,Debug,Serialize)]
+pub struct QuotaUsage {
+    pub storage_bytes: u64,
+    pub storage_bytes_limit: Option<u64>,
+    pub daily_conversions: u64,
+    pub daily_conversions_limit: Option<u64>,
+    pub daily_conversions_reset_unix_time: u64,
+}
+
+impl QuotaUsage {
+    /// Fails if the user can't start another conversion
+    pub fn check(&self) -> Result<(), QuotaError> {
+        if let Some(limit) = self.storage_bytes_limit {
+            if self.storage_bytes >= limit {
+                return Err(QuotaError::StorageExceeded { used: self.storage_bytes, limit });
+            }
+        }
+        if let Some(limit) = self.daily_conversions_limit {
+            if self.daily_conversions >= limit {
+                return Err(QuotaError::DailyConversionsExceeded {
+                    used: self.daily_conversions, limit,
+                    reset_seconds: self.daily_conversions_reset_unix_time.saturating_sub(get_unix_time()),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// NOTE: Days start at midnight UTC so every user's conversions reset at the same time
+pub fn get_quota_usage(app: &AppState, user_id: i64) -> Result<QuotaUsage, QuotaError> {
+    let day_start_unix_time = get_unix_time() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    Ok(QuotaUsage {
+        storage_bytes: app.job_store.select_owner_storage_bytes(user_id)?,
+        storage_bytes_limit: app.app_config.quota_storage_bytes,
+        daily_conversions: app.job_store.select_total_conversions(user_id, day_start_unix_time)?,
+        daily_conversions_limit: app.app_config.quota_daily_conversions,
+        daily_conversions_reset_unix_time: day_start_unix_time + SECONDS_PER_DAY,
+    })
+}
+
+pub fn record_conversion(app: &AppState, user_id: Option<i64>, status: WorkerStatus, kind: JobKind, key: &str) {
+    // NOTE: Conversions that failed to start or were already finished aren't counted
+    let Some(user_id) = user_id.filter(|_| matches!(status, WorkerStatus::Queued | WorkerStatus::Running)) else {
+        return;
+    };
+    if let Err(err) = app.job_store.insert_conversion_entry(user_id, kind, key) {
+        log::warn!("Failed to record conversion: user={user_id}, key={key}, err={err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_usage(storage_bytes: u64, daily_conversions: u64) -> QuotaUsage {
+        QuotaUsage {
+            storage_bytes,
+            storage_bytes_limit: Some(1000),
+            daily_conversions,
+            daily_conversions_limit: Some(10),
+            daily_conversions_reset_unix_time: get_unix_time() + 120,
+        }
+    }
+
+    fn get_retry_after(err: &QuotaError) -> Option<String> {
+        let response = err.error_response();
+        response.headers().get(RETRY_AFTER).map(|value| value.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn limits_are_reached_at_usage() {
+        assert!(create_usage(999, 9).check().is_ok());
+        assert!(matches!(create_usage(1000, 0).check(), Err(QuotaError::StorageExceeded { used: 1000, limit: 1000 })));
+        assert!(matches!(create_usage(0, 10).check(), Err(QuotaError::DailyConversionsExceeded { used: 10, limit: 10, .. })));
+        let usage = QuotaUsage { storage_bytes_limit: None, daily_conversions_limit: None, ..create_usage(u64::MAX, u64::MAX) };
+        assert!(usage.check().is_ok());
+        // NOTE: A limit of zero blocks every conversion
+        let usage = QuotaUsage { daily_conversions_limit: Some(0), ..create_usage(0, 0) };
+        assert!(matches!(usage.check(), Err(QuotaError::DailyConversionsExceeded { .. })));
+    }
+
+    #[test]
+    fn storage_is_checked_first() {
+        let err = create_usage(1000, 10).check().unwrap_err();
+        assert!(matches!(err, QuotaError::StorageExceeded { .. }));
+        assert_eq!(err.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(get_retry_after(&err), None);
+    }
+
+    #[test]
+    fn retry_after_is_time_until_reset() {
+        let err = create_usage(0, 10).check().unwrap_err();
+        let QuotaError::DailyConversionsExceeded { reset_seconds, .. } = err else {
+            panic!("Expected daily conversions to be exceeded: {err:?}");
+        };
+        assert!((119..=120).contains(&reset_seconds), "{reset_seconds}");
+        assert_eq!(err.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(get_retry_after(&err), Some(reset_seconds.to_string()));
+        // NOTE: Clients are told to wait at least a second if the reset has just passed
+        let usage = QuotaUsage { daily_conversions_reset_unix_time: 0, ..create_usage(0, 10) };
+        assert_eq!(get_retry_after(&usage.check().unwrap_err()).as_deref(), Some("1"));
+    }
+}
@@ -0,0 +1,64 @@
+use std::future::Future;
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+};
+use serde::Serialize;
+use tracing::Instrument;
+use crate::util::generate_job_id;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Id of the api call that started a job so it can be found in the server log, system log and job history
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+#[serde(transparent)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(generate_job_id())
+    }
+
+    /// Reverse proxies can pass their own id so the same id is used across services
+    pub fn try_from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let is_valid = !value.is_empty() && value.len() <= MAX_REQUEST_ID_LENGTH &&
+            value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        is_valid.then(|| Self(value.to_owned()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: Option<RequestId>;
+}
+
+/// Id of the request being handled, which is also set for jobs started by that request
+pub fn current_request_id() -> Option<RequestId> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok().flatten()
+}
+
+pub fn with_request_id<F: Future>(request_id: Option<RequestId>, f: F) -> impl Future<Output=F::Output> {
+    CURRENT_REQUEST_ID.scope(request_id, f)
+}
+
+/// Runs each request inside a span with its id and returns the id in the x-request-id header
+pub async fn trace_request(
+    req: ServiceRequest, next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = req.headers().get(REQUEST_ID_HEADER)
+        .and_then(RequestId::try_from_header)
+        .unwrap_or_else(RequestId::generate);
+    let span = tracing::info_span!("request", request_id = request_id.as_str(), method = %req.method(), path = req.path());
+    let mut res = with_request_id(Some(request_id.clone()), next.call(req)).instrument(span).await?;
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(res)
+}
@@ -1,38 +1,96 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use actix_web::{
-    error, 
-    http::{header::{ContentDisposition, ContentType, DispositionParam, DispositionType}, StatusCode}, 
-    web, HttpRequest, HttpResponse
+    cookie::{time, Cookie, SameSite},
+    http::{header::{
+        Charset, ContentDisposition, ContentType, DispositionParam, DispositionType, ExtendedValue, IfNoneMatch,
+        ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, LOCATION, RANGE, RETRY_AFTER,
+    }, StatusCode},
+    web, HttpMessage, HttpRequest, HttpResponse
 };
+use futures_util::StreamExt;
+use tokio::io::AsyncReadExt;
 use serde::{Deserialize, Serialize};
 use derive_more::Display;
 use crate::database::{
-    VideoId, VideoIdError, AudioExtension, WorkerStatus,
-    delete_ffmpeg_entry, select_ffmpeg_entries, select_ffmpeg_entry,
-    delete_ytdlp_entry, select_ytdlp_entries, select_ytdlp_entry,
+    VideoId, VideoIdError, AudioExtension, WorkerStatus, MetadataRow, JobKind, JobRow, FfmpegRow, YtdlpRow, AuthTokenKind,
+    JobsPerDayRow, ChannelCountRow, TableUsageRow, ChannelJobRow, ChannelJobVideoRow, MaintenanceRunRow, EntryFilter,
 };
-use crate::metadata::{get_metadata_url, MetadataCache, Metadata};
-use crate::worker_download::{try_start_download_worker, DownloadState};
-use crate::worker_transcode::{try_start_transcode_worker, TranscodeState, TranscodeKey};
-use crate::app::AppState;
+use crate::metadata::{fetch_metadata, get_metadata_from_cache, LiveStatus, Metadata};
+use crate::worker_download::{try_start_download_worker, DownloadStartError, DownloadState};
+use crate::worker_transcode::{try_start_transcode_worker, get_waveform_path, TranscodeStartError, TranscodeState, TranscodeKey};
+use crate::worker_hls::{
+    try_start_hls_worker, get_hls_directory, get_hls_mime_type, is_hls_filename, is_playlist_finished, PLAYLIST_FILENAME,
+};
+use crate::worker_queue::{get_average_job_durations, JobId, QueuePosition};
+use crate::ytdlp::{list_audio_formats, DownloadOptions, DownloadOptionsError};
+use crate::ytdlp_auth::{check_age_restricted, DEFAULT_AGE_RESTRICTED_VIDEO_ID};
+use crate::ffmpeg::{TranscodeOptions, TranscodeOptionsError};
+use crate::app::{AppState, WorkerCacheEntry};
+use crate::job_events::JobEventBus;
+use crate::auth::{
+    self, hash_password, validate_password, validate_username, verify_password,
+    AuthError, AuthUser, Identity, SESSION_COOKIE, SESSION_DURATION_SECONDS,
+};
+use crate::request_id::{current_request_id, RequestId};
+use crate::quota::{get_quota_usage, record_conversion, QuotaError};
+use crate::backup::{export_database, import_database, BackupError, DatabaseExport};
+use crate::channel::{run_channel_job, validate_channel_id, ChannelError, ChannelFilters};
+use crate::bandwidth::{BandwidthSchedule, BandwidthScheduleError};
+use crate::integrations::{
+    find_video_id, get_discord_pong, get_discord_reply, get_telegram_reply, verify_discord_signature, verify_telegram_secret_token,
+    DiscordInteraction, IntegrationError, TelegramUpdate,
+};
+use crate::maintenance::{try_start_maintenance_task, MaintenanceError, MaintenanceTask};
+use crate::error_code::ErrorCode;
+use crate::storage::{is_object_url, StorageError};
+use crate::system_info::{DirectoryInfo, ToolVersions};
+use crate::conditional::{get_file_etag, get_range, get_revision_etag, is_etag_matched, is_not_modified, serve_file};
+use crate::verify::{verify_file, VerifyError, VerifyStatus};
+use crate::url_parse::{parse_video_url, UrlParseError};
+use crate::estimate;
+use crate::job_state::JobState;
+use crate::archive::{ArchiveEntry, ArchiveError, ZipStream};
+use crate::waveform::Waveform;
+use crate::ffmetadata::find_subtitle_paths;
+use crate::disk_space::DiskSpaceError;
+use crate::data_files::{find_data_file, render_data_listing, DataFile, DataFileError};
+use crate::log_file::find_log_backups;
+use crate::trash::{move_to_trash, restore_trash_entry, TrashError, TrashOutcome, TrashedFile};
+use crate::scheduler::{
+    list_subscription_videos, record_subscription_videos, validate_check_interval, validate_subscription_url,
+    SubscriptionError, DEFAULT_CHECK_INTERVAL_SECONDS,
+};
+use crate::util::{get_file_sha256, get_title_filename, get_unix_time, generate_job_id, is_valid_job_id};
+use crate::generate_bidirectional_binding;
 
 #[derive(Debug,Clone,Serialize,Display)]
 #[display(fmt = "UserApiError({},{})", error, status_code)]
 struct ApiError {
     error: String,
+    code: ErrorCode,
     #[serde(skip)]
     status_code: StatusCode,
 }
 
 impl ApiError {
-    fn _new(error: String, status_code: StatusCode) -> Self {
-        Self { error, status_code }
+    fn _new(error: String, code: ErrorCode, status_code: StatusCode) -> Self {
+        Self { error, code, status_code }
     }
 
     fn invalid_video_id(id: String, err: VideoIdError) -> Self {
         Self {
             error: format!("invalid video id {id}: {err:?}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_video_url(err: UrlParseError) -> Self {
+        Self {
+            error: format!("invalid video url: {err}"),
+            code: ErrorCode::InvalidRequest,
             status_code: StatusCode::BAD_REQUEST,
         }
     }
@@ -40,23 +98,318 @@ impl ApiError {
     fn invalid_audio_extension(ext: String) -> Self {
         Self {
             error: format!("invalid audio extension: {ext}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_log_stream(stream: String) -> Self {
+        Self {
+            error: format!("invalid log stream: {stream}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_download_options(err: DownloadOptionsError) -> Self {
+        Self {
+            error: format!("invalid download options: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_transcode_options(err: TranscodeOptionsError) -> Self {
+        Self {
+            error: format!("invalid transcode options: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_variant(variant: String) -> Self {
+        Self {
+            error: format!("invalid transcode variant: {variant}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_archive_file(file: String) -> Self {
+        Self {
+            error: format!("invalid archive file, expected video_id.ext or video_id.variant.ext: {file}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_archive(err: ArchiveError) -> Self {
+        Self {
+            error: format!("invalid archive: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_hls_file(filename: String) -> Self {
+        Self {
+            error: format!("invalid hls file, expected playlist or segment: {filename}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_subscription(err: SubscriptionError) -> Self {
+        Self {
+            error: format!("invalid subscription: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_channel(err: ChannelError) -> Self {
+        Self {
+            error: format!("invalid channel request: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_import_body(err: String) -> Self {
+        Self {
+            error: format!("invalid export: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_import(err: BackupError) -> Self {
+        let (code, status_code) = match err {
+            BackupError::SchemaVersion { .. } | BackupError::UnknownTable(_) |
+            BackupError::InvalidColumn { .. } | BackupError::MissingFiles(_) => (ErrorCode::InvalidRequest, StatusCode::BAD_REQUEST),
+            BackupError::JobsRunning => (ErrorCode::Conflict, StatusCode::CONFLICT),
+            BackupError::DataFile(DataFileError::OutsideDirectory(_)) => (ErrorCode::InvalidRequest, StatusCode::BAD_REQUEST),
+            BackupError::DataFile(_) => (ErrorCode::InternalError, StatusCode::INTERNAL_SERVER_ERROR),
+            BackupError::JobStore(_) => (ErrorCode::InternalError, StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        Self { error: format!("failed to import: {err}"), code, status_code }
+    }
+
+    fn invalid_maintenance_task(task: String) -> Self {
+        Self {
+            error: format!("invalid maintenance task: {task}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_bandwidth_schedule(err: BandwidthScheduleError) -> Self {
+        Self {
+            error: format!("invalid bandwidth schedule: {err}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn invalid_integration_request(err: IntegrationError) -> Self {
+        let (code, status_code) = match err {
+            IntegrationError::Parse(_) | IntegrationError::InvalidDiscordPublicKey(_) => (ErrorCode::InvalidRequest, StatusCode::BAD_REQUEST),
+            _ => (ErrorCode::Unauthorized, StatusCode::UNAUTHORIZED),
+        };
+        Self { error: format!("invalid integration request: {err}"), code, status_code }
+    }
+
+    fn maintenance(err: MaintenanceError) -> Self {
+        let (code, status_code) = match err {
+            MaintenanceError::AlreadyRunning { .. } => (ErrorCode::Conflict, StatusCode::CONFLICT),
+            MaintenanceError::JobStore(_) => (ErrorCode::InternalError, StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        Self { error: format!("failed to start maintenance task: {err}"), code, status_code }
+    }
+
+    fn duplicate_subscription(url: String) -> Self {
+        Self {
+            error: format!("already subscribed to url: {url}"),
+            code: ErrorCode::Conflict,
+            status_code: StatusCode::CONFLICT,
+        }
+    }
+
+    fn invalid_idempotency_key(key: String) -> Self {
+        Self {
+            error: format!("invalid idempotency key, expected 1 to 64 characters of [a-zA-Z0-9_-]: {key}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn idempotency_key_conflict(key: String) -> Self {
+        Self {
+            error: format!("idempotency key was already used for a different request: {key}"),
+            code: ErrorCode::Conflict,
+            status_code: StatusCode::CONFLICT,
+        }
+    }
+
+    fn invalid_job_id(job_id: String) -> Self {
+        Self {
+            error: format!("invalid job id: {job_id}"),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn not_owner(name: String) -> Self {
+        Self {
+            error: format!("entry belongs to another user: {name}"),
+            code: ErrorCode::Forbidden,
+            status_code: StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn duplicate_username(username: String) -> Self {
+        Self {
+            error: format!("username is already taken: {username}"),
+            code: ErrorCode::Conflict,
+            status_code: StatusCode::CONFLICT,
+        }
+    }
+
+    fn delete_self() -> Self {
+        Self {
+            error: "users can't delete themselves".to_owned(),
+            code: ErrorCode::InvalidRequest,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn live_stream(video_id: &VideoId) -> Self {
+        Self {
+            error: format!("video is a livestream, pass live_from_start=true to record it: {0}", video_id.as_str()),
+            code: ErrorCode::LiveStream,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn upcoming_stream(video_id: &VideoId) -> Self {
+        Self {
+            error: format!("video is a livestream or premiere that hasn't started yet: {0}", video_id.as_str()),
+            code: ErrorCode::UpcomingStream,
+            status_code: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn not_found(name: String) -> Self {
+        Self {
+            error: format!("entry not found: {name}"),
+            code: ErrorCode::NotFound,
+            status_code: StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn storage(err: StorageError) -> Self {
+        Self {
+            error: format!("object storage failed: {err}"),
+            code: ErrorCode::StorageFailed,
+            status_code: StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn verify_failed(err: VerifyError) -> Self {
+        match err {
+            VerifyError::Storage(err) => Self::storage(err),
+            err => Self::internal_server(err),
+        }
+    }
+
+    fn remote_file(name: String) -> Self {
+        Self {
+            error: format!("entry is stored in object storage and can't be read locally: {name}"),
+            code: ErrorCode::InvalidRequest,
             status_code: StatusCode::BAD_REQUEST,
         }
     }
 
+    fn data_file(err: DataFileError) -> Self {
+        let (code, status_code) = match err {
+            DataFileError::OutsideDirectory(_) => (ErrorCode::InvalidRequest, StatusCode::BAD_REQUEST),
+            DataFileError::NotFound(_) | DataFileError::ListingDisabled => (ErrorCode::NotFound, StatusCode::NOT_FOUND),
+            DataFileError::JobStore(_) | DataFileError::Io(_) => (ErrorCode::InternalError, StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        Self { error: err.to_string(), code, status_code }
+    }
+
+    fn trash(err: TrashError) -> Self {
+        let (code, status_code) = match err {
+            TrashError::AlreadyExists(_) | TrashError::Busy(_) | TrashError::MissingDownload(_) => (ErrorCode::Conflict, StatusCode::CONFLICT),
+            TrashError::Storage(err) => return Self::storage(err),
+            TrashError::File { .. } | TrashError::InvalidEntry(_) | TrashError::Blocking(_) | TrashError::JobStore(_) => {
+                (ErrorCode::InternalError, StatusCode::INTERNAL_SERVER_ERROR)
+            },
+        };
+        Self { error: format!("trash failed: {err}"), code, status_code }
+    }
+
+    fn disk_full(err: DiskSpaceError) -> Self {
+        Self {
+            error: format!("not enough disk space: {err}"),
+            code: ErrorCode::DiskFull,
+            status_code: StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+
+    fn download_start(err: DownloadStartError) -> Self {
+        match err {
+            DownloadStartError::DiskFull(err) => Self::disk_full(err),
+            DownloadStartError::Database(err) => Self::internal_server(err),
+        }
+    }
+
+    fn transcode_start(err: TranscodeStartError) -> Self {
+        match err {
+            TranscodeStartError::DiskFull(err) => Self::disk_full(err),
+            TranscodeStartError::Database(err) => Self::internal_server(err),
+        }
+    }
+
+    fn retranscode_busy(name: String) -> Self {
+        Self {
+            error: format!("transcode is already queued or running: {name}"),
+            code: ErrorCode::Conflict,
+            status_code: StatusCode::CONFLICT,
+        }
+    }
+
+    fn retranscode_not_forced(name: String) -> Self {
+        Self {
+            error: format!("transcode already finished, pass force=true to encode it again: {name}"),
+            code: ErrorCode::Conflict,
+            status_code: StatusCode::CONFLICT,
+        }
+    }
+
     fn internal_server(err: impl std::fmt::Debug) -> Self {
         Self {
             error: format!("internal server error: {err:?}"),
+            code: ErrorCode::InternalError,
             status_code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+#[derive(Serialize)]
+struct ApiErrorBody<'a> {
+    #[serde(flatten)]
+    error: &'a ApiError,
+    request_id: Option<RequestId>,
+}
+
 impl actix_web::ResponseError for ApiError {
+    // NOTE: Responses are built inside the request's scope so the id can be returned for bug reports
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
         HttpResponse::build(self.status_code())
             .insert_header(ContentType::json())
-            .json(self)
+            .json(ApiErrorBody { error: self, request_id: current_request_id() })
     }
 
     fn status_code(&self) -> StatusCode {
@@ -64,244 +417,2606 @@ impl actix_web::ResponseError for ApiError {
     }
 }
 
+#[derive(Deserialize)]
+struct VariantParams {
+    #[serde(default)]
+    variant: String,
+}
+
+impl VariantParams {
+    fn validate(self) -> Result<String, ApiError> {
+        if !TranscodeOptions::is_valid_variant(self.variant.as_str()) {
+            return Err(ApiError::invalid_variant(self.variant));
+        }
+        Ok(self.variant)
+    }
+}
+
+/// Lists the audio formats that can be pinned with the format_id download option along with the language of dubbed tracks
+#[actix_web::get("/get_formats/{video_id}")]
+pub async fn get_formats(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let url = format!("https://www.youtube.com/watch?v={0}", video_id.as_str());
+    let formats = web::block(move || list_audio_formats(
+        &app.app_config.ytdlp_binary, url.as_str(),
+        app.app_config.proxy.as_deref(), app.app_config.geo_bypass_country.as_deref(), &app.app_config.ytdlp_auth,
+    )).await?.map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(formats))
+}
+
+const DEFAULT_RETRY_AFTER_SECONDS: u64 = 30;
+const MAX_RETRY_AFTER_SECONDS: u64 = 600;
+
+#[derive(Debug,Clone,Serialize)]
+struct QueueFullResponse {
+    error: String,
+    code: ErrorCode,
+    total_pending_jobs: usize,
+    retry_after_seconds: u64,
+}
+
+fn is_queue_full(app: &AppState) -> bool {
+    app.app_config.max_pending_jobs.is_some_and(|max_pending_jobs| app.worker_thread_pool.total_pending() >= max_pending_jobs)
+}
+
+// NOTE: Requests for jobs that are already queued or finished don't add any work so they are always accepted
+fn is_job_accepted(status: Option<WorkerStatus>) -> bool {
+    matches!(status, Some(WorkerStatus::Queued | WorkerStatus::Running | WorkerStatus::Finished))
+}
+
+/// Asks the client to retry once the next queued job is expected to start
+fn get_queue_full_response(app: &AppState) -> actix_web::Result<HttpResponse> {
+    let durations = get_average_job_durations(&app.job_store).map_err(ApiError::internal_server)?;
+    let retry_after_seconds = app.worker_thread_pool.estimate_next_start_ms(&durations)
+        .map(|ms| ms.div_ceil(1000).clamp(1, MAX_RETRY_AFTER_SECONDS))
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECONDS);
+    let total_pending_jobs = app.worker_thread_pool.total_pending();
+    Ok(HttpResponse::ServiceUnavailable()
+        .insert_header((RETRY_AFTER, retry_after_seconds.to_string()))
+        .json(QueueFullResponse {
+            error: format!("job queue is full with {total_pending_jobs} pending jobs"),
+            code: ErrorCode::QueueFull,
+            total_pending_jobs,
+            retry_after_seconds,
+        }))
+}
+
+/// Returns the user to charge for a new conversion, admins and requests without authentication aren't limited
+fn check_conversion_quota(app: &AppState, identity: &Identity) -> Result<Option<i64>, QuotaError> {
+    let Some(user) = identity.user.as_ref().filter(|user| !user.is_admin) else {
+        return Ok(None);
+    };
+    get_quota_usage(app, user.id)?.check()?;
+    Ok(Some(user.id))
+}
+
+/// Livestreams don't end so they are only downloaded if recording them from the start was requested
+// NOTE: A finished recording is no longer live so it doesn't need to be checked
+fn check_live_status(
+    app: &AppState, video_id: &VideoId, metadata: Option<&Metadata>, mut download_options: DownloadOptions,
+) -> Result<DownloadOptions, ApiError> {
+    let live_status = metadata
+        .and_then(|metadata| metadata.items.first())
+        .map(|item| item.get_live_status())
+        .unwrap_or(LiveStatus::None);
+    let is_downloaded = app.job_store.select_ytdlp_entry(video_id).map_err(ApiError::internal_server)?
+        .is_some_and(|entry| entry.status == WorkerStatus::Finished);
+    match live_status {
+        _ if is_downloaded => download_options.live_from_start = None,
+        LiveStatus::None => download_options.live_from_start = None,
+        LiveStatus::Upcoming => return Err(ApiError::upcoming_stream(video_id)),
+        LiveStatus::Live if download_options.live_from_start != Some(true) => return Err(ApiError::live_stream(video_id)),
+        LiveStatus::Live => {},
+    }
+    Ok(download_options)
+}
+
 #[derive(Debug,Default,Clone,Serialize)]
 struct RequestTranscodeResponse {
     download_status: WorkerStatus,
     transcode_status: WorkerStatus,
     is_skip_transcode: bool,
+    variant: String,
+    job_id: String,
+    /// Only present while the job is waiting for a worker thread
+    download_queue: Option<QueuePosition>,
+    transcode_queue: Option<QueuePosition>,
 }
 
-#[actix_web::get("/request_transcode/{video_id}/{extension}")]
-#[allow(clippy::field_reassign_with_default)]
-pub async fn request_transcode(req: HttpRequest, path: web::Path<(String, String)>) -> actix_web::Result<HttpResponse> {
-    let (video_id, audio_ext) = path.into_inner();
-    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
-    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
-    let transcode_key = TranscodeKey { video_id: video_id.clone(), audio_ext };
-    let app = req.app_data::<AppState>().unwrap().clone();
-    // download audio file
-    let mut response = RequestTranscodeResponse::default();
-    response.download_status = try_start_download_worker(
-        video_id.clone(),
-        app.download_cache.clone(), app.app_config.clone(), app.db_pool.clone(), app.worker_thread_pool.clone(),
-    ).map_err(ApiError::internal_server)?;
-    // transcode
-    let metadata = get_metadata_from_cache(video_id, app.metadata_cache).await.ok();
-    response.transcode_status = try_start_transcode_worker(
-        transcode_key.clone(),
-        app.download_cache, app.transcode_cache, app.app_config.clone(), app.db_pool.clone(), app.worker_thread_pool.clone(),
-        metadata,
-    ).map_err(ApiError::internal_server)?;
-    Ok(HttpResponse::Ok().json(response))
+#[derive(Deserialize)]
+struct IdempotencyParams {
+    idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "lowercase")]
-enum DeleteFileResult {
-    Success { filename: String },
-    Failure { filename: String, reason: String },
+impl IdempotencyParams {
+    fn validate(self) -> Result<Option<String>, ApiError> {
+        let Some(key) = self.idempotency_key else {
+            return Ok(None);
+        };
+        let is_valid = (1..=64).contains(&key.len()) && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !is_valid {
+            return Err(ApiError::invalid_idempotency_key(key));
+        }
+        Ok(Some(key))
+    }
 }
 
-#[derive(Debug,Serialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "lowercase")]
-enum DeleteResponse {
-    Busy,
-    Success { paths: Vec<DeleteFileResult> },
+fn insert_job(app: &AppState, key: &TranscodeKey, idempotency_key: Option<String>) -> Result<String, ApiError> {
+    let job = JobRow {
+        job_id: generate_job_id(),
+        idempotency_key,
+        video_id: key.video_id.clone(),
+        audio_ext: key.audio_ext,
+        variant: key.variant.clone(),
+        unix_time: get_unix_time(),
+    };
+    let insert_err = match app.job_store.insert_job_entry(&job) {
+        Ok(_) => return Ok(job.job_id),
+        Err(err) => err,
+    };
+    // NOTE: A concurrent request with the same idempotency key was inserted first
+    if let Some(ref idempotency_key) = job.idempotency_key {
+        let existing = app.job_store.select_job_entry_by_idempotency_key(idempotency_key).map_err(ApiError::internal_server)?;
+        if let Some(existing) = existing {
+            return Ok(existing.job_id);
+        }
+    }
+    Err(ApiError::internal_server(insert_err))
 }
 
-#[actix_web::get("/delete_download/{video_id}")]
-pub async fn delete_download(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
-    let video_id = path.into_inner();
-    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
-    let app = req.app_data::<AppState>().unwrap().clone();
-    let download_state = app.download_cache.entry(video_id.clone()).or_default();
-    let mut state = download_state.0.lock().unwrap();
-    if state.worker_status.is_busy() {
-        return Ok(HttpResponse::Ok().json(DeleteResponse::Busy));
-    }
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entry = select_ytdlp_entry(&db_conn, &video_id).map_err(ApiError::internal_server)?;
-    let Some(entry) = entry else { return Ok(HttpResponse::NotFound().finish()); };
-    let total_deleted = delete_ytdlp_entry(&db_conn, &video_id).map_err(ApiError::internal_server)?;
-    *state = DownloadState::default();
-    download_state.1.notify_all();
-    drop(state);
-    drop(download_state);
-    drop(db_conn);
-    if total_deleted == 0 { return Ok(HttpResponse::NotFound().finish()); }
-    let paths = vec![entry.audio_path, entry.stdout_log_path, entry.stderr_log_path, entry.system_log_path];
-    let paths: Vec<String> = paths.into_iter().flatten().collect();
-    let paths: Vec<DeleteFileResult> = paths.into_iter().map(|path| {
-        match std::fs::remove_file(std::path::PathBuf::from(path.clone())) {
-            Ok(()) => DeleteFileResult::Success { filename: path },
-            Err(err) => DeleteFileResult::Failure { filename: path, reason: err.to_string() },
+/// Concurrent identical requests without an idempotency key share the job stored in the transcode state
+// NOTE: The state is locked while the job is created so simultaneous requests can't create separate jobs
+fn get_or_create_job(app: &AppState, key: &TranscodeKey, idempotency_key: Option<String>) -> Result<String, ApiError> {
+    let transcode_state = app.transcode_cache.entry(key.clone()).or_default().clone();
+    let mut state = transcode_state.lock().unwrap();
+    if idempotency_key.is_some() {
+        let job_id = insert_job(app, key, idempotency_key)?;
+        if state.job_id.is_none() {
+            state.job_id = Some(job_id.clone());
+            state.bump_revision();
         }
-    }).collect();
-    Ok(HttpResponse::Ok().json(DeleteResponse::Success { paths }))
+        return Ok(job_id);
+    }
+    if let Some(ref job_id) = state.job_id {
+        return Ok(job_id.clone());
+    }
+    // NOTE: Transcodes restored from disk after a restart keep the job that originally requested them
+    let mut job_id = None;
+    if state.worker_status == WorkerStatus::Finished {
+        job_id = app.job_store.select_latest_job_entry(&key.video_id, key.audio_ext, key.variant.as_str())
+            .map_err(ApiError::internal_server)?
+            .map(|job| job.job_id);
+    }
+    let job_id = match job_id {
+        Some(job_id) => job_id,
+        None => insert_job(app, key, None)?,
+    };
+    state.job_id = Some(job_id.clone());
+    state.bump_revision();
+    Ok(job_id)
 }
 
-#[actix_web::get("/delete_transcode/{video_id}/{extension}")]
-pub async fn delete_transcode(req: HttpRequest, path: web::Path<(String, String)>) -> actix_web::Result<HttpResponse> {
+/// Predicts the size and duration of a transcode with the given options without enqueuing it
+#[actix_web::get("/estimate_transcode/{video_id}/{extension}")]
+pub async fn estimate_transcode(
+    req: HttpRequest, path: web::Path<(String, String)>, transcode_options: web::Query<TranscodeOptions>,
+) -> actix_web::Result<HttpResponse> {
     let (video_id, audio_ext) = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
-    let transcode_key = TranscodeKey { video_id: video_id.clone(), audio_ext };
+    let transcode_options = transcode_options.into_inner();
     let app = req.app_data::<AppState>().unwrap().clone();
-    let transcode_state = app.transcode_cache.entry(transcode_key.clone()).or_default();
-    let mut state = transcode_state.0.lock().unwrap();
-    if state.worker_status.is_busy() {
-        return Ok(HttpResponse::Ok().json(DeleteResponse::Busy));
-    }
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entry = select_ffmpeg_entry(&db_conn, &video_id, audio_ext).map_err(ApiError::internal_server)?;
-    let Some(entry) = entry else { return Ok(HttpResponse::NotFound().finish()); };
-    let total_deleted = delete_ffmpeg_entry(&db_conn, &video_id, audio_ext).map_err(ApiError::internal_server)?;
-    *state = TranscodeState::default();
-    transcode_state.1.notify_all();
-    drop(state);
-    drop(transcode_state);
-    drop(db_conn);
-    if total_deleted == 0 { return Ok(HttpResponse::NotFound().finish()); }
-    let paths = vec![entry.audio_path, entry.stdout_log_path, entry.stderr_log_path, entry.system_log_path];
-    let paths: Vec<String> = paths.into_iter().flatten().collect();
-    let paths: Vec<DeleteFileResult> = paths.into_iter().map(|path| {
-        match std::fs::remove_file(std::path::PathBuf::from(path.clone())) {
-            Ok(()) => DeleteFileResult::Success { filename: path },
-            Err(err) => DeleteFileResult::Failure { filename: path, reason: err.to_string() },
-        }
-    }).collect();
-    Ok(HttpResponse::Ok().json(DeleteResponse::Success { paths }))
+    transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    let estimate = estimate::estimate_transcode(&app, &video_id, audio_ext, &transcode_options).await
+        .map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(estimate))
 }
 
-#[actix_web::get("/get_downloads")]
-pub async fn get_downloads(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+/// Pass an idempotency_key so retrying a request returns the same job_id
+#[actix_web::get("/request_transcode/{video_id}/{extension}")]
+pub async fn request_transcode(
+    req: HttpRequest, path: web::Path<(String, String)>,
+    download_options: web::Query<DownloadOptions>, transcode_options: web::Query<TranscodeOptions>,
+    idempotency_params: web::Query<IdempotencyParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
     let app = req.app_data::<AppState>().unwrap().clone();
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entries = select_ytdlp_entries(&db_conn).map_err(ApiError::internal_server)?;
-    Ok(HttpResponse::Ok().json(entries))
+    request_video_transcode(
+        &app, &identity, video_id, audio_ext,
+        transcode_options.into_inner(), download_options.into_inner(), idempotency_params.into_inner(),
+    ).await
 }
 
-#[actix_web::get("/get_transcodes")]
-pub async fn get_transcodes(req: HttpRequest) -> actix_web::Result<HttpResponse> {
-    let app = req.app_data::<AppState>().unwrap().clone();
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entries = select_ffmpeg_entries(&db_conn).map_err(ApiError::internal_server)?;
-    Ok(HttpResponse::Ok().json(entries))
+#[derive(Deserialize)]
+struct TranscodeUrlParams {
+    url: String,
+    /// Trims the transcode to the start and end times of the url
+    #[serde(default)]
+    clip: bool,
 }
 
-#[actix_web::get("/get_download/{video_id}")]
-pub async fn get_download(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+/// Same as request_transcode but takes any YouTube url instead of the video id
+#[actix_web::get("/request_transcode_url/{extension}")]
+pub async fn request_transcode_url(
+    req: HttpRequest, path: web::Path<String>, url_params: web::Query<TranscodeUrlParams>,
+    download_options: web::Query<DownloadOptions>, transcode_options: web::Query<TranscodeOptions>,
+    idempotency_params: web::Query<IdempotencyParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let audio_ext = path.into_inner();
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let url_params = url_params.into_inner();
+    let video_url = parse_video_url(url_params.url.as_str()).map_err(ApiError::invalid_video_url)?;
+    let mut transcode_options = transcode_options.into_inner();
+    if url_params.clip {
+        video_url.apply_clip(&mut transcode_options);
+    }
+    let app = req.app_data::<AppState>().unwrap().clone();
+    request_video_transcode(
+        &app, &identity, video_url.video_id, audio_ext,
+        transcode_options, download_options.into_inner(), idempotency_params.into_inner(),
+    ).await
+}
+
+async fn request_video_transcode(
+    app: &AppState, identity: &Identity, video_id: VideoId, audio_ext: AudioExtension,
+    transcode_options: TranscodeOptions, download_options: DownloadOptions, idempotency_params: IdempotencyParams,
+) -> actix_web::Result<HttpResponse> {
+    let transcode_key = TranscodeKey::new(video_id.clone(), audio_ext, &transcode_options);
+    transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    let download_options = app.app_config.download_options.with_overrides(&download_options);
+    download_options.validate(&app.app_config.allowed_extractor_args).map_err(ApiError::invalid_download_options)?;
+    let idempotency_key = idempotency_params.validate()?;
+    let replayed_job = match idempotency_key {
+        Some(ref key) => app.job_store.select_job_entry_by_idempotency_key(key).map_err(ApiError::internal_server)?,
+        None => None,
+    };
+    if let Some(ref job) = replayed_job {
+        if job.video_id != video_id || job.audio_ext != audio_ext || job.variant != transcode_key.variant {
+            return Err(ApiError::idempotency_key_conflict(idempotency_key.unwrap_or_default()).into());
+        }
+    }
+    let entry = app.job_store.select_ffmpeg_entry(&video_id, audio_ext, transcode_key.variant.as_str())
+        .map_err(ApiError::internal_server)?;
+    if entry.as_ref().is_some_and(|entry| !identity.can_access(entry.owner)) {
+        return Err(ApiError::not_owner(transcode_key.as_str()).into());
+    }
+    let metadata = get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await.ok();
+    let download_options = check_live_status(app, &video_id, metadata.as_deref(), download_options)?;
+    let transcode_status = app.transcode_cache.get(&transcode_key).map(|state| state.lock().unwrap().worker_status);
+    if is_queue_full(app) && !is_job_accepted(transcode_status) {
+        return get_queue_full_response(app);
+    }
+    // NOTE: The cache is empty after a restart so finished transcodes are also checked in the database
+    let is_new_conversion = !is_job_accepted(transcode_status)
+        && !entry.is_some_and(|entry| entry.status == WorkerStatus::Finished);
+    let quota_user_id = if is_new_conversion { check_conversion_quota(app, identity)? } else { None };
+    let job_id = replayed_job.map(|job| job.job_id);
+    let response = start_transcode_job(
+        app, identity, &transcode_key, transcode_options, download_options, metadata, quota_user_id, job_id, idempotency_key,
+    )?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Deserialize)]
+struct ResolveUrlParams {
+    url: String,
+}
+
+/// Video id, start and end times and playlist of a YouTube url so clients don't need to parse it themselves
+#[actix_web::get("/resolve_url")]
+pub async fn resolve_url(params: web::Query<ResolveUrlParams>) -> actix_web::Result<HttpResponse> {
+    let video_url = parse_video_url(params.url.as_str()).map_err(ApiError::invalid_video_url)?;
+    Ok(HttpResponse::Ok().json(video_url))
+}
+
+#[derive(Deserialize)]
+struct QuickAddParams {
+    url: String,
+    /// Audio extension which defaults to the one used for chat bots
+    format: Option<String>,
+    /// Trims the transcode to the start and end times of the url
+    #[serde(default)]
+    clip: bool,
+}
+
+/// Converts the video of any YouTube url and redirects to its progress on the main page
+// NOTE: Made for bookmarklets and browser extensions which can only open a link with the current url
+#[actix_web::get("/quick_add")]
+pub async fn quick_add(req: HttpRequest, params: web::Query<QuickAddParams>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let params = params.into_inner();
+    let video_url = parse_video_url(params.url.as_str()).map_err(ApiError::invalid_video_url)?;
+    let video_id = video_url.video_id.clone();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let audio_ext = match params.format {
+        Some(ext) => AudioExtension::try_from(ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(ext))?,
+        None => app.app_config.integrations.audio_ext,
+    };
+    let mut transcode_options = app.app_config.integrations.transcode_options.clone();
+    if params.clip {
+        video_url.apply_clip(&mut transcode_options);
+        transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    }
+    let transcode_key = TranscodeKey::new(video_id.clone(), audio_ext, &transcode_options);
+    let entry = app.job_store.select_ffmpeg_entry(&video_id, audio_ext, transcode_key.variant.as_str())
+        .map_err(ApiError::internal_server)?;
+    if entry.as_ref().is_some_and(|entry| !identity.can_access(entry.owner)) {
+        return Err(ApiError::not_owner(transcode_key.as_str()).into());
+    }
+    let metadata = get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await.ok();
+    let download_options = check_live_status(&app, &video_id, metadata.as_deref(), app.app_config.download_options.clone())?;
+    let transcode_status = app.transcode_cache.get(&transcode_key).map(|state| state.lock().unwrap().worker_status);
+    if is_queue_full(&app) && !is_job_accepted(transcode_status) {
+        return get_queue_full_response(&app);
+    }
+    let is_new_conversion = !is_job_accepted(transcode_status)
+        && !entry.is_some_and(|entry| entry.status == WorkerStatus::Finished);
+    let quota_user_id = if is_new_conversion { check_conversion_quota(&app, &identity)? } else { None };
+    let response = start_transcode_job(
+        &app, &identity, &transcode_key, transcode_options, download_options, metadata, quota_user_id, None, None,
+    )?;
+    let location = format!("{0}/?job={1}", app.app_config.base_path, response.job_id);
+    Ok(HttpResponse::Found().insert_header((LOCATION, location)).finish())
+}
+
+/// Starts the download and transcode if they aren't already running or finished
+#[allow(clippy::too_many_arguments, clippy::field_reassign_with_default)]
+fn start_transcode_job(
+    app: &AppState, identity: &Identity, transcode_key: &TranscodeKey,
+    transcode_options: TranscodeOptions, mut download_options: DownloadOptions, metadata: Option<Arc<Metadata>>,
+    quota_user_id: Option<i64>, job_id: Option<String>, idempotency_key: Option<String>,
+) -> Result<RequestTranscodeResponse, ApiError> {
+    let video_id = &transcode_key.video_id;
+    // NOTE: The language is part of the transcode variant so the source must be downloaded in the same language
+    download_options.audio_lang = transcode_options.audio_lang.clone();
+    // download audio file
+    let mut response = RequestTranscodeResponse::default();
+    response.variant = transcode_key.variant.clone();
+    response.download_status = try_start_download_worker(
+        video_id.clone(),
+        app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        download_options, identity.owner(),
+    ).map_err(ApiError::download_start)?;
+    // transcode
+    response.transcode_status = try_start_transcode_worker(
+        transcode_key.clone(), transcode_options,
+        app.download_cache.clone(), app.transcode_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        metadata, identity.owner(),
+    ).map_err(ApiError::transcode_start)?;
+    record_conversion(app, quota_user_id, response.transcode_status, JobKind::Transcode, transcode_key.as_str().as_str());
+    response.job_id = match job_id {
+        Some(job_id) => job_id,
+        None => get_or_create_job(app, transcode_key, idempotency_key)?,
+    };
+    if response.download_status == WorkerStatus::Queued || response.transcode_status == WorkerStatus::Queued {
+        let durations = get_average_job_durations(&app.job_store).map_err(ApiError::internal_server)?;
+        let download_job = JobId::new(JobKind::Download, video_id.as_str());
+        let transcode_job = JobId::new(JobKind::Transcode, transcode_key.as_str());
+        response.download_queue = app.worker_thread_pool.get_position(&download_job, &durations);
+        response.transcode_queue = app.worker_thread_pool.get_position(&transcode_job, &durations);
+    }
+    Ok(response)
+}
+
+/// Forgets the output of the transcode so it is encoded again, returning the path of the old output
+// NOTE: The output is forgotten while the state is locked so a concurrent request can't serve it in between
+fn reset_transcode(app: &AppState, key: &TranscodeKey, has_entry: bool) -> Result<Option<String>, ApiError> {
+    let transcode_state = app.transcode_cache.entry(key.clone()).or_default();
+    let mut state = transcode_state.lock().unwrap();
+    if state.worker_status.is_busy() {
+        return Err(ApiError::retranscode_busy(key.as_str()));
+    }
+    let mut old_audio_path = None;
+    if has_entry {
+        app.job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+            old_audio_path = entry.audio_path.take();
+            entry.status = WorkerStatus::None;
+        }).map_err(ApiError::internal_server)?;
+    }
+    *state = TranscodeState::default();
+    app.job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
+    Ok(old_audio_path)
+}
+
+/// Removes the old output and waveform of a transcode that was reset
+async fn remove_transcode_output(app: &AppState, key: &TranscodeKey, old_audio_path: Option<String>) -> Result<(), ApiError> {
+    if let Some(path) = old_audio_path {
+        if is_object_url(path.as_str()) {
+            let storage = app.app_config.object_storage.clone();
+            let res = web::block(move || match storage {
+                Some(storage) => storage.delete_object(path.as_str()),
+                None => Err(StorageError::NotConfigured(path)),
+            }).await.map_err(ApiError::internal_server)?;
+            res.map_err(ApiError::storage)?;
+        } else if let Err(err) = std::fs::remove_file(path.as_str()) {
+            log::warn!("Failed to remove old transcode: path={path}, err={err:?}");
+        }
+    }
+    let _ = std::fs::remove_file(get_waveform_path(key, &app.app_config));
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct RetranscodeParams {
+    #[serde(default)]
+    force: bool,
+}
+
+/// Encodes the transcode again even if it already finished, which needs ?force=true if it is up to date
+/// Used when the embedded metadata or artwork was wrong since a finished transcode is otherwise served as is
+#[actix_web::get("/retranscode/{video_id}/{extension}")]
+pub async fn retranscode(
+    req: HttpRequest, path: web::Path<(String, String)>,
+    download_options: web::Query<DownloadOptions>, transcode_options: web::Query<TranscodeOptions>,
+    retranscode_params: web::Query<RetranscodeParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let transcode_options = transcode_options.into_inner();
+    let transcode_key = TranscodeKey::new(video_id.clone(), audio_ext, &transcode_options);
+    let app = req.app_data::<AppState>().unwrap().clone();
+    transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    let download_options = app.app_config.download_options.with_overrides(&download_options);
+    download_options.validate(&app.app_config.allowed_extractor_args).map_err(ApiError::invalid_download_options)?;
+    let entry = app.job_store.select_ffmpeg_entry(&video_id, audio_ext, transcode_key.variant.as_str())
+        .map_err(ApiError::internal_server)?;
+    if entry.as_ref().is_some_and(|entry| !identity.can_modify(entry.owner)) {
+        return Err(ApiError::not_owner(transcode_key.as_str()).into());
+    }
+    let metadata = get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await.ok();
+    let download_options = check_live_status(&app, &video_id, metadata.as_deref(), download_options)?;
+    if is_queue_full(&app) {
+        return get_queue_full_response(&app);
+    }
+    let quota_user_id = check_conversion_quota(&app, &identity)?;
+    let is_finished = entry.as_ref().is_some_and(|entry| entry.status == WorkerStatus::Finished && entry.audio_path.is_some());
+    if is_finished && !retranscode_params.force {
+        return Err(ApiError::retranscode_not_forced(transcode_key.as_str()).into());
+    }
+    let old_audio_path = reset_transcode(&app, &transcode_key, entry.is_some())?;
+    remove_transcode_output(&app, &transcode_key, old_audio_path).await?;
+    let response = start_transcode_job(
+        &app, &identity, &transcode_key, transcode_options, download_options, metadata, quota_user_id, None, None,
+    )?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+enum DeleteFileResult {
+    Success { filename: String },
+    Failure { filename: String, reason: String },
+}
+
+#[derive(Debug,Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+enum DeleteResponse {
+    Busy,
+    /// Files are moved to the trash where they can be restored with the id until they are purged
+    /// Hls streams aren't put in the trash since they can be generated again from the download
+    Success { trash_id: Option<i64>, paths: Vec<DeleteFileResult> },
+    /// Files that failed to move are listed and the entry is marked with delete_pending until the delete is retried
+    Pending { paths: Vec<DeleteFileResult> },
+}
+
+fn get_delete_file_results(files: Vec<TrashedFile>) -> Vec<DeleteFileResult> {
+    files.into_iter().map(|file| match file.result {
+        Ok(()) => DeleteFileResult::Success { filename: file.path },
+        Err(err) => DeleteFileResult::Failure { filename: file.path, reason: err.to_string() },
+    }).collect()
+}
+
+fn get_delete_response(outcome: TrashOutcome, files: Vec<TrashedFile>) -> DeleteResponse {
+    let paths = get_delete_file_results(files);
+    match outcome {
+        TrashOutcome::Moved(trash_id) => DeleteResponse::Success { trash_id: Some(trash_id), paths },
+        TrashOutcome::Pending => DeleteResponse::Pending { paths },
+    }
+}
+
+/// Rotated stdout and stderr logs are deleted along with the job
+fn get_log_backup_paths(log_paths: &[&Option<String>]) -> Vec<Option<String>> {
+    log_paths.iter().filter_map(|path| path.as_deref())
+        .flat_map(|path| find_log_backups(std::path::Path::new(path)))
+        .map(|path| path.to_str().map(|path| path.to_owned()))
+        .collect()
+}
+
+/// Result of moving a transcode to the trash
+enum TranscodeTrashResult {
+    NotFound,
+    Busy,
+    Trashed(TrashOutcome, Vec<TrashedFile>),
+}
+
+fn trash_transcode(app: &AppState, identity: &Identity, transcode_key: &TranscodeKey) -> Result<TranscodeTrashResult, ApiError> {
+    let TranscodeKey { video_id, audio_ext, variant } = transcode_key;
+    let transcode_state = app.transcode_cache.entry(transcode_key.clone()).or_default();
+    let mut state = transcode_state.lock().unwrap();
+    if state.worker_status.is_busy() {
+        return Ok(TranscodeTrashResult::Busy);
+    }
+    let entry = app.job_store.select_ffmpeg_entry(video_id, *audio_ext, variant.as_str()).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry else { return Ok(TranscodeTrashResult::NotFound); };
+    if !identity.can_modify(entry.owner) {
+        return Err(ApiError::not_owner(transcode_key.as_str()));
+    }
+    // NOTE: Transcodes in object storage are left in the bucket until they are purged from the trash
+    let log_backup_paths = get_log_backup_paths(&[&entry.stdout_log_path, &entry.stderr_log_path]);
+    let mut paths = vec![
+        entry.audio_path.clone(), entry.stdout_log_path.clone(), entry.stderr_log_path.clone(), entry.system_log_path.clone(),
+    ];
+    paths.extend(log_backup_paths);
+    let waveform_path = get_waveform_path(transcode_key, &app.app_config);
+    if waveform_path.exists() {
+        paths.push(waveform_path.to_str().map(|path| path.to_owned()));
+    }
+    let paths: Vec<String> = paths.into_iter().flatten().collect();
+    let (outcome, results) = move_to_trash(
+        app, JobKind::Transcode, transcode_key.as_str().as_str(), entry.owner, entry.file_size_bytes, &entry, paths,
+        || app.job_store.delete_ffmpeg_entry(video_id, *audio_ext, variant.as_str()),
+    ).map_err(ApiError::trash)?;
+    match outcome {
+        TrashOutcome::Moved(_) => *state = TranscodeState::default(),
+        TrashOutcome::Pending => {
+            app.job_store.set_ffmpeg_delete_pending(video_id, *audio_ext, variant.as_str(), true).map_err(ApiError::internal_server)?;
+        },
+    }
+    app.job_events.publish(JobId::new(JobKind::Transcode, transcode_key.as_str()));
+    Ok(TranscodeTrashResult::Trashed(outcome, results))
+}
+
+#[actix_web::get("/delete_download/{video_id}")]
+pub async fn delete_download(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entry = app.job_store.select_ytdlp_entry(&video_id).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry else { return Ok(HttpResponse::NotFound().finish()); };
+    if !identity.can_modify(entry.owner) {
+        return Err(ApiError::not_owner(video_id.as_str().to_owned()).into());
+    }
+    // NOTE: Removing the download cascades to its transcodes so they are moved to the trash first to keep their files
+    let mut transcode_results = Vec::new();
+    let transcodes = app.job_store.select_video_ffmpeg_entries(&video_id).map_err(ApiError::internal_server)?;
+    for transcode in transcodes {
+        let transcode_key = TranscodeKey { video_id: video_id.clone(), audio_ext: transcode.audio_ext, variant: transcode.variant };
+        match trash_transcode(&app, &identity, &transcode_key)? {
+            TranscodeTrashResult::NotFound => {},
+            TranscodeTrashResult::Busy => return Ok(HttpResponse::Ok().json(DeleteResponse::Busy)),
+            TranscodeTrashResult::Trashed(TrashOutcome::Moved(_), results) => transcode_results.extend(results),
+            TranscodeTrashResult::Trashed(TrashOutcome::Pending, results) => {
+                transcode_results.extend(results);
+                return Ok(HttpResponse::Ok().json(get_delete_response(TrashOutcome::Pending, transcode_results)));
+            },
+        }
+    }
+    let download_state = app.download_cache.entry(video_id.clone()).or_default();
+    let mut state = download_state.lock().unwrap();
+    if state.worker_status.is_busy() {
+        return Ok(HttpResponse::Ok().json(DeleteResponse::Busy));
+    }
+    let entry = app.job_store.select_ytdlp_entry(&video_id).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry else { return Ok(HttpResponse::NotFound().finish()); };
+    if !identity.can_modify(entry.owner) {
+        return Err(ApiError::not_owner(video_id.as_str().to_owned()).into());
+    }
+    // NOTE: A transcode requested since its others were moved to the trash keeps the download
+    let transcodes = app.job_store.select_video_ffmpeg_entries(&video_id).map_err(ApiError::internal_server)?;
+    if !transcodes.is_empty() {
+        return Ok(HttpResponse::Ok().json(DeleteResponse::Busy));
+    }
+    let log_backup_paths = get_log_backup_paths(&[&entry.stdout_log_path, &entry.stderr_log_path]);
+    let mut paths = vec![
+        entry.audio_path.clone(), entry.stdout_log_path.clone(), entry.stderr_log_path.clone(), entry.system_log_path.clone(),
+    ];
+    paths.extend(log_backup_paths);
+    paths.extend(find_subtitle_paths(&app.app_config.download, &video_id).iter().map(|path| path.to_str().map(|path| path.to_owned())));
+    let paths: Vec<String> = paths.into_iter().flatten().collect();
+    // NOTE: The lock is held while the files are moved so the download can't be started again until the row is removed
+    let (outcome, results) = move_to_trash(
+        &app, JobKind::Download, video_id.as_str(), entry.owner, entry.file_size_bytes, &entry, paths,
+        || app.job_store.delete_ytdlp_entry(&video_id),
+    ).map_err(ApiError::trash)?;
+    match outcome {
+        TrashOutcome::Moved(_) => *state = DownloadState::default(),
+        TrashOutcome::Pending => {
+            app.job_store.set_ytdlp_delete_pending(&video_id, true).map_err(ApiError::internal_server)?;
+        },
+    }
+    app.job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
+    transcode_results.extend(results);
+    Ok(HttpResponse::Ok().json(get_delete_response(outcome, transcode_results)))
+}
+
+#[actix_web::get("/delete_transcode/{video_id}/{extension}")]
+pub async fn delete_transcode(
+    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = params.into_inner().validate()?;
+    let transcode_key = TranscodeKey { video_id, audio_ext, variant };
+    let app = req.app_data::<AppState>().unwrap().clone();
+    match trash_transcode(&app, &identity, &transcode_key)? {
+        TranscodeTrashResult::NotFound => Ok(HttpResponse::NotFound().finish()),
+        TranscodeTrashResult::Busy => Ok(HttpResponse::Ok().json(DeleteResponse::Busy)),
+        TranscodeTrashResult::Trashed(outcome, results) => Ok(HttpResponse::Ok().json(get_delete_response(outcome, results))),
+    }
+}
+
+#[actix_web::get("/get_trash")]
+pub async fn get_trash(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entries = app.job_store.select_trash_entries().map_err(ApiError::internal_server)?;
+    let entries: Vec<_> = entries.into_iter().filter(|entry| identity.can_access(entry.owner)).collect();
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[actix_web::get("/restore/{trash_id}")]
+pub async fn restore_trash(req: HttpRequest, path: web::Path<i64>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let trash_id = path.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entry = app.job_store.select_trash_entry(trash_id).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry else { return Err(ApiError::not_found(trash_id.to_string()).into()); };
+    if !identity.can_modify(entry.owner) {
+        return Err(ApiError::not_owner(entry.key).into());
+    }
+    restore_trash_entry(&app, &entry).map_err(ApiError::trash)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct RequestHlsResponse {
+    download_status: WorkerStatus,
+    hls_status: WorkerStatus,
+}
+
+#[actix_web::get("/request_hls/{video_id}")]
+pub async fn request_hls(
+    req: HttpRequest, path: web::Path<String>, download_options: web::Query<DownloadOptions>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let download_options = app.app_config.download_options.with_overrides(&download_options);
+    download_options.validate(&app.app_config.allowed_extractor_args).map_err(ApiError::invalid_download_options)?;
+    let entry = app.job_store.select_ytdlp_entry(&video_id).map_err(ApiError::internal_server)?;
+    if entry.is_some_and(|entry| !identity.can_access(entry.owner)) {
+        return Err(ApiError::not_owner(video_id.as_str().to_owned()).into());
+    }
+    let metadata = get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await.ok();
+    let download_options = check_live_status(&app, &video_id, metadata.as_deref(), download_options)?;
+    let hls_status = app.hls_cache.get(&video_id).map(|state| state.lock().unwrap().worker_status);
+    if is_queue_full(&app) && !is_job_accepted(hls_status) {
+        return get_queue_full_response(&app);
+    }
+    let is_new_conversion = !is_job_accepted(hls_status)
+        && !is_playlist_finished(&get_hls_directory(&app.app_config, &video_id).join(PLAYLIST_FILENAME));
+    let quota_user_id = if is_new_conversion { check_conversion_quota(&app, &identity)? } else { None };
+    let download_status = try_start_download_worker(
+        video_id.clone(),
+        app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        download_options, identity.owner(),
+    ).map_err(ApiError::download_start)?;
+    let hls_status = try_start_hls_worker(
+        video_id.clone(),
+        app.download_cache.clone(), app.hls_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+    );
+    record_conversion(&app, quota_user_id, hls_status, JobKind::Hls, video_id.as_str());
+    Ok(HttpResponse::Ok().json(RequestHlsResponse { download_status, hls_status }))
+}
+
+#[actix_web::get("/delete_hls/{video_id}")]
+pub async fn delete_hls(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    // NOTE: Hls streams are generated from the download so they belong to its owner
+    let entry = app.job_store.select_ytdlp_entry(&video_id).map_err(ApiError::internal_server)?;
+    if !identity.can_modify(entry.and_then(|entry| entry.owner)) {
+        return Err(ApiError::not_owner(video_id.as_str().to_owned()).into());
+    }
+    let hls_state = app.hls_cache.entry(video_id.clone()).or_default();
+    let mut state = hls_state.lock().unwrap();
+    if state.worker_status.is_busy() {
+        return Ok(HttpResponse::Ok().json(DeleteResponse::Busy));
+    }
+    *state = TranscodeState::default();
+    app.job_events.publish(JobId::new(JobKind::Hls, video_id.as_str()));
+    drop(state);
+    drop(hls_state);
+    let hls_directory = get_hls_directory(&app.app_config, &video_id);
+    if !hls_directory.exists() { return Ok(HttpResponse::NotFound().finish()); }
+    let filename = hls_directory.to_string_lossy().to_string();
+    let path = match std::fs::remove_dir_all(&hls_directory) {
+        Ok(()) => DeleteFileResult::Success { filename },
+        Err(err) => DeleteFileResult::Failure { filename, reason: err.to_string() },
+    };
+    Ok(HttpResponse::Ok().json(DeleteResponse::Success { trash_id: None, paths: vec![path] }))
+}
+
+#[actix_web::get("/get_downloads")]
+pub async fn get_downloads(req: HttpRequest, filter: web::Query<EntryFilter>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let mut entries = app.job_store.select_filtered_ytdlp_entries(&filter).map_err(ApiError::internal_server)?;
+    entries.retain(|entry| identity.can_access(entry.owner));
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[actix_web::get("/get_transcodes")]
+pub async fn get_transcodes(req: HttpRequest, filter: web::Query<EntryFilter>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let mut entries = app.job_store.select_filtered_ffmpeg_entries(&filter).map_err(ApiError::internal_server)?;
+    entries.retain(|entry| identity.can_access(entry.owner));
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[actix_web::get("/get_download/{video_id}")]
+pub async fn get_download(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
     let video_id = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let app = req.app_data::<AppState>().unwrap().clone();
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entry = select_ytdlp_entry(&db_conn, &video_id).map_err(ApiError::internal_server)?;
-    let Some(entry) = entry else {
+    let entry = app.job_store.select_ytdlp_entry(&video_id).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry.filter(|entry| identity.can_access(entry.owner)) else {
         return Ok(HttpResponse::NotFound().finish());
     };
     Ok(HttpResponse::Ok().json(entry))
 }
 
 #[actix_web::get("/get_transcode/{video_id}/{extension}")]
-pub async fn get_transcode(req: HttpRequest, path: web::Path<(String, String)>) -> actix_web::Result<HttpResponse> {
+pub async fn get_transcode(
+    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
     let (video_id, audio_ext) = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = params.into_inner().validate()?;
     let app = req.app_data::<AppState>().unwrap().clone();
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entry = select_ffmpeg_entry(&db_conn, &video_id, audio_ext).map_err(ApiError::internal_server)?;
-    let Some(entry) = entry else {
+    let entry = app.job_store.select_ffmpeg_entry(&video_id, audio_ext, variant.as_str()).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry.filter(|entry| identity.can_access(entry.owner)) else {
         return Ok(HttpResponse::NotFound().finish());
     };
     Ok(HttpResponse::Ok().json(entry))
 }
 
-#[actix_web::get("/get_download_state/{video_id}")]
-pub async fn get_download_state(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+/// Re-hashes the downloaded file so clients can detect bit-rot or partial writes before using it
+#[actix_web::get("/verify_download/{video_id}")]
+pub async fn verify_download(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
     let video_id = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let app = req.app_data::<AppState>().unwrap().clone();
-    if let Some(download_state) = app.download_cache.get(&video_id) {
-        let download_state = download_state.0.lock().unwrap();
-        if download_state.worker_status != WorkerStatus::None {
-            return Ok(HttpResponse::Ok().json(download_state.clone()));
+    let entry = app.job_store.select_ytdlp_entry(&video_id).map_err(ApiError::internal_server)?;
+    let Some(audio_path) = entry.as_ref()
+        .filter(|entry| entry.status == WorkerStatus::Finished && identity.can_access(entry.owner))
+        .and_then(|entry| entry.audio_path.as_deref())
+    else {
+        return Err(ApiError::not_found(video_id.as_str().to_owned()).into());
+    };
+    let expected_hash = entry.as_ref().and_then(|entry| entry.source_hash.as_deref());
+    let result = verify_file(&app.app_config, audio_path, expected_hash).await.map_err(ApiError::verify_failed)?;
+    if result.status == VerifyStatus::Mismatch || result.status == VerifyStatus::Missing {
+        log::warn!("Download failed verification: id={0}, status={1:?}", video_id.as_str(), result.status);
+    }
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// Re-hashes the transcoded file so clients can detect bit-rot or partial writes before serving it
+#[actix_web::get("/verify/{video_id}/{extension}")]
+pub async fn verify_transcode(
+    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = params.into_inner().validate()?;
+    let transcode_key = TranscodeKey { video_id, audio_ext, variant };
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entry = app.job_store.select_ffmpeg_entry(&transcode_key.video_id, audio_ext, transcode_key.variant.as_str())
+        .map_err(ApiError::internal_server)?;
+    let Some(audio_path) = entry.as_ref()
+        .filter(|entry| entry.status == WorkerStatus::Finished && identity.can_access(entry.owner))
+        .and_then(|entry| entry.audio_path.as_deref())
+    else {
+        return Err(ApiError::not_found(transcode_key.as_str()).into());
+    };
+    let expected_hash = entry.as_ref().and_then(|entry| entry.file_hash.as_deref());
+    let result = verify_file(&app.app_config, audio_path, expected_hash).await.map_err(ApiError::verify_failed)?;
+    if result.status == VerifyStatus::Mismatch || result.status == VerifyStatus::Missing {
+        log::warn!("Transcode failed verification: id={0}, status={1:?}", transcode_key.as_str(), result.status);
+    }
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// Download of the video if it belongs to the user or is shared
+// NOTE: Entries of other users are reported as missing so their video ids aren't leaked
+fn find_accessible_download(app: &AppState, identity: &Identity, video_id: &VideoId) -> Result<Option<YtdlpRow>, ApiError> {
+    let entry = app.job_store.select_ytdlp_entry(video_id).map_err(ApiError::internal_server)?;
+    Ok(entry.filter(|entry| identity.can_access(entry.owner)))
+}
+
+/// Transcode if it belongs to the user or is shared
+fn find_accessible_transcode(app: &AppState, identity: &Identity, key: &TranscodeKey) -> Result<Option<FfmpegRow>, ApiError> {
+    let entry = app.job_store.select_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str()).map_err(ApiError::internal_server)?;
+    Ok(entry.filter(|entry| identity.can_access(entry.owner)))
+}
+
+/// Responds with 304 if the poller already has this revision of the state so it isn't serialised again
+fn get_state_response<T: Serialize>(req: &HttpRequest, revision: u64, state: &T) -> HttpResponse {
+    let etag = get_revision_etag(revision);
+    if is_not_modified(req, &etag) {
+        return HttpResponse::NotModified().insert_header((ETAG, etag.to_string())).finish();
+    }
+    HttpResponse::Ok().insert_header((ETAG, etag.to_string())).json(state)
+}
+
+#[derive(Deserialize)]
+struct WaitParams {
+    /// Seconds to wait for the state to change if the client already has its current revision
+    #[serde(default)]
+    wait: u64,
+}
+
+const MAX_WAIT_SECONDS: u64 = 60;
+
+/// Long polls by waiting until the revision differs from the one in If-None-Match or the timeout elapses
+async fn wait_for_state_change<T: Clone>(
+    req: &HttpRequest, job_events: &JobEventBus, job: JobId, entry: WorkerCacheEntry<T>, wait_seconds: u64, get_revision: fn(&T) -> u64,
+) -> T {
+    let Some(if_none_match) = req.get_header::<IfNoneMatch>().filter(|_| wait_seconds > 0) else {
+        return entry.lock().unwrap().clone();
+    };
+    let timeout = Duration::from_secs(wait_seconds.min(MAX_WAIT_SECONDS));
+    let mut events = job_events.subscribe(job);
+    let wait = async {
+        loop {
+            let state = entry.lock().unwrap().clone();
+            if !is_etag_matched(Some(&if_none_match), &get_revision_etag(get_revision(&state))) {
+                return;
+            }
+            if !events.changed().await {
+                return;
+            }
         }
+    };
+    let _ = actix_web::rt::time::timeout(timeout, wait).await;
+    let state = entry.lock().unwrap().clone();
+    state
+}
+
+/// Pass ?wait= with If-None-Match to wait for the next change instead of polling
+#[actix_web::get("/get_download_state/{video_id}")]
+pub async fn get_download_state(
+    req: HttpRequest, path: web::Path<String>, wait_params: web::Query<WaitParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let Some(entry) = find_accessible_download(&app, &identity, &video_id)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let Some(download_state) = app.download_cache.get(&video_id).map(|state| state.clone()) else {
+        return Ok(get_stored_download_state(&req, &entry));
+    };
+    let download_job = JobId::new(JobKind::Download, video_id.as_str());
+    let download_state = wait_for_state_change(
+        &req, &app.job_events, download_job, download_state, wait_params.wait, |state| state.revision,
+    ).await;
+    if download_state.worker_status == WorkerStatus::None {
+        return Ok(get_stored_download_state(&req, &entry));
+    }
+    Ok(get_state_response(&req, download_state.revision, &download_state))
+}
+
+/// Answers from the database when the download ended before a restart or its state was evicted from the cache
+fn get_stored_download_state(req: &HttpRequest, entry: &YtdlpRow) -> HttpResponse {
+    match DownloadState::from_entry(entry) {
+        Some(state) => get_state_response(req, state.revision, &state),
+        None => HttpResponse::NotFound().finish(),
     }
-    Ok(HttpResponse::NotFound().finish())
 }
 
+/// Pass ?wait= with If-None-Match to wait for the next change instead of polling
 #[actix_web::get("/get_transcode_state/{video_id}/{extension}")]
-pub async fn get_transcode_state(req: HttpRequest, path: web::Path<(String, String)>) -> actix_web::Result<HttpResponse> {
+pub async fn get_transcode_state(
+    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<VariantParams>, wait_params: web::Query<WaitParams>,
+    identity: Identity,
+) -> actix_web::Result<HttpResponse> {
     let (video_id, audio_ext) = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
-    let transcode_key = TranscodeKey { video_id: video_id.clone(), audio_ext };
+    let variant = params.into_inner().validate()?;
+    let transcode_key = TranscodeKey { video_id, audio_ext, variant };
     let app = req.app_data::<AppState>().unwrap().clone();
-    if let Some(transcode_state) = app.transcode_cache.get(&transcode_key) {
-        let transcode_state = transcode_state.0.lock().unwrap();
-        if transcode_state.worker_status != WorkerStatus::None {
-            return Ok(HttpResponse::Ok().json(transcode_state.clone()));
-        }
+    let Some(entry) = find_accessible_transcode(&app, &identity, &transcode_key)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let Some(transcode_state) = app.transcode_cache.get(&transcode_key).map(|state| state.clone()) else {
+        return Ok(get_stored_transcode_state(&req, &entry));
+    };
+    let transcode_job = JobId::new(JobKind::Transcode, transcode_key.as_str());
+    let transcode_state = wait_for_state_change(
+        &req, &app.job_events, transcode_job, transcode_state, wait_params.wait, |state| state.revision,
+    ).await;
+    if transcode_state.worker_status == WorkerStatus::None {
+        return Ok(get_stored_transcode_state(&req, &entry));
     }
-    Ok(HttpResponse::NotFound().finish())
+    Ok(get_state_response(&req, transcode_state.revision, &transcode_state))
 }
 
-#[derive(Deserialize)]
-struct DownloadLinkParams {
-    name: String,
+/// Answers from the database when the transcode ended before a restart or its state was evicted from the cache
+fn get_stored_transcode_state(req: &HttpRequest, entry: &FfmpegRow) -> HttpResponse {
+    match TranscodeState::from_entry(entry) {
+        Some(state) => get_state_response(req, state.revision, &state),
+        None => HttpResponse::NotFound().finish(),
+    }
 }
 
-#[actix_web::get("/get_download_link/{video_id}/{extension}")]
-pub async fn get_download_link(
-    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<DownloadLinkParams>,
-) -> actix_web::Result<actix_files::NamedFile> {
+/// Combines the download and transcode states, falling back to the database if the transcode finished before a restart
+// NOTE: The caller checks the transcode can be accessed since the caches hold the jobs of every user
+fn find_job_state(app: &AppState, key: &TranscodeKey, transcode: &FfmpegRow) -> Option<JobState> {
+    let download_state = app.download_cache.get(&key.video_id).map(|state| state.lock().unwrap().clone());
+    let transcode_state = app.transcode_cache.get(key)
+        .map(|state| state.lock().unwrap().clone())
+        .filter(|state| state.worker_status != WorkerStatus::None)
+        .or_else(|| {
+            if transcode.status != WorkerStatus::Finished || transcode.audio_path.is_none() {
+                return None;
+            }
+            TranscodeState::from_entry(transcode)
+        })?;
+    JobState::new(download_state.as_ref(), &transcode_state)
+}
+
+#[actix_web::get("/get_job_state/{video_id}/{extension}")]
+pub async fn get_job_state(
+    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
     let (video_id, audio_ext) = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = params.into_inner().validate()?;
+    let transcode_key = TranscodeKey { video_id, audio_ext, variant };
     let app = req.app_data::<AppState>().unwrap().clone();
-    let db_conn = app.db_pool.get().map_err(ApiError::internal_server)?;
-    let entry = select_ffmpeg_entry(&db_conn, &video_id, audio_ext).map_err(ApiError::internal_server)?;
-    let Some(entry) = entry else {
-        return Err(error::ErrorNotFound(format!("{0}/{1}", video_id.as_str(), audio_ext.as_str())));
-    };
-    let Some(audio_path) = entry.audio_path else {
-        return Err(error::ErrorNotFound(format!("{0}/{1}", video_id.as_str(), audio_ext.as_str())));
-    };
-    let audio_path = PathBuf::from(audio_path);
-    let file = actix_files::NamedFile::open(audio_path)?;
-    // NOTE: You are supposed to use DispositionParam::FilenameExt to specify non-ascii charsets
-    //       However I cannot figure out which one to use, and most available sites use nonstandard
-    //       filename param to encode utf8 charsets (this is because its only required for
-    //       backwards compatibility and most modern browsers dont care about this)
-    let attachment = file
-        .use_last_modified(true)
-        .set_content_disposition(ContentDisposition {
-            disposition: DispositionType::Attachment,
-            parameters: vec![DispositionParam::Filename(params.name.clone())],
-        });
-    Ok(attachment)
+    let Some(transcode) = find_accessible_transcode(&app, &identity, &transcode_key)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    match find_job_state(&app, &transcode_key, &transcode) {
+        Some(state) => Ok(HttpResponse::Ok().json(state)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
 }
 
-#[actix_web::get("/get_metadata/{video_id}")]
-pub async fn get_metadata(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+#[derive(Debug,Clone,Serialize)]
+struct GetJobResponse {
+    job: JobRow,
+    /// Combined status and progress of the download and transcode
+    state: Option<JobState>,
+    download_state: Option<DownloadState>,
+    transcode_state: Option<TranscodeState>,
+    transcode: Option<FfmpegRow>,
+}
+
+/// Tracks a request by the job_id returned from /request_transcode
+#[actix_web::get("/get_job/{job_id}")]
+pub async fn get_job(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let job_id = path.into_inner();
+    if !is_valid_job_id(job_id.as_str()) {
+        return Err(ApiError::invalid_job_id(job_id).into());
+    }
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let job = app.job_store.select_job_entry(job_id.as_str()).map_err(ApiError::internal_server)?;
+    let Some(job) = job else { return Ok(HttpResponse::NotFound().finish()); };
+    // NOTE: Jobs belong to the owner of the transcode they requested
+    let transcode_key = TranscodeKey { video_id: job.video_id.clone(), audio_ext: job.audio_ext, variant: job.variant.clone() };
+    let Some(transcode) = find_accessible_transcode(&app, &identity, &transcode_key)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let download_state = app.download_cache.get(&job.video_id)
+        .map(|state| state.lock().unwrap().clone())
+        .filter(|state| state.worker_status != WorkerStatus::None);
+    let transcode_state = app.transcode_cache.get(&transcode_key)
+        .map(|state| state.lock().unwrap().clone())
+        .filter(|state| state.worker_status != WorkerStatus::None);
+    let state = find_job_state(&app, &transcode_key, &transcode);
+    Ok(HttpResponse::Ok().json(GetJobResponse { job, state, download_state, transcode_state, transcode: Some(transcode) }))
+}
+
+#[actix_web::get("/get_hls_state/{video_id}")]
+pub async fn get_hls_state(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
     let video_id = path.into_inner();
     let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
     let app = req.app_data::<AppState>().unwrap().clone();
-    let metadata = get_metadata_from_cache(video_id, app.metadata_cache).await.map_err(ApiError::internal_server)?;
-    Ok(HttpResponse::Ok().json(metadata.as_ref()))
+    // NOTE: Hls streams are generated from the download so they belong to its owner
+    if find_accessible_download(&app, &identity, &video_id)?.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if let Some(hls_state) = app.hls_cache.get(&video_id) {
+        let hls_state = hls_state.lock().unwrap();
+        if hls_state.worker_status != WorkerStatus::None {
+            return Ok(HttpResponse::Ok().json(hls_state.clone()));
+        }
+    }
+    Ok(HttpResponse::NotFound().finish())
+}
+
+#[derive(Deserialize)]
+struct DownloadLinkParams {
+    /// Defaults to a filename generated from the title and channel of the video
+    name: Option<String>,
+}
+
+fn get_default_filename(video_id: &VideoId, metadata: Option<&MetadataRow>) -> String {
+    metadata
+        .and_then(|metadata| get_title_filename(metadata.title.as_str(), metadata.channel.as_str()))
+        .unwrap_or_else(|| video_id.as_str().to_owned())
+}
+
+/// Returns where the transcode is stored and the filename it should be served as
+fn find_transcode_file(
+    app: &AppState, identity: &Identity, video_id: &VideoId, audio_ext: AudioExtension, variant: &str, name: Option<String>,
+) -> actix_web::Result<(FfmpegRow, String, String)> {
+    let entry = app.job_store.select_ffmpeg_entry(video_id, audio_ext, variant).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry.filter(|entry| identity.can_access(entry.owner)) else {
+        return Err(ApiError::not_found(format!("{0}/{1}", video_id.as_str(), audio_ext.as_str())).into());
+    };
+    let Some(audio_path) = entry.audio_path.clone() else {
+        return Err(ApiError::not_found(format!("{0}/{1}", video_id.as_str(), audio_ext.as_str())).into());
+    };
+    let name = match name {
+        Some(name) => name,
+        None => {
+            let metadata = app.job_store.select_metadata_entry(video_id).map_err(ApiError::internal_server)?;
+            format!("{0}.{1}", get_default_filename(video_id, metadata.as_ref()), audio_ext.as_str())
+        },
+    };
+    Ok((entry, audio_path, name))
+}
+
+/// Hash of the transcode used as its ETag
+// NOTE: Transcodes that finished before hashes were recorded are hashed on their first download
+async fn get_transcode_file_hash(app: &AppState, entry: &FfmpegRow, audio_path: &str) -> actix_web::Result<String> {
+    if let Some(ref file_hash) = entry.file_hash {
+        return Ok(file_hash.clone());
+    }
+    let path = PathBuf::from(audio_path);
+    let file_hash = web::block(move || get_file_sha256(&path)).await??;
+    app.job_store.select_and_update_ffmpeg_entry(&entry.video_id, entry.audio_ext, entry.variant.as_str(), |entry| {
+        entry.file_hash = Some(file_hash.clone());
+    }).map_err(ApiError::internal_server)?;
+    Ok(file_hash)
 }
 
-async fn get_metadata_from_cache(video_id: VideoId, cache: MetadataCache) -> Result<Arc<Metadata>, Box<dyn std::error::Error>> {
-    if let Some(metadata) = cache.get(&video_id) {
-        return Ok(metadata.clone());
+/// Serves the transcode from disk or from object storage if it was uploaded
+// NOTE: Range requests let the player seek and interrupted downloads resume without fetching the whole file
+//       Proxied objects forward the range to the bucket for the same reason
+async fn serve_transcode_file(
+    req: &HttpRequest, app: &AppState, entry: &FfmpegRow, audio_path: String, name: &str, disposition: DispositionType,
+) -> actix_web::Result<HttpResponse> {
+    let content_type = entry.audio_ext.mime_type();
+    let content_disposition = get_content_disposition(disposition, name);
+    if !is_object_url(audio_path.as_str()) {
+        let file = tokio::fs::File::open(audio_path.as_str()).await?;
+        let etag = get_file_etag(get_transcode_file_hash(app, entry, audio_path.as_str()).await?.as_str());
+        return Ok(serve_file(req, file, &etag, content_type, content_disposition).await?);
+    }
+    let Some(ref storage) = app.app_config.object_storage else {
+        let err = StorageError::NotConfigured(audio_path);
+        return Err(ApiError::storage(err).into());
+    };
+    if !storage.proxy_downloads {
+        let url = storage.presign_download(audio_path.as_str(), content_type, content_disposition.to_string().as_str())
+            .map_err(ApiError::storage)?;
+        return Ok(HttpResponse::Found().insert_header((LOCATION, url)).finish());
+    }
+    // NOTE: The bucket has its own ETag so ours is only available if the hash was recorded before uploading
+    let etag = entry.file_hash.as_deref().map(get_file_etag);
+    if let Some(ref etag) = etag {
+        if is_not_modified(req, etag) {
+            return Ok(HttpResponse::NotModified().insert_header((ETAG, etag.to_string())).finish());
+        }
+    }
+    let range = match etag {
+        Some(ref etag) => get_range(req, etag).map(|range| range.as_bytes()),
+        None => req.headers().get(RANGE).map(|range| range.as_bytes()),
+    };
+    let response = storage.get_object(audio_path.as_str(), range).await.map_err(ApiError::storage)?;
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::OK);
+    let mut builder = HttpResponse::build(status);
+    builder
+        .content_type(content_type)
+        .insert_header(content_disposition);
+    if let Some(etag) = etag {
+        builder.insert_header((ETAG, etag.to_string()));
+    }
+    for name in [ACCEPT_RANGES, CONTENT_RANGE] {
+        if let Some(value) = response.headers().get(name.as_str()) {
+            builder.insert_header((name, value.as_bytes()));
+        }
+    }
+    let content_length = response.headers().get(CONTENT_LENGTH.as_str())
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(content_length) = content_length {
+        builder.no_chunking(content_length);
+    }
+    let stream = futures_util::stream::unfold(response, |mut response| async move {
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), response)),
+            Ok(None) => None,
+            Err(err) => Some((Err(err), response)),
+        }
+    });
+    Ok(builder.streaming(stream))
+}
+
+#[actix_web::get("/get_download_link/{video_id}/{extension}")]
+pub async fn get_download_link(
+    req: HttpRequest, path: web::Path<(String, String)>,
+    params: web::Query<DownloadLinkParams>, variant_params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = variant_params.into_inner().validate()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let (entry, audio_path, name) = find_transcode_file(&app, &identity, &video_id, audio_ext, variant.as_str(), params.into_inner().name)?;
+    serve_transcode_file(&req, &app, &entry, audio_path, name.as_str(), DispositionType::Attachment).await
+}
+
+/// Serves the transcode inline so it can be played by an <audio> element
+#[actix_web::get("/stream/{video_id}/{extension}")]
+pub async fn stream_transcode(
+    req: HttpRequest, path: web::Path<(String, String)>, variant_params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = variant_params.into_inner().validate()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let (entry, audio_path, name) = find_transcode_file(&app, &identity, &video_id, audio_ext, variant.as_str(), None)?;
+    serve_transcode_file(&req, &app, &entry, audio_path, name.as_str(), DispositionType::Inline).await
+}
+
+/// Serves the hls playlist and the segments it refers to relative to itself
+// NOTE: The playlist grows while ffmpeg is running so it shouldn't be cached by the player
+#[actix_web::get("/hls/{video_id}/{filename}")]
+pub async fn get_hls_file(
+    req: HttpRequest, path: web::Path<(String, String)>, identity: Identity,
+) -> actix_web::Result<actix_files::NamedFile> {
+    let (video_id, filename) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    if !is_hls_filename(filename.as_str()) {
+        return Err(ApiError::invalid_hls_file(filename).into());
+    }
+    let app = req.app_data::<AppState>().unwrap().clone();
+    if find_accessible_download(&app, &identity, &video_id)?.is_none() {
+        return Err(ApiError::not_found(video_id.as_str().to_owned()).into());
+    }
+    let path = get_hls_directory(&app.app_config, &video_id).join(filename.as_str());
+    let mime_type: actix_web::mime::Mime = get_hls_mime_type(filename.as_str()).parse().expect("Mime type should be valid");
+    let file = actix_files::NamedFile::open(path)?
+        .use_etag(false)
+        .use_last_modified(false)
+        .set_content_type(mime_type)
+        .set_content_disposition(ContentDisposition { disposition: DispositionType::Inline, parameters: vec![] });
+    Ok(file)
+}
+
+/// Serves downloads and transcodes from the data directory to users who can access them
+// NOTE: Anything that isn't the audio of an entry such as logs and the database is hidden behind a 404
+#[actix_web::get("/data/{path:.*}")]
+pub async fn get_data_file(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let path = path.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let data_file = web::block(move || {
+        find_data_file(&app.app_config, &app.job_store, path.as_str(), |owner| identity.can_access(owner))
+    }).await?.map_err(ApiError::data_file)?;
+    match data_file {
+        // NOTE: Links in the listing are relative to the directory
+        DataFile::Directory(_) if !req.path().ends_with('/') => {
+            Ok(HttpResponse::PermanentRedirect().insert_header((LOCATION, format!("{0}/", req.path()))).finish())
+        },
+        DataFile::Directory(entries) => {
+            let html = render_data_listing(req.path(), entries.as_slice());
+            Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+        },
+        DataFile::File(full_path) => {
+            let file = web::block(move || actix_files::NamedFile::open(full_path)).await??;
+            Ok(file.into_response(&req))
+        },
+    }
+}
+
+/// Peaks for drawing a seek bar which are generated on demand for transcodes that finished without one
+#[actix_web::get("/get_waveform/{video_id}/{extension}")]
+pub async fn get_waveform(
+    req: HttpRequest, path: web::Path<(String, String)>, variant_params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let variant = variant_params.into_inner().validate()?;
+    let transcode_key = TranscodeKey { video_id, audio_ext, variant };
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entry = find_accessible_transcode(&app, &identity, &transcode_key)?;
+    let Some(audio_path) = entry.filter(|entry| entry.status == WorkerStatus::Finished).and_then(|entry| entry.audio_path) else {
+        return Err(ApiError::not_found(transcode_key.as_str()).into());
+    };
+    let waveform_path = get_waveform_path(&transcode_key, &app.app_config);
+    let app_config = app.app_config.clone();
+    let waveform = web::block(move || -> Result<Waveform, ApiError> {
+        if let Ok(waveform) = Waveform::load(&waveform_path) {
+            return Ok(waveform);
+        }
+        // NOTE: Uploaded transcodes only have the waveform generated before they were uploaded
+        if is_object_url(audio_path.as_str()) {
+            return Err(ApiError::not_found(waveform_path.to_string_lossy().into_owned()));
+        }
+        let waveform = app_config.get_transcoder().generate_waveform(&PathBuf::from(audio_path), &app_config.process_priority).map_err(ApiError::internal_server)?;
+        waveform.save(&waveform_path).map_err(ApiError::internal_server)?;
+        Ok(waveform)
+    }).await??;
+    Ok(HttpResponse::Ok().json(waveform))
+}
+
+/// Uses the utf8 filename* param from RFC 5987 for non-ascii names
+/// The filename param is kept as an ascii fallback for browsers that don't support it
+fn get_content_disposition(disposition: DispositionType, name: &str) -> ContentDisposition {
+    let ascii_name: String = name.chars().map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '_' }).collect();
+    let mut parameters = vec![DispositionParam::Filename(ascii_name)];
+    if !name.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_owned()),
+            language_tag: None,
+            value: name.as_bytes().to_vec(),
+        }));
+    }
+    ContentDisposition {
+        disposition,
+        parameters,
+    }
+}
+
+#[derive(Deserialize)]
+struct ArchiveParams {
+    /// Comma separated list of transcodes as video_id.ext or video_id.variant.ext
+    files: String,
+    name: Option<String>,
+}
+
+fn parse_archive_file(file: &str) -> Result<TranscodeKey, ApiError> {
+    let parts: Vec<&str> = file.split('.').collect();
+    let (video_id, variant, audio_ext) = match parts.as_slice() {
+        [video_id, audio_ext] => (*video_id, "", *audio_ext),
+        [video_id, variant, audio_ext] => (*video_id, *variant, *audio_ext),
+        _ => return Err(ApiError::invalid_archive_file(file.to_owned())),
+    };
+    let video_id = VideoId::try_new(video_id).map_err(|e| ApiError::invalid_video_id(video_id.to_owned(), e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext).map_err(|_| ApiError::invalid_audio_extension(audio_ext.to_owned()))?;
+    if !TranscodeOptions::is_valid_variant(variant) {
+        return Err(ApiError::invalid_variant(variant.to_owned()));
+    }
+    Ok(TranscodeKey { video_id, audio_ext, variant: variant.to_owned() })
+}
+
+#[actix_web::get("/get_archive")]
+pub async fn get_archive(
+    req: HttpRequest, params: web::Query<ArchiveParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let params = params.into_inner();
+    let keys = params.files.split(',')
+        .map(|file| file.trim())
+        .filter(|file| !file.is_empty())
+        .map(parse_archive_file)
+        .collect::<Result<Vec<_>, _>>()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let mut entries = Vec::<ArchiveEntry>::new();
+    for key in keys {
+        let entry = app.job_store.select_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str())
+            .map_err(ApiError::internal_server)?;
+        let audio_path = entry
+            .filter(|entry| entry.status == WorkerStatus::Finished && identity.can_access(entry.owner))
+            .and_then(|entry| entry.audio_path);
+        let Some(audio_path) = audio_path else {
+            return Err(ApiError::not_found(key.as_str()).into());
+        };
+        // NOTE: The archive is built from seekable local files so its size is known upfront
+        if is_object_url(audio_path.as_str()) {
+            return Err(ApiError::remote_file(key.as_str()).into());
+        }
+        let metadata = app.job_store.select_metadata_entry(&key.video_id).map_err(ApiError::internal_server)?;
+        let name = get_default_filename(&key.video_id, metadata.as_ref());
+        // NOTE: Multiple variants of the same video would otherwise have the same filename
+        let mut filename = format!("{0}.{1}", name, key.audio_ext.as_str());
+        let mut total_duplicates = 0;
+        while entries.iter().any(|entry| entry.name == filename) {
+            total_duplicates += 1;
+            filename = format!("{0} ({1}).{2}", name, total_duplicates, key.audio_ext.as_str());
+        }
+        let entry = ArchiveEntry::open(filename, PathBuf::from(audio_path))
+            .map_err(|_| ApiError::not_found(key.as_str()))?;
+        entries.push(entry);
+    }
+    let stream = ZipStream::new(entries).map_err(ApiError::invalid_archive)?;
+    let total_size = stream.total_size();
+    let name = params.name.unwrap_or_else(|| "archive.zip".to_owned());
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(get_content_disposition(DispositionType::Attachment, name.as_str()))
+        // NOTE: Content-Length lets the browser show download progress
+        .no_chunking(total_size)
+        .streaming(stream.into_stream().map(|chunk| chunk.map(web::Bytes::from))))
+}
+
+#[derive(Deserialize)]
+struct RefreshMetadataParams {
+    #[serde(default)]
+    retag: bool,
+}
+
+#[derive(Serialize)]
+struct RefreshMetadataResponse<'a> {
+    metadata: &'a Metadata,
+    /// Transcodes that are encoded again to embed the new metadata
+    retagged: Vec<String>,
+}
+
+/// Fetches the metadata again so renamed titles and channels are picked up
+/// Pass ?retag=true to also encode the finished transcodes of the video that you own again with the new metadata
+#[actix_web::get("/refresh_metadata/{video_id}")]
+pub async fn refresh_metadata(
+    req: HttpRequest, path: web::Path<String>, params: web::Query<RefreshMetadataParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let metadata = fetch_metadata(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await
+        .map_err(ApiError::internal_server)?;
+    let mut retagged = Vec::new();
+    if params.retag {
+        let entries = app.job_store.select_ffmpeg_entries().map_err(ApiError::internal_server)?;
+        let entries = entries.into_iter().filter(|entry| {
+            entry.video_id == video_id && entry.status == WorkerStatus::Finished && identity.can_modify(entry.owner)
+        });
+        for entry in entries {
+            let key = TranscodeKey { video_id: video_id.clone(), audio_ext: entry.audio_ext, variant: entry.variant.clone() };
+            // NOTE: Transcodes from before their options were stored used the default options
+            let options = match entry.transcode_options.as_deref().map(serde_json::from_str::<TranscodeOptions>) {
+                Some(Ok(options)) => options,
+                Some(Err(err)) => {
+                    log::warn!("Skipped retagging transcode with invalid options: key={0}, err={err}", key.as_str());
+                    continue;
+                },
+                None => TranscodeOptions::default(),
+            };
+            let old_audio_path = match reset_transcode(&app, &key, true) {
+                Ok(old_audio_path) => old_audio_path,
+                Err(err) => {
+                    log::warn!("Skipped retagging transcode: key={0}, err={1}", key.as_str(), err.error);
+                    continue;
+                },
+            };
+            remove_transcode_output(&app, &key, old_audio_path).await?;
+            start_transcode_job(
+                &app, &identity, &key, options, app.app_config.download_options.clone(), Some(metadata.clone()), None, None, None,
+            )?;
+            retagged.push(key.as_str());
+        }
+    }
+    Ok(HttpResponse::Ok().json(RefreshMetadataResponse { metadata: metadata.as_ref(), retagged }))
+}
+
+#[actix_web::get("/get_metadata/{video_id}")]
+pub async fn get_metadata(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+    let video_id = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let metadata = get_metadata_from_cache(video_id, app.metadata_cache, &app.http_client, &app.job_store).await.map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(metadata.as_ref()))
+}
+
+fn get_file_size(path: Option<&String>) -> Option<u64> {
+    path.and_then(|path| std::fs::metadata(path).ok()).map(|metadata| metadata.len())
+}
+
+#[actix_web::get("/get_library")]
+pub async fn get_library(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let mut entries = app.job_store.select_library_entries().map_err(ApiError::internal_server)?;
+    for entry in entries.iter_mut() {
+        entry.download = entry.download.take().filter(|download| identity.can_access(download.owner));
+        entry.transcodes.retain(|transcode| identity.can_access(transcode.owner));
+    }
+    entries.retain(|entry| entry.download.is_some() || !entry.transcodes.is_empty());
+    // NOTE: Jobs that finished before file sizes were recorded need to be read from disk
+    for entry in entries.iter_mut() {
+        if let Some(ref mut download) = entry.download {
+            if download.file_size_bytes.is_none() {
+                download.file_size_bytes = get_file_size(download.audio_path.as_ref());
+            }
+        }
+        for transcode in entry.transcodes.iter_mut() {
+            if transcode.file_size_bytes.is_none() {
+                transcode.file_size_bytes = get_file_size(transcode.audio_path.as_ref());
+            }
+        }
+    }
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Debug,Default,Clone,Serialize)]
+struct StatsResponse {
+    total_queued_downloads: usize,
+    total_running_downloads: usize,
+    total_queued_transcodes: usize,
+    total_running_transcodes: usize,
+    download_speed_bytes: usize,
+    download_smoothed_speed_bytes: usize,
+}
+
+#[actix_web::get("/stats")]
+pub async fn get_stats(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let mut stats = StatsResponse::default();
+    for entry in app.download_cache.iter() {
+        let state = entry.value().lock().unwrap();
+        match state.worker_status {
+            WorkerStatus::Queued => stats.total_queued_downloads += 1,
+            WorkerStatus::Running => {
+                stats.total_running_downloads += 1;
+                stats.download_speed_bytes += state.speed_bytes.unwrap_or(0);
+                stats.download_smoothed_speed_bytes += state.smoothed_speed_bytes.unwrap_or(0);
+            },
+            WorkerStatus::None | WorkerStatus::Finished | WorkerStatus::Failed => {},
+        }
+    }
+    for entry in app.transcode_cache.iter() {
+        let state = entry.value().lock().unwrap();
+        match state.worker_status {
+            WorkerStatus::Queued => stats.total_queued_transcodes += 1,
+            WorkerStatus::Running => stats.total_running_transcodes += 1,
+            WorkerStatus::None | WorkerStatus::Finished | WorkerStatus::Failed => {},
+        }
+    }
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct SystemLimits {
+    total_transcode_threads: usize,
+    max_pending_jobs: Option<usize>,
+    quota_storage_bytes: Option<u64>,
+    quota_daily_conversions: Option<u64>,
+    thumbnail_max_size: u32,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct SystemDirectories {
+    root: DirectoryInfo,
+    data: DirectoryInfo,
+    download: DirectoryInfo,
+    transcode: DirectoryInfo,
+    hls: DirectoryInfo,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct SystemInfoResponse {
+    server_version: &'static str,
+    tools: ToolVersions,
+    limits: SystemLimits,
+    auth_enabled: bool,
+    pipeline_transcode: bool,
+    object_storage: bool,
+    /// Only admins can see where files are kept on the server
+    directories: Option<SystemDirectories>,
+}
+
+/// Versions and settings of the server for diagnosing mismatched yt-dlp or ffmpeg versions
+#[actix_web::get("/system_info")]
+pub async fn get_system_info(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let is_admin = identity.is_admin();
+    let directories = web::block({
+        let app = app.clone();
+        // NOTE: Free space is queried on every request since it changes as files are downloaded
+        move || {
+            let config = &app.app_config;
+            is_admin.then(|| SystemDirectories {
+                root: DirectoryInfo::new(&config.root),
+                data: DirectoryInfo::new(&config.data),
+                download: DirectoryInfo::new(&config.download),
+                transcode: DirectoryInfo::new(&config.transcode),
+                hls: DirectoryInfo::new(&config.hls),
+            })
+        }
+    }).await?;
+    let config = &app.app_config;
+    Ok(HttpResponse::Ok().json(SystemInfoResponse {
+        server_version: env!("CARGO_PKG_VERSION"),
+        tools: app.tool_versions.as_ref().clone(),
+        limits: SystemLimits {
+            total_transcode_threads: app.worker_thread_pool.total_threads(),
+            max_pending_jobs: config.max_pending_jobs,
+            quota_storage_bytes: config.quota_storage_bytes,
+            quota_daily_conversions: config.quota_daily_conversions,
+            thumbnail_max_size: config.thumbnail_max_size,
+        },
+        auth_enabled: config.auth_enabled,
+        pipeline_transcode: config.pipeline_transcode,
+        object_storage: config.object_storage.is_some(),
+        directories,
+    }))
+}
+
+#[derive(Deserialize)]
+struct AdminStatsParams {
+    days: Option<u64>,
+    top_channels: Option<usize>,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct JobFailureStats {
+    kind: JobKind,
+    error_category: String,
+    total: u64,
+    /// Fraction of all jobs of this kind that failed with this category
+    failure_rate: f64,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct JobSpeedStats {
+    kind: JobKind,
+    bytes_per_second: f64,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct AdminStatsResponse {
+    since_unix_time: u64,
+    jobs_per_day: Vec<JobsPerDayRow>,
+    failures: Vec<JobFailureStats>,
+    top_channels: Vec<ChannelCountRow>,
+    disk_usage: Vec<TableUsageRow>,
+    average_speeds: Vec<JobSpeedStats>,
+}
+
+/// Summarises the job history over the last few days
+#[actix_web::get("/admin/stats")]
+pub async fn get_admin_stats(
+    req: HttpRequest, params: web::Query<AdminStatsParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    const DEFAULT_DAYS: u64 = 30;
+    const MAX_DAYS: u64 = 365;
+    const DEFAULT_TOP_CHANNELS: usize = 10;
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let days = params.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    // NOTE: Align to midnight UTC so the first day isn't partially counted
+    let since_unix_time = (get_unix_time() / 86400).saturating_sub(days-1) * 86400;
+    let top_channels = params.top_channels.unwrap_or(DEFAULT_TOP_CHANNELS);
+    let jobs_per_day = app.job_store.select_jobs_per_day(since_unix_time).map_err(ApiError::internal_server)?;
+    let failures = app.job_store.select_job_failures(since_unix_time).map_err(ApiError::internal_server)?;
+    let top_channels = app.job_store.select_top_channels(since_unix_time, top_channels).map_err(ApiError::internal_server)?;
+    let disk_usage = app.job_store.select_table_usage().map_err(ApiError::internal_server)?;
+    let throughputs = app.job_store.select_job_throughputs(since_unix_time).map_err(ApiError::internal_server)?;
+    let failures = failures.into_iter().map(|row| {
+        let total_jobs: u64 = jobs_per_day.iter().filter(|day| day.kind == row.kind).map(|day| day.total).sum();
+        JobFailureStats {
+            kind: row.kind,
+            error_category: row.error_category,
+            total: row.total,
+            failure_rate: row.total as f64 / total_jobs.max(1) as f64,
+        }
+    }).collect();
+    let average_speeds = throughputs.into_iter().map(|row| JobSpeedStats {
+        kind: row.kind,
+        bytes_per_second: row.total_bytes as f64 * 1000.0 / row.total_elapsed_ms.max(1) as f64,
+    }).collect();
+    Ok(HttpResponse::Ok().json(AdminStatsResponse {
+        since_unix_time, jobs_per_day, failures, top_channels, disk_usage, average_speeds,
+    }))
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+enum LogStream {
+    Stdout,
+    Stderr,
+    System,
+}
+
+generate_bidirectional_binding!(
+    LogStream, &'static str, &str,
+    (Stdout, "stdout"),
+    (Stderr, "stderr"),
+    (System, "system"),
+);
+
+#[derive(Deserialize)]
+struct LogParams {
+    #[serde(default)]
+    follow: bool,
+}
+
+async fn stream_log_file(
+    path: PathBuf, is_follow: bool, is_busy: impl Fn() -> bool + 'static,
+) -> std::io::Result<HttpResponse> {
+    const CHUNK_SIZE: usize = 64*1024;
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+    let file = tokio::fs::File::open(path).await?;
+    let stream = futures_util::stream::unfold((file, is_busy, false), move |(mut file, is_busy, is_done)| {
+        async move {
+            if is_done {
+                return None;
+            }
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            loop {
+                let total_read = match file.read(buffer.as_mut_slice()).await {
+                    Ok(total_read) => total_read,
+                    Err(err) => return Some((Err(actix_web::Error::from(err)), (file, is_busy, true))),
+                };
+                if total_read > 0 {
+                    buffer.truncate(total_read);
+                    return Some((Ok(web::Bytes::from(buffer)), (file, is_busy, false)));
+                }
+                // NOTE: Check if worker is busy before reading again so we get any trailing output
+                if !is_follow || !is_busy() {
+                    let total_read = match file.read(buffer.as_mut_slice()).await {
+                        Ok(total_read) => total_read,
+                        Err(err) => return Some((Err(actix_web::Error::from(err)), (file, is_busy, true))),
+                    };
+                    if total_read == 0 {
+                        return None;
+                    }
+                    buffer.truncate(total_read);
+                    return Some((Ok(web::Bytes::from(buffer)), (file, is_busy, true)));
+                }
+                actix_web::rt::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentType::plaintext())
+        .streaming(stream))
+}
+
+#[actix_web::get("/get_download_log/{video_id}/{stream}")]
+pub async fn get_download_log(
+    req: HttpRequest, path: web::Path<(String, String)>, params: web::Query<LogParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, stream) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let stream = LogStream::try_from(stream.as_str()).map_err(|_| ApiError::invalid_log_stream(stream))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let Some(entry) = find_accessible_download(&app, &identity, &video_id)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let log_path = match stream {
+        LogStream::Stdout => entry.stdout_log_path,
+        LogStream::Stderr => entry.stderr_log_path,
+        LogStream::System => entry.system_log_path,
+    };
+    let Some(log_path) = log_path else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let download_cache = app.download_cache.clone();
+    let is_busy = move || {
+        download_cache.get(&video_id)
+            .map(|state| state.lock().unwrap().worker_status.is_busy())
+            .unwrap_or(false)
+    };
+    let response = stream_log_file(PathBuf::from(log_path), params.follow, is_busy).await?;
+    Ok(response)
+}
+
+#[actix_web::get("/get_transcode_log/{video_id}/{extension}/{stream}")]
+pub async fn get_transcode_log(
+    req: HttpRequest, path: web::Path<(String, String, String)>,
+    params: web::Query<LogParams>, variant_params: web::Query<VariantParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (video_id, audio_ext, stream) = path.into_inner();
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let stream = LogStream::try_from(stream.as_str()).map_err(|_| ApiError::invalid_log_stream(stream))?;
+    let variant = variant_params.into_inner().validate()?;
+    let transcode_key = TranscodeKey { video_id, audio_ext, variant };
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let Some(entry) = find_accessible_transcode(&app, &identity, &transcode_key)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let log_path = match stream {
+        LogStream::Stdout => entry.stdout_log_path,
+        LogStream::Stderr => entry.stderr_log_path,
+        LogStream::System => entry.system_log_path,
+    };
+    let Some(log_path) = log_path else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let transcode_cache = app.transcode_cache.clone();
+    let is_busy = move || {
+        transcode_cache.get(&transcode_key)
+            .map(|state| state.lock().unwrap().worker_status.is_busy())
+            .unwrap_or(false)
+    };
+    let response = stream_log_file(PathBuf::from(log_path), params.follow, is_busy).await?;
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<usize>,
+}
+
+#[actix_web::get("/search")]
+pub async fn search(req: HttpRequest, params: web::Query<SearchParams>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    const DEFAULT_LIMIT: usize = 50;
+    const MAX_LIMIT: usize = 1000;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    // NOTE: Users only find their own downloads and shared ones
+    let owner = if identity.is_admin() { None } else { identity.owner() };
+    let entries = app.job_store.search_metadata_entries(params.q.as_str(), owner, limit).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[actix_web::get("/subscriptions")]
+pub async fn get_subscriptions(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entries = app.job_store.select_subscription_entries().map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Deserialize)]
+struct CreateSubscriptionParams {
+    url: String,
+    check_interval_seconds: Option<u64>,
+}
+
+/// Subscribes to a channel or playlist so new uploads are transcoded to this format
+/// Only admins can manage subscriptions since their uploads are shared with every user
+#[actix_web::get("/subscriptions/create/{extension}")]
+pub async fn create_subscription(
+    req: HttpRequest, path: web::Path<String>,
+    params: web::Query<CreateSubscriptionParams>, transcode_options: web::Query<TranscodeOptions>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let audio_ext = path.into_inner();
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let params = params.into_inner();
+    validate_subscription_url(params.url.as_str()).map_err(ApiError::invalid_subscription)?;
+    let check_interval_seconds = params.check_interval_seconds.unwrap_or(DEFAULT_CHECK_INTERVAL_SECONDS);
+    validate_check_interval(check_interval_seconds).map_err(ApiError::invalid_subscription)?;
+    let transcode_options = transcode_options.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    let entries = app.job_store.select_subscription_entries().map_err(ApiError::internal_server)?;
+    if entries.iter().any(|entry| entry.url == params.url) {
+        return Err(ApiError::duplicate_subscription(params.url).into());
+    }
+    // NOTE: Existing videos are recorded as seen so subscribing doesn't download the entire history
+    //       This also checks that yt-dlp can list the url before it is saved
+    let video_ids = list_subscription_videos(&app, params.url.as_str()).await.map_err(ApiError::invalid_subscription)?;
+    let transcode_options = serde_json::to_string(&transcode_options).ok();
+    let id = app.job_store.insert_subscription_entry(
+        params.url.as_str(), audio_ext, transcode_options.as_deref(), check_interval_seconds,
+    ).map_err(ApiError::internal_server)?;
+    record_subscription_videos(&app, id, video_ids).map_err(ApiError::internal_server)?;
+    app.job_store.select_and_update_subscription_entry(id, |entry| {
+        entry.last_check_unix_time = Some(get_unix_time());
+    }).map_err(ApiError::internal_server)?;
+    let entry = app.job_store.select_subscription_entry(id).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[actix_web::get("/subscriptions/{id}")]
+pub async fn get_subscription(req: HttpRequest, path: web::Path<i64>) -> actix_web::Result<HttpResponse> {
+    let id = path.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entry = app.job_store.select_subscription_entry(id).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry else { return Ok(HttpResponse::NotFound().finish()); };
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[derive(Deserialize)]
+struct UpdateSubscriptionParams {
+    check_interval_seconds: Option<u64>,
+}
+
+/// Replaces the output format of future uploads, videos that were already transcoded are kept
+#[actix_web::get("/subscriptions/{id}/update/{extension}")]
+pub async fn update_subscription(
+    req: HttpRequest, path: web::Path<(i64, String)>,
+    params: web::Query<UpdateSubscriptionParams>, transcode_options: web::Query<TranscodeOptions>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let (id, audio_ext) = path.into_inner();
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    if let Some(check_interval_seconds) = params.check_interval_seconds {
+        validate_check_interval(check_interval_seconds).map_err(ApiError::invalid_subscription)?;
+    }
+    let transcode_options = transcode_options.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    let transcode_options = serde_json::to_string(&transcode_options).ok();
+    let total_updated = app.job_store.select_and_update_subscription_entry(id, |entry| {
+        entry.audio_ext = audio_ext;
+        entry.transcode_options = transcode_options;
+        if let Some(check_interval_seconds) = params.check_interval_seconds {
+            entry.check_interval_seconds = check_interval_seconds;
+        }
+    }).map_err(ApiError::internal_server)?;
+    if total_updated == 0 { return Ok(HttpResponse::NotFound().finish()); }
+    let entry = app.job_store.select_subscription_entry(id).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[actix_web::get("/subscriptions/{id}/delete")]
+pub async fn delete_subscription(req: HttpRequest, path: web::Path<i64>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let id = path.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let total_deleted = app.job_store.delete_subscription_entry(id).map_err(ApiError::internal_server)?;
+    if total_deleted == 0 { return Ok(HttpResponse::NotFound().finish()); }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Lists the uploads of a channel in the background and downloads the ones that match the filters
+#[actix_web::get("/request_channel/{channel_id}/{extension}")]
+pub async fn request_channel(
+    req: HttpRequest, path: web::Path<(String, String)>,
+    filters: web::Query<ChannelFilters>, transcode_options: web::Query<TranscodeOptions>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let (channel_id, audio_ext) = path.into_inner();
+    validate_channel_id(channel_id.as_str()).map_err(ApiError::invalid_channel)?;
+    let audio_ext = AudioExtension::try_from(audio_ext.as_str()).map_err(|_| ApiError::invalid_audio_extension(audio_ext))?;
+    let filters = filters.into_inner();
+    filters.validate().map_err(ApiError::invalid_channel)?;
+    let transcode_options = transcode_options.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    transcode_options.validate(&app.app_config.transcode_presets).map_err(ApiError::invalid_transcode_options)?;
+    if is_queue_full(&app) {
+        return get_queue_full_response(&app);
+    }
+    let quota_user_id = check_conversion_quota(&app, &identity)?;
+    let id = app.job_store.insert_channel_job_entry(
+        channel_id.as_str(), audio_ext, transcode_options.get_variant().as_str(),
+        serde_json::to_string(&transcode_options).ok().as_deref(), serde_json::to_string(&filters).ok().as_deref(),
+        identity.owner(),
+    ).map_err(ApiError::internal_server)?;
+    let entry = app.job_store.select_channel_job_entry(id).map_err(ApiError::internal_server)?
+        .ok_or_else(|| ApiError::not_found(id.to_string()))?;
+    actix_web::rt::spawn(run_channel_job(app.clone(), entry.clone(), quota_user_id));
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[actix_web::get("/channel_jobs")]
+pub async fn get_channel_jobs(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entries = app.job_store.select_channel_job_entries().map_err(ApiError::internal_server)?;
+    let entries: Vec<ChannelJobRow> = entries.into_iter().filter(|entry| identity.can_access(entry.owner)).collect();
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct GetChannelJobResponse {
+    #[serde(flatten)]
+    entry: ChannelJobRow,
+    videos: Vec<ChannelJobVideoRow>,
+}
+
+/// Progress of a channel job along with the download and transcode status of each of its videos
+#[actix_web::get("/channel_jobs/{id}")]
+pub async fn get_channel_job(req: HttpRequest, path: web::Path<i64>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let id = path.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entry = app.job_store.select_channel_job_entry(id).map_err(ApiError::internal_server)?;
+    let Some(entry) = entry else { return Ok(HttpResponse::NotFound().finish()); };
+    if !identity.can_access(entry.owner) {
+        return Err(ApiError::not_owner(id.to_string()).into());
+    }
+    let videos = app.job_store.select_channel_job_video_entries(id, entry.audio_ext, entry.variant.as_str())
+        .map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(GetChannelJobResponse { entry, videos }))
+}
+
+/// Dumps every table to json so the library can be backed up or imported on another machine
+#[actix_web::get("/admin/export")]
+pub async fn get_admin_export(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let export = web::block(move || export_database(&app)).await?.map_err(ApiError::internal_server)?;
+    let filename = format!("ytdlp_webui_export_{0}.json", export.unix_time);
+    Ok(HttpResponse::Ok()
+        .insert_header(get_content_disposition(DispositionType::Attachment, filename.as_str()))
+        .json(export))
+}
+
+// NOTE: The export includes every row of the database so it can be much larger than the default json limit
+const MAX_IMPORT_BYTES: usize = 1024*1024*1024;
+
+#[derive(Deserialize)]
+struct ImportParams {
+    allow_missing: Option<bool>,
+}
+
+/// Replaces the database with an export from /admin/export
+/// Finished entries must have their audio file copied into the data directory beforehand
+#[actix_web::post("/admin/import")]
+pub async fn admin_import(
+    req: HttpRequest, payload: web::Payload, params: web::Query<ImportParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let body = payload.to_bytes_limited(MAX_IMPORT_BYTES).await
+        .map_err(|_| ApiError::invalid_import_body(format!("export is larger than {MAX_IMPORT_BYTES} bytes")))??;
+    let export: DatabaseExport = serde_json::from_slice(&body).map_err(|err| ApiError::invalid_import_body(err.to_string()))?;
+    let allow_missing = params.allow_missing.unwrap_or(false);
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let summary = web::block(move || import_database(&app, export, allow_missing)).await?.map_err(ApiError::invalid_import)?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct MaintenanceTaskStatus {
+    task: MaintenanceTask,
+    schedule: Option<String>,
+    next_unix_time: Option<u64>,
+    /// Id of the run if the task is currently running
+    running_run_id: Option<i64>,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct AdminTasksResponse {
+    tasks: Vec<MaintenanceTaskStatus>,
+    runs: Vec<MaintenanceRunRow>,
+}
+
+#[derive(Deserialize)]
+struct AdminTasksParams {
+    limit: Option<usize>,
+}
+
+/// Schedule of each maintenance task along with the most recent runs
+#[actix_web::get("/admin/tasks")]
+pub async fn get_admin_tasks(
+    req: HttpRequest, params: web::Query<AdminTasksParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    const DEFAULT_LIMIT: usize = 50;
+    const MAX_LIMIT: usize = 1000;
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let unix_time = get_unix_time();
+    let tasks = MaintenanceTask::ALL.iter().map(|&task| {
+        let schedule = app.app_config.maintenance_schedules.iter().find(|(other, _)| *other == task).map(|(_, schedule)| schedule);
+        MaintenanceTaskStatus {
+            task,
+            schedule: schedule.map(|schedule| schedule.as_str().to_owned()),
+            next_unix_time: schedule.and_then(|schedule| schedule.get_next_unix_time(unix_time)),
+            running_run_id: app.running_maintenance_tasks.get(&task).map(|run_id| *run_id),
+        }
+    }).collect();
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let runs = app.job_store.select_maintenance_run_entries(limit).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(AdminTasksResponse { tasks, runs }))
+}
+
+/// Starts the maintenance task now regardless of its schedule
+#[actix_web::get("/admin/tasks/{task}/run")]
+pub async fn run_admin_task(req: HttpRequest, path: web::Path<String>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let task = path.into_inner();
+    let task = MaintenanceTask::try_from(task.as_str()).map_err(|_| ApiError::invalid_maintenance_task(task))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let run_id = try_start_maintenance_task(&app, task, true).map_err(ApiError::maintenance)?;
+    let entry = app.job_store.select_maintenance_run_entry(run_id).map_err(ApiError::internal_server)?
+        .ok_or_else(|| ApiError::not_found(run_id.to_string()))?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct AdminQueueResponse {
+    is_paused: bool,
+    total_pending: usize,
+    total_running: usize,
+    total_threads: usize,
+}
+
+fn get_admin_queue_response(app: &AppState) -> HttpResponse {
+    let pool = &app.worker_thread_pool;
+    HttpResponse::Ok().json(AdminQueueResponse {
+        is_paused: pool.is_paused(),
+        total_pending: pool.total_pending(),
+        total_running: pool.total_running(),
+        total_threads: pool.total_threads(),
+    })
+}
+
+#[actix_web::get("/admin/queue")]
+pub async fn get_admin_queue(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap();
+    Ok(get_admin_queue_response(app))
+}
+
+/// Stops queued downloads and transcodes from starting so the server stops using more cpu and bandwidth
+// NOTE: Jobs can still be requested while paused and wait in the queue until it is resumed
+#[actix_web::get("/admin/queue/pause")]
+pub async fn pause_admin_queue(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap();
+    app.worker_thread_pool.pause();
+    log::info!("Paused worker queue");
+    Ok(get_admin_queue_response(app))
+}
+
+#[actix_web::get("/admin/queue/resume")]
+pub async fn resume_admin_queue(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap();
+    app.worker_thread_pool.resume();
+    log::info!("Resumed worker queue");
+    Ok(get_admin_queue_response(app))
+}
+
+/// Url of the api that a bot posted to, which keeps the base path and the host the bot used
+fn get_integration_api_url(req: &HttpRequest) -> String {
+    let connection_info = req.connection_info();
+    let api_path = req.path().rsplit_once("/integrations/").map(|(path, _)| path).unwrap_or_default();
+    format!("{0}://{1}{api_path}", connection_info.scheme(), connection_info.host())
+}
+
+/// Starts the configured conversion of the video and describes where its progress and download can be found
+async fn try_start_integration_job(req: &HttpRequest, app: &AppState, video_id: VideoId) -> Result<String, ApiError> {
+    let config = &app.app_config.integrations;
+    let transcode_key = TranscodeKey::new(video_id.clone(), config.audio_ext, &config.transcode_options);
+    // NOTE: Bots aren't users so their conversions are shared like requests made without authentication
+    let identity = Identity { user: None };
+    let metadata = get_metadata_from_cache(video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store).await.ok();
+    let download_options = check_live_status(app, &video_id, metadata.as_deref(), app.app_config.download_options.clone())?;
+    let transcode_status = app.transcode_cache.get(&transcode_key).map(|state| state.lock().unwrap().worker_status);
+    if is_queue_full(app) && !is_job_accepted(transcode_status) {
+        return Ok("The server is busy, try again later".to_owned());
+    }
+    let title = metadata.as_ref()
+        .and_then(|metadata| metadata.items.first())
+        .map(|item| item.snippet.title.clone())
+        .unwrap_or_else(|| video_id.as_str().to_owned());
+    let response = start_transcode_job(
+        app, &identity, &transcode_key, config.transcode_options.clone(), download_options, metadata, None, None, None,
+    )?;
+    let api_url = get_integration_api_url(req);
+    let mut download_link = format!("{api_url}/get_download_link/{0}/{1}", video_id.as_str(), config.audio_ext.as_str());
+    if !response.variant.is_empty() {
+        download_link.push_str(format!("?variant={0}", response.variant).as_str());
+    }
+    if response.transcode_status == WorkerStatus::Finished {
+        return Ok(format!("{title} is ready: {download_link}"));
+    }
+    Ok(format!("Converting {title}\nProgress: {api_url}/get_job/{0}\nDownload once finished: {download_link}", response.job_id))
+}
+
+async fn get_integration_reply(req: &HttpRequest, app: &AppState, text: &str) -> String {
+    let Some(video_id) = find_video_id(text) else {
+        return "Send a YouTube link to convert it".to_owned();
+    };
+    match try_start_integration_job(req, app, video_id).await {
+        Ok(reply) => reply,
+        Err(err) => format!("Failed to convert: {0}", err.error),
+    }
+}
+
+/// Webhook of a telegram bot which converts the YouTube links sent to it
+// NOTE: Only enabled with --telegram-secret-token so anyone who finds the url can't start conversions
+#[actix_web::post("/integrations/telegram")]
+pub async fn telegram_integration(req: HttpRequest, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let Some(ref secret_token) = app.app_config.integrations.telegram_secret_token else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let given_token = req.headers().get("X-Telegram-Bot-Api-Secret-Token").and_then(|value| value.to_str().ok());
+    verify_telegram_secret_token(secret_token, given_token).map_err(ApiError::invalid_integration_request)?;
+    let update: TelegramUpdate = serde_json::from_slice(&body).map_err(|err| ApiError::invalid_integration_request(err.into()))?;
+    let Some(message) = update.message else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    let Some(text) = message.text.as_deref() else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    let reply = get_integration_reply(&req, &app, text).await;
+    Ok(HttpResponse::Ok().json(get_telegram_reply(&message, reply.as_str())))
+}
+
+/// Interactions endpoint of a discord application which converts the YouTube link given to its slash command
+#[actix_web::post("/integrations/discord")]
+pub async fn discord_integration(req: HttpRequest, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let Some(ref public_key) = app.app_config.integrations.discord_public_key else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let get_header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok());
+    verify_discord_signature(public_key, get_header("X-Signature-Ed25519"), get_header("X-Signature-Timestamp"), &body)
+        .map_err(ApiError::invalid_integration_request)?;
+    let interaction: DiscordInteraction = serde_json::from_slice(&body).map_err(|err| ApiError::invalid_integration_request(err.into()))?;
+    if interaction.is_ping() {
+        return Ok(HttpResponse::Ok().json(get_discord_pong()));
+    }
+    let Some(text) = interaction.get_command_text() else {
+        return Ok(HttpResponse::Ok().json(get_discord_reply("Use a slash command with a YouTube link")));
+    };
+    let reply = get_integration_reply(&req, &app, text.as_str()).await;
+    Ok(HttpResponse::Ok().json(get_discord_reply(reply.as_str())))
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct AdminBandwidthResponse {
+    windows: Vec<String>,
+    /// Cap of the window that downloads started now are limited to
+    current_limit_rate: Option<String>,
+    /// Rate of downloads outside of every window unless requests set their own
+    default_limit_rate: Option<String>,
+}
+
+fn get_admin_bandwidth_response(app: &AppState) -> HttpResponse {
+    let schedule = app.app_config.bandwidth_schedule.read().unwrap();
+    HttpResponse::Ok().json(AdminBandwidthResponse {
+        windows: schedule.windows().iter().map(|window| window.as_str().to_owned()).collect(),
+        current_limit_rate: schedule.get_limit_rate(get_unix_time()).map(|rate| rate.to_owned()),
+        default_limit_rate: app.app_config.download_options.limit_rate.clone(),
+    })
+}
+
+#[actix_web::get("/admin/bandwidth")]
+pub async fn get_admin_bandwidth(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap();
+    Ok(get_admin_bandwidth_response(app))
+}
+
+#[derive(Deserialize)]
+struct AdminBandwidthBody {
+    windows: Vec<String>,
+}
+
+/// Replaces the windows of the bandwidth schedule which apply to downloads started afterwards
+// NOTE: The schedule isn't saved so the one given by --limit-rate-schedule is used again after a restart
+#[actix_web::post("/admin/bandwidth")]
+pub async fn set_admin_bandwidth(req: HttpRequest, body: web::Json<AdminBandwidthBody>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap();
+    let schedule = BandwidthSchedule::parse(body.windows.as_slice()).map_err(ApiError::invalid_bandwidth_schedule)?;
+    *app.app_config.bandwidth_schedule.write().unwrap() = schedule;
+    log::info!("Changed bandwidth schedule: windows={0:?}", body.windows);
+    Ok(get_admin_bandwidth_response(app))
+}
+
+#[derive(Deserialize)]
+struct AgeRestrictedParams {
+    /// Video to fetch instead of the default age-restricted sample
+    video_id: Option<String>,
+}
+
+/// Tests whether yt-dlp can fetch an age-restricted video with the configured PO token, cookies or OAuth plugin
+#[actix_web::get("/admin/diagnostics/age_restricted")]
+pub async fn check_admin_age_restricted(
+    req: HttpRequest, params: web::Query<AgeRestrictedParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let video_id = params.into_inner().video_id.unwrap_or_else(|| DEFAULT_AGE_RESTRICTED_VIDEO_ID.to_owned());
+    let video_id = VideoId::try_new(video_id.as_str()).map_err(|e| ApiError::invalid_video_id(video_id, e))?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let check = web::block(move || check_age_restricted(
+        &app.app_config.ytdlp_binary, &video_id,
+        app.app_config.proxy.as_deref(), app.app_config.geo_bypass_country.as_deref(), &app.app_config.ytdlp_auth,
+    )).await?;
+    Ok(HttpResponse::Ok().json(check))
+}
+
+#[derive(Deserialize)]
+struct LoginBody {
+    username: String,
+    password: String,
+}
+
+fn get_session_cookie(token: String, path: String, is_secure: bool) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token)
+        .path(path)
+        .http_only(true)
+        .secure(is_secure)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::seconds(SESSION_DURATION_SECONDS as i64))
+        .finish()
+}
+
+/// Starts a session which is stored in a cookie for the web ui
+// NOTE: Credentials are sent in the body so they don't end up in server and proxy logs
+#[actix_web::post("/auth/login")]
+pub async fn login(req: HttpRequest, body: web::Json<LoginBody>) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    if !app.app_config.auth_enabled {
+        return Err(AuthError::Disabled.into());
+    }
+    let body = body.into_inner();
+    let user = app.job_store.select_user_entry_by_username(body.username.as_str()).map_err(AuthError::from)?;
+    let Some(user) = user.filter(|user| verify_password(body.password.as_str(), user.password_hash.as_str())) else {
+        return Err(AuthError::InvalidCredentials.into());
+    };
+    let (_, token) = auth::create_auth_token(&app.job_store, user.id, AuthTokenKind::Session, None)?;
+    let is_secure = req.connection_info().scheme() == "https";
+    Ok(HttpResponse::Ok().cookie(get_session_cookie(token, app.app_config.get_cookie_path(), is_secure)).json(user))
+}
+
+#[actix_web::get("/auth/logout")]
+pub async fn logout(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    if let Some(user) = identity.user {
+        app.job_store.delete_auth_token_entry(user.token_id).map_err(ApiError::internal_server)?;
+    }
+    let mut cookie = Cookie::named(SESSION_COOKIE);
+    cookie.set_path(app.app_config.get_cookie_path());
+    cookie.make_removal();
+    Ok(HttpResponse::Ok().cookie(cookie).finish())
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct GetMeResponse {
+    auth_enabled: bool,
+    user: Option<AuthUser>,
+}
+
+#[actix_web::get("/me")]
+pub async fn get_me(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    Ok(HttpResponse::Ok().json(GetMeResponse { auth_enabled: app.app_config.auth_enabled, user: identity.user }))
+}
+
+/// Only available to logged in users since quotas aren't enforced without authentication
+#[actix_web::get("/me/quota")]
+pub async fn get_quota(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let user = identity.user.ok_or(AuthError::Disabled)?;
+    let usage = get_quota_usage(&app, user.id)?;
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+#[actix_web::get("/users")]
+pub async fn get_users(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entries = app.job_store.select_user_entries().map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Deserialize)]
+struct CreateUserBody {
+    username: String,
+    password: String,
+    #[serde(default)]
+    is_admin: bool,
+}
+
+/// Only admins can create users except for the first user who becomes an admin
+#[actix_web::post("/users/create")]
+pub async fn create_user(req: HttpRequest, body: web::Json<CreateUserBody>) -> actix_web::Result<HttpResponse> {
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let body = body.into_inner();
+    validate_username(body.username.as_str())?;
+    validate_password(body.password.as_str())?;
+    let is_first_user = app.job_store.select_user_entries().map_err(ApiError::internal_server)?.is_empty();
+    if !is_first_user {
+        auth::authenticate(&app, &req)?.require_admin()?;
+    }
+    let existing = app.job_store.select_user_entry_by_username(body.username.as_str()).map_err(ApiError::internal_server)?;
+    if existing.is_some() {
+        return Err(ApiError::duplicate_username(body.username).into());
+    }
+    let password_hash = hash_password(body.password.as_str())?;
+    let id = app.job_store.insert_user_entry(body.username.as_str(), password_hash.as_str(), body.is_admin || is_first_user)
+        .map_err(ApiError::internal_server)?;
+    let entry = app.job_store.select_user_entry(id).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Downloads and transcodes of the user are kept and can still be managed by admins
+#[actix_web::get("/users/{id}/delete")]
+pub async fn delete_user(req: HttpRequest, path: web::Path<i64>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    identity.require_admin()?;
+    let id = path.into_inner();
+    if identity.owner() == Some(id) {
+        return Err(ApiError::delete_self().into());
+    }
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let total_deleted = app.job_store.delete_user_entry(id).map_err(ApiError::internal_server)?;
+    if total_deleted == 0 { return Ok(HttpResponse::NotFound().finish()); }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::get("/auth/tokens")]
+pub async fn get_auth_tokens(req: HttpRequest, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let user = identity.user.ok_or(AuthError::Disabled)?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entries = app.job_store.select_auth_token_entries(user.id).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Deserialize)]
+struct CreateAuthTokenParams {
+    name: Option<String>,
+}
+
+#[derive(Debug,Clone,Serialize)]
+struct CreateAuthTokenResponse {
+    id: i64,
+    /// Only returned once since the token isn't stored
+    token: String,
+}
+
+/// Api tokens are passed as "Authorization: Bearer <token>" and don't expire until they are deleted
+#[actix_web::get("/auth/tokens/create")]
+pub async fn create_auth_token(
+    req: HttpRequest, params: web::Query<CreateAuthTokenParams>, identity: Identity,
+) -> actix_web::Result<HttpResponse> {
+    let user = identity.user.ok_or(AuthError::Disabled)?;
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let (id, token) = auth::create_auth_token(&app.job_store, user.id, AuthTokenKind::Api, params.name.as_deref())?;
+    Ok(HttpResponse::Ok().json(CreateAuthTokenResponse { id, token }))
+}
+
+#[actix_web::get("/auth/tokens/{id}/delete")]
+pub async fn delete_auth_token(req: HttpRequest, path: web::Path<i64>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let user = identity.user.ok_or(AuthError::Disabled)?;
+    let id = path.into_inner();
+    let app = req.app_data::<AppState>().unwrap().clone();
+    let entries = app.job_store.select_auth_token_entries(user.id).map_err(ApiError::internal_server)?;
+    if !entries.iter().any(|entry| entry.id == id) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    app.job_store.delete_auth_token_entry(id).map_err(ApiError::internal_server)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub const API_PREFIX: &str = "/api/v1";
+
+/// Registers every api route so the server and integration tests serve the same routes under API_PREFIX
+pub fn configure_api(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(request_transcode)
+        .service(request_transcode_url)
+        .service(resolve_url)
+        .service(quick_add)
+        .service(retranscode)
+        .service(estimate_transcode)
+        .service(delete_transcode)
+        .service(delete_download)
+        .service(request_hls)
+        .service(delete_hls)
+        .service(get_trash)
+        .service(restore_trash)
+        .service(get_downloads)
+        .service(get_transcodes)
+        .service(get_download)
+        .service(get_transcode)
+        .service(get_download_state)
+        .service(get_transcode_state)
+        .service(verify_download)
+        .service(verify_transcode)
+        .service(get_hls_state)
+        .service(get_job_state)
+        .service(get_job)
+        .service(get_download_link)
+        .service(get_archive)
+        .service(stream_transcode)
+        .service(get_hls_file)
+        .service(get_waveform)
+        .service(get_metadata)
+        .service(refresh_metadata)
+        .service(get_formats)
+        .service(get_stats)
+        .service(get_system_info)
+        .service(get_admin_stats)
+        .service(get_admin_export)
+        .service(admin_import)
+        .service(get_admin_tasks)
+        .service(run_admin_task)
+        .service(get_admin_queue)
+        .service(pause_admin_queue)
+        .service(resume_admin_queue)
+        .service(get_admin_bandwidth)
+        .service(set_admin_bandwidth)
+        .service(check_admin_age_restricted)
+        .service(telegram_integration)
+        .service(discord_integration)
+        .service(get_download_log)
+        .service(get_transcode_log)
+        .service(search)
+        .service(get_library)
+        .service(get_subscriptions)
+        .service(create_subscription)
+        .service(get_subscription)
+        .service(update_subscription)
+        .service(delete_subscription)
+        .service(request_channel)
+        .service(get_channel_jobs)
+        .service(get_channel_job)
+        .service(login)
+        .service(logout)
+        .service(get_me)
+        .service(get_quota)
+        .service(get_users)
+        .service(create_user)
+        .service(delete_user)
+        .service(get_auth_tokens)
+        .service(create_auth_token)
+        .service(delete_auth_token);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use actix_web::{test, web, App};
+    use serde_json::Value;
+    use crate::app::{AppConfig, AppState};
+    use crate::auth::create_auth_token;
+    use crate::database::{AudioExtension, AuthTokenKind, VideoId, WorkerStatus};
+    use crate::fixtures::{MockDownloader, MockTranscoder};
+    use super::{configure_api, get_data_file, API_PREFIX};
+
+    const VIDEO_ID: &str = "dQw4w9WgXcQ";
+    const JOB_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn create_app_config() -> AppConfig {
+        AppConfig {
+            downloader: Some(Arc::new(MockDownloader::default())),
+            transcoder: Some(Arc::new(MockTranscoder::default())),
+            ..AppConfig::default()
+        }
+    }
+
+    fn create_app_state(data_listing: bool) -> AppState {
+        let app_config = AppConfig { data_listing, ..create_app_config() };
+        AppState::new_in_memory(app_config).expect("App state should be created")
+    }
+
+    /// Bearer token of a new user who isn't an admin
+    fn create_user_token(app_state: &AppState, username: &str) -> String {
+        let user_id = app_state.job_store.insert_user_entry(username, "", false).unwrap();
+        let (_, token) = create_auth_token(&app_state.job_store, user_id, AuthTokenKind::Api, None).unwrap();
+        format!("Bearer {token}")
+    }
+
+    async fn wait_for_transcode(app_state: &AppState, video_id: &VideoId, audio_ext: AudioExtension) {
+        let start = Instant::now();
+        loop {
+            let entry = app_state.job_store.select_ffmpeg_entry(video_id, audio_ext, "").unwrap();
+            match entry.map(|entry| entry.status) {
+                Some(WorkerStatus::Finished) => return,
+                Some(WorkerStatus::Failed) => panic!("Transcode failed"),
+                _ => {},
+            }
+            assert!(start.elapsed() < JOB_TIMEOUT, "Transcode didn't finish before timeout");
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn transcode_is_requested_downloaded_and_deleted() {
+        let app_state = create_app_state(false);
+        let app = test::init_service(
+            App::new().app_data(app_state).service(web::scope(API_PREFIX).configure(configure_api)),
+        ).await;
+
+        let req = test::TestRequest::get().uri(format!("{API_PREFIX}/request_transcode/{VIDEO_ID}/mp3").as_str()).to_request();
+        let response: Value = test::call_and_read_body_json(&app, req).await;
+        let job_id = response["job_id"].as_str().expect("Response should have a job id").to_owned();
+
+        let start = Instant::now();
+        loop {
+            let req = test::TestRequest::get().uri(format!("{API_PREFIX}/get_job/{job_id}").as_str()).to_request();
+            let job: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(job["job"]["video_id"], VIDEO_ID);
+            match job["transcode"]["status"].as_str() {
+                Some("finished") => break,
+                Some("failed") => panic!("Transcode failed: {job}"),
+                _ => {},
+            }
+            assert!(start.elapsed() < JOB_TIMEOUT, "Transcode didn't finish before timeout: {job}");
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let download_link = format!("{API_PREFIX}/get_download_link/{VIDEO_ID}/mp3?name=song.mp3");
+        let req = test::TestRequest::get().uri(download_link.as_str()).to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success());
+        let etag = response.headers().get(actix_web::http::header::ETAG).expect("Download should have an ETag").clone();
+        let body = test::read_body(response).await;
+        assert!(body.len() > 8);
+
+        // NOTE: Resuming the download only sends the rest of the file
+        let req = test::TestRequest::get()
+            .uri(download_link.as_str())
+            .insert_header(("Range", "bytes=4-"))
+            .insert_header(("If-Range", etag.clone()))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(test::read_body(response).await, body.slice(4..));
+        let req = test::TestRequest::get().uri(download_link.as_str()).insert_header(("If-None-Match", etag)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+
+        let req = test::TestRequest::get().uri(format!("{API_PREFIX}/get_transcode_log/{VIDEO_ID}/mp3/system").as_str()).to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success());
+        assert!(!test::read_body(response).await.is_empty());
+
+        let req = test::TestRequest::get().uri(format!("{API_PREFIX}/delete_transcode/{VIDEO_ID}/mp3").as_str()).to_request();
+        let response: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(response["type"], "success");
+
+        let req = test::TestRequest::get().uri(download_link.as_str()).to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn data_files_are_only_served_for_entries() {
+        let app_state = create_app_state(true);
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(web::scope(API_PREFIX).configure(configure_api))
+                .service(get_data_file),
+        ).await;
+        let req = test::TestRequest::get().uri(format!("{API_PREFIX}/request_transcode/{VIDEO_ID}/mp3").as_str()).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        wait_for_transcode(&app_state, &VideoId::try_new(VIDEO_ID).unwrap(), AudioExtension::MP3).await;
+
+        let req = test::TestRequest::get().uri("/data/transcode/").to_request();
+        let listing = test::call_and_read_body(&app, req).await;
+        let listing = String::from_utf8_lossy(&listing);
+        assert!(listing.contains(format!("{VIDEO_ID}.mp3").as_str()), "{listing}");
+        assert!(!listing.contains(".log"), "{listing}");
+
+        let req = test::TestRequest::get().uri(format!("/data/transcode/{VIDEO_ID}.mp3").as_str()).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri(format!("/data/transcode/{VIDEO_ID}.mp3.system.log").as_str()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let req = test::TestRequest::get().uri("/data/transcode").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::PERMANENT_REDIRECT);
+    }
+
+    #[actix_web::test]
+    async fn job_state_is_hidden_from_other_users() {
+        let app_state = AppState::new_in_memory(AppConfig { auth_enabled: true, ..create_app_config() })
+            .expect("App state should be created");
+        let owner_token = create_user_token(&app_state, "alice");
+        let other_token = create_user_token(&app_state, "bob");
+        let app = test::init_service(
+            App::new().app_data(app_state.clone()).service(web::scope(API_PREFIX).configure(configure_api)),
+        ).await;
+        let req = test::TestRequest::get()
+            .uri(format!("{API_PREFIX}/request_transcode/{VIDEO_ID}/mp3").as_str())
+            .insert_header(("Authorization", owner_token.clone()))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // NOTE: The job is still in the cache while it runs so the owner has to be checked before reading it
+        for uri in [format!("{API_PREFIX}/get_job_state/{VIDEO_ID}/mp3"), format!("{API_PREFIX}/get_transcode_state/{VIDEO_ID}/mp3")] {
+            let req = test::TestRequest::get().uri(uri.as_str()).insert_header(("Authorization", other_token.clone())).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND, "{uri}");
+            let req = test::TestRequest::get().uri(uri.as_str()).insert_header(("Authorization", owner_token.clone())).to_request();
+            assert!(test::call_service(&app, req).await.status().is_success(), "{uri}");
+        }
+        wait_for_transcode(&app_state, &VideoId::try_new(VIDEO_ID).unwrap(), AudioExtension::MP3).await;
+        let req = test::TestRequest::get()
+            .uri(format!("{API_PREFIX}/get_job_state/{VIDEO_ID}/mp3").as_str())
+            .insert_header(("Authorization", other_token))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
     }
-    let metadata_url = get_metadata_url(video_id.as_str());
-    let response = reqwest::get(metadata_url).await?;
-    let metadata = response.text().await?;
-    let metadata: Metadata = serde_json::from_str(metadata.as_str())?;
-    let metadata = Arc::new(metadata);
-    cache.insert(video_id, metadata.clone());
-    Ok(metadata)
 }
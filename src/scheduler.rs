@@ -0,0 +1,165 @@
+use std::time::Duration;
+use actix_web::web;
+use lazy_static::lazy_static;
+use regex::Regex;
+use thiserror::Error;
+use crate::app::AppState;
+use crate::database::{VideoId, SubscriptionRow};
+use crate::ffmpeg::TranscodeOptions;
+use crate::job_store::JobStoreError;
+use crate::metadata::get_metadata_from_cache;
+use crate::util::get_unix_time;
+use crate::worker_download::try_start_download_worker;
+use crate::worker_transcode::{try_start_transcode_worker, TranscodeKey};
+use crate::ytdlp::{list_playlist_video_ids, DownloadOptions, PlaylistError};
+
+pub const MIN_CHECK_INTERVAL_SECONDS: u64 = 15*60;
+pub const MAX_CHECK_INTERVAL_SECONDS: u64 = 7*24*60*60;
+pub const DEFAULT_CHECK_INTERVAL_SECONDS: u64 = 6*60*60;
+// NOTE: Polling once a minute is precise enough since subscriptions are checked at most every 15 minutes
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+// NOTE: Channels list their newest uploads first so we don't need to list their entire history
+const CHANNEL_PLAYLIST_END: usize = 50;
+
+#[derive(Debug,Error)]
+pub enum SubscriptionError {
+    #[error("Unsupported url, expected a YouTube channel or playlist: {0}")]
+    InvalidUrl(String),
+    #[error("Check interval must be between {min} and {max} seconds: {given}")]
+    InvalidCheckInterval { min: u64, max: u64, given: u64 },
+    #[error("Failed to list videos: {0}")]
+    Playlist(#[from] PlaylistError),
+    #[error("Failed to run blocking task: {0:?}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+    #[error("Invalid transcode options: {0}")]
+    InvalidTranscodeOptions(#[from] serde_json::Error),
+}
+
+// NOTE: We only accept YouTube urls so arbitrary urls can't be passed to yt-dlp
+pub fn validate_subscription_url(url: &str) -> Result<(), SubscriptionError> {
+    lazy_static! {
+        static ref URL_REGEX: Regex = Regex::new(
+            r"^https://(?:www\.)?youtube\.com/(?:(?:@[\w.\-]+|channel/[\w\-]+|c/[\w\-]+|user/[\w\-]+)(?:/videos|/streams)?|playlist\?list=[\w\-]+)$"
+        ).unwrap();
+    }
+    if !URL_REGEX.is_match(url) {
+        return Err(SubscriptionError::InvalidUrl(url.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn validate_check_interval(seconds: u64) -> Result<(), SubscriptionError> {
+    if !(MIN_CHECK_INTERVAL_SECONDS..=MAX_CHECK_INTERVAL_SECONDS).contains(&seconds) {
+        return Err(SubscriptionError::InvalidCheckInterval {
+            min: MIN_CHECK_INTERVAL_SECONDS, max: MAX_CHECK_INTERVAL_SECONDS, given: seconds,
+        });
+    }
+    Ok(())
+}
+
+fn is_playlist_url(url: &str) -> bool {
+    url.contains("/playlist?list=")
+}
+
+fn is_check_due(entry: &SubscriptionRow, unix_time: u64) -> bool {
+    match entry.last_check_unix_time {
+        None => true,
+        Some(last_check) => unix_time.saturating_sub(last_check) >= entry.check_interval_seconds,
+    }
+}
+
+/// Lists the videos that are currently in the channel or playlist
+pub async fn list_subscription_videos(app: &AppState, url: &str) -> Result<Vec<VideoId>, SubscriptionError> {
+    // NOTE: Playlists append new videos to the end so they are listed in full
+    let playlist_end = if is_playlist_url(url) { None } else { Some(CHANNEL_PLAYLIST_END) };
+    let app_config = app.app_config.clone();
+    let url = url.to_owned();
+    let video_ids = web::block(move || list_playlist_video_ids(
+        &app_config.ytdlp_binary, url.as_str(), playlist_end,
+        app_config.proxy.as_deref(), app_config.geo_bypass_country.as_deref(),
+    )).await??;
+    Ok(video_ids)
+}
+
+/// Records the videos as seen and returns the ones that weren't seen before
+pub fn record_subscription_videos(
+    app: &AppState, id: i64, video_ids: Vec<VideoId>,
+) -> Result<Vec<VideoId>, SubscriptionError> {
+    let mut new_video_ids = Vec::<VideoId>::new();
+    for video_id in video_ids {
+        if app.job_store.insert_subscription_video_entry(id, &video_id)? > 0 {
+            new_video_ids.push(video_id);
+        }
+    }
+    Ok(new_video_ids)
+}
+
+/// Starts download and transcode jobs for videos that haven't been seen before
+// NOTE: Subscription jobs aren't owned by a user so they are shared with everyone
+pub async fn check_subscription(app: &AppState, entry: &SubscriptionRow) -> Result<Vec<VideoId>, SubscriptionError> {
+    let transcode_options: TranscodeOptions = match entry.transcode_options {
+        Some(ref options) => serde_json::from_str(options.as_str())?,
+        None => TranscodeOptions::default(),
+    };
+    let download_options = DownloadOptions { audio_lang: transcode_options.audio_lang.clone(), ..app.app_config.download_options.clone() };
+    let video_ids = list_subscription_videos(app, entry.url.as_str()).await?;
+    let new_video_ids = record_subscription_videos(app, entry.id, video_ids)?;
+    for video_id in new_video_ids.iter() {
+        log::info!("Subscription found new video: subscription={0}, id={1}", entry.id, video_id.as_str());
+        let res = try_start_download_worker(
+            video_id.clone(),
+            app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+            download_options.clone(), None,
+        );
+        if let Err(err) = res {
+            log::error!("Subscription failed to start download: id={0}, err={1:?}", video_id.as_str(), err);
+            continue;
+        }
+        let metadata = get_metadata_from_cache(
+            video_id.clone(), app.metadata_cache.clone(), &app.http_client, &app.job_store,
+        ).await.ok();
+        let res = try_start_transcode_worker(
+            TranscodeKey::new(video_id.clone(), entry.audio_ext, &transcode_options), transcode_options.clone(),
+            app.download_cache.clone(), app.transcode_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(),
+            app.worker_thread_pool.clone(), metadata, None,
+        );
+        if let Err(err) = res {
+            log::error!("Subscription failed to start transcode: id={0}, err={1:?}", video_id.as_str(), err);
+        }
+    }
+    Ok(new_video_ids)
+}
+
+/// Periodically checks subscriptions that are due for new uploads
+pub async fn run_subscription_scheduler(app: AppState) {
+    loop {
+        let entries = match app.job_store.select_subscription_entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("Failed to read subscriptions: {err:?}");
+                Vec::new()
+            },
+        };
+        for entry in entries {
+            if !is_check_due(&entry, get_unix_time()) {
+                continue;
+            }
+            let res = check_subscription(&app, &entry).await;
+            if let Err(ref err) = res {
+                log::error!("Subscription check failed: subscription={0}, err={1:?}", entry.id, err);
+            }
+            // NOTE: Reselect in case the subscription was changed or deleted during the check
+            //       Failed checks are retried at the next interval so a broken url doesn't spam yt-dlp
+            let res = app.job_store.select_and_update_subscription_entry(entry.id, |entry| {
+                entry.last_check_unix_time = Some(get_unix_time());
+                entry.last_check_error = res.err().map(|err| err.to_string());
+            });
+            if let Err(err) = res {
+                log::error!("Failed to update subscription: subscription={0}, err={1:?}", entry.id, err);
+            }
+        }
+        actix_web::rt::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use crate::util::{get_civil_date, get_unix_time};
+
+pub const OBJECT_URL_PREFIX: &str = "s3://";
+pub const DEFAULT_PRESIGN_SECONDS: u64 = 60*60;
+// NOTE: S3 rejects presigned urls that are valid for longer than a week
+pub const MAX_PRESIGN_SECONDS: u64 = 7*24*60*60;
+
+#[derive(Debug,Error)]
+pub enum StorageError {
+    #[error("Endpoint must be a http or https url without a path: {0}")]
+    InvalidEndpoint(String),
+    #[error("Bucket name must be 3 to 63 characters of [a-z0-9.-]: {0}")]
+    InvalidBucket(String),
+    #[error("Presigned urls must expire within 1 to {MAX_PRESIGN_SECONDS} seconds: {0}")]
+    InvalidPresignSeconds(u64),
+    #[error("Object doesn't belong to the configured bucket: {0}")]
+    InvalidObjectUrl(String),
+    #[error("Object storage isn't configured: {0}")]
+    NotConfigured(String),
+    #[error("Failed to open file: {0:?}")]
+    FileOpen(std::io::Error),
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Object storage responded with {status}: {body}")]
+    BadStatus { status: u16, body: String },
+}
+
+/// Files stored in a bucket are recorded in the database as s3://bucket/key instead of a local path
+pub fn is_object_url(path: &str) -> bool {
+    path.starts_with(OBJECT_URL_PREFIX)
+}
+
+/// S3 compatible bucket that finished transcodes are uploaded to
+// NOTE: Every request is authorised with a presigned url so the same signing code is used for
+//       uploads and deletes from the workers and downloads from the client
+#[derive(Clone)]
+pub struct S3Storage {
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    // NOTE: Kept separate from the app's client so the proxy for YouTube isn't used for the bucket
+    http_client: reqwest::Client,
+    pub presign_seconds: u64,
+    /// Stream downloads through the server instead of redirecting the client to a presigned url
+    pub proxy_downloads: bool,
+}
+
+// NOTE: The config is logged on startup so the secret key is left out
+impl std::fmt::Debug for S3Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Storage")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .field("presign_seconds", &self.presign_seconds)
+            .field("proxy_downloads", &self.proxy_downloads)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Percent encodes everything except unreserved characters as required by signature version 4
+fn uri_encode(value: &str, is_path: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if is_path => encoded.push('/'),
+            _ => encoded.push_str(format!("%{byte:02X}").as_str()),
+        }
+    }
+    encoded
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Formats the time as YYYYMMDDTHHMMSSZ in UTC
+fn get_amz_date(unix_time: u64) -> String {
+    let (year, month, day) = get_civil_date(unix_time / 86400);
+    let seconds = unix_time % 86400;
+    format!(
+        "{year:04}{month:02}{day:02}T{0:02}{1:02}{2:02}Z",
+        seconds / 3600, (seconds / 60) % 60, seconds % 60,
+    )
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str, bucket: &str, region: &str, access_key: &str, secret_key: &str,
+        presign_seconds: u64, proxy_downloads: bool,
+    ) -> Result<Self, StorageError> {
+        let endpoint_url = reqwest::Url::parse(endpoint).map_err(|_| StorageError::InvalidEndpoint(endpoint.to_owned()))?;
+        let is_valid_endpoint = matches!(endpoint_url.scheme(), "http" | "https") &&
+            endpoint_url.host_str().is_some() && endpoint_url.path() == "/" && endpoint_url.query().is_none();
+        if !is_valid_endpoint {
+            return Err(StorageError::InvalidEndpoint(endpoint.to_owned()));
+        }
+        let is_valid_bucket = (3..=63).contains(&bucket.len()) &&
+            bucket.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-');
+        if !is_valid_bucket {
+            return Err(StorageError::InvalidBucket(bucket.to_owned()));
+        }
+        if !(1..=MAX_PRESIGN_SECONDS).contains(&presign_seconds) {
+            return Err(StorageError::InvalidPresignSeconds(presign_seconds));
+        }
+        Ok(Self {
+            endpoint: endpoint_url,
+            bucket: bucket.to_owned(),
+            region: region.to_owned(),
+            access_key: access_key.to_owned(),
+            secret_key: secret_key.to_owned(),
+            http_client: reqwest::Client::new(),
+            presign_seconds,
+            proxy_downloads,
+        })
+    }
+
+    pub fn get_object_url(&self, key: &str) -> String {
+        format!("{OBJECT_URL_PREFIX}{0}/{key}", self.bucket)
+    }
+
+    pub fn get_object_key<'a>(&self, url: &'a str) -> Result<&'a str, StorageError> {
+        url.strip_prefix(OBJECT_URL_PREFIX)
+            .and_then(|url| url.strip_prefix(self.bucket.as_str()))
+            .and_then(|url| url.strip_prefix('/'))
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| StorageError::InvalidObjectUrl(url.to_owned()))
+    }
+
+    /// Signs the request into the query string using signature version 4
+    // NOTE: Path style urls are used since every S3 compatible service supports them
+    //       The payload isn't signed so files can be uploaded without hashing them first
+    pub fn presign(&self, method: &str, key: &str, params: &[(&str, &str)], unix_time: u64) -> String {
+        let amz_date = get_amz_date(unix_time);
+        let date = &amz_date[..8];
+        let scope = format!("{date}/{0}/s3/aws4_request", self.region);
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{0}:{port}", self.endpoint.host_str().unwrap_or_default()),
+            None => self.endpoint.host_str().unwrap_or_default().to_owned(),
+        };
+        let path = uri_encode(format!("/{0}/{key}", self.bucket).as_str(), true);
+        let credential = format!("{0}/{scope}", self.access_key);
+        let expires = self.presign_seconds.to_string();
+        let mut query: Vec<(String, String)> = [
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256"),
+            ("X-Amz-Credential", credential.as_str()),
+            ("X-Amz-Date", amz_date.as_str()),
+            ("X-Amz-Expires", expires.as_str()),
+            ("X-Amz-SignedHeaders", "host"),
+        ].iter().chain(params.iter())
+            .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+            .collect();
+        query.sort();
+        let query: Vec<String> = query.iter().map(|(name, value)| format!("{name}={value}")).collect();
+        let query = query.join("&");
+        let canonical_request = format!("{method}\n{path}\n{query}\nhost:{host}\n\nhost\nUNSIGNED-PAYLOAD");
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{0:x}",
+            Sha256::digest(canonical_request.as_bytes()),
+        );
+        let key = hmac_sha256(format!("AWS4{0}", self.secret_key).as_bytes(), date);
+        let key = hmac_sha256(&key, self.region.as_str());
+        let key = hmac_sha256(&key, "s3");
+        let key = hmac_sha256(&key, "aws4_request");
+        let signature: String = hmac_sha256(&key, string_to_sign.as_str()).iter().map(|byte| format!("{byte:02x}")).collect();
+        format!("{0}://{host}{path}?{query}&X-Amz-Signature={signature}", self.endpoint.scheme())
+    }
+
+    /// Url the client can download the object from with the given headers
+    pub fn presign_download(&self, url: &str, content_type: &str, content_disposition: &str) -> Result<String, StorageError> {
+        let key = self.get_object_key(url)?;
+        let params = [("response-content-type", content_type), ("response-content-disposition", content_disposition)];
+        Ok(self.presign("GET", key, &params, get_unix_time()))
+    }
+
+    /// Fetches the object so it can be streamed through the server, forwarding the range if given
+    pub async fn get_object(&self, url: &str, range: Option<&[u8]>) -> Result<reqwest::Response, StorageError> {
+        let key = self.get_object_key(url)?;
+        let url = self.presign("GET", key, &[], get_unix_time());
+        let mut request = self.http_client.get(url);
+        if let Some(range) = range {
+            request = request.header(reqwest::header::RANGE, range);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(StorageError::BadStatus { status: status.as_u16(), body });
+        }
+        Ok(response)
+    }
+
+    fn check_response(response: reqwest::blocking::Response) -> Result<(), StorageError> {
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            return Err(StorageError::BadStatus { status: status.as_u16(), body });
+        }
+        Ok(())
+    }
+
+    /// Uploads the file and returns the url of the object, this blocks until the upload finishes
+    pub fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<String, StorageError> {
+        let file = std::fs::File::open(path).map_err(StorageError::FileOpen)?;
+        let total_bytes = file.metadata().map_err(StorageError::FileOpen)?.len();
+        let url = self.presign("PUT", key, &[], get_unix_time());
+        // NOTE: Large files can take longer than the default timeout to upload
+        let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+        let response = client.put(url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(reqwest::blocking::Body::sized(file, total_bytes))
+            .send()?;
+        Self::check_response(response)?;
+        Ok(self.get_object_url(key))
+    }
+
+    /// Deletes the object, this blocks until the request finishes
+    pub fn delete_object(&self, url: &str) -> Result<(), StorageError> {
+        let key = self.get_object_key(url)?;
+        let url = self.presign("DELETE", key, &[], get_unix_time());
+        let response = reqwest::blocking::Client::new().delete(url).send()?;
+        Self::check_response(response)
+    }
+}
@@ -0,0 +1,114 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+#[derive(Debug,Error)]
+pub enum ToolVersionError {
+    #[error("Binary {binary} was not found, check that it is installed or pass its path with the matching --*-binary-path argument")]
+    NotFound { binary: String },
+    #[error("Binary {binary} is not executable, check its permissions")]
+    NotExecutable { binary: String },
+    #[error("Failed to run {binary}: {error:?}")]
+    Spawn { binary: String, error: std::io::Error },
+    #[error("Binary {binary} exited with bad exit code {code:?}: {stderr}")]
+    BadExitCode { binary: String, code: Option<i32>, stderr: String },
+    #[error("Binary {binary} didn't print a version")]
+    MissingVersion { binary: String },
+}
+
+/// Version reported by an external binary
+#[derive(Debug,Clone,Serialize)]
+pub struct ToolVersion {
+    pub binary: String,
+    pub version: String,
+}
+
+#[derive(Debug,Clone,Serialize)]
+pub struct ToolVersions {
+    pub ytdlp: ToolVersion,
+    pub ffmpeg: ToolVersion,
+    pub ffprobe: ToolVersion,
+}
+
+// NOTE: ffmpeg and ffprobe print "<name> version <version> Copyright ..." while yt-dlp only prints the version
+pub fn parse_tool_version(output: &str) -> Option<String> {
+    lazy_static! {
+        static ref VERSION_REGEX: Regex = Regex::new(r"^\S+\s+version\s+(\S+)").unwrap();
+    }
+    let line = output.lines().map(|line| line.trim()).find(|line| !line.is_empty())?;
+    match VERSION_REGEX.captures(line) {
+        Some(captures) => Some(captures.get(1)?.as_str().to_owned()),
+        None => Some(line.to_owned()),
+    }
+}
+
+pub fn get_tool_version(binary: &Path, version_arg: &str) -> Result<String, ToolVersionError> {
+    let binary_name = binary.to_string_lossy().to_string();
+    let output = Command::new(binary)
+        .arg(version_arg)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::NotFound => ToolVersionError::NotFound { binary: binary_name.clone() },
+            std::io::ErrorKind::PermissionDenied => ToolVersionError::NotExecutable { binary: binary_name.clone() },
+            _ => ToolVersionError::Spawn { binary: binary_name.clone(), error },
+        })?;
+    if !output.status.success() {
+        return Err(ToolVersionError::BadExitCode {
+            binary: binary_name,
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    parse_tool_version(String::from_utf8_lossy(&output.stdout).as_ref())
+        .ok_or(ToolVersionError::MissingVersion { binary: binary_name })
+}
+
+impl ToolVersion {
+    pub fn new(binary: &Path, version_arg: &str) -> Result<Self, ToolVersionError> {
+        let version = get_tool_version(binary, version_arg)?;
+        Ok(Self { binary: binary.to_string_lossy().to_string(), version })
+    }
+}
+
+impl ToolVersions {
+    /// Fails if any of the binaries can't be run so a bad install is caught at startup instead of at the first job
+    pub fn new(ytdlp_binary: &Path, ffmpeg_binary: &Path, ffprobe_binary: &Path) -> Result<Self, ToolVersionError> {
+        Ok(Self {
+            ytdlp: ToolVersion::new(ytdlp_binary, "--version")?,
+            ffmpeg: ToolVersion::new(ffmpeg_binary, "-version")?,
+            ffprobe: ToolVersion::new(ffprobe_binary, "-version")?,
+        })
+    }
+
+    /// Binaries that aren't run such as when they are replaced with mocks in tests
+    pub fn unchecked(ytdlp_binary: &Path, ffmpeg_binary: &Path, ffprobe_binary: &Path) -> Self {
+        let unchecked = |binary: &Path| ToolVersion { binary: binary.to_string_lossy().to_string(), version: "unknown".to_owned() };
+        Self {
+            ytdlp: unchecked(ytdlp_binary),
+            ffmpeg: unchecked(ffmpeg_binary),
+            ffprobe: unchecked(ffprobe_binary),
+        }
+    }
+}
+
+/// Directory used by the server and the space left on the disk it is on
+#[derive(Debug,Clone,Serialize)]
+pub struct DirectoryInfo {
+    pub path: String,
+    pub available_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+}
+
+impl DirectoryInfo {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            available_bytes: fs2::available_space(path).ok(),
+            total_bytes: fs2::total_space(path).ok(),
+        }
+    }
+}
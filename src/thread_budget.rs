@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::app::AppConfig;
+
+/// Number of ffmpeg processes started by jobs that are still running
+static TOTAL_ACTIVE_PROCESSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads given to an ffmpeg process, which are given back to the budget once dropped
+pub struct ThreadBudget {
+    pub threads: usize,
+}
+
+impl ThreadBudget {
+    /// Splits the cpu budget evenly between the running ffmpeg processes including this one
+    // NOTE: ffmpeg can't change its thread count once it has started so running processes keep the share they started with
+    pub fn acquire(app_config: &AppConfig) -> Self {
+        let total_active = TOTAL_ACTIVE_PROCESSES.fetch_add(1, Ordering::SeqCst) + 1;
+        let threads = match app_config.ffmpeg_threads {
+            0 => (app_config.ffmpeg_cpu_budget / total_active).max(1),
+            threads => threads,
+        };
+        Self { threads }
+    }
+}
+
+impl Drop for ThreadBudget {
+    fn drop(&mut self) {
+        TOTAL_ACTIVE_PROCESSES.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use rustls::ServerConfig;
+use thiserror::Error;
+
+#[derive(Debug,Error)]
+pub enum TlsError {
+    #[error("Failed to read {path}: {error}")]
+    Read { path: PathBuf, error: std::io::Error },
+    #[error("No certificates were found in {0}")]
+    MissingCertificate(PathBuf),
+    #[error("No private key was found in {0}")]
+    MissingPrivateKey(PathBuf),
+    #[error("Invalid certificate or private key: {0}")]
+    InvalidConfig(#[from] rustls::Error),
+}
+
+fn open_pem_file(path: &Path) -> Result<BufReader<std::fs::File>, TlsError> {
+    let file = std::fs::File::open(path).map_err(|error| TlsError::Read { path: path.to_owned(), error })?;
+    Ok(BufReader::new(file))
+}
+
+/// Server config from a PEM certificate chain and its private key
+// NOTE: Clients negotiate HTTP/2 through ALPN which actix sets up when binding with this config
+pub fn load_tls_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig, TlsError> {
+    let certs = rustls_pemfile::certs(&mut open_pem_file(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| TlsError::Read { path: cert_path.to_owned(), error })?;
+    if certs.is_empty() {
+        return Err(TlsError::MissingCertificate(cert_path.to_owned()));
+    }
+    let key = rustls_pemfile::private_key(&mut open_pem_file(key_path)?)
+        .map_err(|error| TlsError::Read { path: key_path.to_owned(), error })?
+        .ok_or_else(|| TlsError::MissingPrivateKey(key_path.to_owned()))?;
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(config)
+}
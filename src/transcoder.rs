@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::ffprobe::{self, ProbeError, ProbeResult, ReplayGain};
+use crate::job_process::JobProcess;
+use crate::process_priority::ProcessPriority;
+use crate::waveform::{self, Waveform, WaveformError};
+
+/// Backend that encodes transcodes and hls streams, and inspects the files it reads and writes
+pub trait Transcoder: std::fmt::Debug + Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &'static str;
+    /// Starts a transcode or hls job with ffmpeg arguments, where the output path is the last argument
+    fn spawn(&self, args: &[String], is_stdin_piped: bool, priority: &ProcessPriority) -> std::io::Result<JobProcess>;
+    /// Inspects the file without decoding it
+    fn probe_format(&self, path: &Path) -> Result<ProbeResult, ProbeError>;
+    /// Inspects the file and measures its peak volume
+    fn probe_file(&self, path: &Path) -> Result<ProbeResult, ProbeError>;
+    fn measure_replay_gain(&self, path: &Path, volume_filter: Option<&str>) -> Result<ReplayGain, ProbeError>;
+    /// Chromaprint fingerprint of the audio that is looked up on AcoustID
+    fn fingerprint(&self, path: &Path) -> Result<String, ProbeError>;
+    fn generate_waveform(&self, path: &Path, priority: &ProcessPriority) -> Result<Waveform, WaveformError>;
+}
+
+pub type SharedTranscoder = Arc<dyn Transcoder>;
+
+#[derive(Clone,Debug)]
+pub struct FfmpegTranscoder {
+    ffmpeg_binary: PathBuf,
+    ffprobe_binary: PathBuf,
+}
+
+impl FfmpegTranscoder {
+    pub const NAME: &'static str = "ffmpeg";
+
+    pub fn new(ffmpeg_binary: PathBuf, ffprobe_binary: PathBuf) -> Self {
+        Self { ffmpeg_binary, ffprobe_binary }
+    }
+}
+
+impl Transcoder for FfmpegTranscoder {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn spawn(&self, args: &[String], is_stdin_piped: bool, priority: &ProcessPriority) -> std::io::Result<JobProcess> {
+        JobProcess::spawn(self.ffmpeg_binary.as_path(), args, is_stdin_piped, priority)
+    }
+
+    fn probe_format(&self, path: &Path) -> Result<ProbeResult, ProbeError> {
+        ffprobe::probe_format(self.ffprobe_binary.as_path(), path)
+    }
+
+    fn probe_file(&self, path: &Path) -> Result<ProbeResult, ProbeError> {
+        ffprobe::probe_file(self.ffprobe_binary.as_path(), self.ffmpeg_binary.as_path(), path)
+    }
+
+    fn measure_replay_gain(&self, path: &Path, volume_filter: Option<&str>) -> Result<ReplayGain, ProbeError> {
+        ffprobe::measure_replay_gain(self.ffmpeg_binary.as_path(), path, volume_filter)
+    }
+
+    fn fingerprint(&self, path: &Path) -> Result<String, ProbeError> {
+        ffprobe::fingerprint(self.ffmpeg_binary.as_path(), path)
+    }
+
+    fn generate_waveform(&self, path: &Path, priority: &ProcessPriority) -> Result<Waveform, WaveformError> {
+        waveform::generate_waveform(self.ffmpeg_binary.as_path(), priority, path)
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::{Path, PathBuf};
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::app::{AppConfig, AppState};
+use crate::database::{FfmpegRow, JobKind, TrashRow, YtdlpRow};
+use crate::job_store::JobStoreError;
+use crate::storage::{is_object_url, StorageError};
+use crate::util::generate_job_id;
+use crate::worker_download::DownloadState;
+use crate::worker_queue::JobId;
+use crate::worker_transcode::{TranscodeKey, TranscodeState};
+
+pub const DEFAULT_TRASH_DAYS: u64 = 30;
+
+#[derive(Debug,Error)]
+pub enum TrashError {
+    #[error("Entry was requested again after it was deleted: {0}")]
+    AlreadyExists(String),
+    #[error("Entry is being downloaded or transcoded: {0}")]
+    Busy(String),
+    #[error("Download of the transcode was deleted, restore or request it first: {0}")]
+    MissingDownload(String),
+    #[error("Failed to move or remove file: path={path}, err={error}")]
+    File { path: String, error: std::io::Error },
+    #[error("Invalid trash entry: {0}")]
+    InvalidEntry(#[from] serde_json::Error),
+    #[error("Object storage failed: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Object storage task failed: {0}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    #[error("Job store failed: {0}")]
+    JobStore(#[from] JobStoreError),
+}
+
+/// File of a deleted entry, where objects are left in the bucket until the entry is purged
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct TrashFile {
+    pub path: String,
+    pub trash_path: Option<String>,
+}
+
+/// Result of moving a file of a deleted entry into the trash
+pub struct TrashedFile {
+    pub path: String,
+    pub result: std::io::Result<()>,
+}
+
+pub fn get_trash_directory(app_config: &AppConfig) -> PathBuf {
+    app_config.data.join("trash")
+}
+
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    // NOTE: The download directory can be on another filesystem than the data directory
+    if let Err(err) = std::fs::rename(from, to) {
+        if err.kind() != std::io::ErrorKind::CrossesDevices {
+            return Err(err);
+        }
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Outcome of deleting a download or transcode along with the result of moving each of its files
+pub enum TrashOutcome {
+    /// Id of the trash entry the files were moved to, where the row was removed from the job store
+    Moved(i64),
+    /// Some files couldn't be moved so the others were put back and the row was kept to retry the delete
+    Pending,
+}
+
+/// Moves the files back to where they were deleted from when the delete can't be finished
+// NOTE: Files that can't be moved back are left in the trash directory and logged so they can be recovered by hand
+fn rollback_files(files: &[TrashFile]) {
+    for file in files {
+        let Some(trash_path) = file.trash_path.as_deref() else { continue; };
+        if let Err(err) = move_file(Path::new(trash_path), Path::new(file.path.as_str())) {
+            log::error!("Failed to move file back from trash: path={0}, trash_path={1}, err={2:?}", file.path, trash_path, err);
+        }
+    }
+    remove_trash_directory(files);
+}
+
+/// Moves the files into their own directory in the trash then removes the row with delete_row and records it so it can be restored
+/// Files that no longer exist are skipped so an earlier delete that partially failed can be retried
+// NOTE: The row is only removed once every file is in the trash, otherwise files would be left on disk without a row
+#[allow(clippy::too_many_arguments)]
+pub fn move_to_trash<T: Serialize>(
+    app: &AppState, kind: JobKind, key: &str, owner: Option<i64>, file_size_bytes: Option<u64>, entry: &T, paths: Vec<String>,
+    delete_row: impl FnOnce() -> Result<usize, JobStoreError>,
+) -> Result<(TrashOutcome, Vec<TrashedFile>), TrashError> {
+    let directory = get_trash_directory(&app.app_config).join(generate_job_id());
+    let mut files = Vec::<TrashFile>::new();
+    let mut results = Vec::new();
+    for (index, path) in paths.into_iter().enumerate() {
+        if is_object_url(path.as_str()) {
+            files.push(TrashFile { path: path.clone(), trash_path: None });
+            results.push(TrashedFile { path, result: Ok(()) });
+            continue;
+        }
+        if !Path::new(path.as_str()).exists() {
+            results.push(TrashedFile { path, result: Ok(()) });
+            continue;
+        }
+        let filename = Path::new(path.as_str()).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        // NOTE: Logs of the download and transcode can share a filename so each file is prefixed with its index
+        let trash_path = directory.join(format!("{index}_{filename}"));
+        let result = std::fs::create_dir_all(&directory).and_then(|_| move_file(Path::new(path.as_str()), &trash_path));
+        if result.is_ok() {
+            files.push(TrashFile { path: path.clone(), trash_path: Some(trash_path.to_string_lossy().into_owned()) });
+        }
+        results.push(TrashedFile { path, result });
+    }
+    if results.iter().any(|file| file.result.is_err()) {
+        rollback_files(&files);
+        return Ok((TrashOutcome::Pending, results));
+    }
+    let commit = || -> Result<i64, TrashError> {
+        let entry = serde_json::to_string(entry)?;
+        let files = serde_json::to_string(&files)?;
+        let id = app.job_store.insert_trash_entry(kind, key, owner, entry.as_str(), files.as_str(), file_size_bytes)?;
+        if let Err(err) = delete_row() {
+            let _ = app.job_store.delete_trash_entry(id);
+            return Err(err.into());
+        }
+        Ok(id)
+    };
+    match commit() {
+        Ok(id) => Ok((TrashOutcome::Moved(id), results)),
+        Err(err) => {
+            rollback_files(&files);
+            Err(err)
+        },
+    }
+}
+
+fn get_trash_files(entry: &TrashRow) -> Result<Vec<TrashFile>, TrashError> {
+    Ok(serde_json::from_str(entry.files.as_str())?)
+}
+
+/// Moves the files back to where they were deleted from
+// NOTE: Files that are already back in place are skipped so a partially failed restore can be retried
+fn restore_files(files: &[TrashFile]) -> Result<(), TrashError> {
+    for file in files {
+        let Some(trash_path) = file.trash_path.as_deref() else { continue; };
+        let (trash_path, path) = (Path::new(trash_path), Path::new(file.path.as_str()));
+        if !trash_path.exists() && path.exists() {
+            continue;
+        }
+        let res = match path.parent() {
+            Some(parent) => std::fs::create_dir_all(parent).and_then(|_| move_file(trash_path, path)),
+            None => move_file(trash_path, path),
+        };
+        res.map_err(|error| TrashError::File { path: file.path.clone(), error })?;
+    }
+    Ok(())
+}
+
+fn remove_trash_directory(files: &[TrashFile]) {
+    let directory = files.iter().filter_map(|file| Path::new(file.trash_path.as_deref()?).parent()).next();
+    if let Some(directory) = directory {
+        // NOTE: Only removed once it's empty so files that failed to restore are kept
+        let _ = std::fs::remove_dir(directory);
+    }
+}
+
+/// Puts the download or transcode back along with its files, failing if it was requested again since it was deleted
+pub fn restore_trash_entry(app: &AppState, entry: &TrashRow) -> Result<(), TrashError> {
+    let files = get_trash_files(entry)?;
+    match entry.kind {
+        JobKind::Download => {
+            let row: YtdlpRow = serde_json::from_str(entry.entry.as_str())?;
+            let download_state = app.download_cache.entry(row.video_id.clone()).or_default();
+            let mut state = download_state.lock().unwrap();
+            if state.worker_status.is_busy() {
+                return Err(TrashError::Busy(entry.key.clone()));
+            }
+            if app.job_store.select_ytdlp_entry(&row.video_id)?.is_some() {
+                return Err(TrashError::AlreadyExists(entry.key.clone()));
+            }
+            restore_files(&files)?;
+            app.job_store.insert_ytdlp_entry(&row.video_id, row.downloaded_bytes, row.owner)?;
+            app.job_store.update_ytdlp_entry(&row)?;
+            *state = DownloadState::default();
+            app.job_events.publish(JobId::new(JobKind::Download, row.video_id.as_str()));
+        },
+        JobKind::Transcode => {
+            let row: FfmpegRow = serde_json::from_str(entry.entry.as_str())?;
+            let transcode_key = TranscodeKey { video_id: row.video_id.clone(), audio_ext: row.audio_ext, variant: row.variant.clone() };
+            let transcode_state = app.transcode_cache.entry(transcode_key.clone()).or_default();
+            let mut state = transcode_state.lock().unwrap();
+            if state.worker_status.is_busy() {
+                return Err(TrashError::Busy(entry.key.clone()));
+            }
+            if app.job_store.select_ffmpeg_entry(&row.video_id, row.audio_ext, row.variant.as_str())?.is_some() {
+                return Err(TrashError::AlreadyExists(entry.key.clone()));
+            }
+            if app.job_store.select_ytdlp_entry(&row.video_id)?.is_none() {
+                return Err(TrashError::MissingDownload(entry.key.clone()));
+            }
+            restore_files(&files)?;
+            app.job_store.insert_ffmpeg_entry(
+                &row.video_id, row.audio_ext, row.variant.as_str(), row.transcode_options.as_deref(), row.owner,
+            )?;
+            app.job_store.update_ffmpeg_entry(&row)?;
+            *state = TranscodeState::default();
+            app.job_events.publish(JobId::new(JobKind::Transcode, transcode_key.as_str()));
+        },
+        JobKind::Hls => {},
+    }
+    app.job_store.delete_trash_entry(entry.id)?;
+    remove_trash_directory(&files);
+    Ok(())
+}
+
+/// Removes the files of the entry for good, keeping the entry if any of them couldn't be removed so it's retried
+pub async fn purge_trash_entry(app: &AppState, entry: &TrashRow) -> Result<(), TrashError> {
+    let files = get_trash_files(entry)?;
+    for file in files.iter() {
+        match file.trash_path.as_deref() {
+            Some(trash_path) => match std::fs::remove_file(trash_path) {
+                Ok(()) => {},
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+                Err(error) => return Err(TrashError::File { path: trash_path.to_owned(), error }),
+            },
+            None => {
+                let storage = app.app_config.object_storage.clone();
+                let url = file.path.clone();
+                web::block(move || match storage {
+                    Some(storage) => storage.delete_object(url.as_str()),
+                    None => Err(StorageError::NotConfigured(url)),
+                }).await??;
+            },
+        }
+    }
+    remove_trash_directory(&files);
+    app.job_store.delete_trash_entry(entry.id)?;
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use serde::Serialize;
+use thiserror::Error;
+use crate::database::{VideoId, VideoIdError};
+use crate::ffmpeg::TranscodeOptions;
+
+const YOUTUBE_HOSTS: [&str; 6] = [
+    "youtube.com", "www.youtube.com", "m.youtube.com", "music.youtube.com", "youtube-nocookie.com", "www.youtube-nocookie.com",
+];
+const SHORT_LINK_HOSTS: [&str; 2] = ["youtu.be", "www.youtu.be"];
+/// Paths where the video id is the segment after the prefix (e.g. /shorts/<id>)
+const VIDEO_ID_PATHS: [&str; 5] = ["shorts", "live", "embed", "v", "e"];
+const MAX_PLAYLIST_ID_LENGTH: usize = 64;
+
+#[derive(Debug,Error)]
+pub enum UrlParseError {
+    #[error("Invalid url: {0}")]
+    InvalidUrl(String),
+    #[error("Url isn't a YouTube video: {0}")]
+    NotYoutube(String),
+    #[error("Url doesn't have a video id: {0}")]
+    MissingVideoId(String),
+    #[error("Url has an invalid video id: {0}")]
+    InvalidVideoId(#[from] VideoIdError),
+    #[error("Url has an invalid time, expected seconds or a time such as 1h2m3s: {0}")]
+    InvalidTime(String),
+    #[error("Url has an invalid playlist id: {0}")]
+    InvalidPlaylistId(String),
+}
+
+/// Video a YouTube url points to along with the part of it and the playlist it was opened from
+#[derive(Clone,Debug,Serialize)]
+pub struct VideoUrl {
+    pub video_id: VideoId,
+    /// Start time from t= or start= which YouTube adds when sharing from the current time
+    pub start_ms: Option<u64>,
+    /// End time from end= which is only used by embedded players
+    pub end_ms: Option<u64>,
+    /// Playlist from list= which the video was opened from
+    pub playlist_id: Option<String>,
+}
+
+impl VideoUrl {
+    /// Trims the transcode to the start and end of the url unless the request set its own
+    pub fn apply_clip(&self, options: &mut TranscodeOptions) {
+        if options.trim_start_ms.is_none() && options.trim_end_ms.is_none() {
+            options.trim_start_ms = self.start_ms.filter(|&start| start > 0);
+            options.trim_end_ms = self.end_ms;
+        }
+    }
+}
+
+/// Parses times such as 90, 90s, 1m30s or 1h2m3s into milliseconds
+fn parse_time_ms(value: &str) -> Result<u64, UrlParseError> {
+    let invalid = || UrlParseError::InvalidTime(value.to_owned());
+    if let Ok(seconds) = value.parse::<u64>() {
+        return seconds.checked_mul(1000).ok_or_else(invalid);
+    }
+    let mut total_seconds: u64 = 0;
+    let mut digits = String::new();
+    let mut last_unit = u64::MAX;
+    for c in value.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                digits.push(c);
+                continue;
+            },
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        // NOTE: Units must be in order and only given once so 1s2m isn't accepted
+        if digits.is_empty() || unit >= last_unit {
+            return Err(invalid());
+        }
+        let count: u64 = digits.parse().map_err(|_| invalid())?;
+        total_seconds = count.checked_mul(unit).and_then(|v| total_seconds.checked_add(v)).ok_or_else(invalid)?;
+        digits.clear();
+        last_unit = unit;
+    }
+    if !digits.is_empty() || last_unit == u64::MAX {
+        return Err(invalid());
+    }
+    total_seconds.checked_mul(1000).ok_or_else(invalid)
+}
+
+fn parse_playlist_id(value: &str) -> Result<String, UrlParseError> {
+    let is_valid = (1..=MAX_PLAYLIST_ID_LENGTH).contains(&value.len()) &&
+        value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !is_valid {
+        return Err(UrlParseError::InvalidPlaylistId(value.to_owned()));
+    }
+    Ok(value.to_owned())
+}
+
+/// Parses watch, youtu.be, shorts, live, embed and music.youtube.com urls
+// NOTE: Links copied from the address bar of some browsers don't have a scheme so https is assumed
+pub fn parse_video_url(url: &str) -> Result<VideoUrl, UrlParseError> {
+    let url = url.trim();
+    let full_url = if url.contains("://") { url.to_owned() } else { format!("https://{url}") };
+    let parsed_url = reqwest::Url::parse(full_url.as_str()).map_err(|_| UrlParseError::InvalidUrl(url.to_owned()))?;
+    if !matches!(parsed_url.scheme(), "http" | "https") {
+        return Err(UrlParseError::NotYoutube(url.to_owned()));
+    }
+    let host = parsed_url.host_str().unwrap_or_default();
+    let get_query = |name: &str| parsed_url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    let mut segments = parsed_url.path_segments().into_iter().flatten().filter(|segment| !segment.is_empty());
+    let video_id = if SHORT_LINK_HOSTS.contains(&host) {
+        segments.next().map(|id| id.to_owned())
+    } else if YOUTUBE_HOSTS.contains(&host) {
+        match segments.next() {
+            Some("watch") => get_query("v"),
+            Some(prefix) if VIDEO_ID_PATHS.contains(&prefix) => segments.next().map(|id| id.to_owned()),
+            _ => None,
+        }
+    } else {
+        return Err(UrlParseError::NotYoutube(url.to_owned()));
+    };
+    let video_id = video_id.ok_or_else(|| UrlParseError::MissingVideoId(url.to_owned()))?;
+    // NOTE: Older links put the time in the fragment such as #t=1m30s
+    let fragment_time = parsed_url.fragment().and_then(|fragment| fragment.strip_prefix("t="));
+    let start_time = get_query("t").or_else(|| get_query("start")).or_else(|| fragment_time.map(|time| time.to_owned()));
+    Ok(VideoUrl {
+        video_id: VideoId::try_new(video_id.as_str())?,
+        start_ms: start_time.map(|time| parse_time_ms(time.as_str())).transpose()?,
+        end_ms: get_query("end").map(|time| parse_time_ms(time.as_str())).transpose()?,
+        playlist_id: get_query("list").map(|id| parse_playlist_id(id.as_str())).transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIDEO_ID: &str = "dQw4w9WgXcQ";
+
+    /// Url with the start_ms, end_ms and playlist_id it should be parsed into
+    type AcceptedUrl = (&'static str, Option<u64>, Option<u64>, Option<&'static str>);
+
+    #[test]
+    fn urls_are_accepted() {
+        let cases: &[AcceptedUrl] = &[
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ", None, None, None),
+            ("http://youtube.com/watch?feature=share&v=dQw4w9WgXcQ", None, None, None),
+            ("https://m.youtube.com/watch?v=dQw4w9WgXcQ", None, None, None),
+            ("https://music.youtube.com/watch?v=dQw4w9WgXcQ&list=PLabc-_123", None, None, Some("PLabc-_123")),
+            ("https://youtu.be/dQw4w9WgXcQ", None, None, None),
+            ("https://youtu.be/dQw4w9WgXcQ?t=43", Some(43_000), None, None),
+            ("https://www.youtube.com/shorts/dQw4w9WgXcQ", None, None, None),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ?feature=share", None, None, None),
+            ("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?start=10&end=70", Some(10_000), Some(70_000), None),
+            ("https://www.youtube.com/v/dQw4w9WgXcQ", None, None, None),
+            ("https://www.youtube.com/e/dQw4w9WgXcQ", None, None, None),
+            ("youtube.com/watch?v=dQw4w9WgXcQ", None, None, None),
+            ("  youtu.be/dQw4w9WgXcQ  ", None, None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1h2m3s", Some(3_723_000), None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1m30s", Some(90_000), None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=90s", Some(90_000), None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=2h5s", Some(7_205_000), None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=1m30s", Some(90_000), None, None),
+            // NOTE: The query takes priority over the fragment
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=5#t=1m30s", Some(5_000), None, None),
+        ];
+        for &(url, start_ms, end_ms, playlist_id) in cases {
+            let video_url = parse_video_url(url).unwrap_or_else(|err| panic!("{url}: {err}"));
+            assert_eq!(video_url.video_id.as_str(), VIDEO_ID, "{url}");
+            assert_eq!(video_url.start_ms, start_ms, "{url}");
+            assert_eq!(video_url.end_ms, end_ms, "{url}");
+            assert_eq!(video_url.playlist_id.as_deref(), playlist_id, "{url}");
+        }
+    }
+
+    #[test]
+    fn urls_are_rejected() {
+        let cases = [
+            "",
+            "not a url",
+            "ftp://youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://vimeo.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com.evil.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/watch",
+            "https://www.youtube.com/channel/dQw4w9WgXcQ",
+            "https://www.youtube.com/shorts/",
+            "https://youtu.be/",
+            "https://youtu.be/short",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=bad%20list",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1s2m",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1m1m",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1m30",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=m",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1d",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=-5",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&end=abc",
+        ];
+        for url in cases {
+            assert!(parse_video_url(url).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn times_are_parsed() {
+        assert_eq!(parse_time_ms("0").unwrap(), 0);
+        assert_eq!(parse_time_ms("3s").unwrap(), 3_000);
+        assert_eq!(parse_time_ms("1h").unwrap(), 3_600_000);
+        assert_eq!(parse_time_ms("1h3s").unwrap(), 3_603_000);
+        for time in ["", "s", "1h2h", "2s1m", "1.5s", "1m 2s"] {
+            assert!(matches!(parse_time_ms(time), Err(UrlParseError::InvalidTime(_))), "{time}");
+        }
+    }
+
+    #[test]
+    fn time_overflow_is_rejected() {
+        let max_seconds = u64::MAX / 1000;
+        assert_eq!(parse_time_ms(max_seconds.to_string().as_str()).unwrap(), max_seconds*1000);
+        let cases = [
+            (max_seconds+1).to_string(),
+            u64::MAX.to_string(),
+            format!("{0}0", u64::MAX),
+            format!("{0}s", max_seconds+1),
+            format!("{0}h", u64::MAX / 3600 + 1),
+            format!("{0}h{1}s", u64::MAX / 3600, u64::MAX),
+        ];
+        for time in cases {
+            assert!(matches!(parse_time_ms(time.as_str()), Err(UrlParseError::InvalidTime(_))), "{time}");
+        }
+    }
+}
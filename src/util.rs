@@ -6,6 +6,132 @@ pub fn get_unix_time() -> u64 {
         .as_secs()
 }
 
+/// Converts days since the Unix epoch into the (year, month, day) of the proleptic Gregorian calendar
+// NOTE: Uses Howard Hinnant's civil_from_days so we don't need a date library for UTC timestamps
+pub fn get_civil_date(days: u64) -> (i64, u32, u32) {
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era/1460 + day_of_era/36524 - day_of_era/146096) / 365;
+    let day_of_year = day_of_era - (365*year_of_era + year_of_era/4 - year_of_era/100);
+    let mp = (5*day_of_year + 2) / 153;
+    let day = day_of_year - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era*400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+/// Hex encoded sha256 digest of a file's contents
+pub fn get_file_sha256(path: &std::path::Path) -> Result<String, std::io::Error> {
+    use sha2::{Digest, Sha256};
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Moves a finished file out of the staging directory so the destination never has a partially written file
+// NOTE: Renaming across filesystems fails so the file is copied next to the destination first and then renamed over it
+pub fn move_staged_file(from: &std::path::Path, to: &std::path::Path) -> Result<(), std::io::Error> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::rename(from, to) {
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {},
+        res => return res,
+    }
+    let mut copy_path = to.as_os_str().to_owned();
+    copy_path.push(".staging");
+    let copy_path = std::path::PathBuf::from(copy_path);
+    let res = std::fs::copy(from, &copy_path).and_then(|_| std::fs::rename(&copy_path, to));
+    if res.is_err() {
+        let _ = std::fs::remove_file(&copy_path);
+    }
+    res?;
+    std::fs::remove_file(from)
+}
+
+pub const JOB_ID_LENGTH: usize = 16;
+
+/// Unique id of an accepted request
+// NOTE: Hashing the time with a counter is unique enough without depending on a random number generator
+pub fn generate_job_id() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::SystemTime;
+    use sha2::{Digest, Sha256};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("System time before Unix epoch")
+        .as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let seed = format!("{nanos}.{count}.{0}", std::process::id());
+    let digest = format!("{:x}", Sha256::digest(seed.as_bytes()));
+    digest[..JOB_ID_LENGTH].to_owned()
+}
+
+/// Revision of a worker state which is unique across all states so resetting a state never reuses an old revision
+// NOTE: Starting from the current time means revisions from before a restart aren't reused either
+pub fn next_state_revision() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::SystemTime;
+    lazy_static::lazy_static! {
+        static ref COUNTER: AtomicU64 = AtomicU64::new(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("System time before Unix epoch")
+                .as_nanos() as u64
+        );
+    }
+    COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+pub fn is_valid_job_id(job_id: &str) -> bool {
+    job_id.len() == JOB_ID_LENGTH && job_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Removes characters and names that can't be used in filenames on Windows or Linux
+pub fn sanitize_filename(name: &str) -> String {
+    // NOTE: Leave space for a variant and extension to be appended while staying under the 255 byte limit
+    const MAX_FILENAME_BYTES: usize = 200;
+    const WIN32_RESERVED_NAMES: [&str; 4] = ["con", "prn", "aux", "nul"];
+    const WIN32_RESERVED_PREFIXES: [&str; 2] = ["com", "lpt"];
+    let name: String = name.chars().filter(|&c| !matches!(c,
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' |
+        // zero width and bidirectional formatting characters which can disguise the extension
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}'
+    ) && (!c.is_control() || c.is_whitespace())).collect();
+    let mut name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.len() > MAX_FILENAME_BYTES {
+        let mut end = MAX_FILENAME_BYTES;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+    // NOTE: Windows strips trailing dots and spaces which can turn a valid name into a reserved one
+    let name = name.trim_end_matches(['.', ' ']).trim_start();
+    let stem = name.split('.').next().unwrap_or_default().to_ascii_lowercase();
+    let is_reserved = WIN32_RESERVED_NAMES.contains(&stem.as_str()) || WIN32_RESERVED_PREFIXES.iter().any(|prefix| {
+        stem.strip_prefix(prefix).is_some_and(|digit| digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit()))
+    });
+    if is_reserved {
+        return format!("_{name}");
+    }
+    name.to_owned()
+}
+
+/// Human friendly filename of a video without an extension
+pub fn get_title_filename(title: &str, channel: &str) -> Option<String> {
+    let title = sanitize_filename(title);
+    let channel = sanitize_filename(channel);
+    match (title.is_empty(), channel.is_empty()) {
+        (true, _) => None,
+        (false, true) => Some(title),
+        (false, false) => Some(sanitize_filename(format!("{title} - {channel}").as_str())),
+    }
+}
+
 pub fn defer<F: FnOnce()>(f: F) -> impl Drop {
     use core::mem::ManuallyDrop;
     struct Defer<F: FnOnce()>(ManuallyDrop<F>);
@@ -43,22 +169,36 @@ macro_rules! generate_bidirectional_binding {
     }
 }
 
-pub struct ConvertCarriageReturnToNewLine<T: std::io::Read> {
-    reader: T,
-}
-
-impl<T: std::io::Read> ConvertCarriageReturnToNewLine<T> {
-    pub fn new(reader: T) -> Self {
-        Self { reader }
-    }
-}
-
-impl<T: std::io::Read> std::io::Read for ConvertCarriageReturnToNewLine<T> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let res = self.reader.read(buf);
-        if let Ok(total) = res {
-            buf[..total].iter_mut().filter(|v| **v == b'\r').for_each(|v| *v = b'\n');
+/// Reads a line of process output where progress bars end their lines with a carriage return instead of a new line
+/// The line ending is always stored as a new line, returns 0 at the end of the output
+pub async fn read_output_line<R: tokio::io::AsyncBufRead + Unpin>(reader: &mut R, line: &mut String) -> std::io::Result<usize> {
+    use tokio::io::AsyncBufReadExt;
+    let mut buffer = Vec::new();
+    loop {
+        let (is_end, total_used) = {
+            let available = reader.fill_buf().await?;
+            if available.is_empty() {
+                break;
+            }
+            match available.iter().position(|&c| c == b'\r' || c == b'\n') {
+                Some(index) => {
+                    buffer.extend_from_slice(&available[..=index]);
+                    (true, index+1)
+                },
+                None => {
+                    buffer.extend_from_slice(available);
+                    (false, available.len())
+                },
+            }
+        };
+        reader.consume(total_used);
+        if is_end {
+            break;
         }
-        res
     }
+    if let Some(ending) = buffer.last_mut().filter(|c| **c == b'\r') {
+        *ending = b'\n';
+    }
+    line.push_str(String::from_utf8_lossy(&buffer).as_ref());
+    Ok(buffer.len())
 }
@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+use actix_web::web;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use crate::app::AppConfig;
+use crate::storage::{is_object_url, StorageError};
+use crate::util::get_file_sha256;
+
+#[derive(Debug,Error)]
+pub enum VerifyError {
+    #[error("Failed to read file: {0:?}")]
+    FileRead(std::io::Error),
+    #[error("Object storage failed: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Failed to run blocking task: {0:?}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyStatus {
+    /// File matches the hash recorded when it was written
+    Valid,
+    /// File changed since it was written, either from bit-rot or a partial write
+    Mismatch,
+    Missing,
+    /// Entry finished before hashes were recorded so there is nothing to compare against
+    Unrecorded,
+}
+
+#[derive(Clone,Debug,Serialize)]
+pub struct VerifyResult {
+    pub status: VerifyStatus,
+    pub expected_hash: Option<String>,
+    pub actual_hash: Option<String>,
+    pub file_size_bytes: Option<u64>,
+}
+
+/// Hashes the file on disk or in the bucket, returning None if it doesn't exist
+// NOTE: Objects are streamed through the hasher so large files aren't held in memory
+async fn hash_stored_file(app_config: &AppConfig, path: &str) -> Result<Option<(String, u64)>, VerifyError> {
+    if !is_object_url(path) {
+        let path = PathBuf::from(path);
+        let res = web::block(move || -> std::io::Result<(String, u64)> {
+            let file_size_bytes = std::fs::metadata(&path)?.len();
+            Ok((get_file_sha256(&path)?, file_size_bytes))
+        }).await?;
+        return match res {
+            Ok(res) => Ok(Some(res)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(VerifyError::FileRead(err)),
+        };
+    }
+    let Some(ref storage) = app_config.object_storage else {
+        return Err(StorageError::NotConfigured(path.to_owned()).into());
+    };
+    let mut response = match storage.get_object(path, None).await {
+        Ok(response) => response,
+        Err(StorageError::BadStatus { status: 404, .. }) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut hasher = Sha256::new();
+    let mut file_size_bytes: u64 = 0;
+    while let Some(chunk) = response.chunk().await.map_err(StorageError::from)? {
+        hasher.update(&chunk);
+        file_size_bytes += chunk.len() as u64;
+    }
+    Ok(Some((format!("{:x}", hasher.finalize()), file_size_bytes)))
+}
+
+/// Re-hashes the file and compares it against the hash recorded when it was written
+pub async fn verify_file(app_config: &AppConfig, path: &str, expected_hash: Option<&str>) -> Result<VerifyResult, VerifyError> {
+    let Some((actual_hash, file_size_bytes)) = hash_stored_file(app_config, path).await? else {
+        return Ok(VerifyResult {
+            status: VerifyStatus::Missing,
+            expected_hash: expected_hash.map(|hash| hash.to_owned()),
+            actual_hash: None,
+            file_size_bytes: None,
+        });
+    };
+    let status = match expected_hash {
+        None => VerifyStatus::Unrecorded,
+        Some(expected_hash) if expected_hash == actual_hash => VerifyStatus::Valid,
+        Some(_) => VerifyStatus::Mismatch,
+    };
+    Ok(VerifyResult {
+        status,
+        expected_hash: expected_hash.map(|hash| hash.to_owned()),
+        actual_hash: Some(actual_hash),
+        file_size_bytes: Some(file_size_bytes),
+    })
+}
@@ -0,0 +1,133 @@
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::process_priority::ProcessPriority;
+
+pub const TOTAL_BUCKETS: usize = 1000;
+// NOTE: A low sample rate is enough for drawing and keeps decoding fast
+const SAMPLE_RATE: u64 = 8000;
+// NOTE: Samples are summed into 10ms chunks so we don't keep the decoded audio in memory
+const CHUNK_SAMPLES: usize = 80;
+
+/// RMS level of each bucket normalised to the range [0,1]
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct Waveform {
+    pub duration_ms: u64,
+    pub peaks: Vec<f32>,
+}
+
+#[derive(Debug,Error)]
+pub enum WaveformError {
+    #[error("Failed to run {binary}: {error:?}")]
+    Spawn { binary: String, error: std::io::Error },
+    #[error("Failed to read decoded audio: {0:?}")]
+    Read(std::io::Error),
+    #[error("ffmpeg failed with bad code: {0:?}")]
+    BadExitCode(Option<i32>),
+    #[error("File has no audio samples")]
+    Empty,
+}
+
+pub fn get_waveform_arguments(path: &str) -> Vec<String> {
+    [
+        "-hide_banner", "-nostats", "-v", "error",
+        "-i", path,
+        "-map", "0:a:0",
+        "-ac", "1",
+        "-ar", SAMPLE_RATE.to_string().as_str(),
+        "-f", "s16le", "-",
+    ].iter().map(|&arg| arg.to_owned()).collect()
+}
+
+/// Reads until the buffer is full or the end of the stream is reached
+fn read_chunk(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total_read = 0;
+    while total_read < buffer.len() {
+        match reader.read(&mut buffer[total_read..]) {
+            Ok(0) => break,
+            Ok(n) => total_read += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(total_read)
+}
+
+/// Decodes the file with ffmpeg and measures the RMS level of each bucket
+pub fn generate_waveform(ffmpeg_binary: &Path, priority: &ProcessPriority, path: &Path) -> Result<Waveform, WaveformError> {
+    let mut command = Command::new(ffmpeg_binary);
+    priority.apply(&mut command);
+    let mut process = command
+        .args(get_waveform_arguments(path.to_str().unwrap()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| WaveformError::Spawn { binary: ffmpeg_binary.to_string_lossy().to_string(), error })?;
+    let mut stdout = process.stdout.take().expect("Stdout should be piped");
+    // (sum of squares, total samples) for each chunk
+    let mut chunks = Vec::<(f64, usize)>::new();
+    let mut buffer = vec![0u8; CHUNK_SAMPLES*2];
+    loop {
+        let total_read = match read_chunk(&mut stdout, buffer.as_mut_slice()) {
+            Ok(total_read) => total_read,
+            Err(err) => {
+                let _ = process.kill();
+                let _ = process.wait();
+                return Err(WaveformError::Read(err));
+            },
+        };
+        let total_samples = total_read / 2;
+        if total_samples == 0 {
+            break;
+        }
+        let sum_squares: f64 = buffer[..total_samples*2]
+            .chunks_exact(2)
+            .map(|bytes| {
+                let sample = i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / i16::MAX as f64;
+                sample*sample
+            })
+            .sum();
+        chunks.push((sum_squares, total_samples));
+    }
+    let status = process.wait().map_err(WaveformError::Read)?;
+    if !status.success() {
+        return Err(WaveformError::BadExitCode(status.code()));
+    }
+    let total_samples: usize = chunks.iter().map(|&(_, total)| total).sum();
+    if total_samples == 0 {
+        return Err(WaveformError::Empty);
+    }
+    let total_buckets = TOTAL_BUCKETS.min(chunks.len());
+    let peaks = (0..total_buckets).map(|i| {
+        let start = i*chunks.len() / total_buckets;
+        let end = (i+1)*chunks.len() / total_buckets;
+        let (sum_squares, total) = chunks[start..end].iter()
+            .fold((0.0, 0), |(sum_a, total_a), &(sum_b, total_b)| (sum_a+sum_b, total_a+total_b));
+        let rms = (sum_squares / total as f64).sqrt().min(1.0);
+        // NOTE: Round to keep the json compact since the frontend only needs a few pixels of precision
+        ((rms*1000.0).round() / 1000.0) as f32
+    }).collect();
+    Ok(Waveform {
+        duration_ms: total_samples as u64 * 1000 / SAMPLE_RATE,
+        peaks,
+    })
+}
+
+impl Waveform {
+    pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(data.as_slice()).map_err(std::io::Error::from)
+    }
+
+    /// Writes to a temporary file first so readers never see a partially written waveform
+    pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        let data = serde_json::to_vec(self).map_err(std::io::Error::from)?;
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, data)?;
+        std::fs::rename(&temp_path, path)
+    }
+}
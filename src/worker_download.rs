@@ -1,26 +1,33 @@
 use std::cell::RefCell;
-use std::io::{BufReader, BufWriter, BufRead, Write};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::time::{Duration, Instant};
 use dashmap::DashMap;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::io::BufReader;
+use tokio::task::block_in_place;
 use crate::app::{AppConfig, WorkerError, WorkerThreadPool, WorkerCacheEntry};
-use crate::database::{
-    DatabasePool, VideoId, WorkerStatus,
-    insert_ytdlp_entry, select_ytdlp_entry, select_and_update_ytdlp_entry,
-};
-use crate::util::{get_unix_time, defer, ConvertCarriageReturnToNewLine};
+use crate::disk_space::{check_free_space, estimate_download_bytes, DiskSpaceError};
+use crate::database::{VideoId, WorkerStatus, YtdlpRow, JobKind, EntryFilter};
+use crate::downloader::{DownloadProgress, DownloadRequest, ParsedStdoutLine, ParsedStderrLine};
+use crate::error_code::ErrorCode;
+use crate::job_events::JobEventBus;
+use crate::job_store::{SharedJobStore, JobStoreError};
+use crate::log_file::RotatingLogFile;
+use crate::request_id::current_request_id;
+use crate::worker_queue::{JobId, record_job_duration, record_job_history};
+use crate::util::{get_unix_time, get_file_sha256, next_state_revision, defer, read_output_line, move_staged_file};
 use crate::ytdlp;
 
-#[derive(Clone,Debug,Serialize)]
+#[derive(Clone,Debug,Serialize,Deserialize)]
 pub struct DownloadState {
     pub worker_status: WorkerStatus,
     pub file_cached: bool,
     pub fail_reason: Option<String>,
+    pub fail_code: Option<ErrorCode>,
     pub start_time_unix: u64,
     pub end_time_unix: u64,
     pub eta_seconds: Option<u64>,
@@ -28,6 +35,17 @@ pub struct DownloadState {
     pub downloaded_bytes: Option<usize>,
     pub total_bytes: Option<usize>,
     pub speed_bytes: Option<usize>,
+    pub smoothed_speed_bytes: Option<usize>,
+    /// Percentage of the total bytes downloaded, missing if the total is unknown
+    pub progress_percent: Option<f32>,
+    /// Changes whenever the state does so pollers can skip unchanged states, see next_state_revision()
+    pub revision: u64,
+    /// Livestream is being recorded so the total size and eta are unknown until it ends
+    /// The elapsed seconds are then how long it has been recording for
+    pub is_live_recording: bool,
+    /// File that yt-dlp is currently writing to which can be read before the download finishes
+    #[serde(skip)]
+    pub partial_path: Option<PathBuf>,
 }
 
 impl Default for DownloadState {
@@ -37,6 +55,7 @@ impl Default for DownloadState {
             worker_status: WorkerStatus::None,
             file_cached: false,
             fail_reason: None,
+            fail_code: None,
             start_time_unix: curr_time,
             end_time_unix: curr_time,
             eta_seconds: None,
@@ -44,6 +63,11 @@ impl Default for DownloadState {
             downloaded_bytes: None,
             total_bytes: None,
             speed_bytes: None,
+            smoothed_speed_bytes: None,
+            progress_percent: None,
+            revision: next_state_revision(),
+            is_live_recording: false,
+            partial_path: None,
         }
     }
 }
@@ -55,13 +79,54 @@ fn update_field<T>(dst: &mut Option<T>, src: Option<T>) {
 }
 
 impl DownloadState {
-    pub fn update_from_ytdlp(&mut self, progress: ytdlp::DownloadProgress) {
+    pub fn bump_revision(&mut self) {
+        self.revision = next_state_revision();
+    }
+
+    /// State stored when the download ended, which is missing while it's being retried
+    // NOTE: Entries from before final states were stored only know their status
+    pub fn from_entry(entry: &YtdlpRow) -> Option<Self> {
+        if !matches!(entry.status, WorkerStatus::Finished | WorkerStatus::Failed) {
+            return None;
+        }
+        let state = entry.final_state.as_deref().and_then(|state| serde_json::from_str(state).ok());
+        Some(state.unwrap_or_else(|| Self {
+            worker_status: entry.status,
+            file_cached: entry.status == WorkerStatus::Finished,
+            start_time_unix: entry.unix_time,
+            ..Default::default()
+        }))
+    }
+
+    pub fn update_from_progress(&mut self, progress: DownloadProgress) {
+        self.bump_revision();
         self.end_time_unix = get_unix_time();
         update_field(&mut self.eta_seconds, progress.eta_seconds);
         update_field(&mut self.elapsed_seconds, progress.elapsed_seconds);
         update_field(&mut self.downloaded_bytes, progress.downloaded_bytes);
         update_field(&mut self.total_bytes, progress.total_bytes);
         update_field(&mut self.speed_bytes, progress.speed_bytes);
+        // NOTE: yt-dlp prints the instantaneous speed which jumps around a lot
+        //       so we keep an exponentially weighted moving average for display
+        const SMOOTHING_FACTOR: f64 = 0.2;
+        if let Some(speed) = progress.speed_bytes {
+            let smoothed = match self.smoothed_speed_bytes {
+                None => speed as f64,
+                Some(old) => SMOOTHING_FACTOR*(speed as f64) + (1.0-SMOOTHING_FACTOR)*(old as f64),
+            };
+            self.smoothed_speed_bytes = Some(smoothed as usize);
+        }
+        self.update_progress_percent();
+    }
+
+    fn update_progress_percent(&mut self) {
+        // NOTE: yt-dlp estimates the total size of fragmented downloads so the downloaded bytes can overshoot it
+        self.progress_percent = match (self.downloaded_bytes, self.total_bytes) {
+            (Some(downloaded), Some(total)) if total > 0 && !self.is_live_recording => {
+                Some((downloaded as f32 / total as f32 * 100.0).min(100.0))
+            },
+            _ => None,
+        };
     }
 }
 
@@ -69,10 +134,10 @@ pub type DownloadCache = Arc<DashMap<VideoId, WorkerCacheEntry<DownloadState>>>;
 
 #[derive(Debug,Error)]
 pub enum DownloadStartError {
-    #[error("Database connection failed: {0:?}")]
-    DatabaseConnection(#[from] r2d2::Error),
-    #[error("Database execute failed: {0:?}")]
-    DatabaseExecute(#[from] rusqlite::Error),
+    #[error("Database failed: {0}")]
+    Database(#[from] JobStoreError),
+    #[error("Disk full: {0}")]
+    DiskFull(#[from] DiskSpaceError),
 }
 
 #[derive(Debug,Error)]
@@ -89,26 +154,121 @@ pub enum DownloadError {
     MissingOutputFile(PathBuf),
     #[error("Error stored in system log")]
     LoggedFail,
-    #[error("Database connection failed: {0:?}")]
-    DatabaseConnection(#[from] r2d2::Error),
-    #[error("Database execute failed: {0:?}")]
-    DatabaseExecute(#[from] rusqlite::Error),
+    #[error("ytdlp failed with: {0}")]
+    ProcessFailed(ErrorCode),
+    #[error("Database failed: {0}")]
+    Database(#[from] JobStoreError),
+    #[error("Server stopped during the download")]
+    Interrupted,
+    #[error("Failed to move finished download out of the staging directory: {0:?}")]
+    StageOutputFile(std::io::Error),
+}
+
+impl DownloadError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::WorkerError(err) => err.code(),
+            Self::InvalidVideoId => ErrorCode::VideoUnavailable,
+            Self::UsageError(_) | Self::MissingOutputPath | Self::MissingOutputFile(_) | Self::LoggedFail => ErrorCode::YtdlpFailed,
+            Self::ProcessFailed(code) => *code,
+            Self::Database(_) => ErrorCode::InternalError,
+            Self::Interrupted => ErrorCode::Interrupted,
+            Self::StageOutputFile(err) => ErrorCode::from_io_error(err, ErrorCode::InternalError),
+        }
+    }
+}
+
+/// Largest .part file left by yt-dlp for this video, which has the id somewhere in its path due to the output template
+fn find_partial_download(directory: &Path, video_id: &VideoId) -> Option<(PathBuf, u64)> {
+    let mut partial_download: Option<(PathBuf, u64)> = None;
+    for entry in std::fs::read_dir(directory).ok()?.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else { continue; };
+        let candidate = if file_type.is_dir() {
+            find_partial_download(&path, video_id)
+        } else if path.to_string_lossy().contains(video_id.as_str()) && path.extension().is_some_and(|ext| ext == "part") {
+            entry.metadata().ok().map(|metadata| (path, metadata.len()))
+        } else {
+            None
+        };
+        if let Some(candidate) = candidate.filter(|(_, size)| partial_download.as_ref().is_none_or(|(_, max_size)| size > max_size)) {
+            partial_download = Some(candidate);
+        }
+    }
+    partial_download
+}
+
+/// Downloads that were queued or running when the server stopped are marked as failed so requesting them again resumes them
+/// The downloaded bytes are read from the partial file since the stored progress is only updated periodically
+pub fn recover_interrupted_downloads(app_config: &AppConfig, job_store: &SharedJobStore) -> Result<usize, JobStoreError> {
+    let mut entries = Vec::<YtdlpRow>::new();
+    for status in [WorkerStatus::Queued, WorkerStatus::Running] {
+        entries.extend(job_store.select_filtered_ytdlp_entries(&EntryFilter { status: Some(status), ..Default::default() })?);
+    }
+    for entry in entries.iter() {
+        let partial_download = find_partial_download(&app_config.get_download_staging(), &entry.video_id);
+        let downloaded_bytes = match partial_download {
+            Some((ref path, size)) => {
+                log::info!("Found partial download: id={0}, path={1}, bytes={size}", entry.video_id.as_str(), path.to_string_lossy());
+                Some(size as usize)
+            },
+            None => entry.downloaded_bytes,
+        };
+        let error = DownloadError::Interrupted;
+        let state = DownloadState {
+            worker_status: WorkerStatus::Failed,
+            fail_code: Some(error.code()),
+            fail_reason: Some(error.to_string()),
+            start_time_unix: entry.unix_time,
+            downloaded_bytes,
+            ..Default::default()
+        };
+        job_store.select_and_update_ytdlp_entry(&entry.video_id, |entry| {
+            entry.status = WorkerStatus::Failed;
+            entry.downloaded_bytes = downloaded_bytes;
+            entry.final_state = serde_json::to_string(&state).ok();
+        })?;
+    }
+    Ok(entries.len())
+}
+
+/// A finished download is replaced if a different format or audio language was pinned
+// NOTE: The language is compared even if it isn't given so the default track replaces a dubbed one
+fn is_other_format(entry: &YtdlpRow, download_options: &ytdlp::DownloadOptions) -> bool {
+    download_options.format_id.as_ref().is_some_and(|format_id| entry.format_id.as_ref() != Some(format_id)) ||
+        entry.audio_lang != download_options.audio_lang
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn try_start_download_worker(
-    video_id: VideoId, download_cache: DownloadCache, app_config: Arc<AppConfig>,
-    db_pool: DatabasePool, worker_thread_pool: WorkerThreadPool,
+    video_id: VideoId, download_cache: DownloadCache, job_events: JobEventBus, app_config: Arc<AppConfig>,
+    job_store: SharedJobStore, worker_thread_pool: WorkerThreadPool,
+    download_options: ytdlp::DownloadOptions, owner: Option<i64>,
 ) -> Result<WorkerStatus, DownloadStartError> {
     // check if download in progress (cache hit)
     {
         let download_state = download_cache.entry(video_id.clone()).or_default();
-        let mut state = download_state.0.lock().unwrap();
+        let mut state = download_state.lock().unwrap();
         match state.worker_status {
             WorkerStatus::None | WorkerStatus::Failed => {
                 state.worker_status = WorkerStatus::Queued;
-                download_state.1.notify_all();
+                state.bump_revision();
+                job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
             },
-            WorkerStatus::Queued | WorkerStatus::Running | WorkerStatus::Finished => return Ok(state.worker_status),
+            WorkerStatus::Finished => {
+                let entry = job_store.select_ytdlp_entry(&video_id)?;
+                let is_replaced = match entry {
+                    Some(ref entry) => is_other_format(entry, &download_options),
+                    None => true,
+                };
+                if !is_replaced {
+                    return Ok(state.worker_status);
+                }
+                state.worker_status = WorkerStatus::Queued;
+                state.bump_revision();
+                job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
+            },
+            WorkerStatus::Queued | WorkerStatus::Running => return Ok(state.worker_status),
         }
     }
     // rollback download cache entry if enqueue failed
@@ -117,37 +277,69 @@ pub fn try_start_download_worker(
         let is_queue_success = is_queue_success.clone();
         let video_id = video_id.clone();
         let download_cache = download_cache.clone();
+        let job_events = job_events.clone();
         move || {
             if !*is_queue_success.borrow() {
                 let download_state = download_cache.get(&video_id).unwrap();
-                download_state.0.lock().unwrap().worker_status = WorkerStatus::None;
-                download_state.1.notify_all();
+                let mut state = download_state.lock().unwrap();
+                state.worker_status = WorkerStatus::None;
+                state.bump_revision();
+                job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
             }
         }
     });
-    {
-        let db_conn = db_pool.get()?;
+    let resume_bytes: Option<usize> = {
         // check if download finished on disk (cache miss due to reset)
-        let entry = select_ytdlp_entry(&db_conn, &video_id)?;
+        let entry = job_store.select_ytdlp_entry(&video_id)?;
+        let mut resume_bytes = None;
         if let Some(entry) = entry {
+            let is_replaced = is_other_format(&entry, &download_options);
             if let Some(audio_path) = entry.audio_path {
                 let status = entry.status;
                 let audio_path = PathBuf::from(audio_path);
+                // NOTE: yt-dlp skips files that already exist so we remove the old format before downloading again
+                if is_replaced && audio_path.exists() {
+                    let _ = std::fs::remove_file(&audio_path);
+                }
                 if status == WorkerStatus::Finished && audio_path.exists() {
                     let download_state = download_cache.entry(video_id.clone()).or_default();
-                    let mut state = download_state.0.lock().unwrap();
+                    let mut state = download_state.lock().unwrap();
                     state.worker_status = status;
                     state.file_cached = true;
-                    download_state.1.notify_all();
+                    state.progress_percent = Some(100.0);
+                    state.bump_revision();
+                    job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
                     *is_queue_success.borrow_mut() = true;
                     return Ok(status);
                 }
             }
+            // download was interrupted so resume from partially downloaded file
+            if entry.status != WorkerStatus::Finished {
+                resume_bytes = entry.downloaded_bytes.filter(|&bytes| bytes > 0);
+            }
+        }
+        // check the download fits on disk before yt-dlp starts writing it
+        // NOTE: The size is estimated from the duration in the metadata and only the reserved space is checked without it
+        let duration_ms = job_store.select_metadata_entry(&video_id)?.and_then(|metadata| metadata.duration_ms);
+        let download_bytes = duration_ms.map(estimate_download_bytes);
+        for path in [app_config.get_download_staging(), app_config.download.clone()] {
+            check_free_space(&path, download_bytes, app_config.min_free_space_bytes)?;
         }
         // start download worker
-        let _ = insert_ytdlp_entry(&db_conn, &video_id)?;
+        let _ = job_store.insert_ytdlp_entry(&video_id, resume_bytes, owner)?;
+        resume_bytes
+    };
+    if let Some(download_state) = download_cache.get(&video_id) {
+        let mut state = download_state.lock().unwrap();
+        state.downloaded_bytes = resume_bytes;
+        state.progress_percent = None;
+        state.partial_path = None;
+        state.is_live_recording = download_options.live_from_start == Some(true);
+        state.bump_revision();
     }
-    worker_thread_pool.lock().unwrap().execute(move || {
+    let audio_lang = download_options.audio_lang.clone();
+    worker_thread_pool.execute(JobId::new(JobKind::Download, video_id.as_str()), async move {
+        let start_time = Instant::now();
         log::info!("Launching download process: {0}", video_id.as_str());
         // setup logging
         let system_log_path = app_config.download.join(format!("{}.system.log", video_id.as_str()));
@@ -158,16 +350,25 @@ pub fn try_start_download_worker(
                 return;
             },
         };
-        if let Ok(db_conn) = db_pool.get() {
-            select_and_update_ytdlp_entry(&db_conn, &video_id, |entry| {
-                entry.system_log_path = Some(system_log_path.to_str().unwrap().to_owned());
-            }).unwrap();
+        let res = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+            entry.system_log_path = Some(system_log_path.to_str().unwrap().to_owned());
+            entry.audio_lang = audio_lang;
+        });
+        if let Err(err) = res {
+            log::error!("Failed to store system log path: id={0}, err={1:?}", video_id.as_str(), err);
         }
         let system_log_writer = Arc::new(Mutex::new(BufWriter::new(system_log_file)));
+        if let Some(request_id) = current_request_id() {
+            let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[info] Started by request: {0}", request_id.as_str());
+        }
+        if let Some(bytes) = resume_bytes {
+            let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[info] Resuming partial download from {bytes} bytes");
+        }
         // launch process
         let res = enqueue_download_worker(
-            video_id.clone(), download_cache.clone(), app_config.clone(), db_pool.clone(), system_log_writer.clone(),
-        );
+            video_id.clone(), download_cache.clone(), job_events.clone(), app_config.clone(), job_store.clone(), system_log_writer.clone(),
+            resume_bytes.is_some(), download_options,
+        ).await;
         if let Err(ref err) = res {
             let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[error] Worker failed with: {err:?}");
         }
@@ -176,47 +377,86 @@ pub fn try_start_download_worker(
             Ok(path) => (Some(path), WorkerStatus::Finished, None),
             Err(err) => (None, WorkerStatus::Failed, Some(err)),
         };
-        {
-            let db_conn = db_pool.get().unwrap();
-            let _ = select_and_update_ytdlp_entry(&db_conn, &video_id, |entry| {
-                entry.audio_path = audio_path.map(|p| p.to_str().unwrap().to_string());
-                entry.status = worker_status;
-            }).unwrap();
+        if worker_status == WorkerStatus::Finished {
+            record_job_duration(&job_store, JobKind::Download, start_time);
         }
-        // NOTE: update cache so changes to database are visible to signal listeners (transcode threads)
+        let file_size_bytes = audio_path.as_ref().and_then(|p| std::fs::metadata(p).ok()).map(|m| m.len());
+        record_job_history(
+            &job_store, &JobId::new(JobKind::Download, video_id.as_str()), worker_status,
+            worker_error.as_ref().map(|err| err.code().as_str()), file_size_bytes, start_time,
+        );
+        let source_hash = audio_path.as_ref().and_then(|p| match block_in_place(|| get_file_sha256(p)) {
+            Ok(hash) => Some(hash),
+            Err(err) => {
+                let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Failed to hash downloaded file: {err:?}");
+                None
+            },
+        });
         let download_state = download_cache.entry(video_id.clone()).or_default();
-        let mut state = download_state.0.lock().unwrap();
-        state.worker_status = worker_status;
-        state.fail_reason = worker_error.map(|e| e.to_string());
-        download_state.1.notify_all();
+        let mut final_state = download_state.lock().unwrap().clone();
+        final_state.worker_status = worker_status;
+        final_state.end_time_unix = get_unix_time();
+        if worker_status == WorkerStatus::Finished {
+            final_state.progress_percent = Some(100.0);
+        }
+        final_state.fail_code = worker_error.as_ref().map(|e| e.code());
+        final_state.fail_reason = worker_error.map(|e| e.to_string());
+        final_state.bump_revision();
+        let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+            entry.audio_path = audio_path.map(|p| p.to_str().unwrap().to_string());
+            entry.status = worker_status;
+            entry.file_size_bytes = file_size_bytes;
+            entry.source_hash = source_hash;
+            entry.final_state = serde_json::to_string(&final_state).ok();
+        }).unwrap();
+        let fail_reason = final_state.fail_reason.clone();
+        // NOTE: update cache so changes to database are visible to event listeners (transcode workers)
+        *download_state.lock().unwrap() = final_state;
+        job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
+        app_config.notifier.publish_job(&job_store, JobKind::Download, video_id.as_str(), &video_id, worker_status, fail_reason);
     });
     *is_queue_success.borrow_mut() = true;
     Ok(WorkerStatus::Queued)
 }
 
-fn enqueue_download_worker(
-    video_id: VideoId, download_cache: DownloadCache, app_config: Arc<AppConfig>, db_pool: DatabasePool,
-    system_log_writer: Arc<Mutex<impl Write>>,
+#[allow(clippy::too_many_arguments)]
+async fn enqueue_download_worker(
+    video_id: VideoId, download_cache: DownloadCache, job_events: JobEventBus, app_config: Arc<AppConfig>, job_store: SharedJobStore,
+    system_log_writer: Arc<Mutex<impl Write + Send>>, is_resume: bool, mut download_options: ytdlp::DownloadOptions,
 ) -> Result<PathBuf, DownloadError> {
     // logging files
     let stdout_log_path = app_config.download.join(format!("{}.stdout.log", video_id.as_str()));
     let stderr_log_path = app_config.download.join(format!("{}.stderr.log", video_id.as_str()));
     // spawn process
     let url = format!("https://www.youtube.com/watch?v={0}", video_id.as_str());
-    let process_res = Command::new(app_config.ytdlp_binary.clone())
-        .args(ytdlp::get_ytdlp_arguments(
-            url.as_str(), 
-            app_config.ffmpeg_binary.to_str().unwrap(),
-            app_config.download.join("%(id)s.%(ext)s").to_str().unwrap(),
-        ))
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
+    let downloader = app_config.get_downloader(url.as_str());
+    writeln!(&mut system_log_writer.lock().unwrap(), "[info] Downloading with {}: {}", downloader.name(), downloader.binary().to_string_lossy())
+        .map_err(WorkerError::SystemWriteFail)?;
+    let limit_rate = download_options.limit_rate.clone();
+    download_options.limit_rate = app_config.bandwidth_schedule.read().unwrap().apply(limit_rate.clone(), get_unix_time());
+    if download_options.limit_rate != limit_rate {
+        writeln!(&mut system_log_writer.lock().unwrap(), "[info] Download rate capped by the bandwidth schedule: {0}", download_options.limit_rate.as_deref().unwrap_or_default())
+            .map_err(WorkerError::SystemWriteFail)?;
+    }
+    let process_res = downloader.spawn(
+        &DownloadRequest {
+            url: url.as_str(),
+            ffmpeg_binary: app_config.ffmpeg_binary.as_path(),
+            // NOTE: yt-dlp writes into the staging directory so the download directory only has complete downloads
+            output_template: app_config.get_download_staging().join(app_config.output_template.as_str()).as_path(),
+            subtitle_output_template: app_config.download.join(ytdlp::DEFAULT_OUTPUT_TEMPLATE).as_path(),
+            is_resume,
+            proxy: app_config.proxy.as_deref(),
+            geo_bypass_country: app_config.geo_bypass_country.as_deref(),
+            options: &download_options,
+            auth: &app_config.ytdlp_auth,
+        },
+        &app_config.process_priority,
+    );
     let mut process = match process_res {
         Ok(process) => process,
         Err(err) => {
-            writeln!(&mut system_log_writer.lock().unwrap(), "[error] ytdlp failed to start: {err:?}")
+            writeln!(&mut system_log_writer.lock().unwrap(), "[error] {} failed to start: {err:?}", downloader.name())
                 .map_err(WorkerError::SystemWriteFail)?;
             return Err(DownloadError::LoggedFail);
         }
@@ -224,109 +464,135 @@ fn enqueue_download_worker(
     // update as running
     {
         let download_state = download_cache.get(&video_id).unwrap();
-        download_state.0.lock().unwrap().worker_status = WorkerStatus::Running;
-        download_state.1.notify_all();
-    }
-    {
-        let db_conn = db_pool.get()?;
-        let _ = select_and_update_ytdlp_entry(&db_conn, &video_id, |entry| entry.status = WorkerStatus::Running)?;
+        let mut state = download_state.lock().unwrap();
+        state.worker_status = WorkerStatus::Running;
+        state.bump_revision();
+        job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
     }
+    let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| entry.status = WorkerStatus::Running)?;
     // scrape stdout and stderr
-    let stdout_thread = thread::spawn({
-        let db_pool = db_pool.clone();
-        let video_id = video_id.clone();
-        let stdout_handle = process.stdout.take().ok_or(WorkerError::StdoutMissing)?;
-        let mut stdout_reader = BufReader::new(ConvertCarriageReturnToNewLine::new(stdout_handle));
-        let stdout_log_file = std::fs::File::create(stdout_log_path.clone()).map_err(WorkerError::StdoutLogCreate)?;
-        let mut stdout_log_writer = BufWriter::new(stdout_log_file);
-        {
-            let db_conn = db_pool.get()?;
-            let _ = select_and_update_ytdlp_entry(&db_conn, &video_id, |entry| {
-                entry.stdout_log_path = Some(stdout_log_path.to_str().unwrap().to_owned());
-            })?;
-        }
-        move || -> Result<Option<String>, DownloadError> {
-            let mut line = String::new();
-            let mut download_path = None;
-            loop {
-                match stdout_reader.read_line(&mut line) {
-                    Err(_) => break,
-                    Ok(0) => break,
-                    Ok(_) => (),
-                }
-                let _ = stdout_log_writer.write(line.as_bytes()).map_err(WorkerError::StdoutWriteFail)?;
-                match ytdlp::parse_stdout_line(line.as_str()) {
-                    None => (),
-                    Some(ytdlp::ParsedStdoutLine::DownloadProgress(progress)) => {
-                        log::debug!("[download] id={0} progress={progress:?}", video_id.as_str());
-                        let download_state = download_cache.entry(video_id.clone()).or_default();
-                        download_state.0.lock().unwrap().update_from_ytdlp(progress);
-                    },
-                    Some(ytdlp::ParsedStdoutLine::OutputPath(path)) => {
-                        download_path = Some(path);
-                    },
-                }
-                line.clear();
+    let stdout_handle = process.stdout.take().ok_or(WorkerError::StdoutMissing)?;
+    let mut stdout_reader = BufReader::new(stdout_handle);
+    let stdout_log_file = RotatingLogFile::create(&stdout_log_path, app_config.job_log_limits).map_err(WorkerError::StdoutLogCreate)?;
+    let mut stdout_log_writer = BufWriter::new(stdout_log_file);
+    let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+        entry.stdout_log_path = Some(stdout_log_path.to_str().unwrap().to_owned());
+    })?;
+    let stderr_handle = process.stderr.take().ok_or(WorkerError::StderrMissing)?;
+    let mut stderr_reader = BufReader::new(stderr_handle);
+    let stderr_log_file = RotatingLogFile::create(&stderr_log_path, app_config.job_log_limits).map_err(WorkerError::StderrLogCreate)?;
+    let mut stderr_log_writer = BufWriter::new(stderr_log_file);
+    let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+        entry.stderr_log_path = Some(stderr_log_path.to_str().unwrap().to_owned());
+    })?;
+    let stdout_task = async {
+        // NOTE: Persist progress periodically so interrupted downloads can be resumed
+        const DATABASE_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+        let mut last_database_update: Option<Instant> = None;
+        let mut line = String::new();
+        let mut download_path = None;
+        let mut partial_path: Option<PathBuf> = None;
+        loop {
+            match read_output_line(&mut stdout_reader, &mut line).await {
+                Err(_) => break,
+                Ok(0) => break,
+                Ok(_) => (),
             }
-            Ok(download_path)
-        }
-    });
-    let stderr_thread = thread::spawn({
-        let db_pool = db_pool.clone();
-        let video_id = video_id.clone();
-        let stderr_handle = process.stderr.take().ok_or(WorkerError::StderrMissing)?;
-        let mut stderr_reader = BufReader::new(ConvertCarriageReturnToNewLine::new(stderr_handle));
-        let stderr_log_file = std::fs::File::create(stderr_log_path.clone()).map_err(WorkerError::StderrLogCreate)?;
-        let mut stderr_log_writer = BufWriter::new(stderr_log_file);
-        {
-            let db_conn = db_pool.get()?;
-            let _ = select_and_update_ytdlp_entry(&db_conn, &video_id, |entry| {
-                entry.stderr_log_path = Some(stderr_log_path.to_str().unwrap().to_owned());
-            })?;
+            let _ = stdout_log_writer.write(line.as_bytes()).map_err(WorkerError::StdoutWriteFail)?;
+            match downloader.parse_stdout_line(line.as_str()) {
+                None => (),
+                Some(ParsedStdoutLine::DownloadProgress(progress)) => {
+                    log::debug!("[download] id={0} progress={progress:?}", video_id.as_str());
+                    if let Some(download_state) = download_cache.get(&video_id) {
+                        let mut state = download_state.lock().unwrap();
+                        state.update_from_progress(progress);
+                        // NOTE: Only publish the partial file once yt-dlp has started writing to it
+                        //       otherwise a reader could see stale data before it is truncated
+                        if state.partial_path.is_none() {
+                            state.partial_path = partial_path.take();
+                        }
+                    }
+                    job_events.publish(JobId::new(JobKind::Download, video_id.as_str()));
+                    let is_update_database = last_database_update
+                        .map(|t| t.elapsed() >= DATABASE_UPDATE_INTERVAL)
+                        .unwrap_or(true);
+                    if is_update_database && progress.downloaded_bytes.is_some() {
+                        let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+                            entry.downloaded_bytes = progress.downloaded_bytes;
+                        })?;
+                        last_database_update = Some(Instant::now());
+                    }
+                },
+                Some(ParsedStdoutLine::OutputPath(path)) => {
+                    download_path = Some(path);
+                },
+                Some(ParsedStdoutLine::DownloadPath(path)) => {
+                    partial_path = Some(app_config.root.join(format!("{path}.part")));
+                },
+                Some(ParsedStdoutLine::DurationMilliseconds(duration)) => {
+                    let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+                        entry.duration_ms = Some(duration);
+                    })?;
+                },
+                Some(ParsedStdoutLine::FormatId(format_id)) => {
+                    let _ = job_store.select_and_update_ytdlp_entry(&video_id, |entry| {
+                        entry.format_id = Some(format_id);
+                    })?;
+                },
+            }
+            line.clear();
         }
-        move || {
-            let mut line = String::new();
-            let mut extract_path = None;
-            loop {
-                match stderr_reader.read_line(&mut line) {
-                    Err(_) => break,
-                    Ok(0) => break,
-                    Ok(_) => (),
-                }
-                let _ = stderr_log_writer.write(line.as_bytes()).map_err(WorkerError::StderrWriteFail)?;
-                match ytdlp::parse_stderr_line(line.as_str()) {
-                    None => (),
-                    Some(ytdlp::ParsedStderrLine::MissingVideo(_)) => return Err(DownloadError::InvalidVideoId),
-                    Some(ytdlp::ParsedStderrLine::UsageError(message)) => return Err(DownloadError::UsageError(message)),
-                    Some(ytdlp::ParsedStderrLine::ExtractPath(path)) => {
-                        extract_path = Some(path);
-                    },
-                }
-                line.clear();
+        Ok::<_, DownloadError>(download_path)
+    };
+    let stderr_task = async {
+        let mut line = String::new();
+        let mut extract_path = None;
+        let mut failure = None;
+        loop {
+            match read_output_line(&mut stderr_reader, &mut line).await {
+                Err(_) => break,
+                Ok(0) => break,
+                Ok(_) => (),
+            }
+            let _ = stderr_log_writer.write(line.as_bytes()).map_err(WorkerError::StderrWriteFail)?;
+            match downloader.parse_stderr_line(line.as_str()) {
+                None => (),
+                Some(ParsedStderrLine::MissingVideo(_)) => return Err(DownloadError::InvalidVideoId),
+                Some(ParsedStderrLine::UsageError(message)) => return Err(DownloadError::UsageError(message)),
+                Some(ParsedStderrLine::ExtractPath(path)) => {
+                    extract_path = Some(path);
+                },
+                // NOTE: Keep the first error since later ones are usually caused by it
+                Some(ParsedStderrLine::Failure(code)) => {
+                    failure = failure.or(Some(code));
+                },
             }
-            Ok(extract_path)
+            line.clear();
         }
-    });
-    // shutdown threads
-    let download_path = stdout_thread.join().map_err(WorkerError::StdoutThreadJoin)??;
-    let extract_path = stderr_thread.join().map_err(WorkerError::StderrThreadJoin)??;
+        Ok((extract_path, failure))
+    };
+    let (stdout_res, stderr_res) = tokio::join!(stdout_task, stderr_task);
+    let download_path = stdout_res?;
+    let (extract_path, failure) = stderr_res?;
     // shutdown process
-    match process.try_wait() {
-        Ok(None) => {},
-        Ok(Some(exit_status)) => match exit_status.code() {
+    match process.wait().await {
+        Ok(exit_code) => match exit_code {
             None => {},
             Some(0) => {},
             Some(code) => {
-                writeln!(&mut system_log_writer.lock().unwrap(), "[error] ytdlp failed with bad code: {code:?}")
+                writeln!(&mut system_log_writer.lock().unwrap(), "[error] {} failed with bad code: {code:?}", downloader.name())
                     .map_err(WorkerError::SystemWriteFail)?;
-                return Err(DownloadError::LoggedFail);
+                if let Some(hint) = failure.and_then(|code| app_config.ytdlp_auth.get_failure_hint(code)) {
+                    writeln!(&mut system_log_writer.lock().unwrap(), "[info] {hint}").map_err(WorkerError::SystemWriteFail)?;
+                }
+                return Err(failure.map(DownloadError::ProcessFailed).unwrap_or(DownloadError::LoggedFail));
             },
         },
         Err(err) => {
-            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] ytdlp process failed to join: {err:?}")
+            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] {} process failed to join: {err:?}", downloader.name())
                 .map_err(WorkerError::SystemWriteFail)?;
-            if let Err(err) = process.kill() {
-                writeln!(&mut system_log_writer.lock().unwrap(), "[warn] ytdlp process failed to be killed: {err:?}")
+            if let Err(err) = process.start_kill() {
+                writeln!(&mut system_log_writer.lock().unwrap(), "[warn] {} process failed to be killed: {err:?}", downloader.name())
                     .map_err(WorkerError::SystemWriteFail)?;
             }
         },
@@ -337,9 +603,13 @@ fn enqueue_download_worker(
         return Err(DownloadError::MissingOutputPath)
     };
     let audio_path = app_config.root.join(audio_path);
-    if audio_path.exists() {
-        Ok(audio_path)
-    } else {
-        Err(DownloadError::MissingOutputFile(audio_path))
+    if !audio_path.exists() {
+        return Err(DownloadError::MissingOutputFile(audio_path));
     }
+    let Ok(relative_path) = audio_path.strip_prefix(app_config.get_download_staging()) else {
+        return Ok(audio_path);
+    };
+    let download_path = app_config.download.join(relative_path);
+    block_in_place(|| move_staged_file(&audio_path, &download_path)).map_err(DownloadError::StageOutputFile)?;
+    Ok(download_path)
 }
@@ -0,0 +1,283 @@
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tokio::io::BufReader;
+use crate::app::{AppConfig, WorkerError, WorkerThreadPool, WorkerCacheEntry};
+use crate::database::{VideoId, WorkerStatus, JobKind};
+use crate::job_events::JobEventBus;
+use crate::job_store::SharedJobStore;
+use crate::log_file::RotatingLogFile;
+use crate::request_id::current_request_id;
+use crate::util::read_output_line;
+use crate::worker_queue::{JobId, record_job_duration, record_job_history};
+use crate::worker_download::DownloadCache;
+use crate::worker_transcode::{TranscodeState, TranscodeError, wait_for_download, pipe_partial_download};
+use crate::ffmpeg;
+use crate::thread_budget::ThreadBudget;
+
+pub const PLAYLIST_FILENAME: &str = "playlist.m3u8";
+const SEGMENT_FILENAME_FORMAT: &str = "segment_%05d.ts";
+const SEGMENT_DURATION_SECONDS: u32 = 6;
+const AUDIO_BITRATE_KBPS: u32 = 128;
+// NOTE: ffmpeg only writes this tag once the final segment is written
+const PLAYLIST_END_TAG: &str = "#EXT-X-ENDLIST";
+
+// NOTE: We reuse the transcode state since the hls output is produced by the same ffmpeg progress
+pub type HlsCache = Arc<DashMap<VideoId, WorkerCacheEntry<TranscodeState>>>;
+
+/// Each video has its own directory containing the playlist, segments and logs
+pub fn get_hls_directory(app_config: &AppConfig, video_id: &VideoId) -> PathBuf {
+    app_config.hls.join(video_id.as_str())
+}
+
+/// Only the playlist and segments are served, logs are kept private
+pub fn is_hls_filename(filename: &str) -> bool {
+    lazy_static! {
+        static ref SEGMENT_REGEX: Regex = Regex::new(r"^segment_\d{5,}\.ts$").unwrap();
+    }
+    filename == PLAYLIST_FILENAME || SEGMENT_REGEX.is_match(filename)
+}
+
+pub fn get_hls_mime_type(filename: &str) -> &'static str {
+    if filename.ends_with(".m3u8") {
+        "application/vnd.apple.mpegurl"
+    } else {
+        "video/mp2t"
+    }
+}
+
+pub fn is_playlist_finished(playlist_path: &Path) -> bool {
+    std::fs::read_to_string(playlist_path)
+        .map(|playlist| playlist.lines().any(|line| line.trim() == PLAYLIST_END_TAG))
+        .unwrap_or(false)
+}
+
+pub fn try_start_hls_worker(
+    video_id: VideoId,
+    download_cache: DownloadCache, hls_cache: HlsCache, job_events: JobEventBus, app_config: Arc<AppConfig>,
+    job_store: SharedJobStore, worker_thread_pool: WorkerThreadPool,
+) -> WorkerStatus {
+    // check if hls in progress (cache hit)
+    {
+        let hls_state = hls_cache.entry(video_id.clone()).or_default();
+        let mut state = hls_state.lock().unwrap();
+        match state.worker_status {
+            WorkerStatus::None | WorkerStatus::Failed => {},
+            WorkerStatus::Queued | WorkerStatus::Running | WorkerStatus::Finished => return state.worker_status,
+        }
+        // check if hls finished on disk (cache miss due to reset)
+        let playlist_path = get_hls_directory(&app_config, &video_id).join(PLAYLIST_FILENAME);
+        if is_playlist_finished(&playlist_path) {
+            state.worker_status = WorkerStatus::Finished;
+            state.file_cached = true;
+            job_events.publish(JobId::new(JobKind::Hls, video_id.as_str()));
+            return WorkerStatus::Finished;
+        }
+        *state = TranscodeState {
+            worker_status: WorkerStatus::Queued,
+            ..Default::default()
+        };
+        job_events.publish(JobId::new(JobKind::Hls, video_id.as_str()));
+    }
+    worker_thread_pool.execute(JobId::new(JobKind::Hls, video_id.as_str()), async move {
+        let start_time = Instant::now();
+        log::info!("Launching hls process: {0}", video_id.as_str());
+        let res = async {
+            // NOTE: Remove segments left over from a failed run so they aren't mixed with the new ones
+            let hls_directory = get_hls_directory(&app_config, &video_id);
+            if hls_directory.exists() {
+                std::fs::remove_dir_all(&hls_directory).map_err(TranscodeError::CreateOutputDirectory)?;
+            }
+            std::fs::create_dir_all(&hls_directory).map_err(TranscodeError::CreateOutputDirectory)?;
+            // setup logging
+            let system_log_path = hls_directory.join("system.log");
+            let system_log_file = std::fs::File::create(system_log_path).map_err(WorkerError::SystemLogCreate)?;
+            let system_log_writer = Arc::new(Mutex::new(BufWriter::new(system_log_file)));
+            if let Some(request_id) = current_request_id() {
+                let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[info] Started by request: {0}", request_id.as_str());
+            }
+            // launch process
+            let res = enqueue_hls_worker(
+                video_id.clone(), hls_directory, download_cache, hls_cache.clone(), job_events.clone(),
+                app_config.clone(), job_store.clone(), system_log_writer.clone(),
+            ).await;
+            if let Err(ref err) = res {
+                let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[error] Worker failed with: {err:?}");
+            }
+            res
+        }.await;
+        if let Err(ref err) = res {
+            log::error!("Hls worker failed: id={0}, err={1:?}", video_id.as_str(), err);
+        }
+        let (worker_status, worker_error) = match res {
+            Ok(_) => (WorkerStatus::Finished, None),
+            Err(err) => (WorkerStatus::Failed, Some(err)),
+        };
+        if worker_status == WorkerStatus::Finished {
+            record_job_duration(&job_store, JobKind::Hls, start_time);
+        }
+        // NOTE: Segments are split across many files so their size isn't tracked
+        record_job_history(
+            &job_store, &JobId::new(JobKind::Hls, video_id.as_str()), worker_status,
+            worker_error.as_ref().map(|err| err.code().as_str()), None, start_time,
+        );
+        let fail_reason = worker_error.as_ref().map(|e| e.to_string());
+        {
+            let hls_state = hls_cache.entry(video_id.clone()).or_default();
+            let mut state = hls_state.lock().unwrap();
+            state.worker_status = worker_status;
+            state.fail_code = worker_error.as_ref().map(|e| e.code());
+            state.fail_reason = fail_reason.clone();
+        }
+        job_events.publish(JobId::new(JobKind::Hls, video_id.as_str()));
+        app_config.notifier.publish_job(&job_store, JobKind::Hls, video_id.as_str(), &video_id, worker_status, fail_reason);
+    });
+    WorkerStatus::Queued
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn enqueue_hls_worker(
+    video_id: VideoId, hls_directory: PathBuf, download_cache: DownloadCache, hls_cache: HlsCache, job_events: JobEventBus,
+    app_config: Arc<AppConfig>, job_store: SharedJobStore, system_log_writer: Arc<Mutex<impl Write + Send>>,
+) -> Result<PathBuf, TranscodeError> {
+    let playlist_path = hls_directory.join(PLAYLIST_FILENAME);
+    // wait for download worker
+    let download_state = download_cache.entry(video_id.clone()).or_default().clone();
+    let partial_path = wait_for_download(&video_id, &download_state, &job_events, app_config.pipeline_transcode).await?;
+    // get source file to segment
+    let source_path = match partial_path {
+        Some(ref partial_path) => {
+            writeln!(
+                &mut system_log_writer.lock().unwrap(),
+                "[info] Segmenting from partial download: {0}", partial_path.to_string_lossy(),
+            ).map_err(WorkerError::SystemWriteFail)?;
+            None
+        },
+        None => {
+            let entry = job_store.select_ytdlp_entry(&video_id)?.expect("Entry should exist");
+            let Some(source_path) = entry.audio_path else {
+                return Err(TranscodeError::DownloadPathMissing);
+            };
+            let source_path = PathBuf::from(source_path);
+            if !source_path.exists() {
+                return Err(TranscodeError::DownloadFileMissing(source_path));
+            }
+            Some(source_path)
+        },
+    };
+    let stderr_log_path = hls_directory.join("stderr.log");
+    let thread_budget = ThreadBudget::acquire(&app_config);
+    writeln!(&mut system_log_writer.lock().unwrap(), "[info] Segmenting with {0} threads", thread_budget.threads)
+        .map_err(WorkerError::SystemWriteFail)?;
+    // spawn process
+    let process_args = {
+        let mut args = Vec::<String>::new();
+        let push_args = |args: &mut Vec<String>, values: &[&str]| {
+            args.extend(values.iter().map(|&s| s.to_owned()));
+        };
+        match source_path {
+            Some(ref source_path) => push_args(&mut args, &["-i", source_path.to_str().unwrap()]),
+            None => push_args(&mut args, &["-i", "pipe:0"]),
+        }
+        push_args(&mut args, &["-map", "0:a", "-c:a", "aac"]);
+        push_args(&mut args, &["-b:a", format!("{AUDIO_BITRATE_KBPS}k").as_str()]);
+        // NOTE: An event playlist lists segments as they are written so playback can start before ffmpeg finishes
+        push_args(&mut args, &[
+            "-f", "hls",
+            "-hls_time", SEGMENT_DURATION_SECONDS.to_string().as_str(),
+            "-hls_playlist_type", "event",
+            "-hls_segment_filename", hls_directory.join(SEGMENT_FILENAME_FORMAT).to_str().unwrap(),
+            "-threads", thread_budget.threads.to_string().as_str(),
+            "-y", playlist_path.to_str().unwrap(),
+        ]);
+        args
+    };
+    let transcoder = app_config.get_transcoder();
+    let process_res = transcoder.spawn(process_args.as_slice(), partial_path.is_some(), &app_config.process_priority);
+    let mut process = match process_res {
+        Ok(process) => process,
+        Err(err) => {
+            writeln!(&mut system_log_writer.lock().unwrap(), "[error] {} failed to start: {err:?}", transcoder.name())
+                .map_err(WorkerError::SystemWriteFail)?;
+            return Err(TranscodeError::LoggedFail);
+        }
+    };
+    // update as running
+    {
+        let hls_state = hls_cache.get(&video_id).unwrap();
+        hls_state.lock().unwrap().worker_status = WorkerStatus::Running;
+        job_events.publish(JobId::new(JobKind::Hls, video_id.as_str()));
+    }
+    // feed partial download
+    let stdin_handle = match partial_path {
+        None => None,
+        Some(partial_path) => Some((partial_path, process.stdin.take().ok_or(WorkerError::StdinMissing)?)),
+    };
+    let stdin_task = async {
+        match stdin_handle {
+            None => Ok(()),
+            Some((partial_path, stdin_handle)) => pipe_partial_download(partial_path.as_path(), &download_state, stdin_handle).await,
+        }
+    };
+    // scrape stderr
+    let stderr_handle = process.stderr.take().ok_or(WorkerError::StderrMissing)?;
+    let mut stderr_reader = BufReader::new(stderr_handle);
+    let stderr_log_file = RotatingLogFile::create(&stderr_log_path, app_config.job_log_limits).map_err(WorkerError::StderrLogCreate)?;
+    let mut stderr_log_writer = BufWriter::new(stderr_log_file);
+    let stderr_task = async {
+        let mut line = String::new();
+        let mut failure = None;
+        loop {
+            match read_output_line(&mut stderr_reader, &mut line).await {
+                Err(_) => break,
+                Ok(0) => break,
+                Ok(_) => (),
+            }
+            let _ = stderr_log_writer.write(line.as_bytes()).map_err(WorkerError::StderrWriteFail)?;
+            match ffmpeg::parse_stderr_line(line.as_str()) {
+                None => (),
+                Some(ffmpeg::ParsedStderrLine::TranscodeSourceInfo(info)) => {
+                    log::debug!("[hls] id={0} info={info:?}", video_id.as_str());
+                    let hls_state = hls_cache.entry(video_id.clone()).or_default();
+                    hls_state.lock().unwrap().update_from_source_info(info);
+                },
+                Some(ffmpeg::ParsedStderrLine::TranscodeProgress(progress)) => {
+                    log::debug!("[hls] id={0} progress={progress:?}", video_id.as_str());
+                    let hls_state = hls_cache.entry(video_id.clone()).or_default();
+                    hls_state.lock().unwrap().update_from_progress(progress);
+                },
+                Some(ffmpeg::ParsedStderrLine::Failure(err)) => {
+                    failure = failure.or(Some(err));
+                },
+            }
+            line.clear();
+        }
+        Ok::<_, WorkerError>(failure)
+    };
+    let (stderr_res, stdin_res) = tokio::join!(stderr_task, stdin_task);
+    let failure = stderr_res?;
+    stdin_res?;
+    // shutdown process
+    match process.wait().await {
+        Ok(exit_code) => match exit_code {
+            None | Some(0) => {},
+            Some(code) => {
+                writeln!(&mut system_log_writer.lock().unwrap(), "[error] {} failed with bad code: {code:?}", transcoder.name())
+                    .map_err(WorkerError::SystemWriteFail)?;
+                return Err(failure.map(TranscodeError::Ffmpeg).unwrap_or(TranscodeError::LoggedFail));
+            },
+        },
+        Err(err) => {
+            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] {} process failed to join: {err:?}", transcoder.name())
+                .map_err(WorkerError::SystemWriteFail)?;
+        },
+    }
+    if !is_playlist_finished(&playlist_path) {
+        return Err(TranscodeError::MissingOutputFile(playlist_path));
+    }
+    Ok(playlist_path)
+}
@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, watch, Semaphore};
+use tracing::Instrument;
+use crate::database::{JobKind, WorkerStatus};
+use crate::job_store::{SharedJobStore, JobStoreError};
+use crate::request_id::{current_request_id, with_request_id};
+use crate::util::defer;
+
+// NOTE: Only recent jobs are averaged so the estimate follows changes in load and network speed
+const DURATION_HISTORY_LENGTH: usize = 50;
+
+/// Identifies a job in the queue so its position can be looked up
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct JobId {
+    pub kind: JobKind,
+    pub key: String,
+}
+
+impl JobId {
+    pub fn new(kind: JobKind, key: impl Into<String>) -> Self {
+        Self { kind, key: key.into() }
+    }
+}
+
+#[derive(Clone,Debug,Serialize)]
+pub struct QueuePosition {
+    /// Number of queued jobs that will start before this one
+    pub position: usize,
+    pub estimated_wait_ms: Option<u64>,
+}
+
+#[derive(Debug,Default)]
+struct QueueState {
+    pending: VecDeque<JobId>,
+    running: Vec<JobId>,
+}
+
+type QueuedJob = (JobId, Pin<Box<dyn Future<Output=()> + Send>>);
+
+/// Runs jobs as async tasks while keeping track of which jobs are waiting for a free slot
+// NOTE: Jobs wait on the child processes they spawn without holding a thread so the runtime only needs a few threads
+pub struct WorkerQueue {
+    runtime: Option<Runtime>,
+    sender: mpsc::UnboundedSender<QueuedJob>,
+    state: Arc<Mutex<QueueState>>,
+    total_threads: usize,
+    is_paused: watch::Sender<bool>,
+}
+
+impl WorkerQueue {
+    pub fn new(total_threads: usize) -> Result<Self, std::io::Error> {
+        let total_runtime_threads = std::thread::available_parallelism().map(|v| v.get()).unwrap_or(1).min(total_threads.max(1));
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(total_runtime_threads)
+            .thread_name("worker")
+            .enable_all()
+            .build()?;
+        let state = Arc::new(Mutex::new(QueueState::default()));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (is_paused, is_paused_receiver) = watch::channel(false);
+        runtime.spawn(dispatch_jobs(receiver, Arc::new(Semaphore::new(total_threads)), state.clone(), is_paused_receiver));
+        Ok(Self {
+            runtime: Some(runtime),
+            sender,
+            state,
+            total_threads,
+            is_paused,
+        })
+    }
+
+    pub fn execute<F>(&self, job: JobId, f: F)
+    where F: Future<Output=()> + Send + 'static
+    {
+        self.state.lock().unwrap().pending.push_back(job.clone());
+        // NOTE: The job keeps the id and span of the request that queued it so its logs can be traced back to that request
+        let span = tracing::info_span!("job", kind = job.kind.as_str(), key = job.key.as_str());
+        let f = with_request_id(current_request_id(), f.instrument(span));
+        if self.sender.send((job.clone(), Box::pin(f))).is_err() {
+            log::error!("Worker queue was shut down before job could be queued: kind={0}, key={1}", job.kind.as_str(), job.key);
+        }
+    }
+
+    pub fn total_pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    pub fn total_running(&self) -> usize {
+        self.state.lock().unwrap().running.len()
+    }
+
+    pub fn total_threads(&self) -> usize {
+        self.total_threads
+    }
+
+    /// Stops queued jobs from starting while running jobs carry on until they finish
+    pub fn pause(&self) {
+        self.is_paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.is_paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.is_paused.borrow()
+    }
+
+    /// The wait is estimated from the average duration of each kind of job that will run before it
+    fn estimate_wait_ms(&self, state: &QueueState, position: usize, average_durations: &HashMap<JobKind, u64>) -> Option<u64> {
+        // NOTE: Running jobs are assumed to be halfway done on average
+        let pending_ms = state.pending.iter().take(position).map(|job| average_durations.get(&job.kind).copied());
+        let running_ms = state.running.iter().map(|job| average_durations.get(&job.kind).map(|ms| ms/2));
+        let total_ms: Option<u64> = pending_ms.chain(running_ms).sum();
+        total_ms.map(|total_ms| total_ms / self.total_threads.max(1) as u64)
+    }
+
+    /// Returns None if the job isn't waiting for a thread
+    pub fn get_position(&self, job: &JobId, average_durations: &HashMap<JobKind, u64>) -> Option<QueuePosition> {
+        let state = self.state.lock().unwrap();
+        let position = state.pending.iter().position(|pending| pending == job)?;
+        let estimated_wait_ms = self.estimate_wait_ms(&state, position, average_durations);
+        Some(QueuePosition { position, estimated_wait_ms })
+    }
+
+    /// Time until the next queued job is given a thread
+    pub fn estimate_next_start_ms(&self, average_durations: &HashMap<JobKind, u64>) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        self.estimate_wait_ms(&state, 0, average_durations)
+    }
+}
+
+// NOTE: Permits are taken in the order jobs were queued so the pending list matches the order they start in
+async fn dispatch_jobs(
+    mut receiver: mpsc::UnboundedReceiver<QueuedJob>, permits: Arc<Semaphore>, state: Arc<Mutex<QueueState>>,
+    mut is_paused: watch::Receiver<bool>,
+) {
+    while let Some((job, f)) = receiver.recv().await {
+        let Ok(permit) = permits.clone().acquire_owned().await else { break; };
+        // NOTE: Checked after a permit is free so a job that was waiting on one doesn't start once the queue is paused
+        if is_paused.wait_for(|is_paused| !is_paused).await.is_err() {
+            break;
+        }
+        {
+            let mut state = state.lock().unwrap();
+            if let Some(index) = state.pending.iter().position(|pending| *pending == job) {
+                state.pending.remove(index);
+            }
+            state.running.push(job.clone());
+        }
+        let state = state.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            // NOTE: Remove the job even if it panics so it doesn't count towards the wait of later jobs
+            let _remove_running = defer(move || {
+                let mut state = state.lock().unwrap();
+                if let Some(index) = state.running.iter().position(|running| *running == job) {
+                    state.running.swap_remove(index);
+                }
+            });
+            f.await;
+        });
+    }
+}
+
+impl Drop for WorkerQueue {
+    // NOTE: Blocking until jobs finish isn't allowed inside the server's runtime so running jobs are abandoned
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Stores how long a successful job occupied its thread for future wait estimates
+pub fn record_job_duration(job_store: &SharedJobStore, kind: JobKind, start_time: Instant) {
+    let elapsed_ms = start_time.elapsed().as_millis() as u64;
+    if let Err(err) = job_store.insert_job_duration_entry(kind, elapsed_ms) {
+        log::warn!("Failed to store job duration: kind={0}, err={1:?}", kind.as_str(), err);
+    }
+}
+
+/// Stores the outcome of a job so failures and throughput can be summarised for admins
+pub fn record_job_history(
+    job_store: &SharedJobStore, job: &JobId, status: WorkerStatus,
+    error_category: Option<&str>, file_size_bytes: Option<u64>, start_time: Instant,
+) {
+    let elapsed_ms = start_time.elapsed().as_millis() as u64;
+    let request_id = current_request_id();
+    let request_id = request_id.as_ref().map(|id| id.as_str());
+    if let Err(err) = job_store.insert_job_history_entry(job.kind, job.key.as_str(), status, error_category, file_size_bytes, elapsed_ms, request_id) {
+        log::warn!("Failed to store job history: kind={0}, key={1}, err={2:?}", job.kind.as_str(), job.key, err);
+    }
+}
+
+/// Kinds of jobs without any history are left out
+pub fn get_average_job_durations(job_store: &SharedJobStore) -> Result<HashMap<JobKind, u64>, JobStoreError> {
+    let mut durations = HashMap::new();
+    for kind in [JobKind::Download, JobKind::Transcode, JobKind::Hls] {
+        if let Some(duration) = job_store.select_average_job_duration(kind, DURATION_HISTORY_LENGTH)? {
+            durations.insert(kind, duration);
+        }
+    }
+    Ok(durations)
+}
@@ -1,41 +1,63 @@
 use std::cell::RefCell;
-use std::io::{BufReader, BufWriter, BufRead, Write};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use dashmap::DashMap;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::task::block_in_place;
 use crate::app::{AppConfig, WorkerError, WorkerThreadPool, WorkerCacheEntry};
-use crate::database::{
-    DatabasePool, VideoId, AudioExtension, WorkerStatus,
-    select_and_update_ffmpeg_entry, select_ffmpeg_entry, insert_ffmpeg_entry,
-    select_ytdlp_entry,
-};
-use crate::util::{get_unix_time, defer, ConvertCarriageReturnToNewLine};
+use crate::disk_space::{check_free_space, DiskSpaceError};
+use crate::enrichment;
+use crate::database::{VideoId, AudioExtension, WorkerStatus, FfmpegRow, JobKind};
+use crate::error_code::ErrorCode;
+use crate::job_events::JobEventBus;
+use crate::job_store::{SharedJobStore, JobStoreError};
+use crate::log_file::RotatingLogFile;
+use crate::request_id::current_request_id;
+use crate::worker_queue::{JobId, record_job_duration, record_job_history};
+use crate::util::{get_unix_time, get_file_sha256, get_title_filename, next_state_revision, defer, read_output_line, move_staged_file};
 use crate::metadata::{Metadata, Thumbnail};
-use crate::worker_download::DownloadCache;
-use crate::ffmpeg;
+use crate::worker_download::{DownloadCache, DownloadState};
+use crate::ffmpeg::{self, TranscodeOptions};
+use crate::ffprobe::ProbeValidationError;
+use crate::ffmetadata::{self, FfMetadata};
+use crate::storage::StorageError;
+use crate::thread_budget::ThreadBudget;
 
 #[derive(Clone,Debug,PartialEq,Eq,Hash)]
 pub struct TranscodeKey {
     pub video_id: VideoId,
     pub audio_ext: AudioExtension,
+    /// Identifies the transcode options used, see TranscodeOptions::get_variant()
+    pub variant: String,
 }
 
 impl TranscodeKey {
+    pub fn new(video_id: VideoId, audio_ext: AudioExtension, options: &TranscodeOptions) -> Self {
+        Self { video_id, audio_ext, variant: options.get_variant() }
+    }
+
+    /// Also used as the filename of the transcoded file
     pub fn as_str(&self) -> String {
-        format!("{}.{}", self.video_id.as_str(), self.audio_ext.as_str())
+        if self.variant.is_empty() {
+            format!("{}.{}", self.video_id.as_str(), self.audio_ext.as_str())
+        } else {
+            format!("{}.{}.{}", self.video_id.as_str(), self.variant, self.audio_ext.as_str())
+        }
     }
 }
 
-#[derive(Debug,Clone,Serialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub struct TranscodeState {
     pub worker_status: WorkerStatus,
     pub file_cached: bool,
     pub fail_reason: Option<String>,
+    pub fail_code: Option<ErrorCode>,
     pub start_time_unix: u64,
     pub end_time_unix: u64,
     pub source_duration_milliseconds: Option<u64>,
@@ -45,6 +67,19 @@ pub struct TranscodeState {
     pub transcode_size_bytes: Option<usize>,
     pub transcode_speed_bits: Option<usize>,
     pub transcode_speed_factor: Option<f32>,
+    pub eta_milliseconds: Option<u64>,
+    /// Duration of the output after trimming and changing the tempo of the source
+    pub expected_duration_milliseconds: Option<u64>,
+    /// Percentage of the output transcoded, missing if the duration of the source is unknown
+    pub progress_percent: Option<f32>,
+    /// Changes whenever the state does so pollers can skip unchanged states, see next_state_revision()
+    pub revision: u64,
+    /// Duration of leading and trailing silence removed when the trim_silence option is used
+    pub silence_removed_milliseconds: Option<u64>,
+    /// Job that was created when this transcode was requested, shared by concurrent requests
+    pub job_id: Option<String>,
+    /// Thumbnail couldn't be fetched so the transcode was retried without album art
+    pub is_thumbnail_skipped: bool,
 }
 
 impl Default for TranscodeState {
@@ -54,6 +89,7 @@ impl Default for TranscodeState {
             worker_status: WorkerStatus::None,
             file_cached: false,
             fail_reason: None,
+            fail_code: None,
             start_time_unix: curr_time,
             end_time_unix: curr_time,
             source_duration_milliseconds: None,
@@ -63,6 +99,13 @@ impl Default for TranscodeState {
             transcode_size_bytes: None,
             transcode_speed_bits: None,
             transcode_speed_factor: None,
+            eta_milliseconds: None,
+            expected_duration_milliseconds: None,
+            progress_percent: None,
+            revision: next_state_revision(),
+            silence_removed_milliseconds: None,
+            job_id: None,
+            is_thumbnail_skipped: false,
         }
     }
 }
@@ -74,7 +117,27 @@ fn update_field<T>(dst: &mut Option<T>, src: Option<T>) {
 }
 
 impl TranscodeState {
+    pub fn bump_revision(&mut self) {
+        self.revision = next_state_revision();
+    }
+
+    /// State stored when the transcode ended, which is missing while it's being retried
+    // NOTE: Entries from before final states were stored only know their status
+    pub fn from_entry(entry: &FfmpegRow) -> Option<Self> {
+        if !matches!(entry.status, WorkerStatus::Finished | WorkerStatus::Failed) {
+            return None;
+        }
+        let state = entry.final_state.as_deref().and_then(|state| serde_json::from_str(state).ok());
+        Some(state.unwrap_or_else(|| Self {
+            worker_status: entry.status,
+            file_cached: entry.status == WorkerStatus::Finished,
+            start_time_unix: entry.unix_time,
+            ..Default::default()
+        }))
+    }
+
     pub fn update_from_progress(&mut self, progress: ffmpeg::TranscodeProgress) {
+        self.bump_revision();
         self.end_time_unix = get_unix_time();
         // NOTE: On linux the frame number is sometimes 1 for the audio stream so this check doesn't make sense
         //       Instead we only update the progress if the transcode duration is greater than the old duration
@@ -93,9 +156,12 @@ impl TranscodeState {
         update_field(&mut self.transcode_duration_milliseconds , progress.total_time_transcoded.map(|t| t.to_milliseconds()));
         update_field(&mut self.transcode_speed_bits, progress.speed_bits);
         update_field(&mut self.transcode_speed_factor, progress.speed_factor);
+        self.update_progress_percent();
+        self.update_eta();
     }
 
     pub fn update_from_source_info(&mut self, info: ffmpeg::TranscodeSourceInfo) {
+        self.bump_revision();
         self.end_time_unix = get_unix_time();
         // NOTE: we specify multiple sources including thumbnail which gives dodgy info
         //       we check for this by only updating from the longest duration source info
@@ -109,6 +175,36 @@ impl TranscodeState {
         update_field(&mut self.source_duration_milliseconds, info.duration.map(|t| t.to_milliseconds()));
         update_field(&mut self.source_start_time_milliseconds, info.start_time.map(|t| t.to_milliseconds()));
         update_field(&mut self.source_speed_bits, info.speed_bits);
+        self.update_progress_percent();
+        self.update_eta();
+    }
+
+    /// ffmpeg reports the time of the output so we compare it against the expected output duration if it is known
+    fn get_total_duration_milliseconds(&self) -> Option<u64> {
+        self.expected_duration_milliseconds.or(self.source_duration_milliseconds)
+    }
+
+    fn update_progress_percent(&mut self) {
+        let (Some(total_duration), Some(transcode_duration)) = (self.get_total_duration_milliseconds(), self.transcode_duration_milliseconds) else {
+            return;
+        };
+        // NOTE: Removing silence makes the output shorter than expected so it only reaches 100% once it finishes
+        self.progress_percent = (total_duration > 0)
+            .then(|| (transcode_duration as f32 / total_duration as f32 * 100.0).min(100.0));
+    }
+
+    fn update_eta(&mut self) {
+        let (Some(total_duration), Some(transcode_duration), Some(speed_factor)) = (
+            self.get_total_duration_milliseconds(), self.transcode_duration_milliseconds, self.transcode_speed_factor,
+        ) else {
+            return;
+        };
+        // NOTE: ffmpeg reports a speed factor of 0 at the very start of the transcode
+        if speed_factor <= 0.0 {
+            return;
+        }
+        let remaining_duration = total_duration.saturating_sub(transcode_duration);
+        self.eta_milliseconds = Some((remaining_duration as f32 / speed_factor) as u64);
     }
 }
 
@@ -116,10 +212,10 @@ pub type TranscodeCache = Arc<DashMap<TranscodeKey, WorkerCacheEntry<TranscodeSt
 
 #[derive(Debug,Error)]
 pub enum TranscodeStartError {
-    #[error("Database connection failed: {0:?}")]
-    DatabaseConnection(#[from] r2d2::Error),
-    #[error("Database execute failed: {0:?}")]
-    DatabaseExecute(#[from] rusqlite::Error),
+    #[error("Database failed: {0}")]
+    Database(#[from] JobStoreError),
+    #[error("Disk full: {0}")]
+    DiskFull(#[from] DiskSpaceError),
 }
 
 #[derive(Debug,Error)]
@@ -130,41 +226,274 @@ pub enum TranscodeError {
     UsageError(String),
     #[error("Missing output transcode file: {0}")]
     MissingOutputFile(PathBuf),
-    #[error("Download worker failed")]
-    DownloadWorkerFailed,
+    #[error("Download worker failed with: {0}")]
+    DownloadWorkerFailed(ErrorCode),
     #[error("Download worker failed to provide path to downloaded file")]
     DownloadPathMissing,
     #[error("Missing output download file from worker: {0}")]
     DownloadFileMissing(PathBuf),
+    #[error("Failed to create output directory: {0:?}")]
+    CreateOutputDirectory(std::io::Error),
+    #[error("Failed to write chapters and lyrics for ffmpeg: {0:?}")]
+    WriteFfMetadata(std::io::Error),
+    #[error("Failed to pipe partially downloaded file to ffmpeg: {0:?}")]
+    PipePartialDownload(std::io::Error),
     #[error("Copying identically formatted download to transcode failed: {0}")]
     CopyDownloadSameFormat(std::io::Error),
     #[error("Error stored in system log")]
     LoggedFail,
-    #[error("Database connection failed: {0:?}")]
-    DatabaseConnection(#[from] r2d2::Error),
-    #[error("Database execute failed: {0:?}")]
-    DatabaseExecute(#[from] rusqlite::Error),
+    #[error("ffmpeg failed: {0}")]
+    Ffmpeg(ffmpeg::FfmpegError),
+    #[error("Database failed: {0}")]
+    Database(#[from] JobStoreError),
+    #[error("Invalid output transcode file: {0}")]
+    InvalidOutputFile(#[from] ProbeValidationError),
+    #[error("Failed to upload to object storage: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Preset was removed from the config: {0}")]
+    UnknownPreset(String),
+    #[error("Failed to move finished transcode out of the staging directory: {0:?}")]
+    StageOutputFile(std::io::Error),
+    #[error("Source was downloaded in another audio language: expected={expected:?}, downloaded={downloaded:?}")]
+    AudioLangMismatch { expected: Option<String>, downloaded: Option<String> },
+}
+
+impl TranscodeError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::WorkerError(err) => err.code(),
+            Self::UsageError(_) | Self::MissingOutputFile(_) | Self::InvalidOutputFile(_) | Self::LoggedFail => ErrorCode::FfmpegFailed,
+            // NOTE: The transcode failed because of the download so we report why the download failed
+            Self::DownloadWorkerFailed(code) => *code,
+            Self::DownloadPathMissing | Self::DownloadFileMissing(_) => ErrorCode::YtdlpFailed,
+            Self::PipePartialDownload(err) | Self::CreateOutputDirectory(err) |
+            Self::WriteFfMetadata(err) | Self::CopyDownloadSameFormat(err) |
+            Self::StageOutputFile(err) => ErrorCode::from_io_error(err, ErrorCode::InternalError),
+            Self::Ffmpeg(err) => err.code(),
+            Self::Database(_) => ErrorCode::InternalError,
+            Self::Storage(_) => ErrorCode::StorageFailed,
+            Self::UnknownPreset(_) => ErrorCode::InvalidRequest,
+            Self::AudioLangMismatch { .. } => ErrorCode::Conflict,
+        }
+    }
+}
+
+fn download_failed(state: &DownloadState) -> TranscodeError {
+    TranscodeError::DownloadWorkerFailed(state.fail_code.unwrap_or(ErrorCode::YtdlpFailed))
+}
+
+// NOTE: The partial file can be created after yt-dlp reports progress so we poll while waiting on a partial download
+const PARTIAL_DOWNLOAD_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Waits until the download finishes or, if pipelining, until a partial download can be read
+/// Returns the path of the partial download if we can start transcoding early
+pub(crate) async fn wait_for_download(
+    video_id: &VideoId, download_state: &WorkerCacheEntry<DownloadState>, job_events: &JobEventBus, is_pipeline: bool,
+) -> Result<Option<PathBuf>, TranscodeError> {
+    let mut download_events = job_events.subscribe(JobId::new(JobKind::Download, video_id.as_str()));
+    loop {
+        {
+            let download_lock = download_state.lock().unwrap();
+            match download_lock.worker_status {
+                WorkerStatus::Failed => return Err(download_failed(&download_lock)),
+                WorkerStatus::Finished => return Ok(None),
+                WorkerStatus::Running if is_pipeline => {
+                    if let Some(path) = download_lock.partial_path.as_ref().filter(|path| path.exists()) {
+                        return Ok(Some(path.clone()));
+                    }
+                },
+                WorkerStatus::None | WorkerStatus::Queued | WorkerStatus::Running => {},
+            }
+        }
+        if is_pipeline {
+            tokio::time::sleep(PARTIAL_DOWNLOAD_POLL_INTERVAL).await;
+        } else {
+            download_events.changed().await;
+        }
+    }
+}
+
+/// Copies the file into the writer as yt-dlp appends to it until the download finishes
+// NOTE: yt-dlp renames the partial file when it finishes but our open handle still refers to the same file
+pub(crate) async fn pipe_partial_download(
+    partial_path: &Path, download_state: &WorkerCacheEntry<DownloadState>, mut writer: impl AsyncWrite + Unpin,
+) -> Result<(), TranscodeError> {
+    let mut file = tokio::fs::File::open(partial_path).await.map_err(TranscodeError::PipePartialDownload)?;
+    let mut buffer = vec![0u8; 64*1024];
+    let mut is_download_finished = false;
+    loop {
+        let total_read = file.read(&mut buffer).await.map_err(TranscodeError::PipePartialDownload)?;
+        if total_read > 0 {
+            match writer.write_all(&buffer[..total_read]).await {
+                Ok(()) => continue,
+                // NOTE: ffmpeg closes its input early if we only transcode part of the file
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                Err(err) => return Err(TranscodeError::PipePartialDownload(err)),
+            }
+        }
+        // reached the end of the file after download finished so we have everything
+        if is_download_finished {
+            return Ok(());
+        }
+        let worker_status = {
+            let download_lock = download_state.lock().unwrap();
+            if download_lock.worker_status == WorkerStatus::Failed {
+                return Err(download_failed(&download_lock));
+            }
+            download_lock.worker_status
+        };
+        match worker_status {
+            WorkerStatus::Failed => unreachable!(),
+            // NOTE: read again since data could have been written after our last read
+            WorkerStatus::Finished => is_download_finished = true,
+            WorkerStatus::None | WorkerStatus::Queued | WorkerStatus::Running => {
+                tokio::time::sleep(PARTIAL_DOWNLOAD_POLL_INTERVAL).await;
+            },
+        }
+    }
 }
 
+/// Uses the title and channel of the video as the filename if enabled
+/// Resolves both paths since the download and transcode directories can be given in different ways (e.g. ./data and data)
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    let resolve = |path: &Path| -> Option<PathBuf> {
+        let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        Some(std::fs::canonicalize(parent).ok()?.join(path.file_name()?))
+    };
+    match (resolve(a), resolve(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Transcodes that would overwrite the download they are encoded from get their own filename
+// NOTE: This happens when the download and transcode directories are the same and the download has the same extension
+fn get_transcode_path(
+    key: &TranscodeKey, app_config: &AppConfig, job_store: &SharedJobStore, metadata: Option<&Metadata>,
+) -> Result<PathBuf, JobStoreError> {
+    let path = get_named_transcode_path(key, app_config, job_store, metadata)?;
+    let download_path = job_store.select_ytdlp_entry(&key.video_id)?.and_then(|entry| entry.audio_path);
+    let is_download_path = download_path.is_some_and(|download_path| is_same_path(Path::new(download_path.as_str()), &path));
+    if !is_download_path {
+        return Ok(path);
+    }
+    let mut filename = path.file_name().unwrap_or_default().to_owned();
+    filename.push(format!(".transcode.{0}", key.audio_ext.as_str()));
+    Ok(path.with_file_name(filename))
+}
+
+fn get_named_transcode_path(
+    key: &TranscodeKey, app_config: &AppConfig, job_store: &SharedJobStore, metadata: Option<&Metadata>,
+) -> Result<PathBuf, JobStoreError> {
+    let default_path = app_config.transcode.join(key.as_str());
+    if !app_config.title_filenames {
+        return Ok(default_path);
+    }
+    let name = match metadata.and_then(|metadata| metadata.items.first()) {
+        Some(item) => get_title_filename(item.snippet.title.as_str(), item.snippet.channel_title.as_str()),
+        None => job_store.select_metadata_entry(&key.video_id)?
+            .and_then(|metadata| get_title_filename(metadata.title.as_str(), metadata.channel.as_str())),
+    };
+    let Some(name) = name else {
+        return Ok(default_path);
+    };
+    let filename = if key.variant.is_empty() {
+        format!("{0}.{1}", name, key.audio_ext.as_str())
+    } else {
+        format!("{0}.{1}.{2}", name, key.variant, key.audio_ext.as_str())
+    };
+    let path = app_config.transcode.join(filename);
+    // NOTE: Different videos can have the same title so avoid overwriting a transcode that belongs to another job
+    let is_taken = job_store.select_ffmpeg_entries()?.iter().any(|entry| {
+        entry.audio_path.as_deref() == path.to_str() &&
+        (entry.video_id != key.video_id || entry.audio_ext != key.audio_ext || entry.variant != key.variant)
+    });
+    if is_taken {
+        return Ok(default_path);
+    }
+    Ok(path)
+}
+
+/// Waveform peaks are keyed by the transcode instead of its filename like the logs
+pub fn get_waveform_path(key: &TranscodeKey, app_config: &AppConfig) -> PathBuf {
+    app_config.transcode.join(format!("{}.waveform.json", key.as_str()))
+}
+
+/// Uploads the finished transcode if object storage is configured and returns where it is stored
+// NOTE: The local copy is removed once uploaded so the transcode directory only holds files in progress
+fn store_transcode_file(key: &TranscodeKey, app_config: &AppConfig, audio_path: &Path) -> Result<String, TranscodeError> {
+    let Some(ref storage) = app_config.object_storage else {
+        return Ok(audio_path.to_str().unwrap().to_owned());
+    };
+    let object_key = format!("transcode/{0}", key.as_str());
+    let url = storage.upload_file(object_key.as_str(), audio_path, key.audio_ext.mime_type())?;
+    if let Err(err) = std::fs::remove_file(audio_path) {
+        log::warn!("Failed to remove uploaded transcode: path={0}, err={1:?}", audio_path.to_string_lossy(), err);
+    }
+    Ok(url)
+}
+
+/// Json of the preset currently configured for the options which is stored with the transcode
+fn get_transcode_preset_json(options: &TranscodeOptions, app_config: &AppConfig) -> Option<String> {
+    let preset = app_config.transcode_presets.get(options.preset.as_ref()?)?;
+    serde_json::to_string(preset).ok()
+}
+
+/// A finished transcode is stale if the source was downloaded again since it was transcoded
+// NOTE: A transcode remains valid if the source was deleted to save space
+fn is_transcode_stale(
+    job_store: &SharedJobStore, download_cache: &DownloadCache, entry: &FfmpegRow, transcode_preset: Option<&str>,
+) -> Result<bool, JobStoreError> {
+    // NOTE: The preset was edited since the transcode so its encoder settings are outdated
+    if transcode_preset.is_some() && entry.transcode_preset.as_deref() != transcode_preset {
+        return Ok(true);
+    }
+    // NOTE: The source is being downloaded again (e.g. with a different format) so it will change
+    let is_downloading = download_cache.get(&entry.video_id)
+        .is_some_and(|state| state.lock().unwrap().worker_status.is_busy());
+    if is_downloading {
+        return Ok(true);
+    }
+    let source_hash = job_store.select_ytdlp_entry(&entry.video_id)?.and_then(|entry| entry.source_hash);
+    Ok(source_hash.is_some() && source_hash != entry.source_hash)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn try_start_transcode_worker(
-    key: TranscodeKey,
-    download_cache: DownloadCache, transcode_cache: TranscodeCache, app_config: Arc<AppConfig>, 
-    db_pool: DatabasePool, worker_thread_pool: WorkerThreadPool,
-    metadata: Option<Arc<Metadata>>,
+    key: TranscodeKey, options: TranscodeOptions,
+    download_cache: DownloadCache, transcode_cache: TranscodeCache, job_events: JobEventBus, app_config: Arc<AppConfig>,
+    job_store: SharedJobStore, worker_thread_pool: WorkerThreadPool,
+    metadata: Option<Arc<Metadata>>, owner: Option<i64>,
 ) -> Result<WorkerStatus, TranscodeStartError> {
+    let transcode_preset = get_transcode_preset_json(&options, &app_config);
     // check if transcode in progress (cache hit)
     {
         let transcode_state = transcode_cache.entry(key.clone()).or_default();
-        let mut state = transcode_state.0.lock().unwrap();
+        let mut state = transcode_state.lock().unwrap();
         match state.worker_status {
             WorkerStatus::None | WorkerStatus::Failed => {
                 *state = TranscodeState {
                     worker_status: WorkerStatus::Queued,
                     ..Default::default()
                 };
-                transcode_state.1.notify_all();
+                job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
+            },
+            WorkerStatus::Finished => {
+                let entry = job_store.select_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str())?;
+                let is_stale = match entry {
+                    Some(ref entry) => is_transcode_stale(&job_store, &download_cache, entry, transcode_preset.as_deref())?,
+                    None => true,
+                };
+                if !is_stale {
+                    return Ok(state.worker_status);
+                }
+                *state = TranscodeState {
+                    worker_status: WorkerStatus::Queued,
+                    ..Default::default()
+                };
+                job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
             },
-            WorkerStatus::Queued | WorkerStatus::Running | WorkerStatus::Finished => return Ok(state.worker_status),
+            WorkerStatus::Queued | WorkerStatus::Running => return Ok(state.worker_status),
         }
     }
     // rollback transcode cache entry if enqueue failed
@@ -173,35 +502,52 @@ pub fn try_start_transcode_worker(
         let is_queue_success = is_queue_success.clone();
         let key = key.clone();
         let transcode_cache = transcode_cache.clone();
+        let job_events = job_events.clone();
         move || {
             if !*is_queue_success.borrow() {
                 let transcode_state = transcode_cache.get(&key).unwrap();
-                *transcode_state.0.lock().unwrap() = TranscodeState::default();
-                transcode_state.1.notify_all();
+                *transcode_state.lock().unwrap() = TranscodeState::default();
+                job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
             }
         }
     });
     {
-        let db_conn = db_pool.get()?;
         // check if transcode finished on disk (cache miss due to reset)
-        if let Some(entry) = select_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext)? {
-            if let Some(_audio_path) = entry.audio_path {
+        if let Some(entry) = job_store.select_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str())? {
+            if entry.audio_path.is_some() && !is_transcode_stale(&job_store, &download_cache, &entry, transcode_preset.as_deref())? {
                 let status = entry.status;
                 // TODO: Check if deleted
                 // let audio_path = PathBuf::from(audio_path);
                 let transcode_state = transcode_cache.entry(key.clone()).or_default();
-                let mut state = transcode_state.0.lock().unwrap();
+                let mut state = transcode_state.lock().unwrap();
                 state.worker_status = status;
                 state.file_cached = true;
-                transcode_state.1.notify_all();
+                state.progress_percent = Some(100.0);
+                state.bump_revision();
+                job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
                 *is_queue_success.borrow_mut() = true;
                 return Ok(status);
             }
         }
+        // check the transcode fits on disk before ffmpeg starts writing it
+        let source_duration_ms = match job_store.select_ytdlp_entry(&key.video_id)?.and_then(|entry| entry.duration_ms) {
+            Some(duration_ms) => Some(duration_ms),
+            None => job_store.select_metadata_entry(&key.video_id)?.and_then(|metadata| metadata.duration_ms),
+        };
+        let preset = options.preset.as_ref().and_then(|name| app_config.transcode_presets.get(name));
+        let bitrate_kbps = options.get_output_bitrate_kbps(key.audio_ext, preset) as u64;
+        let transcode_bytes = source_duration_ms.map(|duration_ms| options.get_output_duration_ms(duration_ms) * bitrate_kbps / 8);
+        for path in [app_config.get_transcode_staging(), app_config.transcode.clone()] {
+            check_free_space(&path, transcode_bytes, app_config.min_free_space_bytes)?;
+        }
         // start transcode worker
-        let _ = insert_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext)?;
+        let transcode_options = serde_json::to_string(&options).ok();
+        let _ = job_store.insert_ffmpeg_entry(
+            &key.video_id, key.audio_ext, key.variant.as_str(), transcode_options.as_deref(), owner,
+        )?;
     }
-    worker_thread_pool.lock().unwrap().execute(move || {
+    worker_thread_pool.execute(JobId::new(JobKind::Transcode, key.as_str()), async move {
+        let start_time = Instant::now();
         log::info!("Launching transcode process: {0}", key.as_str());
         // setup logging
         let system_log_path = app_config.transcode.join(format!("{}.system.log", key.as_str()));
@@ -212,77 +558,175 @@ pub fn try_start_transcode_worker(
                 return;
             },
         };
-        if let Ok(db_conn) = db_pool.get() {
-            let _ = select_and_update_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext, |entry| {
-                entry.system_log_path = Some(system_log_path.to_str().unwrap().to_owned());
-            }).unwrap();
+        let res = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+            entry.system_log_path = Some(system_log_path.to_str().unwrap().to_owned());
+            entry.transcode_preset = transcode_preset;
+        });
+        if let Err(err) = res {
+            log::error!("Failed to store system log path: id={0}, err={1:?}", key.as_str(), err);
         }
         let system_log_writer = Arc::new(Mutex::new(BufWriter::new(system_log_file)));
+        if let Some(request_id) = current_request_id() {
+            let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[info] Started by request: {0}", request_id.as_str());
+        }
         // launch process
-        let res = enqueue_transcode_worker(
-            key.clone(), download_cache.clone(), transcode_cache.clone(), 
-            app_config.clone(), db_pool.clone(), system_log_writer.clone(),
-            metadata,
-        );
+        let mut res = enqueue_transcode_worker(
+            key.clone(), download_cache.clone(), transcode_cache.clone(), job_events.clone(),
+            app_config.clone(), job_store.clone(), system_log_writer.clone(),
+            options.clone(), metadata.clone(),
+        ).await;
+        // NOTE: Deployments without internet access for ffmpeg can't fetch the thumbnail so we degrade to no album art
+        if let Err(TranscodeError::Ffmpeg(ffmpeg::FfmpegError::ThumbnailFetch(ref line))) = res {
+            let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Retrying without thumbnail since it failed to fetch: {line}");
+            if let Some(transcode_state) = transcode_cache.get(&key) {
+                let mut state = transcode_state.lock().unwrap();
+                state.is_thumbnail_skipped = true;
+                state.transcode_duration_milliseconds = None;
+                state.progress_percent = None;
+                state.bump_revision();
+            }
+            res = enqueue_transcode_worker(
+                key.clone(), download_cache.clone(), transcode_cache.clone(), job_events.clone(),
+                app_config.clone(), job_store.clone(), system_log_writer.clone(),
+                TranscodeOptions { skip_thumbnail: true, ..options }, metadata,
+            ).await;
+        }
         if let Err(ref err) = res {
             let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[error] Worker failed with: {err:?}");
         }
+        // NOTE: The waveform is only used for drawing so failing to generate it doesn't fail the transcode
+        if let Ok(ref audio_path) = res {
+            let waveform_path = get_waveform_path(&key, &app_config);
+            let res = block_in_place(|| {
+                app_config.get_transcoder().generate_waveform(audio_path, &app_config.process_priority)
+                    .map_err(|err| err.to_string())
+                    .and_then(|waveform| waveform.save(&waveform_path).map_err(|err| err.to_string()))
+            });
+            if let Err(err) = res {
+                let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Failed to generate waveform: {err}");
+            }
+        }
+        let file_size_bytes = res.as_ref().ok().and_then(|p| std::fs::metadata(p).ok()).map(|m| m.len());
+        let file_hash = res.as_ref().ok().and_then(|p| match block_in_place(|| get_file_sha256(p)) {
+            Ok(hash) => Some(hash),
+            Err(err) => {
+                let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Failed to hash transcode: {err:?}");
+                None
+            },
+        });
+        let res = res.and_then(|path| block_in_place(|| store_transcode_file(&key, &app_config, &path)));
+        if let Err(ref err @ TranscodeError::Storage(_)) = res {
+            let _ = writeln!(&mut system_log_writer.lock().unwrap(), "[error] Worker failed with: {err:?}");
+        }
         // update database
         let (audio_path, worker_status, worker_error) = match res {
             Ok(path) => (Some(path), WorkerStatus::Finished, None),
             Err(err) => (None, WorkerStatus::Failed, Some(err)),
         };
-        {
-            let db_conn = db_pool.get().unwrap();
-            let _ = select_and_update_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext, |entry| {
-                entry.audio_path = audio_path.map(|p| p.to_str().unwrap().to_string());
-                entry.status = worker_status;
-            }).unwrap();
+        if worker_status == WorkerStatus::Finished {
+            record_job_duration(&job_store, JobKind::Transcode, start_time);
         }
-        // NOTE: update cache so changes to database are visible to signal listeners
+        let file_size_bytes = file_size_bytes.filter(|_| audio_path.is_some());
+        record_job_history(
+            &job_store, &JobId::new(JobKind::Transcode, key.as_str()), worker_status,
+            worker_error.as_ref().map(|err| err.code().as_str()), file_size_bytes, start_time,
+        );
+        let duration_ms = transcode_cache.get(&key).and_then(|state| {
+            let state = state.lock().unwrap();
+            state.transcode_duration_milliseconds.or(state.source_duration_milliseconds)
+        });
         let transcode_state = transcode_cache.entry(key.clone()).or_default();
-        let mut state = transcode_state.0.lock().unwrap();
-        state.worker_status = worker_status;
-        state.fail_reason = worker_error.map(|e| e.to_string());
-        transcode_state.1.notify_all();
+        let mut final_state = transcode_state.lock().unwrap().clone();
+        final_state.worker_status = worker_status;
+        final_state.end_time_unix = get_unix_time();
+        if worker_status == WorkerStatus::Finished {
+            final_state.progress_percent = Some(100.0);
+        }
+        final_state.fail_code = worker_error.as_ref().map(|e| e.code());
+        final_state.fail_reason = worker_error.map(|e| e.to_string());
+        final_state.bump_revision();
+        let _ = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+            entry.audio_path = audio_path;
+            entry.status = worker_status;
+            entry.file_size_bytes = file_size_bytes;
+            entry.file_hash = file_hash.filter(|_| worker_status == WorkerStatus::Finished);
+            // NOTE: Prefer the duration measured by ffprobe over the parsed progress
+            entry.duration_ms = entry.duration_ms.or(duration_ms);
+            entry.final_state = serde_json::to_string(&final_state).ok();
+        }).unwrap();
+        let fail_reason = final_state.fail_reason.clone();
+        // NOTE: update cache so changes to database are visible to signal listeners
+        *transcode_state.lock().unwrap() = final_state;
+        job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
+        app_config.notifier.publish_job(&job_store, JobKind::Transcode, &key.as_str(), &key.video_id, worker_status, fail_reason);
     });
     *is_queue_success.borrow_mut() = true;
     Ok(WorkerStatus::Queued)
 }
 
-fn enqueue_transcode_worker(
-    key: TranscodeKey, download_cache: DownloadCache, transcode_cache: TranscodeCache,
-    app_config: Arc<AppConfig>, db_pool: DatabasePool, system_log_writer: Arc<Mutex<impl Write>>,
-    metadata: Option<Arc<Metadata>>,
+#[allow(clippy::too_many_arguments)]
+async fn enqueue_transcode_worker(
+    key: TranscodeKey, download_cache: DownloadCache, transcode_cache: TranscodeCache, job_events: JobEventBus,
+    app_config: Arc<AppConfig>, job_store: SharedJobStore, system_log_writer: Arc<Mutex<impl Write + Send>>,
+    options: TranscodeOptions, metadata: Option<Arc<Metadata>>,
 ) -> Result<PathBuf, TranscodeError> {
-    let filename = format!("{0}.{1}", key.video_id.as_str(), key.audio_ext.as_str());
-    let audio_path = app_config.transcode.join(filename.as_str());
+    // NOTE: ffmpeg writes into the staging directory so the transcode directory only has complete and validated transcodes
+    let staging_path = app_config.get_transcode_staging().join(key.as_str());
+    let res = transcode_file(
+        key.clone(), download_cache, transcode_cache, job_events, app_config.clone(), job_store.clone(), system_log_writer,
+        options, metadata.clone(), staging_path.clone(),
+    ).await;
+    if let Err(err) = res {
+        let _ = std::fs::remove_file(&staging_path);
+        return Err(err);
+    }
+    // NOTE: The path is chosen once the download finished since it can't overwrite the downloaded file
+    let audio_path = get_transcode_path(&key, &app_config, &job_store, metadata.as_deref())?;
+    block_in_place(|| move_staged_file(&staging_path, &audio_path)).map_err(TranscodeError::StageOutputFile)?;
+    Ok(audio_path)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn transcode_file(
+    key: TranscodeKey, download_cache: DownloadCache, transcode_cache: TranscodeCache, job_events: JobEventBus,
+    app_config: Arc<AppConfig>, job_store: SharedJobStore, system_log_writer: Arc<Mutex<impl Write + Send>>,
+    options: TranscodeOptions, metadata: Option<Arc<Metadata>>, audio_path: PathBuf,
+) -> Result<(), TranscodeError> {
+    let preset = match options.preset {
+        Some(ref name) => Some(app_config.transcode_presets.get(name).ok_or_else(|| TranscodeError::UnknownPreset(name.clone()))?),
+        None => None,
+    };
     // wait for download worker
-    {
-        let download_state = download_cache.entry(key.video_id.clone()).or_default().clone();
-        let mut download_lock = download_state.0.lock().unwrap();
-        loop {
-            match download_lock.worker_status {
-                WorkerStatus::Failed => return Err(TranscodeError::DownloadWorkerFailed),
-                WorkerStatus::Finished => break,
-                WorkerStatus::None | WorkerStatus::Queued | WorkerStatus::Running => {},
-            }
-            download_lock = download_state.1.wait(download_lock).unwrap();
-        }
+    let download_state = download_cache.entry(key.video_id.clone()).or_default().clone();
+    // NOTE: Measuring replaygain, fingerprinting or probing the codec for passthrough needs the whole source so we can't pipeline the download
+    let is_pipeline = app_config.pipeline_transcode && !options.replaygain && !options.passthrough && !options.enrich_tags;
+    let partial_path = wait_for_download(&key.video_id, &download_state, &job_events, is_pipeline).await?;
+    // NOTE: The source is shared between languages so it can be replaced by another language while we were queued
+    let downloaded_lang = job_store.select_ytdlp_entry(&key.video_id)?.and_then(|entry| entry.audio_lang);
+    if downloaded_lang != options.audio_lang {
+        return Err(TranscodeError::AudioLangMismatch { expected: options.audio_lang.clone(), downloaded: downloaded_lang });
     }
     // get source file to transcode
-    let source_path: Option<String> = {
-        let db_conn = db_pool.get()?;
-        let entry = select_ytdlp_entry(&db_conn, &key.video_id)?.expect("Entry should exist");
-        entry.audio_path
-    };
-    let Some(source_path) = source_path else {
-        return Err(TranscodeError::DownloadPathMissing);
+    let source_path = match partial_path {
+        Some(ref partial_path) => {
+            writeln!(
+                &mut system_log_writer.lock().unwrap(),
+                "[info] Transcoding from partial download: {0}", partial_path.to_string_lossy(),
+            ).map_err(WorkerError::SystemWriteFail)?;
+            None
+        },
+        None => {
+            let entry = job_store.select_ytdlp_entry(&key.video_id)?.expect("Entry should exist");
+            let Some(source_path) = entry.audio_path else {
+                return Err(TranscodeError::DownloadPathMissing);
+            };
+            let source_path = PathBuf::from(source_path);
+            if !source_path.exists() {
+                return Err(TranscodeError::DownloadFileMissing(source_path));
+            }
+            Some(source_path)
+        },
     };
-    let source_path = PathBuf::from(source_path);
-    if !source_path.exists() {
-        return Err(TranscodeError::DownloadFileMissing(source_path));
-    }
     // NOTE: Don't copy since we do extra stuff like embed thumbnail and video metadata
     // If the download path is the same format as transcode path then just copy it
     // if source_path.file_name() == audio_path.file_name() {
@@ -299,9 +743,129 @@ fn enqueue_transcode_worker(
     //     *is_transcoded.borrow_mut() = true;
     //     return Ok(audio_path);
     // }
+    // NOTE: yt-dlp only reports the duration after the download so we fall back to the metadata when pipelining
+    let source_duration_ms = job_store.select_ytdlp_entry(&key.video_id)?
+        .and_then(|entry| entry.duration_ms)
+        .or_else(|| metadata.as_ref()?.items.first()?.get_duration_milliseconds());
+    if let Some(transcode_state) = transcode_cache.get(&key) {
+        let mut state = transcode_state.lock().unwrap();
+        state.expected_duration_milliseconds = source_duration_ms.map(|duration| options.get_output_duration_ms(duration));
+        state.bump_revision();
+    }
+    if options.fade_out_ms.is_some() && !options.trim_silence && options.get_output_end_ms(source_duration_ms).is_none() {
+        return Err(TranscodeError::UsageError("Fade out requires the duration of the source or a trim end".to_owned()));
+    }
+    // NOTE: Passthrough falls back to re-encoding if the source codec can't be stored in the requested format
+    let transcoder = app_config.get_transcoder();
+    let is_stream_copy = match source_path {
+        Some(ref source_path) if options.passthrough => {
+            let codec = match block_in_place(|| transcoder.probe_format(source_path)) {
+                Ok(probe) => probe.codec,
+                Err(err) => {
+                    writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Failed to probe source codec for passthrough: {err}")
+                        .map_err(WorkerError::SystemWriteFail)?;
+                    None
+                },
+            };
+            let is_stream_copy = codec.as_deref().is_some_and(|codec| ffmpeg::can_copy_codec(codec, key.audio_ext));
+            writeln!(
+                &mut system_log_writer.lock().unwrap(), "[info] Source codec {0} is {1}",
+                codec.as_deref().unwrap_or("unknown"), if is_stream_copy { "copied without re-encoding" } else { "re-encoded" },
+            ).map_err(WorkerError::SystemWriteFail)?;
+            is_stream_copy
+        },
+        _ => false,
+    };
+    let volume_filter = options.get_volume_filter();
+    let replay_gain = match source_path {
+        Some(ref source_path) if options.replaygain => {
+            // NOTE: The tags are optional so we don't fail the transcode if we can't measure them
+            match block_in_place(|| transcoder.measure_replay_gain(source_path, volume_filter.as_deref())) {
+                Ok(replay_gain) => Some(replay_gain),
+                Err(err) => {
+                    writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Failed to measure replaygain: {err}")
+                        .map_err(WorkerError::SystemWriteFail)?;
+                    None
+                },
+            }
+        },
+        _ => None,
+    };
+    let enrichment = match (source_path.as_ref(), app_config.enricher.as_ref()) {
+        (Some(source_path), Some(enricher)) if options.enrich_tags => {
+            let res = enrichment::get_enrichment_from_cache(
+                enricher, &job_store, &transcoder, &key.video_id, source_path, source_duration_ms,
+            ).await;
+            // NOTE: The tags are optional so we fall back to the tags of the video if the recording isn't identified
+            match res {
+                Ok(entry) => match entry.recording_id {
+                    Some(ref recording_id) => {
+                        writeln!(&mut system_log_writer.lock().unwrap(), "[info] Identified recording: {recording_id}")
+                            .map_err(WorkerError::SystemWriteFail)?;
+                        Some(entry)
+                    },
+                    None => {
+                        writeln!(&mut system_log_writer.lock().unwrap(), "[warn] AcoustID didn't identify the recording")
+                            .map_err(WorkerError::SystemWriteFail)?;
+                        None
+                    },
+                },
+                Err(err) => {
+                    writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Failed to enrich tags: {err}")
+                        .map_err(WorkerError::SystemWriteFail)?;
+                    None
+                },
+            }
+        },
+        (_, None) if options.enrich_tags => {
+            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Tags can't be enriched without an AcoustID api key")
+                .map_err(WorkerError::SystemWriteFail)?;
+            None
+        },
+        _ => None,
+    };
+    // chapters and lyrics are passed to ffmpeg in a separate metadata file
+    let ffmetadata = {
+        let mut ffmetadata = FfMetadata::default();
+        if [AudioExtension::MP3, AudioExtension::M4A].contains(&key.audio_ext) {
+            let item = metadata.as_ref().and_then(|metadata| metadata.items.first());
+            if let (Some(item), Some(duration_ms)) = (item, source_duration_ms) {
+                ffmetadata.chapters = ffmetadata::parse_description_chapters(item.snippet.description.as_str(), duration_ms);
+            }
+            let subtitle = ffmetadata::find_subtitle_paths(&app_config.download, &key.video_id)
+                .first()
+                .and_then(|path| std::fs::read_to_string(path).ok());
+            if let Some(subtitle) = subtitle {
+                let lines = ffmetadata::parse_vtt(subtitle.as_str());
+                if !lines.is_empty() {
+                    ffmetadata.lyrics = Some(ffmetadata::format_lrc(lines.as_slice()));
+                }
+            }
+        }
+        ffmetadata
+    };
+    let ffmetadata_path = match ffmetadata.is_empty() {
+        true => None,
+        false => {
+            let path = app_config.transcode.join(format!("{}.ffmetadata", key.as_str()));
+            std::fs::write(&path, ffmetadata.to_ffmetadata()).map_err(TranscodeError::WriteFfMetadata)?;
+            Some(path)
+        },
+    };
+    let _remove_ffmetadata = defer({
+        let ffmetadata_path = ffmetadata_path.clone();
+        move || {
+            if let Some(path) = ffmetadata_path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    });
     // logging files
     let stdout_log_path = app_config.transcode.join(format!("{}.stdout.log", key.as_str()));
     let stderr_log_path = app_config.transcode.join(format!("{}.stderr.log", key.as_str()));
+    let thread_budget = ThreadBudget::acquire(&app_config);
+    writeln!(&mut system_log_writer.lock().unwrap(), "[info] Transcoding with {0} threads", thread_budget.threads)
+        .map_err(WorkerError::SystemWriteFail)?;
     // spawn process
     let process_args = {
         let mut args = Vec::<String>::new();
@@ -311,10 +875,16 @@ fn enqueue_transcode_worker(
         let push_metadata = |args: &mut Vec<String>, field: &str, value: &str| {
             args.extend(["-metadata".to_owned(), format!("{0}={1}", field, value)]);
         };
-        push_args(&mut args, &["-i", source_path.to_str().unwrap()]);
+        if let Some(preset) = preset {
+            args.extend(preset.hwaccel_args.iter().cloned());
+        }
+        match source_path {
+            Some(ref source_path) => push_args(&mut args, &["-i", source_path.to_str().unwrap()]),
+            None => push_args(&mut args, &["-i", "pipe:0"]),
+        }
         let can_embed_thumbnail = &[AudioExtension::MP3].contains(&key.audio_ext);
         let thumbnail = || -> Option<Thumbnail> {
-            if !can_embed_thumbnail {
+            if !can_embed_thumbnail || options.skip_thumbnail {
                 return None;
             }
             let metadata = metadata.clone()?;
@@ -326,15 +896,25 @@ fn enqueue_transcode_worker(
         if let Some(ref thumbnail) = thumbnail {
             push_args(&mut args, &["-i", thumbnail.url.as_str()]);
         }
+        if let Some(ref ffmetadata_path) = ffmetadata_path {
+            push_args(&mut args, &["-f", "ffmetadata", "-i", ffmetadata_path.to_str().unwrap()]);
+        }
         push_args(&mut args, &["-map", "0:a"]);
         if thumbnail.is_some() {
             push_args(&mut args, &["-map", "1"]);
         }
+        if ffmetadata_path.is_some() {
+            let index = if thumbnail.is_some() { "2" } else { "1" };
+            push_args(&mut args, &["-map_metadata", index, "-map_chapters", index]);
+        }
         push_metadata(&mut args, "video_id", key.video_id.as_str());
+        // NOTE: The title and channel of a video are often not the song and artist so the identified recording is used instead
+        let enriched_title = enrichment.as_ref().and_then(|entry| entry.title.as_deref());
+        let enriched_artist = enrichment.as_ref().and_then(|entry| entry.artist.as_deref());
         if let Some(metadata) = metadata {
             if let Some(item) = metadata.items.first() {
-                push_metadata(&mut args, "title", item.snippet.title.as_str());
-                push_metadata(&mut args, "artist", item.snippet.channel_title.as_str());
+                push_metadata(&mut args, "title", enriched_title.unwrap_or(item.snippet.title.as_str()));
+                push_metadata(&mut args, "artist", enriched_artist.unwrap_or(item.snippet.channel_title.as_str()));
                 push_metadata(&mut args, "description", item.snippet.description.as_str());
                 push_metadata(&mut args, "published_at", item.snippet.published_at.as_str());
                 push_args(&mut args, &["-id3v2_version", "3"]);
@@ -342,26 +922,51 @@ fn enqueue_transcode_worker(
                 thumbnails.sort_by_key(|(_, thumbnail)| thumbnail.width * thumbnail.height);
             }
         }
+        if let Some(ref entry) = enrichment {
+            let track_number = entry.track_number.map(|number| number.to_string());
+            let tags = [
+                ("album", entry.album.as_deref()),
+                ("album_artist", entry.album_artist.as_deref()),
+                ("track", track_number.as_deref()),
+                ("date", entry.release_date.as_deref()),
+            ];
+            for (field, value) in tags {
+                if let Some(value) = value {
+                    push_metadata(&mut args, field, value);
+                }
+            }
+        }
+        if let Some(replay_gain) = replay_gain {
+            push_metadata(&mut args, "REPLAYGAIN_TRACK_GAIN", format!("{:+.2} dB", replay_gain.track_gain_db).as_str());
+            push_metadata(&mut args, "REPLAYGAIN_TRACK_PEAK", format!("{:.6}", replay_gain.track_peak).as_str());
+            // NOTE: The mp4 muxer drops metadata keys it doesn't know about unless told otherwise
+            if key.audio_ext == AudioExtension::M4A {
+                push_args(&mut args, &["-movflags", "use_metadata_tags"]);
+            }
+        }
         if thumbnail.is_some() {
             push_args(&mut args, &["-disposition:0", "attached_pic"]);
+            push_args(&mut args, &["-filter:v", ffmpeg::get_thumbnail_filter(app_config.thumbnail_max_size).as_str()]);
+        }
+        args.extend(options.get_ffmpeg_arguments(source_duration_ms));
+        if let Some(preset) = preset {
+            args.extend(preset.get_ffmpeg_arguments());
+        }
+        if is_stream_copy {
+            push_args(&mut args, &["-c:a", "copy"]);
         }
         push_args(&mut args, &[
-            "-threads", "0",
+            "-threads", thread_budget.threads.to_string().as_str(),
             "-progress", "-", "-y",
             audio_path.to_str().unwrap(),
         ]);
         args
     };
-    let process_res = Command::new(app_config.ffmpeg_binary.clone())
-        .args(process_args.as_slice())
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
+    let process_res = transcoder.spawn(process_args.as_slice(), partial_path.is_some(), &app_config.process_priority);
     let mut process = match process_res {
         Ok(process) => process,
         Err(err) => {
-            writeln!(&mut system_log_writer.lock().unwrap(), "[error] ffmpeg failed to start: {err:?}")
+            writeln!(&mut system_log_writer.lock().unwrap(), "[error] {} failed to start: {err:?}", transcoder.name())
                 .map_err(WorkerError::SystemWriteFail)?;
             return Err(TranscodeError::LoggedFail);
         }
@@ -369,110 +974,186 @@ fn enqueue_transcode_worker(
     // update as running
     {
         let transcode_state = transcode_cache.get(&key).unwrap();
-        transcode_state.0.lock().unwrap().worker_status = WorkerStatus::Running;
-        transcode_state.1.notify_all();
-    }
-    {
-        let db_conn = db_pool.get()?;
-        let _ = select_and_update_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext, |entry| {
-            entry.status = WorkerStatus::Running;
-        })?;
+        let mut state = transcode_state.lock().unwrap();
+        state.worker_status = WorkerStatus::Running;
+        state.bump_revision();
+        job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
     }
+    let _ = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+        entry.status = WorkerStatus::Running;
+    })?;
+    // feed partial download
+    let stdin_handle = match partial_path {
+        None => None,
+        Some(partial_path) => Some((partial_path, process.stdin.take().ok_or(WorkerError::StdinMissing)?)),
+    };
+    let stdin_task = async {
+        match stdin_handle {
+            None => Ok(()),
+            Some((partial_path, stdin_handle)) => pipe_partial_download(partial_path.as_path(), &download_state, stdin_handle).await,
+        }
+    };
     // scrape stdout and stderr
-    let stdout_thread = thread::spawn({
-        let db_pool = db_pool.clone();
-        let key = key.clone();
-        let stdout_handle = process.stdout.take().ok_or(WorkerError::StdoutMissing)?;
-        let mut stdout_reader = BufReader::new(ConvertCarriageReturnToNewLine::new(stdout_handle));
-        let stdout_log_file = std::fs::File::create(stdout_log_path.clone()).map_err(WorkerError::StdoutLogCreate)?;
-        let mut stdout_log_writer = BufWriter::new(stdout_log_file);
-        {
-            let db_conn = db_pool.get()?;
-            let _ = select_and_update_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext, |entry| {
-                entry.stdout_log_path = Some(stdout_log_path.to_str().unwrap().to_owned());
-            })?;
-        }
-        move || -> Result<(), WorkerError> {
-            let mut line = String::new();
-            loop {
-                match stdout_reader.read_line(&mut line) {
-                    Err(_) => break,
-                    Ok(0) => break,
-                    Ok(_) => (),
+    let stdout_handle = process.stdout.take().ok_or(WorkerError::StdoutMissing)?;
+    let mut stdout_reader = BufReader::new(stdout_handle);
+    let stdout_log_file = RotatingLogFile::create(&stdout_log_path, app_config.job_log_limits).map_err(WorkerError::StdoutLogCreate)?;
+    let mut stdout_log_writer = BufWriter::new(stdout_log_file);
+    let _ = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+        entry.stdout_log_path = Some(stdout_log_path.to_str().unwrap().to_owned());
+    })?;
+    let stderr_handle = process.stderr.take().ok_or(WorkerError::StderrMissing)?;
+    let mut stderr_reader = BufReader::new(stderr_handle);
+    let stderr_log_file = RotatingLogFile::create(&stderr_log_path, app_config.job_log_limits).map_err(WorkerError::StderrLogCreate)?;
+    let mut stderr_log_writer = BufWriter::new(stderr_log_file);
+    let _ = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+        entry.stderr_log_path = Some(stderr_log_path.to_str().unwrap().to_owned());
+    })?;
+    // NOTE: The records from "-progress -" on stdout are used once they appear since the progress line on stderr
+    //       changes between versions and locales, but the stderr line is kept as a fallback if stdout has none
+    let has_stdout_progress = AtomicBool::new(false);
+    let stdout_task = async {
+        let mut line = String::new();
+        let mut progress_parser = ffmpeg::ProgressRecordParser::default();
+        loop {
+            match read_output_line(&mut stdout_reader, &mut line).await {
+                Err(_) => break,
+                Ok(0) => break,
+                Ok(_) => (),
+            }
+            let _ = stdout_log_writer.write(line.as_bytes()).map_err(WorkerError::StdoutWriteFail)?;
+            if let Some(progress) = progress_parser.parse_line(line.as_str()) {
+                log::debug!("[transcode] id={0} progress={progress:?}", key.as_str());
+                has_stdout_progress.store(true, Ordering::Relaxed);
+                if let Some(transcode_state) = transcode_cache.get(&key) {
+                    transcode_state.lock().unwrap().update_from_progress(progress);
                 }
-                let _ = stdout_log_writer.write(line.as_bytes()).map_err(WorkerError::StdoutWriteFail)?;
-                line.clear();
+                job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
             }
-            Ok(())
+            line.clear();
         }
-    });
-    let stderr_thread = thread::spawn({
-        let db_pool = db_pool.clone();
-        let key = key.clone();
-        let stderr_handle = process.stderr.take().ok_or(WorkerError::StderrMissing)?;
-        let mut stderr_reader = BufReader::new(ConvertCarriageReturnToNewLine::new(stderr_handle));
-        let stderr_log_file = std::fs::File::create(stderr_log_path.clone()).map_err(WorkerError::StderrLogCreate)?;
-        let mut stderr_log_writer = BufWriter::new(stderr_log_file);
-        {
-            let db_conn = db_pool.get()?;
-            let _ = select_and_update_ffmpeg_entry(&db_conn, &key.video_id, key.audio_ext, |entry| {
-                entry.stderr_log_path = Some(stderr_log_path.to_str().unwrap().to_owned());
-            })?;
-        }
-        move || -> Result<(), WorkerError> {
-            let mut line = String::new();
-            loop {
-                match stderr_reader.read_line(&mut line) {
-                    Err(_) => break,
-                    Ok(0) => break,
-                    Ok(_) => (),
-                }
-                let _ = stderr_log_writer.write(line.as_bytes()).map_err(WorkerError::StderrWriteFail)?;
-                match ffmpeg::parse_stderr_line(line.as_str()) {
-                    None => (),
-                    Some(ffmpeg::ParsedStderrLine::TranscodeSourceInfo(info)) => {
-                        log::debug!("[transcode] id={0} info={info:?}", key.as_str());
-                        let transcode_state = transcode_cache.entry(key.clone()).or_default();
-                        transcode_state.0.lock().unwrap().update_from_source_info(info);
-                    },
-                    Some(ffmpeg::ParsedStderrLine::TranscodeProgress(progress)) => {
-                        log::debug!("[transcode] id={0} progress={progress:?}", key.as_str());
-                        let transcode_state = transcode_cache.entry(key.clone()).or_default();
-                        transcode_state.0.lock().unwrap().update_from_progress(progress);
-                    },
-                }
-                line.clear();
+        Ok::<_, WorkerError>(())
+    };
+    let stderr_task = async {
+        let mut line = String::new();
+        let mut failure = None;
+        loop {
+            match read_output_line(&mut stderr_reader, &mut line).await {
+                Err(_) => break,
+                Ok(0) => break,
+                Ok(_) => (),
+            }
+            let _ = stderr_log_writer.write(line.as_bytes()).map_err(WorkerError::StderrWriteFail)?;
+            match ffmpeg::parse_stderr_line(line.as_str()) {
+                None => (),
+                Some(ffmpeg::ParsedStderrLine::TranscodeSourceInfo(info)) => {
+                    log::debug!("[transcode] id={0} info={info:?}", key.as_str());
+                    if let Some(transcode_state) = transcode_cache.get(&key) {
+                        transcode_state.lock().unwrap().update_from_source_info(info);
+                    }
+                    job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
+                },
+                Some(ffmpeg::ParsedStderrLine::TranscodeProgress(_)) if has_stdout_progress.load(Ordering::Relaxed) => (),
+                Some(ffmpeg::ParsedStderrLine::TranscodeProgress(progress)) => {
+                    log::debug!("[transcode] id={0} progress={progress:?}", key.as_str());
+                    if let Some(transcode_state) = transcode_cache.get(&key) {
+                        transcode_state.lock().unwrap().update_from_progress(progress);
+                    }
+                    job_events.publish(JobId::new(JobKind::Transcode, key.as_str()));
+                },
+                // NOTE: Keep the first error since later ones are usually caused by it
+                Some(ffmpeg::ParsedStderrLine::Failure(err)) => {
+                    failure = failure.or(Some(err));
+                },
             }
-            Ok(())
+            line.clear();
         }
-    });
-    // shutdown threads
-    stdout_thread.join().map_err(WorkerError::StdoutThreadJoin)??;
-    stderr_thread.join().map_err(WorkerError::StderrThreadJoin)??;
+        Ok::<_, WorkerError>(failure)
+    };
+    let (stdout_res, stderr_res, stdin_res) = tokio::join!(stdout_task, stderr_task, stdin_task);
+    stdout_res?;
+    let failure = stderr_res?;
+    stdin_res?;
     // shutdown process
-    match process.try_wait() {
-        Ok(None) => {},
-        Ok(Some(exit_status)) => match exit_status.code() {
+    match process.wait().await {
+        Ok(exit_code) => match exit_code {
             None => {},
             Some(0) => {},
             Some(code) => {
-                writeln!(&mut system_log_writer.lock().unwrap(), "[error] ffmpeg failed with bad code: {code:?}")
+                writeln!(&mut system_log_writer.lock().unwrap(), "[error] {} failed with bad code: {code:?}", transcoder.name())
                     .map_err(WorkerError::SystemWriteFail)?;
-                return Err(TranscodeError::LoggedFail);
+                return Err(failure.map(TranscodeError::Ffmpeg).unwrap_or(TranscodeError::LoggedFail));
             },
         },
         Err(err) => {
-            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] ffmpeg process failed to join: {err:?}")
+            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] {} process failed to join: {err:?}", transcoder.name())
                 .map_err(WorkerError::SystemWriteFail)?;
-            if let Err(err) = process.kill() {
-                writeln!(&mut system_log_writer.lock().unwrap(), "[warn] ffmpeg process failed to be killed: {err:?}")
+            if let Err(err) = process.start_kill() {
+                writeln!(&mut system_log_writer.lock().unwrap(), "[warn] {} process failed to be killed: {err:?}", transcoder.name())
                     .map_err(WorkerError::SystemWriteFail)?;
             }
         },
     }
-    if audio_path.exists() {
-        Ok(audio_path)
+    if !audio_path.exists() {
+        return Err(TranscodeError::MissingOutputFile(audio_path));
+    }
+    // NOTE: A pipelined transcode can finish before the download if ffmpeg stopped reading early
+    let _ = wait_for_download(&key.video_id, &download_state, &job_events, false).await?;
+    let (source_hash, source_duration_ms) = {
+        let entry = job_store.select_ytdlp_entry(&key.video_id)?.expect("Entry should exist");
+        (entry.source_hash, entry.duration_ms)
+    };
+    // NOTE: Record which source was used so the transcode is invalidated if the source is downloaded again
+    let _ = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+        entry.source_hash = source_hash;
+    })?;
+    // NOTE: ffmpeg can't determine the duration of a piped source so we use the one reported by yt-dlp
+    let expected_duration_ms = transcode_cache.get(&key)
+        .and_then(|state| state.lock().unwrap().source_duration_milliseconds)
+        .or(source_duration_ms)
+        .map(|duration| options.get_output_duration_ms(duration));
+    let record_silence_removed = |output_duration_ms: Option<u64>| {
+        let (Some(expected), Some(output)) = (expected_duration_ms, output_duration_ms) else {
+            return;
+        };
+        let transcode_state = transcode_cache.entry(key.clone()).or_default();
+        let mut state = transcode_state.lock().unwrap();
+        state.silence_removed_milliseconds = Some(expected.saturating_sub(output));
+        state.bump_revision();
+    };
+    // validate output since ffmpeg can exit successfully with a truncated or silent file
+    let file_size_bytes = std::fs::metadata(&audio_path).map(|m| m.len()).unwrap_or(0);
+    if file_size_bytes == 0 {
+        return Err(ProbeValidationError::EmptyFile.into());
+    }
+    let probe = match block_in_place(|| transcoder.probe_file(&audio_path)) {
+        Ok(probe) => probe,
+        Err(err) => {
+            // NOTE: ffprobe is optional so we only warn if it isn't available
+            writeln!(&mut system_log_writer.lock().unwrap(), "[warn] Skipping validation of output file: {err}")
+                .map_err(WorkerError::SystemWriteFail)?;
+            if options.trim_silence {
+                let transcode_duration_ms = transcode_cache.get(&key)
+                    .and_then(|state| state.lock().unwrap().transcode_duration_milliseconds);
+                record_silence_removed(transcode_duration_ms);
+            }
+            return Ok(());
+        },
+    };
+    writeln!(&mut system_log_writer.lock().unwrap(), "[info] Probed output file: {probe:?}")
+        .map_err(WorkerError::SystemWriteFail)?;
+    let _ = job_store.select_and_update_ffmpeg_entry(&key.video_id, key.audio_ext, key.variant.as_str(), |entry| {
+        entry.codec = probe.codec.clone();
+        entry.bitrate = probe.bitrate;
+        entry.duration_ms = probe.duration_ms;
+        entry.has_artwork = Some(probe.has_artwork);
+        entry.max_volume_db = probe.max_volume_db;
+    })?;
+    // NOTE: Removing silence shortens the output so it would look truncated
+    if options.trim_silence {
+        record_silence_removed(probe.duration_ms);
+        probe.validate(None)?;
     } else {
-        Err(TranscodeError::MissingOutputFile(audio_path))
+        probe.validate(expected_duration_ms)?;
     }
+    Ok(())
 }
@@ -1,16 +1,198 @@
-use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::database::VideoId;
+use crate::downloader::{Downloader, DownloadProgress, DownloadRequest, ParsedStdoutLine, ParsedStderrLine};
+use crate::error_code::ErrorCode;
+use crate::ytdlp_auth::YtdlpAuth;
+
+/// Tuning options for yt-dlp which can be set globally and overridden per request
+#[derive(Clone,Debug,Default,Deserialize,Serialize)]
+pub struct DownloadOptions {
+    pub concurrent_fragments: Option<u32>,
+    pub limit_rate: Option<String>,
+    pub retries: Option<u32>,
+    pub subtitle_lang: Option<String>,
+    /// Download this format from /get_formats instead of letting yt-dlp pick the best audio
+    pub format_id: Option<String>,
+    /// Download the dubbed audio track in this language (e.g. es) for videos with more than one
+    pub audio_lang: Option<String>,
+    /// Record livestreams from their start instead of rejecting them
+    pub live_from_start: Option<bool>,
+    /// Arguments of a yt-dlp extractor (e.g. youtube:player_client=ios) which must be allowed by the server
+    pub extractor_args: Option<String>,
+}
+
+#[derive(Clone,Debug,Error)]
+pub enum DownloadOptionsError {
+    #[error("Concurrent fragments must be between 1 and {max}: given={given}")]
+    InvalidConcurrentFragments { max: u32, given: u32 },
+    #[error("Limit rate must be a number with an optional K/M/G suffix: given={0}")]
+    InvalidLimitRate(String),
+    #[error("Subtitle language must be a language code such as en or en-US: given={0}")]
+    InvalidSubtitleLang(String),
+    #[error("Format id must be alphanumeric: given={0}")]
+    InvalidFormatId(String),
+    #[error("Audio language must be a language code such as es or en-US: given={0}")]
+    InvalidAudioLang(String),
+    #[error("Audio language can't be combined with a format id since the format already has a language")]
+    AudioLangWithFormatId,
+    #[error("Extractor args must be formatted as extractor:key=value[;key=value]: given={0}")]
+    InvalidExtractorArgs(String),
+    #[error("Extractor args aren't allowed by the server: given={0}")]
+    ExtractorArgsNotAllowed(String),
+}
+
+impl DownloadOptions {
+    pub const MAX_CONCURRENT_FRAGMENTS: u32 = 32;
+
+    /// Extractor args are passed straight to yt-dlp so only the ones allowed by the server are accepted
+    pub fn validate(&self, allowed_extractor_args: &[String]) -> Result<(), DownloadOptionsError> {
+        lazy_static! {
+            static ref FORMAT_ID_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_\-]{1,32}$").unwrap();
+        }
+        if let Some(total) = self.concurrent_fragments {
+            if total == 0 || total > Self::MAX_CONCURRENT_FRAGMENTS {
+                return Err(DownloadOptionsError::InvalidConcurrentFragments {
+                    max: Self::MAX_CONCURRENT_FRAGMENTS,
+                    given: total,
+                });
+            }
+        }
+        if let Some(ref rate) = self.limit_rate {
+            validate_limit_rate(rate)?;
+        }
+        if let Some(ref lang) = self.subtitle_lang {
+            if !is_language_code(lang) {
+                return Err(DownloadOptionsError::InvalidSubtitleLang(lang.clone()));
+            }
+        }
+        if let Some(ref format_id) = self.format_id {
+            if !FORMAT_ID_REGEX.is_match(format_id) {
+                return Err(DownloadOptionsError::InvalidFormatId(format_id.clone()));
+            }
+        }
+        if let Some(ref lang) = self.audio_lang {
+            validate_audio_lang(lang)?;
+            if self.format_id.is_some() {
+                return Err(DownloadOptionsError::AudioLangWithFormatId);
+            }
+        }
+        if let Some(ref extractor_args) = self.extractor_args {
+            validate_extractor_args(extractor_args)?;
+            if !allowed_extractor_args.contains(extractor_args) {
+                return Err(DownloadOptionsError::ExtractorArgsNotAllowed(extractor_args.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fields set in the override take precedence over our own
+    pub fn with_overrides(&self, overrides: &DownloadOptions) -> Self {
+        Self {
+            concurrent_fragments: overrides.concurrent_fragments.or(self.concurrent_fragments),
+            limit_rate: overrides.limit_rate.clone().or(self.limit_rate.clone()),
+            retries: overrides.retries.or(self.retries),
+            subtitle_lang: overrides.subtitle_lang.clone().or(self.subtitle_lang.clone()),
+            format_id: overrides.format_id.clone().or(self.format_id.clone()),
+            audio_lang: overrides.audio_lang.clone().or(self.audio_lang.clone()),
+            live_from_start: overrides.live_from_start.or(self.live_from_start),
+            extractor_args: overrides.extractor_args.clone().or(self.extractor_args.clone()),
+        }
+    }
+}
+
+fn is_language_code(lang: &str) -> bool {
+    lazy_static! {
+        static ref LANGUAGE_CODE_REGEX: Regex = Regex::new(r"^[a-zA-Z]{2,3}(?:-[a-zA-Z0-9]+)*$").unwrap();
+    }
+    LANGUAGE_CODE_REGEX.is_match(lang)
+}
+
+/// Checks the audio language is a language code (e.g. es or en-US) since it is part of the yt-dlp format selector
+pub fn validate_audio_lang(lang: &str) -> Result<(), DownloadOptionsError> {
+    if !is_language_code(lang) {
+        return Err(DownloadOptionsError::InvalidAudioLang(lang.to_owned()));
+    }
+    Ok(())
+}
+
+/// Checks the rate is a number of bytes per second with an optional K/M/G suffix (e.g. 2.5M)
+pub fn validate_limit_rate(rate: &str) -> Result<(), DownloadOptionsError> {
+    lazy_static! {
+        static ref LIMIT_RATE_REGEX: Regex = Regex::new(r"^\d+(?:\.\d+)?[KMG]?$").unwrap();
+    }
+    if !LIMIT_RATE_REGEX.is_match(rate) {
+        return Err(DownloadOptionsError::InvalidLimitRate(rate.to_owned()));
+    }
+    Ok(())
+}
+
+/// Checks the extractor args are formatted as extractor:key=value[;key=value] (e.g. youtube:player_client=ios,web)
+pub fn validate_extractor_args(extractor_args: &str) -> Result<(), DownloadOptionsError> {
+    lazy_static! {
+        static ref EXTRACTOR_ARGS_REGEX: Regex = Regex::new(
+            r"^[a-z0-9_]+:[a-zA-Z0-9_\-]+=[a-zA-Z0-9_,.\-]+(?:;[a-zA-Z0-9_\-]+=[a-zA-Z0-9_,.\-]+)*$"
+        ).unwrap();
+    }
+    if !EXTRACTOR_ARGS_REGEX.is_match(extractor_args) {
+        return Err(DownloadOptionsError::InvalidExtractorArgs(extractor_args.to_owned()));
+    }
+    Ok(())
+}
+
+/// Names downloads by their id unless a template is configured
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "%(id)s.%(ext)s";
+
+#[derive(Clone,Debug,Error)]
+pub enum OutputTemplateError {
+    #[error("Output template must contain %(id)s so downloads of different videos don't overwrite each other: {0}")]
+    MissingId(String),
+    #[error("Output template must end with .%(ext)s so the extension matches the downloaded format: {0}")]
+    MissingExtension(String),
+    #[error("Output template must be a relative path inside the download directory: {0}")]
+    OutsideDirectory(String),
+}
+
+/// Checks the yt-dlp output template (e.g. %(uploader)s/%(title)s [%(id)s].%(ext)s) which is relative to the download directory
+pub fn validate_output_template(template: &str) -> Result<(), OutputTemplateError> {
+    use std::path::Component;
+    if !template.contains("%(id)s") {
+        return Err(OutputTemplateError::MissingId(template.to_owned()));
+    }
+    if !template.ends_with(".%(ext)s") {
+        return Err(OutputTemplateError::MissingExtension(template.to_owned()));
+    }
+    let is_inside = Path::new(template).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    // NOTE: A type prefix such as subtitle: would apply the template to other files instead of the download
+    if !is_inside || template.contains(':') {
+        return Err(OutputTemplateError::OutsideDirectory(template.to_owned()));
+    }
+    Ok(())
+}
 
 // NOTE: The ytdlp cli output is not stable, but we can manually format certain outputs
 //       We will then do pattern matching on that controlled output
-pub fn get_ytdlp_arguments<'a>(url: &'a str, ffmpeg_binary_path: &'a str, output_format: &'a str) -> impl IntoIterator<Item=impl AsRef<OsStr> + 'a> {
-    [
+#[allow(clippy::too_many_arguments)]
+pub fn get_ytdlp_arguments(
+    url: &str, ffmpeg_binary_path: &str, output_format: &str, subtitle_output_format: &str, is_resume: bool,
+    proxy: Option<&str>, geo_bypass_country: Option<&str>, options: &DownloadOptions, auth: &YtdlpAuth,
+) -> Vec<String> {
+    // NOTE: Dubbed tracks are separate audio formats which yt-dlp selects by their language
+    let format = match (&options.format_id, &options.audio_lang) {
+        (Some(format_id), _) => format_id.clone(),
+        (None, Some(lang)) => format!("ba[language={lang}]"),
+        (None, None) => "bestaudio".to_owned(),
+    };
+    let mut args: Vec<String> = [
         url,
         "--extract-audio",
-        "--format", "bestaudio",
-        "--no-continue", // override existing files
+        "--format", format.as_str(),
+        // resume from .part file if interrupted otherwise override existing files
+        if is_resume { "--continue" } else { "--no-continue" },
         "--no-simulate", // avoid running simulation when changing templates
         "--ffmpeg-location", ffmpeg_binary_path,
         // format progress string
@@ -21,41 +203,107 @@ pub fn get_ytdlp_arguments<'a>(url: &'a str, ffmpeg_binary_path: &'a str, output
             "downloaded_bytes=%(progress.downloaded_bytes)d,total_bytes=%(progress.total_bytes)d,",
             "speed=%(progress.speed)d",
         ),
-        "--output", output_format, // "%(id)s.%(ext)s", // detect name of audio after command runs
+        "--output", output_format, // detect name of audio after command runs
         "--print", "@[download-path] %(filename)s",
         "--print", "before_dl:@[before-dl-path] %(filename)s",
         "--print", "pre_process:@[pre-process-path] %(filename)s",
         "--print", "post_process:@[post-process-path] %(filename)s",
         "--print", "after_move:@[after-move-path] %(filename)s",
+        "--print", "after_move:@[duration] %(duration)s",
+        "--print", "after_move:@[format-id] %(format_id)s",
         "--verbose", // print extra debug info to stderr
-    ]
+    ].iter().map(|&arg| arg.to_owned()).collect();
+    if let Some(proxy) = proxy {
+        args.extend(["--proxy".to_owned(), proxy.to_owned()]);
+    }
+    if let Some(country) = geo_bypass_country {
+        args.extend(["--geo-bypass-country".to_owned(), country.to_owned()]);
+    }
+    if let Some(total) = options.concurrent_fragments {
+        args.extend(["--concurrent-fragments".to_owned(), total.to_string()]);
+    }
+    if let Some(ref rate) = options.limit_rate {
+        args.extend(["--limit-rate".to_owned(), rate.clone()]);
+    }
+    if let Some(retries) = options.retries {
+        args.extend(["--retries".to_owned(), retries.to_string()]);
+    }
+    if options.live_from_start == Some(true) {
+        args.push("--live-from-start".to_owned());
+    }
+    args.extend(auth.get_arguments(options.extractor_args.as_deref()));
+    // NOTE: Subtitles are written to their own template as %(id)s.%(lang)s.vtt so they can be found without the download's name
+    if let Some(ref lang) = options.subtitle_lang {
+        args.extend(["--write-subs", "--sub-format", "vtt", "--sub-langs", lang.as_str()].map(str::to_owned));
+        args.extend(["--output".to_owned(), format!("subtitle:{subtitle_output_format}")]);
+    }
+    args
+}
+
+/// Default downloader which runs yt-dlp
+#[derive(Clone,Debug)]
+pub struct YtdlpDownloader {
+    binary: PathBuf,
 }
 
-#[derive(Clone,Copy,Debug,Default,Serialize)]
-pub struct DownloadProgress {
-    pub eta_seconds: Option<u64>,
-    pub elapsed_seconds: Option<u64>,
-    pub downloaded_bytes: Option<usize>,
-    pub total_bytes: Option<usize>,
-    pub speed_bytes: Option<usize>,
+impl YtdlpDownloader {
+    pub const NAME: &'static str = "yt-dlp";
+
+    pub fn new(binary: PathBuf) -> Self {
+        Self { binary }
+    }
 }
 
-const YOUTUBE_ID_REGEX: &str = r"[a-zA-Z0-9\\/.\-\_]+";
+impl Downloader for YtdlpDownloader {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn binary(&self) -> &Path {
+        self.binary.as_path()
+    }
+
+    fn get_arguments(&self, request: &DownloadRequest) -> Vec<String> {
+        get_ytdlp_arguments(
+            request.url,
+            request.ffmpeg_binary.to_str().unwrap(),
+            request.output_template.to_str().unwrap(),
+            request.subtitle_output_template.to_str().unwrap(),
+            request.is_resume,
+            request.proxy,
+            request.geo_bypass_country,
+            request.options,
+            request.auth,
+        )
+    }
+
+    fn parse_stdout_line(&self, line: &str) -> Option<ParsedStdoutLine> {
+        parse_stdout_line(line)
+    }
 
-#[derive(Debug)]
-pub enum ParsedStdoutLine {
-    DownloadProgress(DownloadProgress),
-    OutputPath(String),
+    fn parse_stderr_line(&self, line: &str) -> Option<ParsedStderrLine> {
+        parse_stderr_line(line)
+    }
 }
 
+const YOUTUBE_ID_REGEX: &str = r"[a-zA-Z0-9\\/.\-\_]+";
+// NOTE: Output templates can contain titles so paths are matched until the end of the line
+const FILE_PATH_REGEX: &str = r".+";
+
+// NOTE: Progress fields that yt-dlp doesn't know are printed as NA such as the total size of fragmented downloads
 pub fn parse_stdout_line(line: &str) -> Option<ParsedStdoutLine> {
     lazy_static! {
         static ref DOWNLOAD_PROGRESS_REGEX: Regex = Regex::new(
-            r"@\[progress\]\s+eta=(\d+)?,elapsed=(\d+)?,downloaded_bytes=(\d+),total_bytes=(\d+),speed=(\d+)?",
+            r"@\[progress\]\s+eta=(\d+|NA)?,elapsed=(\d+|NA)?,downloaded_bytes=(\d+|NA)?,total_bytes=(\d+|NA)?,speed=(\d+|NA)?",
         ).unwrap();
         static ref OUTPUT_PATH_REGEX: Regex = Regex::new(format!(
-            r"@\[after-move-path\]\s+({0})", YOUTUBE_ID_REGEX,
+            r"@\[after-move-path\]\s+({0})", FILE_PATH_REGEX,
         ).as_str()).unwrap();
+        static ref DOWNLOAD_PATH_REGEX: Regex = Regex::new(format!(
+            r"@\[before-dl-path\]\s+({0})", FILE_PATH_REGEX,
+        ).as_str()).unwrap();
+        static ref DURATION_REGEX: Regex = Regex::new(r"@\[duration\]\s+(\d+(?:\.\d+)?)").unwrap();
+        static ref FORMAT_ID_REGEX: Regex = Regex::new(r"@\[format-id\]\s+([a-zA-Z0-9_\-]+)").unwrap();
     }
     let line = line.trim();
     if let Some(captures) = DOWNLOAD_PROGRESS_REGEX.captures(line) {
@@ -77,28 +325,58 @@ pub fn parse_stdout_line(line: &str) -> Option<ParsedStdoutLine> {
         let filename: Option<String> = captures.get(1).map(|m| m.as_str().to_owned());
         return Some(ParsedStdoutLine::OutputPath(filename?));
     }
+    if let Some(captures) = DOWNLOAD_PATH_REGEX.captures(line) {
+        let filename: Option<String> = captures.get(1).map(|m| m.as_str().to_owned());
+        return Some(ParsedStdoutLine::DownloadPath(filename?));
+    }
+    if let Some(captures) = DURATION_REGEX.captures(line) {
+        let seconds: Option<f64> = captures.get(1).and_then(|m| m.as_str().parse().ok());
+        return Some(ParsedStdoutLine::DurationMilliseconds((seconds? * 1000.0) as u64));
+    }
+    if let Some(captures) = FORMAT_ID_REGEX.captures(line) {
+        let format_id: Option<String> = captures.get(1).map(|m| m.as_str().to_owned());
+        return Some(ParsedStdoutLine::FormatId(format_id?));
+    }
     None
 }
 
-#[derive(Clone,Debug)]
-pub enum ParsedStderrLine {
-    UsageError(String),
-    MissingVideo(String),
-    ExtractPath(String),
+/// Classifies the error messages printed by yt-dlp so clients know why a download failed
+pub fn classify_error_line(line: &str) -> Option<ErrorCode> {
+    lazy_static! {
+        static ref ERROR_PATTERNS: Vec<(Regex, ErrorCode)> = [
+            (r"(?i)private video", ErrorCode::PrivateVideo),
+            (r"(?i)live event will begin|premieres in", ErrorCode::UpcomingStream),
+            (r"(?i)not (?:made this video )?available in your country|geo.?restrict", ErrorCode::GeoBlocked),
+            (r"(?i)confirm your age|age.?restricted|inappropriate for some users", ErrorCode::AgeRestricted),
+            (r"(?i)members.?only|join this channel|sign in to confirm|login required|use --cookies", ErrorCode::LoginRequired),
+            (r"(?i)video unavailable|video is unavailable|has been removed|video is no longer available", ErrorCode::VideoUnavailable),
+            (r"(?i)http error 429|too many requests", ErrorCode::RateLimited),
+            (r"(?i)no space left on device|errno 28", ErrorCode::DiskFull),
+            // NOTE: A pinned format id or audio language that the video doesn't have
+            (r"(?i)requested format is not available", ErrorCode::InvalidRequest),
+            (
+                r"(?i)unable to download|name resolution|getaddrinfo|connection (?:refused|reset)|timed out|network is unreachable",
+                ErrorCode::NetworkError,
+            ),
+        ].into_iter().map(|(pattern, code)| (Regex::new(pattern).unwrap(), code)).collect();
+    }
+    // NOTE: yt-dlp also prints warnings for errors it recovered from so only fatal errors are classified
+    let message = line.strip_prefix("ERROR:")?;
+    ERROR_PATTERNS.iter().find(|(regex, _)| regex.is_match(message)).map(|(_, code)| *code)
 }
 
 pub fn parse_stderr_line(line: &str) -> Option<ParsedStderrLine> {
     lazy_static! {
         static ref USAGE_ERROR_REGEX: Regex = Regex::new(
-            r"yt-dlp.exe:\s+error:\s+(.+)"
+            r"yt-dlp(?:\.exe)?:\s+error:\s+(.+)"
         ).unwrap();
         static ref MISSING_VIDEO_REGEX: Regex = Regex::new(format!(
             r"ERROR:\s+\[youtube\]\s+({0}): Video unavailable", 
             YOUTUBE_ID_REGEX,
         ).as_str()).unwrap();
         static ref EXTRACT_PATH_REGEX: Regex = Regex::new(format!(
-            r"\[ExtractAudio\]\s*Destination:\s*({0})", 
-            YOUTUBE_ID_REGEX,
+            r"\[ExtractAudio\]\s*Destination:\s*({0})",
+            FILE_PATH_REGEX,
         ).as_str()).unwrap();
     }
     let line = line.trim();
@@ -117,5 +395,201 @@ pub fn parse_stderr_line(line: &str) -> Option<ParsedStderrLine> {
             return Some(ParsedStderrLine::ExtractPath(id.to_owned()));
         }
     }
-    None
+    classify_error_line(line).map(ParsedStderrLine::Failure)
+}
+
+/// Audio only format which can be pinned with DownloadOptions::format_id
+#[derive(Clone,Debug,Serialize)]
+pub struct AudioFormat {
+    pub format_id: String,
+    pub ext: Option<String>,
+    pub codec: Option<String>,
+    pub bitrate_kbps: Option<f64>,
+    pub sample_rate: Option<u64>,
+    pub filesize_bytes: Option<u64>,
+    pub note: Option<String>,
+    /// Language of the audio track which can be requested with DownloadOptions::audio_lang
+    pub language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VideoInfo {
+    #[serde(default)]
+    formats: Vec<FormatInfo>,
+}
+
+#[derive(Deserialize)]
+struct FormatInfo {
+    format_id: String,
+    ext: Option<String>,
+    acodec: Option<String>,
+    vcodec: Option<String>,
+    abr: Option<f64>,
+    asr: Option<u64>,
+    filesize: Option<u64>,
+    filesize_approx: Option<u64>,
+    format_note: Option<String>,
+    language: Option<String>,
+}
+
+#[derive(Debug,Error)]
+pub enum FormatListError {
+    #[error("Failed to run {binary}: {error:?}")]
+    Spawn { binary: String, error: std::io::Error },
+    #[error("yt-dlp failed with bad code: {code:?}, stderr={stderr}")]
+    BadExitCode { code: Option<i32>, stderr: String },
+    #[error("Failed to parse yt-dlp output: {0:?}")]
+    InvalidOutput(#[from] serde_json::Error),
+}
+
+pub fn get_formats_arguments(url: &str, proxy: Option<&str>, geo_bypass_country: Option<&str>, auth: &YtdlpAuth) -> Vec<String> {
+    let mut args: Vec<String> = [url, "--dump-single-json", "--no-playlist"].iter().map(|&arg| arg.to_owned()).collect();
+    if let Some(proxy) = proxy {
+        args.extend(["--proxy".to_owned(), proxy.to_owned()]);
+    }
+    if let Some(country) = geo_bypass_country {
+        args.extend(["--geo-bypass-country".to_owned(), country.to_owned()]);
+    }
+    args.extend(auth.get_arguments(None));
+    args
+}
+
+pub fn parse_audio_formats(output: &str) -> Result<Vec<AudioFormat>, serde_json::Error> {
+    let info: VideoInfo = serde_json::from_str(output)?;
+    let formats = info.formats.into_iter()
+        .filter(|format| format.vcodec.as_deref() == Some("none") && format.acodec.as_deref() != Some("none"))
+        .map(|format| AudioFormat {
+            format_id: format.format_id,
+            ext: format.ext,
+            codec: format.acodec,
+            bitrate_kbps: format.abr,
+            sample_rate: format.asr,
+            filesize_bytes: format.filesize.or(format.filesize_approx),
+            note: format.format_note,
+            language: format.language,
+        })
+        .collect();
+    Ok(formats)
+}
+
+/// Asks yt-dlp for the formats of a video without downloading it
+pub fn list_audio_formats(
+    ytdlp_binary: &Path, url: &str, proxy: Option<&str>, geo_bypass_country: Option<&str>, auth: &YtdlpAuth,
+) -> Result<Vec<AudioFormat>, FormatListError> {
+    let output = Command::new(ytdlp_binary)
+        .args(get_formats_arguments(url, proxy, geo_bypass_country, auth))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| FormatListError::Spawn { binary: ytdlp_binary.to_string_lossy().to_string(), error })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(FormatListError::BadExitCode { code: output.status.code(), stderr });
+    }
+    Ok(parse_audio_formats(String::from_utf8_lossy(&output.stdout).as_ref())?)
+}
+
+#[derive(Debug,Error)]
+pub enum PlaylistError {
+    #[error("Failed to run {binary}: {error:?}")]
+    Spawn { binary: String, error: std::io::Error },
+    #[error("yt-dlp failed with bad code: {code:?}, stderr={stderr}")]
+    BadExitCode { code: Option<i32>, stderr: String },
+}
+
+/// Only prints the id of each entry so the videos themselves aren't fetched
+pub fn get_playlist_arguments(
+    url: &str, playlist_end: Option<usize>, proxy: Option<&str>, geo_bypass_country: Option<&str>,
+) -> Vec<String> {
+    let mut args: Vec<String> = [url, "--flat-playlist", "--print", "id", "--no-warnings"]
+        .iter().map(|&arg| arg.to_owned()).collect();
+    if let Some(playlist_end) = playlist_end {
+        args.extend(["--playlist-end".to_owned(), playlist_end.to_string()]);
+    }
+    if let Some(proxy) = proxy {
+        args.extend(["--proxy".to_owned(), proxy.to_owned()]);
+    }
+    if let Some(country) = geo_bypass_country {
+        args.extend(["--geo-bypass-country".to_owned(), country.to_owned()]);
+    }
+    args
+}
+
+// NOTE: Entries that aren't videos (e.g. channel tabs or nested playlists) don't have a valid video id
+pub fn parse_playlist_video_ids(output: &str) -> Vec<VideoId> {
+    output.lines().filter_map(|line| VideoId::try_new(line.trim()).ok()).collect()
+}
+
+/// Asks yt-dlp for the videos in a channel or playlist without downloading them
+pub fn list_playlist_video_ids(
+    ytdlp_binary: &Path, url: &str, playlist_end: Option<usize>, proxy: Option<&str>, geo_bypass_country: Option<&str>,
+) -> Result<Vec<VideoId>, PlaylistError> {
+    let output = Command::new(ytdlp_binary)
+        .args(get_playlist_arguments(url, playlist_end, proxy, geo_bypass_country))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| PlaylistError::Spawn { binary: ytdlp_binary.to_string_lossy().to_string(), error })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(PlaylistError::BadExitCode { code: output.status.code(), stderr });
+    }
+    Ok(parse_playlist_video_ids(String::from_utf8_lossy(&output.stdout).as_ref()))
+}
+
+/// Upload listed from a channel without fetching the video itself
+#[derive(Clone,Debug,Serialize)]
+pub struct ChannelEntry {
+    pub video_id: VideoId,
+    pub title: Option<String>,
+    pub duration_seconds: Option<u64>,
+    /// Formatted as YYYYMMDD
+    pub upload_date: Option<String>,
+}
+
+// NOTE: Flat playlists don't include the upload date unless yt-dlp estimates it from the "x days ago" text
+//       Titles are printed last since they are the only field that can contain the separator
+pub fn get_channel_arguments(url: &str, proxy: Option<&str>, geo_bypass_country: Option<&str>) -> Vec<String> {
+    let mut args: Vec<String> = [
+        url, "--flat-playlist", "--extractor-args", "youtube:approximate_date",
+        "--print", "%(id)s\t%(upload_date)s\t%(duration)s\t%(title)s", "--no-warnings",
+    ].iter().map(|&arg| arg.to_owned()).collect();
+    if let Some(proxy) = proxy {
+        args.extend(["--proxy".to_owned(), proxy.to_owned()]);
+    }
+    if let Some(country) = geo_bypass_country {
+        args.extend(["--geo-bypass-country".to_owned(), country.to_owned()]);
+    }
+    args
+}
+
+// NOTE: yt-dlp prints NA for missing fields
+pub fn parse_channel_entries(output: &str) -> Vec<ChannelEntry> {
+    fn parse_field(field: Option<&str>) -> Option<&str> {
+        field.map(|field| field.trim()).filter(|field| !field.is_empty() && *field != "NA")
+    }
+    output.lines().filter_map(|line| {
+        let mut fields = line.splitn(4, '\t');
+        let video_id = VideoId::try_new(fields.next()?.trim()).ok()?;
+        let upload_date = parse_field(fields.next()).map(|date| date.to_owned());
+        let duration_seconds = parse_field(fields.next())
+            .and_then(|duration| duration.parse::<f64>().ok())
+            .map(|duration| duration as u64);
+        let title = parse_field(fields.next()).map(|title| title.to_owned());
+        Some(ChannelEntry { video_id, title, duration_seconds, upload_date })
+    }).collect()
+}
+
+/// Asks yt-dlp for every upload of a channel, newest first
+pub fn list_channel_entries(
+    ytdlp_binary: &Path, url: &str, proxy: Option<&str>, geo_bypass_country: Option<&str>,
+) -> Result<Vec<ChannelEntry>, PlaylistError> {
+    let output = Command::new(ytdlp_binary)
+        .args(get_channel_arguments(url, proxy, geo_bypass_country))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| PlaylistError::Spawn { binary: ytdlp_binary.to_string_lossy().to_string(), error })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(PlaylistError::BadExitCode { code: output.status.code(), stderr });
+    }
+    Ok(parse_channel_entries(String::from_utf8_lossy(&output.stdout).as_ref()))
 }
@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use thiserror::Error;
+use crate::database::VideoId;
+use crate::error_code::ErrorCode;
+use crate::ytdlp::{classify_error_line, list_audio_formats, FormatListError};
+
+/// Age-restricted video used by the diagnostics route unless another one is given
+pub const DEFAULT_AGE_RESTRICTED_VIDEO_ID: &str = "HtVdAasjOgU";
+// NOTE: The youtube-oauth2 plugin logs in when given this username with an empty password
+const OAUTH_USERNAME: &str = "oauth";
+const AUTH_HINT: &str = "Age-restricted videos need --cookies-path, --po-token, --po-token-provider-url or --ytdlp-oauth";
+
+#[derive(Debug,Error)]
+pub enum YtdlpAuthError {
+    #[error("PO token must be formatted as <client>.<context>+<token> (e.g. web.gvs+<token>): {0}")]
+    InvalidPoToken(String),
+    #[error("PO token provider must be a http url: {0}")]
+    InvalidProviderUrl(String),
+    #[error("Cookies file doesn't exist: {0}")]
+    MissingCookies(PathBuf),
+    #[error("Plugin directory doesn't exist: {0}")]
+    MissingPluginDir(PathBuf),
+}
+
+/// Workarounds that let yt-dlp download videos which YouTube only shows to logged in or verified clients
+#[derive(Clone,Default)]
+pub struct YtdlpAuth {
+    /// Proof of origin token for a client and context (e.g. web.gvs+<token>) passed to the youtube extractor
+    pub po_token: Option<String>,
+    /// Url of a bgutil provider which generates a PO token for every request, needs its yt-dlp plugin
+    pub po_token_provider_url: Option<String>,
+    /// Cookies exported from a browser that is logged in to an account which can view age-restricted videos
+    pub cookies_path: Option<PathBuf>,
+    /// Log in with the youtube-oauth2 plugin, which prints a device code to the download logs the first time
+    pub oauth: bool,
+    /// Directories searched for yt-dlp plugins such as the PO token provider and OAuth plugin
+    pub plugin_dirs: Vec<PathBuf>,
+}
+
+// NOTE: The config is logged on startup so the PO token is left out
+impl std::fmt::Debug for YtdlpAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("YtdlpAuth")
+            .field("po_token", &self.po_token.is_some())
+            .field("po_token_provider_url", &self.po_token_provider_url)
+            .field("cookies_path", &self.cookies_path)
+            .field("oauth", &self.oauth)
+            .field("plugin_dirs", &self.plugin_dirs)
+            .finish()
+    }
+}
+
+impl YtdlpAuth {
+    pub fn validate(&self) -> Result<(), YtdlpAuthError> {
+        lazy_static! {
+            static ref PO_TOKEN_REGEX: Regex = Regex::new(r"^[a-z_]+\.[a-z]+\+[a-zA-Z0-9_\-=%]+$").unwrap();
+        }
+        if let Some(ref po_token) = self.po_token {
+            if !PO_TOKEN_REGEX.is_match(po_token) {
+                // NOTE: Only the client and context are shown so the token isn't logged
+                let prefix = po_token.split_once('+').map(|(prefix, _)| prefix).unwrap_or_default();
+                return Err(YtdlpAuthError::InvalidPoToken(format!("{prefix}+...")));
+            }
+        }
+        if let Some(ref url) = self.po_token_provider_url {
+            let is_valid_url = reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some());
+            // NOTE: The url is passed as an extractor arg which can't contain these separators
+            if !is_valid_url || url.contains([';', ',']) {
+                return Err(YtdlpAuthError::InvalidProviderUrl(url.clone()));
+            }
+        }
+        if let Some(ref path) = self.cookies_path {
+            if !path.is_file() {
+                return Err(YtdlpAuthError::MissingCookies(path.clone()));
+            }
+        }
+        if let Some(path) = self.plugin_dirs.iter().find(|path| !path.is_dir()) {
+            return Err(YtdlpAuthError::MissingPluginDir(path.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.po_token.is_some() || self.po_token_provider_url.is_some() || self.cookies_path.is_some() || self.oauth
+    }
+
+    /// Values of --extractor-args where the PO token is added to the youtube args of the request
+    // NOTE: yt-dlp only keeps the last args given to an extractor so they are merged instead of passed twice
+    fn get_extractor_args(&self, extractor_args: Option<&str>) -> Vec<String> {
+        let mut values = Vec::new();
+        let po_token = self.po_token.as_ref().map(|po_token| format!("po_token={po_token}"));
+        match (extractor_args, po_token) {
+            (Some(args), Some(po_token)) if args.starts_with("youtube:") => values.push(format!("{args};{po_token}")),
+            (args, po_token) => {
+                values.extend(args.map(|args| args.to_owned()));
+                values.extend(po_token.map(|po_token| format!("youtube:{po_token}")));
+            },
+        }
+        if let Some(ref url) = self.po_token_provider_url {
+            values.push(format!("youtubepot-bgutilhttp:base_url={url}"));
+        }
+        values
+    }
+
+    /// Arguments for yt-dlp, where the extractor args of the request are passed along with our own
+    pub fn get_arguments(&self, extractor_args: Option<&str>) -> Vec<String> {
+        let mut args = Vec::new();
+        for plugin_dir in self.plugin_dirs.iter() {
+            args.extend(["--plugin-dirs".to_owned(), plugin_dir.to_string_lossy().to_string()]);
+        }
+        if let Some(ref path) = self.cookies_path {
+            args.extend(["--cookies".to_owned(), path.to_string_lossy().to_string()]);
+        }
+        if self.oauth {
+            args.extend(["--username", OAUTH_USERNAME, "--password", ""].map(str::to_owned));
+        }
+        for value in self.get_extractor_args(extractor_args) {
+            args.extend(["--extractor-args".to_owned(), value]);
+        }
+        args
+    }
+
+    /// Hint written to the logs when a download fails because YouTube wants a logged in client
+    pub fn get_failure_hint(&self, code: ErrorCode) -> Option<&'static str> {
+        let is_auth_failure = matches!(code, ErrorCode::AgeRestricted | ErrorCode::LoginRequired);
+        (is_auth_failure && !self.is_configured()).then_some(AUTH_HINT)
+    }
+}
+
+/// Which workarounds are configured without revealing the PO token
+#[derive(Clone,Debug,Serialize)]
+pub struct YtdlpAuthSummary {
+    pub po_token: bool,
+    pub po_token_provider_url: Option<String>,
+    pub cookies: bool,
+    pub oauth: bool,
+    pub plugin_dirs: Vec<String>,
+}
+
+/// Result of fetching an age-restricted video with the configured workarounds
+#[derive(Clone,Debug,Serialize)]
+pub struct AgeRestrictedCheck {
+    pub video_id: String,
+    pub is_success: bool,
+    /// Why yt-dlp couldn't fetch the video (e.g. age_restricted or login_required)
+    pub code: Option<ErrorCode>,
+    /// Last error printed by yt-dlp
+    pub error: Option<String>,
+    pub hint: Option<&'static str>,
+    pub auth: YtdlpAuthSummary,
+}
+
+/// Lists the formats of an age-restricted video which only succeeds if yt-dlp is allowed to view it
+pub fn check_age_restricted(
+    ytdlp_binary: &Path, video_id: &VideoId, proxy: Option<&str>, geo_bypass_country: Option<&str>, auth: &YtdlpAuth,
+) -> AgeRestrictedCheck {
+    let url = format!("https://www.youtube.com/watch?v={0}", video_id.as_str());
+    let (code, error) = match list_audio_formats(ytdlp_binary, url.as_str(), proxy, geo_bypass_country, auth) {
+        Ok(_) => (None, None),
+        Err(FormatListError::BadExitCode { stderr, .. }) => {
+            let code = stderr.lines().find_map(classify_error_line).unwrap_or(ErrorCode::YtdlpFailed);
+            let error = stderr.lines().rfind(|line| line.starts_with("ERROR:")).unwrap_or(stderr.as_str()).to_owned();
+            (Some(code), Some(error))
+        },
+        Err(err) => (Some(ErrorCode::InternalError), Some(err.to_string())),
+    };
+    AgeRestrictedCheck {
+        video_id: video_id.as_str().to_owned(),
+        is_success: code.is_none(),
+        code,
+        error,
+        hint: code.and_then(|code| auth.get_failure_hint(code)),
+        auth: YtdlpAuthSummary {
+            po_token: auth.po_token.is_some(),
+            po_token_provider_url: auth.po_token_provider_url.clone(),
+            cookies: auth.cookies_path.is_some(),
+            oauth: auth.oauth,
+            plugin_dirs: auth.plugin_dirs.iter().map(|path| path.to_string_lossy().to_string()).collect(),
+        },
+    }
+}
@@ -1,4 +1,5 @@
-const BASE_URL = `http://${window.location.host}`;
+// NOTE: Resolved from where this script is served so the api is found when hosted under a base path
+const BASE_URL = new URL(".", import.meta.url).href.replace(/\/$/, "");
 const API_URL = `${BASE_URL}/api/v1`;
 
 export const WorkerStatus = Object.freeze({
@@ -9,20 +10,68 @@ export const WorkerStatus = Object.freeze({
   Failed: "failed",
 });
 
+// Messages for error codes returned by the server, unknown codes fall back to the raw error
+const ERROR_CODE_MESSAGES = Object.freeze({
+  video_unavailable: "Video is unavailable or has been removed",
+  private_video: "Video is private",
+  geo_blocked: "Video is not available in the server's country",
+  age_restricted: "Video is age restricted and requires signing in",
+  login_required: "Video requires signing in to YouTube",
+  rate_limited: "YouTube is rate limiting the server, try again later",
+  network_error: "Server failed to connect to YouTube",
+  disk_full: "Server ran out of disk space",
+  invalid_source: "Downloaded file is corrupt",
+  unsupported_codec: "Audio format isn't supported by the server's ffmpeg",
+  permission_denied: "Server doesn't have permission to write the file",
+  storage_failed: "Failed to upload to storage",
+  live_stream: "Video is a livestream, enable recording from the start to download it",
+  upcoming_stream: "Livestream or premiere hasn't started yet",
+  ytdlp_failed: "Download failed",
+  ffmpeg_failed: "Transcode failed",
+  interrupted: "Server restarted during the download, request it again to resume",
+  quota_exceeded: "Your quota has been exceeded",
+  queue_full: "Server is busy, try again later",
+});
+
+export const get_error_message = (code, fallback) => {
+  return ERROR_CODE_MESSAGES[code] ?? fallback;
+}
+
 export class TranscodeApi {
   static get_download_link = (id, ext, name) => {
     let param = encodeURIComponent(name);
     return `${API_URL}/get_download_link/${id}/${ext}?name=${param}`;
   }
 
-  static get_downloads = async () => {
-    let response = await fetch(`${API_URL}/get_downloads`);
+  static get_stream_link = (id, ext) => {
+    return `${API_URL}/stream/${id}/${ext}`;
+  }
+
+  // files are formatted as video_id.ext or video_id.variant.ext
+  static get_archive_link = (files, name) => {
+    let files_param = encodeURIComponent(files.join(","));
+    let name_param = encodeURIComponent(name);
+    return `${API_URL}/get_archive?files=${files_param}&name=${name_param}`;
+  }
+
+  // filter is { status, since_unix_time, before_unix_time } where each field is optional
+  static get_downloads = async (filter = {}) => {
+    let params = new URLSearchParams(filter);
+    let response = await fetch(`${API_URL}/get_downloads?${params}`);
     if (!response.ok) throw response;
     return await response.json();
   }
 
-  static get_transcodes = async () => {
-    let response = await fetch(`${API_URL}/get_transcodes`);
+  // filter is { status, audio_ext, since_unix_time, before_unix_time } where each field is optional
+  static get_transcodes = async (filter = {}) => {
+    let params = new URLSearchParams(filter);
+    let response = await fetch(`${API_URL}/get_transcodes?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_library = async () => {
+    let response = await fetch(`${API_URL}/get_library`);
     if (!response.ok) throw response;
     return await response.json();
   }
@@ -39,8 +88,76 @@ export class TranscodeApi {
     return await response.json();
   }
 
-  static request_transcode = async (id, format) => {
-    let response = await fetch(`${API_URL}/request_transcode/${id}/${format}`);
+  // status is one of valid, mismatch, missing or unrecorded
+  static verify_download = async (id) => {
+    let response = await fetch(`${API_URL}/verify_download/${id}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static verify_transcode = async (id, ext) => {
+    let response = await fetch(`${API_URL}/verify/${id}/${ext}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_job = async (job_id) => {
+    let response = await fetch(`${API_URL}/get_job/${job_id}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_waveform = async (id, ext) => {
+    let response = await fetch(`${API_URL}/get_waveform/${id}/${ext}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // options are the same as for estimate_transcode along with download options, e.g. { audio_lang: "es" }
+  static request_transcode = async (id, format, options = {}) => {
+    let params = new URLSearchParams();
+    for (let [key, value] of Object.entries(options)) {
+      if (value !== undefined && value !== null && value !== "") params.append(key, value);
+    }
+    let response = await fetch(`${API_URL}/request_transcode/${id}/${format}?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // url can be any youtube link, clip trims the transcode to its t= start time
+  static request_transcode_url = async (url, format, clip = false) => {
+    let params = new URLSearchParams({ url, clip });
+    let response = await fetch(`${API_URL}/request_transcode_url/${format}?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // returns { video_id, start_ms, end_ms, playlist_id } of a youtube link
+  static resolve_url = async (url) => {
+    let params = new URLSearchParams({ url });
+    let response = await fetch(`${API_URL}/resolve_url?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // encodes a finished transcode again, replacing its output
+  static retranscode = async (id, format, options = {}) => {
+    let params = new URLSearchParams({ force: true });
+    for (let [key, value] of Object.entries(options)) {
+      if (value !== undefined && value !== null && value !== "") params.append(key, value);
+    }
+    let response = await fetch(`${API_URL}/retranscode/${id}/${format}?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // options are the same as for request_transcode, e.g. { bitrate_kbps: 192, trim_start_ms: 0, channels: 1 }
+  static estimate_transcode = async (id, format, options = {}) => {
+    let params = new URLSearchParams();
+    for (let [key, value] of Object.entries(options)) {
+      if (value !== undefined && value !== null && value !== "") params.append(key, value);
+    }
+    let response = await fetch(`${API_URL}/estimate_transcode/${id}/${format}?${params}`);
     if (!response.ok) throw response;
     return await response.json();
   }
@@ -57,6 +174,18 @@ export class TranscodeApi {
     return await response.json();
   }
 
+  static get_trash = async () => {
+    let response = await fetch(`${API_URL}/get_trash`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static restore_trash = async (trash_id) => {
+    let response = await fetch(`${API_URL}/restore/${trash_id}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
   static get_download_progress = async (id) => {
     let response = await fetch(`${API_URL}/get_download_state/${id}`);
     if (!response.ok) throw response;
@@ -69,6 +198,173 @@ export class TranscodeApi {
     return await response.json();
   }
 
+  // combined status and progress of the download and transcode
+  static get_job_progress = async (id, format, variant = "") => {
+    let params = new URLSearchParams({ variant });
+    let response = await fetch(`${API_URL}/get_job_state/${id}/${format}?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_subscriptions = async () => {
+    let response = await fetch(`${API_URL}/subscriptions`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static create_subscription = async (url, format) => {
+    let param = encodeURIComponent(url);
+    let response = await fetch(`${API_URL}/subscriptions/create/${format}?url=${param}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static delete_subscription = async (id) => {
+    let response = await fetch(`${API_URL}/subscriptions/${id}/delete`);
+    if (!response.ok) throw response;
+  }
+
+  // filters can contain date_after, date_before, min_duration_seconds, title_regex and max_count
+  static request_channel = async (channel_id, format, filters = {}) => {
+    let params = new URLSearchParams();
+    for (let [key, value] of Object.entries(filters)) {
+      if (value !== undefined && value !== null && value !== "") params.append(key, value);
+    }
+    let response = await fetch(`${API_URL}/request_channel/${encodeURIComponent(channel_id)}/${format}?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_channel_jobs = async () => {
+    let response = await fetch(`${API_URL}/channel_jobs`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_channel_job = async (id) => {
+    let response = await fetch(`${API_URL}/channel_jobs/${id}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static login = async (username, password) => {
+    let response = await fetch(`${API_URL}/auth/login`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ username, password }),
+    });
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static logout = async () => {
+    let response = await fetch(`${API_URL}/auth/logout`);
+    if (!response.ok) throw response;
+  }
+
+  static get_me = async () => {
+    let response = await fetch(`${API_URL}/me`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_quota = async () => {
+    let response = await fetch(`${API_URL}/me/quota`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_stats = async () => {
+    let response = await fetch(`${API_URL}/stats`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_system_info = async () => {
+    let response = await fetch(`${API_URL}/system_info`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_admin_stats = async (days) => {
+    let response = await fetch(`${API_URL}/admin/stats?days=${days}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_admin_tasks = async () => {
+    let response = await fetch(`${API_URL}/admin/tasks`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // task is one of retry_failed_downloads, purge_old_logs or refresh_stale_metadata
+  static run_admin_task = async (task) => {
+    let response = await fetch(`${API_URL}/admin/tasks/${task}/run`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_admin_queue = async () => {
+    let response = await fetch(`${API_URL}/admin/queue`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // Queued jobs don't start until the queue is resumed
+  static pause_admin_queue = async () => {
+    let response = await fetch(`${API_URL}/admin/queue/pause`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static resume_admin_queue = async () => {
+    let response = await fetch(`${API_URL}/admin/queue/resume`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  static get_admin_bandwidth = async () => {
+    let response = await fetch(`${API_URL}/admin/bandwidth`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // windows are formatted as HH:MM-HH:MM=RATE in UTC (e.g. 08:00-23:00=2M)
+  static set_admin_bandwidth = async (windows) => {
+    let response = await fetch(`${API_URL}/admin/bandwidth`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ windows }),
+    });
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // fetches an age-restricted video to test the configured cookies, PO token or OAuth plugin
+  static check_admin_age_restricted = async (video_id) => {
+    let params = new URLSearchParams();
+    if (video_id) params.append("video_id", video_id);
+    let response = await fetch(`${API_URL}/admin/diagnostics/age_restricted?${params}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
+  // Opening the link converts the video of the url and redirects to its progress
+  static get_quick_add_link = (url, format, clip = false) => {
+    let params = new URLSearchParams({ url });
+    if (format) params.append("format", format);
+    if (clip) params.append("clip", true);
+    return `${API_URL}/quick_add?${params}`;
+  }
+
+  // audio only formats of the video, dubbed tracks have a language that can be given as audio_lang
+  static get_formats = async (id) => {
+    let response = await fetch(`${API_URL}/get_formats/${id}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
+
   static get_metadata_link = (id) => {
     return `${API_URL}/get_metadata/${id}`;
   }
@@ -82,4 +378,11 @@ export class TranscodeApi {
     }
     return data.items[0];
   }
+
+  // retag encodes the finished transcodes of the video again with the new metadata
+  static refresh_metadata = async (id, retag = false) => {
+    let response = await fetch(`${API_URL}/refresh_metadata/${id}?retag=${retag}`);
+    if (!response.ok) throw response;
+    return await response.json();
+  }
 }
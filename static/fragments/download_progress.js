@@ -1,4 +1,4 @@
-import { WorkerStatus } from "../api.js"
+import { WorkerStatus, get_error_message } from "../api.js"
 import { 
   convert_to_short_standard_prefix, convert_dhms_to_string, convert_seconds_to_dhms,
   unix_time_to_string,
@@ -55,6 +55,9 @@ export const DownloadProgress = {
           };
         };
         case WorkerStatus.Running: {
+          if (this.progress.is_live_recording) {
+            return { width: 100, class: 'bg-danger', text: "Live" };
+          }
           let percentage = 0;
           if (this.progress.downloaded_bytes !== null) {
             percentage = this.progress.downloaded_bytes / this.progress.total_bytes * 100;
@@ -66,16 +69,22 @@ export const DownloadProgress = {
     },
     subtitle_text() {
       if (this.progress == null || this.progress?.file_cached) return null;
-      if (this.progress.worker_status == WorkerStatus.Failed) return this.progress.fail_reason;
+      if (this.progress.worker_status == WorkerStatus.Failed) return get_error_message(this.progress.fail_code, this.progress.fail_reason);
       if (this.progress.downloaded_bytes == null) {
         return (this.progress.worker_status == WorkerStatus.Finished) ? null : "Waiting for download to start";
       }
 
       let [curr_bytes, curr_bytes_unit] = convert_to_short_standard_prefix(this.progress.downloaded_bytes);
+      if (this.progress.is_live_recording) {
+        let text_elapsed = convert_dhms_to_string(convert_seconds_to_dhms(this.progress.elapsed_seconds ?? 0));
+        return `Recording livestream for ${text_elapsed} - ${curr_bytes.toFixed(2)}${curr_bytes_unit}B`;
+      }
       let [total_bytes, total_bytes_unit] = convert_to_short_standard_prefix(this.progress.total_bytes);
       let text_prediction = undefined;
       if (this.progress.eta_seconds !== null) {
-        let [speed_bytes, speed_bytes_unit] = convert_to_short_standard_prefix(this.progress.speed_bytes);
+        let [speed_bytes, speed_bytes_unit] = convert_to_short_standard_prefix(
+          this.progress.smoothed_speed_bytes ?? this.progress.speed_bytes
+        );
         let text_speed = `${speed_bytes.toFixed(2)}${speed_bytes_unit}B/s`;
         let text_eta = `ETA ${convert_dhms_to_string(convert_seconds_to_dhms(this.progress.eta_seconds))}`;
         text_prediction = `@ ${text_speed} - (${text_eta})`;
@@ -104,7 +113,9 @@ export const DownloadProgress = {
       }
       if (this.progress.eta_seconds !== null) {
         table.eta = convert_dhms_to_string(convert_seconds_to_dhms(this.progress.eta_seconds));
-        let [speed_bytes, speed_bytes_unit] = convert_to_short_standard_prefix(this.progress.speed_bytes);
+        let [speed_bytes, speed_bytes_unit] = convert_to_short_standard_prefix(
+          this.progress.smoothed_speed_bytes ?? this.progress.speed_bytes
+        );
         table.download_speed = `${speed_bytes.toFixed(2)} ${speed_bytes_unit}B/s`;
       }
       return table;
@@ -1,4 +1,4 @@
-import { WorkerStatus } from "../api.js"
+import { WorkerStatus, get_error_message } from "../api.js"
 import { 
   convert_to_short_standard_prefix, convert_seconds_to_dhms, convert_dhms_to_string,
   unix_time_to_string,
@@ -67,7 +67,7 @@ export const TranscodeProgress = {
     },
     subtitle_text() {
       if (this.progress == null || this.progress?.file_cached) return null;
-      if (this.progress.worker_status == WorkerStatus.Failed) return this.progress.fail_reason;
+      if (this.progress.worker_status == WorkerStatus.Failed) return get_error_message(this.progress.fail_code, this.progress.fail_reason);
       if (this.progress.transcode_duration_milliseconds == null) {
         return (this.progress.worker_status == WorkerStatus.Finished) ? null : "Waiting for transcode to start";
       }
@@ -76,11 +76,16 @@ export const TranscodeProgress = {
       // let [speed_bits, speed_bits_unit] = convert_to_short_standard_prefix(this.progress.transcode_speed_bits);
       // speed_bits = Number(speed_bits).toFixed(2);
 
-      // estimate eta given elapsed time and percentage
+      // use server eta otherwise estimate eta given elapsed time and percentage
       let time_elapsed_seconds = this.progress.end_time_unix-this.progress.start_time_unix;
-      let percentage = this.progress.transcode_duration_milliseconds / this.progress.source_duration_milliseconds;
-      let remaining_percentage = 1 - percentage;
-      let eta_seconds = (time_elapsed_seconds/percentage)*remaining_percentage;
+      let eta_seconds = undefined;
+      if (this.progress.eta_milliseconds != null) {
+        eta_seconds = this.progress.eta_milliseconds / 1000;
+      } else {
+        let percentage = this.progress.transcode_duration_milliseconds / this.progress.source_duration_milliseconds;
+        let remaining_percentage = 1 - percentage;
+        eta_seconds = (time_elapsed_seconds/percentage)*remaining_percentage;
+      }
 
       // NOTE: This is now garbage because the transcode size gives erroneous values when embedding thumbnail
       // estimate size of final file
@@ -131,6 +136,9 @@ export const TranscodeProgress = {
         table.transcode_bitrate = `${bitrate.toFixed(2)} ${bitrate_units}b/s`;
         table.transcode_speed_factor = this.progress.transcode_speed_factor.toFixed(2);
       }
+      if (this.progress.eta_milliseconds != null) {
+        table.eta = convert_dhms_to_string(convert_seconds_to_dhms(this.progress.eta_milliseconds/1000));
+      }
       return table;
     },
   },
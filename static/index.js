@@ -4,7 +4,7 @@ import { Column, ColumnType, Table } from "./fragments/table.js";
 import { DownloadProgress } from "./fragments/download_progress.js";
 import { TranscodeProgress } from "./fragments/transcode_progress.js";
 import { Metadata } from "./fragments/metadata.js";
-import { unix_time_to_string, extract_youtube_video_id, sanitise_to_filepath } from "./util.js";
+import { unix_time_to_string, extract_youtube_video_id, sanitise_to_filepath, file_size_to_string } from "./util.js";
 
 const get_cache_key = (video_id, audio_ext) => `${video_id}.${audio_ext}`;
 
@@ -44,6 +44,7 @@ export let create_app = () => createApp({
         new Column("video_id", true, { "type": "text", ignore_case: false }, "Id", ColumnType.TEXT),
         new Column("status", true, { "type": "text", ignore_case: false }, "Status", ColumnType.TEXT),
         new Column("unix_time", true, null, "Date", ColumnType.DATE, unix_time_to_string),
+        new Column("file_size_bytes", true, null, "Size", ColumnType.TEXT, file_size_to_string),
         new Column("audio_path", false, null, "Audio", ColumnType.LINK),
         new Column("stdout_log_path", false, null, "Stdout", ColumnType.LINK),
         new Column("stderr_log_path", false, null, "Stderr", ColumnType.LINK),
@@ -54,6 +55,7 @@ export let create_app = () => createApp({
         new Column("audio_ext", true, { "type": "text", ignore_case: false }, "Ext", ColumnType.TEXT),
         new Column("status", true, { "type": "text", ignore_case: false }, "Status", ColumnType.TEXT),
         new Column("unix_time", true, null, "Date", ColumnType.DATE, unix_time_to_string),
+        new Column("file_size_bytes", true, null, "Size", ColumnType.TEXT, file_size_to_string),
         new Column("audio_path", false, null, "Audio", ColumnType.LINK),
         new Column("stdout_log_path", false, null, "Stdout", ColumnType.LINK),
         new Column("stderr_log_path", false, null, "Stderr", ColumnType.LINK),
@@ -78,6 +80,25 @@ export let create_app = () => createApp({
       if (!is_ready) return null;
       return TranscodeApi.get_download_link(video_id, audio_ext, this.focused_transcode.download_name);
     },
+    stream_link() {
+      let video_id = this.focused_transcode.video_id;
+      let audio_ext = this.focused_transcode.audio_ext;
+      if (video_id === null) return null;
+      let key = get_cache_key(video_id, audio_ext);
+      let is_ready = this.transcode_state_cache[key]?.status == WorkerStatus.Finished;
+      if (!is_ready) return null;
+      return TranscodeApi.get_stream_link(video_id, audio_ext);
+    },
+    archive_link() {
+      let files = Object.values(this.transcode_state_cache)
+        .filter((state) => state.status == WorkerStatus.Finished)
+        .map((state) => {
+          if (state.variant) return `${state.video_id}.${state.variant}.${state.audio_ext}`;
+          return `${state.video_id}.${state.audio_ext}`;
+        });
+      if (files.length == 0) return null;
+      return TranscodeApi.get_archive_link(files, "transcodes.zip");
+    },
   },
   methods: {
     async update_request_id() {
@@ -136,6 +157,24 @@ export let create_app = () => createApp({
         this.subscribe_to_transcode(video_id, audio_ext),
       ]);
     },
+    // NOTE: Jobs started by /quick_add redirect here with ?job= so their progress is shown
+    async load_job_from_url() {
+      let job_id = new URLSearchParams(window.location.search).get("job");
+      if (job_id === null) return;
+      let res = null;
+      try {
+        res = await TranscodeApi.get_job(job_id);
+      } catch {
+        this.transcode_request.url_error = "Job was not found";
+        return;
+      }
+      let { video_id, audio_ext } = res.job;
+      this.transcode_request.url = `https://www.youtube.com/watch?v=${video_id}`;
+      this.transcode_request.format = audio_ext;
+      await this.update_request_id();
+      if (this.metadata === null) return;
+      await this.update_focused_transcode(video_id, audio_ext);
+    },
     async refresh_downloads() {
       let res = await TranscodeApi.get_downloads();
       for (let state of res) {
@@ -252,9 +291,17 @@ export let create_app = () => createApp({
       elem.rel = "nofollow";
       elem.click();
     },
+    async download_archive() {
+      if (this.archive_link === null) return;
+      let elem = document.createElement("a");
+      elem.href = this.archive_link;
+      elem.rel = "nofollow";
+      elem.click();
+    },
   },
   mounted() {
     this.update_request_id();
+    this.load_job_from_url();
     this.refresh_downloads();
     this.refresh_transcodes();
   },
@@ -74,6 +74,12 @@ export const convert_to_long_standard_prefix = (value) => {
   return [new_value, prefix];
 };
 
+export const file_size_to_string = (bytes) => {
+  if (bytes === null || bytes === undefined) return "";
+  let [value, prefix] = convert_to_short_standard_prefix(bytes);
+  return `${value.toFixed(2)} ${prefix}B`;
+};
+
 // dhms = day hours minutes seconds
 export const convert_seconds_to_dhms = (seconds) => {
   const DAY_TOTAL_SECONDS = 24*60*60;
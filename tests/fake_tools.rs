@@ -0,0 +1,99 @@
+// Runs downloads and transcodes through the workers with the fake yt-dlp, ffmpeg and ffprobe binaries
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use ytdlp_server::app::{AppConfig, AppState};
+use ytdlp_server::database::{AudioExtension, VideoId, WorkerStatus};
+use ytdlp_server::ffmpeg::TranscodeOptions;
+use ytdlp_server::worker_download::try_start_download_worker;
+use ytdlp_server::worker_transcode::{try_start_transcode_worker, TranscodeKey};
+
+const VIDEO_ID: &str = "dQw4w9WgXcQ";
+// NOTE: Video ids are 11 characters which this happens to be
+const UNAVAILABLE_VIDEO_ID: &str = "unavailable";
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const JOB_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn create_app_state() -> AppState {
+    let app_config = AppConfig {
+        ytdlp_binary: PathBuf::from(env!("CARGO_BIN_EXE_fake_ytdlp")),
+        ffmpeg_binary: PathBuf::from(env!("CARGO_BIN_EXE_fake_ffmpeg")),
+        ffprobe_binary: PathBuf::from(env!("CARGO_BIN_EXE_fake_ffprobe")),
+        ..AppConfig::default()
+    };
+    AppState::new_in_memory(app_config).expect("App state should be created")
+}
+
+fn start_download(app: &AppState, video_id: &VideoId) -> WorkerStatus {
+    try_start_download_worker(
+        video_id.clone(),
+        app.download_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        app.app_config.download_options.clone(), None,
+    ).expect("Download should start")
+}
+
+/// Statuses of the row in the order they were seen until it finished or failed
+fn wait_for_statuses(get_status: impl Fn() -> Option<WorkerStatus>) -> Vec<WorkerStatus> {
+    let start = Instant::now();
+    let mut statuses: Vec<WorkerStatus> = Vec::new();
+    while start.elapsed() < JOB_TIMEOUT {
+        let status = get_status();
+        if let Some(status) = status.filter(|status| statuses.last() != Some(status)) {
+            statuses.push(status);
+        }
+        if matches!(status, Some(WorkerStatus::Finished | WorkerStatus::Failed)) {
+            return statuses;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    panic!("Job didn't end before timeout: statuses={statuses:?}");
+}
+
+#[test]
+fn download_and_transcode_finish() {
+    let app = create_app_state();
+    let video_id = VideoId::try_new(VIDEO_ID).unwrap();
+    let options = TranscodeOptions::default();
+    let key = TranscodeKey::new(video_id.clone(), AudioExtension::MP3, &options);
+    assert_eq!(start_download(&app, &video_id), WorkerStatus::Queued);
+    let transcode_status = try_start_transcode_worker(
+        key.clone(), options,
+        app.download_cache.clone(), app.transcode_cache.clone(), app.job_events.clone(), app.app_config.clone(), app.job_store.clone(), app.worker_thread_pool.clone(),
+        None, None,
+    ).expect("Transcode should start");
+    assert_eq!(transcode_status, WorkerStatus::Queued);
+
+    let download_statuses = wait_for_statuses(|| {
+        app.job_store.select_ytdlp_entry(&video_id).unwrap().map(|entry| entry.status)
+    });
+    assert_eq!(download_statuses.last(), Some(&WorkerStatus::Finished));
+    assert!(download_statuses.contains(&WorkerStatus::Running), "download never ran: {download_statuses:?}");
+    let download = app.job_store.select_ytdlp_entry(&video_id).unwrap().unwrap();
+    let audio_path = download.audio_path.expect("Finished download should have a path");
+    assert!(PathBuf::from(audio_path).exists());
+
+    let transcode_statuses = wait_for_statuses(|| {
+        app.job_store.select_ffmpeg_entry(&video_id, key.audio_ext, key.variant.as_str()).unwrap().map(|entry| entry.status)
+    });
+    assert_eq!(transcode_statuses.last(), Some(&WorkerStatus::Finished));
+    assert!(transcode_statuses.contains(&WorkerStatus::Running), "transcode never ran: {transcode_statuses:?}");
+    let transcode = app.job_store.select_ffmpeg_entry(&video_id, key.audio_ext, key.variant.as_str()).unwrap().unwrap();
+    let audio_path = transcode.audio_path.expect("Finished transcode should have a path");
+    assert!(PathBuf::from(audio_path).exists());
+
+    // NOTE: Finished downloads are found on disk instead of being downloaded again
+    assert_eq!(start_download(&app, &video_id), WorkerStatus::Finished);
+}
+
+#[test]
+fn unavailable_download_fails() {
+    std::env::set_var("FAKE_YTDLP_FAIL_IDS", UNAVAILABLE_VIDEO_ID);
+    let app = create_app_state();
+    let video_id = VideoId::try_new(UNAVAILABLE_VIDEO_ID).unwrap();
+    assert_eq!(start_download(&app, &video_id), WorkerStatus::Queued);
+    let statuses = wait_for_statuses(|| {
+        app.job_store.select_ytdlp_entry(&video_id).unwrap().map(|entry| entry.status)
+    });
+    assert_eq!(statuses.last(), Some(&WorkerStatus::Failed));
+    let download = app.job_store.select_ytdlp_entry(&video_id).unwrap().unwrap();
+    assert!(download.audio_path.is_none());
+}